@@ -3,8 +3,22 @@
 #include <stdbool.h>
 #include <stdint.h>
 
+// BootInfo header magic ("IONB") and the version this entry understands.
+#define BOOT_INFO_MAGIC   0x424E4F49
+#define BOOT_INFO_VERSION 2
+
+// Versioned header, placed right before the BootInfo data.
+typedef struct {
+    uint32_t magic;
+    uint16_t version;
+    uint16_t length;   // total length, including the header
+    uint32_t features;
+    uint32_t reserved;
+} BootInfoHeader;
+
 // BootInfo definition
 typedef struct {
+    BootInfoHeader header;
     uint32_t multiboot_magic;
     uint32_t multiboot_info;
     uint32_t cpuid_edx;
@@ -14,7 +28,6 @@ typedef struct {
     uint64_t framebuffer_addr;
     uint64_t memory_map_addr;
     uint64_t kernel_entry;
-    uint64_t boot_entry;
 } BootInfo;
 
 // Wrapper struct for validation
@@ -28,11 +41,15 @@ extern void rust_kernel_entry(const ValidatedBootInfo* boot_info);
 
 // Validation function
 static bool validate_boot_info(const BootInfo* bi) {
+    // an unknown header means every field below is garbage.
+    if (bi->header.magic != BOOT_INFO_MAGIC) return false;
+    if (bi->header.version < BOOT_INFO_VERSION) return false;
+    if (bi->header.length < sizeof(BootInfo)) return false;
     if (bi->multiboot_magic != 0x36d76289) return false;
     if (bi->page_table_base == 0 || (bi->page_table_base & 0xFFF) != 0) return false;
     if (bi->stack_top == 0 || (bi->stack_top & 0xF) != 0) return false;
     if (bi->kernel_entry == 0) return false;
-    if (bi->framebuffer_addr == 0 ) return false;
+    // the framebuffer is optional, see BOOT_FEATURE_FRAMEBUFFER.
     if (bi->memory_map_addr == 0) return false;
     return true;
 }
@@ -0,0 +1,182 @@
+//! The Multiple APIC Description Table, which lists the local APICs, the IOAPICs, and how the ISA
+//! interrupts are wired to them.
+use super::{HEADER_SIZE, u32_at, u64_at};
+
+/// The entries start after the header, the local APIC address and the flags.
+const ENTRIES: usize = HEADER_SIZE + 8;
+/// The machine also has the two legacy 8259 PICs.
+const PCAT_COMPAT: u32 = 1 << 0;
+
+const LOCAL_APIC: u8 = 0;
+const IO_APIC: u8 = 1;
+const SOURCE_OVERRIDE: u8 = 2;
+const LOCAL_APIC_OVERRIDE: u8 = 5;
+const LOCAL_X2APIC: u8 = 9;
+
+/// The processor is enabled, or can be enabled.
+const PROCESSOR_ENABLED: u32 = 1 << 0;
+const PROCESSOR_ONLINE_CAPABLE: u32 = 1 << 1;
+
+/// An entry of the MADT which the kernel uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entry {
+    /// A processor, with the ID of its local APIC.
+    LocalApic {
+        /// The ID of the local APIC.
+        apic_id: u32,
+        /// Wether the processor can be used.
+        enabled: bool,
+    },
+    /// An IOAPIC, which handles the global system interrupts from `gsi_base` on.
+    IoApic {
+        /// The ID of the IOAPIC.
+        id: u8,
+        /// The physical address of its registers.
+        addr: u64,
+        /// The first global system interrupt it handles.
+        gsi_base: u32,
+    },
+    /// The ISA interrupt `irq` is wired to the global system interrupt `gsi`, not the one with the
+    /// same number.
+    SourceOverride(SourceOverride),
+    /// The 64 bit physical address of the local APICs, replacing the one in the header.
+    LocalApicAddress(u64),
+}
+
+/// Where an ISA interrupt is wired to, and how it is signalled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceOverride {
+    /// The ISA interrupt.
+    pub irq: u8,
+    /// The global system interrupt.
+    pub gsi: u32,
+    /// The interrupt is active when the line is low, instead of high.
+    pub active_low: bool,
+    /// The interrupt is level triggered, instead of edge triggered.
+    pub level_triggered: bool,
+}
+
+impl SourceOverride {
+    /// ISA interrupts without an override: the same number, active high, and edge triggered.
+    pub const fn identity(irq: u8) -> Self {
+        Self { irq, gsi: irq as u32, active_low: false, level_triggered: false }
+    }
+}
+
+impl Entry {
+    /// Parses an entry, `None` if the kernel does not use its type, or it is truncated.
+    pub fn parse(entry: &[u8]) -> Option<Self> {
+        match *entry.first()? {
+            LOCAL_APIC if entry.len() >= 8 => {
+                let flags = u32_at(entry, 4)?;
+                Some(Self::LocalApic {
+                    apic_id: u32::from(entry[3]),
+                    enabled: flags & (PROCESSOR_ENABLED | PROCESSOR_ONLINE_CAPABLE) != 0,
+                })
+            }
+            LOCAL_X2APIC if entry.len() >= 16 => {
+                let flags = u32_at(entry, 8)?;
+                Some(Self::LocalApic {
+                    apic_id: u32_at(entry, 4)?,
+                    enabled: flags & (PROCESSOR_ENABLED | PROCESSOR_ONLINE_CAPABLE) != 0,
+                })
+            }
+            IO_APIC if entry.len() >= 12 => Some(Self::IoApic {
+                id: entry[2],
+                addr: u64::from(u32_at(entry, 4)?),
+                gsi_base: u32_at(entry, 8)?,
+            }),
+            SOURCE_OVERRIDE if entry.len() >= 10 => {
+                let flags = u16::from_le_bytes([entry[8], entry[9]]);
+                // 0b00 is the default of the bus, which is active high and edge triggered for ISA.
+                Some(Self::SourceOverride(SourceOverride {
+                    irq: entry[3],
+                    gsi: u32_at(entry, 4)?,
+                    active_low: flags & 0b11 == 0b11,
+                    level_triggered: (flags >> 2) & 0b11 == 0b11,
+                }))
+            }
+            LOCAL_APIC_OVERRIDE if entry.len() >= 12 => Some(Self::LocalApicAddress(u64_at(entry, 4)?)),
+            _ => None,
+        }
+    }
+}
+
+/// The physical address of the local APICs, from the header of the MADT `table`
+pub fn local_apic_address(table: &'static [u8]) -> Option<u64> {
+    let header = u64::from(u32_at(table, HEADER_SIZE)?);
+    Some(entries(table).find_map(|entry| match entry {
+        Entry::LocalApicAddress(addr) => Some(addr),
+        _ => None,
+    }).unwrap_or(header))
+}
+
+/// Wether the machine also has the legacy 8259 PICs, which have to be masked.
+pub fn has_pics(table: &'static [u8]) -> bool {
+    u32_at(table, HEADER_SIZE + 4).is_some_and(|flags| flags & PCAT_COMPAT != 0)
+}
+
+/// The entries of the MADT `table` which the kernel uses; a truncated entry ends them.
+pub fn entries(table: &'static [u8]) -> impl Iterator<Item = Entry> {
+    let mut rest = table.get(ENTRIES..).unwrap_or_default();
+    core::iter::from_fn(move || {
+        let &len = rest.get(1)?;
+        // an entry is at least its type and length.
+        if len < 2 || usize::from(len) > rest.len() {
+            return None;
+        }
+        let (entry, next) = rest.split_at(usize::from(len));
+        rest = next;
+        Some(entry)
+    }).filter_map(Entry::parse)
+}
+
+/// Where the ISA interrupt `irq` is wired to, according to the MADT `table`
+pub fn isa_route(table: &'static [u8], irq: u8) -> SourceOverride {
+    entries(table).find_map(|entry| match entry {
+        Entry::SourceOverride(route) if route.irq == irq => Some(route),
+        _ => None,
+    }).unwrap_or(SourceOverride::identity(irq))
+}
+
+/// Tests reading the entries of a MADT.
+#[cfg(feature = "test")]
+pub fn test_madt(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    // a local APIC, an IOAPIC, the timer on GSI 2, SCI level triggered and active low, and a
+    // truncated entry.
+    static TABLE: [u8; ENTRIES + 8 + 12 + 10 + 10 + 2] = {
+        let mut table = [0; ENTRIES + 8 + 12 + 10 + 10 + 2];
+        let header = [0x00, 0x00, 0xE0, 0xFE, PCAT_COMPAT as u8, 0, 0, 0];
+        let entries: [u8; 8 + 12 + 10 + 10 + 2] = [
+            LOCAL_APIC, 8, 0, 0, 1, 0, 0, 0,
+            IO_APIC, 12, 1, 0, 0x00, 0x00, 0xC0, 0xFE, 0, 0, 0, 0,
+            SOURCE_OVERRIDE, 10, 0, 0, 2, 0, 0, 0, 0, 0,
+            SOURCE_OVERRIDE, 10, 0, 9, 9, 0, 0, 0, 0b1111, 0,
+            IO_APIC, 12,
+        ];
+        let mut i = 0;
+        while i < header.len() {
+            table[HEADER_SIZE + i] = header[i];
+            i += 1;
+        }
+        let mut i = 0;
+        while i < entries.len() {
+            table[ENTRIES + i] = entries[i];
+            i += 1;
+        }
+        table
+    };
+    test_assert_eq!(local_apic_address(&TABLE), Some(0xFEE0_0000))?;
+    test_assert!(has_pics(&TABLE))?;
+    let mut parsed = entries(&TABLE);
+    test_assert_eq!(parsed.next(), Some(Entry::LocalApic { apic_id: 0, enabled: true }))?;
+    test_assert_eq!(parsed.next(), Some(Entry::IoApic { id: 1, addr: 0xFEC0_0000, gsi_base: 0 }))?;
+    test_assert_eq!(parsed.nth(1), Some(Entry::SourceOverride(isa_route(&TABLE, 9))))?;
+    test_assert_eq!(parsed.next(), None)?;
+
+    test_assert_eq!(isa_route(&TABLE, 0), SourceOverride { irq: 0, gsi: 2, active_low: false, level_triggered: false })?;
+    test_assert_eq!(isa_route(&TABLE, 9), SourceOverride { irq: 9, gsi: 9, active_low: true, level_triggered: true })?;
+    test_assert_eq!(isa_route(&TABLE, 1), SourceOverride::identity(1))
+}
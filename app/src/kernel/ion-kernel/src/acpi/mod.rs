@@ -0,0 +1,191 @@
+//! ACPI tables, found through the RSDP the bootloader copied into the multiboot2 information.
+//! 
+//! Only finding tables is supported, there is no AML interpreter. Tables are read in place, as
+//! they are identity mapped; a table which is not mapped is treated as missing.
+//! 
+//! ```rust,no_run
+//! if let Some(srat) = acpi::find(*b"SRAT") {
+//!     for affinity in acpi::srat::memory_affinities(srat) { /* ... */ }
+//! }
+//! ```
+use core::{fmt::{self, Display}, sync::atomic::{AtomicU64, Ordering}};
+
+use x86_64::VirtAddr;
+
+use crate::{c_lib::BootInfo, log::{debug, warn}, mem::translate};
+
+pub mod madt;
+pub mod srat;
+
+/// The size of the header of every table, except the RSDP.
+pub const HEADER_SIZE: usize = 36;
+
+/// Why the tables could not be found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    /// The bootloader passed no RSDP.
+    NoRsdp,
+    /// The RSDP, or the table with this signature, has a bad signature, length or checksum.
+    Invalid([u8; 4]),
+    /// The table at this address is not mapped.
+    Unmapped(u64),
+}
+
+impl Display for AcpiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoRsdp => write!(f, "the bootloader passed no ACPI RSDP"),
+            Self::Invalid(signature) => write!(f, "the ACPI table {} is not valid", signature.escape_ascii()),
+            Self::Unmapped(addr) => write!(f, "the ACPI table at {addr:#x} is not mapped"),
+        }
+    }
+}
+
+impl core::error::Error for AcpiError {}
+
+/// Wether the bytes add up to 0, as every ACPI structure does.
+pub fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// The root table, from the RSDP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Root {
+    /// ACPI 1.0: the RSDT, with 32 bit table addresses.
+    Rsdt(u64),
+    /// ACPI 2.0 and later: the XSDT, with 64 bit table addresses.
+    Xsdt(u64),
+}
+
+impl Root {
+    /// Parses the RSDP, which is 20 bytes long in ACPI 1.0, and 36 bytes long later on.
+    /// # Errors
+    /// Returns [`AcpiError::Invalid`] if the signature or a checksum is wrong.
+    pub fn from_rsdp(rsdp: &[u8]) -> Result<Self, AcpiError> {
+        let invalid = AcpiError::Invalid(*b"RSDP");
+        if rsdp.get(..8) != Some(b"RSD PTR ") || !checksum_ok(rsdp.get(..20).ok_or(invalid)?) {
+            return Err(invalid);
+        }
+        // revision 0 is ACPI 1.0, which has no XSDT.
+        if rsdp[15] >= 2 {
+            let length = u32_at(rsdp, 20).ok_or(invalid)? as usize;
+            let extended = rsdp.get(..length).filter(|extended| length >= 36 && checksum_ok(extended)).ok_or(invalid)?;
+            return Ok(Self::Xsdt(u64_at(extended, 24).ok_or(invalid)?));
+        }
+        Ok(Self::Rsdt(u64::from(u32_at(rsdp, 16).ok_or(invalid)?)))
+    }
+}
+
+/// The bytes at the physical `addr`, if every page of them is identity mapped.
+fn physical(addr: u64, len: usize) -> Result<&'static [u8], AcpiError> {
+    let end = addr.checked_add(len as u64).ok_or(AcpiError::Unmapped(addr))?;
+    let mapped = |page: u64| {
+        VirtAddr::try_new(page).ok().and_then(translate).is_some_and(|translation| translation.phys.as_u64() == page)
+    };
+    if addr == 0 || !(addr & !0xFFF..end).step_by(4096).all(mapped) {
+        return Err(AcpiError::Unmapped(addr));
+    }
+    // Safety: every page is identity mapped, and the firmware does not reclaim ACPI tables.
+    Ok(unsafe { core::slice::from_raw_parts(addr as usize as *const u8, len) })
+}
+
+/// The table at the physical `addr`, with its header.
+/// # Errors
+/// Returns an error if it is not mapped, or its checksum is wrong.
+pub fn table_at(addr: u64) -> Result<&'static [u8], AcpiError> {
+    let header = physical(addr, HEADER_SIZE)?;
+    let signature = [header[0], header[1], header[2], header[3]];
+    let length = u32_at(header, 4).unwrap_or_default() as usize;
+    if length < HEADER_SIZE {
+        return Err(AcpiError::Invalid(signature));
+    }
+    let table = physical(addr, length)?;
+    if !checksum_ok(table) {
+        return Err(AcpiError::Invalid(signature));
+    }
+    Ok(table)
+}
+
+/// The physical address of the root table, 0 before [`init`], and the upper bit is set for the
+/// XSDT.
+static ROOT: AtomicU64 = AtomicU64::new(0);
+const XSDT: u64 = 1 << 63;
+
+/// The root table, if [`init`] found one.
+pub fn root() -> Option<Root> {
+    match ROOT.load(Ordering::Relaxed) {
+        0 => None,
+        addr if addr & XSDT != 0 => Some(Root::Xsdt(addr & !XSDT)),
+        addr => Some(Root::Rsdt(addr)),
+    }
+}
+
+/// The addresses of the tables in the root table `root`
+pub fn tables(root: &'static [u8], xsdt: bool) -> impl Iterator<Item = u64> {
+    let entry_size = if xsdt { 8 } else { 4 };
+    root[HEADER_SIZE.min(root.len())..].chunks_exact(entry_size).map(move |entry| {
+        if xsdt { u64_at(entry, 0).unwrap_or_default() } else { u64::from(u32_at(entry, 0).unwrap_or_default()) }
+    })
+}
+
+/// The first valid table with `signature`, E.g. `*b"SRAT"`
+pub fn find(signature: [u8; 4]) -> Option<&'static [u8]> {
+    let (addr, xsdt) = match root()? {
+        Root::Rsdt(addr) => (addr, false),
+        Root::Xsdt(addr) => (addr, true),
+    };
+    let root = table_at(addr).ok()?;
+    tables(root, xsdt).filter_map(|addr| table_at(addr).ok()).find(|table| table[..4] == signature)
+}
+
+fn find_root(boot_info: &BootInfo) -> Result<Root, AcpiError> {
+    let rsdp = boot_info.multiboot2().ok().and_then(|info| info.acpi_rsdp()).ok_or(AcpiError::NoRsdp)?;
+    let root = Root::from_rsdp(rsdp)?;
+    let (Root::Rsdt(addr) | Root::Xsdt(addr)) = root;
+    table_at(addr)?;
+    ROOT.store(if matches!(root, Root::Xsdt(_)) { addr | XSDT } else { addr }, Ordering::Relaxed);
+    Ok(root)
+}
+
+/// Finds the root table, through the RSDP in the multiboot2 information.
+/// # Errors
+/// Returns an error if there is no RSDP, or the root table is not valid.
+pub fn init(boot_info: &BootInfo) -> Result<Root, AcpiError> {
+    let result = find_root(boot_info);
+    match result {
+        Ok(root) => debug!("ACPI: {root:x?}"),
+        Err(e) => warn!("The ACPI tables are unavailable: {e}"),
+    }
+    result
+}
+
+/// Tests parsing an RSDP, and reading the table addresses of a root table.
+#[cfg(feature = "test")]
+pub fn test_acpi(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::test_assert_eq;
+
+    let mut rsdp = [0; 20];
+    rsdp[..8].copy_from_slice(b"RSD PTR ");
+    rsdp[16..20].copy_from_slice(&0x7FE_1234u32.to_le_bytes());
+    test_assert_eq!(Root::from_rsdp(&rsdp), Err(AcpiError::Invalid(*b"RSDP")))?;
+    rsdp[8] = 0u8.wrapping_sub(rsdp.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)));
+    test_assert_eq!(Root::from_rsdp(&rsdp), Ok(Root::Rsdt(0x7FE_1234)))?;
+
+    static XSDT_TABLE: [u8; HEADER_SIZE + 16] = {
+        let mut table = [0; HEADER_SIZE + 16];
+        table[HEADER_SIZE] = 0x10;
+        table[HEADER_SIZE + 9] = 0x20;
+        table
+    };
+    let mut addrs = tables(&XSDT_TABLE, true);
+    test_assert_eq!((addrs.next(), addrs.next(), addrs.next()), (Some(0x10), Some(0x2000), None))?;
+    test_assert_eq!(tables(&XSDT_TABLE, false).count(), 4)
+}
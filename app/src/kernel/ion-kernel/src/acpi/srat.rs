@@ -0,0 +1,114 @@
+//! The System Resource Affinity Table, which assigns physical memory to proximity domains (NUMA
+//! nodes).
+//! 
+//! Only the memory affinity structures are read, processors are not assigned to nodes yet.
+use core::ops::Range;
+
+use super::{HEADER_SIZE, u32_at};
+
+/// The structures start after the header, and 12 reserved bytes.
+const STRUCTURES: usize = HEADER_SIZE + 12;
+const MEMORY_AFFINITY: u8 = 1;
+const ENABLED: u32 = 1 << 0;
+const HOT_PLUGGABLE: u32 = 1 << 1;
+
+/// A range of physical memory in a proximity domain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryAffinity {
+    /// The proximity domain, which is the NUMA node.
+    pub domain: u32,
+    /// The physical range (end exclusive)
+    pub range: Range<u64>,
+    /// Wether the memory can be added or removed at runtime.
+    pub hot_pluggable: bool,
+}
+
+impl MemoryAffinity {
+    /// Parses a memory affinity structure, `None` if it is not one, or it is disabled.
+    pub fn parse(structure: &[u8]) -> Option<Self> {
+        if structure.first() != Some(&MEMORY_AFFINITY) || structure.len() < 40 {
+            return None;
+        }
+        let flags = u32_at(structure, 28)?;
+        if flags & ENABLED == 0 {
+            return None;
+        }
+        let base = u64::from(u32_at(structure, 8)?) | u64::from(u32_at(structure, 12)?) << 32;
+        let length = u64::from(u32_at(structure, 16)?) | u64::from(u32_at(structure, 20)?) << 32;
+        Some(Self {
+            domain: u32_at(structure, 2)?,
+            range: base..base.saturating_add(length),
+            hot_pluggable: flags & HOT_PLUGGABLE != 0,
+        })
+    }
+}
+
+/// The structures of the SRAT `table`, as `(type, bytes)`; a truncated structure ends them.
+pub fn structures(table: &'static [u8]) -> impl Iterator<Item = (u8, &'static [u8])> {
+    let mut rest = table.get(STRUCTURES..).unwrap_or_default();
+    core::iter::from_fn(move || {
+        let (&typ, &len) = (rest.first()?, rest.get(1)?);
+        // a structure is at least its type and length.
+        if len < 2 || usize::from(len) > rest.len() {
+            return None;
+        }
+        let (structure, next) = rest.split_at(usize::from(len));
+        rest = next;
+        Some((typ, structure))
+    })
+}
+
+/// The enabled memory affinities of the SRAT `table`
+pub fn memory_affinities(table: &'static [u8]) -> impl Iterator<Item = MemoryAffinity> {
+    structures(table).filter_map(|(_, structure)| MemoryAffinity::parse(structure))
+}
+
+/// Tests reading the memory affinities of an SRAT.
+#[cfg(feature = "test")]
+pub fn test_srat(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::test_assert_eq;
+
+    const fn memory(domain: u8, base: u64, length: u64, flags: u8) -> [u8; 40] {
+        let mut structure = [0; 40];
+        structure[0] = MEMORY_AFFINITY;
+        structure[1] = 40;
+        structure[2] = domain;
+        let (base, length) = (base.to_le_bytes(), length.to_le_bytes());
+        let mut i = 0;
+        while i < 8 {
+            structure[8 + i] = base[i];
+            structure[16 + i] = length[i];
+            i += 1;
+        }
+        structure[28] = flags;
+        structure
+    }
+    static TABLE: [u8; STRUCTURES + 16 + 3 * 40 + 2] = {
+        let mut table = [0; STRUCTURES + 16 + 3 * 40 + 2];
+        // a processor affinity, which is skipped.
+        table[STRUCTURES] = 0;
+        table[STRUCTURES + 1] = 16;
+        let structures = [
+            memory(0, 0, 0x8000_0000, 1),
+            memory(1, 0x1_0000_0000, 0x8000_0000, 3),
+            memory(2, 0x2_0000_0000, 0x1000, 0),
+        ];
+        let mut i = 0;
+        while i < 3 * 40 {
+            table[STRUCTURES + 16 + i] = structures[i / 40][i % 40];
+            i += 1;
+        }
+        // a truncated structure, which ends the table.
+        table[STRUCTURES + 16 + 3 * 40] = MEMORY_AFFINITY;
+        table[STRUCTURES + 16 + 3 * 40 + 1] = 40;
+        table
+    };
+    test_assert_eq!(structures(&TABLE).count(), 4)?;
+    let mut affinities = memory_affinities(&TABLE);
+    test_assert_eq!(affinities.next(), Some(MemoryAffinity { domain: 0, range: 0..0x8000_0000, hot_pluggable: false }))?;
+    test_assert_eq!(
+        affinities.next(),
+        Some(MemoryAffinity { domain: 1, range: 0x1_0000_0000..0x1_8000_0000, hot_pluggable: true })
+    )?;
+    test_assert_eq!(affinities.next(), None)
+}
@@ -0,0 +1,94 @@
+//! CPUID querying from Rust.
+//! 
+//! The bootloader queries `cpuid` in 32 bit mode and passes the `edx`/`ecx` registers through the
+//! [`BootInfo`](crate::c_lib::BootInfo), but by the time the kernel reads them they may be stale (the
+//! boot stage changes control registers afterwards) or simply wrong. This module queries the CPU
+//! directly, so the kernel does not have to trust the boot stage.
+use core::arch::x86_64::{CpuidResult, __cpuid_count};
+
+use crate::{c_lib::bit_flags::BitFlags, collections::ArrayString, log::{debug, warn}};
+
+/// Bits of leaf 1 `ecx` that are expected to change after the boot stage queried them.
+/// 
+/// - bit 27 (OSXSAVE) mirrors `CR4.OSXSAVE`, which the boot stage sets after its query.
+const VOLATILE_ECX_BITS: u32 = 1 << 27;
+
+/// Executes `cpuid` with the given leaf and sub-leaf.
+/// 
+/// `cpuid` is always available in long mode, so this never fails.
+pub fn cpuid(leaf: u32, sub_leaf: u32) -> CpuidResult {
+    __cpuid_count(leaf, sub_leaf)
+}
+
+/// Returns the highest supported basic leaf.
+pub fn max_leaf() -> u32 {
+    cpuid(0, 0).eax
+}
+
+/// Returns the highest supported extended leaf (`0x8000_0000` and above).
+pub fn max_extended_leaf() -> u32 {
+    cpuid(0x8000_0000, 0).eax
+}
+
+/// Returns the vendor id, e.g. `GenuineIntel` or `AuthenticAMD`.
+pub fn vendor() -> ArrayString<12> {
+    let res = cpuid(0, 0);
+    let mut vendor = ArrayString::new();
+    for reg in [res.ebx, res.edx, res.ecx] {
+        for byte in reg.to_le_bytes() {
+            // can not fail, there are exactly 12 bytes.
+            let _ = vendor.push(if byte.is_ascii_graphic() { char::from(byte) } else { '?' });
+        }
+    }
+    vendor
+}
+
+/// The feature registers (`edx` and `ecx`) of CPUID leaf 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeatureRegisters {
+    /// The `edx` register, stored as bit flags.
+    pub edx: BitFlags,
+    /// The `ecx` register, stored as bit flags.
+    pub ecx: BitFlags,
+}
+
+impl FeatureRegisters {
+    /// Queries the feature registers directly from the CPU.
+    pub fn query() -> Self {
+        let res = cpuid(1, 0);
+        Self {
+            edx: BitFlags::new(res.edx),
+            ecx: BitFlags::new(res.ecx),
+        }
+    }
+
+    /// Compares these (queried) registers with the ones passed in by the boot stage, logging any
+    /// mismatches.
+    /// 
+    /// Returns wether an unexpected mismatch was found. Bits that are known to change after the boot
+    /// stage queried them (see [`VOLATILE_ECX_BITS`]) are only logged in debug builds.
+    pub fn compare_with_boot(&self, boot: &FeatureRegisters) -> bool {
+        let edx_diff = self.edx.into_inner() ^ boot.edx.into_inner();
+        let ecx_diff = self.ecx.into_inner() ^ boot.ecx.into_inner();
+
+        if ecx_diff & VOLATILE_ECX_BITS != 0 {
+            debug!("CPUID ecx volatile bits differ from boot: {:#b}", ecx_diff & VOLATILE_ECX_BITS);
+        }
+
+        let ecx_diff = ecx_diff & !VOLATILE_ECX_BITS;
+        if edx_diff != 0 {
+            warn!(
+                "CPUID edx mismatch: boot={:#010x}, queried={:#010x} (differs in {:#b})",
+                boot.edx.into_inner(), self.edx.into_inner(), edx_diff
+            );
+        }
+        if ecx_diff != 0 {
+            warn!(
+                "CPUID ecx mismatch: boot={:#010x}, queried={:#010x} (differs in {:#b})",
+                boot.ecx.into_inner(), self.ecx.into_inner(), ecx_diff
+            );
+        }
+
+        edx_diff != 0 || ecx_diff != 0
+    }
+}
@@ -0,0 +1,264 @@
+//! A full CPUID decode, formatted like Linux's `/proc/cpuinfo`.
+//! 
+//! This answers which optional features the (virtual) machine actually provides, as opposed to
+//! the ones the kernel requires.
+use core::fmt::{self, Display};
+
+use crate::collections::{ArrayString, ArrayVec};
+use super::{cpuid::{cpuid, max_extended_leaf, max_leaf, vendor}, power_info::PowerInfo};
+
+/// Names of the leaf 1 `edx` flags, by bit.
+const LEAF1_EDX: [&str; 32] = [
+    "fpu", "vme", "de", "pse", "tsc", "msr", "pae", "mce", "cx8", "apic", "", "sep", "mtrr", "pge",
+    "mca", "cmov", "pat", "pse36", "pn", "clflush", "", "dts", "acpi", "mmx", "fxsr", "sse", "sse2",
+    "ss", "ht", "tm", "ia64", "pbe",
+];
+
+/// Names of the leaf 1 `ecx` flags, by bit.
+const LEAF1_ECX: [&str; 32] = [
+    "pni", "pclmulqdq", "dtes64", "monitor", "ds_cpl", "vmx", "smx", "est", "tm2", "ssse3", "cid",
+    "sdbg", "fma", "cx16", "xtpr", "pdcm", "", "pcid", "dca", "sse4_1", "sse4_2", "x2apic", "movbe",
+    "popcnt", "tsc_deadline_timer", "aes", "xsave", "osxsave", "avx", "f16c", "rdrand", "hypervisor",
+];
+
+/// Names of the leaf 7 `ebx` flags, by bit.
+const LEAF7_EBX: [&str; 32] = [
+    "fsgsbase", "tsc_adjust", "sgx", "bmi1", "hle", "avx2", "", "smep", "bmi2", "erms", "invpcid",
+    "rtm", "cqm", "", "mpx", "rdt_a", "avx512f", "avx512dq", "rdseed", "adx", "smap", "avx512ifma",
+    "", "clflushopt", "clwb", "intel_pt", "avx512pf", "avx512er", "avx512cd", "sha_ni", "avx512bw",
+    "avx512vl",
+];
+
+/// Names of the leaf `0x8000_0001` `edx` flags, by bit, without the ones repeating leaf 1.
+const EXT1_EDX: [&str; 32] = [
+    "", "", "", "", "", "", "", "", "", "", "", "syscall", "", "", "", "", "", "", "", "mp", "nx",
+    "", "mmxext", "", "", "fxsr_opt", "pdpe1gb", "rdtscp", "", "lm", "3dnowext", "3dnow",
+];
+
+/// Names of the leaf `0x8000_0001` `ecx` flags, by bit.
+const EXT1_ECX: [&str; 32] = [
+    "lahf_lm", "cmp_legacy", "svm", "extapic", "cr8_legacy", "abm", "sse4a", "misalignsse",
+    "3dnowprefetch", "osvw", "ibs", "xop", "skinit", "wdt", "", "lwp", "fma4", "tce", "",
+    "nodeid_msr", "", "tbm", "topoext", "perfctr_core", "perfctr_nb", "", "bpext", "ptsc",
+    "perfctr_llc", "mwaitx", "", "",
+];
+
+/// Family, model and stepping, decoded from leaf 1 `eax`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    /// The family, including the extended family.
+    pub family: u32,
+    /// The model, including the extended model.
+    pub model: u32,
+    /// The stepping.
+    pub stepping: u32,
+}
+
+impl Signature {
+    /// Decodes leaf 1 `eax`.
+    pub const fn from_eax(eax: u32) -> Self {
+        let base_family = (eax >> 8) & 0xF;
+        let base_model = (eax >> 4) & 0xF;
+        let family = if base_family == 0xF { base_family + ((eax >> 20) & 0xFF) } else { base_family };
+        let model = if base_family == 0x6 || base_family == 0xF {
+            (((eax >> 16) & 0xF) << 4) + base_model
+        } else {
+            base_model
+        };
+        Self { family, model, stepping: eax & 0xF }
+    }
+}
+
+/// The kind of a cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    /// Data only.
+    Data,
+    /// Instructions only.
+    Instruction,
+    /// Data and instructions.
+    Unified,
+}
+
+/// A cache, from the deterministic cache parameters leaf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cache {
+    /// The level, starting at 1.
+    pub level: u8,
+    /// What the cache holds.
+    pub kind: CacheKind,
+    /// The size in bytes.
+    pub size: u32,
+    /// The associativity.
+    pub ways: u32,
+    /// The size of a line, in bytes.
+    pub line_size: u32,
+}
+
+impl Cache {
+    /// Decodes a sub-leaf of leaf 4 (Intel) or `0x8000_001D` (AMD), `None` ends the list.
+    pub const fn from_regs(eax: u32, ebx: u32, ecx: u32) -> Option<Self> {
+        let kind = match eax & 0x1F {
+            1 => CacheKind::Data,
+            2 => CacheKind::Instruction,
+            3 => CacheKind::Unified,
+            _ => return None,
+        };
+        let ways = (ebx >> 22) + 1;
+        let partitions = ((ebx >> 12) & 0x3FF) + 1;
+        let line_size = (ebx & 0xFFF) + 1;
+        let sets = ecx.wrapping_add(1);
+        Some(Self {
+            level: ((eax >> 5) & 0x7) as u8,
+            kind,
+            size: ways.wrapping_mul(partitions).wrapping_mul(line_size).wrapping_mul(sets),
+            ways,
+            line_size,
+        })
+    }
+
+    /// The Linux style name, e.g. `L1d`
+    pub const fn name(&self) -> &'static str {
+        match (self.level, self.kind) {
+            (1, CacheKind::Data) => "L1d",
+            (1, CacheKind::Instruction) => "L1i",
+            (1, CacheKind::Unified) => "L1",
+            (2, _) => "L2",
+            (3, _) => "L3",
+            _ => "L4",
+        }
+    }
+}
+
+/// Maximum amount of caches listed.
+pub const MAX_CACHES: usize = 8;
+
+/// Everything the kernel knows about the CPU.
+#[derive(Debug, Clone)]
+pub struct CpuInfo {
+    /// The vendor id.
+    pub vendor: ArrayString<12>,
+    /// See [`Signature`]
+    pub signature: Signature,
+    /// The caches, ordered by level.
+    pub caches: ArrayVec<Cache, MAX_CACHES>,
+    /// Frequencies, thermal status and the brand string.
+    pub power: PowerInfo,
+    /// Leaf 1 `edx`, leaf 1 `ecx`, leaf 7 `ebx`, leaf `0x8000_0001` `edx` and `ecx`, or 0 if the
+    /// leaf is not supported.
+    flags: [u32; 5],
+}
+
+impl CpuInfo {
+    /// Queries the CPU.
+    pub fn query() -> Self {
+        let vendor = vendor();
+        let leaf1 = cpuid(1, 0);
+        let leaf7_ebx = if max_leaf() >= 7 { cpuid(7, 0).ebx } else { 0 };
+        let (ext1_edx, ext1_ecx) = if max_extended_leaf() >= 0x8000_0001 {
+            let res = cpuid(0x8000_0001, 0);
+            (res.edx, res.ecx)
+        } else {
+            (0, 0)
+        };
+
+        // AMD reports the same format in its own leaf.
+        let cache_leaf = match vendor.as_str() {
+            "GenuineIntel" if max_leaf() >= 4 => Some(4),
+            "AuthenticAMD" if max_extended_leaf() >= 0x8000_001D => Some(0x8000_001D),
+            _ => None,
+        };
+        let mut caches = ArrayVec::new();
+        if let Some(leaf) = cache_leaf {
+            for sub_leaf in 0..MAX_CACHES as u32 {
+                let res = cpuid(leaf, sub_leaf);
+                match Cache::from_regs(res.eax, res.ebx, res.ecx) {
+                    // can not fail, there are at most MAX_CACHES sub leaves.
+                    Some(cache) => { let _ = caches.push(cache); }
+                    None => break,
+                }
+            }
+        }
+
+        Self {
+            vendor,
+            signature: Signature::from_eax(leaf1.eax),
+            caches,
+            power: PowerInfo::query(),
+            flags: [leaf1.edx, leaf1.ecx, leaf7_ebx, ext1_edx, ext1_ecx],
+        }
+    }
+
+    /// Calls `f` with the name of every supported flag.
+    pub fn for_each_flag(&self, mut f: impl FnMut(&'static str)) {
+        for (reg, names) in self.flags.iter().zip([&LEAF1_EDX, &LEAF1_ECX, &LEAF7_EBX, &EXT1_EDX, &EXT1_ECX]) {
+            for (bit, name) in names.iter().enumerate() {
+                if reg & (1 << bit) != 0 && !name.is_empty() {
+                    f(name);
+                }
+            }
+        }
+    }
+
+    /// Returns whether the CPU has the flag named `name`, e.g. `avx2`
+    pub fn has_flag(&self, name: &str) -> bool {
+        let mut found = false;
+        self.for_each_flag(|flag| found |= flag == name);
+        found
+    }
+
+    /// The last level cache, which Linux reports as `cache size`.
+    pub fn last_level_cache(&self) -> Option<&Cache> {
+        self.caches.iter().max_by_key(|cache| cache.level)
+    }
+}
+
+impl Display for CpuInfo {
+    /// Formats like a processor entry of Linux's `/proc/cpuinfo`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "processor\t: 0")?;
+        writeln!(f, "vendor_id\t: {}", self.vendor)?;
+        writeln!(f, "cpu family\t: {}", self.signature.family)?;
+        writeln!(f, "model\t\t: {}", self.signature.model)?;
+        writeln!(f, "stepping\t: {}", self.signature.stepping)?;
+        // model name, frequencies and thermal status.
+        write!(f, "{}", self.power)?;
+        if let Some(cache) = self.last_level_cache() {
+            writeln!(f, "cache size\t: {} KB", cache.size / 1024)?;
+        }
+        for cache in self.caches.iter() {
+            writeln!(f, "{} cache\t: {} KB, {}-way, {} byte lines", cache.name(), cache.size / 1024, cache.ways, cache.line_size)?;
+        }
+        write!(f, "flags\t\t:")?;
+        let mut result = Ok(());
+        self.for_each_flag(|flag| result = result.and_then(|()| write!(f, " {flag}")));
+        result?;
+        writeln!(f)
+    }
+}
+
+#[cfg(feature = "test")]
+/// Tests
+pub mod test {
+    use crate::test::{TestInfo, TestResult, test_assert, test_assert_eq};
+    use super::*;
+
+    /// Tests decoding CPUID, and that the kernel's required features are listed.
+    pub fn test_cpuinfo(_: TestInfo) -> TestResult {
+        // a Coffee Lake i7-8700
+        test_assert_eq!(Signature::from_eax(0x000906EA), Signature { family: 6, model: 158, stepping: 10 })?;
+        // a Zen 2 EPYC
+        test_assert_eq!(Signature::from_eax(0x00830F10), Signature { family: 23, model: 49, stepping: 0 })?;
+
+        // 32 KB L1d, 8-way, 64 sets of 64 byte lines.
+        let l1d = Cache::from_regs(0x121, 0x01C0_003F, 63);
+        test_assert_eq!(l1d.map(|c| (c.name(), c.size, c.ways)), Some(("L1d", 32 * 1024, 8)))?;
+        test_assert!(Cache::from_regs(0, 0, 0).is_none())?;
+
+        let info = super::CpuInfo::query();
+        test_assert!(info.has_flag("fpu") && info.has_flag("cx16") && info.has_flag("lm"))?;
+        let mut text = ArrayString::<2048>::new();
+        let _ = core::fmt::write(&mut text, format_args!("{info}"));
+        test_assert!(text.contains("vendor_id") && text.contains(" fpu"))
+    }
+}
@@ -0,0 +1,10 @@
+//! Architecture specific operations
+//! 
+//! Currently, Ion OS only supports `x86_64`, so everything here assumes it.
+
+/// CPUID querying and decoding.
+pub mod cpuid;
+/// CPU frequency and thermal information.
+pub mod power_info;
+/// The `/proc/cpuinfo` decode of CPUID.
+pub mod cpuinfo;
@@ -0,0 +1,159 @@
+//! CPU frequency and thermal information.
+//! 
+//! Everything here is optional, VMs often report no frequencies, and hide the thermal sensors.
+use core::fmt::{self, Display};
+
+use x86_64::registers::model_specific::Msr;
+
+use crate::collections::ArrayString;
+use super::cpuid::{cpuid, max_extended_leaf, max_leaf, vendor};
+
+/// `IA32_THERM_STATUS`, the thermal status of the current core.
+const IA32_THERM_STATUS: u32 = 0x19C;
+/// `IA32_PACKAGE_THERM_STATUS`, the thermal status of the whole package.
+const IA32_PACKAGE_THERM_STATUS: u32 = 0x1B1;
+
+/// Returns the brand string, e.g. `Intel(R) Core(TM) i7-8700 CPU @ 3.20GHz`
+/// 
+/// Returns `None` if the CPU does not have one.
+pub fn brand_string() -> Option<ArrayString<48>> {
+    if max_extended_leaf() < 0x8000_0004 {
+        return None;
+    }
+    let bytes = (0x8000_0002..=0x8000_0004)
+        .map(|leaf| cpuid(leaf, 0))
+        .flat_map(|res| [res.eax, res.ebx, res.ecx, res.edx])
+        .flat_map(u32::to_le_bytes);
+    let mut brand = ArrayString::<48>::new();
+    for byte in bytes.take_while(|&byte| byte != 0) {
+        // can not fail, there are at most 48 bytes.
+        let _ = brand.push(if byte.is_ascii() { char::from(byte) } else { '?' });
+    }
+    // the string is often right aligned with spaces.
+    let trimmed = brand.trim();
+    let mut result = ArrayString::<48>::new();
+    // can not fail, it is not longer than before.
+    let _ = result.push_str(trimmed);
+    (!result.is_empty()).then_some(result)
+}
+
+/// Frequencies of CPUID leaf `0x16`, in MHz.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frequencies {
+    /// The base frequency.
+    pub base_mhz: u16,
+    /// The maximum (turbo) frequency, if reported.
+    pub max_mhz: Option<u16>,
+    /// The bus (reference) frequency, if reported.
+    pub bus_mhz: Option<u16>,
+}
+
+impl Frequencies {
+    /// Queries the frequencies, returns `None` if the CPU does not report them.
+    pub fn query() -> Option<Self> {
+        if max_leaf() < 0x16 {
+            return None;
+        }
+        let res = cpuid(0x16, 0);
+        let field = |reg: u32| Some(reg as u16).filter(|&mhz| mhz != 0);
+        Some(Self { base_mhz: field(res.eax)?, max_mhz: field(res.ebx), bus_mhz: field(res.ecx) })
+    }
+}
+
+/// A decoded `IA32_THERM_STATUS` or `IA32_PACKAGE_THERM_STATUS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThermalStatus {
+    /// Degrees Celsius below the maximum junction temperature (TjMax), if the reading is valid.
+    pub below_tj_max: Option<u8>,
+    /// The sensor is above its throttling threshold.
+    pub throttling: bool,
+    /// The sensor was above its throttling threshold since the log was cleared.
+    pub throttled: bool,
+    /// The sensor reached the critical temperature.
+    pub critical: bool,
+}
+
+impl ThermalStatus {
+    /// Decodes the raw MSR value.
+    pub const fn from_raw(raw: u64) -> Self {
+        let valid = raw & (1 << 31) != 0;
+        Self {
+            below_tj_max: if valid { Some(((raw >> 16) & 0x7F) as u8) } else { None },
+            throttling: raw & (1 << 0) != 0,
+            throttled: raw & (1 << 1) != 0,
+            critical: raw & (1 << 4) != 0,
+        }
+    }
+}
+
+impl Display for ThermalStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.below_tj_max {
+            Some(degrees) => write!(f, "{degrees}C below TjMax")?,
+            None => write!(f, "no reading")?,
+        }
+        if self.critical {
+            write!(f, ", critical")?;
+        }
+        if self.throttling {
+            write!(f, ", throttling")?;
+        } else if self.throttled {
+            write!(f, ", throttled before")?;
+        }
+        Ok(())
+    }
+}
+
+/// Frequency and thermal information about the CPU.
+#[derive(Debug, Clone, Copy)]
+pub struct PowerInfo {
+    /// See [`brand_string`]
+    pub brand: Option<ArrayString<48>>,
+    /// See [`Frequencies`]
+    pub frequencies: Option<Frequencies>,
+    /// The thermal status of the current core.
+    pub core_thermal: Option<ThermalStatus>,
+    /// The thermal status of the package.
+    pub package_thermal: Option<ThermalStatus>,
+}
+
+impl PowerInfo {
+    /// Queries the CPU.
+    /// 
+    /// The thermal MSRs are only read on Intel CPUs, which report the sensors in CPUID leaf 6,
+    /// as reading them would fault elsewhere.
+    pub fn query() -> Self {
+        let thermal = if vendor().as_str() == "GenuineIntel" && max_leaf() >= 6 { cpuid(6, 0).eax } else { 0 };
+        // Safety: the MSRs are architectural, when the CPU reports the sensors.
+        let read = |msr: u32| unsafe { Msr::new(msr).read() };
+        Self {
+            brand: brand_string(),
+            frequencies: Frequencies::query(),
+            core_thermal: (thermal & (1 << 0) != 0).then(|| ThermalStatus::from_raw(read(IA32_THERM_STATUS))),
+            package_thermal: (thermal & (1 << 6) != 0).then(|| ThermalStatus::from_raw(read(IA32_PACKAGE_THERM_STATUS))),
+        }
+    }
+}
+
+impl Display for PowerInfo {
+    /// Formats as `/proc/cpuinfo` style `key : value` lines.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "model name\t: {}", self.brand.as_ref().map_or("unknown", |b| b.as_str()))?;
+        if let Some(freq) = self.frequencies {
+            writeln!(f, "cpu MHz\t\t: {}", freq.base_mhz)?;
+            if let Some(max) = freq.max_mhz {
+                writeln!(f, "cpu max MHz\t: {max}")?;
+            }
+            if let Some(bus) = freq.bus_mhz {
+                writeln!(f, "bus MHz\t\t: {bus}")?;
+            }
+        }
+        if let Some(thermal) = self.core_thermal {
+            writeln!(f, "core thermal\t: {thermal}")?;
+        }
+        if let Some(thermal) = self.package_thermal {
+            writeln!(f, "package thermal\t: {thermal}")?;
+        }
+        Ok(())
+    }
+}
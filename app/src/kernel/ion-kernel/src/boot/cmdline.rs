@@ -0,0 +1,157 @@
+//! The kernel command line, as `key=value` options and bare flags separated by spaces.
+//! 
+//! Options are read with [`command_line`], once [`init`] set it:
+//! - `loglevel=LEVEL`, the [least severe level logged](crate::log::set_max_level)
+//! - `serial=off`, the serial ports are not used for logs or the console
+//! - `ata=off`, the ATA disks are not probed
+//! - `heap_size=SIZE`, the size of the [heap](crate::lib_alloc), such as `1M`
+//! - `root=`, `video=`, `console=` and `serial.log=`, see their modules
+//! 
+//! If an option is passed more than once, the last value is used.
+use core::{fmt, str::FromStr};
+
+use crate::sync::once_cell::InterruptSafeOnceCell;
+
+/// The options of a command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandLine<'a> {
+    line: &'a str,
+}
+
+impl<'a> CommandLine<'a> {
+    /// The options of `line`
+    pub const fn new(line: &'a str) -> Self {
+        Self { line }
+    }
+
+    /// The whole command line.
+    pub fn as_str(&self) -> &'a str {
+        self.line
+    }
+
+    /// Every option, as its key, and its value if it is not a bare flag.
+    pub fn options(&self) -> impl Iterator<Item = (&'a str, Option<&'a str>)> + 'a {
+        self.line.split_ascii_whitespace().map(|option| match option.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (option, None),
+        })
+    }
+
+    /// Wether `key` was passed, as a flag or with a value.
+    pub fn has(&self, key: &str) -> bool {
+        self.options().any(|(option, _)| option == key)
+    }
+
+    /// The value of `key`, `None` if it was not passed, or only as a flag.
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.options().filter(|&(option, _)| option == key).filter_map(|(_, value)| value).last()
+    }
+
+    /// The value of `key`, parsed.
+    pub fn parse<T: FromStr>(&self, key: &str) -> Option<Result<T, T::Err>> {
+        self.get(key).map(str::parse)
+    }
+
+    /// The value of `key` as a boolean, see [`parse_bool`]. A bare flag is `true`.
+    pub fn get_bool(&self, key: &str) -> Option<Result<bool, ParseBoolError>> {
+        match self.get(key) {
+            Some(value) => Some(parse_bool(value)),
+            None => self.has(key).then_some(Ok(true)),
+        }
+    }
+
+    /// The value of `key` as a size in bytes, see [`parse_size`]
+    pub fn get_size(&self, key: &str) -> Option<Result<usize, ParseSizeError>> {
+        self.get(key).map(parse_size)
+    }
+}
+
+/// The value is not a boolean.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseBoolError;
+
+impl fmt::Display for ParseBoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected one of on, off, yes, no, true, false, 1 or 0")
+    }
+}
+
+/// Parses `on`, `yes`, `true` and `1`, or `off`, `no`, `false` and `0`, ignoring case.
+pub fn parse_bool(value: &str) -> Result<bool, ParseBoolError> {
+    const TRUE: [&str; 4] = ["on", "yes", "true", "1"];
+    const FALSE: [&str; 4] = ["off", "no", "false", "0"];
+    if TRUE.iter().any(|name| name.eq_ignore_ascii_case(value)) {
+        Ok(true)
+    } else if FALSE.iter().any(|name| name.eq_ignore_ascii_case(value)) {
+        Ok(false)
+    } else {
+        Err(ParseBoolError)
+    }
+}
+
+/// The value is not a size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseSizeError;
+
+impl fmt::Display for ParseSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected a number of bytes, with an optional K, M or G suffix")
+    }
+}
+
+/// Parses a number of bytes, in decimal or with `0x` in hexadecimal, with an optional `K`, `M`
+/// or `G` suffix (powers of 1024), ignoring case.
+pub fn parse_size(value: &str) -> Result<usize, ParseSizeError> {
+    let (number, shift) = match value.as_bytes().last().map(u8::to_ascii_uppercase) {
+        Some(b'K') => (&value[..value.len() - 1], 10),
+        Some(b'M') => (&value[..value.len() - 1], 20),
+        Some(b'G') => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    let number = match number.strip_prefix("0x").or_else(|| number.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => number.parse(),
+    };
+    number.ok().and_then(|number| number.checked_mul(1 << shift)).ok_or(ParseSizeError)
+}
+
+static COMMAND_LINE: InterruptSafeOnceCell<&'static str> = InterruptSafeOnceCell::new();
+
+/// Sets the command line, only the first call has an effect.
+/// 
+/// This is called before the kernel is [initialized](crate::init), so it can honor the options.
+pub fn init(line: &'static str) {
+    let _ = COMMAND_LINE.set(line);
+}
+
+/// The kernel command line, empty before [`init`]
+pub fn command_line() -> CommandLine<'static> {
+    CommandLine::new(COMMAND_LINE.get().copied().unwrap_or(""))
+}
+
+/// Tests reading options, and parsing booleans and sizes.
+#[cfg(feature = "test")]
+pub fn test_command_line(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    let line = CommandLine::new("quiet  loglevel=debug serial=off root= heap_size=1M loglevel=trace");
+    test_assert_eq!(line.options().count(), 6)?;
+    test_assert_eq!(line.options().next(), Some(("quiet", None)))?;
+    test_assert_eq!(line.get("loglevel"), Some("trace"))?;
+    test_assert_eq!(line.get("root"), Some(""))?;
+    test_assert_eq!(line.get("quiet"), None)?;
+    test_assert!(line.has("quiet") && !line.has("video"))?;
+    test_assert_eq!(line.get_bool("quiet"), Some(Ok(true)))?;
+    test_assert_eq!(line.get_bool("serial"), Some(Ok(false)))?;
+    test_assert_eq!(line.get_bool("loglevel"), Some(Err(ParseBoolError)))?;
+    test_assert_eq!(line.get_bool("ata"), None)?;
+    test_assert_eq!(line.get_size("heap_size"), Some(Ok(1024 * 1024)))?;
+    test_assert_eq!(line.parse::<crate::log::Level>("loglevel"), Some(Ok(crate::log::Level::Trace)))?;
+
+    test_assert_eq!(parse_size("4096"), Ok(4096))?;
+    test_assert_eq!(parse_size("0x10k"), Ok(16 * 1024))?;
+    test_assert_eq!(parse_size("2G"), Ok(2 << 30))?;
+    test_assert_eq!(parse_size("M"), Err(ParseSizeError))?;
+    test_assert_eq!(parse_size("1T"), Err(ParseSizeError))?;
+    test_assert_eq!(parse_bool("YES"), Ok(true))
+}
@@ -0,0 +1,8 @@
+//! Information passed by the bootloader.
+//! 
+//! The [`BootInfo`](crate::c_lib::BootInfo) is read from the C entry, everything else the
+//! bootloader passed is in the [multiboot2 information](multiboot2), and the options are on the
+//! [command line](cmdline).
+
+pub mod cmdline;
+pub mod multiboot2;
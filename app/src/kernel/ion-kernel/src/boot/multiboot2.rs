@@ -0,0 +1,379 @@
+//! The multiboot2 information structure, passed by the bootloader.
+//! 
+//! [`Info`] is validated once, when it is created: it must be 8 byte aligned, its total size
+//! must cover its tags, and the tags must end with an end tag. Afterwards, the
+//! [tags](Info::tags) and the typed accessors never read outside of it.
+//! 
+//! ```rust,no_run
+//! let info = boot_info.multiboot2()?;
+//! for entry in info.memory_map().into_iter().flatten() {
+//!     println!("{:#x}..{:#x}", entry.start_addr(), entry.end_addr());
+//! }
+//! ```
+use core::{ffi::CStr, fmt::{self, Display}};
+
+use crate::c_lib::{BootModule, FrameBufferInfo, MemoryMapEntry, MultibootTagType, VbeInfo};
+
+/// The value of `eax` after a multiboot2 bootloader jumped to the kernel.
+pub const MAGIC: u32 = 0x36d7_6289;
+
+/// The size of the header of the info, and of each tag.
+const HEADER_SIZE: usize = 8;
+
+/// Why the info is not valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiboot2Error {
+    /// The bootloader is not multiboot2 compliant, the magic was this.
+    BadMagic(u32),
+    /// The pointer to the info is null, or not 8 byte aligned.
+    BadAddress(u64),
+    /// The total size is smaller than the header and the end tag, or not a multiple of 8.
+    BadSize(u32),
+    /// A tag does not fit into the total size.
+    TagOutOfBounds(usize),
+    /// The tags do not end with an end tag.
+    NoEndTag,
+}
+
+impl Display for Multiboot2Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic(magic) => write!(f, "bad multiboot2 magic {magic:#x}, expected {MAGIC:#x}"),
+            Self::BadAddress(addr) => write!(f, "the multiboot2 info at {addr:#x} is not 8 byte aligned"),
+            Self::BadSize(size) => write!(f, "the multiboot2 info has an invalid size of {size} bytes"),
+            Self::TagOutOfBounds(offset) => write!(f, "the multiboot2 tag at offset {offset} is out of bounds"),
+            Self::NoEndTag => write!(f, "the multiboot2 tags have no end tag"),
+        }
+    }
+}
+
+impl core::error::Error for Multiboot2Error {}
+
+/// Checks the `magic` value the bootloader passed.
+/// # Errors
+/// Returns [`Multiboot2Error::BadMagic`] if it is not [`MAGIC`]
+pub fn check_magic(magic: u32) -> Result<(), Multiboot2Error> {
+    match magic {
+        MAGIC => Ok(()),
+        magic => Err(Multiboot2Error::BadMagic(magic)),
+    }
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// A tag, see [`Info::tags`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tag {
+    /// The type, see [`MultibootTagType`]
+    pub typ: u32,
+    /// The tag, including its type and size.
+    pub bytes: &'static [u8],
+}
+
+impl Tag {
+    /// Wether the tag has the type `typ`
+    pub fn is(&self, typ: MultibootTagType) -> bool {
+        self.typ == typ as u32
+    }
+
+    /// The tag, after its type and size.
+    pub fn data(&self) -> &'static [u8] {
+        &self.bytes[HEADER_SIZE..]
+    }
+}
+
+/// The tags of an [`Info`], in order.
+#[derive(Debug, Clone)]
+pub struct Tags {
+    bytes: &'static [u8],
+    offset: usize,
+}
+
+impl Iterator for Tags {
+    type Item = Tag;
+
+    fn next(&mut self) -> Option<Tag> {
+        let typ = u32_at(self.bytes, self.offset)?;
+        let size = u32_at(self.bytes, self.offset + 4)? as usize;
+        if typ == MultibootTagType::End as u32 || size < HEADER_SIZE {
+            return None;
+        }
+        let bytes = self.bytes.get(self.offset..self.offset + size)?;
+        self.offset = (self.offset + size).next_multiple_of(8);
+        Some(Tag { typ, bytes })
+    }
+}
+
+/// The validated multiboot2 information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Info {
+    bytes: &'static [u8],
+}
+
+impl Info {
+    /// Validates the info in `bytes`, see the [module docs](self)
+    /// # Errors
+    /// Returns an error if the info is not valid.
+    pub fn new(bytes: &'static [u8]) -> Result<Self, Multiboot2Error> {
+        if !(bytes.as_ptr() as usize).is_multiple_of(8) {
+            return Err(Multiboot2Error::BadAddress(bytes.as_ptr() as u64));
+        }
+        let size = u32_at(bytes, 0).ok_or(Multiboot2Error::BadSize(bytes.len() as u32))?;
+        let total = size as usize;
+        if total < 2 * HEADER_SIZE || !total.is_multiple_of(8) || total > bytes.len() {
+            return Err(Multiboot2Error::BadSize(size));
+        }
+        let bytes = &bytes[..total];
+        let mut offset = HEADER_SIZE;
+        loop {
+            let (typ, size) = u32_at(bytes, offset).zip(u32_at(bytes, offset + 4)).ok_or(Multiboot2Error::NoEndTag)?;
+            let size = size as usize;
+            if size < HEADER_SIZE || offset + size > total {
+                return Err(Multiboot2Error::TagOutOfBounds(offset));
+            }
+            if typ == MultibootTagType::End as u32 {
+                return Ok(Self { bytes });
+            }
+            offset = (offset + size).next_multiple_of(8);
+        }
+    }
+
+    /// Validates the info at `addr`
+    /// # Errors
+    /// Returns an error if `addr` is null or not aligned, or the info is not valid.
+    /// # Safety
+    /// If `addr` is aligned, and not null, it must point to memory which is identity mapped and
+    /// stays valid, and which starts with its total size.
+    pub unsafe fn from_addr(addr: u64) -> Result<Self, Multiboot2Error> {
+        if addr == 0 || !addr.is_multiple_of(8) {
+            return Err(Multiboot2Error::BadAddress(addr));
+        }
+        // Safety: the caller guarantees the info starts with its total size.
+        let total = unsafe { (addr as usize as *const u32).read() };
+        // Safety: as above, the info is `total` bytes long.
+        Self::new(unsafe { core::slice::from_raw_parts(addr as usize as *const u8, total as usize) })
+    }
+
+    /// The physical address of the info.
+    pub fn addr(&self) -> u64 {
+        self.bytes.as_ptr() as u64
+    }
+
+    /// The total size of the info, in bytes.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Wether the info has no tags, only the end tag.
+    pub fn is_empty(&self) -> bool {
+        self.tags().next().is_none()
+    }
+
+    /// The tags, without the end tag.
+    pub fn tags(&self) -> Tags {
+        Tags { bytes: self.bytes, offset: HEADER_SIZE }
+    }
+
+    /// The first tag of type `typ`
+    pub fn tag(&self, typ: MultibootTagType) -> Option<Tag> {
+        self.tags().find(|tag| tag.is(typ))
+    }
+
+    /// The kernel command line, `None` if there is none, or it is not UTF-8.
+    pub fn command_line(&self) -> Option<&'static str> {
+        let tag = self.tag(MultibootTagType::CommandLine)?;
+        CStr::from_bytes_until_nul(tag.data()).ok()?.to_str().ok()
+    }
+
+    /// The name of the bootloader, E.g. `GRUB 2.12`
+    pub fn bootloader_name(&self) -> Option<&'static str> {
+        let tag = self.tag(MultibootTagType::BootLoaderName)?;
+        CStr::from_bytes_until_nul(tag.data()).ok()?.to_str().ok()
+    }
+
+    /// The modules loaded by the bootloader.
+    pub fn modules(&self) -> impl Iterator<Item = BootModule> {
+        self.tags().filter(|tag| tag.is(MultibootTagType::Module)).filter_map(|tag| BootModule::parse(tag.bytes))
+    }
+
+    /// The frame buffer, if the bootloader set one up.
+    pub fn frame_buffer(&self) -> Option<FrameBufferInfo> {
+        FrameBufferInfo::parse(self.tag(MultibootTagType::FramebufferInfo)?.bytes)
+    }
+
+    /// The VBE information, if the bootloader set up the frame buffer through VBE.
+    pub fn vbe(&self) -> Option<VbeInfo> {
+        VbeInfo::parse(self.tag(MultibootTagType::VbeInfo)?.bytes)
+    }
+
+    /// The copy of the ACPI RSDP, the one of ACPI 2.0 if there are both.
+    pub fn acpi_rsdp(&self) -> Option<&'static [u8]> {
+        let tag = self.tag(MultibootTagType::AcpiNewRsdp).or_else(|| self.tag(MultibootTagType::AcpiOldRsdp))?;
+        Some(tag.data())
+    }
+
+    /// The entries of the memory map, `None` if there is no memory map.
+    pub fn memory_map(&self) -> Option<MemoryMap> {
+        let tag = self.tag(MultibootTagType::MemoryMap)?;
+        let entry_size = u32_at(tag.bytes, 8)? as usize;
+        (entry_size >= 24).then_some(MemoryMap { entries: tag.bytes.get(16..)?, entry_size })
+    }
+
+    /// The section headers of the kernel ELF file, `None` if the bootloader did not pass them.
+    pub fn elf_sections(&self) -> Option<ElfSections> {
+        let tag = self.tag(MultibootTagType::ElfSections)?;
+        let (count, entry_size, names) = (u32_at(tag.bytes, 8)?, u32_at(tag.bytes, 12)?, u32_at(tag.bytes, 16)?);
+        (entry_size as usize >= ElfSection::SIZE).then_some(ElfSections {
+            headers: tag.bytes.get(20..)?,
+            count: count as usize,
+            entry_size: entry_size as usize,
+            names: names as usize,
+        })
+    }
+}
+
+impl Display for Info {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "multiboot2 info at {:#x}, {} bytes, {} tags", self.addr(), self.len(), self.tags().count())
+    }
+}
+
+/// The entries of the memory map tag, see [`Info::memory_map`]
+#[derive(Debug, Clone)]
+pub struct MemoryMap {
+    entries: &'static [u8],
+    entry_size: usize,
+}
+
+impl Iterator for MemoryMap {
+    type Item = MemoryMapEntry;
+
+    fn next(&mut self) -> Option<MemoryMapEntry> {
+        let entry = self.entries.get(..self.entry_size)?;
+        self.entries = &self.entries[self.entry_size..];
+        Some(MemoryMapEntry {
+            addr: u64_at(entry, 0)?,
+            len: u64_at(entry, 8)?,
+            entry_type: u32_at(entry, 16)?,
+            reserved: u32_at(entry, 20)?,
+        })
+    }
+}
+
+/// An ELF section header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElfSection {
+    /// The offset of the name, in the section name table.
+    pub name: u32,
+    /// The section type, E.g. 1 for program data.
+    pub typ: u32,
+    /// The section flags.
+    pub flags: u64,
+    /// The address the section was loaded at, 0 if it was not loaded.
+    pub addr: u64,
+    /// The size in bytes.
+    pub size: u64,
+}
+
+impl ElfSection {
+    /// The size of a 64 bit section header.
+    pub const SIZE: usize = 64;
+
+    fn parse(header: &[u8]) -> Option<Self> {
+        Some(Self {
+            name: u32_at(header, 0)?,
+            typ: u32_at(header, 4)?,
+            flags: u64_at(header, 8)?,
+            addr: u64_at(header, 16)?,
+            size: u64_at(header, 32)?,
+        })
+    }
+
+    /// The bytes of the section, `None` if it was not loaded.
+    /// # Safety
+    /// The section must be identity mapped, and stay valid.
+    pub unsafe fn bytes(&self) -> Option<&'static [u8]> {
+        // Safety: the caller guarantees the section is mapped.
+        (self.addr != 0).then(|| unsafe { core::slice::from_raw_parts(self.addr as usize as *const u8, self.size as usize) })
+    }
+}
+
+/// The section headers of the kernel, see [`Info::elf_sections`]
+#[derive(Debug, Clone, Copy)]
+pub struct ElfSections {
+    headers: &'static [u8],
+    count: usize,
+    entry_size: usize,
+    /// The index of the section name table.
+    names: usize,
+}
+
+impl ElfSections {
+    /// The section at `index`
+    pub fn get(&self, index: usize) -> Option<ElfSection> {
+        if index >= self.count {
+            return None;
+        }
+        ElfSection::parse(self.headers.get(index * self.entry_size..(index + 1) * self.entry_size)?)
+    }
+
+    /// Every section.
+    pub fn iter(&self) -> impl Iterator<Item = ElfSection> + '_ {
+        (0..self.count).map_while(|index| self.get(index))
+    }
+
+    /// The loaded section called `name`, E.g. `.debug_line`
+    /// # Safety
+    /// The section name table must be identity mapped, and stay valid.
+    pub unsafe fn find(&self, name: &str) -> Option<ElfSection> {
+        // Safety: the caller guarantees the name table is mapped.
+        let names = unsafe { self.get(self.names)?.bytes()? };
+        self.iter().find(|section| {
+            let section_name = names.get(section.name as usize..).and_then(|names| CStr::from_bytes_until_nul(names).ok());
+            section.addr != 0 && section_name.is_some_and(|section_name| section_name.to_bytes() == name.as_bytes())
+        })
+    }
+}
+
+/// Tests validating the info, and reading its tags.
+#[cfg(feature = "test")]
+pub fn test_multiboot2(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    #[repr(C, align(8))]
+    struct Aligned<const N: usize>([u8; N]);
+
+    static INFO: Aligned<96> = Aligned([
+        96, 0, 0, 0, 0, 0, 0, 0,
+        // command line: "quiet"
+        1, 0, 0, 0, 14, 0, 0, 0, b'q', b'u', b'i', b'e', b't', 0, 0, 0,
+        // a module at 0x1000..0x2000, "initrd"
+        3, 0, 0, 0, 23, 0, 0, 0, 0, 0x10, 0, 0, 0, 0x20, 0, 0, b'i', b'n', b'i', b't', b'r', b'd', 0, 0,
+        // a memory map, with one usable entry of 1 MiB at 1 MiB
+        6, 0, 0, 0, 40, 0, 0, 0, 24, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0x10, 0, 0, 0, 0, 0, 0, 0, 0x10, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0,
+        // end
+        0, 0, 0, 0, 8, 0, 0, 0,
+    ]);
+    let info = Info::new(&INFO.0).map_err(|_| "the info was not valid")?;
+    test_assert_eq!(info.tags().count(), 3)?;
+    test_assert_eq!(info.command_line(), Some("quiet"))?;
+    test_assert_eq!(info.bootloader_name(), None)?;
+    let module = info.modules().next().ok_or("the module was not found")?;
+    test_assert_eq!((module.start, module.end, module.name), (0x1000, 0x2000, "initrd"))?;
+    let entry = info.memory_map().and_then(|mut map| map.next()).ok_or("the memory map was not found")?;
+    test_assert_eq!((entry.addr, entry.len, entry.entry_type), (0x10_0000, 0x10_0000, 1))?;
+    test_assert!(info.elf_sections().is_none())?;
+
+    // the command line tag does not fit.
+    static TRUNCATED: Aligned<24> = Aligned([24, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    test_assert_eq!(Info::new(&TRUNCATED.0), Err(Multiboot2Error::TagOutOfBounds(8)))?;
+    test_assert_eq!(Info::new(&INFO.0[..16]), Err(Multiboot2Error::BadSize(96)))?;
+    test_assert_eq!(check_magic(0x2bad_b002), Err(Multiboot2Error::BadMagic(0x2bad_b002)))
+}
@@ -79,6 +79,11 @@ impl<Int: Uint> BitFlags<Int> {
         Self { int: i }
     }
 
+    /// Returns the inner integer.
+    pub const fn into_inner(self) -> Int {
+        self.int
+    }
+
     /// Creates a new unset `BitFlags`
     pub const fn new_unset() -> Self {
         Self { int: Int::ZEROED }
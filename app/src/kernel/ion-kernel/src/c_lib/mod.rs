@@ -1,18 +1,125 @@
-use core::{ffi::CStr, fmt::Debug, marker::PhantomData, ptr::NonNull};
+use core::{ffi::CStr, fmt::{Debug, Display}, marker::PhantomData, ptr::NonNull};
 
-use crate::{c_lib::bit_flags::BitFlags, serial_println};
+use crate::{arch::cpuid::FeatureRegisters, boot::multiboot2, c_lib::bit_flags::BitFlags, collections::ArrayVec, serial_println};
 
 /// module containing tools for handling Bit Flags
 pub mod bit_flags;
 /// module for handling bits.
 pub mod bit;
 
+/// Magic value at the start of every [`BootInfoHeader`] ("IONB")
+pub const BOOT_INFO_MAGIC: u32 = 0x424E_4F49;
+
+/// The [`BootInfoInput`] layout version this kernel understands.
+/// 
+/// Version 1 is the legacy layout, which had no header at all.
+/// 
+/// Newer boot stages may report a higher version, as long as they only append fields.
+pub const BOOT_INFO_VERSION: u16 = 2;
+
+/// Versioned header placed at the start of [`BootInfoInput`]
+/// 
+/// This allows the boot stage and the kernel to evolve independently.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BootInfoHeader {
+    /// Always [`BOOT_INFO_MAGIC`]
+    pub magic: u32,
+    /// Layout version, see [`BOOT_INFO_VERSION`]
+    pub version: u16,
+    /// Total length of the boot info, including this header.
+    pub length: u16,
+    /// Which optional fields were filled in by the boot stage.
+    pub features: BootFeatures,
+    /// Reserved for future use.
+    pub reserved: u32,
+}
+
+impl BootInfoHeader {
+    /// Validates the header.
+    /// # Errors
+    /// Returns an error if the header is not a [`BootInfoHeader`] this kernel can read.
+    pub fn validate(&self) -> Result<(), BootInfoError> {
+        if self.magic != BOOT_INFO_MAGIC {
+            // the legacy layout starts directly with the multiboot magic.
+            return if self.magic == MultibootMagic::Multiboot2 as u32
+                || self.magic == MultibootMagic::Multiboot1 as u32 
+            {
+                Err(BootInfoError::Legacy)
+            } else {
+                Err(BootInfoError::BadMagic(self.magic))
+            };
+        }
+        if self.version < BOOT_INFO_VERSION {
+            return Err(BootInfoError::UnsupportedVersion(self.version));
+        }
+        if (self.length as usize) < size_of::<BootInfoInput>() {
+            return Err(BootInfoError::TooShort(self.length));
+        }
+        Ok(())
+    }
+}
+
+/// Feature bits of a [`BootInfoHeader`]
+/// 
+/// Each bit marks an optional field of [`BootInfoInput`] as filled in.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BootFeatures(BitFlags);
+
+impl BootFeatures {
+    /// `memory_map_addr` points to a multiboot memory map.
+    pub const MEMORY_MAP: usize = 0;
+    /// `framebuffer_addr` is set.
+    pub const FRAMEBUFFER: usize = 1;
+    /// `cpuid_edx` and `cpuid_ecx` are set.
+    /// 
+    /// These are legacy fields, the kernel queries cpuid itself.
+    pub const CPUID: usize = 2;
+
+    /// Returns wether the feature bit `feature` is set.
+    pub const fn contains(&self, feature: usize) -> bool {
+        self.0.read_flag(feature)
+    }
+}
+
+/// An Error while reading the [`BootInfoInput`]
+#[derive(Debug, Clone)]
+pub enum BootInfoError {
+    /// The boot stage uses the legacy (version 1) layout, without a header.
+    Legacy,
+    /// The header's magic is not [`BOOT_INFO_MAGIC`]
+    BadMagic(u32),
+    /// The layout version is older than [`BOOT_INFO_VERSION`]
+    UnsupportedVersion(u16),
+    /// The length in the header is too short for a [`BootInfoInput`]
+    TooShort(u16),
+    /// The header is valid, but the C Entry rejected the contents.
+    Invalid(BootInfoInput),
+}
+
+impl Display for BootInfoError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Legacy => write!(f, "the bootloader uses the legacy boot info layout (version 1), which is no longer supported"),
+            Self::BadMagic(m) => write!(f, "bad boot info magic {m:#x}, expected {BOOT_INFO_MAGIC:#x}"),
+            Self::UnsupportedVersion(v) => write!(f, "boot info version {v} is not supported, expected at least {BOOT_INFO_VERSION}"),
+            Self::TooShort(l) => write!(f, "boot info is {l} bytes long, expected at least {}", size_of::<BootInfoInput>()),
+            Self::Invalid(_) => write!(f, "the boot info was rejected by the C Entry"),
+        }
+    }
+}
+
+impl core::error::Error for BootInfoError {}
+
 /// The Actual BootInfo used, in raw numbers
 /// 
 /// see [`BootInfo`] for Rust Types.
 #[repr(C)]
 #[derive(Clone, Debug)]
 pub struct BootInfoInput {
+    /// Versioned header
+    pub header: BootInfoHeader,
     /// Multiboot magic value
     /// 
     /// equal to 0x36d76289
@@ -20,15 +127,21 @@ pub struct BootInfoInput {
     /// multiboot info
     pub multiboot_info: u32,
     /// The edx register after querying the `cpuid` command
+    /// 
+    /// Only set with [`BootFeatures::CPUID`]
     pub cpuid_edx: u32,
     /// The ecx register after querying the `cpuid` command
+    /// 
+    /// Only set with [`BootFeatures::CPUID`]
     pub cpuid_ecx: u32,
     /// address of the page table base
     pub page_table_base: u64,
     /// stack's top
     pub stack_top: u64,
-    /// Frame Buffer Address, currently always set to 0 due to lack of implementation
-    // TODO: impl
+    /// Frame Buffer Address, from the multiboot framebuffer tag.
+    /// 
+    /// Only set with [`BootFeatures::FRAMEBUFFER`], see [`BootInfo::frame_buffer`] for the rest of
+    /// the tag.
     pub framebuffer_addr: u64,
     /// Memory Map Address, currently always set to 0 due to lack of implementation.
     // TODO: impl
@@ -38,21 +151,29 @@ pub struct BootInfoInput {
 }
 
 impl BootInfoInput {
+    /// The kernel command line, empty if the bootloader passed none.
+    pub fn command_line(&self) -> &'static str {
+        // Safety: the multiboot info is identity mapped, and was checked by the C Entry.
+        let multiboot = unsafe { multiboot2::Info::from_addr(u64::from(self.multiboot_info)) }.ok();
+        multiboot.and_then(|info| info.command_line()).unwrap_or("")
+    }
+
     /// converts the [`BootInfoInput`] into a rust type
     pub fn into_rust(self) -> BootInfo {
         use core::{ptr::{without_provenance_mut, without_provenance}, mem};
+        // Safety: the multiboot info is identity mapped, and was checked by the C Entry.
+        let multiboot = unsafe { multiboot2::Info::from_addr(u64::from(self.multiboot_info)) }.ok();
         BootInfo { 
-            cpuid_ecx: BitFlags::new(self.cpuid_ecx),
-            cpuid_edx: BitFlags::new(self.cpuid_edx),
+            version: self.header.version,
+            features: self.header.features,
+            cpuid: self.header.features.contains(BootFeatures::CPUID).then(|| FeatureRegisters {
+                edx: BitFlags::new(self.cpuid_edx),
+                ecx: BitFlags::new(self.cpuid_ecx),
+            }),
             // Safety: kernel entry is always set.
             kernel_entry: unsafe { mem::transmute::<usize, unsafe extern "C" fn(BootInfoInput) -> !>(self.kernel_entry as usize) },
             // Safety: We cast a u32 to a usize, which means the address is always valid
-            multiboot_info: { 
-                let ptr: SmallPtr<MultibootTag> = unsafe { SmallPtr::new_unchecked(without_provenance(self.multiboot_info as usize)) };
-                let inner = unsafe { ptr.into_inner().as_ref().unwrap() };
-                serial_println!("{:#?}", inner);
-                ptr
-            } ,
+            multiboot_info: unsafe { SmallPtr::new_unchecked(without_provenance(self.multiboot_info as usize)) },
             multiboot_magic: {
                 if self.multiboot_magic == 0x36d76289 {
                     MultibootMagic::Multiboot2
@@ -64,7 +185,20 @@ impl BootInfoInput {
             },
             page_table_base: NonNull::new(without_provenance_mut(self.page_table_base as usize)).unwrap(),
             stack_top: NonNull::new(without_provenance_mut(self.stack_top as usize)).unwrap(),
-            frame_buffer: NonNull::new(without_provenance_mut(self.framebuffer_addr as usize)).unwrap(),
+            frame_buffer: multiboot
+                .filter(|_| self.header.features.contains(BootFeatures::FRAMEBUFFER))
+                .and_then(|info| info.frame_buffer()),
+            command_line: self.command_line(),
+            vbe: multiboot.and_then(|info| info.vbe()),
+            modules: {
+                let mut modules = ArrayVec::new();
+                for module in multiboot.iter().flat_map(multiboot2::Info::modules) {
+                    if modules.push(module).is_err() {
+                        break;
+                    }
+                }
+                modules
+            },
             mem_map_addr: {
                 let data_ptr = self.memory_map_addr as *const MultibootMemoryIntermediate;
                 let header = unsafe {
@@ -133,6 +267,10 @@ impl Debug for MultibootMagic {
 #[derive(Debug, Clone)]
 /// Boot info, with proper Rust types.
 pub struct BootInfo {
+    /// Layout version reported by the boot stage.
+    pub version: u16,
+    /// Optional fields set by the boot stage.
+    pub features: BootFeatures,
     /// Multiboot magic value
     /// 
     /// equal to 0x36d76289 on multiboot2
@@ -143,20 +281,23 @@ pub struct BootInfo {
     // must keep the the memory layout the same as 
     // BootInfoInput
     pub multiboot_info: SmallPtr<MultibootTag>, 
-    /// The edx register after querying the `cpuid` command
+    /// The registers after the boot stage queried the `cpuid` command, if it did.
     /// 
-    /// this is stored as bit flags.
-    pub cpuid_edx: BitFlags,
-    /// The ecx register after querying the `cpuid` command
-    /// 
-    /// this is stored as bit flags
-    pub cpuid_ecx: BitFlags,
+    /// These values may be stale, and are no longer required by the boot info ABI. Use
+    /// [`FeatureRegisters::query`] instead.
+    pub cpuid: Option<FeatureRegisters>,
     /// pointer to page table base
     pub page_table_base: NonNull<()>,
     /// pointer to stack top
     pub stack_top: NonNull<()>,
-    /// pointer to frame buffer.
-    pub frame_buffer: NonNull<()>,
+    /// The frame buffer set up by the bootloader, if there is one.
+    pub frame_buffer: Option<FrameBufferInfo>,
+    /// The kernel command line, empty if the bootloader passed none.
+    pub command_line: &'static str,
+    /// The VBE information, if the bootloader set up the frame buffer through VBE.
+    pub vbe: Option<VbeInfo>,
+    /// The modules loaded by the bootloader, E.g. an initrd image, at most [`MAX_MODULES`]
+    pub modules: ArrayVec<BootModule, MAX_MODULES>,
     /// pointer to memory map.
     pub mem_map_addr: NonNull<MultibootMemory>,
     /// C kernel entry, as a function pointer
@@ -170,6 +311,16 @@ pub struct BootInfo {
 }
 
 
+impl BootInfo {
+    /// The multiboot2 information, validated.
+    /// # Errors
+    /// Returns an error if the bootloader passed an invalid structure.
+    pub fn multiboot2(&self) -> Result<multiboot2::Info, multiboot2::Multiboot2Error> {
+        // Safety: the multiboot info is identity mapped, and reserved at boot.
+        unsafe { multiboot2::Info::from_addr(self.multiboot_info.into_inner() as u64) }
+    }
+}
+
 /// C BootInfo, passed in to the main function.
 #[repr(C)]
 #[derive(Debug)]
@@ -181,20 +332,26 @@ pub struct BootInfoC {
 impl BootInfoC {
     /// Returns the inner value
     /// # Errors
-    /// This Function returns the value either way, but if it is in the [`Err`] variant, it means the
-    /// [`BootInfo`] was not valid.
-    pub fn into_inner(self) -> Result<BootInfoInput, BootInfoInput> {
-        let f = if self.valid {
-            Ok
-        } else {
-            Err
-        };
+    /// This Function returns an error if the [`BootInfoHeader`] can not be read by this kernel, or
+    /// if the C Entry marked the [`BootInfo`] as invalid (in which case the value is still returned, 
+    /// in [`BootInfoError::Invalid`]).
+    pub fn into_inner(self) -> Result<BootInfoInput, BootInfoError> {
         if self.input_ptr.is_null() {
             panic!("MEMORY ERROR: BOOT INFO POINTER IS INVALID  ({:?}) (aligned: {})", self.input_ptr, self.input_ptr.is_aligned());
         }
 
-        // Safety: We check the pointer is non-null, and we ensure in C this pointer is never invalid.
-        f(unsafe { self.input_ptr.read_unaligned() })
+        // Safety: We check the pointer is non-null, and every layout version starts with at least
+        // as many bytes as the header.
+        let header = unsafe { self.input_ptr.cast::<BootInfoHeader>().read_unaligned() };
+        header.validate()?;
+
+        // Safety: We check the pointer is non-null, and the header says the data is long enough.
+        let input = unsafe { self.input_ptr.read_unaligned() };
+        if self.valid {
+            Ok(input)
+        } else {
+            Err(BootInfoError::Invalid(input))
+        }
     }
 }
 
@@ -365,6 +522,187 @@ pub struct MultibootFramebufferTag {
     pub reserved: u16,
 }
 
+/// How the pixels of a [`FrameBufferInfo`] are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Direct color, with the position and the size (in bits) of each channel.
+    Rgb {
+        /// Position and size of red.
+        red: (u8, u8),
+        /// Position and size of green.
+        green: (u8, u8),
+        /// Position and size of blue.
+        blue: (u8, u8),
+    },
+    /// Colors are indices into a palette.
+    Indexed,
+    /// EGA text mode, the width and height are in characters.
+    Text,
+}
+
+/// A frame buffer, parsed from the multiboot framebuffer tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameBufferInfo {
+    /// Physical address of the frame buffer.
+    pub addr: u64,
+    /// Bytes per row.
+    pub pitch: u32,
+    /// Width, in pixels (or characters, in text mode).
+    pub width: u32,
+    /// Height, in pixels (or characters, in text mode).
+    pub height: u32,
+    /// Bits per pixel.
+    pub bpp: u8,
+    /// How pixels are stored.
+    pub format: PixelFormat,
+}
+
+impl FrameBufferInfo {
+    /// Parses a multiboot framebuffer tag, including its type and size.
+    /// 
+    /// Returns `None` if the tag is too short, or has an unknown type.
+    pub fn parse(tag: &[u8]) -> Option<Self> {
+        let u32_at = |at: usize| Some(u32::from_le_bytes(tag.get(at..at + 4)?.try_into().ok()?));
+        let format = match *tag.get(29)? {
+            0 => PixelFormat::Indexed,
+            1 => {
+                let color = tag.get(32..38)?;
+                PixelFormat::Rgb { red: (color[0], color[1]), green: (color[2], color[3]), blue: (color[4], color[5]) }
+            }
+            2 => PixelFormat::Text,
+            _ => return None,
+        };
+        Some(Self {
+            addr: u64::from(u32_at(8)?) | u64::from(u32_at(12)?) << 32,
+            pitch: u32_at(16)?,
+            width: u32_at(20)?,
+            height: u32_at(24)?,
+            bpp: *tag.get(28)?,
+            format,
+        })
+    }
+
+    /// Size of the frame buffer, in bytes.
+    pub fn size(&self) -> u64 {
+        u64::from(self.pitch) * u64::from(self.height)
+    }
+
+    /// Whether this is a graphical frame buffer, instead of EGA text mode.
+    pub fn is_graphical(&self) -> bool {
+        self.format != PixelFormat::Text
+    }
+}
+
+/// The VBE controller and current mode, parsed from the multiboot VBE info tag.
+/// 
+/// Only BIOS boots have this tag, UEFI (GOP) boots only pass the [`FrameBufferInfo`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VbeInfo {
+    /// The current VBE mode number.
+    pub mode: u16,
+    /// VBE version, as BCD (`0x0300` is VBE 3.0).
+    pub version: u16,
+    /// Video memory, in bytes.
+    pub memory: u32,
+    /// Physical address of the list of supported mode numbers, which ends with `0xFFFF`
+    pub mode_list: u32,
+    /// Width, height and bits per pixel of the current mode.
+    pub current: (u16, u16, u8),
+}
+
+impl VbeInfo {
+    /// Where the controller info starts in the tag.
+    const CONTROL_INFO: usize = 16;
+    /// Where the mode info starts in the tag.
+    const MODE_INFO: usize = Self::CONTROL_INFO + 512;
+
+    /// Parses a multiboot VBE info tag, including its type and size.
+    /// 
+    /// Returns `None` if the tag is too short, or the controller info has no `VESA` signature.
+    pub fn parse(tag: &[u8]) -> Option<Self> {
+        let u16_at = |at: usize| Some(u16::from_le_bytes(tag.get(at..at + 2)?.try_into().ok()?));
+        let control = Self::CONTROL_INFO;
+        if tag.get(control..control + 4)? != b"VESA" {
+            return None;
+        }
+        // a real mode far pointer, as offset and segment.
+        let mode_list = u32::from(u16_at(control + 16)?) << 4 | u32::from(u16_at(control + 14)?);
+        Some(Self {
+            mode: u16_at(8)?,
+            version: u16_at(control + 4)?,
+            memory: u32::from(u16_at(control + 18)?) << 16,
+            mode_list,
+            current: (u16_at(Self::MODE_INFO + 18)?, u16_at(Self::MODE_INFO + 20)?, *tag.get(Self::MODE_INFO + 25)?),
+        })
+    }
+
+    /// Calls `f` with every supported mode number, at most `max` of them.
+    /// 
+    /// The list is in BIOS memory, which the bootloader may have reused, so the numbers are only a
+    /// hint. A list outside of the first MiB is ignored.
+    /// # Safety
+    /// The first MiB of memory must be identity mapped.
+    pub unsafe fn for_each_mode(&self, max: usize, mut f: impl FnMut(u16)) {
+        if self.mode_list == 0 || self.mode_list as usize + max * 2 > 0x10_0000 {
+            return;
+        }
+        let list = self.mode_list as usize as *const u16;
+        for i in 0..max {
+            // Safety: the list is in the first MiB, which the caller guarantees is mapped.
+            let mode = unsafe { list.add(i).read_unaligned() };
+            if mode == 0xFFFF {
+                break;
+            }
+            f(mode);
+        }
+    }
+}
+
+/// Maximum amount of [`BootModule`]s kept in the [`BootInfo`]
+pub const MAX_MODULES: usize = 8;
+
+/// A module loaded by the bootloader, parsed from a multiboot module tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootModule {
+    /// Physical address of the first byte.
+    pub start: u64,
+    /// Physical address after the last byte.
+    pub end: u64,
+    /// The string after the module on the bootloader's command line, E.g. `initrd`
+    pub name: &'static str,
+}
+
+impl BootModule {
+    /// Parses a multiboot module tag, including its type and size.
+    /// 
+    /// Returns `None` if the tag is too short, or the module ends before it starts.
+    pub fn parse(tag: &'static [u8]) -> Option<Self> {
+        let u32_at = |at: usize| Some(u32::from_le_bytes(tag.get(at..at + 4)?.try_into().ok()?));
+        let (start, end) = (u64::from(u32_at(8)?), u64::from(u32_at(12)?));
+        let name = CStr::from_bytes_until_nul(tag.get(16..)?).ok().and_then(|name| name.to_str().ok()).unwrap_or("");
+        (start <= end).then_some(Self { start, end, name })
+    }
+
+    /// The size in bytes.
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    /// Wether the module is empty.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// The bytes of the module.
+    /// # Safety
+    /// The module must be identity mapped, and [reserved](crate::mem::regions), so it is not
+    /// overwritten.
+    pub unsafe fn bytes(&self) -> &'static [u8] {
+        // Safety: the caller guarantees the module is mapped, and stays valid.
+        unsafe { core::slice::from_raw_parts(self.start as usize as *const u8, self.len() as usize) }
+    }
+}
+
 /// Module Tag
 #[repr(C)]
 #[derive(Debug)]
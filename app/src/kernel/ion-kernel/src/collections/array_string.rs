@@ -0,0 +1,156 @@
+use core::{fmt::{Debug, Display, Write}, ops::Deref};
+
+use super::CapacityError;
+
+/// A UTF-8 string with a fixed capacity of `N` bytes, stored inline.
+/// 
+/// Formatting into a full `ArrayString` truncates the output (at a char boundary), which makes
+/// it suitable for log messages in interrupt handlers.
+/// 
+/// # Example
+/// ```rust,no_run
+/// use core::fmt::Write;
+/// use crate::collections::ArrayString;
+/// 
+/// let mut s = ArrayString::<16>::new();
+/// write!(s, "irq {}", 33).unwrap();
+/// assert_eq!(&*s, "irq 33");
+/// ```
+#[derive(Clone, Copy)]
+pub struct ArrayString<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> ArrayString<N> {
+    /// Creates a new, empty `ArrayString`
+    pub const fn new() -> Self {
+        Self { buf: [0; N], len: 0 }
+    }
+
+    /// Length in bytes.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns wether the string is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The maximum length in bytes, `N`
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the string as a `&str`
+    pub fn as_str(&self) -> &str {
+        // Safety: we only ever push whole UTF-8 sequences.
+        unsafe { core::str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+
+    /// Appends `s`
+    /// # Errors
+    /// Returns an error if `s` does not fit, in which case nothing is appended.
+    pub fn push_str(&mut self, s: &str) -> Result<(), CapacityError> {
+        if s.len() > N - self.len {
+            return Err(CapacityError(()));
+        }
+        self.buf[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+        self.len += s.len();
+        Ok(())
+    }
+
+    /// Appends as much of `s` as fits, returning wether all of it did.
+    pub fn push_str_truncating(&mut self, s: &str) -> bool {
+        let mut end = s.len().min(N - self.len);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        // can not fail, we checked the capacity.
+        let _ = self.push_str(&s[..end]);
+        end == s.len()
+    }
+
+    /// Appends `c`
+    /// # Errors
+    /// Returns an error if `c` does not fit.
+    pub fn push(&mut self, c: char) -> Result<(), CapacityError<char>> {
+        self.push_str(c.encode_utf8(&mut [0; 4])).map_err(|_| CapacityError(c))
+    }
+
+    /// Removes the last char and returns it.
+    pub fn pop(&mut self) -> Option<char> {
+        let c = self.as_str().chars().next_back()?;
+        self.len -= c.len_utf8();
+        Some(c)
+    }
+
+    /// Shortens the string to `len` bytes.
+    /// # Panics
+    /// Panics if `len` is not on a char boundary.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            assert!(self.as_str().is_char_boundary(len), "truncate length {len} is not a char boundary");
+            self.len = len;
+        }
+    }
+
+    /// Removes everything.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl<const N: usize> Default for ArrayString<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Deref for ArrayString<N> {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> Write for ArrayString<N> {
+    /// Appends `s`, truncating it if it does not fit.
+    /// # Errors
+    /// Returns an error if `s` was truncated.
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        if self.push_str_truncating(s) {
+            Ok(())
+        } else {
+            Err(core::fmt::Error)
+        }
+    }
+}
+
+impl<const N: usize> Display for ArrayString<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<const N: usize> Debug for ArrayString<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> PartialEq for ArrayString<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl<const N: usize> Eq for ArrayString<N> {}
+
+impl<const N: usize> PartialEq<str> for ArrayString<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
@@ -0,0 +1,226 @@
+use core::{fmt::Debug, mem::MaybeUninit, ops::{Deref, DerefMut}};
+
+use super::CapacityError;
+
+/// A vector with a fixed capacity of `N`, stored inline.
+/// 
+/// # Example
+/// ```rust,no_run
+/// use crate::collections::ArrayVec;
+/// 
+/// let mut vec = ArrayVec::<u8, 2>::new();
+/// vec.push(1).unwrap();
+/// vec.push(2).unwrap();
+/// assert!(vec.push(3).is_err());
+/// assert_eq!(&*vec, &[1, 2]);
+/// ```
+pub struct ArrayVec<T, const N: usize> {
+    buf: [MaybeUninit<T>; N],
+    len: usize,
+}
+
+impl<T, const N: usize> ArrayVec<T, N> {
+    /// Creates a new, empty `ArrayVec`
+    pub const fn new() -> Self {
+        Self { buf: [const { MaybeUninit::uninit() }; N], len: 0 }
+    }
+
+    /// The amount of elements.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns wether there are no elements.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns wether no more elements fit.
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// The maximum amount of elements, `N`
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Appends `val` to the end.
+    /// # Errors
+    /// Returns `val` in a [`CapacityError`] if the vector is full.
+    pub fn push(&mut self, val: T) -> Result<(), CapacityError<T>> {
+        if self.is_full() {
+            return Err(CapacityError(val));
+        }
+        self.buf[self.len].write(val);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the last element and returns it.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.len -= 1;
+        // Safety: elements below the old `len` are initialized, and we no longer track this one.
+        Some(unsafe { self.buf[self.len].assume_init_read() })
+    }
+
+    /// Inserts `val` at `index`, shifting the following elements.
+    /// # Errors
+    /// Returns `val` in a [`CapacityError`] if the vector is full.
+    /// # Panics
+    /// Panics if `index > len`
+    pub fn insert(&mut self, index: usize, val: T) -> Result<(), CapacityError<T>> {
+        assert!(index <= self.len, "insertion index {index} is out of bounds (len {})", self.len);
+        if self.is_full() {
+            return Err(CapacityError(val));
+        }
+        // Safety: index <= len < N, so both ranges stay inside the buffer.
+        unsafe {
+            let p = self.buf.as_mut_ptr().add(index);
+            core::ptr::copy(p, p.add(1), self.len - index);
+        }
+        self.buf[index].write(val);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the element at `index`, shifting the following elements.
+    /// # Panics
+    /// Panics if `index >= len`
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "removal index {index} is out of bounds (len {})", self.len);
+        // Safety: index < len, so the element is initialized, and the copy stays inside the buffer.
+        unsafe {
+            let val = self.buf[index].assume_init_read();
+            let p = self.buf.as_mut_ptr().add(index);
+            core::ptr::copy(p.add(1), p, self.len - index - 1);
+            self.len -= 1;
+            val
+        }
+    }
+
+    /// Removes the element at `index`, replacing it with the last element.
+    /// # Panics
+    /// Panics if `index >= len`
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        let last = self.len - 1;
+        self.as_mut_slice().swap(index, last);
+        // can not fail, as index < len
+        self.pop().unwrap()
+    }
+
+    /// Keeps only the elements for which `f` returns true, in their order.
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        let mut index = 0;
+        while index < self.len {
+            if f(&self.as_slice()[index]) {
+                index += 1;
+            } else {
+                self.remove(index);
+            }
+        }
+    }
+
+    /// Shortens the vector to `len` elements, dropping the rest.
+    pub fn truncate(&mut self, len: usize) {
+        while self.len > len {
+            self.pop();
+        }
+    }
+
+    /// Removes all elements.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Returns the elements as a slice.
+    pub fn as_slice(&self) -> &[T] {
+        // Safety: the first `len` elements are initialized.
+        unsafe { core::slice::from_raw_parts(self.buf.as_ptr().cast::<T>(), self.len) }
+    }
+
+    /// Returns the elements as a mutable slice.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        // Safety: the first `len` elements are initialized.
+        unsafe { core::slice::from_raw_parts_mut(self.buf.as_mut_ptr().cast::<T>(), self.len) }
+    }
+}
+
+impl<T: Copy, const N: usize> ArrayVec<T, N> {
+    /// Appends all elements of `other`.
+    /// # Errors
+    /// Returns an error if not all elements fit, in which case nothing is appended.
+    pub fn extend_from_slice(&mut self, other: &[T]) -> Result<(), CapacityError> {
+        if other.len() > N - self.len {
+            return Err(CapacityError(()));
+        }
+        for val in other {
+            // can not fail, we checked the capacity.
+            let _ = self.push(*val);
+        }
+        Ok(())
+    }
+}
+
+impl<T, const N: usize> Default for ArrayVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for ArrayVec<T, N> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, const N: usize> Deref for ArrayVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize> DerefMut for ArrayVec<T, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.as_mut_slice()
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for ArrayVec<T, N> {
+    fn clone(&self) -> Self {
+        let mut new = Self::new();
+        for val in self.iter() {
+            // can not fail, the capacities are equal.
+            let _ = new.push(val.clone());
+        }
+        new
+    }
+}
+
+impl<T: Debug, const N: usize> Debug for ArrayVec<T, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq, const N: usize> PartialEq for ArrayVec<T, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Eq, const N: usize> Eq for ArrayVec<T, N> {}
+
+impl<'a, T, const N: usize> IntoIterator for &'a ArrayVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
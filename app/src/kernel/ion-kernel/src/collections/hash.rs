@@ -0,0 +1,71 @@
+//! Hashers for [`HashMap`](super::HashMap) and [`HashSet`](super::HashSet)
+//! 
+//! [`FnvBuildHasher`] is the default: it is fast for the short keys the kernel uses, but is not
+//! resistant to collision attacks. Maps keyed by untrusted input should use a
+//! [`SipBuildHasher`] with random keys, once the kernel has an entropy source.
+use core::hash::{BuildHasher, Hasher};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// The 64-bit FNV-1a hash function.
+#[derive(Debug, Clone, Copy)]
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self(FNV_OFFSET)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+/// Creates [`FnvHasher`]s
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FnvBuildHasher;
+
+impl BuildHasher for FnvBuildHasher {
+    type Hasher = FnvHasher;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        FnvHasher::default()
+    }
+}
+
+/// Creates SipHash 2-4 hashers with fixed keys.
+#[derive(Debug, Clone, Copy)]
+pub struct SipBuildHasher {
+    k0: u64,
+    k1: u64,
+}
+
+impl SipBuildHasher {
+    /// Creates a new `SipBuildHasher` using the keys `k0` and `k1`
+    /// 
+    /// These should be random, otherwise there is no benefit over [`FnvBuildHasher`].
+    pub const fn with_keys(k0: u64, k1: u64) -> Self {
+        Self { k0, k1 }
+    }
+}
+
+impl BuildHasher for SipBuildHasher {
+    // core's SipHasher is deprecated in favour of std's RandomState, which we do not have.
+    #[allow(deprecated)]
+    type Hasher = core::hash::SipHasher;
+
+    #[allow(deprecated)]
+    fn build_hasher(&self) -> Self::Hasher {
+        core::hash::SipHasher::new_with_keys(self.k0, self.k1)
+    }
+}
@@ -0,0 +1,493 @@
+//! A hash map using open addressing.
+//! 
+//! Entries are stored inline in a single table, found by linear probing, and removed with
+//! backward shift deletion, so there are no tombstones and lookups never degrade after many
+//! removals.
+use core::{alloc::Allocator, borrow::Borrow, fmt::Debug, hash::{BuildHasher, Hash}, iter::FusedIterator, mem, ops::Index};
+
+use alloc::{alloc::Global, vec::Vec};
+
+use super::hash::FnvBuildHasher;
+
+/// The table is grown once it is more than 3/4 full.
+const fn max_load(slots: usize) -> usize {
+    slots / 4 * 3
+}
+
+/// The smallest table, of at least `min` slots, with a [`max_load`] of at least `needed`.
+fn slots_for(needed: usize, min: usize) -> usize {
+    let mut slots = min.max(8);
+    while max_load(slots) < needed {
+        slots *= 2;
+    }
+    slots
+}
+
+struct Bucket<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+/// A hash map, generic over the hasher and the allocator.
+/// 
+/// # Example
+/// ```rust,no_run
+/// use crate::collections::HashMap;
+/// 
+/// let mut fds = HashMap::new();
+/// fds.insert(0, "stdin");
+/// assert_eq!(fds.get(&0), Some(&"stdin"));
+/// assert_eq!(fds.remove(&0), Some("stdin"));
+/// ```
+pub struct HashMap<K, V, S = FnvBuildHasher, A: Allocator = Global> {
+    /// Empty, or a power of two in length.
+    slots: Vec<Option<Bucket<K, V>>, A>,
+    len: usize,
+    hasher: S,
+}
+
+impl<K, V> HashMap<K, V> {
+    /// Creates a new, empty `HashMap`. This does not allocate.
+    pub const fn new() -> Self {
+        Self { slots: Vec::new(), len: 0, hasher: FnvBuildHasher }
+    }
+
+    /// Creates a new `HashMap` which can hold at least `capacity` elements without growing.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher_in(capacity, FnvBuildHasher, Global)
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    /// Creates a new, empty `HashMap` using `hasher`. This does not allocate.
+    pub const fn with_hasher(hasher: S) -> Self {
+        Self { slots: Vec::new(), len: 0, hasher }
+    }
+}
+
+impl<K, V, S, A: Allocator> HashMap<K, V, S, A> {
+    /// Creates a new, empty `HashMap` using `hasher`, which allocates from `alloc`.
+    pub fn with_hasher_in(hasher: S, alloc: A) -> Self {
+        Self { slots: Vec::new_in(alloc), len: 0, hasher }
+    }
+
+    /// Creates a new `HashMap` using `hasher`, which allocates from `alloc`, and can hold at least
+    /// `capacity` elements without growing.
+    pub fn with_capacity_and_hasher_in(capacity: usize, hasher: S, alloc: A) -> Self {
+        let mut map = Self::with_hasher_in(hasher, alloc);
+        if capacity > 0 {
+            let slots = slots_for(capacity, 0);
+            map.slots.resize_with(slots, || None);
+        }
+        map
+    }
+
+    /// The amount of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns wether there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The amount of elements the map can hold without growing.
+    pub fn capacity(&self) -> usize {
+        max_load(self.slots.len())
+    }
+
+    /// The hasher used by this map.
+    pub fn hasher(&self) -> &S {
+        &self.hasher
+    }
+
+    /// Removes all elements, keeping the allocated table.
+    pub fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|s| *s = None);
+        self.len = 0;
+    }
+
+    /// Iterates over all key value pairs, in an unspecified order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { inner: self.slots.iter(), remaining: self.len }
+    }
+
+    /// Iterates over all key value pairs, with mutable values, in an unspecified order.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut { inner: self.slots.iter_mut(), remaining: self.len }
+    }
+
+    /// Iterates over all keys, in an unspecified order.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// Iterates over all values, in an unspecified order.
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    /// Iterates over all values mutably, in an unspecified order.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.iter_mut().map(|(_, v)| v)
+    }
+
+    fn mask(&self) -> usize {
+        self.slots.len() - 1
+    }
+
+    /// Returns the index of the slot that should hold `hash`
+    fn ideal(&self, hash: u64) -> usize {
+        // truncation is fine, the mask is smaller than usize anyway.
+        hash as usize & self.mask()
+    }
+
+    /// Removes the bucket at `index`, and shifts back the following buckets of the same probe
+    /// sequence.
+    fn remove_at(&mut self, mut index: usize) -> Bucket<K, V> {
+        // can not fail, callers pass an occupied slot.
+        let removed = self.slots[index].take().unwrap();
+        self.len -= 1;
+        let mask = self.mask();
+        let mut next = (index + 1) & mask;
+        while let Some(bucket) = &self.slots[next] {
+            // distance from the ideal slot to the hole, and to the bucket.
+            let ideal = self.ideal(bucket.hash);
+            if (index.wrapping_sub(ideal) & mask) < (next.wrapping_sub(ideal) & mask) {
+                self.slots[index] = self.slots[next].take();
+                index = next;
+            }
+            next = (next + 1) & mask;
+        }
+        removed
+    }
+
+    /// Keeps only the elements for which `f` returns true.
+    pub fn retain(&mut self, mut f: impl FnMut(&K, &mut V) -> bool) {
+        let mut i = 0;
+        while i < self.slots.len() {
+            let keep = match &mut self.slots[i] {
+                Some(b) => f(&b.key, &mut b.value),
+                None => true,
+            };
+            if keep {
+                i += 1;
+            } else {
+                // a bucket may have been shifted into `i`, so check it again.
+                self.remove_at(i);
+            }
+        }
+    }
+}
+
+// growing needs a second table from the same allocator, hence `A: Clone`
+impl<K: Hash + Eq, V, S: BuildHasher, A: Allocator + Clone> HashMap<K, V, S, A> {
+    fn find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.len == 0 {
+            return None;
+        }
+        let hash = self.hasher.hash_one(key);
+        let mut i = self.ideal(hash);
+        while let Some(bucket) = &self.slots[i] {
+            if bucket.hash == hash && bucket.key.borrow() == key {
+                return Some(i);
+            }
+            i = (i + 1) & self.mask();
+        }
+        None
+    }
+
+    /// Makes room for at least `additional` more elements.
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.len + additional;
+        if needed <= self.capacity() {
+            return;
+        }
+        let slots = slots_for(needed, self.slots.len());
+        let mut new = Vec::with_capacity_in(slots, self.slots.allocator().clone());
+        new.resize_with(slots, || None);
+        let old = mem::replace(&mut self.slots, new);
+        for bucket in old.into_iter().flatten() {
+            self.place(bucket);
+        }
+    }
+
+    /// Puts `bucket` into the first free slot of its probe sequence, returning the index.
+    /// 
+    /// The table must have a free slot, and must not contain the key already.
+    fn place(&mut self, bucket: Bucket<K, V>) -> usize {
+        let mut i = self.ideal(bucket.hash);
+        while self.slots[i].is_some() {
+            i = (i + 1) & self.mask();
+        }
+        self.slots[i] = Some(bucket);
+        i
+    }
+
+    fn insert_new(&mut self, key: K, value: V) -> usize {
+        self.reserve(1);
+        let hash = self.hasher.hash_one(&key);
+        self.len += 1;
+        self.place(Bucket { hash, key, value })
+    }
+
+    /// Inserts `value` under `key`, returning the previous value, if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(i) = self.find(&key) {
+            // can not fail, find returns occupied slots.
+            return Some(mem::replace(&mut self.slots[i].as_mut().unwrap().value, value));
+        }
+        self.insert_new(key, value);
+        None
+    }
+
+    /// Returns the value under `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).and_then(|i| self.slots[i].as_ref()).map(|b| &b.value)
+    }
+
+    /// Returns the value under `key` mutably.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).and_then(|i| self.slots[i].as_mut()).map(|b| &mut b.value)
+    }
+
+    /// Returns the stored key and the value under `key`
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).and_then(|i| self.slots[i].as_ref()).map(|b| (&b.key, &b.value))
+    }
+
+    /// Returns wether there is a value under `key`
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    /// Removes the value under `key` and returns it.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, v)| v)
+    }
+
+    /// Removes the value under `key` and returns it, along with the stored key.
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let i = self.find(key)?;
+        let bucket = self.remove_at(i);
+        Some((bucket.key, bucket.value))
+    }
+
+    /// Returns the value under `key`, inserting the result of `f` if there is none.
+    pub fn get_or_insert_with(&mut self, key: K, f: impl FnOnce() -> V) -> &mut V {
+        let i = match self.find(&key) {
+            Some(i) => i,
+            None => self.insert_new(key, f()),
+        };
+        // can not fail, the slot is occupied.
+        &mut self.slots[i].as_mut().unwrap().value
+    }
+}
+
+impl<K, V, S: Default> Default for HashMap<K, V, S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K: Clone, V: Clone, S: Clone, A: Allocator + Clone> Clone for HashMap<K, V, S, A> {
+    fn clone(&self) -> Self {
+        let slots = self.slots.iter().map(|s| s.as_ref().map(|b| Bucket {
+            hash: b.hash,
+            key: b.key.clone(),
+            value: b.value.clone(),
+        }));
+        let mut new = Vec::with_capacity_in(self.slots.len(), self.slots.allocator().clone());
+        new.extend(slots);
+        Self { slots: new, len: self.len, hasher: self.hasher.clone() }
+    }
+}
+
+impl<K: Debug, V: Debug, S, A: Allocator> Debug for HashMap<K, V, S, A> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, S, A, Q> Index<&Q> for HashMap<K, V, S, A>
+where
+    K: Hash + Eq + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    type Output = V;
+
+    /// Returns the value under `key`
+    /// # Panics
+    /// Panics if there is no such value.
+    fn index(&self, key: &Q) -> &Self::Output {
+        self.get(key).expect("key not in map")
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher, A: Allocator + Clone> Extend<(K, V)> for HashMap<K, V, S, A> {
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Default> FromIterator<(K, V)> for HashMap<K, V, S> {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut map = Self::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<'a, K, V, S, A: Allocator> IntoIterator for &'a HashMap<K, V, S, A> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S, A: Allocator> IntoIterator for &'a mut HashMap<K, V, S, A> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<K, V, S, A: Allocator> IntoIterator for HashMap<K, V, S, A> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { inner: self.slots.into_iter(), remaining: self.len }
+    }
+}
+
+/// Iterator over the entries of a [`HashMap`]
+pub struct Iter<'a, K, V> {
+    inner: core::slice::Iter<'a, Option<Bucket<K, V>>>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bucket = self.inner.by_ref().flatten().next()?;
+        self.remaining -= 1;
+        Some((&bucket.key, &bucket.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone(), remaining: self.remaining }
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for Iter<'_, K, V> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+/// Iterator over the entries of a [`HashMap`], with mutable values
+pub struct IterMut<'a, K, V> {
+    inner: core::slice::IterMut<'a, Option<Bucket<K, V>>>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bucket = self.inner.by_ref().flatten().next()?;
+        self.remaining -= 1;
+        Some((&bucket.key, &mut bucket.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+impl<K, V> FusedIterator for IterMut<'_, K, V> {}
+
+impl<K, V> Debug for IterMut<'_, K, V> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("IterMut").field("remaining", &self.remaining).finish()
+    }
+}
+
+/// Owning iterator over the entries of a [`HashMap`]
+pub struct IntoIter<K, V, A: Allocator = Global> {
+    inner: alloc::vec::IntoIter<Option<Bucket<K, V>>, A>,
+    remaining: usize,
+}
+
+impl<K, V, A: Allocator> Iterator for IntoIter<K, V, A> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bucket = self.inner.by_ref().flatten().next()?;
+        self.remaining -= 1;
+        Some((bucket.key, bucket.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, A: Allocator> ExactSizeIterator for IntoIter<K, V, A> {}
+impl<K, V, A: Allocator> FusedIterator for IntoIter<K, V, A> {}
+
+impl<K, V, A: Allocator> Debug for IntoIter<K, V, A> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("IntoIter").field("remaining", &self.remaining).finish()
+    }
+}
@@ -0,0 +1,146 @@
+//! A hash set, backed by a [`HashMap`] with `()` values.
+use core::{alloc::Allocator, borrow::Borrow, fmt::Debug, hash::{BuildHasher, Hash}};
+
+use alloc::alloc::Global;
+
+use super::{hash::FnvBuildHasher, hash_map::{self, HashMap}};
+
+/// A hash set, generic over the hasher and the allocator.
+/// 
+/// # Example
+/// ```rust,no_run
+/// use crate::collections::HashSet;
+/// 
+/// let mut seen = HashSet::new();
+/// assert!(seen.insert(0x1E));
+/// assert!(!seen.insert(0x1E));
+/// ```
+pub struct HashSet<T, S = FnvBuildHasher, A: Allocator = Global> {
+    map: HashMap<T, (), S, A>,
+}
+
+impl<T> HashSet<T> {
+    /// Creates a new, empty `HashSet`. This does not allocate.
+    pub const fn new() -> Self {
+        Self { map: HashMap::new() }
+    }
+
+    /// Creates a new `HashSet` which can hold at least `capacity` elements without growing.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { map: HashMap::with_capacity(capacity) }
+    }
+}
+
+impl<T, S> HashSet<T, S> {
+    /// Creates a new, empty `HashSet` using `hasher`. This does not allocate.
+    pub const fn with_hasher(hasher: S) -> Self {
+        Self { map: HashMap::with_hasher(hasher) }
+    }
+}
+
+impl<T, S, A: Allocator> HashSet<T, S, A> {
+    /// Creates a new, empty `HashSet` using `hasher`, which allocates from `alloc`.
+    pub fn with_hasher_in(hasher: S, alloc: A) -> Self {
+        Self { map: HashMap::with_hasher_in(hasher, alloc) }
+    }
+
+    /// The amount of elements.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns wether there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Removes all elements, keeping the allocated table.
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Iterates over all elements, in an unspecified order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.map.keys()
+    }
+
+    /// Keeps only the elements for which `f` returns true.
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        self.map.retain(|k, _| f(k));
+    }
+}
+
+impl<T: Hash + Eq, S: BuildHasher, A: Allocator + Clone> HashSet<T, S, A> {
+    /// Adds `value`, returning wether it was not present yet.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.map.insert(value, ()).is_none()
+    }
+
+    /// Returns wether `value` is present.
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.contains_key(value)
+    }
+
+    /// Removes `value`, returning wether it was present.
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.remove(value).is_some()
+    }
+
+    /// Removes `value` and returns the stored element, if it was present.
+    pub fn take<Q>(&mut self, value: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.remove_entry(value).map(|(k, _)| k)
+    }
+}
+
+impl<T, S: Default> Default for HashSet<T, S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<T: Clone, S: Clone, A: Allocator + Clone> Clone for HashSet<T, S, A> {
+    fn clone(&self) -> Self {
+        Self { map: self.map.clone() }
+    }
+}
+
+impl<T: Debug, S, A: Allocator> Debug for HashSet<T, S, A> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Hash + Eq, S: BuildHasher, A: Allocator + Clone> Extend<T> for HashSet<T, S, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.map.extend(iter.into_iter().map(|v| (v, ())));
+    }
+}
+
+impl<T: Hash + Eq, S: BuildHasher + Default> FromIterator<T> for HashSet<T, S> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut set = Self::default();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<T, S, A: Allocator> IntoIterator for HashSet<T, S, A> {
+    type Item = T;
+    type IntoIter = core::iter::Map<hash_map::IntoIter<T, (), A>, fn((T, ())) -> T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter().map((|(k, ())| k) as fn((T, ())) -> T)
+    }
+}
@@ -0,0 +1,121 @@
+//! Interned strings
+//! 
+//! Frequently repeated strings (log targets, task names, device names) are stored once, and
+//! referred to by a [`Symbol`], which is a single `u32`, making comparisons and hashing cheap.
+//! 
+//! Interned strings are never freed, so only intern strings from a bounded set.
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use core::fmt::{Debug, Display};
+
+use spin::Mutex;
+
+/// An interned string.
+/// 
+/// Two symbols are equal if and only if their strings are equal.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// Interns `s`, see [`intern`]
+    pub fn new(s: &str) -> Self {
+        intern(s)
+    }
+
+    /// The string this symbol refers to.
+    pub fn as_str(self) -> &'static str {
+        // can not fail, symbols are only created by the interner, which never removes strings.
+        with_interner(|interner| interner.resolve(self))
+    }
+
+    /// The raw id of this symbol.
+    pub const fn id(self) -> u32 {
+        self.0
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Symbol({}, {:?})", self.0, self.as_str())
+    }
+}
+
+/// The table behind [`intern`], separate from the global so it can be tested on its own.
+pub(crate) struct Interner {
+    ids: BTreeMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+impl Interner {
+    pub(crate) const fn new() -> Self {
+        Self { ids: BTreeMap::new(), strings: Vec::new() }
+    }
+
+    pub(crate) fn get(&self, s: &str) -> Option<Symbol> {
+        self.ids.get(s).copied()
+    }
+
+    pub(crate) fn resolve(&self, sym: Symbol) -> &'static str {
+        self.strings[sym.0 as usize]
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Returns the symbol for `s`, calling `store` to get a `'static` copy if it is new.
+    pub(crate) fn get_or_insert_with(&mut self, s: &str, store: impl FnOnce() -> &'static str) -> Symbol {
+        if let Some(sym) = self.get(s) {
+            return sym;
+        }
+        let s = store();
+        let sym = Symbol(u32::try_from(self.strings.len()).expect("too many interned strings"));
+        self.strings.push(s);
+        self.ids.insert(s, sym);
+        sym
+    }
+}
+
+static INTERNER: Mutex<Interner> = Mutex::new(Interner::new());
+
+/// Locks the interner with interrupts disabled, so an interrupt handler interning a string can not
+/// deadlock on it.
+fn with_interner<R>(f: impl FnOnce(&mut Interner) -> R) -> R {
+    x86_64::instructions::interrupts::without_interrupts(|| f(&mut INTERNER.lock()))
+}
+
+/// Interns `s`, copying it to the heap if it has not been interned yet.
+pub fn intern(s: &str) -> Symbol {
+    if let Some(sym) = lookup(s) {
+        return sym;
+    }
+    // copied before locking, so the allocator does not run with the interner locked. If another
+    // caller interned `s` meanwhile, the copy is freed again.
+    let copy: Box<str> = Box::from(s);
+    let mut copy = Some(copy);
+    let sym = with_interner(|interner| {
+        interner.get_or_insert_with(s, || Box::leak(copy.take().expect("only taken once")))
+    });
+    drop(copy);
+    sym
+}
+
+/// Interns `s` without copying it.
+pub fn intern_static(s: &'static str) -> Symbol {
+    with_interner(|interner| interner.get_or_insert_with(s, || s))
+}
+
+/// Returns the symbol for `s`, if it has been interned.
+pub fn lookup(s: &str) -> Option<Symbol> {
+    with_interner(|interner| interner.get(s))
+}
+
+/// The amount of interned strings.
+pub fn count() -> usize {
+    with_interner(|interner| interner.len())
+}
@@ -0,0 +1,53 @@
+//! Kernel collections
+//! 
+//! [`ArrayVec`], [`ArrayString`] and [`RingBuffer`] do not allocate: they have a fixed capacity,
+//! known at compile time, so they can be used in interrupt handlers (keyboard, serial RX,
+//! tracing, etc.) without allocating or taking the heap lock.
+//! 
+//! [`Symbol`]s are interned strings, see [`intern`]
+//! 
+//! [`HashMap`] and [`HashSet`] allocate, and use FNV hashing by default, see [`hash`]
+use core::fmt::Display;
+
+/// A vector with a fixed capacity.
+pub mod array_vec;
+/// A string with a fixed capacity.
+pub mod array_string;
+/// A fixed size FIFO queue.
+pub mod ring_buffer;
+pub mod intern;
+pub mod hash;
+pub mod hash_map;
+pub mod hash_set;
+
+pub use array_string::ArrayString;
+pub use array_vec::ArrayVec;
+pub use hash_map::HashMap;
+pub use hash_set::HashSet;
+pub use intern::Symbol;
+pub use ring_buffer::RingBuffer;
+
+/// The collection is full.
+/// 
+/// The inner value is the element that did not fit, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityError<T = ()>(pub T);
+
+impl<T> CapacityError<T> {
+    /// Returns the element that did not fit.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Display for CapacityError<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "insufficient capacity")
+    }
+}
+
+impl<T: core::fmt::Debug> core::error::Error for CapacityError<T> {}
+
+#[cfg(feature = "test")]
+/// Tests
+pub mod tests;
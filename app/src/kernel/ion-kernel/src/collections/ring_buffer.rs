@@ -0,0 +1,135 @@
+use core::{fmt::Debug, mem::MaybeUninit};
+
+use super::CapacityError;
+
+/// A fixed size FIFO queue of `N` elements, stored inline.
+/// 
+/// The buffer itself is not synchronized, so it must be put behind a lock (which disables
+/// interrupts) when shared with an interrupt handler.
+/// 
+/// # Example
+/// ```rust,no_run
+/// use crate::collections::RingBuffer;
+/// 
+/// let mut scancodes = RingBuffer::<u8, 2>::new();
+/// scancodes.push(0x1E).unwrap();
+/// scancodes.push(0x9E).unwrap();
+/// assert_eq!(scancodes.push_overwrite(0x1F), Some(0x1E));
+/// assert_eq!(scancodes.pop(), Some(0x9E));
+/// ```
+pub struct RingBuffer<T, const N: usize> {
+    buf: [MaybeUninit<T>; N],
+    /// Index of the oldest element.
+    head: usize,
+    len: usize,
+}
+
+impl<T, const N: usize> RingBuffer<T, N> {
+    /// Creates a new, empty `RingBuffer`
+    pub const fn new() -> Self {
+        Self { buf: [const { MaybeUninit::uninit() }; N], head: 0, len: 0 }
+    }
+
+    /// The amount of queued elements.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns wether the queue is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns wether no more elements fit.
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// The maximum amount of elements, `N`
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    const fn index(&self, i: usize) -> usize {
+        (self.head + i) % N
+    }
+
+    /// Appends `val` to the back of the queue.
+    /// # Errors
+    /// Returns `val` in a [`CapacityError`] if the queue is full.
+    pub fn push(&mut self, val: T) -> Result<(), CapacityError<T>> {
+        if self.is_full() {
+            return Err(CapacityError(val));
+        }
+        let tail = self.index(self.len);
+        self.buf[tail].write(val);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Appends `val` to the back of the queue, dropping the oldest element if the queue is full.
+    /// 
+    /// Returns the overwritten element, if any.
+    pub fn push_overwrite(&mut self, val: T) -> Option<T> {
+        if N == 0 {
+            return Some(val);
+        }
+        let old = if self.is_full() { self.pop() } else { None };
+        // can not fail, there is room now.
+        let _ = self.push(val);
+        old
+    }
+
+    /// Removes the oldest element and returns it.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        // Safety: the element at head is initialized while len > 0, and we stop tracking it.
+        let val = unsafe { self.buf[self.head].assume_init_read() };
+        self.head = self.index(1);
+        self.len -= 1;
+        Some(val)
+    }
+
+    /// Returns the oldest element, without removing it.
+    pub fn peek(&self) -> Option<&T> {
+        // Safety: the element at head is initialized while len > 0.
+        (!self.is_empty()).then(|| unsafe { self.buf[self.head].assume_init_ref() })
+    }
+
+    /// Returns the `i`th oldest element.
+    pub fn get(&self, i: usize) -> Option<&T> {
+        // Safety: elements `head..head + len` (wrapping) are initialized.
+        (i < self.len).then(|| unsafe { self.buf[self.index(i)].assume_init_ref() })
+    }
+
+    /// Iterates over the elements, oldest first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator {
+        // can not fail, i < len
+        (0..self.len).map(|i| self.get(i).unwrap())
+    }
+
+    /// Removes all elements.
+    pub fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+impl<T, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for RingBuffer<T, N> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T: Debug, const N: usize> Debug for RingBuffer<T, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,68 @@
+use core::fmt::Write;
+
+use crate::{collections::{ArrayString, ArrayVec, RingBuffer}, test::{TestInfo, TestResult, test_assert, test_assert_eq}};
+
+/// Tests the fixed capacity collections.
+pub fn test_fixed_collections(_: TestInfo) -> TestResult {
+    let mut vec = ArrayVec::<usize, 4>::new();
+    for i in 0..4 {
+        test_assert!(vec.push(i).is_ok())?;
+    }
+    test_assert!(vec.push(4).is_err())?;
+    test_assert_eq!(vec.remove(1), 1)?;
+    test_assert_eq!(vec.as_slice(), &[0, 2, 3])?;
+    test_assert!(vec.push(4).is_ok())?;
+    vec.retain(|&i| i != 2 && i != 4);
+    test_assert_eq!(vec.as_slice(), &[0, 3])?;
+
+    let mut s = ArrayString::<8>::new();
+    test_assert!(write!(s, "{}", 1234567890).is_err())?;
+    test_assert_eq!(s.as_str(), "12345678")?;
+
+    let mut ring = RingBuffer::<u8, 3>::new();
+    for b in 0..3 {
+        test_assert!(ring.push(b).is_ok())?;
+    }
+    test_assert_eq!(ring.push_overwrite(3), Some(0))?;
+    test_assert_eq!(ring.pop(), Some(1))?;
+    test_assert_eq!(ring.len(), 2)
+}
+
+/// Tests inserting into, growing and removing from a [`HashMap`](crate::collections::HashMap)
+pub fn test_hash_map(_: TestInfo) -> TestResult {
+    use crate::collections::HashMap;
+
+    for capacity in [1, 3, 6, 7, 100] {
+        test_assert!(HashMap::<u32, u32>::with_capacity(capacity).capacity() >= capacity)?;
+    }
+
+    let mut map = HashMap::new();
+    for i in 0..100u32 {
+        test_assert_eq!(map.insert(i, i * 2), None)?;
+    }
+    test_assert_eq!(map.len(), 100)?;
+    test_assert_eq!(map.insert(7, 0), Some(14))?;
+    map.retain(|k, _| k % 2 == 0);
+    test_assert_eq!(map.len(), 50)?;
+    test_assert_eq!(map.get(&7), None)?;
+    test_assert_eq!(map.remove(&8), Some(16))?;
+    test_assert!((0..100).filter(|k| k % 2 == 0 && *k != 8).all(|k| map.get(&k) == Some(&(k * 2))))
+}
+
+/// Tests that an [`Interner`](crate::collections::intern::Interner) hands out one symbol per
+/// distinct string.
+/// 
+/// A local interner is used, the global one never frees its strings, which would count as a leak.
+pub fn test_intern(_: TestInfo) -> TestResult {
+    use crate::collections::intern::Interner;
+
+    let mut interner = Interner::new();
+    let a = interner.get_or_insert_with("ata0", || "ata0");
+    let b = interner.get_or_insert_with("kbd", || "kbd");
+    test_assert!(a != b)?;
+    test_assert_eq!(interner.get_or_insert_with("ata0", || unreachable!()), a)?;
+    test_assert_eq!(interner.get("kbd"), Some(b))?;
+    test_assert_eq!(interner.get("serial"), None)?;
+    test_assert_eq!(interner.resolve(b), "kbd")?;
+    test_assert_eq!(interner.len(), 2)
+}
@@ -0,0 +1,340 @@
+//! The kernel configuration, a small key-value store.
+//! 
+//! Entries are saved as text to [`PATH`], one `key=value` per line. It survives a power cycle if
+//! the root is a volume on a disk, see [`mount_root`](crate::fs::mount_root), and is lost with the
+//! RAM filesystem otherwise.
+//! 
+//! [`load`] reads it at boot, once the root is mounted, and applies the keys read by subsystems at
+//! boot:
+//! - `loglevel`: the [maximum level](crate::log::set_max_level) logged.
+//! - `loglevel.SINK`: the [level](crate::log::sink::set_level) of a log sink, like `loglevel.serial`
+//! - `keyboard.layout`: only `us` is supported so far.
+//! - `hostname`: see [`hostname`], it must be [valid](crate::sys::is_valid_hostname)
+//! 
+//! Other subsystems read their keys with [`get`]. The `get` and `setconf` shell
+//! [commands](set_command) edit it, and every change is saved right away. The latter is not named
+//! `set`, which [scripts](crate::shell::script) use for their variables.
+use alloc::string::String;
+use core::fmt::{self, Display, Write};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{collections::{ArrayString, ArrayVec}, fs, io, log::{self, warn}, sync::AdaptiveMutex};
+
+/// The file the configuration is saved to.
+pub const PATH: &str = "/etc/ion.conf";
+const DIR: &str = "/etc";
+/// Maximum amount of entries.
+pub const MAX_ENTRIES: usize = 32;
+/// Maximum length of a key.
+pub const MAX_KEY_LEN: usize = 32;
+/// Maximum length of a value.
+pub const MAX_VALUE_LEN: usize = 64;
+/// The hostname if none is configured.
+pub const DEFAULT_HOSTNAME: &str = "ion";
+
+/// A configuration key.
+pub type Key = ArrayString<MAX_KEY_LEN>;
+/// A configuration value.
+pub type Value = ArrayString<MAX_VALUE_LEN>;
+
+/// An error while changing the configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The key is empty, or has characters other than ASCII letters, digits, `.`, `_` and `-`
+    InvalidKey,
+    /// The value has a newline, or a control character.
+    InvalidValue,
+    /// The key or the value is too long.
+    TooLong,
+    /// There are [`MAX_ENTRIES`] already.
+    Full,
+    /// The value is not valid for the key, such as an unknown log level.
+    Rejected(&'static str),
+    /// The change was made, but could not be saved to [`PATH`]
+    Unsaved(io::Error),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidKey => write!(f, "keys may only contain ASCII letters, digits, `.`, `_` and `-`"),
+            Self::InvalidValue => write!(f, "values may not contain control characters"),
+            Self::TooLong => write!(f, "keys are at most {MAX_KEY_LEN} bytes, values {MAX_VALUE_LEN}"),
+            Self::Full => write!(f, "the configuration is full"),
+            Self::Rejected(why) => write!(f, "{why}"),
+            Self::Unsaved(e) => write!(f, "the change was not saved to {PATH}: {e}"),
+        }
+    }
+}
+
+impl core::error::Error for ConfigError {}
+
+/// The entries, in the order they were first set.
+#[derive(Debug, Clone, Default)]
+struct Store {
+    entries: ArrayVec<(Key, Value), MAX_ENTRIES>,
+}
+
+impl Store {
+    const fn new() -> Self {
+        Self { entries: ArrayVec::new() }
+    }
+
+    /// Parses `text`, returning the store and the amount of lines which were skipped.
+    fn parse(text: &str) -> (Self, usize) {
+        let mut store = Self::new();
+        let mut skipped = 0;
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let parsed = line.split_once('=').ok_or(ConfigError::InvalidKey);
+            if parsed.and_then(|(key, value)| store.set(key.trim(), value.trim())).is_err() {
+                skipped += 1;
+            }
+        }
+        (store, skipped)
+    }
+
+    fn get(&self, key: &str) -> Option<&Value> {
+        self.entries.iter().find(|(k, _)| k.as_str() == key).map(|(_, v)| v)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        validate(key, value)?;
+        let mut new = Value::new();
+        new.push_str(value).map_err(|_| ConfigError::TooLong)?;
+        if let Some((_, old)) = self.entries.iter_mut().find(|(k, _)| k.as_str() == key) {
+            *old = new;
+            return Ok(());
+        }
+        let mut k = Key::new();
+        k.push_str(key).map_err(|_| ConfigError::TooLong)?;
+        self.entries.push((k, new)).map_err(|_| ConfigError::Full)
+    }
+
+    fn remove(&mut self, key: &str) -> bool {
+        let index = self.entries.iter().position(|(k, _)| k.as_str() == key);
+        index.map(|i| self.entries.remove(i)).is_some()
+    }
+
+    fn write(&self, out: &mut impl Write) -> fmt::Result {
+        self.entries.iter().try_for_each(|(key, value)| writeln!(out, "{key}={value}"))
+    }
+}
+
+fn validate(key: &str, value: &str) -> Result<(), ConfigError> {
+    if key.is_empty() || !key.bytes().all(|b| b.is_ascii_alphanumeric() || b"._-".contains(&b)) {
+        return Err(ConfigError::InvalidKey);
+    }
+    if value.chars().any(char::is_control) {
+        return Err(ConfigError::InvalidValue);
+    }
+    if key.len() > MAX_KEY_LEN || value.len() > MAX_VALUE_LEN {
+        return Err(ConfigError::TooLong);
+    }
+    Ok(())
+}
+
+static STORE: Mutex<Store> = Mutex::new(Store::new());
+/// Held while saving, which does disk I/O, so the last change is written last.
+static SAVING: AdaptiveMutex<()> = AdaptiveMutex::new("config-save", ());
+
+/// Saves the [`STORE`] to [`PATH`], creating its directory if needed.
+fn save() -> Result<(), ConfigError> {
+    let _saving = SAVING.lock();
+    let mut text = String::new();
+    // can not fail, writing to a string only fails if the heap is full, which aborts.
+    let _ = without_interrupts(|| STORE.lock().write(&mut text));
+    match fs::create_dir(DIR) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(ConfigError::Unsaved(e)),
+        _ => {}
+    }
+    fs::write(PATH, text.as_bytes()).map_err(ConfigError::Unsaved)
+}
+
+/// Applies a key read at boot, `Ok` for keys which are not.
+fn apply(key: &str, value: &str) -> Result<(), ConfigError> {
+    match key {
+        "loglevel" => log::set_max_level(value.parse().map_err(|_| ConfigError::Rejected("unknown log level"))?),
+        _ if key.starts_with("loglevel.") => {
+            let level = value.parse().map_err(|_| ConfigError::Rejected("unknown log level"))?;
+            if !log::sink::set_level(&key["loglevel.".len()..], level) {
+                return Err(ConfigError::Rejected("unknown log sink"));
+            }
+        }
+        "keyboard.layout" if !value.eq_ignore_ascii_case("us") => return Err(ConfigError::Rejected("only the `us` keyboard layout is supported")),
+        "hostname" if !crate::sys::is_valid_hostname(value) => return Err(ConfigError::Rejected("hostnames may only contain ASCII letters, digits, `-` and `.`")),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Loads the saved configuration, and applies it.
+/// 
+/// Called at boot, once the root is mounted.
+pub fn load() {
+    let text = match fs::read(PATH) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+        Err(e) => {
+            warn!("The configuration could not be read: {e}");
+            return;
+        }
+    };
+    let Ok(text) = core::str::from_utf8(&text) else {
+        warn!("The configuration is not UTF-8, it is ignored");
+        return;
+    };
+    let (store, skipped) = Store::parse(text);
+    if skipped != 0 {
+        warn!("Skipped {skipped} invalid lines of the configuration");
+    }
+    for (key, value) in store.entries.iter() {
+        if let Err(e) = apply(key, value) {
+            warn!("config: {key}: {e}");
+        }
+    }
+    log::info!("Loaded {} configuration entries.", store.entries.len());
+    without_interrupts(|| *STORE.lock() = store);
+}
+
+/// The value of `key`, if it is set.
+pub fn get(key: &str) -> Option<Value> {
+    without_interrupts(|| STORE.lock().get(key).copied())
+}
+
+/// Sets `key` to `value`, applies it, and saves the configuration.
+/// # Errors
+/// Returns an error if the key or value is invalid, or the configuration is full. Returns
+/// [`ConfigError::Unsaved`] if it was set, but could not be saved.
+pub fn set(key: &str, value: &str) -> Result<(), ConfigError> {
+    validate(key, value)?;
+    apply(key, value)?;
+    without_interrupts(|| STORE.lock().set(key, value))?;
+    save()
+}
+
+/// Removes `key`, and saves the configuration.
+/// # Errors
+/// Returns [`ConfigError::Unsaved`] if it was removed, but could not be saved.
+pub fn remove(key: &str) -> Result<bool, ConfigError> {
+    if !without_interrupts(|| STORE.lock().remove(key)) {
+        return Ok(false);
+    }
+    save().map(|()| true)
+}
+
+/// Calls `f` with every entry, in the order they were first set.
+pub fn for_each(mut f: impl FnMut(&str, &str)) {
+    let store = without_interrupts(|| STORE.lock().clone());
+    store.entries.iter().for_each(|(key, value)| f(key, value));
+}
+
+/// The configured `hostname`, or [`DEFAULT_HOSTNAME`]
+pub fn hostname() -> Value {
+    get("hostname").unwrap_or_else(|| {
+        let mut name = Value::new();
+        // can not fail, the default is short.
+        let _ = name.push_str(DEFAULT_HOSTNAME);
+        name
+    })
+}
+
+/// The `get` shell command: `get`, or `get KEY`
+pub fn get_command(args: &[&str]) -> i32 {
+    use crate::text::println;
+
+    match args {
+        [_] => {
+            for_each(|key, value| println!("{key}={value}"));
+            0
+        }
+        [_, key] => match get(key) {
+            Some(value) => {
+                println!("{value}");
+                0
+            }
+            None => {
+                println!("get: {key} is not set");
+                1
+            }
+        },
+        _ => {
+            println!("usage: get [KEY]");
+            2
+        }
+    }
+}
+
+/// The `setconf` shell command: `setconf KEY VALUE...` sets a key, `setconf KEY` removes it.
+pub fn set_command(args: &[&str]) -> i32 {
+    use crate::text::println;
+
+    match args {
+        [_, key] => match remove(key) {
+            Ok(true) => 0,
+            Ok(false) => {
+                println!("setconf: {key} is not set");
+                1
+            }
+            Err(e) => {
+                println!("setconf: {e}");
+                1
+            }
+        },
+        [_, key, words @ ..] => {
+            let mut value = Value::new();
+            for (i, word) in words.iter().enumerate() {
+                if (i != 0 && value.push(' ').is_err()) || value.push_str(word).is_err() {
+                    println!("setconf: {}", ConfigError::TooLong);
+                    return 1;
+                }
+            }
+            match set(key, &value) {
+                Ok(()) => 0,
+                Err(e) => {
+                    println!("setconf: {e}");
+                    1
+                }
+            }
+        }
+        _ => {
+            println!("usage: setconf KEY [VALUE...]");
+            2
+        }
+    }
+}
+
+/// Tests parsing, editing, and saving the configuration.
+#[cfg(feature = "test")]
+pub fn test_config(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    let (mut store, skipped) = Store::parse("# comment\nhostname = ion-test\n\nno value\nbad key=1\nloglevel=warn\nhostname=ion-2\n");
+    test_assert_eq!(skipped, 2)?;
+    test_assert_eq!(store.get("hostname").map(|v| v.as_str()), Some("ion-2"))?;
+    test_assert_eq!(store.set("bad\nkey", "1"), Err(ConfigError::InvalidKey))?;
+    test_assert_eq!(store.set("key", "a\nb"), Err(ConfigError::InvalidValue))?;
+    test_assert!(store.remove("loglevel") && !store.remove("loglevel"))?;
+    let mut text = ArrayString::<64>::new();
+    store.write(&mut text).map_err(|_| "the store was not written")?;
+    test_assert_eq!(text.as_str(), "hostname=ion-2\n")?;
+
+    // the live store, which is saved.
+    let existed = fs::metadata(PATH).is_ok();
+    let level = log::max_level();
+    test_assert_eq!(set("loglevel", "loud"), Err(ConfigError::Rejected("unknown log level")))?;
+    set("test.key", "value").map_err(|_| "the key was not set")?;
+    test_assert_eq!(get("test.key").map(|v| v.as_str() == "value"), Some(true))?;
+    let saved = fs::read(PATH).map_err(|_| "the configuration was not saved")?;
+    test_assert!(core::str::from_utf8(&saved).is_ok_and(|text| text.contains("test.key=value\n")))?;
+    test_assert_eq!(remove("test.key"), Ok(true))?;
+    test_assert_eq!(remove("test.key"), Ok(false))?;
+    test_assert_eq!(get("test.key"), None)?;
+    if !existed {
+        // the root filesystem keeps its nodes, which would count as leaked.
+        fs::remove(PATH).map_err(|_| "the configuration was not removed")?;
+        let _ = fs::remove(DIR);
+    }
+    test_assert_eq!(log::max_level(), level)
+}
@@ -0,0 +1,308 @@
+//! Consoles, which [`print`](crate::text::print) and [`log`](crate::log) write to.
+//! 
+//! Exactly one console is active at a time. It is selected at boot by [`init`], and can be
+//! changed later with [`select`] (for example, by the kernel command line). Before that, the
+//! [`EarlyConsole`] writes to the debug port.
+//! 
+//! The output of a function can be [captured](capture) instead, E.g. to page it.
+use alloc::string::String;
+use core::fmt::{self, Display};
+
+/// Selecting text with the mouse.
+pub mod selection;
+
+use spin::Mutex;
+
+use crate::{c_lib::BootInfo, collections::ArrayString, serial, text::{Color, ColorCode, VGA_BUFFER, WRITER, framebuffer::FRAME_BUFFER, verify_vga_mapping}};
+
+/// An output device for kernel text.
+pub trait Console: Sync {
+    /// The name used to select this console.
+    fn name(&self) -> &'static str;
+
+    /// Writes `args` to the console.
+    fn write_args(&self, args: fmt::Arguments);
+
+    /// Sets the color of following text, if the console supports colors.
+    fn set_color(&self, _color: ColorCode) {}
+
+    /// The color of following text.
+    fn color(&self) -> ColorCode {
+        ColorCode::new(Color::White, Color::Black)
+    }
+
+    /// Makes sure everything written so far is visible.
+    fn flush(&self) {}
+
+    /// Clears the console, if it can be cleared.
+    fn clear(&self) {}
+}
+
+impl fmt::Debug for dyn Console {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Console").field(&self.name()).finish()
+    }
+}
+
+/// Writes to the debug port with [`serial::dbg`], which needs no locks or initialization.
+/// 
+/// Active from boot until [`init`] checked which consoles work.
+#[derive(Debug, Clone, Copy)]
+pub struct EarlyConsole;
+
+impl Console for EarlyConsole {
+    fn name(&self) -> &'static str {
+        "early"
+    }
+
+    fn write_args(&self, args: fmt::Arguments) {
+        use core::fmt::Write;
+
+        struct Dbg;
+
+        impl Write for Dbg {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                serial::dbg::str(s);
+                Ok(())
+            }
+        }
+
+        let _ = Dbg.write_fmt(args);
+    }
+}
+
+/// The main region of the VGA text buffer, see [`WRITER`]
+#[derive(Debug, Clone, Copy)]
+pub struct VgaConsole;
+
+impl Console for VgaConsole {
+    fn name(&self) -> &'static str {
+        "vga"
+    }
+
+    fn write_args(&self, args: fmt::Arguments) {
+        WRITER.print(args);
+    }
+
+    fn set_color(&self, color: ColorCode) {
+        x86_64::instructions::interrupts::without_interrupts(|| WRITER.lock().set_color(color));
+    }
+
+    fn color(&self) -> ColorCode {
+        x86_64::instructions::interrupts::without_interrupts(|| WRITER.lock().color())
+    }
+
+    fn flush(&self) {
+        x86_64::instructions::interrupts::without_interrupts(|| WRITER.lock().flush());
+    }
+
+    fn clear(&self) {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let mut writer = WRITER.lock();
+            writer.clear();
+            writer.flush();
+        });
+    }
+}
+
+/// The debug port, and the [serial port](crate::serial::config::Role::Console) chosen by
+/// `console=`
+/// 
+/// Colors are ignored.
+#[derive(Debug, Clone, Copy)]
+pub struct SerialConsole;
+
+impl Console for SerialConsole {
+    fn name(&self) -> &'static str {
+        "serial"
+    }
+
+    fn write_args(&self, args: fmt::Arguments) {
+        let _ = serial::write_to(serial::config::Role::Console, args);
+    }
+
+    /// Clears the terminal on the other side, with ANSI escapes.
+    fn clear(&self) {
+        self.write_args(format_args!("\x1b[2J\x1b[H"));
+    }
+}
+
+/// The graphical frame buffer, see [`FRAME_BUFFER`]
+/// 
+/// Discards output until the frame buffer is [set up](crate::text::framebuffer::init).
+#[derive(Debug, Clone, Copy)]
+pub struct FrameBufferConsole;
+
+impl Console for FrameBufferConsole {
+    fn name(&self) -> &'static str {
+        "framebuffer"
+    }
+
+    fn write_args(&self, args: fmt::Arguments) {
+        use core::fmt::Write;
+
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            if let Some(writer) = FRAME_BUFFER.lock().as_mut() {
+                let _ = writer.write_fmt(args);
+            }
+        });
+    }
+
+    fn set_color(&self, color: ColorCode) {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            if let Some(writer) = FRAME_BUFFER.lock().as_mut() {
+                writer.set_color(color);
+            }
+        });
+    }
+
+    fn color(&self) -> ColorCode {
+        let color = x86_64::instructions::interrupts::without_interrupts(|| FRAME_BUFFER.lock().as_ref().map(|w| w.color()));
+        color.unwrap_or(ColorCode::new(Color::White, Color::Black))
+    }
+
+    fn clear(&self) {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            if let Some(writer) = FRAME_BUFFER.lock().as_mut() {
+                writer.clear();
+            }
+        });
+    }
+}
+
+/// Discards all output.
+#[derive(Debug, Clone, Copy)]
+pub struct NullConsole;
+
+impl Console for NullConsole {
+    fn name(&self) -> &'static str {
+        "null"
+    }
+
+    fn write_args(&self, _args: fmt::Arguments) {}
+}
+
+/// Maximum amount of bytes [`capture`] keeps, later output is dropped.
+pub const MAX_CAPTURE: usize = 16 * 1024;
+
+/// Collects the output during [`capture`], it can not be selected.
+/// 
+/// The text is stored inline, so interrupt handlers can print without allocating.
+#[derive(Debug)]
+struct CaptureConsole {
+    text: Mutex<ArrayString<MAX_CAPTURE>>,
+}
+
+impl Console for CaptureConsole {
+    fn name(&self) -> &'static str {
+        "capture"
+    }
+
+    fn write_args(&self, args: fmt::Arguments) {
+        use core::fmt::Write;
+
+        // truncates once it is full.
+        x86_64::instructions::interrupts::without_interrupts(|| { let _ = self.text.lock().write_fmt(args); });
+    }
+}
+
+static CAPTURE: CaptureConsole = CaptureConsole { text: Mutex::new(ArrayString::new()) };
+
+/// Every console which can be selected.
+static CONSOLES: [&'static dyn Console; 5] = [&EarlyConsole, &VgaConsole, &SerialConsole, &FrameBufferConsole, &NullConsole];
+
+static ACTIVE: Mutex<&'static dyn Console> = Mutex::new(&EarlyConsole);
+
+/// The active console.
+pub fn active() -> &'static dyn Console {
+    // copy the reference out, so the lock is not held while printing. Interrupt handlers print as
+    // well, so it is taken with interrupts disabled.
+    x86_64::instructions::interrupts::without_interrupts(|| *ACTIVE.lock())
+}
+
+/// Iterates over every console which can be selected.
+pub fn consoles() -> impl Iterator<Item = &'static dyn Console> {
+    CONSOLES.iter().copied()
+}
+
+/// There is no console with this name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownConsole<'a>(pub &'a str);
+
+impl Display for UnknownConsole<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown console `{}`", self.0)
+    }
+}
+
+/// Makes the console called `name` active.
+/// # Errors
+/// Returns [`UnknownConsole`] if there is no such console.
+pub fn select(name: &str) -> Result<(), UnknownConsole<'_>> {
+    let console = consoles().find(|c| c.name() == name).ok_or(UnknownConsole(name))?;
+    active().flush();
+    x86_64::instructions::interrupts::without_interrupts(|| *ACTIVE.lock() = console);
+    Ok(())
+}
+
+/// Runs `f` with everything printed, and logged to the console, captured instead of shown. Returns
+/// the result of `f`, and up to [`MAX_CAPTURE`] bytes of its output.
+/// 
+/// Captures may be nested, the inner one gets the output while it runs.
+pub fn capture<R>(f: impl FnOnce() -> R) -> (R, String) {
+    let (previous, start) = x86_64::instructions::interrupts::without_interrupts(|| {
+        let start = CAPTURE.text.lock().len();
+        (core::mem::replace(&mut *ACTIVE.lock(), &CAPTURE), start)
+    });
+    let result = f();
+    let output = x86_64::instructions::interrupts::without_interrupts(|| {
+        *ACTIVE.lock() = previous;
+        let mut text = CAPTURE.text.lock();
+        let output = text[start..].into();
+        text.truncate(start);
+        output
+    });
+    (result, output)
+}
+
+/// Selects the console based on the boot info, replacing the [`EarlyConsole`]
+/// 
+/// The VGA text console is used, unless the boot stage set up a graphical framebuffer, in which
+/// case the VGA text buffer is not visible and the serial console is used instead, until the
+/// [framebuffer console](crate::text::framebuffer::init) is set up. The serial console is also
+/// used if the VGA text buffer is [not mapped](crate::text::verify_vga_mapping).
+pub fn init(boot_info: &BootInfo) {
+    let graphical = boot_info.frame_buffer
+        .is_some_and(|fb| fb.is_graphical() && fb.addr != VGA_BUFFER);
+    let vga = !graphical && verify_vga_mapping();
+    // can not fail, both consoles always exist.
+    let _ = select(if vga { "vga" } else { "serial" });
+    if vga {
+        selection::init();
+    } else if !graphical {
+        crate::log::warn!("The VGA text buffer is not mapped, using the serial console");
+    }
+}
+
+/// The `clear` shell command, clears the active console.
+pub fn clear_command(args: &[&str]) -> i32 {
+    if args.len() > 1 {
+        crate::text::println!("usage: clear");
+        return 2;
+    }
+    active().clear();
+    0
+}
+
+/// Tests that the VGA text buffer was verified before it was selected, and the early console.
+#[cfg(feature = "test")]
+pub fn test_early_console(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    test_assert_eq!(crate::text::vga_mapped(), verify_vga_mapping())?;
+    test_assert!(active().name() != "early")?;
+    test_assert!(active().name() != "vga" || crate::text::vga_mapped())?;
+    // writes to the debug port, without locks.
+    EarlyConsole.write_args(format_args!("test: early console\n"));
+    test_assert!(consoles().any(|c| c.name() == "early"))
+}
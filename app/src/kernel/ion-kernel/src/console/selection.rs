@@ -0,0 +1,124 @@
+//! Selecting text of the VGA console with the mouse, like gpm on Linux.
+//! 
+//! Dragging with the left button highlights cells, which are copied to the [`clipboard`] when it
+//! is released. The middle button pastes the clipboard, by typing it into the input line.
+use core::ops::Range;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{collections::ArrayString, input::{self, Button, InputEvent, TimedEvent}, text::{self, BUFFER_HEIGHT, BUFFER_WIDTH}};
+
+/// Mouse movement needed to move the pointer by one column.
+const COUNTS_PER_COLUMN: i32 = 8;
+/// Mouse movement needed to move the pointer by one row.
+const COUNTS_PER_ROW: i32 = 16;
+/// Every cell of the screen, with a newline after every row.
+const CLIPBOARD_LEN: usize = (BUFFER_WIDTH + 1) * BUFFER_HEIGHT;
+
+#[derive(Debug)]
+struct State {
+    /// Pointer position in mouse counts, right and down from the top left.
+    x: i32,
+    y: i32,
+    /// Where the left button was pressed, while it is held.
+    anchor: Option<usize>,
+}
+
+impl State {
+    fn pointer(&self) -> usize {
+        (self.y / COUNTS_PER_ROW) as usize * BUFFER_WIDTH + (self.x / COUNTS_PER_COLUMN) as usize
+    }
+}
+
+static STATE: Mutex<State> = Mutex::new(State { x: 0, y: 0, anchor: None });
+static CLIPBOARD: Mutex<ArrayString<CLIPBOARD_LEN>> = Mutex::new(ArrayString::new());
+
+/// The cells between two cells, including both.
+fn between(a: usize, b: usize) -> Range<usize> {
+    a.min(b)..a.max(b) + 1
+}
+
+/// The last copied text.
+pub fn clipboard() -> ArrayString<CLIPBOARD_LEN> {
+    let mut copy = ArrayString::new();
+    without_interrupts(|| copy.push_str_truncating(&CLIPBOARD.lock()));
+    copy
+}
+
+/// Replaces the clipboard, text which does not fit is dropped.
+pub fn set_clipboard(text: &str) {
+    without_interrupts(|| {
+        let mut clipboard = CLIPBOARD.lock();
+        clipboard.clear();
+        clipboard.push_str_truncating(text);
+    })
+}
+
+/// Highlights `cells`, and copies their text to the clipboard.
+pub fn select(cells: Range<usize>) {
+    let mut copied = ArrayString::<CLIPBOARD_LEN>::new();
+    // can not fail, the clipboard fits the whole screen.
+    let _ = text::read_screen(cells.clone(), &mut copied);
+    set_clipboard(&copied);
+    text::set_highlight(cells, text::highlight().1);
+}
+
+/// Removes the highlight, the clipboard is kept.
+pub fn clear() {
+    text::set_highlight(0..0, text::highlight().1);
+}
+
+fn handle(event: &TimedEvent) {
+    let mut state = STATE.lock();
+    match event.event {
+        InputEvent::Motion { dx, dy } => {
+            state.x = (state.x + dx).clamp(0, BUFFER_WIDTH as i32 * COUNTS_PER_COLUMN - 1);
+            // the mouse counts up, the screen down.
+            state.y = (state.y - dy).clamp(0, BUFFER_HEIGHT as i32 * COUNTS_PER_ROW - 1);
+            let pointer = state.pointer();
+            match state.anchor {
+                Some(anchor) => text::set_highlight(between(anchor, pointer), Some(pointer)),
+                None => text::set_highlight(text::highlight().0, Some(pointer)),
+            }
+        }
+        InputEvent::Button { button: Button::Left, pressed: true } => {
+            let pointer = state.pointer();
+            state.anchor = Some(pointer);
+            text::set_highlight(0..0, Some(pointer));
+        }
+        InputEvent::Button { button: Button::Left, pressed: false } => {
+            let pointer = state.pointer();
+            // a click without dragging only removes the highlight.
+            if let Some(anchor) = state.anchor.take().filter(|&anchor| anchor != pointer) {
+                drop(state);
+                select(between(anchor, pointer));
+            }
+        }
+        InputEvent::Button { button: Button::Middle, pressed: true } => {
+            drop(state);
+            crate::interrupts::keyboard::type_str(&clipboard());
+        }
+        _ => {}
+    }
+}
+
+/// Starts following the mouse.
+pub fn init() {
+    if input::subscribe(handle).is_err() {
+        crate::log::warn!("Mouse selection is disabled, there are too many input subscribers");
+    }
+}
+
+/// Tests copying the text of a selection.
+#[cfg(feature = "test")]
+pub fn test_selection(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::{test::test_assert_eq, text::{WRITER, println}};
+
+    println!("\ncopy me   ");
+    println!("and me");
+    let row = without_interrupts(|| WRITER.lock().rows().end - 3);
+    select(row * BUFFER_WIDTH..(row + 1) * BUFFER_WIDTH + 3);
+    clear();
+    test_assert_eq!(clipboard().as_str(), "copy me\nand")
+}
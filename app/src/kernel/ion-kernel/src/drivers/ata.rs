@@ -0,0 +1,388 @@
+//! ATA disks on the legacy IDE channels, using PIO.
+//! 
+//! [`init`] probes both drives of the primary (`0x1F0`) and secondary (`0x170`) channel with
+//! `IDENTIFY DEVICE`, and registers each disk as a [block device](crate::storage), `hda` to `hdd`.
+//! ATAPI drives, such as CD-ROMs, are skipped.
+//! 
+//! Transfers poll the status register, one sector at a time, so the channel interrupts (IRQ 14
+//! and 15) stay masked. Disks with more than 2^28 sectors are addressed with 48 bit LBAs.
+//! 
+//! The driver is [isolated](super::isolation) as `ata`: if it faults, the disks are unregistered.
+use core::time::Duration;
+
+use spin::Mutex;
+use x86_64::instructions::{interrupts::without_interrupts, port::Port};
+
+use super::isolation;
+use crate::{collections::ArrayString, io::{self, Error, ErrorKind}, log::{debug, info, warn}, storage::{self, BlockDevice, SECTOR_SIZE}, time};
+
+/// How long to wait for a drive, before giving up.
+pub const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The amount of drives, two on each channel.
+pub const DRIVES: usize = 4;
+
+/// The largest sector count of 28 bit LBAs.
+const LBA28_SECTORS: u64 = 1 << 28;
+
+/// The drive is busy, the other bits are not valid.
+const STATUS_BUSY: u8 = 1 << 7;
+/// The drive faulted, without setting [`STATUS_ERROR`]
+const STATUS_FAULT: u8 = 1 << 5;
+/// The drive has data for us, or wants data.
+const STATUS_DATA_REQUEST: u8 = 1 << 3;
+/// The last command failed.
+const STATUS_ERROR: u8 = 1 << 0;
+/// Read when there is no drive on the channel.
+const STATUS_FLOATING: u8 = 0xFF;
+
+/// Disables the interrupts of the channel, in the device control register.
+const CONTROL_NO_INTERRUPTS: u8 = 1 << 1;
+
+/// Addressing by LBA, instead of by cylinder, head and sector.
+const DRIVE_LBA: u8 = 1 << 6;
+/// Bits of the drive register which are always set.
+const DRIVE_OBSOLETE: u8 = 0b1010_0000;
+/// Selects the second drive of the channel.
+const DRIVE_SECONDARY: u8 = 1 << 4;
+
+const CMD_READ_SECTORS: u8 = 0x20;
+const CMD_READ_SECTORS_EXT: u8 = 0x24;
+const CMD_WRITE_SECTORS: u8 = 0x30;
+const CMD_WRITE_SECTORS_EXT: u8 = 0x34;
+const CMD_FLUSH_CACHE: u8 = 0xE7;
+const CMD_FLUSH_CACHE_EXT: u8 = 0xEA;
+const CMD_IDENTIFY: u8 = 0xEC;
+
+const TIMED_OUT: Error = Error::new(ErrorKind::TimedOut, "the disk did not respond");
+const DEVICE_ERROR: Error = Error::new(ErrorKind::Other, "the disk reported an error");
+
+/// What a disk reported about itself, see [`Identity::parse`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Identity {
+    /// The model, E.g. `QEMU HARDDISK`
+    pub model: ArrayString<40>,
+    /// The serial number.
+    pub serial: ArrayString<20>,
+    /// The amount of addressable sectors.
+    pub sectors: u64,
+    /// The disk supports 48 bit LBAs.
+    pub lba48: bool,
+}
+
+/// An ATA string: 2 characters per word, the first one in the high byte, padded with spaces.
+fn ata_string<const N: usize>(words: &[u16]) -> ArrayString<N> {
+    let mut string = ArrayString::new();
+    for byte in words.iter().flat_map(|word| word.to_be_bytes()) {
+        let c = if byte.is_ascii_graphic() || byte == b' ' { char::from(byte) } else { '?' };
+        let _ = string.push(c);
+    }
+    let len = string.trim_end().len();
+    string.truncate(len);
+    string
+}
+
+impl Identity {
+    /// Parses the 256 words returned by `IDENTIFY DEVICE`, `None` if the disk has no LBAs.
+    pub fn parse(words: &[u16; 256]) -> Option<Self> {
+        // word 49, bit 9: LBA is supported.
+        if words[49] & (1 << 9) == 0 {
+            return None;
+        }
+        // word 83, bit 10: the 48 bit feature set is supported.
+        let lba48 = words[83] & (1 << 10) != 0;
+        let sectors = if lba48 {
+            words[100..104].iter().rev().fold(0, |sectors, &word| sectors << 16 | u64::from(word))
+        } else {
+            u64::from(words[61]) << 16 | u64::from(words[60])
+        };
+        Some(Self { model: ata_string(&words[27..47]), serial: ata_string(&words[10..20]), sectors, lba48 })
+    }
+
+    /// The size in bytes.
+    pub fn size(&self) -> u64 {
+        self.sectors * SECTOR_SIZE as u64
+    }
+}
+
+/// The registers of an IDE channel.
+#[derive(Debug)]
+struct Channel {
+    data: Port<u16>,
+    sector_count: Port<u8>,
+    lba_low: Port<u8>,
+    lba_mid: Port<u8>,
+    lba_high: Port<u8>,
+    drive: Port<u8>,
+    /// Status when read, command when written.
+    command: Port<u8>,
+    /// Alternate status when read, device control when written.
+    control: Port<u8>,
+}
+
+impl Channel {
+    const fn new(base: u16, control: u16) -> Self {
+        Self {
+            data: Port::new(base),
+            sector_count: Port::new(base + 2),
+            lba_low: Port::new(base + 3),
+            lba_mid: Port::new(base + 4),
+            lba_high: Port::new(base + 5),
+            drive: Port::new(base + 6),
+            command: Port::new(base + 7),
+            control: Port::new(control),
+        }
+    }
+
+    /// Reads the alternate status, which does not acknowledge interrupts.
+    fn status(&mut self) -> u8 {
+        // Safety: reading the alternate status has no side effects.
+        unsafe { self.control.read() }
+    }
+
+    /// Selects `drive`, with the high LBA bits of 28 bit commands.
+    fn select(&mut self, secondary: bool, lba_high: u8) {
+        let secondary = if secondary { DRIVE_SECONDARY } else { 0 };
+        let drive = DRIVE_OBSOLETE | DRIVE_LBA | secondary | lba_high;
+        // Safety: selecting a drive only changes which one the other registers talk to.
+        unsafe { self.drive.write(drive) };
+        // the status is valid 400ns later, each read takes about 100ns.
+        for _ in 0..4 {
+            self.status();
+        }
+    }
+
+    /// Waits until the drive is not busy, and returns the status.
+    fn wait_idle(&mut self) -> io::Result<u8> {
+        time::poll_until(TIMEOUT, || Some(self.status()).filter(|status| status & STATUS_BUSY == 0)).ok_or(TIMED_OUT)
+    }
+
+    /// Waits until the drive requests a data transfer.
+    fn wait_data(&mut self) -> io::Result<()> {
+        let status = time::poll_until(TIMEOUT, || {
+            Some(self.status()).filter(|status| status & STATUS_BUSY == 0 && status & (STATUS_DATA_REQUEST | STATUS_ERROR | STATUS_FAULT) != 0)
+        })
+        .ok_or(TIMED_OUT)?;
+        if status & (STATUS_ERROR | STATUS_FAULT) != 0 {
+            return Err(DEVICE_ERROR);
+        }
+        Ok(())
+    }
+
+    /// Writes the sector count and LBA, then `command`. With `lba48`, the high bytes are written
+    /// first, each register holds two bytes.
+    fn issue(&mut self, secondary: bool, lba: u64, lba48: bool, command: u8) -> io::Result<()> {
+        let bytes = lba.to_le_bytes();
+        // 28 bit LBAs keep their high 4 bits in the drive register.
+        self.select(secondary, if lba48 { 0 } else { bytes[3] & 0x0F });
+        self.wait_idle()?;
+        // Safety: the drive is idle, and takes the parameters of a command.
+        unsafe {
+            if lba48 {
+                self.sector_count.write(0);
+                self.lba_low.write(bytes[3]);
+                self.lba_mid.write(bytes[4]);
+                self.lba_high.write(bytes[5]);
+            }
+            self.sector_count.write(1);
+            self.lba_low.write(bytes[0]);
+            self.lba_mid.write(bytes[1]);
+            self.lba_high.write(bytes[2]);
+            self.command.write(command);
+        }
+        Ok(())
+    }
+
+    fn read_sector(&mut self, secondary: bool, lba: u64, lba48: bool, buf: &mut [u8; SECTOR_SIZE]) -> io::Result<()> {
+        self.issue(secondary, lba, lba48, if lba48 { CMD_READ_SECTORS_EXT } else { CMD_READ_SECTORS })?;
+        self.wait_data()?;
+        for chunk in buf.chunks_exact_mut(2) {
+            // Safety: the drive requested the transfer of a sector.
+            chunk.copy_from_slice(&unsafe { self.data.read() }.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    fn write_sector(&mut self, secondary: bool, lba: u64, lba48: bool, buf: &[u8; SECTOR_SIZE]) -> io::Result<()> {
+        self.issue(secondary, lba, lba48, if lba48 { CMD_WRITE_SECTORS_EXT } else { CMD_WRITE_SECTORS })?;
+        self.wait_data()?;
+        for chunk in buf.chunks_exact(2) {
+            // Safety: the drive requested the transfer of a sector.
+            unsafe { self.data.write(u16::from_le_bytes([chunk[0], chunk[1]])) };
+        }
+        // the sector may only be in the drive's cache so far.
+        self.wait_idle()?;
+        // Safety: the drive is idle.
+        unsafe { self.command.write(if lba48 { CMD_FLUSH_CACHE_EXT } else { CMD_FLUSH_CACHE }) };
+        match self.wait_idle()? & (STATUS_ERROR | STATUS_FAULT) {
+            0 => Ok(()),
+            _ => Err(DEVICE_ERROR),
+        }
+    }
+
+    /// Identifies a drive, `None` if there is none, or it is not an ATA disk.
+    fn identify(&mut self, secondary: bool) -> io::Result<Option<Identity>> {
+        self.select(secondary, 0);
+        // Safety: the parameters of IDENTIFY DEVICE are 0.
+        unsafe {
+            self.sector_count.write(0);
+            self.lba_low.write(0);
+            self.lba_mid.write(0);
+            self.lba_high.write(0);
+            self.command.write(CMD_IDENTIFY);
+        }
+        if self.status() == 0 {
+            return Ok(None);
+        }
+        self.wait_idle()?;
+        // ATAPI and SATA drives put their signature here, instead of identifying.
+        // Safety: reading the LBA registers has no side effects.
+        let signature = unsafe { (self.lba_mid.read(), self.lba_high.read()) };
+        if signature != (0, 0) {
+            debug!("Skipping a drive with the signature {:#04x}:{:#04x}", signature.0, signature.1);
+            return Ok(None);
+        }
+        if self.wait_data().is_err() {
+            return Ok(None);
+        }
+        let mut words = [0; 256];
+        for word in words.iter_mut() {
+            // Safety: the drive requested the transfer of its identity.
+            *word = unsafe { self.data.read() };
+        }
+        Ok(Identity::parse(&words))
+    }
+}
+
+static CHANNELS: [Mutex<Channel>; 2] = [Mutex::new(Channel::new(0x1F0, 0x3F6)), Mutex::new(Channel::new(0x170, 0x376))];
+
+/// One of the [`DRIVES`], a [`BlockDevice`] once [`init`] found a disk.
+#[derive(Debug)]
+pub struct Disk {
+    channel: usize,
+    secondary: bool,
+    identity: Mutex<Option<Identity>>,
+}
+
+static DISKS: [Disk; DRIVES] = [
+    Disk { channel: 0, secondary: false, identity: Mutex::new(None) },
+    Disk { channel: 0, secondary: true, identity: Mutex::new(None) },
+    Disk { channel: 1, secondary: false, identity: Mutex::new(None) },
+    Disk { channel: 1, secondary: true, identity: Mutex::new(None) },
+];
+
+/// The name of a drive, E.g. `hda` for the first drive of the primary channel.
+pub fn drive_name(drive: usize) -> &'static str {
+    ["hda", "hdb", "hdc", "hdd"][drive]
+}
+
+impl Disk {
+    /// What the disk reported, `None` if there is no disk.
+    pub fn identity(&self) -> Option<Identity> {
+        without_interrupts(|| *self.identity.lock())
+    }
+
+    /// Checks `lba`, and returns wether it needs 48 bits.
+    fn check(&self, lba: u64) -> io::Result<bool> {
+        let identity = self.identity().ok_or(Error::new(ErrorKind::NotFound, "there is no disk"))?;
+        if lba >= identity.sectors {
+            return Err(Error::new(ErrorKind::InvalidInput, "the sector is past the end of the disk"));
+        }
+        // 28 bit commands are shorter, so they are used when possible.
+        Ok(identity.lba48 && lba >= LBA28_SECTORS)
+    }
+}
+
+impl BlockDevice for Disk {
+    fn sectors(&self) -> u64 {
+        self.identity().map_or(0, |identity| identity.sectors)
+    }
+
+    fn read_sector(&self, lba: u64, buf: &mut [u8; SECTOR_SIZE]) -> io::Result<()> {
+        let lba48 = self.check(lba)?;
+        isolation::run("ata", || {
+            without_interrupts(|| CHANNELS[self.channel].lock().read_sector(self.secondary, lba, lba48, buf))
+        })?
+    }
+
+    fn write_sector(&self, lba: u64, buf: &[u8; SECTOR_SIZE]) -> io::Result<()> {
+        let lba48 = self.check(lba)?;
+        isolation::run("ata", || {
+            without_interrupts(|| CHANNELS[self.channel].lock().write_sector(self.secondary, lba, lba48, buf))
+        })?
+    }
+}
+
+/// The disk at `drive`, if [`init`] found one.
+pub fn disk(drive: usize) -> Option<&'static Disk> {
+    DISKS.get(drive).filter(|disk| disk.identity().is_some())
+}
+
+/// Unregisters the disks, after the driver faulted.
+fn detach() {
+    for drive in 0..DRIVES {
+        storage::unregister(drive_name(drive));
+    }
+}
+
+/// Probes both channels, and registers the disks, see the [module docs](self)
+pub fn init() {
+    // fails if the channels are probed again, when it is registered already.
+    let _ = isolation::register("ata", detach);
+    for (drive, disk) in DISKS.iter().enumerate() {
+        let identity = isolation::run("ata", || without_interrupts(|| {
+            let mut channel = CHANNELS[disk.channel].lock();
+            if channel.status() == STATUS_FLOATING {
+                return Ok(None);
+            }
+            // Safety: the transfers are polled.
+            unsafe { channel.control.write(CONTROL_NO_INTERRUPTS) };
+            channel.identify(disk.secondary)
+        })).unwrap_or_else(|e| Err(e.into()));
+        let identity = match identity {
+            Ok(Some(identity)) => identity,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("Failed to identify {}: {e}", drive_name(drive));
+                continue;
+            }
+        };
+        without_interrupts(|| *disk.identity.lock() = Some(identity));
+        info!("{}: {} ({} sectors, {} MiB)", drive_name(drive), identity.model, identity.sectors, identity.size() >> 20);
+        if storage::register(drive_name(drive), disk).is_err() {
+            warn!("{} was not registered", drive_name(drive));
+        }
+    }
+}
+
+/// Tests parsing the identity of a disk.
+#[cfg(feature = "test")]
+pub fn test_ata_identity(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::test_assert_eq;
+
+    let mut words = [0; 256];
+    test_assert_eq!(Identity::parse(&words), None)?;
+
+    words[49] = 1 << 9;
+    words[60] = 0x0000;
+    words[61] = 0x0010;
+    // "QEMU HARDDISK", padded with spaces, the first character in the high byte.
+    for (word, pair) in words[27..47].iter_mut().zip(b"QEMU HARDDISK                           ".chunks_exact(2)) {
+        *word = u16::from_be_bytes([pair[0], pair[1]]);
+    }
+    for (word, pair) in words[10..20].iter_mut().zip(b"QM00001             ".chunks_exact(2)) {
+        *word = u16::from_be_bytes([pair[0], pair[1]]);
+    }
+    let identity = Identity::parse(&words).ok_or("the identity was not parsed")?;
+    test_assert_eq!(&*identity.model, "QEMU HARDDISK")?;
+    test_assert_eq!(&*identity.serial, "QM00001")?;
+    test_assert_eq!(identity.sectors, 0x10_0000)?;
+    test_assert_eq!(identity.size(), 512 << 20)?;
+    test_assert_eq!(identity.lba48, false)?;
+
+    words[83] = 1 << 10;
+    words[100..104].copy_from_slice(&[0x0000, 0x0000, 0x0001, 0x0000]);
+    let identity = Identity::parse(&words).ok_or("the identity was not parsed")?;
+    test_assert_eq!(identity.lba48, true)?;
+    test_assert_eq!(identity.sectors, 1 << 32)
+}
@@ -0,0 +1,235 @@
+//! Isolating the kernel from drivers which fault.
+//! 
+//! A driver is [registered](register) with a function which detaches it from the rest of the
+//! kernel, E.g. by unregistering its block devices, and runs its code through [`run`]. A panic, or
+//! a page fault, in that code is caught: the driver is reported with an oops, detached and
+//! disabled, and later calls to [`run`] fail with [`DriverError::Disabled`] instead of touching the
+//! device again.
+//! 
+//! The MMIO ranges of a driver are [tracked](track_mmio), so a page fault on them names the driver,
+//! even outside of [`run`]. Once the driver is disabled, its ranges are poisoned: the pages stay
+//! mapped, but [`is_poisoned`] tells code which still has a pointer into them to keep away.
+//! 
+//! As with [`catch`], the locks held by the faulting code stay locked, so they should only be
+//! shared with the rest of the kernel through the detach function.
+use core::{fmt::{self, Display}, ops::Range};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{collections::{ArrayVec, CapacityError}, io, log::warn, panic::{catch::{catch, CaughtPanic}, oops::{self, oops}}};
+
+/// Maximum amount of registered drivers.
+pub const MAX_DRIVERS: usize = 16;
+/// Maximum amount of tracked MMIO ranges.
+pub const MAX_MMIO_RANGES: usize = 16;
+
+/// Detaches a driver from the rest of the kernel, after it faulted.
+/// 
+/// It runs after the fault was caught, so the locks of the driver may still be held.
+pub type DetachFn = fn();
+
+#[derive(Debug, Clone, Copy)]
+struct Driver {
+    name: &'static str,
+    detach: DetachFn,
+    disabled: bool,
+}
+
+/// A physical range of device memory, used by a driver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MmioRange {
+    /// The name of the driver.
+    pub driver: &'static str,
+    /// The physical range (end exclusive)
+    pub range: Range<u64>,
+    /// Wether the driver was disabled, so the range must not be accessed anymore.
+    pub poisoned: bool,
+}
+
+static DRIVERS: Mutex<ArrayVec<Driver, MAX_DRIVERS>> = Mutex::new(ArrayVec::new());
+static MMIO: Mutex<ArrayVec<MmioRange, MAX_MMIO_RANGES>> = Mutex::new(ArrayVec::new());
+/// The driver whose code is running in [`run`]
+static RUNNING: Mutex<Option<&'static str>> = Mutex::new(None);
+
+/// Why a driver did not run.
+// the panic is kept by value, the heap may be what panicked.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy)]
+pub enum DriverError {
+    /// The driver is not registered.
+    NotRegistered,
+    /// The driver faulted before, and was disabled.
+    Disabled,
+    /// The driver faulted now, and was disabled.
+    Faulted(CaughtPanic),
+}
+
+impl Display for DriverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotRegistered => write!(f, "the driver is not registered"),
+            Self::Disabled => write!(f, "the driver is disabled"),
+            Self::Faulted(panic) => write!(f, "the driver faulted, and was disabled: {panic}"),
+        }
+    }
+}
+
+impl core::error::Error for DriverError {}
+
+impl From<DriverError> for io::Error {
+    fn from(e: DriverError) -> Self {
+        match e {
+            DriverError::NotRegistered => io::Error::new(io::ErrorKind::NotFound, "the driver is not registered"),
+            DriverError::Disabled => io::Error::new(io::ErrorKind::Other, "the driver is disabled"),
+            DriverError::Faulted(_) => io::Error::new(io::ErrorKind::Other, "the driver faulted, and was disabled"),
+        }
+    }
+}
+
+/// Registers the driver `name`, which is detached by `detach` if it faults.
+/// # Errors
+/// Returns an error if there are [`MAX_DRIVERS`] already, or one with the same name.
+pub fn register(name: &'static str, detach: DetachFn) -> Result<(), CapacityError> {
+    without_interrupts(|| {
+        let mut drivers = DRIVERS.lock();
+        if drivers.iter().any(|d| d.name == name) {
+            return Err(CapacityError(()));
+        }
+        drivers.push(Driver { name, detach, disabled: false }).map_err(|_| CapacityError(()))
+    })
+}
+
+/// Unregisters the driver `name` and its MMIO ranges, returns wether it was registered.
+pub fn unregister(name: &str) -> bool {
+    without_interrupts(|| {
+        MMIO.lock().retain(|r| r.driver != name);
+        let mut drivers = DRIVERS.lock();
+        let index = drivers.iter().position(|d| d.name == name);
+        index.map(|i| drivers.remove(i)).is_some()
+    })
+}
+
+/// Tracks the physical `range` as device memory of the driver `name`
+/// # Errors
+/// Returns an error if there are [`MAX_MMIO_RANGES`] already.
+pub fn track_mmio(name: &'static str, range: Range<u64>) -> Result<(), CapacityError> {
+    let disabled = is_disabled(name);
+    without_interrupts(|| {
+        MMIO.lock().push(MmioRange { driver: name, range, poisoned: disabled }).map_err(|_| CapacityError(()))
+    })
+}
+
+/// Wether the driver `name` was disabled after a fault.
+pub fn is_disabled(name: &str) -> bool {
+    without_interrupts(|| DRIVERS.lock().iter().any(|d| d.name == name && d.disabled))
+}
+
+/// Wether `addr` is in an MMIO range of a disabled driver.
+pub fn is_poisoned(addr: u64) -> bool {
+    without_interrupts(|| MMIO.lock().iter().any(|r| r.poisoned && r.range.contains(&addr)))
+}
+
+/// Calls `f` on every tracked MMIO range.
+pub fn for_each_mmio(f: impl FnMut(&MmioRange)) {
+    let ranges = without_interrupts(|| MMIO.lock().clone());
+    ranges.iter().for_each(f);
+}
+
+/// The driver to blame for a page fault at `addr`: the driver running in [`run`], or the one
+/// whose MMIO contains `addr`.
+/// 
+/// Called by the page fault handler, so it does not wait for the locks.
+pub(crate) fn fault_owner(addr: u64) -> Option<&'static str> {
+    if let Some(running) = RUNNING.try_lock().and_then(|running| *running) {
+        return Some(running);
+    }
+    MMIO.try_lock()?.iter().find(|r| r.range.contains(&addr)).map(|r| r.driver)
+}
+
+/// Runs `f` as code of the driver `name`, and detaches and disables the driver if it panics or
+/// faults.
+/// # Errors
+/// Returns an error if the driver is not registered, is disabled, or faulted in `f`.
+#[allow(clippy::result_large_err)]
+pub fn run<R>(name: &'static str, f: impl FnOnce() -> R) -> Result<R, DriverError> {
+    let driver = without_interrupts(|| DRIVERS.lock().iter().find(|d| d.name == name).copied());
+    match driver {
+        None => return Err(DriverError::NotRegistered),
+        Some(driver) if driver.disabled => return Err(DriverError::Disabled),
+        Some(_) => {}
+    }
+    let outer = without_interrupts(|| RUNNING.lock().replace(name));
+    let result = catch(f);
+    without_interrupts(|| *RUNNING.lock() = outer);
+    result.map_err(|panic| {
+        detach(name, &panic);
+        DriverError::Faulted(panic)
+    })
+}
+
+/// Reports the fault of the driver `name`, disables it, poisons its MMIO, and detaches it.
+fn detach(name: &'static str, panic: &CaughtPanic) {
+    oops!(name, "the driver faulted: {panic}");
+    let detach = without_interrupts(|| {
+        let mut mmio = MMIO.lock();
+        mmio.iter_mut().filter(|r| r.driver == name).for_each(|r| r.poisoned = true);
+        let mut drivers = DRIVERS.lock();
+        let driver = drivers.iter_mut().find(|d| d.name == name)?;
+        driver.disabled = true;
+        Some(driver.detach)
+    });
+    // called without the locks, it may unregister other things.
+    if let Some(detach) = detach {
+        detach();
+    }
+    warn!("The driver {name} was detached and disabled.");
+}
+
+/// Enables the driver `name` again, after its device was reset. Returns wether it was disabled.
+pub fn enable(name: &str) -> bool {
+    let enabled = without_interrupts(|| {
+        MMIO.lock().iter_mut().filter(|r| r.driver == name).for_each(|r| r.poisoned = false);
+        let mut drivers = DRIVERS.lock();
+        let driver = drivers.iter_mut().find(|d| d.name == name && d.disabled);
+        driver.map(|d| d.disabled = false).is_some()
+    });
+    if enabled {
+        oops::mark_recovered(name);
+    }
+    enabled
+}
+
+/// Tests detaching a driver which faults on its MMIO.
+#[cfg(feature = "test")]
+pub fn test_driver_isolation(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::test::{test_assert, test_assert_eq};
+
+    // not mapped, like in the page fault test.
+    const MMIO_BASE: u64 = 0x5557_0000_0000;
+    static DETACHED: AtomicUsize = AtomicUsize::new(0);
+
+    test_assert!(matches!(run("test-driver", || ()), Err(DriverError::NotRegistered)))?;
+    register("test-driver", || { DETACHED.fetch_add(1, Ordering::Relaxed); }).map_err(|_| "the driver was not registered")?;
+    test_assert!(register("test-driver", || ()).is_err())?;
+    track_mmio("test-driver", MMIO_BASE..MMIO_BASE + 0x1000).map_err(|_| "the MMIO was not tracked")?;
+    test_assert_eq!(run("test-driver", || 5).ok(), Some(5))?;
+
+    // Safety: the address is not mapped, the fault is caught.
+    let faulted = run("test-driver", || unsafe { core::ptr::read_volatile((MMIO_BASE + 8) as *const u32) });
+    let Err(DriverError::Faulted(panic)) = faulted else {
+        return crate::test::TestResult::Failure("the read did not fault");
+    };
+    test_assert!(panic.message.contains("in the driver `test-driver`"))?;
+    test_assert_eq!(DETACHED.load(Ordering::Relaxed), 1)?;
+    test_assert!(is_disabled("test-driver") && is_poisoned(MMIO_BASE + 8) && !is_poisoned(MMIO_BASE + 0x1000))?;
+    test_assert!(matches!(run("test-driver", || ()), Err(DriverError::Disabled)))?;
+    test_assert!(oops::is_failed("test-driver"))?;
+
+    test_assert!(enable("test-driver") && !enable("test-driver"))?;
+    test_assert!(!is_poisoned(MMIO_BASE + 8) && !oops::is_failed("test-driver"))?;
+    test_assert!(unregister("test-driver"))?;
+    test_assert!(!unregister("test-driver"))
+}
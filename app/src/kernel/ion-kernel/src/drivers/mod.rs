@@ -0,0 +1,10 @@
+//! Device drivers.
+
+/// ATA disks, on the IDE channels.
+pub mod ata;
+
+/// Disabling drivers which fault, instead of panicking.
+pub mod isolation;
+
+/// The PS/2 controller, and its devices.
+pub mod ps2;
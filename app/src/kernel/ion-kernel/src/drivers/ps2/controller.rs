@@ -0,0 +1,315 @@
+//! The PS/2 controller.
+//! 
+//! [`init`] runs the canonical initialization sequence: both ports are disabled, the output
+//! buffer is flushed, the controller and its ports are tested, and it is detected whether there
+//! is a second port (for a mouse). Afterwards, the working ports are enabled again.
+//! 
+//! Devices are talked to through [`lock`], so the keyboard and mouse drivers do not interleave
+//! their commands.
+use core::{fmt::{self, Display}, time::Duration};
+
+use spin::{Mutex, MutexGuard};
+use x86_64::instructions::port::Port;
+
+use crate::{log::{debug, warn}, time};
+
+/// How long to wait for the controller, before giving up.
+/// 
+/// See [`time::poll_until`], this is accurate with interrupts disabled as well.
+pub const TIMEOUT: Duration = Duration::from_millis(100);
+
+const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64;
+
+/// The output buffer has a byte for us.
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+/// The controller has not taken our last byte yet.
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+
+const CMD_READ_CONFIG: u8 = 0x20;
+const CMD_WRITE_CONFIG: u8 = 0x60;
+const CMD_DISABLE_PORT2: u8 = 0xA7;
+const CMD_ENABLE_PORT2: u8 = 0xA8;
+const CMD_TEST_PORT2: u8 = 0xA9;
+const CMD_SELF_TEST: u8 = 0xAA;
+const CMD_TEST_PORT1: u8 = 0xAB;
+const CMD_DISABLE_PORT1: u8 = 0xAD;
+const CMD_ENABLE_PORT1: u8 = 0xAE;
+const CMD_WRITE_PORT2: u8 = 0xD4;
+
+const SELF_TEST_PASSED: u8 = 0x55;
+
+/// Bits of the controller configuration byte.
+pub mod config {
+    /// Interrupts of the first port (IRQ 1).
+    pub const PORT1_IRQ: u8 = 1 << 0;
+    /// Interrupts of the second port (IRQ 12).
+    pub const PORT2_IRQ: u8 = 1 << 1;
+    /// The clock of the first port is disabled.
+    pub const PORT1_CLOCK_OFF: u8 = 1 << 4;
+    /// The clock of the second port is disabled.
+    pub const PORT2_CLOCK_OFF: u8 = 1 << 5;
+    /// Scancodes of the first port are translated to set 1.
+    pub const TRANSLATION: u8 = 1 << 6;
+}
+
+/// One of the controller's ports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortId {
+    /// The first port, usually the keyboard.
+    First,
+    /// The second port, usually the mouse.
+    Second,
+}
+
+/// An error of the PS/2 controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerError {
+    /// The controller did not respond in time.
+    Timeout,
+    /// The self test returned this, instead of `0x55`
+    SelfTestFailed(u8),
+    /// Neither port passed its interface test.
+    NoWorkingPorts,
+    /// The port is not present, or failed its test.
+    PortUnavailable(PortId),
+}
+
+impl Display for ControllerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "the PS/2 controller timed out"),
+            Self::SelfTestFailed(resp) => write!(f, "the PS/2 controller failed its self test ({resp:#04x})"),
+            Self::NoWorkingPorts => write!(f, "no PS/2 port works"),
+            Self::PortUnavailable(port) => write!(f, "the {port:?} PS/2 port is unavailable"),
+        }
+    }
+}
+
+impl core::error::Error for ControllerError {}
+
+/// What [`init`] found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControllerInfo {
+    /// The controller has a second port.
+    pub dual_port: bool,
+    /// The first port passed its interface test.
+    pub port1: bool,
+    /// The second port exists, and passed its interface test.
+    pub port2: bool,
+    /// The first port's scancodes are translated to set 1.
+    pub translation: bool,
+}
+
+impl ControllerInfo {
+    /// Whether `port` can be used.
+    pub fn has(&self, port: PortId) -> bool {
+        match port {
+            PortId::First => self.port1,
+            PortId::Second => self.port2,
+        }
+    }
+}
+
+/// The PS/2 controller, see [`lock`]
+#[derive(Debug)]
+pub struct Controller {
+    data: Port<u8>,
+    status: Port<u8>,
+    info: Option<ControllerInfo>,
+}
+
+impl Controller {
+    /// What [`init`] found, `None` before it ran.
+    pub fn info(&self) -> Option<ControllerInfo> {
+        self.info
+    }
+
+    fn status(&mut self) -> u8 {
+        // Safety: reading the status has no side effects.
+        unsafe { self.status.read() }
+    }
+
+    /// Writes to the data port, once the controller is ready.
+    fn write_data(&mut self, byte: u8) -> Result<(), ControllerError> {
+        time::poll_until(TIMEOUT, || (self.status() & STATUS_INPUT_FULL == 0).then_some(()))
+            .ok_or(ControllerError::Timeout)?;
+        // Safety: the controller is ready for a byte.
+        unsafe { self.data.write(byte) };
+        Ok(())
+    }
+
+    /// Sends a command to the controller itself.
+    pub fn command(&mut self, command: u8) -> Result<(), ControllerError> {
+        time::poll_until(TIMEOUT, || (self.status() & STATUS_INPUT_FULL == 0).then_some(()))
+            .ok_or(ControllerError::Timeout)?;
+        // Safety: the controller is ready for a command.
+        unsafe { self.status.write(command) };
+        Ok(())
+    }
+
+    /// Sends a command to the controller, and reads its response.
+    pub fn command_with_response(&mut self, command: u8) -> Result<u8, ControllerError> {
+        self.command(command)?;
+        self.read()
+    }
+
+    /// Sends a command to the controller, with an argument.
+    pub fn command_with_arg(&mut self, command: u8, arg: u8) -> Result<(), ControllerError> {
+        self.command(command)?;
+        self.write_data(arg)
+    }
+
+    /// Reads the configuration byte, see [`config`]
+    pub fn config(&mut self) -> Result<u8, ControllerError> {
+        self.command_with_response(CMD_READ_CONFIG)
+    }
+
+    /// Writes the configuration byte, see [`config`]
+    pub fn set_config(&mut self, config: u8) -> Result<(), ControllerError> {
+        self.command_with_arg(CMD_WRITE_CONFIG, config)
+    }
+
+    /// Reads a byte from either device, or a controller response.
+    pub fn read(&mut self) -> Result<u8, ControllerError> {
+        time::poll_until(TIMEOUT, || (self.status() & STATUS_OUTPUT_FULL != 0).then_some(()))
+            .ok_or(ControllerError::Timeout)?;
+        // Safety: there is a byte in the output buffer.
+        Ok(unsafe { self.data.read() })
+    }
+
+    /// Reads a byte from either device, or a controller response, `None` if there is none yet.
+    pub fn try_read(&mut self) -> Option<u8> {
+        // Safety: there is a byte in the output buffer.
+        (self.status() & STATUS_OUTPUT_FULL != 0).then(|| unsafe { self.data.read() })
+    }
+
+    /// Writes a byte to the device on `port`, `None` if the controller is not ready for it yet.
+    /// # Errors
+    /// Returns an error if the port does not work (once [`init`] ran), or on a timeout.
+    pub fn try_write(&mut self, port: PortId, byte: u8) -> Result<Option<()>, ControllerError> {
+        if self.status() & STATUS_INPUT_FULL != 0 {
+            return Ok(None);
+        }
+        self.write(port, byte).map(Some)
+    }
+
+    /// Writes a byte to the device on `port`
+    /// # Errors
+    /// Returns an error if the port does not work (once [`init`] ran), or on a timeout.
+    pub fn write(&mut self, port: PortId, byte: u8) -> Result<(), ControllerError> {
+        if self.info.is_some_and(|info| !info.has(port)) {
+            return Err(ControllerError::PortUnavailable(port));
+        }
+        if port == PortId::Second {
+            self.command(CMD_WRITE_PORT2)?;
+        }
+        self.write_data(byte)
+    }
+
+    /// Discards every byte in the output buffer.
+    pub fn flush(&mut self) {
+        // bounded, in case the status port reads as 0xFF, when there is no controller.
+        for _ in 0..64 {
+            if self.status() & STATUS_OUTPUT_FULL == 0 {
+                break;
+            }
+            // Safety: discarding a byte is what we want.
+            unsafe { self.data.read() };
+        }
+    }
+
+    fn init(&mut self) -> Result<ControllerInfo, ControllerError> {
+        self.command(CMD_DISABLE_PORT1)?;
+        self.command(CMD_DISABLE_PORT2)?;
+        self.flush();
+        let original = self.config()?;
+
+        let result = self.test_and_enable(original);
+        if result.is_err() {
+            // best effort, so a keyboard which worked before keeps working.
+            let _ = self.set_config(original);
+            let _ = self.command(CMD_ENABLE_PORT1);
+        }
+        result
+    }
+
+    fn test_and_enable(&mut self, original: u8) -> Result<ControllerInfo, ControllerError> {
+        // no interrupts while testing, translation is kept, as the decoder depends on it.
+        let config = original & !(config::PORT1_IRQ | config::PORT2_IRQ | config::PORT1_CLOCK_OFF);
+        self.set_config(config)?;
+
+        let result = self.command_with_response(CMD_SELF_TEST)?;
+        if result != SELF_TEST_PASSED {
+            return Err(ControllerError::SelfTestFailed(result));
+        }
+        // some controllers reset themselves during the self test.
+        self.set_config(config)?;
+
+        // the second port's clock only turns on, if there is one.
+        let mut dual_port = false;
+        if original & config::PORT2_CLOCK_OFF != 0 {
+            self.command(CMD_ENABLE_PORT2)?;
+            dual_port = self.config()? & config::PORT2_CLOCK_OFF == 0;
+            self.command(CMD_DISABLE_PORT2)?;
+        }
+
+        let port1 = self.command_with_response(CMD_TEST_PORT1)? == 0;
+        let port2 = dual_port && self.command_with_response(CMD_TEST_PORT2)? == 0;
+        if !port1 && !port2 {
+            return Err(ControllerError::NoWorkingPorts);
+        }
+
+        let mut config = config;
+        if port1 {
+            self.command(CMD_ENABLE_PORT1)?;
+            config |= config::PORT1_IRQ;
+        }
+        if port2 {
+            self.command(CMD_ENABLE_PORT2)?;
+            config = (config | config::PORT2_IRQ) & !config::PORT2_CLOCK_OFF;
+        }
+        self.set_config(config)?;
+        self.flush();
+
+        Ok(ControllerInfo { dual_port, port1, port2, translation: config & config::TRANSLATION != 0 })
+    }
+}
+
+static CONTROLLER: Mutex<Controller> = Mutex::new(Controller {
+    data: Port::new(DATA_PORT),
+    status: Port::new(STATUS_PORT),
+    info: None,
+});
+
+/// Locks the controller.
+/// 
+/// Interrupts should be disabled while it is locked, as the keyboard interrupt may use it.
+pub fn lock() -> MutexGuard<'static, Controller> {
+    CONTROLLER.lock()
+}
+
+/// What [`init`] found, `None` before it ran, or if it failed.
+pub fn info() -> Option<ControllerInfo> {
+    x86_64::instructions::interrupts::without_interrupts(|| lock().info())
+}
+
+/// Initializes the controller, see the [module docs](self)
+/// 
+/// On error, the configuration is restored, when possible.
+pub fn init() -> Result<ControllerInfo, ControllerError> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut controller = lock();
+        match controller.init() {
+            Ok(info) => {
+                debug!("PS/2 controller: {:?}", info);
+                controller.info = Some(info);
+                Ok(info)
+            }
+            Err(e) => {
+                warn!("Failed to initialize the PS/2 controller: {e}");
+                Err(e)
+            }
+        }
+    })
+}
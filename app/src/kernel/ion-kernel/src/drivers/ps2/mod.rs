@@ -0,0 +1,8 @@
+//! The PS/2 controller (8042), see [`controller`], and the mouse, see [`mouse`]
+//! 
+//! The keyboard itself is handled in [`interrupts::keyboard`](crate::interrupts::keyboard).
+
+pub mod controller;
+pub mod mouse;
+
+pub use controller::{Controller, ControllerError, ControllerInfo, PortId};
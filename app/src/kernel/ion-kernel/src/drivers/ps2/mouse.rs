@@ -0,0 +1,244 @@
+//! The PS/2 mouse, on the controller's second port.
+//! 
+//! [`init`] enables the scroll wheel, if the mouse has one, and registers the mouse as an
+//! [`input`] device. Its packets are decoded in the interrupt handler, and turned into
+//! [`InputEvent`]s.
+use core::fmt::{self, Display};
+
+use spin::Mutex;
+use x86_64::{instructions::{interrupts::without_interrupts, port::Port}, structures::idt::InterruptStackFrame};
+
+use crate::{input::{self, Button, DeviceId, DeviceKind, InputEvent}, interrupts::{self, pic8259::handlers::notify}, log::{info, warn}};
+
+use super::controller::{self, Controller, ControllerError, PortId};
+
+const CMD_SET_SAMPLE_RATE: u8 = 0xF3;
+const CMD_GET_ID: u8 = 0xF2;
+const CMD_ENABLE_REPORTING: u8 = 0xF4;
+const CMD_SET_DEFAULTS: u8 = 0xF6;
+const ACK: u8 = 0xFA;
+
+/// Setting these sample rates in order enables the wheel of an IntelliMouse.
+const WHEEL_KNOCK: [u8; 3] = [200, 100, 80];
+/// The id of a mouse with a wheel.
+const ID_WHEEL: u8 = 0x03;
+
+/// The interrupt line of the second port.
+const IRQ: u8 = 12;
+
+/// The first byte of a packet always has this bit set, which is used to stay in sync.
+const ALWAYS_ONE: u8 = 1 << 3;
+const X_SIGN: u8 = 1 << 4;
+const Y_SIGN: u8 = 1 << 5;
+const X_OVERFLOW: u8 = 1 << 6;
+const Y_OVERFLOW: u8 = 1 << 7;
+
+/// An error while initializing the mouse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseError {
+    /// The controller failed.
+    Controller(ControllerError),
+    /// The mouse sent this, instead of an ACK.
+    UnexpectedByte(u8),
+}
+
+impl From<ControllerError> for MouseError {
+    fn from(e: ControllerError) -> Self {
+        Self::Controller(e)
+    }
+}
+
+impl Display for MouseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Controller(e) => Display::fmt(e, f),
+            Self::UnexpectedByte(b) => write!(f, "the mouse sent {b:#04x}, instead of an ACK"),
+        }
+    }
+}
+
+impl core::error::Error for MouseError {}
+
+/// A decoded packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Packet {
+    /// Horizontal movement, right is positive.
+    pub dx: i16,
+    /// Vertical movement, up is positive.
+    pub dy: i16,
+    /// The held buttons: left, right and middle, from the lowest bit.
+    pub buttons: u8,
+    /// Wheel movement, down is positive.
+    pub wheel: i8,
+}
+
+impl Packet {
+    /// Whether `button` is held.
+    pub fn is_held(&self, button: Button) -> bool {
+        self.buttons & button_bit(button) != 0
+    }
+}
+
+fn button_bit(button: Button) -> u8 {
+    match button {
+        Button::Left => 1 << 0,
+        Button::Right => 1 << 1,
+        Button::Middle => 1 << 2,
+    }
+}
+
+/// Assembles packets from the bytes of a mouse.
+#[derive(Debug, Clone, Copy)]
+pub struct PacketDecoder {
+    bytes: [u8; 4],
+    len: usize,
+    wheel: bool,
+}
+
+impl PacketDecoder {
+    /// A decoder of 3 byte packets, or 4 byte ones with a wheel.
+    pub const fn new(wheel: bool) -> Self {
+        Self { bytes: [0; 4], len: 0, wheel }
+    }
+
+    fn packet_len(&self) -> usize {
+        if self.wheel { 4 } else { 3 }
+    }
+
+    /// Adds a byte, and returns the packet, once it is complete.
+    pub fn feed(&mut self, byte: u8) -> Option<Packet> {
+        // a lost byte would shift every later packet, so drop bytes until a valid first one.
+        if self.len == 0 && byte & ALWAYS_ONE == 0 {
+            return None;
+        }
+        self.bytes[self.len] = byte;
+        self.len += 1;
+        if self.len < self.packet_len() {
+            return None;
+        }
+        self.len = 0;
+
+        let [flags, x, y, z] = self.bytes;
+        let axis = |value: u8, sign: u8, overflow: u8| {
+            if flags & overflow != 0 {
+                0
+            } else if flags & sign != 0 {
+                value as i16 - 0x100
+            } else {
+                value as i16
+            }
+        };
+        Some(Packet {
+            dx: axis(x, X_SIGN, X_OVERFLOW),
+            dy: axis(y, Y_SIGN, Y_OVERFLOW),
+            buttons: flags & 0b111,
+            // 4 bit two's complement.
+            wheel: if self.wheel { ((z << 4) as i8) >> 4 } else { 0 },
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Mouse {
+    decoder: PacketDecoder,
+    buttons: u8,
+    device: DeviceId,
+}
+
+static MOUSE: Mutex<Option<Mouse>> = Mutex::new(None);
+
+/// Sends a command to the mouse, and waits for its ACK.
+fn command(controller: &mut Controller, byte: u8) -> Result<(), MouseError> {
+    controller.write(PortId::Second, byte)?;
+    match controller.read()? {
+        ACK => Ok(()),
+        other => Err(MouseError::UnexpectedByte(other)),
+    }
+}
+
+/// Enables the wheel if there is one, and reporting, returns whether there is a wheel.
+fn enable(controller: &mut Controller) -> Result<bool, MouseError> {
+    command(controller, CMD_SET_DEFAULTS)?;
+    for rate in WHEEL_KNOCK {
+        command(controller, CMD_SET_SAMPLE_RATE)?;
+        command(controller, rate)?;
+    }
+    command(controller, CMD_GET_ID)?;
+    let wheel = controller.read()? == ID_WHEEL;
+    command(controller, CMD_ENABLE_REPORTING)?;
+    Ok(wheel)
+}
+
+/// Initializes the mouse, if the controller has a second port.
+/// 
+/// Must be called after [`controller::init`]
+pub fn init() -> Result<(), MouseError> {
+    if !controller::info().is_some_and(|info| info.has(PortId::Second)) {
+        return Err(ControllerError::PortUnavailable(PortId::Second).into());
+    }
+    let wheel = without_interrupts(|| enable(&mut controller::lock()));
+    let wheel = match wheel {
+        Ok(wheel) => wheel,
+        Err(e) => {
+            warn!("Failed to initialize the PS/2 mouse: {e}");
+            return Err(e);
+        }
+    };
+    let name = if wheel { "PS/2 wheel mouse" } else { "PS/2 mouse" };
+    let Ok(device) = input::register(name, DeviceKind::Mouse) else {
+        warn!("The PS/2 mouse was not registered, there are too many input devices");
+        return Ok(());
+    };
+    without_interrupts(|| *MOUSE.lock() = Some(Mouse { decoder: PacketDecoder::new(wheel), buttons: 0, device }));
+    interrupts::unmask(IRQ);
+    info!("{name}: {device}");
+    Ok(())
+}
+
+/// Handles a byte of the mouse.
+pub extern "x86-interrupt" fn mouse_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    let _context = crate::interrupts::context::enter();
+    let entry = crate::interrupts::stats::enter();
+    // Safety: the mouse interrupt means its byte is in the output buffer.
+    let byte: u8 = unsafe { Port::new(0x60).read() };
+
+    let decoded = MOUSE.lock().as_mut().and_then(|mouse| {
+        let packet = mouse.decoder.feed(byte)?;
+        let changed = core::mem::replace(&mut mouse.buttons, packet.buttons) ^ packet.buttons;
+        Some((mouse.device, packet, changed))
+    });
+    if let Some((device, packet, changed)) = decoded {
+        if packet.dx != 0 || packet.dy != 0 {
+            input::push(device, InputEvent::Motion { dx: packet.dx.into(), dy: packet.dy.into() });
+        }
+        for button in [Button::Left, Button::Right, Button::Middle] {
+            if changed & button_bit(button) != 0 {
+                input::push(device, InputEvent::Button { button, pressed: packet.is_held(button) });
+            }
+        }
+        if packet.wheel != 0 {
+            input::push(device, InputEvent::Wheel(packet.wheel.into()));
+        }
+    }
+    notify!(unsafe Mouse, entry);
+}
+
+/// Tests decoding packets, including losing sync.
+#[cfg(feature = "test")]
+pub fn test_mouse_packets(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::test_assert_eq;
+
+    let mut decoder = PacketDecoder::new(false);
+    // left held, moved right by 5 and down by 1.
+    let packets: [Option<Packet>; 3] = [0x29, 0x05, 0xFF].map(|b| decoder.feed(b));
+    test_assert_eq!(packets, [None, None, Some(Packet { dx: 5, dy: -1, buttons: 1, wheel: 0 })])?;
+    // a stray byte without the always one bit is dropped.
+    test_assert_eq!(decoder.feed(0x00), None)?;
+    // an overflowing axis does not move.
+    let packets: [Option<Packet>; 3] = [0x48, 0x80, 0x02].map(|b| decoder.feed(b));
+    test_assert_eq!(packets[2], Some(Packet { dx: 0, dy: 2, buttons: 0, wheel: 0 }))?;
+
+    let mut decoder = PacketDecoder::new(true);
+    let packets: [Option<Packet>; 4] = [0x08, 0x00, 0x00, 0x0F].map(|b| decoder.feed(b));
+    test_assert_eq!(packets[3], Some(Packet { dx: 0, dy: 0, buttons: 0, wheel: -1 }))
+}
@@ -0,0 +1,221 @@
+//! Device nodes, mounted at `/dev`
+//! 
+//! Drivers [register](register) their devices by name, E.g. `ttyS0`, and opening the node calls the
+//! [`Device`], which returns its own [`File`]. A name may contain `/`, then the node is in a
+//! directory, E.g. `input/event0`, which exists while there are nodes in it. Nodes can not be
+//! created or removed through the filesystem.
+use alloc::{boxed::Box, string::{String, ToString}, sync::Arc, vec::Vec};
+use core::fmt;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{Dir, DirEntry, File, FileSystem, FileType, Metadata, OpenOptions};
+use crate::{collections::{ArrayVec, CapacityError}, io::{self, Error, ErrorKind}};
+
+/// Maximum amount of registered devices.
+pub const MAX_DEVICES: usize = 32;
+
+/// A device, which is opened through its node.
+pub trait Device: Sync {
+    /// Opens the device.
+    /// # Errors
+    /// Returns an error if the device can not be opened, E.g. because it is busy.
+    fn open(&self) -> io::Result<Box<dyn File>>;
+}
+
+impl fmt::Debug for dyn Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Device").finish_non_exhaustive()
+    }
+}
+
+static DEVICES: Mutex<ArrayVec<(&'static str, &'static dyn Device), MAX_DEVICES>> = Mutex::new(ArrayVec::new());
+
+const READ_ONLY: Error = Error::new(ErrorKind::ReadOnlyFilesystem, "device nodes are registered by drivers");
+const NOT_FOUND: Error = Error::new(ErrorKind::NotFound, "no such device");
+
+/// Registers `device` as `/dev/{name}`, the components of `name` are separated by `/`
+/// # Errors
+/// Returns an error if there are [`MAX_DEVICES`] already, or one with the same name.
+pub fn register(name: &'static str, device: &'static dyn Device) -> Result<(), CapacityError> {
+    without_interrupts(|| {
+        let mut devices = DEVICES.lock();
+        if devices.iter().any(|&(n, _)| n == name) {
+            return Err(CapacityError(()));
+        }
+        devices.push((name, device)).map_err(|_| CapacityError(()))
+    })
+}
+
+/// Unregisters the device called `name`, returns wether it was registered. Open files stay open.
+pub fn unregister(name: &str) -> bool {
+    without_interrupts(|| {
+        let mut devices = DEVICES.lock();
+        let i = devices.iter().position(|&(n, _)| n == name);
+        i.map(|i| devices.remove(i)).is_some()
+    })
+}
+
+fn find(name: &str) -> io::Result<&'static dyn Device> {
+    without_interrupts(|| DEVICES.lock().iter().find(|&&(n, _)| n == name).map(|&(_, device)| device)).ok_or(NOT_FOUND)
+}
+
+/// The filesystem of the registered devices.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DevFs;
+
+impl FileSystem for DevFs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn root(&self) -> Arc<dyn Dir> {
+        Arc::new(DevDir { prefix: String::new() })
+    }
+}
+
+/// A directory of the registered devices, the nodes whose names start with `prefix`
+#[derive(Debug, Clone)]
+struct DevDir {
+    /// Empty for `/dev`, otherwise the path of the directory in it, ending in `/`
+    prefix: String,
+}
+
+impl DevDir {
+    /// The path of `name` in this directory, relative to `/dev`
+    fn path(&self, name: &str) -> String {
+        alloc::format!("{}{name}", self.prefix)
+    }
+
+    /// Returns wether `path` is a directory, as there are nodes in it.
+    fn is_dir(path: &str) -> bool {
+        without_interrupts(|| DEVICES.lock().iter().any(|&(n, _)| n.strip_prefix(path).is_some_and(|rest| rest.starts_with('/'))))
+    }
+}
+
+impl Dir for DevDir {
+    fn entries(&self) -> io::Result<Vec<DirEntry>> {
+        let mut entries: Vec<DirEntry> = without_interrupts(|| {
+            DEVICES.lock().iter().filter_map(|&(name, _)| {
+                let rest = name.strip_prefix(self.prefix.as_str())?;
+                Some(match rest.split_once('/') {
+                    Some((dir, _)) => DirEntry { name: dir.to_string(), file_type: FileType::Dir },
+                    None => DirEntry { name: rest.to_string(), file_type: FileType::Device },
+                })
+            }).collect()
+        });
+        entries.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        // a directory is listed once, however many nodes it has.
+        entries.dedup_by(|a, b| a.name == b.name);
+        Ok(entries)
+    }
+
+    fn metadata(&self, name: &str) -> io::Result<Metadata> {
+        let path = self.path(name);
+        if Self::is_dir(&path) {
+            return Ok(Metadata { file_type: FileType::Dir, len: 0 });
+        }
+        find(&path).map(|_| Metadata { file_type: FileType::Device, len: 0 })
+    }
+
+    fn open(&self, name: &str, _: OpenOptions) -> io::Result<Box<dyn File>> {
+        let path = self.path(name);
+        if Self::is_dir(&path) {
+            return Err(Error::new(ErrorKind::IsADirectory, "it is a directory"));
+        }
+        // creating a node fails, truncating and appending mean nothing to a device.
+        find(&path)?.open()
+    }
+
+    fn open_dir(&self, name: &str) -> io::Result<Arc<dyn Dir>> {
+        let path = self.path(name);
+        if Self::is_dir(&path) {
+            return Ok(Arc::new(DevDir { prefix: path + "/" }));
+        }
+        find(&path)?;
+        Err(Error::new(ErrorKind::NotADirectory, "it is a device"))
+    }
+
+    fn create_dir(&self, _: &str) -> io::Result<Arc<dyn Dir>> {
+        Err(READ_ONLY)
+    }
+
+    fn remove(&self, _: &str) -> io::Result<()> {
+        Err(READ_ONLY)
+    }
+}
+
+/// Tests registering a device, and opening its node.
+#[cfg(feature = "test")]
+pub fn test_devfs(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    #[derive(Debug)]
+    struct Zero;
+
+    impl Device for Zero {
+        fn open(&self) -> io::Result<Box<dyn File>> {
+            // a file of zeros, which ignores writes.
+            Ok(Box::new(ZeroFile))
+        }
+    }
+
+    #[derive(Debug)]
+    struct ZeroFile;
+
+    impl io::Read for ZeroFile {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            buf.fill(0);
+            Ok(buf.len())
+        }
+    }
+
+    impl io::Write for ZeroFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl io::Seek for ZeroFile {
+        fn seek(&mut self, _: io::SeekFrom) -> io::Result<u64> {
+            Ok(0)
+        }
+    }
+
+    impl File for ZeroFile {
+        fn metadata(&self) -> Metadata {
+            Metadata { file_type: FileType::Device, len: 0 }
+        }
+
+        fn set_len(&mut self, _: u64) -> io::Result<()> {
+            Err(READ_ONLY)
+        }
+    }
+
+    register("test-zero", &Zero).map_err(|_| "the device was not registered")?;
+    test_assert!(register("test-zero", &Zero).is_err())?;
+    let mut file = super::open("/dev/test-zero", OpenOptions::new()).map_err(|_| "the node was not opened")?;
+    let mut buf = [1; 4];
+    test_assert_eq!(file.read(&mut buf), Ok(4))?;
+    test_assert_eq!(buf, [0; 4])?;
+    test_assert_eq!(file.write(b"ignored"), Ok(7))?;
+    test_assert_eq!(super::metadata("/dev/test-zero").map(|m| m.file_type), Ok(FileType::Device))?;
+    test_assert_eq!(super::create_dir("/dev/dir").map_err(|e| e.kind()), Err(ErrorKind::ReadOnlyFilesystem))?;
+    test_assert!(unregister("test-zero"))?;
+    test_assert_eq!(super::metadata("/dev/test-zero").map_err(|e| e.kind()), Err(ErrorKind::NotFound))?;
+
+    // a node in a directory.
+    register("test/zero", &Zero).map_err(|_| "the device was not registered")?;
+    test_assert_eq!(super::metadata("/dev/test").map(|m| m.file_type), Ok(FileType::Dir))?;
+    let names: Vec<String> = super::read_dir("/dev/test").unwrap_or_default().into_iter().map(|e| e.name).collect();
+    test_assert_eq!(names, ["zero"])?;
+    test_assert!(super::open("/dev/test/zero", OpenOptions::new()).is_ok())?;
+    test_assert_eq!(super::open("/dev/test", OpenOptions::new()).map(drop).map_err(|e| e.kind()), Err(ErrorKind::IsADirectory))?;
+    test_assert!(unregister("test/zero"))?;
+    test_assert_eq!(super::metadata("/dev/test").map_err(|e| e.kind()), Err(ErrorKind::NotFound))
+}
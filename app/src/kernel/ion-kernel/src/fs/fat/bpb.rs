@@ -0,0 +1,153 @@
+//! The BIOS parameter block, which describes the layout of a FAT volume.
+use crate::io::{Error, ErrorKind, Result};
+
+/// The size of a directory entry.
+pub const DIR_ENTRY_SIZE: u64 = 32;
+
+/// The width of the entries of the allocation table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    /// 12 bit entries, for floppies.
+    Fat12,
+    /// 16 bit entries.
+    Fat16,
+    /// 28 bit entries, with the root directory in a cluster chain.
+    Fat32,
+}
+
+/// Where the root directory is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Root {
+    /// FAT12 and FAT16: a region after the tables, of `len` bytes.
+    Fixed {
+        /// Bytes from the start of the volume.
+        offset: u64,
+        /// The size in bytes.
+        len: u64,
+    },
+    /// FAT32: a cluster chain, like other directories.
+    Cluster(u32),
+}
+
+/// The layout of a volume, from its boot sector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// The width of the table entries.
+    pub fat_type: FatType,
+    /// Bytes per sector.
+    pub sector_size: u64,
+    /// Bytes per cluster.
+    pub cluster_size: u64,
+    /// Bytes from the start of the volume to the first table.
+    pub fat_offset: u64,
+    /// Bytes from the start of the volume to cluster 2, the first one.
+    pub data_offset: u64,
+    /// The amount of data clusters.
+    pub clusters: u32,
+    /// The root directory.
+    pub root: Root,
+}
+
+fn u16_at(sector: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([sector[offset], sector[offset + 1]])
+}
+
+fn u32_at(sector: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([sector[offset], sector[offset + 1], sector[offset + 2], sector[offset + 3]])
+}
+
+impl Layout {
+    /// Parses the first sector of a volume.
+    /// # Errors
+    /// Returns [`ErrorKind::InvalidData`] if it is not a FAT boot sector.
+    pub fn parse(sector: &[u8; 512]) -> Result<Self> {
+        let invalid = |message| Error::new(ErrorKind::InvalidData, message);
+        if sector[510..] != [0x55, 0xAA] {
+            return Err(invalid("the boot sector has no signature"));
+        }
+        let sector_size = u16_at(sector, 11);
+        let sectors_per_cluster = sector[13];
+        let reserved = u16_at(sector, 14);
+        let fats = sector[16];
+        let root_entries = u16_at(sector, 17);
+        let total = match u16_at(sector, 19) {
+            0 => u32_at(sector, 32),
+            total => u32::from(total),
+        };
+        let fat_size = match u16_at(sector, 22) {
+            0 => u32_at(sector, 36),
+            size => u32::from(size),
+        };
+        if !matches!(sector_size, 512 | 1024 | 2048 | 4096) || !sectors_per_cluster.is_power_of_two() {
+            return Err(invalid("the sector or cluster size is not valid"));
+        }
+        if reserved == 0 || fats == 0 || fat_size == 0 {
+            return Err(invalid("the volume has no allocation table"));
+        }
+
+        let sector_size = u64::from(sector_size);
+        let root_sectors = (u64::from(root_entries) * DIR_ENTRY_SIZE).div_ceil(sector_size);
+        let fat_offset = u64::from(reserved) * sector_size;
+        let root_offset = fat_offset + u64::from(fats) * u64::from(fat_size) * sector_size;
+        let data_offset = root_offset + root_sectors * sector_size;
+        let data_sectors = (u64::from(total) * sector_size).checked_sub(data_offset)
+            .ok_or(invalid("the volume is smaller than its tables"))? / sector_size;
+        let clusters = (data_sectors / u64::from(sectors_per_cluster)) as u32;
+
+        // the type only depends on the amount of clusters.
+        let fat_type = match clusters {
+            0..4085 => FatType::Fat12,
+            4085..65525 => FatType::Fat16,
+            _ => FatType::Fat32,
+        };
+        let root = match fat_type {
+            FatType::Fat32 => Root::Cluster(u32_at(sector, 44)),
+            _ => Root::Fixed { offset: root_offset, len: u64::from(root_entries) * DIR_ENTRY_SIZE },
+        };
+        Ok(Self {
+            fat_type,
+            sector_size,
+            cluster_size: sector_size * u64::from(sectors_per_cluster),
+            fat_offset,
+            data_offset,
+            clusters,
+            root,
+        })
+    }
+
+    /// Bytes from the start of the volume to `cluster`
+    pub fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.data_offset + u64::from(cluster - 2) * self.cluster_size
+    }
+
+    /// Wether `cluster` is a data cluster of this volume.
+    pub fn is_data_cluster(&self, cluster: u32) -> bool {
+        (2..self.clusters + 2).contains(&cluster)
+    }
+
+    /// Where the entry of `cluster` is in the table, and how many bytes to read.
+    pub fn fat_entry(&self, cluster: u32) -> (u64, usize) {
+        let cluster = u64::from(cluster);
+        match self.fat_type {
+            FatType::Fat12 => (self.fat_offset + cluster + cluster / 2, 2),
+            FatType::Fat16 => (self.fat_offset + cluster * 2, 2),
+            FatType::Fat32 => (self.fat_offset + cluster * 4, 4),
+        }
+    }
+
+    /// The next cluster, from the entry of `cluster` read at [`fat_entry`](Self::fat_entry), or
+    /// `None` at the end of the chain.
+    pub fn next_cluster(&self, cluster: u32, entry: &[u8]) -> Option<u32> {
+        let next = match self.fat_type {
+            FatType::Fat12 => {
+                let value = u16_at(entry, 0);
+                // odd clusters use the high 12 bits.
+                u32::from(if cluster % 2 == 1 { value >> 4 } else { value & 0xFFF })
+            }
+            FatType::Fat16 => u32::from(u16_at(entry, 0)),
+            FatType::Fat32 => u32_at(entry, 0) & 0x0FFF_FFFF,
+        };
+        // free, bad, or end of chain markers are not data clusters.
+        self.is_data_cluster(next).then_some(next)
+    }
+}
@@ -0,0 +1,135 @@
+//! Directory entries, with their long file names.
+//! 
+//! Every file has a short 8.3 entry. A long name is stored in entries before it, 13 UTF-16 units
+//! each, in reverse order, with the checksum of the short name, so entries left over by an old
+//! driver which renamed the file are ignored.
+use alloc::{string::String, vec::Vec};
+
+use super::bpb::DIR_ENTRY_SIZE;
+use crate::fs::FileType;
+
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+/// Read only, hidden, system and volume id, which no short entry has at once.
+const ATTR_LONG_NAME: u8 = 0x0F;
+/// The first byte of a deleted entry.
+const DELETED: u8 = 0xE5;
+/// Marks the last part of a long name, which comes first.
+const LAST_LONG_ENTRY: u8 = 0x40;
+/// Offsets of the UTF-16 units in a long name entry.
+const LONG_NAME_UNITS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// A file or directory of a FAT directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The long name, or the short name.
+    pub name: String,
+    /// Wether it is a file or a directory.
+    pub file_type: FileType,
+    /// Its first cluster, 0 for an empty file.
+    pub cluster: u32,
+    /// The size of a file in bytes.
+    pub size: u32,
+}
+
+/// The checksum of a short name, stored in its long name entries.
+pub fn checksum(short_name: &[u8]) -> u8 {
+    short_name.iter().fold(0u8, |sum, &b| sum.rotate_right(1).wrapping_add(b))
+}
+
+/// The short name as text, E.g. `README.TXT`, with the lowercase flags of Windows NT.
+fn short_name(raw: &[u8]) -> String {
+    let case = raw[12];
+    // bytes of the OEM code page are shown as Latin-1.
+    let part = |bytes: &[u8], lower: bool| -> String {
+        bytes.trim_ascii_end().iter().map(|&b| char::from(if lower { b.to_ascii_lowercase() } else { b })).collect()
+    };
+    let mut name = part(&raw[..8], case & 0x08 != 0);
+    // 0x05 stands for a first byte of 0xE5, which means deleted.
+    if raw[0] == 0x05 {
+        name.replace_range(..1, "\u{E5}");
+    }
+    let extension = part(&raw[8..11], case & 0x10 != 0);
+    if !extension.is_empty() {
+        name.push('.');
+        name.push_str(&extension);
+    }
+    name
+}
+
+/// A long name, being collected from its entries.
+#[derive(Debug, Default)]
+struct LongName {
+    units: Vec<u16>,
+    checksum: u8,
+    /// The sequence number of the next entry, counting down to 1.
+    next: u8,
+}
+
+impl LongName {
+    /// Adds a long name entry, starting a new name at its last part.
+    fn feed(&mut self, raw: &[u8]) {
+        let sequence = raw[0];
+        if sequence & LAST_LONG_ENTRY != 0 {
+            self.units = alloc::vec![0xFFFF; 13 * usize::from(sequence & 0x1F)];
+            self.checksum = raw[13];
+            self.next = sequence & 0x1F;
+        }
+        // out of order, or from another name.
+        if self.next == 0 || sequence & 0x1F != self.next || raw[13] != self.checksum {
+            self.units.clear();
+            self.next = 0;
+            return;
+        }
+        let start = 13 * usize::from(self.next - 1);
+        for (i, &offset) in LONG_NAME_UNITS.iter().enumerate() {
+            self.units[start + i] = u16::from_le_bytes([raw[offset], raw[offset + 1]]);
+        }
+        self.next -= 1;
+    }
+
+    /// The complete name for the short entry `raw`, if it belongs to it.
+    fn take(&mut self, raw: &[u8]) -> Option<String> {
+        let complete = self.next == 0 && !self.units.is_empty() && self.checksum == checksum(&raw[..11]);
+        let units = core::mem::take(&mut self.units);
+        if !complete {
+            return None;
+        }
+        // the name ends with a 0, and is padded with 0xFFFF
+        let len = units.iter().position(|&u| u == 0 || u == 0xFFFF).unwrap_or(units.len());
+        Some(char::decode_utf16(units[..len].iter().copied()).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect())
+    }
+}
+
+/// The entries of a directory, from its raw bytes. `.` and `..` are skipped.
+pub fn parse(bytes: &[u8]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut long_name = LongName::default();
+    for raw in bytes.chunks_exact(DIR_ENTRY_SIZE as usize) {
+        let attributes = raw[11];
+        match raw[0] {
+            // no entries follow.
+            0 => break,
+            DELETED => {
+                long_name.units.clear();
+                continue;
+            }
+            _ if attributes & 0x3F == ATTR_LONG_NAME => {
+                long_name.feed(raw);
+                continue;
+            }
+            _ => {}
+        }
+        let long = long_name.take(raw);
+        if attributes & ATTR_VOLUME_ID != 0 || raw[0] == b'.' {
+            continue;
+        }
+        entries.push(Entry {
+            name: long.unwrap_or_else(|| short_name(raw)),
+            file_type: if attributes & ATTR_DIRECTORY != 0 { FileType::Dir } else { FileType::File },
+            cluster: (u32::from(u16::from_le_bytes([raw[20], raw[21]])) << 16) | u32::from(u16::from_le_bytes([raw[26], raw[27]])),
+            size: u32::from_le_bytes([raw[28], raw[29], raw[30], raw[31]]),
+        });
+    }
+    entries
+}
@@ -0,0 +1,346 @@
+//! FAT12, FAT16 and FAT32 volumes, read only.
+//! 
+//! A volume is read from any [`Source`], a stream of bytes which can seek, such as a
+//! [`Cursor`](crate::io::Cursor) over a disk image in memory, or a
+//! [`BlockStream`](crate::storage::stream::BlockStream) over a disk. The type is found from the
+//! amount of clusters, like other drivers do, and long file names are read. Names are looked up
+//! without regard to ASCII case, as FAT does not keep it.
+//! 
+//! ```rust,no_run
+//! let volume = FatFs::new(Box::new(Cursor::new(image)))?;
+//! fs::mount("/boot", Arc::new(volume))?;
+//! let config = fs::read("/boot/ion/kernel.cfg")?;
+//! ```
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::fmt;
+
+use super::{Dir, DirEntry, File, FileSystem, FileType, Metadata, OpenOptions};
+use crate::{io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write}, lib_alloc::try_vec, sync::AdaptiveMutex};
+
+pub mod bpb;
+pub mod dir;
+
+use bpb::{Layout, Root};
+
+/// Where a volume is read from.
+pub trait Source: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> Source for T {}
+
+const READ_ONLY: Error = Error::new(ErrorKind::ReadOnlyFilesystem, "FAT volumes are read only");
+const NOT_FOUND: Error = Error::new(ErrorKind::NotFound, "no such file or directory");
+
+#[derive(Debug)]
+struct Volume {
+    /// Held while reading, which may wait for a device.
+    source: AdaptiveMutex<Box<dyn Source>>,
+    layout: Layout,
+}
+
+impl fmt::Debug for dyn Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Source").finish_non_exhaustive()
+    }
+}
+
+impl Volume {
+    /// Fills `buf` from `offset` bytes into the volume.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let mut source = self.source.lock();
+        source.seek(SeekFrom::Start(offset))?;
+        source.read_exact(buf)
+    }
+
+    /// The clusters of the chain starting at `first`
+    fn chain(&self, first: u32) -> io::Result<Vec<u32>> {
+        let mut clusters = Vec::new();
+        let mut next = self.layout.is_data_cluster(first).then_some(first);
+        while let Some(cluster) = next {
+            // a loop in the table would never end.
+            if clusters.len() > self.layout.clusters as usize {
+                return Err(Error::new(ErrorKind::InvalidData, "the cluster chain has a loop"));
+            }
+            clusters.push(cluster);
+            let (offset, len) = self.layout.fat_entry(cluster);
+            let mut entry = [0; 4];
+            self.read_at(offset, &mut entry[..len])?;
+            next = self.layout.next_cluster(cluster, &entry);
+        }
+        Ok(clusters)
+    }
+
+    /// Reads the whole chain starting at `first`
+    fn read_chain(&self, first: u32) -> io::Result<Vec<u8>> {
+        let clusters = self.chain(first)?;
+        let cluster_size = self.layout.cluster_size as usize;
+        let mut bytes = try_vec(0, clusters.len() * cluster_size)?;
+        for (cluster, buf) in clusters.iter().zip(bytes.chunks_exact_mut(cluster_size)) {
+            self.read_at(self.layout.cluster_offset(*cluster), buf)?;
+        }
+        Ok(bytes)
+    }
+}
+
+/// A mounted FAT volume.
+#[derive(Debug)]
+pub struct FatFs {
+    volume: Arc<Volume>,
+}
+
+impl FatFs {
+    /// Reads the boot sector of a volume.
+    /// # Errors
+    /// Returns [`ErrorKind::InvalidData`] if it is not a FAT volume, or the error of reading.
+    pub fn new(mut source: Box<dyn Source>) -> io::Result<Self> {
+        let mut sector = [0; 512];
+        source.seek(SeekFrom::Start(0))?;
+        source.read_exact(&mut sector)?;
+        let layout = Layout::parse(&sector)?;
+        Ok(Self { volume: Arc::new(Volume { source: AdaptiveMutex::new("fat-volume", source), layout }) })
+    }
+
+    /// The layout of the volume.
+    pub fn layout(&self) -> &Layout {
+        &self.volume.layout
+    }
+}
+
+impl FileSystem for FatFs {
+    fn name(&self) -> &'static str {
+        match self.volume.layout.fat_type {
+            bpb::FatType::Fat12 => "fat12",
+            bpb::FatType::Fat16 => "fat16",
+            bpb::FatType::Fat32 => "fat32",
+        }
+    }
+
+    fn root(&self) -> Arc<dyn Dir> {
+        let location = match self.volume.layout.root {
+            Root::Fixed { offset, len } => Location::Fixed { offset, len },
+            Root::Cluster(cluster) => Location::Chain(cluster),
+        };
+        Arc::new(FatDir { volume: self.volume.clone(), location })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Location {
+    Fixed { offset: u64, len: u64 },
+    Chain(u32),
+}
+
+/// A directory of a [`FatFs`]
+#[derive(Debug)]
+pub struct FatDir {
+    volume: Arc<Volume>,
+    location: Location,
+}
+
+impl FatDir {
+    /// The entries, read from the volume on every call.
+    fn read_entries(&self) -> io::Result<Vec<dir::Entry>> {
+        let bytes = match self.location {
+            Location::Fixed { offset, len } => {
+                let mut bytes = try_vec(0, len as usize)?;
+                self.volume.read_at(offset, &mut bytes)?;
+                bytes
+            }
+            Location::Chain(cluster) => self.volume.read_chain(cluster)?,
+        };
+        Ok(dir::parse(&bytes))
+    }
+
+    fn find(&self, name: &str) -> io::Result<dir::Entry> {
+        self.read_entries()?.into_iter().find(|e| e.name.eq_ignore_ascii_case(name)).ok_or(NOT_FOUND)
+    }
+}
+
+impl Dir for FatDir {
+    fn entries(&self) -> io::Result<Vec<DirEntry>> {
+        let mut entries: Vec<DirEntry> = self.read_entries()?.into_iter()
+            .map(|e| DirEntry { name: e.name, file_type: e.file_type })
+            .collect();
+        entries.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    fn metadata(&self, name: &str) -> io::Result<Metadata> {
+        let entry = self.find(name)?;
+        Ok(Metadata { file_type: entry.file_type, len: entry.size.into() })
+    }
+
+    fn open(&self, name: &str, options: OpenOptions) -> io::Result<Box<dyn File>> {
+        if options != OpenOptions::new() {
+            return Err(READ_ONLY);
+        }
+        let entry = self.find(name)?;
+        if entry.file_type == FileType::Dir {
+            return Err(Error::new(ErrorKind::IsADirectory, "it is a directory"));
+        }
+        let clusters = self.volume.chain(entry.cluster)?;
+        if (clusters.len() as u64) * self.volume.layout.cluster_size < entry.size.into() {
+            return Err(Error::new(ErrorKind::InvalidData, "the file is larger than its clusters"));
+        }
+        Ok(Box::new(FatFile { volume: self.volume.clone(), clusters, size: entry.size.into(), pos: 0 }))
+    }
+
+    fn open_dir(&self, name: &str) -> io::Result<Arc<dyn Dir>> {
+        let entry = self.find(name)?;
+        if entry.file_type != FileType::Dir {
+            return Err(Error::new(ErrorKind::NotADirectory, "it is not a directory"));
+        }
+        Ok(Arc::new(FatDir { volume: self.volume.clone(), location: Location::Chain(entry.cluster) }))
+    }
+
+    fn create_dir(&self, _: &str) -> io::Result<Arc<dyn Dir>> {
+        Err(READ_ONLY)
+    }
+
+    fn remove(&self, _: &str) -> io::Result<()> {
+        Err(READ_ONLY)
+    }
+}
+
+/// An open file of a [`FatFs`]
+#[derive(Debug)]
+pub struct FatFile {
+    volume: Arc<Volume>,
+    clusters: Vec<u32>,
+    size: u64,
+    pos: u64,
+}
+
+impl Read for FatFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let cluster_size = self.volume.layout.cluster_size;
+        let (index, offset) = (self.pos / cluster_size, self.pos % cluster_size);
+        // reads stop at the end of a cluster, the next one may be anywhere.
+        let len = (buf.len() as u64).min(self.size.saturating_sub(self.pos)).min(cluster_size - offset) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+        let cluster = self.clusters[index as usize];
+        self.volume.read_at(self.volume.layout.cluster_offset(cluster) + offset, &mut buf[..len])?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Write for FatFile {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(READ_ONLY)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for FatFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => (offset, 0),
+            SeekFrom::End(offset) => (self.size, offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        self.pos = base.checked_add_signed(offset)
+            .ok_or(Error::new(ErrorKind::InvalidInput, "the position would be before the start"))?;
+        Ok(self.pos)
+    }
+}
+
+impl File for FatFile {
+    fn metadata(&self) -> Metadata {
+        Metadata { file_type: FileType::File, len: self.size }
+    }
+
+    fn set_len(&mut self, _: u64) -> io::Result<()> {
+        Err(READ_ONLY)
+    }
+}
+
+/// Tests reading a small FAT12 image, with a long file name and a directory.
+#[cfg(feature = "test")]
+pub fn test_fat(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use alloc::string::String;
+
+    use crate::{io::Cursor, test::{test_assert, test_assert_eq}};
+
+    const SECTOR: usize = 512;
+    // boot sector, one table, the root directory, then clusters 2.. of one sector each.
+    let mut image = alloc::vec![0u8; 64 * SECTOR];
+    image[11..13].copy_from_slice(&512u16.to_le_bytes());
+    image[13] = 1;
+    image[14..16].copy_from_slice(&1u16.to_le_bytes());
+    image[16] = 1;
+    image[17..19].copy_from_slice(&16u16.to_le_bytes());
+    image[19..21].copy_from_slice(&64u16.to_le_bytes());
+    image[22..24].copy_from_slice(&1u16.to_le_bytes());
+    image[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+    let mut set_fat = |cluster: usize, value: u16| {
+        let offset = SECTOR + cluster + cluster / 2;
+        let old = u16::from_le_bytes([image[offset], image[offset + 1]]);
+        let new = if cluster % 2 == 1 { (old & 0x000F) | (value << 4) } else { (old & 0xF000) | value };
+        image[offset..offset + 2].copy_from_slice(&new.to_le_bytes());
+    };
+    // the long file spans clusters 2 and 3, the others have one.
+    for (cluster, next) in [(2, 3), (3, 0xFFF), (4, 0xFFF), (5, 0xFFF), (6, 0xFFF)] {
+        set_fat(cluster, next);
+    }
+
+    fn short(raw: &mut [u8], name: &[u8; 11], attributes: u8, cluster: u16, size: u32) {
+        raw[..11].copy_from_slice(name);
+        raw[11] = attributes;
+        raw[26..28].copy_from_slice(&cluster.to_le_bytes());
+        raw[28..32].copy_from_slice(&size.to_le_bytes());
+    }
+    fn long(raw: &mut [u8], sequence: u8, units: &[u16], checksum: u8) {
+        raw[0] = sequence;
+        raw[11] = 0x0F;
+        raw[13] = checksum;
+        let offsets = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+        for (i, offset) in offsets.into_iter().enumerate() {
+            let unit = units.get(i).copied().unwrap_or(if i == units.len() { 0 } else { 0xFFFF });
+            raw[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+    }
+
+    let root = 2 * SECTOR;
+    let entry = |i: usize| root + i * 32;
+    let units: Vec<u16> = "Long File Name.txt".encode_utf16().collect();
+    let checksum = dir::checksum(b"LONGFI~1TXT");
+    long(&mut image[entry(0)..entry(1)], 0x42, &units[13..], checksum);
+    long(&mut image[entry(1)..entry(2)], 0x01, &units[..13], checksum);
+    short(&mut image[entry(2)..entry(3)], b"LONGFI~1TXT", 0x20, 2, 600);
+    short(&mut image[entry(3)..entry(4)], b"HELLO   TXT", 0x20, 4, 5);
+    short(&mut image[entry(4)..entry(5)], b"SUB        ", 0x10, 5, 0);
+    let sub = 3 * SECTOR + 3 * SECTOR;
+    short(&mut image[sub..sub + 32], b".          ", 0x10, 5, 0);
+    short(&mut image[sub + 32..sub + 64], b"..         ", 0x10, 0, 0);
+    short(&mut image[sub + 64..sub + 96], b"INNER   TXT", 0x20, 6, 3);
+    image[3 * SECTOR..3 * SECTOR + 600].fill(b'x');
+    image[3 * SECTOR + 599] = b'!';
+    image[3 * SECTOR + 2 * SECTOR..][..5].copy_from_slice(b"hello");
+    image[3 * SECTOR + 4 * SECTOR..][..3].copy_from_slice(b"abc");
+
+    let fs = FatFs::new(Box::new(Cursor::new(image))).map_err(|_| "the volume was not read")?;
+    test_assert_eq!(fs.name(), "fat12")?;
+    let root = fs.root();
+    let names: Vec<String> = root.entries().unwrap_or_default().into_iter().map(|e| e.name).collect();
+    test_assert_eq!(names, ["HELLO.TXT", "Long File Name.txt", "SUB"])?;
+
+    let mut file = root.open("long file name.TXT", OpenOptions::new()).map_err(|_| "the long name was not found")?;
+    let mut data = alloc::vec![0; 600];
+    test_assert_eq!(file.read_exact(&mut data), Ok(()))?;
+    test_assert!(data[..599].iter().all(|&b| b == b'x') && data[599] == b'!')?;
+    test_assert_eq!(file.read(&mut data), Ok(0))?;
+
+    let sub = root.open_dir("sub").map_err(|_| "the directory was not found")?;
+    test_assert_eq!(sub.metadata("inner.txt").map(|m| m.len), Ok(3))?;
+    let mut file = sub.open("INNER.TXT", OpenOptions::new()).map_err(|_| "the file was not found")?;
+    test_assert_eq!(file.seek(SeekFrom::Start(1)), Ok(1))?;
+    test_assert_eq!(file.read(&mut data), Ok(2))?;
+    test_assert_eq!(&data[..2], b"bc")?;
+    test_assert_eq!(root.open("hello.txt", OpenOptions::create()).map(drop).map_err(|e| e.kind()), Err(ErrorKind::ReadOnlyFilesystem))
+}
@@ -0,0 +1,408 @@
+//! The virtual filesystem.
+//! 
+//! Filesystems implement [`FileSystem`], and are [mounted](mount) at a directory. A path is
+//! resolved by the mount with the longest matching prefix, then by [`Dir::open_dir`] for each of
+//! the remaining components, see [`path`]. At boot, a [`RamFs`](ramfs::RamFs) is mounted at `/`,
+//! the [devices](devfs) at `/dev`, and the [kernel information](procfs) at `/proc`. [FAT](fat)
+//! volumes can be mounted read only, and `root=DEVICE` on the kernel command line replaces the root
+//! with the FAT volume on a [block device](crate::storage), E.g. `root=initrd`, see [`mount_root`]
+//! 
+//! ```rust,no_run
+//! fs::create_dir("/etc")?;
+//! fs::write("/etc/hostname", b"ion")?;
+//! assert_eq!(fs::read("/etc/hostname")?, b"ion");
+//! ```
+use alloc::{boxed::Box, string::{String, ToString}, sync::Arc, vec::Vec};
+use core::fmt;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{boot::cmdline::CommandLine, c_lib::BootInfo, collections::ArrayVec, io::{self, Error, ErrorKind}, lib_alloc::try_vec, log::{info, warn}, storage::{self, stream::BlockStream}};
+
+pub mod devfs;
+pub mod fat;
+pub mod path;
+pub mod procfs;
+pub mod ramfs;
+
+/// Maximum amount of mounted filesystems.
+pub const MAX_MOUNTS: usize = 16;
+
+/// Wether an entry is a file or a directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    /// A file, with bytes.
+    File,
+    /// A directory, with entries.
+    Dir,
+    /// A [device](devfs) node.
+    Device,
+}
+
+/// Information about a file or directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    /// Wether it is a file or a directory.
+    pub file_type: FileType,
+    /// The size in bytes of a file, the amount of entries of a directory.
+    pub len: u64,
+}
+
+/// An entry of a directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    /// The name, without the path of the directory.
+    pub name: String,
+    /// Wether it is a file or a directory.
+    pub file_type: FileType,
+}
+
+/// How a file is opened, for reading by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpenOptions {
+    /// Create the file if it does not exist.
+    pub create: bool,
+    /// Remove the contents of the file.
+    pub truncate: bool,
+    /// Write at the end of the file, wherever the position is.
+    pub append: bool,
+}
+
+impl OpenOptions {
+    /// Opens an existing file.
+    pub const fn new() -> Self {
+        Self { create: false, truncate: false, append: false }
+    }
+
+    /// Creates the file if it does not exist, and removes its contents if it does.
+    pub const fn create() -> Self {
+        Self { create: true, truncate: true, append: false }
+    }
+
+    /// Creates the file if it does not exist, and writes at its end.
+    pub const fn append() -> Self {
+        Self { create: true, truncate: false, append: true }
+    }
+}
+
+/// An open file, with a position which is moved by reading and writing.
+pub trait File: io::Read + io::Write + io::Seek + Send {
+    /// Information about the file.
+    fn metadata(&self) -> Metadata;
+
+    /// Cuts or extends the file, with zeros, to `len` bytes. The position is kept.
+    /// # Errors
+    /// Returns an error if the size can not be changed.
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+}
+
+/// A directory of a [`FileSystem`]
+pub trait Dir: Send + Sync {
+    /// Every entry, sorted by name.
+    fn entries(&self) -> io::Result<Vec<DirEntry>>;
+
+    /// Information about the entry called `name`
+    /// # Errors
+    /// Returns [`ErrorKind::NotFound`] if there is no such entry.
+    fn metadata(&self, name: &str) -> io::Result<Metadata>;
+
+    /// Opens the file called `name`
+    /// # Errors
+    /// Returns [`ErrorKind::NotFound`] if there is no such entry, and it was not to be created, or
+    /// [`ErrorKind::IsADirectory`]
+    fn open(&self, name: &str, options: OpenOptions) -> io::Result<Box<dyn File>>;
+
+    /// Opens the directory called `name`
+    /// # Errors
+    /// Returns [`ErrorKind::NotFound`] if there is no such entry, or [`ErrorKind::NotADirectory`]
+    fn open_dir(&self, name: &str) -> io::Result<Arc<dyn Dir>>;
+
+    /// Creates an empty directory called `name`
+    /// # Errors
+    /// Returns [`ErrorKind::AlreadyExists`] if there is an entry called `name`, or
+    /// [`ErrorKind::InvalidInput`] if it is not a [valid name](path::is_valid_name).
+    fn create_dir(&self, name: &str) -> io::Result<Arc<dyn Dir>>;
+
+    /// Removes the file or empty directory called `name`
+    /// # Errors
+    /// Returns [`ErrorKind::NotFound`] if there is no such entry, or
+    /// [`ErrorKind::DirectoryNotEmpty`]
+    fn remove(&self, name: &str) -> io::Result<()>;
+}
+
+/// A filesystem, which can be [mounted](mount).
+pub trait FileSystem: Send + Sync {
+    /// The name of the kind of filesystem, E.g. `ramfs`
+    fn name(&self) -> &'static str;
+
+    /// The root directory.
+    fn root(&self) -> Arc<dyn Dir>;
+
+    /// Writes the changes which are only in memory to the device. By default there are none.
+    /// # Errors
+    /// Returns an error if writing failed.
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for dyn File {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("File").field(&self.metadata()).finish()
+    }
+}
+
+impl fmt::Debug for dyn Dir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dir").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for dyn FileSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("FileSystem").field(&self.name()).finish()
+    }
+}
+
+#[derive(Debug)]
+struct Mount {
+    components: Vec<String>,
+    fs: Arc<dyn FileSystem>,
+}
+
+static MOUNTS: Mutex<ArrayVec<Mount, MAX_MOUNTS>> = Mutex::new(ArrayVec::new());
+
+/// Mounts `fs` at the directory `path`, or at `/`
+/// # Errors
+/// Returns [`ErrorKind::AlreadyExists`] if there is a filesystem mounted there,
+/// [`ErrorKind::NotADirectory`] if `path` is not a directory, or [`ErrorKind::Other`] if there are
+/// [`MAX_MOUNTS`] already.
+pub fn mount(path: &str, fs: Arc<dyn FileSystem>) -> io::Result<()> {
+    let components = path::components(path)?;
+    // the root of the first filesystem has nothing to be mounted on.
+    if !components.is_empty() {
+        resolve_dir(&components)?;
+    }
+    let components: Vec<String> = components.iter().map(ToString::to_string).collect();
+    without_interrupts(|| {
+        let mut mounts = MOUNTS.lock();
+        if mounts.iter().any(|m| m.components == components) {
+            return Err(Error::new(ErrorKind::AlreadyExists, "a filesystem is mounted there already"));
+        }
+        mounts.push(Mount { components, fs }).map_err(|_| Error::new(ErrorKind::Other, "too many mounted filesystems"))
+    })
+}
+
+/// Unmounts the filesystem at `path`, and returns it.
+/// # Errors
+/// Returns [`ErrorKind::NotFound`] if there is none, or [`ErrorKind::InvalidInput`] if
+/// filesystems are mounted inside of it.
+pub fn unmount(path: &str) -> io::Result<Arc<dyn FileSystem>> {
+    let components = path::components(path)?;
+    without_interrupts(|| {
+        let mut mounts = MOUNTS.lock();
+        let i = mounts.iter().position(|m| m.components == components)
+            .ok_or(Error::new(ErrorKind::NotFound, "no filesystem is mounted there"))?;
+        let nested = |m: &Mount| m.components.len() > components.len() && m.components.starts_with(&mounts[i].components);
+        if mounts.iter().any(nested) {
+            return Err(Error::new(ErrorKind::InvalidInput, "filesystems are mounted inside of it"));
+        }
+        Ok(mounts.remove(i).fs)
+    })
+}
+
+/// Replaces the filesystem at `/` with `fs`, and returns the previous one. Filesystems mounted
+/// inside of it stay mounted, even if `fs` has no directory for them.
+/// # Errors
+/// Returns [`ErrorKind::NotFound`] if nothing is mounted at `/`
+pub fn replace_root(fs: Arc<dyn FileSystem>) -> io::Result<Arc<dyn FileSystem>> {
+    without_interrupts(|| {
+        let mut mounts = MOUNTS.lock();
+        let root = mounts.iter_mut().find(|m| m.components.is_empty())
+            .ok_or(Error::new(ErrorKind::NotFound, "no filesystem is mounted at /"))?;
+        Ok(core::mem::replace(&mut root.fs, fs))
+    })
+}
+
+/// Calls `f` with the path and filesystem of every mount.
+pub fn for_each_mount(mut f: impl FnMut(&str, &dyn FileSystem)) {
+    let mounts: Vec<(String, Arc<dyn FileSystem>)> = without_interrupts(|| {
+        MOUNTS.lock().iter().map(|m| (alloc::format!("/{}", m.components.join("/")), m.fs.clone())).collect()
+    });
+    mounts.iter().for_each(|(path, fs)| f(path, &**fs));
+}
+
+/// The directory at `components`
+fn resolve_dir(components: &[&str]) -> io::Result<Arc<dyn Dir>> {
+    // cloned out, so the filesystem is not called with the lock.
+    let (depth, fs) = without_interrupts(|| {
+        MOUNTS.lock().iter()
+            .filter(|m| m.components.len() <= components.len() && m.components.iter().zip(components).all(|(a, b)| a == b))
+            .max_by_key(|m| m.components.len())
+            .map(|m| (m.components.len(), m.fs.clone()))
+    }).ok_or(Error::new(ErrorKind::NotFound, "no filesystem is mounted"))?;
+    components[depth..].iter().try_fold(fs.root(), |dir, name| dir.open_dir(name))
+}
+
+/// Opens the file at `path`
+/// # Errors
+/// Returns an error if the path is not valid, or the error of [`Dir::open`]
+pub fn open(path: &str, options: OpenOptions) -> io::Result<Box<dyn File>> {
+    let (parent, name) = path::split_parent(path)?;
+    resolve_dir(&parent)?.open(name, options)
+}
+
+/// Opens the directory at `path`
+/// # Errors
+/// Returns an error if the path is not valid, or the error of [`Dir::open_dir`]
+pub fn open_dir(path: &str) -> io::Result<Arc<dyn Dir>> {
+    resolve_dir(&path::components(path)?)
+}
+
+/// The entries of the directory at `path`
+/// # Errors
+/// Returns an error if the path is not valid, or the error of [`Dir::open_dir`]
+pub fn read_dir(path: &str) -> io::Result<Vec<DirEntry>> {
+    open_dir(path)?.entries()
+}
+
+/// Information about the file or directory at `path`
+/// # Errors
+/// Returns an error if the path is not valid, or the error of [`Dir::metadata`]
+pub fn metadata(path: &str) -> io::Result<Metadata> {
+    let components = path::components(path)?;
+    match components.split_last() {
+        Some((name, parent)) => resolve_dir(parent)?.metadata(name),
+        // the root has no parent to ask.
+        None => {
+            let len = resolve_dir(&[])?.entries()?.len();
+            Ok(Metadata { file_type: FileType::Dir, len: len as u64 })
+        }
+    }
+}
+
+/// Creates an empty directory at `path`
+/// # Errors
+/// Returns an error if the path is not valid, or the error of [`Dir::create_dir`]
+pub fn create_dir(path: &str) -> io::Result<()> {
+    let (parent, name) = path::split_parent(path)?;
+    resolve_dir(&parent)?.create_dir(name).map(drop)
+}
+
+/// Removes the file or empty directory at `path`
+/// # Errors
+/// Returns an error if the path is not valid, or the error of [`Dir::remove`]
+pub fn remove(path: &str) -> io::Result<()> {
+    let (parent, name) = path::split_parent(path)?;
+    resolve_dir(&parent)?.remove(name)
+}
+
+/// Reads the whole file at `path`
+/// # Errors
+/// Returns the error of [`open`], or of reading, or [`MemoryError`](io::ErrorKind::MemoryError) if
+/// the heap has no room for the contents.
+pub fn read(path: &str) -> io::Result<Vec<u8>> {
+    let mut file = open(path, OpenOptions::new())?;
+    let mut data = try_vec(0, file.metadata().len as usize)?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Replaces the contents of the file at `path`, creating it if needed.
+/// # Errors
+/// Returns the error of [`open`], or of writing.
+pub fn write(path: &str, data: &[u8]) -> io::Result<()> {
+    open(path, OpenOptions::create())?.write_all(data)
+}
+
+/// [Syncs](FileSystem::sync) every mounted filesystem.
+/// # Errors
+/// Returns the first error, after trying the other filesystems as well.
+pub fn sync_all() -> io::Result<()> {
+    let mut result = Ok(());
+    for_each_mount(|path, fs| {
+        if let Err(e) = fs.sync() {
+            warn!("{path} could not be synced: {e}");
+            result = result.and(Err(e));
+        }
+    });
+    result
+}
+
+/// Mounts a RAM filesystem at `/`, the [devices](devfs) at `/dev` and the [kernel
+/// information](procfs) at `/proc`, and registers the shutdown hook which [syncs](sync_all) them.
+pub fn init() {
+    let hook = || sync_all().map_err(|_| "a filesystem could not be synced");
+    if crate::power::shutdown::register(crate::power::shutdown::Stage::Filesystems, "vfs", hook).is_err() {
+        warn!("The filesystems are not synced on shutdown, there are too many shutdown hooks.");
+    }
+    if let Err(e) = mount("/", Arc::new(ramfs::RamFs::new())) {
+        warn!("The root filesystem could not be mounted: {e}");
+        return;
+    }
+    if let Err(e) = create_dir("/dev").and_then(|()| mount("/dev", Arc::new(devfs::DevFs))) {
+        warn!("The devices could not be mounted: {e}");
+    }
+    procfs::init();
+    if let Err(e) = create_dir("/proc").and_then(|()| mount("/proc", Arc::new(procfs::ProcFs))) {
+        warn!("The kernel information could not be mounted: {e}");
+    }
+}
+
+/// The value of `root=` on the kernel `command_line`, if there is one.
+pub fn root_from_command_line(command_line: &str) -> Option<&str> {
+    CommandLine::new(command_line).get("root")
+}
+
+/// Replaces the RAM filesystem at `/` with the FAT volume on the block device named by `root=`,
+/// if there is one. The devices stay mounted at `/dev`
+pub fn mount_root(boot_info: &BootInfo) {
+    let Some(name) = root_from_command_line(boot_info.command_line) else {
+        return;
+    };
+    let result = storage::get(name)
+        .ok_or(Error::new(ErrorKind::NotFound, "there is no such block device"))
+        .and_then(|device| fat::FatFs::new(Box::new(BlockStream::new(device))))
+        .and_then(|volume| replace_root(Arc::new(volume)));
+    match result {
+        Ok(_) => info!("Mounted {name} at /"),
+        Err(e) => warn!("Ignoring the root={name} argument: {e}"),
+    }
+}
+
+/// Tests resolving paths across mounts.
+#[cfg(feature = "test")]
+pub fn test_vfs(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    let kind = |e: Error| e.kind();
+    create_dir("/test-vfs").map_err(|_| "the directory was not created")?;
+    write("/test-vfs/a", b"root").map_err(|_| "the file was not written")?;
+    mount("/test-vfs", Arc::new(ramfs::RamFs::new())).map_err(|_| "the filesystem was not mounted")?;
+    // the file of the root filesystem is hidden by the mount.
+    test_assert_eq!(read("/test-vfs/a").map_err(kind), Err(ErrorKind::NotFound))?;
+    test_assert_eq!(mount("/test-vfs", Arc::new(ramfs::RamFs::new())).map_err(kind), Err(ErrorKind::AlreadyExists))?;
+    create_dir("/test-vfs/dir").map_err(|_| "the directory was not created")?;
+    write("/test-vfs/dir/../b", b"mounted").map_err(|_| "the file was not written")?;
+    test_assert_eq!(read("/test-vfs/./b"), Ok(b"mounted".to_vec()))?;
+    test_assert_eq!(metadata("/test-vfs/dir").map(|m| m.file_type), Ok(FileType::Dir))?;
+    test_assert_eq!(read_dir("/test-vfs").map(|e| e.len()), Ok(2))?;
+    test_assert_eq!(open("/test-vfs/b/c", OpenOptions::new()).map(drop).map_err(kind), Err(ErrorKind::NotADirectory))?;
+
+    test_assert!(unmount("/test-vfs").is_ok())?;
+    test_assert_eq!(read("/test-vfs/a"), Ok(b"root".to_vec()))?;
+    remove("/test-vfs/a").map_err(|_| "the file was not removed")?;
+    remove("/test-vfs").map_err(|_| "the directory was not removed")?;
+    test_assert_eq!(metadata("/test-vfs").map_err(kind), Err(ErrorKind::NotFound))?;
+
+    // the devices stay mounted, when the root is replaced.
+    write("/test-root", b"old").map_err(|_| "the file was not written")?;
+    let previous = replace_root(Arc::new(ramfs::RamFs::new())).map_err(|_| "the root was not replaced")?;
+    test_assert_eq!(read("/test-root").map_err(kind), Err(ErrorKind::NotFound))?;
+    test_assert!(read_dir("/dev").is_ok())?;
+    replace_root(previous).map_err(|_| "the root was not restored")?;
+    test_assert_eq!(read("/test-root"), Ok(b"old".to_vec()))?;
+    remove("/test-root").map_err(|_| "the file was not removed")?;
+    test_assert_eq!(root_from_command_line("quiet root=initrd"), Some("initrd"))
+}
@@ -0,0 +1,60 @@
+//! Splitting paths into their components.
+//! 
+//! Paths are absolute, there is no working directory yet. `.` and empty components are skipped,
+//! and `..` goes to the parent, which is the root for the root itself.
+use alloc::vec::Vec;
+
+use crate::io::{Error, ErrorKind, Result};
+
+/// Separates the components of a path.
+pub const SEPARATOR: char = '/';
+
+/// The components of `path`, with `.` and `..` resolved.
+/// # Errors
+/// Returns [`ErrorKind::InvalidInput`] if the path is not absolute.
+pub fn components(path: &str) -> Result<Vec<&str>> {
+    let Some(path) = path.strip_prefix(SEPARATOR) else {
+        return Err(Error::new(ErrorKind::InvalidInput, "the path is not absolute"));
+    };
+    let mut components = Vec::new();
+    for component in path.split(SEPARATOR) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            name => components.push(name),
+        }
+    }
+    Ok(components)
+}
+
+/// The components of the parent of `path`, and its name.
+/// # Errors
+/// Returns [`ErrorKind::InvalidInput`] if the path is not absolute, or is the root, which has no
+/// name.
+pub fn split_parent(path: &str) -> Result<(Vec<&str>, &str)> {
+    let mut components = components(path)?;
+    let name = components.pop().ok_or(Error::new(ErrorKind::InvalidInput, "the root has no name"))?;
+    Ok((components, name))
+}
+
+/// Wether `name` may be the name of a file or directory.
+pub fn is_valid_name(name: &str) -> bool {
+    !matches!(name, "" | "." | "..") && !name.contains(SEPARATOR)
+}
+
+/// Tests resolving paths.
+#[cfg(feature = "test")]
+pub fn test_paths(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    test_assert_eq!(components("/"), Ok(alloc::vec![]))?;
+    test_assert_eq!(components("//a/./b/"), Ok(alloc::vec!["a", "b"]))?;
+    test_assert_eq!(components("/a/../../b/c/.."), Ok(alloc::vec!["b"]))?;
+    test_assert_eq!(components("a/b").map_err(|e| e.kind()), Err(ErrorKind::InvalidInput))?;
+    test_assert_eq!(split_parent("/a/b"), Ok((alloc::vec!["a"], "b")))?;
+    test_assert!(split_parent("/..").is_err())?;
+    test_assert!(is_valid_name("init.rc"))?;
+    test_assert!(!is_valid_name("..") && !is_valid_name("a/b"))
+}
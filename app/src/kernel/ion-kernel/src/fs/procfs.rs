@@ -0,0 +1,192 @@
+//! Kernel information as files, mounted at `/proc`
+//! 
+//! Subsystems [register](register) an entry by name with a function which writes its text, E.g.
+//! `version`. Opening the entry calls the function, so the file shows the state at the time it was
+//! opened. Entries can not be written, created or removed through the filesystem.
+//! 
+//! [`init`] registers `cpuinfo`, the [CPUID decode](crate::arch::cpuinfo), and `version`, the
+//! [version line](crate::sys::info).
+use alloc::{boxed::Box, string::{String, ToString}, sync::Arc, vec::Vec};
+use core::fmt;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{Dir, DirEntry, File, FileSystem, FileType, Metadata, OpenOptions};
+use crate::{collections::{ArrayVec, CapacityError}, io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write}};
+
+/// Maximum amount of registered entries.
+pub const MAX_ENTRIES: usize = 32;
+
+/// Writes the text of an entry.
+pub type Generate = fn(&mut dyn fmt::Write) -> fmt::Result;
+
+static ENTRIES: Mutex<ArrayVec<(&'static str, Generate), MAX_ENTRIES>> = Mutex::new(ArrayVec::new());
+
+const READ_ONLY: Error = Error::new(ErrorKind::ReadOnlyFilesystem, "proc entries are registered by the kernel");
+const NOT_FOUND: Error = Error::new(ErrorKind::NotFound, "no such entry");
+
+/// Registers `generate` as `/proc/{name}`
+/// # Errors
+/// Returns an error if there are [`MAX_ENTRIES`] already, or one with the same name.
+pub fn register(name: &'static str, generate: Generate) -> Result<(), CapacityError> {
+    without_interrupts(|| {
+        let mut entries = ENTRIES.lock();
+        if entries.iter().any(|&(n, _)| n == name) {
+            return Err(CapacityError(()));
+        }
+        entries.push((name, generate)).map_err(|_| CapacityError(()))
+    })
+}
+
+/// Unregisters the entry called `name`, returns wether it was registered. Open files keep their
+/// text.
+pub fn unregister(name: &str) -> bool {
+    without_interrupts(|| {
+        let mut entries = ENTRIES.lock();
+        let i = entries.iter().position(|&(n, _)| n == name);
+        i.map(|i| entries.remove(i)).is_some()
+    })
+}
+
+fn find(name: &str) -> io::Result<Generate> {
+    without_interrupts(|| ENTRIES.lock().iter().find(|&&(n, _)| n == name).map(|&(_, generate)| generate)).ok_or(NOT_FOUND)
+}
+
+/// Registers the built in entries, `cpuinfo` and `version`
+pub fn init() {
+    let cpuinfo: Generate = |f| write!(f, "{}", crate::arch::cpuinfo::CpuInfo::query());
+    let version: Generate = |f| writeln!(f, "{}", crate::sys::info());
+    for (name, generate) in [("cpuinfo", cpuinfo), ("version", version)] {
+        if register(name, generate).is_err() {
+            crate::log::warn!("/proc/{name} could not be registered");
+        }
+    }
+}
+
+/// The filesystem of the registered entries.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcFs;
+
+impl FileSystem for ProcFs {
+    fn name(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn root(&self) -> Arc<dyn Dir> {
+        Arc::new(ProcDir)
+    }
+}
+
+/// The directory of the registered entries, there are no others.
+#[derive(Debug, Clone, Copy)]
+struct ProcDir;
+
+impl Dir for ProcDir {
+    fn entries(&self) -> io::Result<Vec<DirEntry>> {
+        let mut entries: Vec<DirEntry> = without_interrupts(|| {
+            ENTRIES.lock().iter().map(|&(name, _)| DirEntry { name: name.to_string(), file_type: FileType::File }).collect()
+        });
+        entries.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    fn metadata(&self, name: &str) -> io::Result<Metadata> {
+        // the length is only known once the text is generated.
+        find(name).map(|_| Metadata { file_type: FileType::File, len: 0 })
+    }
+
+    fn open(&self, name: &str, options: OpenOptions) -> io::Result<Box<dyn File>> {
+        let generate = find(name)?;
+        if options.truncate || options.append {
+            return Err(READ_ONLY);
+        }
+        let mut text = String::new();
+        generate(&mut text).map_err(|_| Error::new(ErrorKind::Other, "the entry could not be generated"))?;
+        Ok(Box::new(ProcFile { text: text.into_bytes(), pos: 0 }))
+    }
+
+    fn open_dir(&self, name: &str) -> io::Result<Arc<dyn Dir>> {
+        find(name)?;
+        Err(Error::new(ErrorKind::NotADirectory, "it is not a directory"))
+    }
+
+    fn create_dir(&self, _: &str) -> io::Result<Arc<dyn Dir>> {
+        Err(READ_ONLY)
+    }
+
+    fn remove(&self, _: &str) -> io::Result<()> {
+        Err(READ_ONLY)
+    }
+}
+
+/// An open entry, with the text generated when it was opened.
+#[derive(Debug)]
+struct ProcFile {
+    text: Vec<u8>,
+    pos: u64,
+}
+
+impl Read for ProcFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let rest = self.text.get(self.pos as usize..).unwrap_or_default();
+        let len = buf.len().min(rest.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Write for ProcFile {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(READ_ONLY)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for ProcFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => {
+                self.pos = offset;
+                return Ok(offset);
+            }
+            SeekFrom::End(offset) => (self.text.len() as u64, offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        self.pos = base.checked_add_signed(offset)
+            .ok_or(Error::new(ErrorKind::InvalidInput, "the position would be before the start"))?;
+        Ok(self.pos)
+    }
+}
+
+impl File for ProcFile {
+    fn metadata(&self) -> Metadata {
+        Metadata { file_type: FileType::File, len: self.text.len() as u64 }
+    }
+
+    fn set_len(&mut self, _: u64) -> io::Result<()> {
+        Err(READ_ONLY)
+    }
+}
+
+/// Tests reading the built in entries, and registering one.
+#[cfg(feature = "test")]
+pub fn test_procfs(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    let version = super::read("/proc/version").map_err(|_| "/proc/version was not read")?;
+    test_assert!(version.starts_with(crate::sys::KERNEL_NAME.as_bytes()) && version.ends_with(b"\n"))?;
+    let cpuinfo = super::read("/proc/cpuinfo").map_err(|_| "/proc/cpuinfo was not read")?;
+    test_assert!(cpuinfo.starts_with(b"processor\t: 0\n"))?;
+
+    register("test-answer", |f| write!(f, "{}", 42)).map_err(|_| "the entry was not registered")?;
+    test_assert!(register("test-answer", |_| Ok(())).is_err())?;
+    test_assert_eq!(super::read("/proc/test-answer").ok().as_deref(), Some(&b"42"[..]))?;
+    test_assert_eq!(super::write("/proc/test-answer", b"43").map_err(|e| e.kind()), Err(ErrorKind::ReadOnlyFilesystem))?;
+    test_assert!(unregister("test-answer"))?;
+    test_assert_eq!(super::metadata("/proc/test-answer").map_err(|e| e.kind()), Err(ErrorKind::NotFound))
+}
@@ -0,0 +1,258 @@
+//! A filesystem in memory, lost on reboot.
+//! 
+//! Files are vectors of bytes, shared by every open [`RamFile`], so writes are seen by the others
+//! right away. A removed file stays readable through the files which are still open.
+use alloc::{boxed::Box, collections::BTreeMap, string::{String, ToString}, sync::Arc, vec::Vec};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{Dir, DirEntry, File, FileSystem, FileType, Metadata, OpenOptions, path};
+use crate::io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+type Data = Arc<Mutex<Vec<u8>>>;
+
+#[derive(Debug, Clone)]
+enum Node {
+    File(Data),
+    Dir(Arc<RamDir>),
+}
+
+impl Node {
+    fn metadata(&self) -> Metadata {
+        match self {
+            Self::File(data) => Metadata { file_type: FileType::File, len: without_interrupts(|| data.lock().len()) as u64 },
+            Self::Dir(dir) => Metadata { file_type: FileType::Dir, len: without_interrupts(|| dir.entries.lock().len()) as u64 },
+        }
+    }
+}
+
+const NOT_FOUND: Error = Error::new(ErrorKind::NotFound, "no such file or directory");
+
+/// A directory of a [`RamFs`]
+#[derive(Debug, Default)]
+pub struct RamDir {
+    entries: Mutex<BTreeMap<String, Node>>,
+}
+
+impl RamDir {
+    fn get(&self, name: &str) -> io::Result<Node> {
+        without_interrupts(|| self.entries.lock().get(name).cloned()).ok_or(NOT_FOUND)
+    }
+}
+
+impl Dir for RamDir {
+    fn entries(&self) -> io::Result<Vec<DirEntry>> {
+        Ok(without_interrupts(|| {
+            let entries = self.entries.lock();
+            entries.iter().map(|(name, node)| {
+                let file_type = match node {
+                    Node::File(_) => FileType::File,
+                    Node::Dir(_) => FileType::Dir,
+                };
+                DirEntry { name: name.clone(), file_type }
+            }).collect()
+        }))
+    }
+
+    fn metadata(&self, name: &str) -> io::Result<Metadata> {
+        Ok(self.get(name)?.metadata())
+    }
+
+    fn open(&self, name: &str, options: OpenOptions) -> io::Result<Box<dyn File>> {
+        if !path::is_valid_name(name) {
+            return Err(Error::new(ErrorKind::InvalidInput, "the name is not valid"));
+        }
+        let node = without_interrupts(|| {
+            let mut entries = self.entries.lock();
+            match entries.get(name) {
+                Some(node) => Ok(node.clone()),
+                None if options.create => {
+                    let node = Node::File(Data::default());
+                    entries.insert(name.to_string(), node.clone());
+                    Ok(node)
+                }
+                None => Err(NOT_FOUND),
+            }
+        })?;
+        let Node::File(data) = node else {
+            return Err(Error::new(ErrorKind::IsADirectory, "it is a directory"));
+        };
+        if options.truncate {
+            without_interrupts(|| data.lock().clear());
+        }
+        Ok(Box::new(RamFile { data, pos: 0, append: options.append }))
+    }
+
+    fn open_dir(&self, name: &str) -> io::Result<Arc<dyn Dir>> {
+        match self.get(name)? {
+            Node::Dir(dir) => Ok(dir),
+            Node::File(_) => Err(Error::new(ErrorKind::NotADirectory, "it is not a directory")),
+        }
+    }
+
+    fn create_dir(&self, name: &str) -> io::Result<Arc<dyn Dir>> {
+        if !path::is_valid_name(name) {
+            return Err(Error::new(ErrorKind::InvalidInput, "the name is not valid"));
+        }
+        without_interrupts(|| {
+            let mut entries = self.entries.lock();
+            if entries.contains_key(name) {
+                return Err(Error::new(ErrorKind::AlreadyExists, "the entry exists already"));
+            }
+            let dir = Arc::new(RamDir::default());
+            entries.insert(name.to_string(), Node::Dir(dir.clone()));
+            Ok(dir as Arc<dyn Dir>)
+        })
+    }
+
+    fn remove(&self, name: &str) -> io::Result<()> {
+        without_interrupts(|| {
+            let mut entries = self.entries.lock();
+            match entries.get(name).ok_or(NOT_FOUND)? {
+                Node::Dir(dir) if !dir.entries.lock().is_empty() => {
+                    return Err(Error::new(ErrorKind::DirectoryNotEmpty, "the directory is not empty"));
+                }
+                _ => {}
+            }
+            entries.remove(name);
+            Ok(())
+        })
+    }
+}
+
+/// An open file of a [`RamFs`]
+#[derive(Debug)]
+pub struct RamFile {
+    data: Data,
+    pos: u64,
+    append: bool,
+}
+
+impl Read for RamFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = without_interrupts(|| {
+            let data = self.data.lock();
+            let rest = data.get(self.pos as usize..).unwrap_or_default();
+            let len = buf.len().min(rest.len());
+            buf[..len].copy_from_slice(&rest[..len]);
+            len
+        });
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for RamFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        without_interrupts(|| {
+            let mut data = self.data.lock();
+            if self.append {
+                self.pos = data.len() as u64;
+            }
+            let start = self.pos as usize;
+            let end = start + buf.len();
+            // a position past the end leaves a gap of zeros.
+            if data.len() < end {
+                data.resize(end, 0);
+            }
+            data[start..end].copy_from_slice(buf);
+            self.pos = end as u64;
+        });
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for RamFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => {
+                self.pos = offset;
+                return Ok(offset);
+            }
+            SeekFrom::End(offset) => (self.metadata().len, offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        self.pos = base.checked_add_signed(offset)
+            .ok_or(Error::new(ErrorKind::InvalidInput, "the position would be before the start"))?;
+        Ok(self.pos)
+    }
+}
+
+impl File for RamFile {
+    fn metadata(&self) -> Metadata {
+        Node::File(self.data.clone()).metadata()
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        without_interrupts(|| self.data.lock().resize(len as usize, 0));
+        Ok(())
+    }
+}
+
+/// A filesystem in memory.
+#[derive(Debug, Default)]
+pub struct RamFs {
+    root: Arc<RamDir>,
+}
+
+impl RamFs {
+    /// An empty filesystem.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl FileSystem for RamFs {
+    fn name(&self) -> &'static str {
+        "ramfs"
+    }
+
+    fn root(&self) -> Arc<dyn Dir> {
+        self.root.clone()
+    }
+}
+
+/// Tests files and directories of a [`RamFs`], the scratch directory of the test.
+#[cfg(feature = "test")]
+pub fn test_ramfs(info: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    let kind = |e: Error| e.kind();
+    let root = info.scratch;
+    test_assert_eq!(root.open("missing", OpenOptions::new()).map(drop).map_err(kind), Err(ErrorKind::NotFound))?;
+
+    let mut file = root.open("file", OpenOptions::create()).map_err(|_| "the file was not created")?;
+    test_assert_eq!(file.write_all(b"hello world"), Ok(()))?;
+    test_assert_eq!(file.seek(SeekFrom::Start(6)), Ok(6))?;
+    test_assert_eq!(file.write_all(b"there"), Ok(()))?;
+    test_assert_eq!(file.seek(SeekFrom::End(-5)), Ok(6))?;
+    let mut buf = [0; 5];
+    test_assert_eq!(file.read_exact(&mut buf), Ok(()))?;
+    test_assert_eq!(&buf, b"there")?;
+    test_assert_eq!(file.seek(SeekFrom::Current(-12)).map_err(kind), Err(ErrorKind::InvalidInput))?;
+    test_assert_eq!(file.set_len(5), Ok(()))?;
+
+    // another handle shares the data, and appends.
+    let mut other = root.open("file", OpenOptions::append()).map_err(|_| "the file was not opened")?;
+    test_assert_eq!(other.write_all(b"!"), Ok(()))?;
+    test_assert_eq!(file.seek(SeekFrom::Start(0)), Ok(0))?;
+    let mut buf = [0; 8];
+    test_assert_eq!(file.read(&mut buf), Ok(6))?;
+    test_assert_eq!(&buf[..6], b"hello!")?;
+
+    let dir = root.create_dir("dir").map_err(|_| "the directory was not created")?;
+    test_assert_eq!(root.create_dir("dir").map(drop).map_err(kind), Err(ErrorKind::AlreadyExists))?;
+    test_assert_eq!(root.open("dir", OpenOptions::new()).map(drop).map_err(kind), Err(ErrorKind::IsADirectory))?;
+    dir.open("inner", OpenOptions::create()).map_err(|_| "the file was not created")?;
+    test_assert_eq!(root.remove("dir").map_err(kind), Err(ErrorKind::DirectoryNotEmpty))?;
+    let names: Vec<String> = root.entries().unwrap_or_default().into_iter().map(|e| e.name).collect();
+    test_assert_eq!(names, ["dir", "file"])?;
+    test_assert!(dir.remove("inner").is_ok() && root.remove("dir").is_ok() && root.remove("file").is_ok())?;
+    // still open.
+    test_assert_eq!(file.metadata().len, 6)
+}
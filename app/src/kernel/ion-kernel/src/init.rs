@@ -1,6 +1,6 @@
 use core::fmt::Display;
 
-use crate::{interrupts, serial_println};
+use crate::{interrupts, log::progress::Progress, serial_println};
 
 /// An error while Initializing the Kernel
 /// 
@@ -32,13 +32,44 @@ impl InitErr {
 /// - IDT Table
 /// 
 /// and the rest is TODO.
+/// 
+/// `serial=off` and `ata=off` on the [command line](crate::boot::cmdline) skip those drivers.
 /// # Error
 /// returns the first error, as an [`InitErr`]
 pub fn init() -> Result<(), InitErr> {
+    let command_line = crate::boot::cmdline::command_line();
+    let mut progress = Progress::new("Initializing", 8);
     // serial_println!("Now Initializing GDT and TSS.");
     // interrupts::init_gdt_tss();
     serial_println!("Now Initializing IDT.");
     interrupts::init_interrupt_operations();
+    progress.advance(1);
+    crate::time::init();
+    // the debug port keeps working without it.
+    if command_line.get_bool("serial") != Some(Ok(false)) {
+        crate::serial::tx::init();
+    }
+    progress.advance(1);
+    // the keyboard keeps working without it, so this is not fatal.
+    let _ = crate::drivers::ps2::controller::init();
+    progress.advance(1);
+    interrupts::keyboard::init();
+    progress.advance(1);
+    // most machines have no PS/2 mouse.
+    let _ = crate::drivers::ps2::mouse::init();
+    progress.advance(1);
+    interrupts::keyboard::hotkeys::register_defaults();
+    progress.advance(1);
+    crate::monitor::init();
+    if command_line.get_bool("ata") != Some(Ok(false)) {
+        crate::drivers::ata::init();
+    }
+    crate::storage::init();
+    progress.advance(1);
+    crate::shell::complete::register_defaults();
+    crate::shell::kshell::register_defaults();
+    progress.advance(1);
+    progress.finish();
 
     // interrupts::enable();
 
@@ -0,0 +1,419 @@
+//! Input events, from every keyboard and mouse.
+//! 
+//! Drivers [`register`] a device, and [`push`] its events, which are timestamped and queued per
+//! device. Consumers either [`read`] a device's queue, or [`subscribe`] to the events of every
+//! device, so they do not care whether a device is PS/2 or USB.
+//! 
+//! Every device has a node, `/dev/input/eventN`, which reads the queue as records laid out like
+//! Linux's `struct input_event`, see [`Record`]
+//! 
+//! ```rust,no_run
+//! use crate::input::{self, InputEvent};
+//! 
+//! input::subscribe(|event| if let InputEvent::Wheel(delta) = event.event {
+//!     crate::log::info!("scrolled {delta} on {}", event.device);
+//! })?;
+//! ```
+//! 
+//! Events are pushed from interrupt handlers, so subscribers must be short.
+use alloc::boxed::Box;
+use core::{fmt::{self, Display}, time::Duration};
+
+use pc_keyboard::KeyCode;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{collections::{ArrayString, ArrayVec, CapacityError, RingBuffer}, fs::{File, FileType, Metadata, devfs}, io, log::warn};
+
+/// Maximum amount of registered devices.
+pub const MAX_DEVICES: usize = 8;
+/// Maximum amount of subscribers.
+pub const MAX_SUBSCRIBERS: usize = 8;
+/// How many events are queued per device, older ones are dropped.
+pub const QUEUE_LEN: usize = 64;
+
+/// A mouse button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    /// The left button.
+    Left,
+    /// The right button.
+    Right,
+    /// The middle button, or pressing the wheel.
+    Middle,
+}
+
+/// Something that happened on an input device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    /// A key was pressed, repeated, or released.
+    Key {
+        /// The key.
+        code: KeyCode,
+        /// `false` if it was released.
+        pressed: bool,
+    },
+    /// The pointer moved by an amount, right and up are positive.
+    Motion {
+        /// Horizontal movement.
+        dx: i32,
+        /// Vertical movement.
+        dy: i32,
+    },
+    /// A mouse button was pressed or released.
+    Button {
+        /// The button.
+        button: Button,
+        /// `false` if it was released.
+        pressed: bool,
+    },
+    /// The wheel was scrolled, down is positive.
+    Wheel(i32),
+}
+
+/// The kind of an input device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    /// Sends [`InputEvent::Key`]
+    Keyboard,
+    /// Sends [`InputEvent::Motion`], [`InputEvent::Button`] and [`InputEvent::Wheel`]
+    Mouse,
+}
+
+/// A registered input device, displayed as its node name: `event0`, `event1`...
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeviceId(u8);
+
+impl DeviceId {
+    /// The index of the device.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    /// The path of the device's node, `/dev/input/eventN`
+    pub fn path(self) -> ArrayString<24> {
+        let mut path = ArrayString::new();
+        let _ = fmt::write(&mut path, format_args!("/dev/input/{self}"));
+        path
+    }
+}
+
+impl Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "event{}", self.0)
+    }
+}
+
+/// An [`InputEvent`], with where and when it happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedEvent {
+    /// The time since boot, see [`time::now`](crate::time::now)
+    pub time: Duration,
+    /// The device which sent the event.
+    pub device: DeviceId,
+    /// The event.
+    pub event: InputEvent,
+}
+
+/// Information on a registered device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// The device.
+    pub id: DeviceId,
+    /// A human readable name, like `"PS/2 mouse"`
+    pub name: &'static str,
+    /// The kind of device.
+    pub kind: DeviceKind,
+}
+
+#[derive(Debug)]
+struct Device {
+    info: DeviceInfo,
+    queue: RingBuffer<TimedEvent, QUEUE_LEN>,
+    dropped: usize,
+}
+
+/// A subscription, see [`subscribe`]
+#[derive(Debug, Clone, Copy)]
+pub struct Subscription(fn(&TimedEvent));
+
+static DEVICES: Mutex<[Option<Device>; MAX_DEVICES]> = Mutex::new([const { None }; MAX_DEVICES]);
+static SUBSCRIBERS: Mutex<ArrayVec<fn(&TimedEvent), MAX_SUBSCRIBERS>> = Mutex::new(ArrayVec::new());
+
+/// Registers a device, which gets the lowest free id.
+pub fn register(name: &'static str, kind: DeviceKind) -> Result<DeviceId, CapacityError> {
+    without_interrupts(|| {
+        let mut devices = DEVICES.lock();
+        let index = devices.iter().position(Option::is_none).ok_or(CapacityError(()))?;
+        let info = DeviceInfo { id: DeviceId(index as u8), name, kind };
+        devices[index] = Some(Device { info, queue: RingBuffer::new(), dropped: 0 });
+        Ok(info.id)
+    })
+    .inspect(|&id| if devfs::register(NODE_NAMES[id.index()], &NODES[id.index()]).is_err() {
+        warn!("{} has no device node, there are too many devices", id.path());
+    })
+}
+
+/// Unregisters a device, its queued events are dropped, and its id may be reused.
+pub fn unregister(id: DeviceId) {
+    devfs::unregister(NODE_NAMES[id.index()]);
+    without_interrupts(|| DEVICES.lock()[id.index()] = None)
+}
+
+/// Calls `f` with every registered device.
+pub fn for_each_device(mut f: impl FnMut(DeviceInfo)) {
+    let devices: ArrayVec<DeviceInfo, MAX_DEVICES> = without_interrupts(|| {
+        let mut infos = ArrayVec::new();
+        for device in DEVICES.lock().iter().flatten() {
+            let _ = infos.push(device.info);
+        }
+        infos
+    });
+    devices.iter().copied().for_each(&mut f);
+}
+
+/// Timestamps an event of `device`, queues it, and passes it to the subscribers.
+/// 
+/// Events of unregistered devices are ignored. If the queue is full, the oldest event is dropped.
+pub fn push(device: DeviceId, event: InputEvent) {
+    let event = TimedEvent { time: crate::time::now(), device, event };
+    let subscribers = without_interrupts(|| {
+        if let Some(device) = &mut DEVICES.lock()[device.index()] {
+            if device.queue.push_overwrite(event).is_some() {
+                device.dropped += 1;
+            }
+        } else {
+            return None;
+        }
+        Some(SUBSCRIBERS.lock().clone())
+    });
+    for subscriber in subscribers.iter().flat_map(|s| s.iter()) {
+        subscriber(&event);
+    }
+}
+
+/// Takes the oldest queued event of `device`.
+pub fn read(device: DeviceId) -> Option<TimedEvent> {
+    without_interrupts(|| DEVICES.lock()[device.index()].as_mut()?.queue.pop())
+}
+
+/// How many events of `device` were dropped, as they were not read in time.
+pub fn dropped(device: DeviceId) -> usize {
+    without_interrupts(|| DEVICES.lock()[device.index()].as_ref().map_or(0, |device| device.dropped))
+}
+
+/// Calls `f` with every event pushed from now on, of every device.
+pub fn subscribe(f: fn(&TimedEvent)) -> Result<Subscription, CapacityError> {
+    without_interrupts(|| SUBSCRIBERS.lock().push(f).map_err(|_| CapacityError(())))?;
+    Ok(Subscription(f))
+}
+
+/// Stops calling the subscriber.
+pub fn unsubscribe(subscription: Subscription) {
+    without_interrupts(|| {
+        let mut subscribers = SUBSCRIBERS.lock();
+        if let Some(i) = subscribers.iter().position(|&f| core::ptr::fn_addr_eq(f, subscription.0)) {
+            subscribers.remove(i);
+        }
+    })
+}
+
+// Device nodes
+
+/// The names of the nodes in `/dev`, by device index.
+const NODE_NAMES: [&str; MAX_DEVICES] = [
+    "input/event0", "input/event1", "input/event2", "input/event3",
+    "input/event4", "input/event5", "input/event6", "input/event7",
+];
+static NODES: [Node; MAX_DEVICES] = [Node(0), Node(1), Node(2), Node(3), Node(4), Node(5), Node(6), Node(7)];
+
+const READ_ONLY: io::Error = io::Error::new(io::ErrorKind::ReadOnlyFilesystem, "input devices can not be written");
+
+/// Linux's event types and codes, for [`Record`]
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_WHEEL: u16 = 0x08;
+const BTN_LEFT: u16 = 0x110;
+const BTN_RIGHT: u16 = 0x111;
+const BTN_MIDDLE: u16 = 0x112;
+
+/// An event as read from a device node, laid out like Linux's `struct input_event` on x86_64.
+/// 
+/// Every [`InputEvent`] is one or two records, followed by an `EV_SYN` record. Keys have the type
+/// `EV_KEY`, and their [`KeyCode`] as the code, which is not Linux's key code. Motion and the wheel
+/// are `EV_REL`, with the wheel positive up like on Linux, and buttons are `EV_KEY` with Linux's
+/// button codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record {
+    /// The time since boot.
+    pub time: Duration,
+    /// The event type, E.g. `EV_KEY`
+    pub kind: u16,
+    /// The key, button or axis.
+    pub code: u16,
+    /// 1 for pressed and 0 for released, or the relative movement.
+    pub value: i32,
+}
+
+impl Record {
+    /// The size of a record in bytes.
+    pub const SIZE: usize = 24;
+
+    /// The records of `event`, up to three.
+    pub fn from_event(event: &TimedEvent) -> ArrayVec<Record, 3> {
+        let record = |kind, code, value| Record { time: event.time, kind, code, value };
+        let mut records = ArrayVec::new();
+        // can not fail, there are at most three.
+        let mut push = |record| { let _ = records.push(record); };
+        match event.event {
+            InputEvent::Key { code, pressed } => push(record(EV_KEY, code as u16, i32::from(pressed))),
+            InputEvent::Motion { dx, dy } => {
+                push(record(EV_REL, REL_X, dx));
+                // Linux's y axis points down.
+                push(record(EV_REL, REL_Y, -dy));
+            }
+            InputEvent::Button { button, pressed } => {
+                let code = match button {
+                    Button::Left => BTN_LEFT,
+                    Button::Right => BTN_RIGHT,
+                    Button::Middle => BTN_MIDDLE,
+                };
+                push(record(EV_KEY, code, i32::from(pressed)));
+            }
+            InputEvent::Wheel(delta) => push(record(EV_REL, REL_WHEEL, -delta)),
+        }
+        push(record(EV_SYN, 0, 0));
+        records
+    }
+
+    /// The bytes of the record: seconds and microseconds as 64 bit numbers, then the type, code and
+    /// value.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.time.as_secs().to_le_bytes());
+        bytes[8..16].copy_from_slice(&u64::from(self.time.subsec_micros()).to_le_bytes());
+        bytes[16..18].copy_from_slice(&self.kind.to_le_bytes());
+        bytes[18..20].copy_from_slice(&self.code.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.value.to_le_bytes());
+        bytes
+    }
+}
+
+/// The node of the device with this index.
+#[derive(Debug)]
+struct Node(u8);
+
+impl devfs::Device for Node {
+    fn open(&self) -> io::Result<Box<dyn File>> {
+        Ok(Box::new(NodeFile { id: DeviceId(self.0), pending: ArrayVec::new() }))
+    }
+}
+
+/// An open device node, reading its queue.
+#[derive(Debug)]
+struct NodeFile {
+    id: DeviceId,
+    /// The records of an event which did not fit in the last read, in reverse order.
+    pending: ArrayVec<Record, 3>,
+}
+
+impl io::Read for NodeFile {
+    /// Reads whole records, as many as fit in `buf` and are queued, without waiting for events.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.len() < Record::SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the buffer is shorter than a record"));
+        }
+        let mut len = 0;
+        for chunk in buf.chunks_exact_mut(Record::SIZE) {
+            if self.pending.is_empty() {
+                let Some(event) = read(self.id) else { break };
+                for record in Record::from_event(&event).iter().rev() {
+                    // can not fail, the records of one event fit.
+                    let _ = self.pending.push(*record);
+                }
+            }
+            let Some(record) = self.pending.pop() else { break };
+            chunk.copy_from_slice(&record.to_bytes());
+            len += Record::SIZE;
+        }
+        Ok(len)
+    }
+}
+
+impl io::Write for NodeFile {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(READ_ONLY)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Seek for NodeFile {
+    fn seek(&mut self, _: io::SeekFrom) -> io::Result<u64> {
+        Ok(0)
+    }
+}
+
+impl File for NodeFile {
+    fn metadata(&self) -> Metadata {
+        Metadata { file_type: FileType::Device, len: 0 }
+    }
+
+    fn set_len(&mut self, _: u64) -> io::Result<()> {
+        Err(READ_ONLY)
+    }
+}
+
+/// Tests queueing, timestamps and subscriptions, with a [`TestClock`](crate::time::TestClock)
+#[cfg(feature = "test")]
+pub fn test_input(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::test::{test_assert, test_assert_eq};
+
+    static WHEEL: AtomicUsize = AtomicUsize::new(0);
+    fn on_event(event: &TimedEvent) {
+        if let InputEvent::Wheel(_) = event.event {
+            WHEEL.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let clock = crate::time::TestClock::install();
+    let id = register("test mouse", DeviceKind::Mouse).map_err(|_| "no free device")?;
+    let subscription = subscribe(on_event).map_err(|_| "no free subscriber")?;
+    test_assert!(id.path().as_str().starts_with("/dev/input/event"))?;
+
+    clock.advance(5);
+    push(id, InputEvent::Motion { dx: 1, dy: -1 });
+    push(id, InputEvent::Wheel(1));
+    unsubscribe(subscription);
+    push(id, InputEvent::Wheel(-1));
+
+    let first = read(id);
+    test_assert_eq!(first.map(|e| e.event), Some(InputEvent::Motion { dx: 1, dy: -1 }))?;
+    test_assert_eq!(first.map(|e| e.time), Some(clock.now()))?;
+    test_assert_eq!(WHEEL.load(Ordering::Relaxed), 1)?;
+
+    let mut node = crate::fs::open(&id.path(), crate::fs::OpenOptions::new()).map_err(|_| "the node was not opened")?;
+    let mut records = [0; 2 * Record::SIZE];
+    test_assert_eq!(node.read(&mut records[..Record::SIZE - 1]).map_err(|e| e.kind()), Err(io::ErrorKind::InvalidInput))?;
+    test_assert_eq!(node.read(&mut records).ok(), Some(2 * Record::SIZE))?;
+    let wheel = Record { time: clock.now(), kind: EV_REL, code: REL_WHEEL, value: -1 };
+    test_assert_eq!(records[..Record::SIZE], wheel.to_bytes())?;
+    test_assert_eq!(records[Record::SIZE..], Record { kind: EV_SYN, code: 0, value: 0, ..wheel }.to_bytes())?;
+    drop(node);
+    // keeps the queue as it was.
+    push(id, InputEvent::Wheel(0));
+
+    for _ in 0..QUEUE_LEN {
+        push(id, InputEvent::Wheel(0));
+    }
+    test_assert_eq!(dropped(id), 2)?;
+    unregister(id);
+    test_assert_eq!(read(id), None)
+}
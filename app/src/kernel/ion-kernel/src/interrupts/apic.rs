@@ -0,0 +1,385 @@
+//! The local APIC and the IOAPIC, which replace the legacy 8259 PICs.
+//! 
+//! [`init`] reads the [MADT](crate::acpi::madt), enables the local APIC of the CPU (through the
+//! x2APIC MSRs if the CPU has them, otherwise through its MMIO registers), routes the ISA
+//! interrupts which the PICs had unmasked through the IOAPIC to the same vectors, and masks the
+//! PICs. The handlers stay the same, only [`unmask`](super::unmask) and
+//! [`end_of_interrupt`](super::end_of_interrupt) go to the APIC instead.
+//! 
+//! Without an APIC, a MADT or an IOAPIC, or with `apic=off` on the
+//! [command line](crate::boot::cmdline), the PICs stay in use. [`disable`] hands the interrupts
+//! back to the PICs at runtime, which a soft reboot does, as the next kernel starts with them.
+use core::{fmt::{self, Display}, sync::atomic::{AtomicU8, AtomicU32, AtomicU64, Ordering}};
+
+use spin::Mutex;
+use x86_64::{
+    PhysAddr, VirtAddr,
+    instructions::interrupts::without_interrupts,
+    registers::model_specific::Msr,
+    structures::{idt::InterruptStackFrame, paging::PageTableFlags},
+};
+
+use super::pic8259::{self, PIC_1_OFFSET};
+use crate::{acpi::{self, madt::{self, SourceOverride}}, arch::cpuid::FeatureRegisters, collections::ArrayVec, log::{debug, warn}, mem::vmm::{self, VmmError}, power::shutdown::{self, Stage}};
+
+/// The vector of spurious interrupts of the local APIC, which are not acknowledged.
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+/// The amount of ISA interrupts, which the PICs handled.
+pub const ISA_IRQS: usize = 16;
+/// Maximum amount of IOAPICs.
+pub const MAX_IO_APICS: usize = 4;
+
+const IA32_APIC_BASE: u32 = 0x1B;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+/// The x2APIC MSR of a local APIC register is this, plus its MMIO offset divided by 16.
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+// local APIC registers, by their MMIO offset.
+const REG_ID: u32 = 0x20;
+const REG_TASK_PRIORITY: u32 = 0x80;
+const REG_EOI: u32 = 0xB0;
+const REG_SPURIOUS: u32 = 0xF0;
+/// Enables the local APIC, in [`REG_SPURIOUS`]
+const SPURIOUS_ENABLE: u32 = 1 << 8;
+
+// IOAPIC registers, selected by writing their index to `IOREGSEL`, and accessed at `IOWIN`.
+const IOREGSEL: u64 = 0x00;
+const IOWIN: u64 = 0x10;
+const IOAPIC_VERSION: u32 = 0x01;
+const IOAPIC_REDIRECTION: u32 = 0x10;
+
+const REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+const REDIRECTION_LEVEL: u64 = 1 << 15;
+const REDIRECTION_MASKED: u64 = 1 << 16;
+
+/// How interrupts are delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Mode {
+    /// Through the legacy 8259 PICs.
+    Pic,
+    /// Through the IOAPIC, to a local APIC with MMIO registers.
+    XApic,
+    /// Through the IOAPIC, to a local APIC with MSRs.
+    X2Apic,
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pic => "8259 PICs",
+            Self::XApic => "xAPIC",
+            Self::X2Apic => "x2APIC",
+        })
+    }
+}
+
+/// Why the APIC is not used.
+#[derive(Debug)]
+pub enum ApicError {
+    /// `apic=off` is on the command line.
+    Disabled,
+    /// The CPU has no local APIC.
+    Unsupported,
+    /// There is no MADT, so the IOAPICs are unknown.
+    NoMadt,
+    /// The MADT lists no IOAPIC.
+    NoIoApic,
+    /// The registers could not be mapped.
+    Map(VmmError),
+}
+
+impl Display for ApicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Disabled => write!(f, "the APIC is disabled on the command line"),
+            Self::Unsupported => write!(f, "the CPU has no local APIC"),
+            Self::NoMadt => write!(f, "there is no ACPI MADT"),
+            Self::NoIoApic => write!(f, "the MADT lists no IOAPIC"),
+            Self::Map(e) => write!(f, "the APIC registers could not be mapped: {e}"),
+        }
+    }
+}
+
+impl core::error::Error for ApicError {}
+
+/// An IOAPIC, which handles the global system interrupts from `gsi_base` on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IoApic {
+    addr: u64,
+    gsi_base: u32,
+    /// The amount of redirection entries.
+    entries: u32,
+}
+
+impl IoApic {
+    /// # Safety
+    /// The registers must be mapped, and the caller must hold the lock of [`IO_APICS`]
+    unsafe fn read(&self, reg: u32) -> u32 {
+        // Safety: forwarded from the caller.
+        unsafe {
+            ((self.addr + IOREGSEL) as *mut u32).write_volatile(reg);
+            ((self.addr + IOWIN) as *const u32).read_volatile()
+        }
+    }
+
+    /// # Safety
+    /// See [`IoApic::read`]
+    unsafe fn write(&self, reg: u32, value: u32) {
+        // Safety: forwarded from the caller.
+        unsafe {
+            ((self.addr + IOREGSEL) as *mut u32).write_volatile(reg);
+            ((self.addr + IOWIN) as *mut u32).write_volatile(value);
+        }
+    }
+
+    /// # Safety
+    /// See [`IoApic::read`]
+    unsafe fn set_redirection(&self, gsi: u32, entry: u64) {
+        let reg = IOAPIC_REDIRECTION + 2 * (gsi - self.gsi_base);
+        // Safety: forwarded from the caller. The entry is masked while it is half written.
+        unsafe {
+            self.write(reg, REDIRECTION_MASKED as u32);
+            self.write(reg + 1, (entry >> 32) as u32);
+            self.write(reg, entry as u32);
+        }
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        (self.gsi_base..self.gsi_base + self.entries).contains(&gsi)
+    }
+}
+
+static MODE: AtomicU8 = AtomicU8::new(Mode::Pic as u8);
+/// The physical (and identity mapped) address of the local APIC registers, for [`Mode::XApic`]
+static LOCAL_APIC: AtomicU64 = AtomicU64::new(0);
+static LOCAL_APIC_ID: AtomicU32 = AtomicU32::new(0);
+static IO_APICS: Mutex<ArrayVec<IoApic, MAX_IO_APICS>> = Mutex::new(ArrayVec::new());
+static ROUTES: Mutex<[SourceOverride; ISA_IRQS]> = Mutex::new({
+    let mut routes = [SourceOverride::identity(0); ISA_IRQS];
+    let mut irq = 0;
+    while irq < ISA_IRQS {
+        routes[irq] = SourceOverride::identity(irq as u8);
+        irq += 1;
+    }
+    routes
+});
+/// The masks of the PICs before [`init`] masked them, restored by [`disable`]
+static PIC_MASKS: AtomicU32 = AtomicU32::new(0xFFFF);
+
+/// How interrupts are delivered.
+pub fn mode() -> Mode {
+    match MODE.load(Ordering::Acquire) {
+        1 => Mode::XApic,
+        2 => Mode::X2Apic,
+        _ => Mode::Pic,
+    }
+}
+
+/// Wether the APIC replaced the PICs.
+pub fn is_active() -> bool {
+    mode() != Mode::Pic
+}
+
+/// Reads the local APIC register at the MMIO offset `reg`
+/// # Safety
+/// The local APIC must be enabled in `mode`, and mapped for [`Mode::XApic`]
+unsafe fn read(mode: Mode, reg: u32) -> u32 {
+    // Safety: forwarded from the caller.
+    unsafe {
+        match mode {
+            Mode::X2Apic => Msr::new(X2APIC_MSR_BASE + (reg >> 4)).read() as u32,
+            _ => ((LOCAL_APIC.load(Ordering::Relaxed) + u64::from(reg)) as *const u32).read_volatile(),
+        }
+    }
+}
+
+/// Writes the local APIC register at the MMIO offset `reg`
+/// # Safety
+/// See [`read`]
+unsafe fn write(mode: Mode, reg: u32, value: u32) {
+    // Safety: forwarded from the caller.
+    unsafe {
+        match mode {
+            Mode::X2Apic => Msr::new(X2APIC_MSR_BASE + (reg >> 4)).write(u64::from(value)),
+            _ => ((LOCAL_APIC.load(Ordering::Relaxed) + u64::from(reg)) as *mut u32).write_volatile(value),
+        }
+    }
+}
+
+/// The redirection entry which delivers `route` as `vector`, to the local APIC `apic_id`
+pub fn redirection(route: SourceOverride, vector: u8, apic_id: u32, masked: bool) -> u64 {
+    let mut entry = u64::from(vector) | u64::from(apic_id & 0xFF) << 56;
+    if route.active_low {
+        entry |= REDIRECTION_ACTIVE_LOW;
+    }
+    if route.level_triggered {
+        entry |= REDIRECTION_LEVEL;
+    }
+    if masked {
+        entry |= REDIRECTION_MASKED;
+    }
+    entry
+}
+
+/// Sets the redirection entry of the ISA interrupt `irq`, to its vector behind the PICs'.
+fn route(irq: u8, masked: bool) {
+    let route = without_interrupts(|| ROUTES.lock()[usize::from(irq)]);
+    let entry = redirection(route, PIC_1_OFFSET + irq, LOCAL_APIC_ID.load(Ordering::Relaxed), masked);
+    without_interrupts(|| {
+        let io_apics = IO_APICS.lock();
+        match io_apics.iter().find(|io_apic| io_apic.handles(route.gsi)) {
+            // Safety: the IOAPICs were mapped by `init`, and are locked.
+            Some(io_apic) => unsafe { io_apic.set_redirection(route.gsi, entry) },
+            None => warn!("IRQ {irq} is wired to GSI {}, which no IOAPIC handles", route.gsi),
+        }
+    });
+}
+
+/// Unmasks the ISA interrupt `irq` (0-15) on the IOAPIC.
+pub fn unmask(irq: u8) {
+    route(irq, false);
+}
+
+/// Signals the end of an interrupt to the local APIC.
+/// # Safety
+/// The APIC must be [active](is_active), and an interrupt must be in service.
+pub unsafe fn end_of_interrupt() {
+    // Safety: forwarded from the caller.
+    unsafe { write(mode(), REG_EOI, 0) };
+}
+
+/// Identity maps the page of the registers at `addr`, unless it is mapped already.
+fn map(addr: u64) -> Result<(), VmmError> {
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+    vmm::map_phys(VirtAddr::new(addr), PhysAddr::new(addr), 4, flags)?;
+    // a fault on them names the APIC, see `drivers::isolation`.
+    let page = addr & !(vmm::PAGE_SIZE - 1);
+    let _ = crate::drivers::isolation::track_mmio("apic", page..page + vmm::PAGE_SIZE);
+    Ok(())
+}
+
+/// Replaces the PICs with the APIC, see the [module docs](self)
+/// # Errors
+/// Returns an error if the APIC can not be used, then the PICs stay in use.
+pub fn init() -> Result<Mode, ApicError> {
+    if crate::boot::cmdline::command_line().get_bool("apic") == Some(Ok(false)) {
+        return Err(ApicError::Disabled);
+    }
+    let features = FeatureRegisters::query();
+    if !features.edx.read_flag(9) {
+        return Err(ApicError::Unsupported);
+    }
+    let table = acpi::find(*b"APIC").ok_or(ApicError::NoMadt)?;
+    let x2apic = features.ecx.read_flag(21);
+    let mode = if x2apic { Mode::X2Apic } else { Mode::XApic };
+
+    let mut io_apics = ArrayVec::<IoApic, MAX_IO_APICS>::new();
+    for entry in madt::entries(table) {
+        if let madt::Entry::IoApic { addr, gsi_base, .. } = entry {
+            map(addr).map_err(ApicError::Map)?;
+            let mut io_apic = IoApic { addr, gsi_base, entries: 0 };
+            // Safety: just mapped, and not shared yet.
+            io_apic.entries = ((unsafe { io_apic.read(IOAPIC_VERSION) } >> 16) & 0xFF) + 1;
+            if io_apics.push(io_apic).is_err() {
+                warn!("There are more than {MAX_IO_APICS} IOAPICs, the rest is not used");
+                break;
+            }
+        }
+    }
+    if io_apics.is_empty() {
+        return Err(ApicError::NoIoApic);
+    }
+    if !x2apic {
+        // can not fail, the address is in the header.
+        let addr = madt::local_apic_address(table).unwrap_or_default();
+        map(addr).map_err(ApicError::Map)?;
+        LOCAL_APIC.store(addr, Ordering::Relaxed);
+    }
+
+    without_interrupts(|| {
+        let mut routes = ROUTES.lock();
+        for (irq, route) in routes.iter_mut().enumerate() {
+            *route = madt::isa_route(table, irq as u8);
+        }
+        *IO_APICS.lock() = io_apics.clone();
+    });
+
+    without_interrupts(|| {
+        // Safety: the local APIC exists, the MSR only enables it, and it is mapped for xAPIC.
+        let id = unsafe {
+            let mut base = Msr::new(IA32_APIC_BASE);
+            let value = base.read() | APIC_BASE_ENABLE;
+            base.write(if x2apic { value | APIC_BASE_X2APIC } else { value });
+            write(mode, REG_TASK_PRIORITY, 0);
+            let spurious = read(mode, REG_SPURIOUS) & !0xFF;
+            write(mode, REG_SPURIOUS, spurious | SPURIOUS_ENABLE | u32::from(SPURIOUS_VECTOR));
+            let id = read(mode, REG_ID);
+            if x2apic { id } else { id >> 24 }
+        };
+        LOCAL_APIC_ID.store(id, Ordering::Relaxed);
+
+        // every entry starts masked, the PICs' unmasked lines are then unmasked again.
+        for io_apic in io_apics.iter() {
+            for gsi in io_apic.gsi_base..io_apic.gsi_base + io_apic.entries {
+                // Safety: mapped, and only shared once `MODE` is set.
+                unsafe { io_apic.set_redirection(gsi, REDIRECTION_MASKED) };
+            }
+        }
+        let masks = pic8259::masks();
+        PIC_MASKS.store(u32::from(masks), Ordering::Relaxed);
+        pic8259::set_masks(0xFFFF);
+        for irq in (0..ISA_IRQS as u8).filter(|&irq| irq != 2 && masks & (1 << irq) == 0) {
+            route(irq, false);
+        }
+        MODE.store(mode as u8, Ordering::Release);
+    });
+    // the next kernel of a soft reboot expects the PICs.
+    let _ = shutdown::register(Stage::Drivers, "apic", || {
+        disable();
+        Ok(())
+    });
+    debug!("APIC: local APIC {}, {} IOAPIC(s)", LOCAL_APIC_ID.load(Ordering::Relaxed), io_apics.len());
+    Ok(mode)
+}
+
+/// Hands the interrupts back to the PICs, with the masks they had before [`init`]
+/// 
+/// The local APIC stays enabled, as the PICs are connected through it.
+pub fn disable() {
+    if !is_active() {
+        return;
+    }
+    without_interrupts(|| {
+        let io_apics = IO_APICS.lock();
+        for io_apic in io_apics.iter() {
+            for gsi in io_apic.gsi_base..io_apic.gsi_base + io_apic.entries {
+                // Safety: mapped by `init`, and locked.
+                unsafe { io_apic.set_redirection(gsi, REDIRECTION_MASKED) };
+            }
+        }
+        MODE.store(Mode::Pic as u8, Ordering::Release);
+        pic8259::set_masks(PIC_MASKS.load(Ordering::Relaxed) as u16);
+    });
+}
+
+/// Spurious interrupts of the local APIC, which must not be acknowledged.
+pub(super) extern "x86-interrupt" fn spurious(_frame: InterruptStackFrame) {}
+
+/// Tests encoding redirection entries.
+#[cfg(feature = "test")]
+pub fn test_apic(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    let timer = SourceOverride { irq: 0, gsi: 2, active_low: false, level_triggered: false };
+    test_assert_eq!(redirection(timer, 32, 0, false), 32)?;
+    test_assert_eq!(redirection(timer, 32, 3, true), 32 | 3 << 56 | REDIRECTION_MASKED)?;
+    let sci = SourceOverride { irq: 9, gsi: 9, active_low: true, level_triggered: true };
+    test_assert_eq!(redirection(sci, 41, 0, false), 41 | REDIRECTION_ACTIVE_LOW | REDIRECTION_LEVEL)?;
+
+    // the timer keeps ticking, through whichever controller is in use.
+    let ticks = crate::time::ticks();
+    crate::time::sleep(core::time::Duration::from_millis(30));
+    test_assert!(crate::time::ticks() > ticks)
+}
@@ -0,0 +1,71 @@
+//! Whether code runs in an interrupt handler.
+//!
+//! Hardware interrupt handlers [enter](enter) the interrupt context for as long as they run. Code
+//! which must not run there, because it allocates or waits, checks it with
+//! [`assert_not_interrupt`], which panics in debug builds, instead of corrupting the heap or
+//! hanging. The [allocator](crate::lib_alloc) checks it in all builds.
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// How many interrupt handlers are running, nested.
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Marks an interrupt handler as running, until it is dropped.
+#[derive(Debug)]
+#[must_use = "the interrupt context ends when this is dropped"]
+pub struct InterruptContext(());
+
+impl Drop for InterruptContext {
+    fn drop(&mut self) {
+        DEPTH.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Enters the interrupt context, at the start of a handler.
+///
+/// A handler which switches tasks has to drop it before, the next task is not in the handler.
+pub fn enter() -> InterruptContext {
+    DEPTH.fetch_add(1, Ordering::Relaxed);
+    InterruptContext(())
+}
+
+/// Whether an interrupt handler is running.
+pub fn in_interrupt() -> bool {
+    DEPTH.load(Ordering::Relaxed) != 0
+}
+
+/// How many interrupt handlers are running, saved by [`catch`](crate::panic::catch).
+pub(crate) fn depth() -> usize {
+    DEPTH.load(Ordering::Relaxed)
+}
+
+/// Restores the depth saved by [`depth`], when the handlers in between were skipped.
+pub(crate) fn restore(depth: usize) {
+    DEPTH.store(depth, Ordering::Relaxed);
+}
+
+/// Panics in debug builds, if an interrupt handler is running.
+pub macro assert_not_interrupt() {
+    debug_assert!(!$crate::interrupts::context::in_interrupt(), "this must not run in an interrupt handler")
+}
+
+/// Panics in debug builds, if interrupts are enabled.
+pub macro assert_irqs_disabled() {
+    debug_assert!(!x86_64::instructions::interrupts::are_enabled(), "this must run with interrupts disabled")
+}
+
+/// Tests entering and leaving nested interrupt contexts.
+#[cfg(feature = "test")]
+pub fn test_context(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::test_assert;
+
+    test_assert!(!in_interrupt())?;
+    let outer = enter();
+    let inner = enter();
+    drop(inner);
+    test_assert!(in_interrupt())?;
+    drop(outer);
+    test_assert!(!in_interrupt())?;
+    x86_64::instructions::interrupts::without_interrupts(|| assert_irqs_disabled!());
+    assert_not_interrupt!();
+    crate::test::TestResult::Ok
+}
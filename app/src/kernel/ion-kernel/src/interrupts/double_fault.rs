@@ -1,4 +1,4 @@
-use x86_64::structures::idt::InterruptStackFrame;
+use x86_64::{registers::control::Cr2, structures::idt::InterruptStackFrame};
 
 
 /// Index of a Double Fault in the IST.
@@ -8,5 +8,10 @@ pub(super) extern "x86-interrupt" fn double_fault(
     frame: InterruptStackFrame,
     err: u64
 ) -> ! {
+    // page faults have their own stack, but another exception pushed on an overflowed stack faults.
+    let addr = Cr2::read_raw();
+    if let Some(slot) = crate::mem::stacks::guard_of(addr) {
+        panic!("Reached a Double Fault: kernel stack overflow, the task on stack {slot} overflowed it, at {addr:#x}\n{frame:#?}");
+    }
     panic!("Reached a Double Fault: {err}\n{frame:#?}");
 }
\ No newline at end of file
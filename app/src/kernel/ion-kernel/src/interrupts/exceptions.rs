@@ -0,0 +1,184 @@
+//! Handlers for the CPU exceptions which are not handled elsewhere.
+//! 
+//! None of them can be recovered from, returning would run the faulting instruction again. So each
+//! handler reports the exception on serial, with the error code and the stack frame, logs it, then
+//! panics with the exception and the instruction pointer, so the panic screen shows them and the
+//! kernel halts.
+use core::fmt::{self, Display, Write};
+
+use x86_64::structures::idt::InterruptStackFrame;
+
+use crate::{collections::ArrayString, log::error, panic::screen::RawSerial};
+
+/// A CPU exception, with a handler in this module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    /// `#DE`, a division by zero, or a quotient which does not fit.
+    DivideError,
+    /// `#UD`, an instruction which does not exist, or `ud2`
+    InvalidOpcode,
+    /// `#NP`, loading a segment which is not present.
+    SegmentNotPresent,
+    /// `#SS`, a stack segment which is not present, or a non canonical stack address.
+    StackSegmentFault,
+    /// `#GP`, any other protection violation.
+    GeneralProtection,
+    /// `#MF`, an unmasked x87 floating point exception.
+    X87FloatingPoint,
+    /// `#AC`, an unaligned access, with alignment checking enabled.
+    AlignmentCheck,
+    /// `#XM`, an unmasked SSE floating point exception.
+    SimdFloatingPoint,
+}
+
+impl Exception {
+    /// The mnemonic used by the Intel manuals, like `#GP`
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::DivideError => "#DE",
+            Self::InvalidOpcode => "#UD",
+            Self::SegmentNotPresent => "#NP",
+            Self::StackSegmentFault => "#SS",
+            Self::GeneralProtection => "#GP",
+            Self::X87FloatingPoint => "#MF",
+            Self::AlignmentCheck => "#AC",
+            Self::SimdFloatingPoint => "#XM",
+        }
+    }
+
+    /// Whether the error code is a [selector error code](SelectorError)
+    fn has_selector(&self) -> bool {
+        matches!(self, Self::SegmentNotPresent | Self::StackSegmentFault | Self::GeneralProtection)
+    }
+}
+
+impl Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::DivideError => "divide error",
+            Self::InvalidOpcode => "invalid opcode",
+            Self::SegmentNotPresent => "segment not present",
+            Self::StackSegmentFault => "stack segment fault",
+            Self::GeneralProtection => "general protection fault",
+            Self::X87FloatingPoint => "x87 floating point exception",
+            Self::AlignmentCheck => "alignment check",
+            Self::SimdFloatingPoint => "SIMD floating point exception",
+        };
+        write!(f, "{name} ({})", self.mnemonic())
+    }
+}
+
+/// A decoded selector error code, pushed by `#NP`, `#SS` and `#GP`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectorError(pub u64);
+
+impl SelectorError {
+    /// Whether the exception happened while delivering an external event, like an interrupt.
+    pub fn external(&self) -> bool {
+        self.0 & 1 != 0
+    }
+
+    /// The table of the selector: `GDT`, `IDT` or `LDT`
+    pub fn table(&self) -> &'static str {
+        match (self.0 >> 1) & 0b11 {
+            0 => "GDT",
+            2 => "LDT",
+            _ => "IDT",
+        }
+    }
+
+    /// The index of the selector in its [table](Self::table)
+    pub fn index(&self) -> u64 {
+        (self.0 >> 3) & 0x1fff
+    }
+}
+
+impl Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // a #GP with no selector, like a non canonical address, pushes 0.
+        if self.0 == 0 {
+            return write!(f, "error code 0");
+        }
+        write!(f, "error code {:#x} ({} entry {}", self.0, self.table(), self.index())?;
+        if self.external() {
+            write!(f, ", external")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Reports `exception` on serial and through the log, then panics.
+fn report(exception: Exception, frame: &InterruptStackFrame, code: Option<u64>) -> ! {
+    let ip = frame.instruction_pointer.as_u64();
+    let mut detail = ArrayString::<64>::new();
+    let _ = match code {
+        Some(code) if exception.has_selector() => write!(detail, ", {}", SelectorError(code)),
+        Some(code) => write!(detail, ", error code {code:#x}"),
+        None => Ok(()),
+    };
+    if exception == Exception::SimdFloatingPoint {
+        let _ = write!(detail, ", mxcsr {:?}", x86_64::registers::mxcsr::read());
+    }
+
+    // serial first, printing may be locked by the faulting code.
+    let mut serial = RawSerial;
+    let _ = writeln!(serial, "\nEXCEPTION: {exception} at {ip:#x}{detail}\n{frame:#?}");
+    error!("{exception} at {ip:#x}{detail}, stack pointer {:#x}", frame.stack_pointer);
+    panic!("{exception} at {ip:#x}{detail}");
+}
+
+pub(super) extern "x86-interrupt" fn divide_error(frame: InterruptStackFrame) {
+    report(Exception::DivideError, &frame, None);
+}
+
+pub(super) extern "x86-interrupt" fn invalid_opcode(frame: InterruptStackFrame) {
+    report(Exception::InvalidOpcode, &frame, None);
+}
+
+pub(super) extern "x86-interrupt" fn segment_not_present(frame: InterruptStackFrame, code: u64) {
+    report(Exception::SegmentNotPresent, &frame, Some(code));
+}
+
+pub(super) extern "x86-interrupt" fn stack_segment_fault(frame: InterruptStackFrame, code: u64) {
+    report(Exception::StackSegmentFault, &frame, Some(code));
+}
+
+pub(super) extern "x86-interrupt" fn general_protection_fault(frame: InterruptStackFrame, code: u64) {
+    report(Exception::GeneralProtection, &frame, Some(code));
+}
+
+pub(super) extern "x86-interrupt" fn x87_floating_point(frame: InterruptStackFrame) {
+    report(Exception::X87FloatingPoint, &frame, None);
+}
+
+pub(super) extern "x86-interrupt" fn alignment_check(frame: InterruptStackFrame, code: u64) {
+    report(Exception::AlignmentCheck, &frame, Some(code));
+}
+
+pub(super) extern "x86-interrupt" fn simd_floating_point(frame: InterruptStackFrame) {
+    report(Exception::SimdFloatingPoint, &frame, None);
+}
+
+/// Tests that exceptions are reported, instead of triple faulting.
+#[cfg(feature = "test")]
+pub fn test_exceptions(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::{panic::catch::catch, test::{test_assert, test_assert_eq}};
+
+    // Safety: ud2 only raises #UD, which is caught.
+    let caught = catch(|| unsafe { core::arch::asm!("ud2") });
+    let message = caught.err().ok_or("ud2 did not fault")?.message;
+    test_assert!(message.starts_with("invalid opcode (#UD) at 0x"))?;
+
+    // Safety: the division raises #DE, which is caught.
+    let caught = catch(|| unsafe {
+        core::arch::asm!("xor ecx, ecx", "div ecx", out("eax") _, out("ecx") _, out("edx") _);
+    });
+    let message = caught.err().ok_or("the division did not fault")?.message;
+    test_assert!(message.starts_with("divide error (#DE) at 0x"))?;
+
+    // index 2 of the GDT, while delivering an interrupt.
+    test_assert_eq!(SelectorError(0x11).table(), "GDT")?;
+    test_assert_eq!(SelectorError(0x11).index(), 2)?;
+    test_assert!(SelectorError(0x11).external())?;
+    test_assert_eq!(SelectorError(0x1b).table(), "IDT")
+}
@@ -1,17 +1,26 @@
+use core::ops::Range;
+
 use x86_64::{VirtAddr, structures::gdt::Descriptor};
 use x86_64::structures::tss::TaskStateSegment;
 use lazy_static::lazy_static;
 
+/// The size of every interrupt stack.
+pub const IST_STACK_SIZE: usize = 4096 * 5;
 
 lazy_static! {
     static ref TSS: TaskStateSegment = {
         let mut tss = TaskStateSegment::new();
         tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+            static mut STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+
+            let stack_start = VirtAddr::from_ptr(&raw const STACK);
+            stack_start + IST_STACK_SIZE as u64
+        };
+        tss.interrupt_stack_table[PAGE_FAULT_IST_INDEX as usize] = {
+            static mut STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
 
             let stack_start = VirtAddr::from_ptr(&raw const STACK);
-            stack_start + STACK_SIZE as u64
+            stack_start + IST_STACK_SIZE as u64
         };
         tss
     };
@@ -19,7 +28,7 @@ lazy_static! {
 
 use x86_64::structures::gdt::{GlobalDescriptorTable, SegmentSelector};
 
-use crate::interrupts::double_fault::DOUBLE_FAULT_IST_INDEX;
+use crate::interrupts::{double_fault::DOUBLE_FAULT_IST_INDEX, page_fault::PAGE_FAULT_IST_INDEX};
 
 lazy_static! {
     static ref GDT: (GlobalDescriptorTable, Selectors) = {
@@ -46,3 +55,12 @@ pub fn init() {
         load_tss(GDT.1.tss_selector);
     }
 }
+
+/// Calls `f` with the index and address range of every interrupt stack in the TSS.
+pub fn for_each_interrupt_stack(mut f: impl FnMut(usize, Range<u64>)) {
+    // the TSS is packed, its fields can not be borrowed.
+    let table = TSS.interrupt_stack_table;
+    for (index, top) in table.iter().enumerate().filter(|(_, top)| !top.is_null()) {
+        f(index, top.as_u64() - IST_STACK_SIZE as u64..top.as_u64());
+    }
+}
@@ -0,0 +1,247 @@
+//! Hotkeys, which are handled before keys reach the input line.
+//! 
+//! ```rust,no_run
+//! use pc_keyboard::KeyCode;
+//! use crate::interrupts::keyboard::hotkeys::{self, Modifiers};
+//! 
+//! hotkeys::register((Modifiers::CTRL | Modifiers::ALT) + KeyCode::F12, || crate::log::info!("F12!"))?;
+//! ```
+//! 
+//! Handlers run on the [executor](crate::task::executor), with interrupts disabled and the
+//! keyboard locked, so they must be short, and must not wait for keyboard input. Longer work is
+//! [spawned](crate::task::spawn) instead.
+use core::{fmt::{self, Display}, ops::{Add, BitOr}};
+
+use pc_keyboard::{KeyCode, KeyEvent, KeyState};
+use spin::Mutex;
+
+use crate::collections::ArrayVec;
+
+/// Maximum amount of registered hotkeys.
+pub const MAX_HOTKEYS: usize = 16;
+
+/// A set of modifier keys, regardless of their side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    /// No modifiers.
+    pub const NONE: Self = Self(0);
+    /// Either Control key.
+    pub const CTRL: Self = Self(1 << 0);
+    /// Either Alt key, including AltGr.
+    pub const ALT: Self = Self(1 << 1);
+    /// Either Shift key.
+    pub const SHIFT: Self = Self(1 << 2);
+    /// Either Windows key.
+    pub const SUPER: Self = Self(1 << 3);
+
+    /// The modifier `code` is, if it is one.
+    pub const fn from_key(code: KeyCode) -> Option<Self> {
+        match code {
+            KeyCode::LControl | KeyCode::RControl => Some(Self::CTRL),
+            KeyCode::LAlt | KeyCode::RAltGr => Some(Self::ALT),
+            KeyCode::LShift | KeyCode::RShift => Some(Self::SHIFT),
+            KeyCode::LWin | KeyCode::RWin => Some(Self::SUPER),
+            _ => None,
+        }
+    }
+
+    /// Returns both sets of modifiers.
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOr for Modifiers {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+    }
+}
+
+impl Add<KeyCode> for Modifiers {
+    type Output = Hotkey;
+
+    fn add(self, key: KeyCode) -> Hotkey {
+        Hotkey { modifiers: self, key }
+    }
+}
+
+impl Display for Modifiers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (flag, name) in [(Self::CTRL, "Ctrl+"), (Self::ALT, "Alt+"), (Self::SHIFT, "Shift+"), (Self::SUPER, "Super+")] {
+            if self.0 & flag.0 != 0 {
+                f.write_str(name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A key, with the modifiers which must be held exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotkey {
+    /// The modifiers.
+    pub modifiers: Modifiers,
+    /// The key.
+    pub key: KeyCode,
+}
+
+impl Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{:?}", self.modifiers, self.key)
+    }
+}
+
+/// An error while registering a hotkey.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyError {
+    /// There are [`MAX_HOTKEYS`] already.
+    Full,
+    /// The hotkey has a handler already.
+    Taken(Hotkey),
+}
+
+impl Display for HotkeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full => write!(f, "no more than {MAX_HOTKEYS} hotkeys can be registered"),
+            Self::Taken(hotkey) => write!(f, "the hotkey {hotkey} is taken"),
+        }
+    }
+}
+
+impl core::error::Error for HotkeyError {}
+
+#[derive(Debug)]
+struct State {
+    hotkeys: ArrayVec<(Hotkey, fn()), MAX_HOTKEYS>,
+    held: u8,
+    /// The key of the hotkey which was triggered, until it is released.
+    triggered: Option<KeyCode>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State { hotkeys: ArrayVec::new(), held: 0, triggered: None });
+
+/// Calls `handler` whenever `hotkey` is pressed, instead of passing the key on.
+/// # Errors
+/// Returns an error if the hotkey is taken, or there are too many hotkeys.
+pub fn register(hotkey: Hotkey, handler: fn()) -> Result<(), HotkeyError> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut state = STATE.lock();
+        if state.hotkeys.iter().any(|(h, _)| *h == hotkey) {
+            return Err(HotkeyError::Taken(hotkey));
+        }
+        state.hotkeys.push((hotkey, handler)).map_err(|_| HotkeyError::Full)
+    })
+}
+
+/// Removes the handler of `hotkey`, returning whether it had one.
+pub fn unregister(hotkey: Hotkey) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut state = STATE.lock();
+        match state.hotkeys.iter().position(|(h, _)| *h == hotkey) {
+            Some(i) => {
+                state.hotkeys.remove(i);
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// Calls `f` with every registered hotkey.
+pub fn for_each(mut f: impl FnMut(Hotkey)) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        for (hotkey, _) in STATE.lock().hotkeys.iter() {
+            f(*hotkey);
+        }
+    })
+}
+
+/// Registers the built in hotkeys.
+/// 
+/// - Ctrl+Alt+Delete: reboots, after the [shutdown sequence](crate::power::reboot_sequence).
+pub fn register_defaults() {
+    let reboot = || {
+        // the hooks may wait on the disk, so they do not run in the handler.
+        if crate::task::spawn("reboot", || crate::power::reboot_sequence()).is_err() {
+            crate::power::reboot();
+        }
+    };
+    if let Err(e) = register((Modifiers::CTRL | Modifiers::ALT) + KeyCode::Delete, reboot) {
+        crate::log::warn!("Failed to register a default hotkey: {e}");
+    }
+}
+
+/// Forgets the held modifiers.
+pub(super) fn reset() {
+    let mut state = STATE.lock();
+    state.held = 0;
+    state.triggered = None;
+}
+
+/// Handles hotkeys, returning whether `event` was consumed.
+/// 
+/// Called with interrupts disabled, before the key is passed on.
+pub(super) fn handle(event: &KeyEvent) -> bool {
+    let handler = {
+        let mut state = STATE.lock();
+        if let Some(modifier) = Modifiers::from_key(event.code) {
+            match event.state {
+                KeyState::Down => state.held |= modifier.0,
+                KeyState::Up => state.held &= !modifier.0,
+                KeyState::SingleShot => {}
+            }
+            return false;
+        }
+        // the repeats and release of a triggered hotkey are consumed too.
+        if state.triggered == Some(event.code) {
+            if event.state == KeyState::Up {
+                state.triggered = None;
+            }
+            return true;
+        }
+        if event.state != KeyState::Down {
+            return false;
+        }
+        let pressed = Modifiers(state.held) + event.code;
+        let Some(&(_, handler)) = state.hotkeys.iter().find(|(h, _)| *h == pressed) else { return false };
+        state.triggered = Some(event.code);
+        handler
+    };
+    // the lock is dropped, so handlers may register hotkeys.
+    handler();
+    true
+}
+
+/// Tests that hotkeys are consumed, and other keys are not.
+#[cfg(feature = "test")]
+pub fn test_hotkeys(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::test::{test_assert, test_assert_eq};
+    use super::replay::{replay, take_line};
+
+    static PRESSED: AtomicUsize = AtomicUsize::new(0);
+
+    take_line();
+
+    let hotkey = (Modifiers::CTRL | Modifiers::ALT) + KeyCode::F1;
+    test_assert!(register(hotkey, || { PRESSED.fetch_add(1, Ordering::SeqCst); }).is_ok())?;
+    test_assert!(matches!(register(hotkey, || {}), Err(HotkeyError::Taken(_))))?;
+
+    // ctrl, alt, f1 (twice, as the keyboard repeats it), then the releases.
+    replay(&[0x1D, 0x38, 0x3B, 0x3B, 0xBB, 0xB8, 0x9D]);
+    test_assert_eq!(PRESSED.load(Ordering::SeqCst), 1)?;
+    test_assert_eq!(take_line().as_str(), "")?;
+
+    // without the modifiers, f1 is passed on.
+    replay(&[0x3B, 0xBB]);
+    test_assert!(unregister(hotkey))?;
+    test_assert!(!unregister(hotkey))?;
+    test_assert_eq!(PRESSED.load(Ordering::SeqCst), 1)?;
+    test_assert!(!take_line().is_empty())
+}
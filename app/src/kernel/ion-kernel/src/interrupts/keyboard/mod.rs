@@ -1,132 +1,264 @@
 #![allow(unused)]
-use core::{cell::OnceCell, ops::{Deref, DerefMut}};
+use core::{ops::{Deref, DerefMut}, sync::atomic::{AtomicBool, Ordering}};
 
 use x86_64::{instructions::port::{Port, PortGeneric, ReadWriteAccess}, structures::idt::InterruptStackFrame};
 
-use crate::{interrupts::{keyboard::ps2::{DefaultIO, set_scancode_set}, pic8259::handlers::notify}, serial_println, text::{WRITER, print}};
+use crate::{input, sync::{self, InterruptSafeOnceCell}, interrupts::{context::{assert_not_interrupt, in_interrupt}, keyboard::ps2::{DefaultIO, set_scancode_set}, pic8259::handlers::notify}, log::{info, warn}, serial_println, text::{INPUT_LINE, println}};
 
-use pc_keyboard::{DecodedKey, HandleControl, KeyCode, Keyboard, ScancodeSet, ScancodeSet1, ScancodeSet2, layouts::{self, Us104Key}};
+use pc_keyboard::{DecodedKey, HandleControl, KeyCode, KeyEvent, KeyState, Keyboard, ScancodeSet, ScancodeSet1, ScancodeSet2, layouts::{self, Us104Key}};
 use spin::{Mutex, MutexGuard};
 
 lazy_static::lazy_static! {
-    static ref KEYBOARD: Mutex<Keyboard<layouts::Us104Key, ps2::ScancodeSet>> = {
-        // let mut data = Port::new(0x60);
-        // let mut write = Port::new(0x64);
-    
-        Mutex::new(Keyboard::new(ps2::ScancodeSet::None, Us104Key, HandleControl::Ignore))
+    static ref KEYBOARD: sync::Mutex<Keyboard<layouts::Us104Key, ps2::Decoder>> = {
+        sync::Mutex::new("KEYBOARD", Keyboard::new(ps2::Decoder::new(ps2::ScancodeSet::None), Us104Key, HandleControl::Ignore))
     };
 }
 
-struct Once {
-    init: *const ps2::ScancodeSet
-}
-
-impl Once {
-    pub const fn new(init: &ps2::ScancodeSet) -> Self {
-        Self { init: init as *const ps2::ScancodeSet }
-    }
-
-    pub const fn new_ptr(init: *const ps2::ScancodeSet) -> Self {
-        Self { init }
-    }
-
-    pub fn set(&self, set: ps2::ScancodeSet) {
-        let ptr = self.init as *mut ps2::ScancodeSet;
-        unsafe {
-            *ptr = set
-        }
-    }
-
-    pub fn query(&self) -> ps2::ScancodeSet {
-        unsafe { *self.init }
-    }
-}
+/// The keyboard, as an input device.
+static INPUT_DEVICE: InterruptSafeOnceCell<input::DeviceId> = InterruptSafeOnceCell::new();
 
-unsafe impl Sync for Once {}
+/// The negotiated scancode set, keyboards plugged in later are switched to it.
+static SCANCODE_SET: InterruptSafeOnceCell<ps2::ScancodeSet> = InterruptSafeOnceCell::new();
 
-static mut SCAN_CODE_SET_IS_SET: ps2::ScancodeSet = ps2::ScancodeSet::None;
-static SCAN_CODE_SET_QUERIED: Once = Once::new_ptr(unsafe { &raw const SCAN_CODE_SET_IS_SET });
+/// Set while [`init`] talks to the keyboard, the bytes it sends are responses, not scancodes.
+static EXCHANGING: AtomicBool = AtomicBool::new(false);
 
 /// Handler Keyboard Input
 pub extern "x86-interrupt" fn keyboard_interrupt_handler(
     _stack_frame: InterruptStackFrame)
 {
-    // Note: the current implementation is simply here as a placeholder until we implement multi-tasking,
-    // which is soon.
+    let _context = crate::interrupts::context::enter();
+    let entry = crate::interrupts::stats::enter();
+    // decoding is left to the executor, see `scancodes`
 
 
     use x86_64::instructions::{port::Port, interrupts};
     
     interrupts::without_interrupts(|| {
+        // read by `init` instead.
+        if EXCHANGING.load(Ordering::Relaxed) {
+            notify!(unsafe Keyboard, entry);
+            return;
+        }
         let mut port = Port::new(0x60);
     
         let scancode: u8 = unsafe { port.read() };
-    
-        let mut keyboard = KEYBOARD.lock();
-            // To impl
-            // if SCAN_CODE_SET_QUERIED.query() == ps2::ScancodeSet::None {
-            //     // let mut data = Port::new(0x60);
-            //     // let mut write = Port::new(0x64);
-            //     // if let Some(set) = query_scan_code(&mut data, &mut write) {
-            //     //     *keyboard = Keyboard::new(set, Us104Key, HandleControl::Ignore);
-            //     //     SCAN_CODE_SET_QUERIED.toggle();
-            //     // }
-
-            //     set_scancode_set(&mut DefaultIO, ps2::ScancodeSet::Set1);
-
-            //     *keyboard = Keyboard::new(ps2::ScancodeSet::Set1, Us104Key, HandleControl::Ignore);
-            //     SCAN_CODE_SET_QUERIED.set(ps2::ScancodeSet::Set1);
-            // }
-            if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-                if let Some(key) = keyboard.process_keyevent(key_event) {
-                    match key {
-                        DecodedKey::Unicode(character) => { 
-                            if character as u8 == 8 {
-                                x86_64::instructions::interrupts::without_interrupts(|| {
-                                    let mut lock = WRITER.lock();
-                                    lock.backspace();
-                                    drop(lock);
-                                })
-                            } else if character as u8 == 9 {
-                                use core::fmt::Write;
-                                x86_64::instructions::interrupts::without_interrupts(|| {
-                                    let mut lock = WRITER.lock();
-                                    write!(lock, "    ");
-                                    drop(lock);
-                                })
-                            } else if character as u8 == 46 {
-                                x86_64::instructions::interrupts::without_interrupts(|| {
-                                    let mut lock = WRITER.lock();
-                                    lock.delete_row();
-                                    drop(lock);
-                                })
-                            } else {
-                                print!("{}", character);
-                                serial_println!("{}", character as u8);
-                            }
-                        },
-                        DecodedKey::RawKey(key) => {
-                            if key == pc_keyboard::KeyCode::Backspace {
-                                x86_64::instructions::interrupts::without_interrupts(|| {
-                                    let mut lock = WRITER.lock();
-                                    lock.backspace();
-                                    drop(lock);
-                                })
-                            } else if key == KeyCode::Delete {
-                                x86_64::instructions::interrupts::without_interrupts(|| {
-                                    let mut lock = WRITER.lock();
-                                    lock.delete_row();
-                                    drop(lock);
-                                })
-                            } else {
-                                print!("{:?}", key)
-                            }
-                        },
-                    }
-                }
-            }
-        notify!(unsafe Keyboard);
+
+        replay::record(scancode);
+        scancodes::push(scancode);
+        notify!(unsafe Keyboard, entry);
     })
 }
 
-mod ps2;
\ No newline at end of file
+/// Sent by a keyboard after its self test, which it runs whenever it is plugged in.
+const SELF_TEST_PASSED: u8 = 0xAA;
+/// The prefix of extended scancodes.
+const EXTENDED_PREFIX: u8 = 0xE0;
+/// The make code of the left shift, its break code is the same as [`SELF_TEST_PASSED`]
+const LSHIFT_MAKE: u8 = 0x2A;
+
+/// Whether the left shift is held, so its release is not taken for a self test.
+static LSHIFT_HELD: AtomicBool = AtomicBool::new(false);
+/// Whether the last scancode was [`EXTENDED_PREFIX`]
+static AFTER_PREFIX: AtomicBool = AtomicBool::new(false);
+
+static DEVICE_TYPE: Mutex<Option<ps2::DeviceType>> = Mutex::new(None);
+
+/// The type of the keyboard, `None` if it could not be identified (yet).
+pub fn device_type() -> Option<ps2::DeviceType> {
+    x86_64::instructions::interrupts::without_interrupts(|| *DEVICE_TYPE.lock())
+}
+
+/// The scancode set being decoded, `None` if it was not negotiated (yet), and set 1 is decoded.
+pub fn scancode_set() -> Option<ps2::ScancodeSet> {
+    SCANCODE_SET.get().copied()
+}
+
+/// Whether the controller translates scancodes to set 1, assumed if it was not initialized.
+fn translated() -> bool {
+    crate::drivers::ps2::controller::info().is_none_or(|info| info.translation)
+}
+
+/// Identifies the keyboard, and negotiates the scancode set to decode.
+/// 
+/// Called at boot, and again whenever a keyboard is plugged in.
+pub fn init() {
+    if INPUT_DEVICE.get().is_none() {
+        match input::register("PS/2 keyboard", input::DeviceKind::Keyboard) {
+            Ok(id) => { let _ = INPUT_DEVICE.set(id); }
+            Err(_) => warn!("The keyboard was not registered, there are too many input devices"),
+        }
+    }
+    // the handler leaves the responses to the commands, which may wait with interrupts enabled.
+    EXCHANGING.store(true, Ordering::Relaxed);
+    let device = ps2::identify(&mut DefaultIO).ok();
+    let set = ps2::negotiate(&mut DefaultIO, translated(), scancode_set());
+    EXCHANGING.store(false, Ordering::Relaxed);
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        *DEVICE_TYPE.lock() = device;
+        if let Ok(set) = set {
+            *KEYBOARD.lock() = Keyboard::new(ps2::Decoder::new(set), Us104Key, HandleControl::Ignore);
+            let _ = SCANCODE_SET.set(set);
+        }
+    });
+    match device {
+        Some(device) if device.is_keyboard() => info!("Keyboard: {:?}", device),
+        Some(device) => warn!("The first PS/2 port has a {:?}, not a keyboard", device),
+        None => warn!("The keyboard could not be identified"),
+    }
+    match set {
+        Ok(set) => info!("Decoding scancode {:?}", set),
+        Err(e) => warn!("Could not negotiate the scancode set, decoding set 1: {:?}", e),
+    }
+}
+
+/// Resets the decoder after the keyboard was plugged in again, so keys held before do not stay
+/// held, and identifies it later.
+fn reconnected() {
+    let set = scancode_set().unwrap_or(ps2::ScancodeSet::None);
+    *KEYBOARD.lock() = Keyboard::new(ps2::Decoder::new(set), Us104Key, HandleControl::Ignore);
+    repeat::reset();
+    hotkeys::reset();
+    // not from this interrupt, the keyboard is still busy.
+    if crate::time::after(core::time::Duration::from_millis(50), init).is_err() {
+        warn!("Could not identify the reconnected keyboard, there are too many timers");
+    }
+}
+
+/// Decodes a scancode, and echoes the resulting key to the input line.
+/// 
+/// Called by [`scancodes::process`], must be called with interrupts disabled.
+fn handle_scancode(scancode: u8) {
+    // only set 1 has a key whose code is the same as a self test.
+    let set1 = matches!(scancode_set(), None | Some(ps2::ScancodeSet::Set1));
+    if !AFTER_PREFIX.swap(scancode == EXTENDED_PREFIX, Ordering::Relaxed) {
+        match scancode {
+            LSHIFT_MAKE if set1 => LSHIFT_HELD.store(true, Ordering::Relaxed),
+            SELF_TEST_PASSED if !LSHIFT_HELD.load(Ordering::Relaxed) => {
+                reconnected();
+                return;
+            }
+            SELF_TEST_PASSED => LSHIFT_HELD.store(false, Ordering::Relaxed),
+            _ => {}
+        }
+    }
+
+    let mut keyboard = KEYBOARD.lock();
+    if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
+        if hotkeys::handle(&key_event) {
+            return;
+        }
+        if let Some(key_event) = repeat::filter(&key_event) {
+            handle_event(&mut keyboard, key_event);
+        }
+    }
+}
+
+/// Processes a key event, and echoes the resulting key to the input line.
+fn handle_event(keyboard: &mut Keyboard<Us104Key, ps2::Decoder>, key_event: KeyEvent) {
+    if let Some(&id) = INPUT_DEVICE.get() {
+        input::push(id, input::InputEvent::Key { code: key_event.code, pressed: key_event.state != KeyState::Up });
+    }
+    if let Some(key) = keyboard.process_keyevent(key_event) {
+        match key {
+            DecodedKey::Unicode(character) => type_char(character),
+            DecodedKey::RawKey(key) => {
+                if key == pc_keyboard::KeyCode::Backspace {
+                    x86_64::instructions::interrupts::without_interrupts(|| {
+                        let mut lock = INPUT_LINE.lock();
+                        lock.backspace();
+                        lock.flush();
+                        drop(lock);
+                    })
+                } else if key == KeyCode::Delete {
+                    x86_64::instructions::interrupts::without_interrupts(|| {
+                        let mut lock = INPUT_LINE.lock();
+                        lock.delete_row();
+                        lock.flush();
+                        drop(lock);
+                    })
+                } else {
+                    INPUT_LINE.print(format_args!("{:?}", key))
+                }
+            },
+        }
+    }
+}
+
+/// Types a character into the input line, like a key press.
+fn type_char(character: char) {
+    if character as u8 == 8 {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let mut lock = INPUT_LINE.lock();
+            lock.backspace();
+            lock.flush();
+            drop(lock);
+        })
+    } else if character as u8 == 9 {
+        // completing allocates, which an interrupt handler must not.
+        if !in_interrupt() {
+            complete_line();
+        }
+    } else if character as u8 == 127 {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let mut lock = INPUT_LINE.lock();
+            lock.delete_row();
+            lock.flush();
+            drop(lock);
+        })
+    } else if character == '\n' {
+        // move the finished line from the input line to the main area.
+        let line = x86_64::instructions::interrupts::without_interrupts(|| {
+            let mut lock = INPUT_LINE.lock();
+            let line = lock.current_line();
+            lock.delete_row();
+            lock.flush();
+            line
+        });
+        stdin::push_line(&line);
+        println!("{}", line);
+    } else {
+        INPUT_LINE.print(format_args!("{}", character));
+        serial_println!("{}", character as u8);
+    }
+}
+
+/// Completes the last word of the input line, or lists the candidates if there are several and
+/// none can be completed further.
+/// 
+/// Must not be called in an interrupt handler.
+fn complete_line() {
+    assert_not_interrupt!();
+    let line = x86_64::instructions::interrupts::without_interrupts(|| {
+        let lock = INPUT_LINE.lock();
+        let mut line = lock.current_line();
+        // put back the trailing spaces, so the word after them is completed.
+        while line.len() < lock.column() && line.push(' ').is_ok() {}
+        line
+    });
+    let completion = crate::shell::complete::complete(&line);
+    let insertion = completion.insertion(&line);
+    let candidates = completion.completions.candidates();
+    if insertion.is_empty() && candidates.len() > 1 {
+        println!("{}", candidates.join("  "));
+    } else {
+        INPUT_LINE.print(format_args!("{insertion}"));
+    }
+}
+
+/// Types `text` into the input line, as if it was typed on the keyboard.
+/// 
+/// Newlines finish the line, moving it to the main area.
+pub fn type_str(text: &str) {
+    text.chars().for_each(type_char);
+}
+
+pub mod hotkeys;
+pub(crate) mod ps2;
+pub mod repeat;
+pub mod replay;
+pub mod scancodes;
+pub mod stdin;
+
+pub use replay::replay;
+pub use stdin::{Stdin, read_line};
@@ -4,8 +4,10 @@
 //! 
 //! Handles both "raw" (1/2/3) and "translated" (0x43/0x41/0x3F) returns.
 
-use pc_keyboard::{ScancodeSet1, ScancodeSet2};
-use x86_64::instructions::port::Port;
+use pc_keyboard::{KeyCode, KeyEvent, KeyState, ScancodeSet as _, ScancodeSet1, ScancodeSet2};
+use x86_64::instructions::{interrupts::without_interrupts, port::Port};
+
+use crate::{drivers::ps2::{PortId, controller}, time};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Ps2Resp {
@@ -22,20 +24,200 @@ pub enum ScancodeSet {
     None
 }
 
-impl pc_keyboard::ScancodeSet for ScancodeSet {
-    fn advance_state(&mut self, code: u8) -> Result<Option<pc_keyboard::KeyEvent>, pc_keyboard::Error> {
-        match self {
-            // if we have not set, use set 1
-            // if the set is 3, we do not support that (yet), use 1 instead.
-            ScancodeSet::None  |
-             ScancodeSet::Set1 |
-             ScancodeSet::Set3
-                => ScancodeSet1::new().advance_state(code),
-            ScancodeSet::Set2 => ScancodeSet2::new().advance_state(code)
+/// Decodes scancodes of the negotiated set.
+/// 
+/// The state of a multi byte scancode is kept between bytes.
+pub struct Decoder {
+    set: ScancodeSet,
+    set1: ScancodeSet1,
+    set2: ScancodeSet2,
+    /// Set 3: a break prefix (0xF0) was received.
+    set3_break: bool,
+}
+
+impl Decoder {
+    /// A decoder for `set`, [`ScancodeSet::None`] is decoded as set 1, as translation is the default.
+    pub fn new(set: ScancodeSet) -> Self {
+        Self { set, set1: ScancodeSet1::new(), set2: ScancodeSet2::new(), set3_break: false }
+    }
+
+    /// The set this decodes.
+    pub fn set(&self) -> ScancodeSet {
+        self.set
+    }
+
+    fn advance_set3(&mut self, code: u8) -> Result<Option<KeyEvent>, pc_keyboard::Error> {
+        if code == SET3_BREAK {
+            self.set3_break = true;
+            return Ok(None);
+        }
+        let state = if core::mem::take(&mut self.set3_break) { KeyState::Up } else { KeyState::Down };
+        match set3_keycode(code) {
+            Some(key) => Ok(Some(KeyEvent::new(key, state))),
+            None => Err(pc_keyboard::Error::UnknownKeyCode),
         }
     }
 }
 
+impl core::fmt::Debug for Decoder {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Decoder").field("set", &self.set).finish_non_exhaustive()
+    }
+}
+
+impl pc_keyboard::ScancodeSet for Decoder {
+    fn advance_state(&mut self, code: u8) -> Result<Option<KeyEvent>, pc_keyboard::Error> {
+        match self.set {
+            ScancodeSet::None | ScancodeSet::Set1 => self.set1.advance_state(code),
+            ScancodeSet::Set2 => self.set2.advance_state(code),
+            ScancodeSet::Set3 => self.advance_set3(code),
+        }
+    }
+}
+
+/// Set 3 prefixes every break code with this.
+const SET3_BREAK: u8 = 0xF0;
+
+/// Maps a set 3 scancode to its key.
+/// 
+/// Set 3 has a single byte for every key, without any `0xE0` prefixes.
+pub fn set3_keycode(code: u8) -> Option<KeyCode> {
+    Some(match code {
+        0x07 => KeyCode::F1,
+        0x08 => KeyCode::Escape,
+        0x0D => KeyCode::Tab,
+        0x0E => KeyCode::Oem8,
+        0x0F => KeyCode::F2,
+        0x11 => KeyCode::LControl,
+        0x12 => KeyCode::LShift,
+        0x14 => KeyCode::CapsLock,
+        0x15 => KeyCode::Q,
+        0x16 => KeyCode::Key1,
+        0x17 => KeyCode::F3,
+        0x19 => KeyCode::LAlt,
+        0x1A => KeyCode::Z,
+        0x1B => KeyCode::S,
+        0x1C => KeyCode::A,
+        0x1D => KeyCode::W,
+        0x1E => KeyCode::Key2,
+        0x1F => KeyCode::F4,
+        0x21 => KeyCode::C,
+        0x22 => KeyCode::X,
+        0x23 => KeyCode::D,
+        0x24 => KeyCode::E,
+        0x25 => KeyCode::Key4,
+        0x26 => KeyCode::Key3,
+        0x27 => KeyCode::F5,
+        0x29 => KeyCode::Spacebar,
+        0x2A => KeyCode::V,
+        0x2B => KeyCode::F,
+        0x2C => KeyCode::T,
+        0x2D => KeyCode::R,
+        0x2E => KeyCode::Key5,
+        0x2F => KeyCode::F6,
+        0x31 => KeyCode::N,
+        0x32 => KeyCode::B,
+        0x33 => KeyCode::H,
+        0x34 => KeyCode::G,
+        0x35 => KeyCode::Y,
+        0x36 => KeyCode::Key6,
+        0x37 => KeyCode::F7,
+        0x39 => KeyCode::RAltGr,
+        0x3A => KeyCode::M,
+        0x3B => KeyCode::J,
+        0x3C => KeyCode::U,
+        0x3D => KeyCode::Key7,
+        0x3E => KeyCode::Key8,
+        0x3F => KeyCode::F8,
+        0x41 => KeyCode::OemComma,
+        0x42 => KeyCode::K,
+        0x43 => KeyCode::I,
+        0x44 => KeyCode::O,
+        0x45 => KeyCode::Key0,
+        0x46 => KeyCode::Key9,
+        0x47 => KeyCode::F9,
+        0x49 => KeyCode::OemPeriod,
+        0x4A => KeyCode::Oem2,
+        0x4B => KeyCode::L,
+        0x4C => KeyCode::Oem1,
+        0x4D => KeyCode::P,
+        0x4E => KeyCode::OemMinus,
+        0x4F => KeyCode::F10,
+        0x52 => KeyCode::Oem3,
+        0x54 => KeyCode::Oem4,
+        0x55 => KeyCode::OemPlus,
+        0x56 => KeyCode::F11,
+        0x57 => KeyCode::PrintScreen,
+        0x58 => KeyCode::RControl,
+        0x59 => KeyCode::RShift,
+        0x5A => KeyCode::Return,
+        0x5B => KeyCode::Oem6,
+        0x5C => KeyCode::Oem7,
+        0x5E => KeyCode::F12,
+        0x5F => KeyCode::ScrollLock,
+        0x60 => KeyCode::ArrowDown,
+        0x61 => KeyCode::ArrowLeft,
+        0x62 => KeyCode::PauseBreak,
+        0x63 => KeyCode::ArrowUp,
+        0x64 => KeyCode::Delete,
+        0x65 => KeyCode::End,
+        0x66 => KeyCode::Backspace,
+        0x67 => KeyCode::Insert,
+        0x69 => KeyCode::Numpad1,
+        0x6A => KeyCode::ArrowRight,
+        0x6B => KeyCode::Numpad4,
+        0x6C => KeyCode::Numpad7,
+        0x6D => KeyCode::PageDown,
+        0x6E => KeyCode::Home,
+        0x6F => KeyCode::PageUp,
+        0x70 => KeyCode::Numpad0,
+        0x71 => KeyCode::NumpadPeriod,
+        0x72 => KeyCode::Numpad2,
+        0x73 => KeyCode::Numpad5,
+        0x74 => KeyCode::Numpad6,
+        0x75 => KeyCode::Numpad8,
+        0x76 => KeyCode::NumpadLock,
+        0x77 => KeyCode::NumpadDivide,
+        0x79 => KeyCode::NumpadEnter,
+        0x7A => KeyCode::Numpad3,
+        0x7C => KeyCode::NumpadAdd,
+        0x7D => KeyCode::Numpad9,
+        0x7E => KeyCode::NumpadMultiply,
+        0x84 => KeyCode::NumpadSubtract,
+        0x8B => KeyCode::LWin,
+        0x8C => KeyCode::RWin,
+        0x8D => KeyCode::Apps,
+        _ => return None,
+    })
+}
+
+/// Set 3 command: make every key typematic, and send make and break codes.
+const CMD_SET3_ALL_MAKE_BREAK: u8 = 0xFA;
+
+/// Picks the set to decode, and prepares the keyboard for it.
+/// 
+/// With `translation`, the controller turns set 2 into set 1, so the keyboard must use set 2.
+/// Otherwise, the keyboard's current set is decoded, unless a `previous` set was decoded, which the
+/// keyboard is switched to. Set 3 keyboards are told to send break codes for every key, as they do
+/// not for some by default.
+pub fn negotiate<I: Ps2Io>(io: &mut I, translation: bool, previous: Option<ScancodeSet>) -> Result<ScancodeSet, Ps2Error> {
+    let current = get_scancode_set(io)?;
+    if translation {
+        if current != ScancodeSet::Set2 {
+            set_scancode_set(io, ScancodeSet::Set2)?;
+        }
+        return Ok(ScancodeSet::Set1);
+    }
+    let set = previous.unwrap_or(current);
+    if set != current {
+        set_scancode_set(io, set)?;
+    }
+    if set == ScancodeSet::Set3 {
+        send_with_ack(io, CMD_SET3_ALL_MAKE_BREAK, 5)?;
+    }
+    Ok(set)
+}
+
 #[derive(Debug)]
 pub enum Ps2Error {
     Timeout,
@@ -59,35 +241,31 @@ pub trait Ps2Io {
 #[derive(Debug, Clone, Copy)]
 pub struct DefaultIO;
 
+/// Whether the waits of [`DefaultIO`] may let other tasks run, otherwise, the controller is locked
+/// for the whole wait, timed by the PIT's counter.
+fn may_yield() -> bool {
+    x86_64::instructions::interrupts::are_enabled() && !crate::interrupts::context::in_interrupt()
+}
+
 impl Ps2Io for DefaultIO {
     fn write_data(&mut self, byte: u8) -> Result<(), Ps2Error> {
-        let mut status_port: Port<u8> = Port::new(0x64);
-        let mut data_port: Port<u8> = Port::new(0x60);
-
-        // Wait until input buffer is clear (bit 1 == 0)
-        for _ in 0..100_000 {
-            let status = unsafe { status_port.read() };
-            if status & 0x02 == 0 {
-                unsafe { data_port.write(byte) };
-                return Ok(());
-            }
+        if !may_yield() {
+            return without_interrupts(|| controller::lock().write(PortId::First, byte)).map_err(|_| Ps2Error::Timeout);
         }
-        Err(Ps2Error::Timeout)
+        // the controller is only locked for each poll, so other tasks run in between.
+        time::poll_until(controller::TIMEOUT, || {
+            without_interrupts(|| controller::lock().try_write(PortId::First, byte)).transpose()
+        })
+        .ok_or(Ps2Error::Timeout)?
+        .map_err(|_| Ps2Error::Timeout)
     }
 
     fn read_data(&mut self) -> Result<u8, Ps2Error> {
-        let mut status_port: Port<u8> = Port::new(0x64);
-        let mut data_port: Port<u8> = Port::new(0x60);
-
-        // Wait until output buffer is full (bit 0 == 1)
-        for _ in 0..100_000 {
-            let status = unsafe { status_port.read() };
-            if status & 0x01 != 0 {
-                let byte = unsafe { data_port.read() };
-                return Ok(byte);
-            }
+        if !may_yield() {
+            return without_interrupts(|| controller::lock().read()).map_err(|_| Ps2Error::Timeout);
         }
-        Err(Ps2Error::Timeout)
+        time::poll_until(controller::TIMEOUT, || without_interrupts(|| controller::lock().try_read()))
+            .ok_or(Ps2Error::Timeout)
     }
 
     fn tiny_delay(&mut self) {
@@ -185,7 +363,85 @@ pub fn get_scancode_set<I: Ps2Io>(io: &mut I) -> Result<ScancodeSet, Ps2Error> {
     }
 }
 
-use pc_keyboard::KeyCode;
+/// Keyboard command: identify the device, which is answered with up to 2 id bytes.
+const CMD_IDENTIFY: u8 = 0xF2;
+/// Keyboard command: start sending scancodes.
+const CMD_ENABLE_SCANNING: u8 = 0xF4;
+/// Keyboard command: stop sending scancodes, so they are not confused with responses.
+const CMD_DISABLE_SCANNING: u8 = 0xF5;
+
+/// The type of a PS/2 device, from its response to identify (0xF2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceType {
+    /// An AT keyboard, which does not answer at all.
+    AtKeyboard,
+    /// A standard MF2 keyboard, possibly with translation.
+    Mf2Keyboard,
+    /// A short keyboard, like on ThinkPads.
+    ShortKeyboard,
+    /// A 122-key keyboard, or an NCD N-97 keyboard.
+    HostConnected122Key,
+    /// A 122-key keyboard.
+    Keyboard122Key,
+    /// A Japanese "G", "P" or "A" keyboard.
+    JapaneseKeyboard,
+    /// An NCD Sun layout keyboard.
+    NcdSunKeyboard,
+    /// A standard mouse.
+    Mouse,
+    /// A mouse with a scroll wheel.
+    ScrollMouse,
+    /// A mouse with 5 buttons.
+    FiveButtonMouse,
+    /// Any other response.
+    Unknown(u8, Option<u8>),
+}
+
+impl DeviceType {
+    /// Decodes the id bytes, which may be empty.
+    pub fn from_id(id: &[u8]) -> Self {
+        match id {
+            [] => Self::AtKeyboard,
+            [0x00] => Self::Mouse,
+            [0x03] => Self::ScrollMouse,
+            [0x04] => Self::FiveButtonMouse,
+            [0xAB, 0x83 | 0x41 | 0xC1] => Self::Mf2Keyboard,
+            [0xAB, 0x84 | 0x54] => Self::ShortKeyboard,
+            [0xAB, 0x85] => Self::HostConnected122Key,
+            [0xAB, 0x86] => Self::Keyboard122Key,
+            [0xAB, 0x90..=0x92] => Self::JapaneseKeyboard,
+            [0xAC, 0xA1] => Self::NcdSunKeyboard,
+            [first, rest @ ..] => Self::Unknown(*first, rest.first().copied()),
+        }
+    }
+
+    /// Whether the device is a keyboard.
+    pub fn is_keyboard(&self) -> bool {
+        !matches!(self, Self::Mouse | Self::ScrollMouse | Self::FiveButtonMouse | Self::Unknown(..))
+    }
+}
+
+/// Identifies the device, scanning is paused meanwhile.
+pub fn identify<I: Ps2Io>(io: &mut I) -> Result<DeviceType, Ps2Error> {
+    send_with_ack(io, CMD_DISABLE_SCANNING, 5)?;
+    let result = send_with_ack(io, CMD_IDENTIFY, 5).and_then(|()| {
+        let mut id = [0; 2];
+        let mut len = 0;
+        while len < id.len() {
+            match io.read_data() {
+                Ok(byte) => id[len] = byte,
+                // AT keyboards send nothing, and mice only one byte.
+                Err(Ps2Error::Timeout) => break,
+                Err(e) => return Err(e),
+            }
+            len += 1;
+        }
+        Ok(DeviceType::from_id(&id[..len]))
+    });
+    send_with_ack(io, CMD_ENABLE_SCANNING, 5)?;
+    result
+}
+
 
 /// Represents a Set 1 scancode sequence.
 /// Most keys are one byte, but extended keys use an E0 prefix.
@@ -0,0 +1,214 @@
+//! Software key repeat and debouncing.
+//! 
+//! The typematic repeats of the keyboard itself are dropped, instead, the timer interrupt queues a
+//! repeat of the held key after [`RepeatConfig::delay`], every [`RepeatConfig::interval`], which
+//! is typed by [`scancodes::process`](super::scancodes::process). This makes repeating independent
+//! of how the keyboard was configured.
+//! 
+//! A key pressed again shortly after it was released is treated as a bounce, and dropped.
+//! Modifiers and lock keys are never repeated nor debounced.
+//! 
+//! Everything is timed by [`time`](crate::time), so the resolution is that of the timer.
+use core::time::Duration;
+
+use pc_keyboard::{KeyCode, KeyEvent, KeyState};
+use spin::Mutex;
+
+/// Configures key repeat and debouncing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepeatConfig {
+    /// Whether keys are repeated by software. Otherwise, the keyboard's own repeats are used.
+    pub enabled: bool,
+    /// How long a key must be held, before it repeats.
+    pub delay: Duration,
+    /// Time between repeats.
+    pub interval: Duration,
+    /// A press within this time of the key's release is dropped. Zero disables debouncing.
+    pub debounce: Duration,
+}
+
+impl RepeatConfig {
+    /// The default config: 500ms delay, 30 repeats a second, and 10ms of debouncing.
+    pub const DEFAULT: Self = Self {
+        enabled: true,
+        delay: Duration::from_millis(500),
+        interval: Duration::from_micros(33_333),
+        debounce: Duration::from_millis(10),
+    };
+}
+
+impl Default for RepeatConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    config: RepeatConfig,
+    /// The key being held, and when it repeats next.
+    held: Option<(KeyCode, Duration)>,
+    /// The key released last, and when.
+    released: Option<(KeyCode, Duration)>,
+    /// The repeat queued by [`tick`], waiting to be typed.
+    due: Option<KeyCode>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State { config: RepeatConfig::DEFAULT, held: None, released: None, due: None });
+
+/// Sets the repeat config, stopping a current repeat.
+pub fn set_config(config: RepeatConfig) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut state = STATE.lock();
+        state.config = config;
+        state.held = None;
+        state.due = None;
+    })
+}
+
+/// Forgets the held and released keys.
+pub(super) fn reset() {
+    let mut state = STATE.lock();
+    state.held = None;
+    state.released = None;
+    state.due = None;
+}
+
+/// The current repeat config.
+pub fn config() -> RepeatConfig {
+    x86_64::instructions::interrupts::without_interrupts(|| STATE.lock().config)
+}
+
+/// Returns whether `code` is a modifier or lock key.
+fn is_modifier(code: KeyCode) -> bool {
+    matches!(code,
+        KeyCode::LShift | KeyCode::RShift |
+        KeyCode::LControl | KeyCode::RControl |
+        KeyCode::LAlt | KeyCode::RAltGr |
+        KeyCode::LWin | KeyCode::RWin |
+        KeyCode::CapsLock | KeyCode::NumpadLock | KeyCode::ScrollLock
+    )
+}
+
+/// Filters a decoded key event, returning `None` if it is a bounce or a typematic repeat, or the
+/// event to pass on.
+/// 
+/// Called with interrupts disabled.
+pub(super) fn filter(event: &KeyEvent) -> Option<KeyEvent> {
+    if is_modifier(event.code) {
+        return Some(event.clone());
+    }
+    let now = crate::time::now();
+    let mut state = STATE.lock();
+    match event.state {
+        KeyState::Down => {
+            if let Some((code, released)) = state.released {
+                if code == event.code && now.saturating_sub(released) < state.config.debounce {
+                    return None;
+                }
+            }
+            if state.config.enabled {
+                if matches!(state.held, Some((code, _)) if code == event.code) {
+                    return None;
+                }
+                state.held = Some((event.code, now + state.config.delay));
+            }
+        }
+        KeyState::Up => {
+            if matches!(state.held, Some((code, _)) if code == event.code) {
+                state.held = None;
+                state.due = None;
+            }
+            state.released = Some((event.code, now));
+        }
+        KeyState::SingleShot => {}
+    }
+    Some(event.clone())
+}
+
+/// Queues a repeat of the held key, if it is due.
+/// 
+/// Called by the timer interrupt, the repeat is typed by [`type_due`].
+pub fn tick() {
+    {
+        let Some(mut state) = STATE.try_lock() else { return };
+        let Some((code, next)) = state.held else { return };
+        let now = crate::time::now();
+        if !state.config.enabled || now < next {
+            return;
+        }
+        // catch up on missed repeats, instead of sending them all at once.
+        let mut next = next + state.config.interval;
+        if next <= now {
+            next = now + state.config.interval;
+        }
+        state.held = Some((code, next));
+        // a repeat which was not typed yet is not queued twice.
+        state.due = Some(code);
+    }
+    super::scancodes::wake();
+}
+
+/// Whether a repeat is queued.
+/// 
+/// Called with interrupts disabled.
+pub(super) fn is_due() -> bool {
+    STATE.lock().due.is_some()
+}
+
+/// Types the queued repeat, if there is one.
+/// 
+/// Called by [`scancodes::process`](super::scancodes::process), never in an interrupt handler.
+pub(super) fn type_due() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let Some(code) = STATE.lock().due.take() else { return };
+        super::handle_event(&mut super::KEYBOARD.lock(), KeyEvent::new(code, KeyState::Down));
+    })
+}
+
+/// Tests repeating and debouncing, with a [`TestClock`](crate::time::TestClock)
+#[cfg(feature = "test")]
+pub fn test_key_repeat(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::{test::test_assert_eq, time::TestClock};
+    use super::replay::{replay, take_line};
+
+    const A_MAKE: u8 = 0x1E;
+    const A_BREAK: u8 = 0x9E;
+
+    let clock = TestClock::install();
+    let before = config();
+    set_config(RepeatConfig::DEFAULT);
+    take_line();
+
+    // hold `a`, the keyboard's own repeat is dropped.
+    replay(&[A_MAKE, A_MAKE]);
+    clock.advance(499);
+    tick();
+    type_due();
+    test_assert_eq!(take_line().as_str(), "a")?;
+    clock.advance(1);
+    tick();
+    type_due();
+    clock.advance(34);
+    tick();
+    type_due();
+    replay(&[A_BREAK]);
+    clock.advance(1000);
+    tick();
+    type_due();
+    test_assert_eq!(take_line().as_str(), "aa")?;
+
+    // a bounce, then a real press.
+    replay(&[A_MAKE]);
+    clock.advance(100);
+    replay(&[A_BREAK]);
+    clock.advance(5);
+    replay(&[A_MAKE, A_BREAK]);
+    clock.advance(100);
+    replay(&[A_MAKE, A_BREAK]);
+    let typed = take_line();
+
+    set_config(before);
+    drop(clock);
+    test_assert_eq!(typed.as_str(), "aa")
+}
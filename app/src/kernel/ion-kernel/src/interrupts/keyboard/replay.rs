@@ -0,0 +1,102 @@
+//! Replaying scancodes, and recording live input.
+//! 
+//! Replayed scancodes go through the same decoding and echoing as typed ones, so the line editor
+//! can be driven by tests, or by a recorded demo script.
+use spin::Mutex;
+
+use crate::collections::ArrayVec;
+
+/// Maximum amount of scancodes a recording can hold, later ones are dropped.
+pub const RECORD_CAPACITY: usize = 1024;
+
+/// Scancodes recorded from the keyboard.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    /// The recorded scancodes.
+    pub scancodes: ArrayVec<u8, RECORD_CAPACITY>,
+    /// Whether scancodes were dropped, because the recording was full.
+    pub overflowed: bool,
+}
+
+static RECORDER: Mutex<Option<Recording>> = Mutex::new(None);
+
+/// Feeds `scancodes` to the keyboard, as if they were typed.
+pub fn replay(scancodes: &[u8]) {
+    for &scancode in scancodes {
+        // the same as the interrupt handler, so typed keys are not decoded in between.
+        x86_64::instructions::interrupts::without_interrupts(|| super::handle_scancode(scancode));
+    }
+}
+
+/// Takes what was typed into the input line, clearing it.
+#[cfg(feature = "test")]
+pub fn take_line() -> crate::collections::ArrayString<{ crate::text::BUFFER_WIDTH }> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut input = crate::text::INPUT_LINE.lock();
+        let line = input.current_line();
+        input.delete_row();
+        line
+    })
+}
+
+/// Starts recording scancodes from the keyboard, discarding a previous recording.
+/// 
+/// Replayed scancodes are not recorded.
+pub fn start_recording() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        *RECORDER.lock() = Some(Recording { scancodes: ArrayVec::new(), overflowed: false });
+    })
+}
+
+/// Stops recording, returning what was recorded, or `None` if nothing was being recorded.
+pub fn stop_recording() -> Option<Recording> {
+    x86_64::instructions::interrupts::without_interrupts(|| RECORDER.lock().take())
+}
+
+/// Whether scancodes are being recorded.
+pub fn is_recording() -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| RECORDER.lock().is_some())
+}
+
+/// Records a scancode, if recording.
+/// 
+/// Called by the interrupt handler.
+pub(super) fn record(scancode: u8) {
+    if let Some(recording) = RECORDER.lock().as_mut() {
+        if recording.scancodes.push(scancode).is_err() {
+            recording.overflowed = true;
+        }
+    }
+}
+
+/// Tests replaying scancodes through the input line.
+#[cfg(feature = "test")]
+pub fn test_replay(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use x86_64::instructions::interrupts::without_interrupts;
+
+    use crate::{test::{test_assert, test_assert_eq}, text::INPUT_LINE};
+
+    let clear = || without_interrupts(|| INPUT_LINE.lock().delete_row());
+    clear();
+
+    // h, i, backspace, o (Set 1, make and break)
+    replay(&[0x23, 0xA3, 0x17, 0x97, 0x0E, 0x8E, 0x18, 0x98]);
+    let typed = without_interrupts(|| INPUT_LINE.lock().current_line());
+    clear();
+    test_assert_eq!(typed.as_str(), "ho")?;
+
+    // the keyboard is replugged while right shift is held, so it is released.
+    replay(&[0x36, 0xAA, 0x23, 0xA3]);
+    let typed = without_interrupts(|| INPUT_LINE.lock().current_line());
+    clear();
+    test_assert_eq!(typed.as_str(), "h")?;
+
+    // nothing is typed during the test, so the recording stays empty.
+    start_recording();
+    replay(&[0x23, 0xA3]);
+    test_assert!(is_recording())?;
+    let recording = stop_recording();
+    clear();
+    test_assert!(matches!(recording, Some(r) if r.scancodes.is_empty()))?;
+    test_assert!(stop_recording().is_none())
+}
@@ -0,0 +1,143 @@
+//! The scancodes read by the interrupt handler, waiting to be decoded.
+//! 
+//! The handler only queues the scancode and wakes the [stream](ScancodeStream), the decoding and
+//! echoing is done by [`process`], on the [executor](crate::task::executor), with interrupts
+//! enabled in between scancodes. Key [repeats](super::repeat) queued by the timer interrupt are
+//! typed by [`process`] as well.
+use core::{future::{Future, poll_fn}, pin::Pin, sync::atomic::{AtomicBool, AtomicU64, Ordering}, task::{Context, Poll, Waker}};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{collections::RingBuffer, log::warn};
+
+/// How many scancodes are queued, later ones are dropped.
+pub const QUEUE_LEN: usize = 128;
+
+static QUEUE: Mutex<RingBuffer<u8, QUEUE_LEN>> = Mutex::new(RingBuffer::new());
+/// Woken when a scancode is queued, only replaced outside of the handler.
+static WAKER: Mutex<Option<Waker>> = Mutex::new(None);
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+/// Whether the stream was taken.
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Queues a scancode, and wakes the stream.
+/// 
+/// Called by the interrupt handler.
+pub(super) fn push(scancode: u8) {
+    if QUEUE.lock().push(scancode).is_err() {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+    wake();
+}
+
+/// Wakes the stream, without queueing a scancode.
+/// 
+/// Called by interrupt handlers.
+pub(super) fn wake() {
+    if let Some(waker) = WAKER.lock().as_ref() {
+        waker.wake_by_ref();
+    }
+}
+
+/// How many scancodes were dropped, because the queue was full.
+pub fn dropped() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// The scancodes typed on the keyboard, there is only one stream.
+#[derive(Debug)]
+pub struct ScancodeStream(());
+
+impl ScancodeStream {
+    /// Takes the stream, `None` if it was already taken.
+    pub fn take() -> Option<Self> {
+        (!TAKEN.swap(true, Ordering::Relaxed)).then_some(Self(()))
+    }
+
+    /// Polls for the next scancode.
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<u8> {
+        without_interrupts(|| {
+            if let Some(scancode) = QUEUE.lock().pop() {
+                return Poll::Ready(scancode);
+            }
+            // still with interrupts disabled, so a scancode is not queued before the waker is set.
+            let mut waker = WAKER.lock();
+            if !waker.as_ref().is_some_and(|waker| waker.will_wake(cx.waker())) {
+                *waker = Some(cx.waker().clone());
+            }
+            Poll::Pending
+        })
+    }
+
+    /// The next scancode.
+    // a future like the `next` of a stream, it is no iterator.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Next<'_> {
+        Next(self)
+    }
+}
+
+impl Drop for ScancodeStream {
+    fn drop(&mut self) {
+        let waker = without_interrupts(|| WAKER.lock().take());
+        drop(waker);
+        TAKEN.store(false, Ordering::Relaxed);
+    }
+}
+
+/// The future returned by [`ScancodeStream::next`]
+#[derive(Debug)]
+#[must_use = "futures do nothing unless awaited"]
+pub struct Next<'a>(&'a mut ScancodeStream);
+
+impl Future for Next<'_> {
+    type Output = u8;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u8> {
+        self.0.poll_next(cx)
+    }
+}
+
+/// Decodes and echoes the queued scancodes, and types the queued key repeats, forever.
+/// 
+/// Spawned on the executor at boot.
+pub async fn process() {
+    let Some(mut scancodes) = ScancodeStream::take() else {
+        warn!("The scancodes are already processed");
+        return;
+    };
+    loop {
+        // checked with interrupts disabled, so a repeat is not queued before the waker is set.
+        let scancode = poll_fn(|cx| without_interrupts(|| {
+            if super::repeat::is_due() {
+                return Poll::Ready(None);
+            }
+            scancodes.poll_next(cx).map(Some)
+        })).await;
+        match scancode {
+            // like a replay, so the keyboard state is not changed by the handler in between.
+            Some(scancode) => without_interrupts(|| super::handle_scancode(scancode)),
+            None => super::repeat::type_due(),
+        }
+    }
+}
+
+/// Tests that queued scancodes are decoded.
+#[cfg(feature = "test")]
+pub fn test_scancode_queue(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use core::time::Duration;
+
+    use crate::{test::test_assert_eq, text::INPUT_LINE};
+
+    let clear = || without_interrupts(|| INPUT_LINE.lock().delete_row());
+    clear();
+    // h (Set 1, make and break), as if typed.
+    without_interrupts(|| [0x23, 0xA3].into_iter().for_each(push));
+    let typed = crate::time::poll_until(Duration::from_secs(1), || {
+        let line = without_interrupts(|| INPUT_LINE.lock().current_line());
+        (!line.is_empty()).then_some(line)
+    });
+    clear();
+    test_assert_eq!(typed.as_deref(), Some("h"))
+}
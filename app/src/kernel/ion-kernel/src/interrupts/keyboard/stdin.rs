@@ -0,0 +1,163 @@
+//! Lines typed on the keyboard, for kernel code to read.
+//! 
+//! Lines are edited on the [input line](crate::text::INPUT_LINE), and queued when enter finishes
+//! them, so readers never see backspaces. [`Stdin`] reads them through [`io::Read`] and
+//! [`io::BufRead`], or [`read_line`] reads a single one:
+//! 
+//! ```rust,no_run
+//! let name = keyboard::read_line()?;
+//! println!("hello, {}", name.trim_end());
+//! ```
+//! 
+//! Reading blocks until a line is typed, yielding to the other tasks. The keys are decoded on the
+//! [executor](crate::task::executor), so futures on it, and interrupt handlers, must not read.
+use alloc::string::String;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{collections::RingBuffer, io::{self, BufRead}, task};
+
+/// How many bytes of finished lines are queued, later ones are dropped.
+pub const QUEUE_LEN: usize = 512;
+
+/// How many bytes a [`Stdin`] takes from the queue at once.
+const BUFFER_LEN: usize = 64;
+
+static QUEUE: Mutex<RingBuffer<u8, QUEUE_LEN>> = Mutex::new(RingBuffer::new());
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Queues a finished line, and its `\n`
+/// 
+/// A line which does not fit is dropped whole, so readers never see half a line.
+pub(super) fn push_line(line: &str) {
+    without_interrupts(|| {
+        let mut queue = QUEUE.lock();
+        if queue.capacity() - queue.len() <= line.len() {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        // there is room for the line, checked above.
+        line.bytes().chain(*b"\n").for_each(|byte| { let _ = queue.push(byte); });
+    })
+}
+
+/// How many lines were dropped, because the queue was full.
+pub fn dropped() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// Reads the lines typed on the keyboard.
+#[derive(Debug, Clone)]
+pub struct Stdin {
+    buffer: [u8; BUFFER_LEN],
+    start: usize,
+    end: usize,
+}
+
+impl Stdin {
+    /// Creates a new [`Stdin`]
+    /// 
+    /// Every [`Stdin`] takes from the same queue, bytes buffered by one are not seen by the others.
+    pub const fn new() -> Self {
+        Self { buffer: [0; BUFFER_LEN], start: 0, end: 0 }
+    }
+
+    /// Takes bytes from the queue into the empty buffer, waiting until there are some.
+    /// 
+    /// Stops after a `\n`, so a dropped [`Stdin`] loses at most the rest of the line it was reading.
+    fn refill(&mut self) {
+        loop {
+            let taken = without_interrupts(|| {
+                let mut queue = QUEUE.lock();
+                let mut taken = 0;
+                while taken < BUFFER_LEN {
+                    let Some(byte) = queue.pop() else { break };
+                    self.buffer[taken] = byte;
+                    taken += 1;
+                    if byte == b'\n' {
+                        break;
+                    }
+                }
+                taken
+            });
+            if taken > 0 {
+                (self.start, self.end) = (0, taken);
+                return;
+            }
+            task::yield_now();
+        }
+    }
+}
+
+impl Default for Stdin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl io::Read for Stdin {
+    /// Reads the buffered bytes, waiting for a line if there are none.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl io::BufRead for Stdin {
+    /// The keyboard never ends, this waits for a line if nothing is buffered.
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.start == self.end {
+            self.refill();
+        }
+        Ok(&self.buffer[self.start..self.end])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.start = (self.start + amount).min(self.end);
+    }
+}
+
+/// Waits for a line to be typed, and returns it, including its `\n`
+/// # Errors
+/// Returns [`io::ErrorKind::InvalidData`] if the line is not UTF-8, which typed lines always are.
+pub fn read_line() -> io::Result<String> {
+    let mut line = String::new();
+    Stdin::new().read_line(&mut line)?;
+    Ok(line)
+}
+
+/// Tests reading typed lines, and completing them with Tab.
+#[cfg(feature = "test")]
+pub fn test_stdin(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use io::Read;
+
+    use crate::test::test_assert_eq;
+
+    // lines typed by other tests.
+    without_interrupts(|| QUEUE.lock().clear());
+    super::type_str("first\nsec");
+    // not finished, so not queued.
+    test_assert_eq!(without_interrupts(|| QUEUE.lock().len()), 6)?;
+    super::type_str("\x08cond\n");
+    test_assert_eq!(read_line().as_deref(), Ok("first\n"))?;
+
+    let mut stdin = Stdin::new();
+    let mut buf = [0; 3];
+    stdin.read_exact(&mut buf).map_err(|_| "the line was not read")?;
+    test_assert_eq!(&buf, b"sec")?;
+    let mut rest = String::new();
+    stdin.read_line(&mut rest).map_err(|_| "the line was not read")?;
+    test_assert_eq!(rest.as_str(), "ond\n")?;
+
+    // completes the command name, `uptime` is the only one starting with `upt`
+    super::type_str("upt\t\n");
+    test_assert_eq!(read_line().as_deref(), Ok("uptime\n"))
+}
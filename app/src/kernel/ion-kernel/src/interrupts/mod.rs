@@ -13,16 +13,29 @@ lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
         idt.breakpoint.set_handler_fn(breakpoint_handler);
-        idt.page_fault.set_handler_fn(page_fault::page_fault);
+        idt.divide_error.set_handler_fn(exceptions::divide_error);
+        idt.invalid_opcode.set_handler_fn(exceptions::invalid_opcode);
+        idt.segment_not_present.set_handler_fn(exceptions::segment_not_present);
+        idt.stack_segment_fault.set_handler_fn(exceptions::stack_segment_fault);
+        idt.general_protection_fault.set_handler_fn(exceptions::general_protection_fault);
+        idt.x87_floating_point.set_handler_fn(exceptions::x87_floating_point);
+        idt.alignment_check.set_handler_fn(exceptions::alignment_check);
+        idt.simd_floating_point.set_handler_fn(exceptions::simd_floating_point);
         unsafe {
             idt.double_fault.set_handler_fn(double_fault::double_fault)
                 .set_stack_index(double_fault::DOUBLE_FAULT_IST_INDEX);
+            idt.page_fault.set_handler_fn(page_fault::page_fault)
+                .set_stack_index(page_fault::PAGE_FAULT_IST_INDEX);
         }
+        idt[apic::SPURIOUS_VECTOR].set_handler_fn(apic::spurious);
         // Hardware Interrupts.
         set_index!(
             idt,
             Timer => pic8259::handlers::timer,
-            Keyboard => keyboard::keyboard_interrupt_handler
+            Keyboard => keyboard::keyboard_interrupt_handler,
+            Com2 => crate::serial::tx::com2_interrupt_handler,
+            Com1 => crate::serial::tx::com1_interrupt_handler,
+            Mouse => crate::drivers::ps2::mouse::mouse_interrupt_handler
         );
 
         idt
@@ -38,6 +51,30 @@ pub fn init_interrupt_operations() {
     serial_println!("Initialized IDT properly");
 }
 
+/// Unmasks the ISA interrupt `irq` (0-15), on the IOAPIC once the [APIC](apic) replaced the PICs.
+pub fn unmask(irq: u8) {
+    if apic::is_active() {
+        apic::unmask(irq);
+    } else {
+        pic8259::unmask(irq);
+    }
+}
+
+/// Signals the end of the interrupt `index` to the PICs, or to the [APIC](apic) once it replaced
+/// them.
+/// # Safety
+/// Must only be called at the end of the handler of `index`
+pub unsafe fn end_of_interrupt(index: InterruptIndex) {
+    // Safety: forwarded from the caller.
+    unsafe {
+        if apic::is_active() {
+            apic::end_of_interrupt();
+        } else {
+            pic8259::PICS.lock().notify_end_of_interrupt(index.as_u8());
+        }
+    }
+}
+
 extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
     println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
 }
@@ -59,7 +96,16 @@ pub mod test {
 pub mod gdt;
 /// PIC 8259 Compatibility.
 pub mod pic8259;
+/// The local APIC and IOAPIC, which replace the PICs.
+pub mod apic;
 /// Keyboard Interrupt Handling.
 pub mod keyboard;
+/// Interrupt counts.
+pub mod stats;
+/// Whether code runs in an interrupt handler.
+pub mod context;
 mod double_fault;
-mod page_fault;
\ No newline at end of file
+/// The page fault handler, and demand paging.
+pub mod page_fault;
+/// The handlers of the other CPU exceptions.
+pub mod exceptions;
\ No newline at end of file
@@ -1,13 +1,185 @@
-use x86_64::structures::idt::{InterruptStackFrame, PageFaultErrorCode};
-use x86_64::registers::control::Cr2;
+//! The page fault handler, and demand paged regions.
+//! 
+//! A fault on a page which is not present, in a region [registered](register) as demand paged, is
+//! passed to the region's function, which maps the page. The faulting instruction then runs again.
+//! 
+//! Any other fault is reported on serial, with the error code and the stack frame, then panics with
+//! the faulting address, the access, and the instruction pointer, so the panic screen shows them.
+//! A fault of a driver names it, see [`isolation`](crate::drivers::isolation).
+//! 
+//! The handler runs on its own [interrupt stack](PAGE_FAULT_IST_INDEX), so a fault on the guard page
+//! of an overflowing [kernel stack](crate::mem::stacks) is reported as a kernel stack overflow,
+//! instead of becoming a double fault. A fault inside the handler would reuse that stack, so
+//! neither the handler nor a [`DemandFn`] may fault.
+use core::{fmt::{self, Display, Write}, ops::Range};
 
-use crate::println;
+use spin::Mutex;
+use x86_64::{VirtAddr, registers::control::Cr2, structures::idt::{InterruptStackFrame, PageFaultErrorCode}};
+
+use crate::{collections::{ArrayVec, CapacityError}, panic::screen::RawSerial};
+
+/// Index of the page fault stack in the IST.
+pub const PAGE_FAULT_IST_INDEX: u16 = 1;
+
+/// Maximum amount of demand paged regions.
+pub const MAX_REGIONS: usize = 8;
+
+/// Maps the page containing a faulting address, in a demand paged region.
+/// 
+/// It runs in the page fault handler, so it must not fault on the same page, or allocate on the
+/// heap, which may be locked by the faulting code.
+pub type DemandFn = fn(VirtAddr) -> Result<(), &'static str>;
+
+#[derive(Debug, Clone)]
+struct DemandRegion {
+    range: Range<u64>,
+    name: &'static str,
+    map: DemandFn,
+}
+
+static REGIONS: Mutex<ArrayVec<DemandRegion, MAX_REGIONS>> = Mutex::new(ArrayVec::new());
+
+/// Registers the virtual `range` as demand paged, faults on its pages which are not present are
+/// passed to `map`
+/// # Errors
+/// Returns an error if there are [`MAX_REGIONS`] already.
+pub fn register(range: Range<u64>, name: &'static str, map: DemandFn) -> Result<(), CapacityError> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        REGIONS.lock().push(DemandRegion { range, name, map }).map_err(|_| CapacityError(()))
+    })
+}
+
+/// Unregisters the region named `name`, returns wether it was registered.
+pub fn unregister(name: &'static str) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut regions = REGIONS.lock();
+        let index = regions.iter().position(|r| r.name == name);
+        index.map(|i| regions.remove(i)).is_some()
+    })
+}
+
+/// The access which faulted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Reading data.
+    Read,
+    /// Writing data.
+    Write,
+    /// Fetching an instruction.
+    Execute,
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Execute => "instruction fetch",
+        })
+    }
+}
+
+/// A decoded page fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    /// The address which was accessed, from `cr2`
+    pub addr: u64,
+    /// The address of the faulting instruction.
+    pub ip: VirtAddr,
+    /// The error code pushed by the CPU.
+    pub error: PageFaultErrorCode,
+}
+
+impl Fault {
+    /// The access which faulted.
+    pub fn access(&self) -> Access {
+        if self.error.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+            Access::Execute
+        } else if self.error.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+            Access::Write
+        } else {
+            Access::Read
+        }
+    }
+
+    /// Whether the page was present, so the access was not allowed, instead of not mapped.
+    pub fn is_protection_violation(&self) -> bool {
+        self.error.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
+    }
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let page = if self.is_protection_violation() { "protected" } else { "not present" };
+        let mode = if self.error.contains(PageFaultErrorCode::USER_MODE) { "user" } else { "kernel" };
+        write!(f, "page fault at {:#x}: {} of a {page} page, by {mode} code at {:#x}", self.addr, self.access(), self.ip)?;
+        if self.error.contains(PageFaultErrorCode::MALFORMED_TABLE) {
+            write!(f, " (a page table has a reserved bit set)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Maps the page of `fault` if it is in a demand paged region, `None` if it is not in one.
+fn demand(fault: &Fault) -> Option<Result<(), (&'static str, &'static str)>> {
+    if fault.is_protection_violation() {
+        return None;
+    }
+    // the faulting code may be registering a region.
+    let region = REGIONS.try_lock()?.iter().find(|r| r.range.contains(&fault.addr)).cloned()?;
+    Some((region.map)(VirtAddr::new_truncate(fault.addr)).map_err(|e| (region.name, e)))
+}
 
 pub(super) extern "x86-interrupt" fn page_fault(
     frame: InterruptStackFrame,
     error: PageFaultErrorCode,
 ) {
-    let addr = Cr2::read();
-    println!("Page Fault @ {:?} ec={:?}\n{:#?}", addr, error, frame);
-    loop { x86_64::instructions::hlt(); }
-}
\ No newline at end of file
+    let fault = Fault { addr: Cr2::read_raw(), ip: frame.instruction_pointer, error };
+    let demand = demand(&fault);
+    if let Some(Ok(())) = demand {
+        return;
+    }
+
+    // the screen is drawn by the panic, serial gets the details.
+    let mut serial = RawSerial;
+    let _ = writeln!(serial, "\nEXCEPTION: {fault}");
+    let _ = writeln!(serial, "error code: {error:?}\n{frame:#?}");
+    // a fault of a driver is caught by `drivers::isolation::run`, which disables the driver.
+    if let Some(slot) = crate::mem::stacks::guard_of(fault.addr) {
+        panic!("kernel stack overflow: the task on stack {slot} overflowed it, {fault}");
+    }
+    match (demand, crate::drivers::isolation::fault_owner(fault.addr)) {
+        (Some(Err((region, e))), _) => panic!("{fault}, in the demand paged region `{region}`, which failed: {e}"),
+        (_, Some(driver)) => panic!("{fault}, in the driver `{driver}`"),
+        _ => panic!("{fault}"),
+    }
+}
+
+/// Tests reporting faults, and passing them to demand paged regions.
+#[cfg(feature = "test")]
+pub fn test_page_fault(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::{panic::catch::catch, test::{test_assert, test_assert_eq}};
+
+    const UNMAPPED: u64 = 0x5555_0000_0000;
+    const DEMAND: u64 = 0x5556_0000_0000;
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    // Safety: the address is not mapped, the fault is caught.
+    let caught = catch(|| unsafe { core::ptr::read_volatile(UNMAPPED as *const u8) });
+    let message = caught.err().ok_or("the read did not fault")?.message;
+    test_assert!(message.starts_with("page fault at 0x555500000000: read of a not present page, by kernel code"))?;
+
+    register(DEMAND..DEMAND + 0x1000, "test-demand", |addr| {
+        CALLS.fetch_add(1, Ordering::Relaxed);
+        if addr.as_u64() == DEMAND + 8 { Err("not backed") } else { Ok(()) }
+    }).map_err(|_| "the region was not registered")?;
+    // Safety: as above.
+    let caught = catch(|| unsafe { core::ptr::write_volatile((DEMAND + 8) as *mut u8, 1) });
+    test_assert!(unregister("test-demand"))?;
+    let message = caught.err().ok_or("the write did not fault")?.message;
+    test_assert_eq!(CALLS.load(Ordering::Relaxed), 1)?;
+    test_assert!(message.contains("write of a not present page") && message.ends_with("`test-demand`, which failed: not backed"))?;
+    test_assert!(!unregister("test-demand"))
+}
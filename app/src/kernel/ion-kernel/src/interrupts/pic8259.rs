@@ -1,5 +1,6 @@
 use pic8259::ChainedPics;
 use spin;
+use x86_64::instructions::port::Port;
 
 /// 1st Offset use for [`PICS`]
 pub const PIC_1_OFFSET: u8 = 32;
@@ -18,11 +19,62 @@ pub fn init() {
     unsafe { PICS.lock().initialize() };
 }
 
+/// Unmasks an interrupt line (0-15), lines of the second PIC also unmask the cascade (2).
+pub fn unmask(irq: u8) {
+    let mut master: Port<u8> = Port::new(0x21);
+    let mut slave: Port<u8> = Port::new(0xA1);
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _pics = PICS.lock();
+        // Safety: only clears mask bits, the PICs were initialized.
+        unsafe {
+            if irq < 8 {
+                let mask = master.read();
+                master.write(mask & !(1 << irq));
+            } else {
+                let mask = master.read();
+                master.write(mask & !(1 << 2));
+                let mask = slave.read();
+                slave.write(mask & !(1 << (irq - 8)));
+            }
+        }
+    })
+}
+
+/// The interrupt masks of both PICs, the second one in the upper byte.
+pub fn masks() -> u16 {
+    let mut master: Port<u8> = Port::new(0x21);
+    let mut slave: Port<u8> = Port::new(0xA1);
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _pics = PICS.lock();
+        // Safety: reading the masks has no side effects.
+        unsafe { u16::from_le_bytes([master.read(), slave.read()]) }
+    })
+}
+
+/// Sets the interrupt masks of both PICs, see [`masks`]. `0xFFFF` masks every line, when the
+/// [APIC](super::apic) replaces the PICs.
+pub fn set_masks(masks: u16) {
+    let mut master: Port<u8> = Port::new(0x21);
+    let mut slave: Port<u8> = Port::new(0xA1);
+    let [low, high] = masks.to_le_bytes();
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _pics = PICS.lock();
+        // Safety: the PICs were initialized, only their masks change.
+        unsafe {
+            master.write(low);
+            slave.write(high);
+        }
+    })
+}
+
 /// Index for Hardware Interrupts.
 /// 
 /// List
 /// - Timer: 32
 /// - Keyboard: 33
+/// - Com2: 35
+/// - Com1: 36
+/// - Mouse: 44
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum InterruptIndex {
@@ -36,6 +88,20 @@ pub enum InterruptIndex {
     /// 
     /// Equivalent to [`PIC_1_OFFSET`] + 1
     Keyboard,
+    /// Index for a COM2 (and COM4) Interrupt.
+    /// 
+    /// Raised when the UART can take more output, see [`tx`](crate::serial::tx).
+    /// 
+    /// Equivalent to [`PIC_1_OFFSET`] + 3
+    Com2 = PIC_1_OFFSET + 3,
+    /// Index for a COM1 (and COM3) Interrupt.
+    /// 
+    /// Equivalent to [`PIC_1_OFFSET`] + 4
+    Com1,
+    /// Index for a PS/2 Mouse Interrupt.
+    /// 
+    /// Equivalent to [`PIC_2_OFFSET`] + 4
+    Mouse = PIC_2_OFFSET + 4,
 }
 
 impl InterruptIndex {
@@ -54,22 +120,39 @@ impl InterruptIndex {
 pub mod handlers {
     use x86_64::structures::idt::InterruptStackFrame;
 
-    /// Notifies that the interrupt handler has ended.
+    /// Notifies that the interrupt handler has ended, and counts the interrupt and the time since
+    /// the handler was entered, see [`stats`](crate::interrupts::stats).
+    /// 
+    /// The end of the interrupt goes to the PICs, or to the [APIC](crate::interrupts::apic) once it
+    /// replaced them.
     /// 
     /// Requires an explicit `unsafe` keyword.
     pub macro notify {
-        (unsafe $name:ident) => {
+        (unsafe $name:ident, $entry:expr) => {
+            crate::interrupts::stats::record(super::InterruptIndex::$name, $entry);
             unsafe {
-                super::PICS.lock()
-                    .notify_end_of_interrupt(super::InterruptIndex::$name.as_u8());
+                crate::interrupts::end_of_interrupt(super::InterruptIndex::$name);
             }
         }
     }
 
     /// Intel 8253 timer interrupt.
     /// 
-    /// simply notifies PIC that the interrupt was handled.
+    /// counts the tick, fires timers, repeats held keys, flushes pending VGA output, notifies PIC
+    /// that the interrupt was handled, and stops tests which take too long.
     pub extern "x86-interrupt" fn timer(_frame: InterruptStackFrame) {
-        notify!(unsafe Timer);
+        let context = crate::interrupts::context::enter();
+        let entry = crate::interrupts::stats::enter();
+        crate::time::tick();
+        crate::time::timer::run_expired();
+        crate::interrupts::keyboard::repeat::tick();
+        crate::text::flush();
+        notify!(unsafe Timer, entry);
+        // neither the watchdog nor the next task return here right away.
+        drop(context);
+        // may not return, if it stops a test.
+        #[cfg(feature = "test")]
+        crate::test::isolate::watchdog_tick();
+        crate::task::preempt();
     }
 }
\ No newline at end of file
@@ -0,0 +1,216 @@
+//! Counts and latencies of hardware interrupts, recorded by
+//! [`notify`](super::pic8259::handlers::notify).
+//! 
+//! Handlers take a timestamp when they are [entered](enter), so the time spent in each handler is
+//! known, as a mean and the worst case. Regions run with [`without_interrupts`] from this module
+//! are timed too, and the longest one is kept with its location, as it delays every interrupt.
+//! 
+//! Times are measured with the TSC, and only recorded once its frequency is known. There is no
+//! procfs yet, the `irqstat` shell [command] prints them.
+use core::{fmt::{self, Write}, panic::Location, sync::atomic::{AtomicU64, Ordering}, time::Duration};
+
+use spin::Mutex;
+
+use super::pic8259::InterruptIndex;
+use crate::{collections::ArrayString, time::clocksource::TSC};
+
+/// Every counted interrupt.
+pub const COUNTED: [InterruptIndex; 5] = [
+    InterruptIndex::Timer, InterruptIndex::Keyboard, InterruptIndex::Com2, InterruptIndex::Com1, InterruptIndex::Mouse,
+];
+
+static COUNTS: [AtomicU64; COUNTED.len()] = [const { AtomicU64::new(0) }; COUNTED.len()];
+/// Handled interrupts with a known time, and their total and longest time in nanoseconds.
+static TIMED: [AtomicU64; COUNTED.len()] = [const { AtomicU64::new(0) }; COUNTED.len()];
+static TOTAL_NANOS: [AtomicU64; COUNTED.len()] = [const { AtomicU64::new(0) }; COUNTED.len()];
+static MAX_NANOS: [AtomicU64; COUNTED.len()] = [const { AtomicU64::new(0) }; COUNTED.len()];
+/// The longest region with interrupts disabled, and where it started.
+static LONGEST_DISABLED: Mutex<Option<(Duration, &'static Location<'static>)>> = Mutex::new(None);
+
+fn slot(index: InterruptIndex) -> usize {
+    match index {
+        InterruptIndex::Timer => 0,
+        InterruptIndex::Keyboard => 1,
+        InterruptIndex::Com2 => 2,
+        InterruptIndex::Com1 => 3,
+        InterruptIndex::Mouse => 4,
+    }
+}
+
+/// When a handler was entered, see [`enter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry(u64);
+
+/// Timestamps the start of a handler, for [`notify`](super::pic8259::handlers::notify)
+#[inline(always)]
+pub fn enter() -> Entry {
+    // Safety: every x86_64 CPU has a TSC.
+    Entry(unsafe { core::arch::x86_64::_rdtsc() })
+}
+
+/// The time since `entry`, if the TSC frequency is known.
+fn since(entry: Entry) -> Option<Duration> {
+    let freq = TSC.frequency();
+    // Safety: see `enter`
+    let cycles = unsafe { core::arch::x86_64::_rdtsc() }.wrapping_sub(entry.0);
+    (freq != 0).then(|| Duration::from_nanos((u128::from(cycles) * 1_000_000_000 / u128::from(freq)) as u64))
+}
+
+/// Counts a handled interrupt, which was entered at `entry`
+pub(crate) fn record(index: InterruptIndex, entry: Entry) {
+    let slot = slot(index);
+    COUNTS[slot].fetch_add(1, Ordering::Relaxed);
+    if let Some(time) = since(entry) {
+        record_time(slot, time);
+    }
+}
+
+fn record_time(slot: usize, time: Duration) {
+    let nanos = time.as_nanos() as u64;
+    TIMED[slot].fetch_add(1, Ordering::Relaxed);
+    TOTAL_NANOS[slot].fetch_add(nanos, Ordering::Relaxed);
+    MAX_NANOS[slot].fetch_max(nanos, Ordering::Relaxed);
+}
+
+/// How often `index` was handled since boot.
+pub fn count(index: InterruptIndex) -> u64 {
+    COUNTS[slot(index)].load(Ordering::Relaxed)
+}
+
+/// The counts of every interrupt in [`COUNTED`], in the same order.
+pub fn counts() -> [u64; COUNTED.len()] {
+    COUNTED.map(count)
+}
+
+/// The time spent in the handler of an interrupt.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Latency {
+    /// The mean time.
+    pub mean: Duration,
+    /// The longest time.
+    pub max: Duration,
+}
+
+/// The time spent in the handler of `index`
+pub fn latency(index: InterruptIndex) -> Latency {
+    let slot = slot(index);
+    let timed = TIMED[slot].load(Ordering::Relaxed);
+    Latency {
+        mean: Duration::from_nanos(TOTAL_NANOS[slot].load(Ordering::Relaxed) / timed.max(1)),
+        max: Duration::from_nanos(MAX_NANOS[slot].load(Ordering::Relaxed)),
+    }
+}
+
+/// Runs `f` with interrupts disabled, like [`x86_64::instructions::interrupts::without_interrupts`],
+/// timing how long they were disabled.
+#[track_caller]
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    use x86_64::instructions::interrupts;
+
+    let location = Location::caller();
+    // only the outermost region is timed, it contains the others.
+    let outermost = interrupts::are_enabled();
+    interrupts::without_interrupts(|| {
+        let entry = enter();
+        let result = f();
+        if outermost {
+            if let Some(time) = since(entry) {
+                record_disabled(time, location);
+            }
+        }
+        result
+    })
+}
+
+fn record_disabled(time: Duration, location: &'static Location<'static>) {
+    if let Some(mut longest) = LONGEST_DISABLED.try_lock() {
+        if longest.is_none_or(|(max, _)| time > max) {
+            *longest = Some((time, location));
+        }
+    }
+}
+
+/// The longest region run with [`without_interrupts`], and where it started.
+pub fn longest_disabled() -> Option<(Duration, &'static Location<'static>)> {
+    x86_64::instructions::interrupts::without_interrupts(|| *LONGEST_DISABLED.lock())
+}
+
+/// Forgets the latencies, but not the counts, which the [monitor](crate::monitor) uses.
+pub fn reset() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        for slot in 0..COUNTED.len() {
+            TIMED[slot].store(0, Ordering::Relaxed);
+            TOTAL_NANOS[slot].store(0, Ordering::Relaxed);
+            MAX_NANOS[slot].store(0, Ordering::Relaxed);
+        }
+        *LONGEST_DISABLED.lock() = None;
+    });
+}
+
+/// Writes a table of the counts and latencies.
+pub fn write_report(out: &mut impl Write) -> fmt::Result {
+    if TSC.frequency() == 0 {
+        writeln!(out, "the TSC is not calibrated, latencies are not measured")?;
+    }
+    writeln!(out, "{:<10} {:>10} {:>12} {:>12}", "vector", "count", "mean", "max")?;
+    // padding is only applied to strings, not to the debug output of the values.
+    fn debug(value: impl fmt::Debug) -> ArrayString<24> {
+        let mut s = ArrayString::new();
+        let _ = write!(s, "{value:?}");
+        s
+    }
+    for index in COUNTED {
+        let latency = latency(index);
+        let (name, mean, max) = (debug(index), debug(latency.mean), debug(latency.max));
+        writeln!(out, "{:<10} {:>10} {:>12} {:>12}", name.as_str(), count(index), mean.as_str(), max.as_str())?;
+    }
+    match longest_disabled() {
+        Some((time, location)) => writeln!(out, "interrupts were disabled for up to {time:?}, at {location}"),
+        None => writeln!(out, "no timed region disabled interrupts"),
+    }
+}
+
+/// The `irqstat` shell command: `irqstat`, or `irqstat reset`
+pub fn command(args: &[&str]) -> i32 {
+    use crate::text::{print, println};
+
+    match args {
+        [_] => {
+            let mut report = alloc::string::String::new();
+            let _ = write_report(&mut report);
+            print!("{report}");
+            0
+        }
+        [_, "reset"] => {
+            reset();
+            0
+        }
+        _ => {
+            println!("usage: irqstat [reset]");
+            2
+        }
+    }
+}
+
+/// Tests recording latencies, and timing disabled regions.
+#[cfg(feature = "test")]
+pub fn test_latency(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    reset();
+    let mouse = slot(InterruptIndex::Mouse);
+    record_time(mouse, Duration::from_micros(3));
+    record_time(mouse, Duration::from_micros(5));
+    test_assert_eq!(latency(InterruptIndex::Mouse), Latency { mean: Duration::from_micros(4), max: Duration::from_micros(5) })?;
+
+    let line = line!() + 1;
+    without_interrupts(|| without_interrupts(|| crate::time::poll_until(Duration::from_millis(1), || None::<()>)));
+    test_assert!(x86_64::instructions::interrupts::are_enabled())?;
+    if TSC.frequency() != 0 {
+        let (time, location) = longest_disabled().ok_or("the region was not timed")?;
+        test_assert!(time >= Duration::from_millis(1))?;
+        test_assert_eq!(location.line(), line)?;
+    }
+    reset();
+    test_assert_eq!(latency(InterruptIndex::Mouse), Latency::default())
+}
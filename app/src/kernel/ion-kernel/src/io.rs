@@ -0,0 +1,397 @@
+//! Byte streams, like `std::io`, which is not available without `std`.
+//! 
+//! Only what kernel code needs so far: [`Read`], [`BufRead`] for reading lines, [`Write`] and
+//! [`Seek`]. They are implemented by the [keyboard](crate::interrupts::keyboard::Stdin), by
+//! [files](crate::fs::File), by byte slices and vectors, and by a [`Cursor`] over bytes in
+//! memory.
+//! 
+//! Vectored reads and writes take several buffers at once, as [`IoSliceMut`] and [`IoSlice`], which
+//! devices access through a [scatter-gather list](sg).
+use alloc::{string::String, vec::Vec};
+use core::{fmt, ops::{Deref, DerefMut}};
+
+pub mod sg;
+
+/// The kind of an [`Error`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The stream ended before the expected amount of bytes.
+    UnexpectedEof,
+    /// The bytes are not valid, E.g. a line which is not UTF-8.
+    InvalidData,
+    /// An argument is not valid, E.g. a relative path, or a seek before the start.
+    InvalidInput,
+    /// A write could not write any bytes.
+    WriteZero,
+    /// A file or directory does not exist.
+    NotFound,
+    /// A file or directory exists already.
+    AlreadyExists,
+    /// A directory was expected.
+    NotADirectory,
+    /// A file was expected, but it is a directory.
+    IsADirectory,
+    /// A directory which is not empty can not be removed.
+    DirectoryNotEmpty,
+    /// The filesystem can not be changed.
+    ReadOnlyFilesystem,
+    /// A device did not respond in time.
+    TimedOut,
+    /// The heap had no room for the memory the operation needed.
+    MemoryError,
+    /// Any other error.
+    Other,
+}
+
+/// An error of a stream, or of the [filesystem](crate::fs).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Error {
+    kind: ErrorKind,
+    message: &'static str,
+}
+
+impl Error {
+    /// Creates a new [`Error`]
+    pub const fn new(kind: ErrorKind, message: &'static str) -> Self {
+        Self { kind, message }
+    }
+
+    /// The kind of this error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl core::error::Error for Error {}
+
+impl From<core::alloc::AllocError> for Error {
+    fn from(_: core::alloc::AllocError) -> Self {
+        Self::new(ErrorKind::MemoryError, "the heap is out of memory")
+    }
+}
+
+impl From<alloc::collections::TryReserveError> for Error {
+    fn from(_: alloc::collections::TryReserveError) -> Self {
+        Self::new(ErrorKind::MemoryError, "the heap is out of memory")
+    }
+}
+
+/// The result of stream operations.
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// A buffer of a vectored write, see [`Write::write_vectored`]
+#[derive(Debug, Clone, Copy)]
+pub struct IoSlice<'a>(&'a [u8]);
+
+impl<'a> IoSlice<'a> {
+    /// Wraps `buf`
+    pub const fn new(buf: &'a [u8]) -> Self {
+        Self(buf)
+    }
+}
+
+impl Deref for IoSlice<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.0
+    }
+}
+
+/// A buffer of a vectored read, see [`Read::read_vectored`]
+#[derive(Debug)]
+pub struct IoSliceMut<'a>(&'a mut [u8]);
+
+impl<'a> IoSliceMut<'a> {
+    /// Wraps `buf`
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self(buf)
+    }
+}
+
+impl Deref for IoSliceMut<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.0
+    }
+}
+
+impl DerefMut for IoSliceMut<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.0
+    }
+}
+
+/// A source of bytes.
+pub trait Read {
+    /// Reads some bytes into `buf`, returning how many. `0` means the stream ended, or `buf` is
+    /// empty.
+    /// # Errors
+    /// Returns an error if the stream could not be read.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+    /// Reads some bytes into `bufs`, in order, returning how many. By default only the first
+    /// buffer which is not empty is read into.
+    /// # Errors
+    /// Returns the error of [`read`](Self::read)
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> Result<usize> {
+        match bufs.iter_mut().find(|buf| !buf.is_empty()) {
+            Some(buf) => self.read(buf),
+            None => Ok(0),
+        }
+    }
+
+    /// Reads exactly enough bytes to fill `buf`
+    /// # Errors
+    /// Returns [`ErrorKind::UnexpectedEof`] if the stream ends before, or the error of [`read`](Self::read)
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.read(buf)? {
+                0 => return Err(Error::new(ErrorKind::UnexpectedEof, "the stream ended early")),
+                n => buf = &mut buf[n..],
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A [`Read`]er with a buffer, which can read lines.
+pub trait BufRead: Read {
+    /// Returns the buffered bytes, reading more if the buffer is empty. An empty slice means the
+    /// stream ended.
+    /// # Errors
+    /// Returns an error if the stream could not be read.
+    fn fill_buf(&mut self) -> Result<&[u8]>;
+
+    /// Marks `amount` bytes of the buffer as read.
+    fn consume(&mut self, amount: usize);
+
+    /// Appends bytes to `buf` up to and including `byte`, or until the stream ends, returning how
+    /// many.
+    /// # Errors
+    /// Returns the error of [`fill_buf`](Self::fill_buf), the bytes read before are still appended.
+    fn read_until(&mut self, byte: u8, buf: &mut Vec<u8>) -> Result<usize> {
+        let mut read = 0;
+        loop {
+            let available = self.fill_buf()?;
+            if available.is_empty() {
+                return Ok(read);
+            }
+            let (found, used) = match available.iter().position(|&b| b == byte) {
+                Some(i) => (true, i + 1),
+                None => (false, available.len()),
+            };
+            buf.extend_from_slice(&available[..used]);
+            self.consume(used);
+            read += used;
+            if found {
+                return Ok(read);
+            }
+        }
+    }
+
+    /// Appends a line to `buf`, including its `\n`, returning how many bytes were read. `0` means
+    /// the stream ended.
+    /// # Errors
+    /// Returns [`ErrorKind::InvalidData`] if the line is not UTF-8, then nothing is appended.
+    fn read_line(&mut self, buf: &mut String) -> Result<usize> {
+        let mut line = Vec::new();
+        let read = self.read_until(b'\n', &mut line)?;
+        let line = core::str::from_utf8(&line).map_err(|_| Error::new(ErrorKind::InvalidData, "the line is not UTF-8"))?;
+        buf.push_str(line);
+        Ok(read)
+    }
+}
+
+/// A sink of bytes.
+pub trait Write {
+    /// Writes some bytes of `buf`, returning how many.
+    /// # Errors
+    /// Returns an error if the stream could not be written.
+    fn write(&mut self, buf: &[u8]) -> Result<usize>;
+
+    /// Writes some bytes of `bufs`, in order, returning how many. By default only the first buffer
+    /// which is not empty is written.
+    /// # Errors
+    /// Returns the error of [`write`](Self::write)
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize> {
+        match bufs.iter().find(|buf| !buf.is_empty()) {
+            Some(buf) => self.write(buf),
+            None => Ok(0),
+        }
+    }
+
+    /// Writes buffered bytes to their destination.
+    /// # Errors
+    /// Returns an error if the stream could not be written.
+    fn flush(&mut self) -> Result<()>;
+
+    /// Writes all of `buf`
+    /// # Errors
+    /// Returns [`ErrorKind::WriteZero`] if a write wrote nothing, or the error of [`write`](Self::write)
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf)? {
+                0 => return Err(Error::new(ErrorKind::WriteZero, "the stream took no bytes")),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A position to [`Seek`] to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    /// Bytes from the start.
+    Start(u64),
+    /// Bytes from the end, may be past it.
+    End(i64),
+    /// Bytes from the current position.
+    Current(i64),
+}
+
+/// A stream with a position, which can be moved.
+pub trait Seek {
+    /// Moves the position, returning it as bytes from the start.
+    /// # Errors
+    /// Returns [`ErrorKind::InvalidInput`] if the position would be before the start.
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64>;
+
+    /// The position, as bytes from the start.
+    /// # Errors
+    /// Returns the error of [`seek`](Self::seek)
+    fn stream_position(&mut self) -> Result<u64> {
+        self.seek(SeekFrom::Current(0))
+    }
+}
+
+/// Reads and seeks in bytes in memory, E.g. a disk image.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cursor<T> {
+    inner: T,
+    pos: u64,
+}
+
+impl<T> Cursor<T> {
+    /// A cursor at the start of `inner`
+    pub const fn new(inner: T) -> Self {
+        Self { inner, pos: 0 }
+    }
+
+    /// The position, as bytes from the start.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// The bytes.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the bytes.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: AsRef<[u8]>> Read for Cursor<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let bytes = self.inner.as_ref();
+        let mut rest = bytes.get(self.pos as usize..).unwrap_or_default();
+        let read = rest.read(buf)?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<T: AsRef<[u8]>> Seek for Cursor<T> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => (offset, 0),
+            SeekFrom::End(offset) => (self.inner.as_ref().len() as u64, offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        self.pos = base.checked_add_signed(offset)
+            .ok_or(Error::new(ErrorKind::InvalidInput, "the position would be before the start"))?;
+        Ok(self.pos)
+    }
+}
+
+impl Read for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = buf.len().min(self.len());
+        let (read, rest) = self.split_at(len);
+        buf[..len].copy_from_slice(read);
+        *self = rest;
+        Ok(len)
+    }
+}
+
+impl BufRead for &[u8] {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        Ok(*self)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        *self = &self[amount.min(self.len())..];
+    }
+}
+
+impl Write for Vec<u8> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize> {
+        let len = self.len();
+        bufs.iter().for_each(|buf| self.extend_from_slice(buf));
+        Ok(self.len() - len)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Tests reading bytes and lines from a slice.
+#[cfg(feature = "test")]
+pub fn test_io(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::test_assert_eq;
+
+    let mut input: &[u8] = b"first\nsecond\n\xFF\n";
+    let mut line = String::new();
+    test_assert_eq!(input.read_line(&mut line), Ok(6))?;
+    test_assert_eq!(input.read_line(&mut line), Ok(7))?;
+    test_assert_eq!(line.as_str(), "first\nsecond\n")?;
+    test_assert_eq!(input.read_line(&mut line).map_err(|e| e.kind()), Err(ErrorKind::InvalidData))?;
+    test_assert_eq!(input.read_line(&mut line), Ok(0))?;
+
+    let mut input: &[u8] = b"abc";
+    let mut buf = [0; 2];
+    test_assert_eq!(input.read_exact(&mut buf), Ok(()))?;
+    test_assert_eq!(&buf, b"ab")?;
+    test_assert_eq!(input.read_exact(&mut buf).map_err(|e| e.kind()), Err(ErrorKind::UnexpectedEof))?;
+
+    let mut output = Vec::new();
+    test_assert_eq!(output.write_all(b"ab"), Ok(()))?;
+    test_assert_eq!(output.write(b"c"), Ok(1))?;
+    test_assert_eq!(output.as_slice(), b"abc")?;
+    test_assert_eq!(output.write_vectored(&[IoSlice::new(b"d"), IoSlice::new(b""), IoSlice::new(b"e")]), Ok(2))?;
+    test_assert_eq!(output.as_slice(), b"abcde")?;
+    output.truncate(3);
+
+    let mut cursor = Cursor::new(output);
+    test_assert_eq!(cursor.seek(SeekFrom::End(-1)), Ok(2))?;
+    test_assert_eq!(cursor.read(&mut buf), Ok(1))?;
+    test_assert_eq!(cursor.read(&mut buf), Ok(0))?;
+    test_assert_eq!(cursor.seek(SeekFrom::Current(-4)).map_err(|e| e.kind()), Err(ErrorKind::InvalidInput))
+}
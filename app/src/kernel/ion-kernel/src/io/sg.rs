@@ -0,0 +1,167 @@
+//! Scatter-gather lists, which hand the buffers of a vectored read or write to a device.
+//! 
+//! An [`SgList`] is built from [`IoSlice`]s or [`IoSliceMut`]s, and borrows them as long as it
+//! lives. It holds the physical [segments](Segment) of the buffers, split where the pages are not
+//! contiguous and merged where they are, which a driver puts in its descriptors instead of copying
+//! through a [DMA buffer](crate::mem::dma).
+use core::{fmt::{self, Display}, marker::PhantomData};
+
+use x86_64::VirtAddr;
+
+use super::{IoSlice, IoSliceMut};
+use crate::{collections::ArrayVec, mem};
+
+/// Maximum amount of segments in a list.
+pub const MAX_SEGMENTS: usize = 32;
+const PAGE_SIZE: u64 = 4096;
+
+/// A physically contiguous part of a buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    /// The physical address.
+    pub phys: u64,
+    /// The length in bytes.
+    pub len: usize,
+}
+
+/// An error while building an [`SgList`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SgError {
+    /// A buffer is not mapped at this virtual address.
+    NotMapped(u64),
+    /// The buffers have more than [`MAX_SEGMENTS`] segments.
+    TooManySegments,
+}
+
+impl Display for SgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotMapped(addr) => write!(f, "the buffer at {addr:#x} is not mapped"),
+            Self::TooManySegments => write!(f, "the buffers have more than {MAX_SEGMENTS} segments"),
+        }
+    }
+}
+
+impl core::error::Error for SgError {}
+
+/// The physical segments of buffers, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct SgList<'a> {
+    segments: ArrayVec<Segment, MAX_SEGMENTS>,
+    _buffers: PhantomData<&'a [u8]>,
+}
+
+impl<'a> SgList<'a> {
+    /// The segments of `bufs`, which the device reads.
+    /// # Errors
+    /// Returns an error if a buffer is not mapped, or there are too many segments.
+    pub fn from_slices(bufs: &'a [IoSlice<'_>]) -> Result<Self, SgError> {
+        let mut list = Self { segments: ArrayVec::new(), _buffers: PhantomData };
+        for buf in bufs {
+            list.push_virt(buf.as_ptr() as u64, buf.len())?;
+        }
+        Ok(list)
+    }
+
+    /// The segments of `bufs`, which the device writes. They stay borrowed mutably, so the CPU
+    /// does not access them meanwhile.
+    /// # Errors
+    /// Returns an error if a buffer is not mapped, or there are too many segments.
+    pub fn from_slices_mut(bufs: &'a mut [IoSliceMut<'_>]) -> Result<Self, SgError> {
+        let mut list = Self { segments: ArrayVec::new(), _buffers: PhantomData };
+        for buf in bufs.iter() {
+            list.push_virt(buf.as_ptr() as u64, buf.len())?;
+        }
+        Ok(list)
+    }
+
+    /// Appends the segments of `len` bytes at the virtual `addr`, page by page.
+    fn push_virt(&mut self, addr: u64, len: usize) -> Result<(), SgError> {
+        let end = addr + len as u64;
+        let mut addr = addr;
+        while addr < end {
+            let page_end = (addr / PAGE_SIZE + 1) * PAGE_SIZE;
+            let chunk = page_end.min(end) - addr;
+            let phys = mem::translate_addr(VirtAddr::new(addr)).ok_or(SgError::NotMapped(addr))?;
+            self.push(Segment { phys: phys.as_u64(), len: chunk as usize })?;
+            addr += chunk;
+        }
+        Ok(())
+    }
+
+    /// Appends `segment`, merged into the last one if it follows it.
+    fn push(&mut self, segment: Segment) -> Result<(), SgError> {
+        match self.segments.last_mut() {
+            Some(last) if last.phys + last.len as u64 == segment.phys => {
+                last.len += segment.len;
+                Ok(())
+            }
+            _ => self.segments.push(segment).map_err(|_| SgError::TooManySegments),
+        }
+    }
+
+    /// The segments, in the order of the buffers.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// The segments, split so none is longer than `max_len` bytes, E.g. the limit of a descriptor.
+    /// 
+    /// # Panics
+    /// Panics if `max_len` is 0.
+    pub fn split(&self, max_len: usize) -> impl Iterator<Item = Segment> + '_ {
+        assert!(max_len > 0, "segments can not be split into empty ones");
+        self.segments.iter().flat_map(move |segment| {
+            (0..segment.len).step_by(max_len).map(move |offset| Segment {
+                phys: segment.phys + offset as u64,
+                len: max_len.min(segment.len - offset),
+            })
+        })
+    }
+
+    /// The total length of the buffers in bytes.
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|segment| segment.len).sum()
+    }
+
+    /// Wether the buffers are empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Tests the segments of buffers on the heap and in a DMA buffer.
+#[cfg(feature = "test")]
+pub fn test_sg(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use alloc::vec;
+
+    use crate::{mem::dma::DmaBuffer, test::{test_assert, test_assert_eq}};
+
+    let data = vec![0u8; 3 * PAGE_SIZE as usize];
+    let bufs = [IoSlice::new(&data[100..]), IoSlice::new(&[]), IoSlice::new(&data[..10])];
+    let list = SgList::from_slices(&bufs).map_err(|_| "the heap buffers are not mapped")?;
+    test_assert_eq!(list.len(), data.len() - 90)?;
+    let first = list.segments()[0];
+    let phys = mem::translate_addr(VirtAddr::new(data.as_ptr() as u64 + 100)).map(|phys| phys.as_u64());
+    test_assert_eq!(Some(first.phys), phys)?;
+    let last = list.segments()[list.segments().len() - 1];
+    let phys = mem::translate_addr(VirtAddr::new(data.as_ptr() as u64)).map(|phys| phys.as_u64());
+    test_assert_eq!((Some(last.phys), last.len), (phys, 10))?;
+
+    // contiguous, so one segment, until it is split.
+    let mut buffer = DmaBuffer::new([0u8; PAGE_SIZE as usize + 1]).map_err(|_| "the buffer was not allocated")?;
+    let phys = buffer.phys_addr();
+    let mut bufs = [IoSliceMut::new(&mut buffer[..])];
+    let list = SgList::from_slices_mut(&mut bufs).map_err(|_| "the DMA buffer is not mapped")?;
+    test_assert_eq!(list.segments(), &[Segment { phys, len: PAGE_SIZE as usize + 1 }])?;
+    let mut split = list.split(PAGE_SIZE as usize);
+    test_assert_eq!(split.next(), Some(Segment { phys, len: PAGE_SIZE as usize }))?;
+    test_assert_eq!(split.next(), Some(Segment { phys: phys + PAGE_SIZE, len: 1 }))?;
+    test_assert_eq!(split.next(), None)?;
+
+    let bufs = [IoSlice::new(&[])];
+    test_assert!(SgList::from_slices(&bufs).is_ok_and(|list| list.is_empty()))?;
+    // not mapped, like in the page fault test.
+    let mut list = SgList { segments: ArrayVec::new(), _buffers: PhantomData };
+    test_assert_eq!(list.push_virt(0x5557_0000_0000, 1), Err(SgError::NotMapped(0x5557_0000_0000)))
+}
@@ -15,16 +15,13 @@
     missing_debug_implementations
 )]
 #![feature(
-    lang_items, 
     decl_macro, 
     panic_can_unwind, 
-    custom_test_frameworks, 
     try_trait_v2, 
     const_trait_impl, 
-    const_range, 
-    const_destruct,
     abi_x86_interrupt,
-    debug_closure_helpers
+    debug_closure_helpers,
+    allocator_api
 )]
 
 use alloc::boxed::Box;
@@ -54,6 +51,46 @@ pub mod serial;
 pub mod mem;
 /// Allocation tools
 pub mod lib_alloc;
+/// Architecture specific operations
+pub mod arch;
+/// Information passed by the bootloader
+pub mod boot;
+/// ACPI tables
+pub mod acpi;
+/// Rebooting and powering off
+pub mod power;
+/// Console output devices
+pub mod console;
+/// Time keeping and timeouts
+pub mod time;
+/// Device drivers
+pub mod drivers;
+/// Kernel collections (fixed capacity, hash maps and interned strings)
+pub mod collections;
+/// Interrupt safe synchronization
+pub mod sync;
+/// Keyboard and mouse events
+pub mod input;
+/// Reading and writing byte streams
+pub mod io;
+/// Files and directories, and the RAM filesystem
+pub mod fs;
+/// Block devices, their cache, and streams over them
+pub mod storage;
+/// Top-like system monitor
+pub mod monitor;
+/// The kernel shell and its scripts
+pub mod shell;
+/// Supervised kernel services
+pub mod services;
+/// Kernel tasks, and the scheduler
+pub mod task;
+/// The persistent kernel configuration
+pub mod config;
+/// Kernel version, build and hostname
+pub mod sys;
+/// Images and graphics on the frame buffer
+pub mod video;
 
 
 cfg_if::cfg_if! {
@@ -63,6 +100,21 @@ cfg_if::cfg_if! {
         fn trivial_assertion(_: TestInfo) -> TestResult {
             test_assert_eq!(1, 1, "Huh?")
         }
+
+        fn test_resources(info: TestInfo) -> TestResult {
+            use alloc::vec::Vec;
+            use crate::test::test_assert;
+
+            serial_println!("captured {}", info.ord);
+            let mut expected = collections::ArrayString::<32>::new();
+            let _ = core::fmt::write(&mut expected, format_args!("captured {}", info.ord));
+            test_assert!(info.output.contains(&expected))?;
+
+            let mut scratch = Vec::new_in(info.arena);
+            scratch.extend_from_slice(&[1u8, 2, 3]);
+            test_assert!(info.arena.used() >= 3)?;
+            test_assert!(!info.deadline.expired())
+        }
     }
 }
 
@@ -174,6 +226,14 @@ fn assert_cpuid_features(edx: BitFlags, ecx: BitFlags) {
 pub unsafe extern "C" fn rust_kernel_entry(boot_info: *const BootInfoC) -> ! {
 
     serial_println!("\nWelcome User of QEMU! Thank you for using Ion OS");
+    serial_println!("{}", sys::info());
+
+    // read before anything is initialized, so every step honors the options.
+    // Safety: the pointer is guaranteed always to be valid, as this is passed in from C.
+    if let Ok(input) = unsafe { boot_info.read() }.into_inner() {
+        boot::cmdline::init(input.command_line());
+    }
+    log::init(boot::cmdline::command_line());
 
     // initialize first to catch page faults/double faults
     match init::init() {
@@ -197,54 +257,218 @@ pub unsafe extern "C" fn rust_kernel_entry(boot_info: *const BootInfoC) -> ! {
     serial_println!("{:?}", boot_info);
 
     let boot_info = boot_info.unwrap_or_else(|e| {
-        panic!("Invalid Boot Info:\n {e:#?}")
-    }).into_rust();
+        panic!("Invalid Boot Info: {e}\n {e:#?}")
+    });
+    // kept in its raw form, to be handed to the next kernel on a soft reboot.
+    let raw_boot_info = boot_info.clone();
+    let boot_info = boot_info.into_rust();
+
+    console::init(&boot_info);
+    serial::config::init(&boot_info);
 
     
     
-    assert_cpuid_features(boot_info.cpuid_edx, boot_info.cpuid_ecx);
-    
-    let _ptr = boot_info.multiboot_info.into_inner().as_ref().unwrap();
+    // the boot stage's cpuid registers may be stale, so only use them for comparison.
+    let cpu_features = arch::cpuid::FeatureRegisters::query();
+    if let Some(boot_cpuid) = &boot_info.cpuid {
+        cpu_features.compare_with_boot(boot_cpuid);
+    }
 
+    assert_cpuid_features(cpu_features.edx, cpu_features.ecx);
     
+    if let Err(e) = boot::multiboot2::check_magic(raw_boot_info.multiboot_magic) {
+        warn!("{e}");
+    }
+    match boot_info.multiboot2() {
+        Ok(info) => info!("{info}, from {}", info.bootloader_name().unwrap_or("an unknown bootloader")),
+        Err(e) => warn!("The boot information is incomplete: {e}"),
+    }
+
 
     // TODO: load boot data here into global var
 
     // allocation
 
-    let mut mapper = mem::init();
-    let mut f_alloc = mem::BootInfoFrameAllocator::init(boot_info.mem_map_addr);
+    // must happen before the frame allocator hands out any frames.
+    if let Err(e) = mem::regions::reserve_boot_regions(&boot_info) {
+        panic!("Failed to reserve boot memory regions: {e}");
+    }
+    panic::lines::init(&boot_info);
+    // not needed to boot, only to soft reboot.
+    if let Err(e) = power::kexec::init(&raw_boot_info) {
+        warn!("Soft reboots are unavailable: {e}");
+    }
+    if let Err(e) = mem::dma::reserve() {
+        warn!("DMA buffers are unavailable: {e}");
+    }
+    #[cfg(feature = "test")]
+    if let Err(e) = test::persist::reserve() {
+        panic!("Failed to reserve the test journal: {e}");
+    }
+
+    // the frame allocator hands out frames per node.
+    if acpi::init(&boot_info).is_ok() {
+        mem::numa::init();
+    }
+
+    // Safety: the memory map is valid, it was read from the boot info.
+    let memory_map = unsafe { mem::copy_memory_map(boot_info.mem_map_addr) };
+    mem::vmm::init(mem::init(), mem::BootInfoFrameAllocator::init(memory_map));
 
-    init_heap(&mut mapper, &mut f_alloc)
+    init_heap()
         .expect("Heap Initialization Failed");
+    let early_alloc = mem::bump_early::hand_off();
+    info!("Heap initialized, early allocator handed off ({early_alloc}).");
+    // the filesystems allocate, so they are mounted once the heap is up.
+    fs::init();
+    storage::module::init(&boot_info);
+    fs::mount_root(&boot_info);
+    // read from the root, so only once it is mounted.
+    config::load();
+    match text::framebuffer::init(&boot_info) {
+        Ok(()) => info!("Switched to the framebuffer console."),
+        Err(text::framebuffer::FrameBufferError::Missing) => {}
+        Err(e) => warn!("The framebuffer console is unavailable: {e}"),
+    }
+    video::mode::init(&boot_info);
+    match interrupts::apic::init() {
+        Ok(mode) => info!("Interrupts are delivered through the {mode}."),
+        Err(e) => warn!("Using the 8259 PICs: {e}"),
+    }
+    time::clocksource::init_late();
+
+    task::init();
+    task::executor::init();
+    // decoded on the executor, the interrupt handler only queues them.
+    task::executor::spawn(interrupts::keyboard::scancodes::process());
 
     serial_println!("Initialized");
+    sys::booted();
+    mem::report();
 
     _ = Box::new(41);
 
     cfg_if! {
         if #[cfg(feature = "test")] {
+            use test::{ShouldPanic, Tagged, Tags};
+
+            // a borrowed `Tagged` is only promoted to `'static` if its tags are constant.
+            const KEYBOARD: Tags = Tags::INTERRUPTS.union(Tags::TEXT);
+
+            test::persist::run(&[
+                test::persist::Persistent { name: "test_reboot", run: test::persist::test_reboot },
+            ]);
             run_tests(&[
                 // all tests go here
                 // control, test for tests
                 &trivial_assertion,
+                &test_resources,
+                &test::persist::test_persistent_passed,
+                &test::filter::test_timeout_option,
+                // panics
+                &panic::catch::test::test_catch,
+                &ShouldPanic { test: panic::catch::test::test_should_panic, expected: Some("index out of bounds") },
+                &panic::lines::test_line_table,
+                &panic::oops::test_oops,
                 // interrupts
-                &interrupts::test::test_breakpoint,
+                &Tagged { test: interrupts::test::test_breakpoint, tags: Tags::INTERRUPTS },
+                &interrupts::context::test_context,
+                &interrupts::page_fault::test_page_fault,
+                &interrupts::exceptions::test_exceptions,
+                &Tagged { test: test::mock::test_scripted_ps2, tags: Tags::INTERRUPTS },
+                &Tagged { test: interrupts::keyboard::replay::test_replay, tags: KEYBOARD },
+                &Tagged { test: interrupts::keyboard::scancodes::test_scancode_queue, tags: KEYBOARD },
+                &Tagged { test: interrupts::keyboard::repeat::test_key_repeat, tags: KEYBOARD },
+                &Tagged { test: interrupts::keyboard::hotkeys::test_hotkeys, tags: KEYBOARD },
+                &input::test_input,
+                &io::test_io,
+                &io::sg::test_sg,
+                &fs::path::test_paths,
+                &fs::ramfs::test_ramfs,
+                &fs::test_vfs,
+                &fs::fat::test_fat,
+                &fs::devfs::test_devfs,
+                &fs::procfs::test_procfs,
+                &drivers::ata::test_ata_identity,
+                &drivers::isolation::test_driver_isolation,
+                &storage::cache::test_block_cache,
+                &storage::stream::test_block_stream,
+                &storage::module::test_boot_module,
+                &boot::multiboot2::test_multiboot2,
+                &boot::cmdline::test_command_line,
+                &acpi::test_acpi,
+                &acpi::srat::test_srat,
+                &acpi::madt::test_madt,
+                &mem::dma::test_dma,
+                &mem::stacks::test_stacks,
+                &mem::vmm::test_vmm,
+                &interrupts::apic::test_apic,
+                &Tagged { test: interrupts::keyboard::stdin::test_stdin, tags: Tags::TEXT },
+                &drivers::ps2::mouse::test_mouse_packets,
+                // Time
+                &time::test_clock::test_clock,
+                &time::clocksource::test_clocksource,
+                &time::timer::test_timers,
+                &time::timer::test_timer_wheel,
                 // VGA
-                &text::test_println_output,
+                &Tagged { test: text::test_println_output, tags: Tags::TEXT },
+                &Tagged { test: text::test_regions, tags: Tags::TEXT },
+                &Tagged { test: console::test_early_console, tags: Tags::TEXT },
+                &Tagged { test: console::selection::test_selection, tags: Tags::TEXT },
+                &text::framebuffer::test_framebuffer,
+                &video::image::test_image,
+                &video::mode::test_video_modes,
                 // Alloc
-                &lib_alloc::tests::test_large_alloc,
-                &lib_alloc::tests::test_freed_mem_used,
-                &lib_alloc::tests::test_alloc_tools,
+                &Tagged { test: lib_alloc::tests::test_large_alloc, tags: Tags::ALLOC },
+                &Tagged { test: lib_alloc::tests::test_freed_mem_used, tags: Tags::ALLOC },
+                &Tagged { test: lib_alloc::tests::test_alloc_tools, tags: Tags::ALLOC },
+                &Tagged { test: lib_alloc::tests::test_arena, tags: Tags::ALLOC },
+                &Tagged { test: lib_alloc::tests::test_heap_size, tags: Tags::ALLOC },
+                &Tagged { test: lib_alloc::tests::test_try_alloc, tags: Tags::ALLOC },
+                &Tagged { test: lib_alloc::tests::test_irq_lock, tags: Tags::ALLOC },
+                // Arch
+                &arch::cpuinfo::test::test_cpuinfo,
+                // Collections
+                &Tagged { test: collections::tests::test_fixed_collections, tags: Tags::COLLECTIONS },
+                &Tagged { test: collections::tests::test_hash_map, tags: Tags::COLLECTIONS },
+                &Tagged { test: collections::tests::test_intern, tags: Tags::COLLECTIONS },
+                &monitor::test_monitor,
+                &shell::script::test_script,
+                &shell::history::test_history,
+                &shell::complete::test_completion,
+                &shell::kshell::test_kshell,
+                &shell::editor::test_pager_and_editor,
+                &shell::stress::test_stress,
+                &interrupts::stats::test_latency,
+                &services::test_services,
+                &config::test_config,
+                &sys::test_sys_info,
+                &mem::layout::test_memory_report,
+                &log::sink::test_log_sinks,
+                &serial::tx::test_serial_tx,
+                &serial::config::test_serial_config,
+                &log::progress::test_progress,
+                &task::test_tasks,
+                &task::workqueue::test_workqueue,
+                &task::executor::test_executor,
+                &power::kexec::test_kexec,
+                // Sync
+                &sync::once_cell::test_once_cell,
+                &sync::lockdep::test_lockdep,
+                &sync::adaptive::test_adaptive_mutex,
+                // Memory
+                &Tagged { test: mem::regions::test::test_region_conflicts, tags: Tags::MEMORY },
+                &Tagged { test: mem::numa::test_numa_nodes, tags: Tags::MEMORY },
             ]);
             panic!("End of tests; you can now exit.");
         } else {
             println!("Not Testing");
+            shell::kshell::spawn();
         }
     }
 
 
-    hlt_loop()
+    services::supervise()
 }
 
 /// Halts the CPU forever.
@@ -0,0 +1,159 @@
+//! Arena (bump) allocator for request scoped allocations.
+//! 
+//! Parsers (multiboot, ELF, directory scans, etc.) often need a lot of small scratch allocations
+//! which all die at the same time. An [`Arena`] hands these out by bumping an offset into one
+//! buffer, and frees all of them at once with an O(1) [`reset`](Arena::reset).
+use core::{alloc::{AllocError, Allocator, Layout}, cell::Cell, ptr::NonNull};
+
+use alloc::{alloc::Global, boxed::Box};
+
+/// A bump allocator over a fixed buffer.
+/// 
+/// Use it through a reference, as `&Arena` implements [`Allocator`].
+/// 
+/// # Example
+/// ```rust,no_run
+/// use alloc::vec::Vec;
+/// use crate::lib_alloc::Arena;
+/// 
+/// let mut arena = Arena::new(4096).unwrap();
+/// {
+///     let mut scratch = Vec::new_in(&arena);
+///     scratch.extend_from_slice(&[1, 2, 3]);
+/// }
+/// // everything allocated in the arena is gone.
+/// arena.reset();
+/// ```
+#[derive(Debug)]
+pub struct Arena {
+    buf: NonNull<[u8]>,
+    /// Offset of the next free byte.
+    next: Cell<usize>,
+    /// Start of the most recent allocation, which may grow in place or be freed.
+    last: Cell<usize>,
+    /// Highest value of `next` since creation.
+    high_water: Cell<usize>,
+}
+
+impl Arena {
+    /// Creates a new arena with `capacity` bytes, allocated from the heap.
+    /// # Errors
+    /// Returns [`AllocError`] if the heap can not provide the buffer.
+    pub fn new(capacity: usize) -> Result<Self, AllocError> {
+        let layout = Layout::from_size_align(capacity, 16).map_err(|_| AllocError)?;
+        let buf = Global.allocate(layout)?;
+        Ok(Self { buf, next: Cell::new(0), last: Cell::new(0), high_water: Cell::new(0) })
+    }
+
+    /// Frees every allocation at once.
+    /// 
+    /// Requires `&mut self`, so no allocations can still be alive.
+    pub fn reset(&mut self) {
+        self.next.set(0);
+        self.last.set(0);
+    }
+
+    /// Total size of the arena, in bytes.
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Amount of bytes currently allocated (including alignment padding).
+    pub fn used(&self) -> usize {
+        self.next.get()
+    }
+
+    /// Highest amount of bytes ever in use at once.
+    pub fn high_water(&self) -> usize {
+        self.high_water.get()
+    }
+
+    fn base(&self) -> usize {
+        self.buf.cast::<u8>().as_ptr() as usize
+    }
+
+    /// Returns the offset of `ptr` if it is the most recent allocation.
+    fn last_offset(&self, ptr: NonNull<u8>) -> Option<usize> {
+        let offset = ptr.as_ptr() as usize - self.base();
+        (offset == self.last.get() && self.next.get() != 0).then_some(offset)
+    }
+
+    fn set_next(&self, next: usize) {
+        self.next.set(next);
+        self.high_water.set(self.high_water.get().max(next));
+    }
+
+    fn slice(&self, offset: usize, len: usize) -> NonNull<[u8]> {
+        // Safety: offset is always inside the buffer, which is never null.
+        let ptr = unsafe { NonNull::new_unchecked((self.base() + offset) as *mut u8) };
+        NonNull::slice_from_raw_parts(ptr, len)
+    }
+}
+
+unsafe impl Allocator for Arena {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let base = self.base();
+        let start = (base + self.next.get())
+            .checked_next_multiple_of(layout.align())
+            .ok_or(AllocError)? - base;
+        let end = start.checked_add(layout.size()).ok_or(AllocError)?;
+        if end > self.capacity() {
+            return Err(AllocError);
+        }
+        self.last.set(start);
+        self.set_next(end);
+        Ok(self.slice(start, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, _layout: Layout) {
+        // only the most recent allocation can be given back, everything else waits for `reset`
+        if let Some(offset) = self.last_offset(ptr) {
+            self.next.set(offset);
+        }
+    }
+
+    unsafe fn grow(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        // grow in place if this is the most recent allocation, and the alignment is still satisfied.
+        let in_place = self.last_offset(ptr)
+            .filter(|_| (ptr.as_ptr() as usize).is_multiple_of(new_layout.align()))
+            .filter(|offset| offset.checked_add(new_layout.size()).is_some_and(|end| end <= self.capacity()));
+        if let Some(offset) = in_place {
+            self.set_next(offset + new_layout.size());
+            return Ok(self.slice(offset, new_layout.size()));
+        }
+
+        let new = self.allocate(new_layout)?;
+        // Safety: the new allocation is fresh, so it can not overlap the old one. The caller
+        // ensures `ptr` is valid for `old_layout`.
+        unsafe {
+            core::ptr::copy_nonoverlapping(ptr.as_ptr(), new.cast::<u8>().as_ptr(), old_layout.size());
+        }
+        Ok(new)
+    }
+
+    unsafe fn shrink(&self, ptr: NonNull<u8>, _old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if !(ptr.as_ptr() as usize).is_multiple_of(new_layout.align()) {
+            return Err(AllocError);
+        }
+        if let Some(offset) = self.last_offset(ptr) {
+            self.next.set(offset + new_layout.size());
+        }
+        Ok(NonNull::slice_from_raw_parts(ptr, new_layout.size()))
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        // Safety: `buf` was allocated by `Global` with this layout in `new`.
+        unsafe {
+            Global.deallocate(self.buf.cast(), Layout::from_size_align_unchecked(self.capacity(), 16));
+        }
+    }
+}
+
+/// Allocates `val` in `arena`, returning a [`Box`] tied to the arena's lifetime.
+/// # Errors
+/// Returns [`AllocError`] if the arena is full.
+pub fn arena_box<T>(arena: &Arena, val: T) -> Result<Box<T, &Arena>, AllocError> {
+    Box::try_new_in(val, arena)
+}
@@ -0,0 +1,43 @@
+//! The lock of the heap, which can not deadlock when the allocator is interrupted.
+//! 
+//! The heap is only locked with interrupts disabled, so a hardware interrupt never finds it locked.
+//! An exception raised inside the allocator can, E.g. a page fault, or an NMI, and on the one CPU
+//! waiting for the interrupted code would hang forever. So an [`IrqLock`] does not wait, the
+//! allocation fails instead.
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// A lock which is taken with interrupts disabled, and fails instead of waiting.
+#[derive(Debug)]
+pub struct IrqLock<T> {
+    inner: spin::Mutex<T>,
+    /// Times the lock was found taken.
+    contended: AtomicUsize,
+}
+
+impl<T> IrqLock<T> {
+    /// A free lock around `value`
+    pub const fn new(value: T) -> Self {
+        Self { inner: spin::Mutex::new(value), contended: AtomicUsize::new(0) }
+    }
+
+    /// Calls `f` with the value, with interrupts disabled.
+    /// 
+    /// Returns `None` without calling `f` if the lock is taken, which means the code holding it
+    /// was interrupted.
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        without_interrupts(|| match self.inner.try_lock() {
+            Some(mut value) => Some(f(&mut value)),
+            None => {
+                self.contended.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        })
+    }
+
+    /// Times [`with`](Self::with) found the lock taken.
+    pub fn contended(&self) -> usize {
+        self.contended.load(Ordering::Relaxed)
+    }
+}
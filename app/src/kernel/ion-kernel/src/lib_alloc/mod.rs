@@ -1,56 +1,77 @@
-use linked_list_allocator::LockedHeap;
+use alloc::vec::Vec;
+use core::{alloc::{AllocError, Allocator, GlobalAlloc, Layout}, ptr::NonNull, sync::atomic::{AtomicUsize, Ordering}};
+
+use linked_list_allocator::Heap;
+
+use crate::{boot::cmdline::CommandLine, interrupts::context::in_interrupt, log::warn};
 
 // Heap Defs.
 
 /// The Beginning of the Heap.
 pub const HEAP_START: usize = 0x_4444_4444_0000;
 
-/// The Heap's Size
+/// The Heap's Size, unless `heap_size=` on the [command line](crate::boot::cmdline) says
+/// otherwise.
 pub const HEAP_SIZE: usize = 100 * 1024; // 100 KiB
 
+/// The smallest size `heap_size=` can set.
+pub const MIN_HEAP_SIZE: usize = 64 * 1024; // 64 KiB
+
+/// The largest size `heap_size=` can set.
+pub const MAX_HEAP_SIZE: usize = 256 * 1024 * 1024; // 256 MiB
+
+static SIZE: AtomicUsize = AtomicUsize::new(HEAP_SIZE);
+
+/// The Heap's Size, as set by [`init_heap`]
+pub fn heap_size() -> usize {
+    SIZE.load(Ordering::Relaxed)
+}
+
 /// The Heap's End.
 /// 
-/// Equals [`HEAP_START`] + [`HEAP_SIZE`].
-pub const HEAP_END: usize = HEAP_START + HEAP_SIZE;
+/// Equals [`HEAP_START`] + [`heap_size`].
+pub fn heap_end() -> usize {
+    HEAP_START + heap_size()
+}
 
 /// Returns wether addr is inside of the heap.
-pub const fn is_heap_addr(addr: usize) -> bool {
-    (HEAP_START..=HEAP_END).contains(&addr)
+pub fn is_heap_addr(addr: usize) -> bool {
+    (HEAP_START..=heap_end()).contains(&addr)
 }
 
-use x86_64::{
-    structures::paging::{
-        mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
-    },
-    VirtAddr,
-};
-
-/// Initialize the Heap.
-pub fn init_heap(
-    mapper: &mut impl Mapper<Size4KiB>,
-    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
-) -> Result<(), MapToError<Size4KiB>> {
-    let page_range = {
-        let heap_start = VirtAddr::new(HEAP_START as u64);
-        let heap_end = heap_start + HEAP_SIZE as u64 - 1u64;
-        let heap_start_page = Page::containing_address(heap_start);
-        let heap_end_page = Page::containing_address(heap_end);
-        Page::range_inclusive(heap_start_page, heap_end_page)
+/// The heap size from `heap_size=` on the kernel `command_line`, rounded up to whole pages and
+/// clamped to [`MIN_HEAP_SIZE`]..=[`MAX_HEAP_SIZE`]
+pub fn size_from_command_line(command_line: CommandLine<'_>) -> usize {
+    let size = match command_line.get_size("heap_size") {
+        None => return HEAP_SIZE,
+        Some(Ok(size)) => size,
+        Some(Err(e)) => {
+            warn!("Ignoring the heap_size= argument: {e}");
+            return HEAP_SIZE;
+        }
     };
-
-    for page in page_range {
-        let frame = frame_allocator
-            .allocate_frame()
-            .ok_or(MapToError::FrameAllocationFailed)?;
-        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
-        unsafe {
-            mapper.map_to(page, frame, flags, frame_allocator)?.flush()
-        };
+    let clamped = size.clamp(MIN_HEAP_SIZE, MAX_HEAP_SIZE).next_multiple_of(4096);
+    if clamped != size {
+        warn!("heap_size={size} is not a multiple of 4 KiB between 64 KiB and 256 MiB, using {clamped}");
     }
+    clamped
+}
 
-    unsafe {
-        GLOBAL_ALLOC.lock().init(HEAP_START as *mut u8, HEAP_SIZE);
-    }
+use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+
+use crate::mem::vmm::{self, VmmError};
+
+/// Initialize the Heap, with the size from the kernel command line.
+/// 
+/// Must be called after the [virtual memory manager](vmm) is initialized.
+pub fn init_heap() -> Result<(), VmmError> {
+    let size = size_from_command_line(crate::boot::cmdline::command_line());
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    vmm::map_range(VirtAddr::new(HEAP_START as u64), size as u64, flags)?;
+
+    // Safety: the pages were just mapped, and nothing else uses them.
+    GLOBAL_ALLOC.heap.with(|heap| unsafe { heap.init(HEAP_START as *mut u8, size) });
+    SIZE.store(size, Ordering::Relaxed);
 
     Ok(())
 }
@@ -61,7 +82,139 @@ pub fn init_heap(
 /// This static the global allocator.
 /// 
 /// This should be used through [`Box`](alloc::boxed::Box), and other alloc types.
-static GLOBAL_ALLOC: LockedHeap = LockedHeap::empty();
+static GLOBAL_ALLOC: TrackedHeap = TrackedHeap {
+    heap: IrqLock::new(Heap::empty()),
+    allocations: AtomicUsize::new(0),
+    deallocations: AtomicUsize::new(0),
+    live_bytes: AtomicUsize::new(0),
+    peak_bytes: AtomicUsize::new(0),
+    failures: AtomicUsize::new(0),
+};
+
+/// The heap, counting allocations so leaks can be found.
+struct TrackedHeap {
+    heap: IrqLock<Heap>,
+    allocations: AtomicUsize,
+    deallocations: AtomicUsize,
+    live_bytes: AtomicUsize,
+    peak_bytes: AtomicUsize,
+    failures: AtomicUsize,
+}
+
+impl TrackedHeap {
+    fn allocated(&self, size: usize) {
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+        let live = self.live_bytes.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak_bytes.fetch_max(live, Ordering::Relaxed);
+    }
+
+    fn deallocated(&self, size: usize) {
+        self.deallocations.fetch_add(1, Ordering::Relaxed);
+        self.live_bytes.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for TrackedHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // in all builds, the heap lock only fails instead of deadlocking for exceptions and NMIs.
+        assert!(!in_interrupt(), "an interrupt handler allocated, which may find the heap locked");
+        // with interrupts disabled, a task is never preempted while it holds the heap lock.
+        let ptr = self.heap.with(|heap| heap.allocate_first_fit(layout).ok()).flatten()
+            .map_or(core::ptr::null_mut(), NonNull::as_ptr);
+        if ptr.is_null() {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        assert!(!in_interrupt(), "an interrupt handler freed memory, which may find the heap locked");
+        // Safety: forwarded from the caller, `ptr` was allocated from the heap, so it is not null.
+        let freed = self.heap.with(|heap| unsafe { heap.deallocate(NonNull::new_unchecked(ptr), layout) });
+        // when the heap is locked, the memory is leaked, and still counted as live.
+        if freed.is_some() {
+            self.deallocated(layout.size());
+        }
+    }
+}
+
+/// Statistics of the heap, see [`stats`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocStats {
+    /// Total amount of allocations.
+    pub allocations: usize,
+    /// Total amount of deallocations.
+    pub deallocations: usize,
+    /// Bytes currently allocated.
+    pub live_bytes: usize,
+    /// The most bytes allocated at once.
+    pub peak_bytes: usize,
+    /// Allocations the heap had no room for.
+    pub failures: usize,
+    /// Allocations and frees which found the heap locked by the code they interrupted. The
+    /// allocations failed, and the frees leaked.
+    pub contended: usize,
+}
+
+impl AllocStats {
+    /// The amount of allocations which have not been freed.
+    pub fn live_allocations(&self) -> usize {
+        self.allocations - self.deallocations
+    }
+
+    /// Returns the allocations made and not freed since `earlier`, as (allocations, bytes), if there
+    /// are any.
+    pub fn leaked_since(&self, earlier: &AllocStats) -> Option<(usize, usize)> {
+        let allocs = self.live_allocations().saturating_sub(earlier.live_allocations());
+        let bytes = self.live_bytes.saturating_sub(earlier.live_bytes);
+        (allocs > 0 || bytes > 0).then_some((allocs, bytes))
+    }
+}
+
+/// Returns the current statistics of the heap.
+pub fn stats() -> AllocStats {
+    AllocStats {
+        allocations: GLOBAL_ALLOC.allocations.load(Ordering::Relaxed),
+        deallocations: GLOBAL_ALLOC.deallocations.load(Ordering::Relaxed),
+        live_bytes: GLOBAL_ALLOC.live_bytes.load(Ordering::Relaxed),
+        peak_bytes: GLOBAL_ALLOC.peak_bytes.load(Ordering::Relaxed),
+        failures: GLOBAL_ALLOC.failures.load(Ordering::Relaxed),
+        contended: GLOBAL_ALLOC.heap.contended(),
+    }
+}
+
+// Fallible Allocation
+
+/// Allocates memory for `layout` from the heap, returning an error instead of calling
+/// [`handle_alloc_error`](alloc::alloc::handle_alloc_error), which panics, when it has no room.
+/// 
+/// The memory must be freed with [`dealloc`](alloc::alloc::dealloc) and the same `layout`.
+/// # Errors
+/// Returns [`AllocError`] if the heap has no room for `layout`
+pub fn try_alloc(layout: Layout) -> Result<NonNull<u8>, AllocError> {
+    alloc::alloc::Global.allocate(layout).map(NonNull::cast)
+}
+
+/// A vector of `len` copies of `value`, like `vec![value; len]`, without panicking when the heap
+/// has no room for it.
+/// # Errors
+/// Returns [`AllocError`] if the heap has no room for `len` values.
+pub fn try_vec<T: Clone>(value: T, len: usize) -> Result<Vec<T>, AllocError> {
+    let mut vec = Vec::new();
+    vec.try_reserve_exact(len).map_err(|_| AllocError)?;
+    vec.resize(len, value);
+    Ok(vec)
+}
+
+/// Arena allocator for request scoped allocations.
+pub mod arena;
+/// The lock of the heap.
+pub mod irq_lock;
+
+pub use arena::Arena;
+pub use irq_lock::IrqLock;
 
 #[cfg(feature = "test")]
 /// Tests
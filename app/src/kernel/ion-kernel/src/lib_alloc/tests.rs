@@ -1,6 +1,6 @@
 use alloc::{boxed::Box, collections::{LinkedList, VecDeque}, rc::Rc, string::String, vec, vec::Vec};
 
-use crate::test::{TestInfo, TestResult, test_assert_eq};
+use crate::{lib_alloc::Arena, test::{TestInfo, TestResult, test_assert, test_assert_eq}};
 
 /// Tests allocation Tools.
 pub fn test_alloc_tools(inf: TestInfo) -> TestResult {
@@ -64,4 +64,69 @@ pub fn test_freed_mem_used(_: TestInfo) -> TestResult {
     }
 
     TestResult::Ok
-}
\ No newline at end of file
+}
+
+/// Tests arena allocation, exhaustion and reset.
+pub fn test_arena(_: TestInfo) -> TestResult {
+    let mut arena = Arena::new(256).map_err(|_| "arena creation failed")?;
+    {
+        let mut scratch = Vec::new_in(&arena);
+        for i in 0..16u32 {
+            scratch.push(i);
+        }
+        test_assert_eq!(scratch.iter().sum::<u32>(), 120)?;
+        test_assert!(arena.used() >= 16 * size_of::<u32>())?;
+
+        // larger than the whole arena
+        test_assert!(Vec::<u8, _>::try_with_capacity_in(512, &arena).is_err())?;
+    }
+    arena.reset();
+    test_assert_eq!(arena.used(), 0)?;
+    test_assert!(arena.high_water() >= 16 * size_of::<u32>())
+}
+
+/// Tests the heap size from the command line.
+pub fn test_heap_size(_: TestInfo) -> TestResult {
+    use super::{HEAP_SIZE, MAX_HEAP_SIZE, MIN_HEAP_SIZE, size_from_command_line};
+    use crate::boot::cmdline::CommandLine;
+
+    test_assert_eq!(size_from_command_line(CommandLine::new("quiet")), HEAP_SIZE)?;
+    test_assert_eq!(size_from_command_line(CommandLine::new("heap_size=1M")), 1024 * 1024)?;
+    test_assert_eq!(size_from_command_line(CommandLine::new("heap_size=100000")), 100 * 1024)?;
+    test_assert_eq!(size_from_command_line(CommandLine::new("heap_size=1K")), MIN_HEAP_SIZE)?;
+    test_assert_eq!(size_from_command_line(CommandLine::new("heap_size=1G")), MAX_HEAP_SIZE)?;
+    test_assert_eq!(size_from_command_line(CommandLine::new("heap_size=lots")), HEAP_SIZE)?;
+    test_assert!(super::heap_size() >= MIN_HEAP_SIZE)
+}
+
+/// Tests allocating without panicking when the heap has no room.
+pub fn test_try_alloc(_: TestInfo) -> TestResult {
+    use core::alloc::Layout;
+
+    use crate::io::{self, ErrorKind};
+
+    let failures = super::stats().failures;
+    let huge = Layout::from_size_align(super::heap_size() * 2, 8).map_err(|_| "the layout is invalid")?;
+    test_assert!(super::try_alloc(huge).is_err())?;
+    test_assert_eq!(super::stats().failures, failures + 1)?;
+    let error = io::Error::from(super::try_vec(0u8, super::heap_size() * 2).unwrap_err());
+    test_assert_eq!(error.kind(), ErrorKind::MemoryError)?;
+
+    let small = Layout::new::<u64>();
+    let ptr = super::try_alloc(small).map_err(|_| "a small allocation failed")?;
+    // Safety: allocated above with the same layout.
+    unsafe { alloc::alloc::dealloc(ptr.as_ptr(), small) };
+    test_assert_eq!(super::try_vec(7u8, 3).map_err(|_| "a small vector failed")?, vec![7, 7, 7])
+}
+
+/// Tests that the heap's lock fails instead of waiting for the code it interrupted.
+pub fn test_irq_lock(_: TestInfo) -> TestResult {
+    use super::IrqLock;
+
+    let lock = IrqLock::new(1);
+    test_assert_eq!(lock.with(|value| *value + 1), Some(2))?;
+    // like an exception raised while the allocator holds the lock.
+    let nested = lock.with(|_| lock.with(|value| *value));
+    test_assert_eq!((nested, lock.contended()), (Some(None), 1))?;
+    test_assert_eq!(super::stats().contended, 0)
+}
@@ -0,0 +1,250 @@
+/// Progress bars and spinners on the status line.
+pub mod progress;
+/// Rate limiting and deduplication of messages.
+pub mod ratelimit;
+/// Where messages are written to, and their level filters.
+pub mod sink;
+
+use core::{fmt, str::FromStr, sync::atomic::{AtomicU8, AtomicUsize, Ordering}};
+
+use spin::Mutex;
+
+use crate::{boot::cmdline::CommandLine, collections::{ArrayString, ArrayVec, CapacityError}};
+
+/// Log levels, from least to most severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    /// Trace log
+    Trace,
+    /// Debug log, does not show in release
+    Debug,
+    /// Info log
+    Info,
+    /// Warning log
+    Warn,
+    /// Error Log
+    Error,
+}
+
+impl Level {
+    /// Every level, from least to most severe.
+    pub const ALL: [Level; 5] = [Level::Trace, Level::Debug, Level::Info, Level::Warn, Level::Error];
+
+    const fn from_u8(level: u8) -> Self {
+        match level {
+            0 => Level::Trace,
+            1 => Level::Debug,
+            2 => Level::Info,
+            3 => Level::Warn,
+            _ => Level::Error,
+        }
+    }
+}
+
+/// The string is not the name of a [`Level`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLevelError;
+
+impl fmt::Display for ParseLevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected one of trace, debug, info, warn or error")
+    }
+}
+
+impl FromStr for Level {
+    type Err = ParseLevelError;
+
+    /// Parses a level name, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Level::ALL.into_iter()
+            .find(|level| {
+                let mut name = ArrayString::<5>::new();
+                // can not fail, the names are at most 5 bytes.
+                let _ = fmt::write(&mut name, format_args!("{level:?}"));
+                name.eq_ignore_ascii_case(s)
+            })
+            .ok_or(ParseLevelError)
+    }
+}
+
+// Level Filtering
+
+/// Maximum length of a target prefix in [`set_target_level`]
+pub const MAX_TARGET_LEN: usize = 48;
+/// Maximum amount of target overrides.
+pub const MAX_TARGET_OVERRIDES: usize = 16;
+
+static MIN_LEVEL: AtomicU8 = AtomicU8::new(Level::Trace as u8);
+/// Mirrors the length of [`TARGET_LEVELS`], so logging does not have to lock it if it is empty.
+static TARGET_COUNT: AtomicUsize = AtomicUsize::new(0);
+static TARGET_LEVELS: Mutex<ArrayVec<(ArrayString<MAX_TARGET_LEN>, Level), MAX_TARGET_OVERRIDES>> = Mutex::new(ArrayVec::new());
+
+/// Sets the least severe level which is still logged.
+/// 
+/// Targets with an override from [`set_target_level`] are not affected.
+pub fn set_max_level(level: Level) {
+    MIN_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// The least severe level which is still logged, see [`set_max_level`]
+pub fn max_level() -> Level {
+    Level::from_u8(MIN_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Sets the least severe level which is still logged for `target`, a module path prefix (such as
+/// `ion_kernel::mem`), overriding [`max_level`].
+/// 
+/// The longest matching prefix is used.
+/// # Errors
+/// Returns an error if `target` is too long, or there are too many overrides.
+pub fn set_target_level(target: &str, level: Level) -> Result<(), CapacityError> {
+    let mut prefix = ArrayString::new();
+    prefix.push_str(target)?;
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut levels = TARGET_LEVELS.lock();
+        if let Some(entry) = levels.iter_mut().find(|(t, _)| *t == prefix) {
+            entry.1 = level;
+        } else {
+            levels.push((prefix, level)).map_err(|_| CapacityError(()))?;
+        }
+        TARGET_COUNT.store(levels.len(), Ordering::Relaxed);
+        Ok(())
+    })
+}
+
+/// Removes the override for `target`, returning wether there was one.
+pub fn clear_target_level(target: &str) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut levels = TARGET_LEVELS.lock();
+        let index = levels.iter().position(|(t, _)| t.as_str() == target);
+        if let Some(index) = index {
+            levels.swap_remove(index);
+        }
+        TARGET_COUNT.store(levels.len(), Ordering::Relaxed);
+        index.is_some()
+    })
+}
+
+/// Calls `f` for every target override.
+pub fn for_each_target_level(mut f: impl FnMut(&str, Level)) {
+    let levels = x86_64::instructions::interrupts::without_interrupts(|| TARGET_LEVELS.lock().clone());
+    for (target, level) in levels.iter() {
+        f(target, *level);
+    }
+}
+
+/// The `loglevel` shell command: `loglevel` shows the levels, `loglevel LEVEL` sets
+/// [`max_level`], and `loglevel SINK LEVEL` the level of a [sink], or of a target if it contains
+/// `::`
+pub fn loglevel_command(args: &[&str]) -> i32 {
+    use crate::text::println;
+
+    let usage = || {
+        println!("usage: loglevel [SINK|TARGET] [LEVEL]");
+        2
+    };
+    let parse = |level: &str| level.parse::<Level>().map_err(|e| println!("loglevel: {e}"));
+    match args {
+        [_] => {
+            println!("max: {:?}", max_level());
+            sink::for_each(|name, level| println!("{name}: {level:?}"));
+            for_each_target_level(|target, level| println!("{target}: {level:?}"));
+        }
+        [_, level] => match parse(level) {
+            Ok(level) => set_max_level(level),
+            Err(()) => return 2,
+        },
+        [_, name, level] => {
+            let Ok(level) = parse(level) else { return 2 };
+            if name.contains("::") {
+                if set_target_level(name, level).is_err() {
+                    println!("loglevel: too many targets, or the target is too long");
+                    return 1;
+                }
+            } else if !sink::set_level(name, level) {
+                println!("loglevel: there is no sink called {name}");
+                return 1;
+            }
+        }
+        _ => return usage(),
+    }
+    0
+}
+
+/// Sets the [`max_level`] from `loglevel=` on the kernel `command_line`
+pub fn init(command_line: CommandLine<'_>) {
+    match command_line.parse::<Level>("loglevel") {
+        None => {}
+        Some(Ok(level)) => set_max_level(level),
+        Some(Err(e)) => warn!("Ignoring the loglevel= argument: {e}"),
+    }
+}
+
+/// Returns wether a message at `level` from `target` is logged.
+pub fn enabled(level: Level, target: &str) -> bool {
+    if !cfg!(debug_assertions) && level == Level::Debug {
+        return false;
+    }
+    let mut min = max_level();
+    if TARGET_COUNT.load(Ordering::Relaxed) > 0 {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let levels = TARGET_LEVELS.lock();
+            let best = levels.iter()
+                .filter(|(prefix, _)| target.starts_with(prefix.as_str()))
+                .max_by_key(|(prefix, _)| prefix.len());
+            if let Some((_, level)) = best {
+                min = *level;
+            }
+        });
+    }
+    level >= min
+}
+
+/// Low‑level logging function: passes the message to the [sinks](sink)
+#[inline]
+#[track_caller]
+pub fn log(level: Level, args: fmt::Arguments) {
+    log_target(level, "", args);
+}
+
+/// Low‑level logging function, for messages from `target` (usually the module path): passes the
+/// message to the [sinks](sink), if [`enabled`]
+#[inline]
+#[track_caller]
+pub fn log_target(level: Level, target: &str, args: fmt::Arguments) {
+    if !enabled(level, target) {
+        return;
+    }
+    let location = core::panic::Location::caller();
+    let suppressed = match ratelimit::check(level, location, args) {
+        ratelimit::Verdict::Suppress => return,
+        ratelimit::Verdict::Log(suppressed) => suppressed,
+    };
+    sink::dispatch(&sink::Record { level, target, location, args, suppressed });
+}
+
+/// Info log
+pub macro info($($args:tt)*) {
+    $crate::log::log_target($crate::log::Level::Info, module_path!(), format_args!($($args)*))
+}
+
+/// Warn log
+pub macro warn($($args:tt)*) {
+    $crate::log::log_target($crate::log::Level::Warn, module_path!(), format_args!($($args)*))
+}
+
+/// Trace log
+pub macro trace($($args:tt)*) {
+    $crate::log::log_target($crate::log::Level::Trace, module_path!(), format_args!($($args)*))
+}
+
+/// Error log
+pub macro error($($args:tt)*) {
+    $crate::log::log_target($crate::log::Level::Error, module_path!(), format_args!($($args)*))
+}
+
+/// Debug log, will not show in release.
+pub macro debug($($args:tt)*) {
+    $crate::log::log_target($crate::log::Level::Debug, module_path!(), format_args!($($args)*))
+}
\ No newline at end of file
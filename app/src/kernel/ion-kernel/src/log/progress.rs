@@ -0,0 +1,145 @@
+//! Progress indicators on the [status line](crate::text::STATUS_LINE).
+//! 
+//! Slow work, such as initialization or checking a disk, shows a [`Progress`], so a slow boot does
+//! not look like a frozen screen:
+//! 
+//! ```rust,no_run
+//! let mut progress = Progress::new("Checking blocks", blocks);
+//! for block in 0..blocks {
+//!     check(block);
+//!     progress.advance(1);
+//! }
+//! progress.finish();
+//! ```
+//! 
+//! Work with an unknown length shows a [spinner](Progress::spinner) instead of a bar. The
+//! [monitor](crate::monitor) shares the status line, and replaces the progress on its next refresh.
+use core::fmt::{self, Write};
+
+use crate::{collections::ArrayString, text::{BUFFER_WIDTH, STATUS_LINE, line_drawing::{FULL_BLOCK, LIGHT_SHADE}}};
+
+/// Width of the bar, in characters.
+pub const BAR_WIDTH: usize = 20;
+
+/// The frames of the spinner.
+pub const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// A progress bar or spinner, shown on the status line until it is finished or dropped.
+#[derive(Debug)]
+pub struct Progress {
+    label: &'static str,
+    /// `None` for a spinner.
+    total: Option<u64>,
+    done: u64,
+    frame: usize,
+}
+
+impl Progress {
+    /// Shows a bar for `total` steps, labeled `label`
+    pub fn new(label: &'static str, total: u64) -> Self {
+        Self::show(Self { label, total: Some(total), done: 0, frame: 0 })
+    }
+
+    /// Shows a spinner labeled `label`, for work with an unknown length.
+    pub fn spinner(label: &'static str) -> Self {
+        Self::show(Self { label, total: None, done: 0, frame: 0 })
+    }
+
+    fn show(self) -> Self {
+        self.draw();
+        self
+    }
+
+    /// The steps done so far.
+    pub fn done(&self) -> u64 {
+        self.done
+    }
+
+    /// The percentage done, `None` for a spinner.
+    /// 
+    /// An empty bar is always done.
+    pub fn percent(&self) -> Option<u8> {
+        let total = self.total?;
+        if total == 0 {
+            return Some(100);
+        }
+        // at most 100, so this always fits.
+        Some((u128::from(self.done.min(total)) * 100 / u128::from(total)) as u8)
+    }
+
+    /// Sets the steps done to `done`, and redraws the bar if its percentage changed.
+    pub fn set(&mut self, done: u64) {
+        let before = self.percent();
+        self.done = done;
+        if self.percent() != before {
+            self.draw();
+        }
+    }
+
+    /// Adds `steps` to the steps done, see [`set`](Self::set)
+    pub fn advance(&mut self, steps: u64) {
+        self.set(self.done.saturating_add(steps));
+    }
+
+    /// Moves the spinner to its next frame, and redraws it.
+    pub fn tick(&mut self) {
+        self.frame = (self.frame + 1) % SPINNER.len();
+        self.draw();
+    }
+
+    /// Writes the line shown for this progress, `[████░░░░]  42% label` or `[/] label`
+    pub fn write_line(&self, out: &mut impl Write) -> fmt::Result {
+        let Some(percent) = self.percent() else {
+            return write!(out, "[{}] {}", SPINNER[self.frame], self.label);
+        };
+        let filled = usize::from(percent) * BAR_WIDTH / 100;
+        out.write_char('[')?;
+        (0..BAR_WIDTH).try_for_each(|i| out.write_char(if i < filled { FULL_BLOCK } else { LIGHT_SHADE }))?;
+        write!(out, "] {percent:>3}% {}", self.label)
+    }
+
+    fn draw(&self) {
+        // multi byte characters take one cell, so this fits more than the bar.
+        let mut line = ArrayString::<{ BUFFER_WIDTH * 3 }>::new();
+        // a line which is too long is cut off.
+        let _ = self.write_line(&mut line);
+        STATUS_LINE.set(format_args!("{line}"));
+    }
+
+    /// Removes the progress from the status line.
+    pub fn finish(self) {
+        // see Drop
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        STATUS_LINE.set(format_args!(""));
+    }
+}
+
+/// Tests the bar and spinner lines.
+#[cfg(feature = "test")]
+pub fn test_progress(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    let mut line = ArrayString::<{ BUFFER_WIDTH * 3 }>::new();
+    let mut progress = Progress::new("test", 8);
+    test_assert_eq!(progress.percent(), Some(0))?;
+    progress.advance(3);
+    test_assert_eq!(progress.percent(), Some(37))?;
+    progress.write_line(&mut line).map_err(|_| "the line does not fit")?;
+    test_assert!(line.starts_with("[███████░"))?;
+    test_assert!(line.ends_with("]  37% test"))?;
+    progress.set(20);
+    test_assert_eq!(progress.percent(), Some(100))?;
+    progress.finish();
+    test_assert_eq!(Progress::new("empty", 0).percent(), Some(100))?;
+
+    let mut spinner = Progress::spinner("test");
+    test_assert_eq!(spinner.percent(), None)?;
+    spinner.tick();
+    line.clear();
+    spinner.write_line(&mut line).map_err(|_| "the line does not fit")?;
+    test_assert_eq!(line.as_str(), "[/] test")
+}
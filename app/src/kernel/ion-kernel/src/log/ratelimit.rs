@@ -0,0 +1,162 @@
+//! Rate limiting and deduplication of log messages.
+//! 
+//! Identical messages (same level, location and text) which follow each other within a window are
+//! collapsed into a single "previous message repeated N times" line, and at most [`BURST`]
+//! messages are logged per window. This keeps an interrupt storm (such as a stuck keyboard IRQ)
+//! from flooding the consoles.
+//! 
+//! Windows are measured in TSC cycles, so they are only roughly a fixed time.
+use core::{fmt::{self, Write}, hash::Hasher, panic::Location, sync::atomic::{AtomicBool, AtomicU64, Ordering}};
+
+use spin::Mutex;
+
+use crate::{collections::hash::FnvHasher, log::Level};
+
+/// Default window length, in TSC cycles (roughly a second on current hardware).
+pub const DEFAULT_WINDOW: u64 = 2_000_000_000;
+/// Maximum amount of messages logged per window.
+pub const BURST: u32 = 64;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static WINDOW: AtomicU64 = AtomicU64::new(DEFAULT_WINDOW);
+
+static STATE: Mutex<State> = Mutex::new(State {
+    last: None,
+    last_seen: 0,
+    repeated: 0,
+    window_start: 0,
+    logged: 0,
+    dropped: 0,
+});
+
+struct State {
+    /// Hash of the last logged message.
+    last: Option<u64>,
+    last_seen: u64,
+    /// How often the last message was suppressed.
+    repeated: u32,
+    window_start: u64,
+    /// Messages logged in the current window.
+    logged: u32,
+    /// Messages dropped as the burst limit was reached, since the last logged message. This is
+    /// kept across windows, so it is reported even if the first message of the next window is a
+    /// repeat.
+    dropped: u32,
+}
+
+/// Enables or disables rate limiting.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Sets the window length, in TSC cycles.
+pub fn set_window(cycles: u64) {
+    WINDOW.store(cycles, Ordering::Relaxed);
+}
+
+/// Messages which were not logged, and should be reported before the next message.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Suppressed {
+    /// How often the previous message was repeated.
+    pub repeated: u32,
+    /// How many messages were dropped because of the burst limit.
+    pub dropped: u32,
+}
+
+impl Suppressed {
+    /// Returns wether nothing was suppressed.
+    pub fn is_empty(&self) -> bool {
+        self.repeated == 0 && self.dropped == 0
+    }
+}
+
+impl fmt::Display for Suppressed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.repeated > 0 {
+            write!(f, "previous message repeated {} times", self.repeated)?;
+        }
+        if self.dropped > 0 {
+            if self.repeated > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} messages dropped by rate limit", self.dropped)?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of [`check`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Log the message, after reporting the suppressed messages.
+    Log(Suppressed),
+    /// Do not log the message.
+    Suppress,
+}
+
+struct HashWriter(FnvHasher);
+
+impl Write for HashWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+fn hash_message(level: Level, loc: &Location, args: fmt::Arguments) -> u64 {
+    let mut hasher = HashWriter(FnvHasher::default());
+    hasher.0.write_u8(level as u8);
+    let _ = write!(hasher, "{loc}{args}");
+    hasher.0.finish()
+}
+
+/// Decides wether a message should be logged.
+pub fn check(level: Level, loc: &Location, args: fmt::Arguments) -> Verdict {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Verdict::Log(Suppressed::default());
+    }
+    let hash = hash_message(level, loc, args);
+    // Safety: rdtsc has no side effects.
+    let now = unsafe { core::arch::x86_64::_rdtsc() };
+    let window = WINDOW.load(Ordering::Relaxed);
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut state = STATE.lock();
+        if now.wrapping_sub(state.window_start) >= window {
+            state.window_start = now;
+            state.logged = 0;
+        }
+
+        if state.last == Some(hash) && now.wrapping_sub(state.last_seen) < window {
+            state.last_seen = now;
+            state.repeated += 1;
+            return Verdict::Suppress;
+        }
+
+        if state.logged >= BURST {
+            state.dropped += 1;
+            return Verdict::Suppress;
+        }
+
+        let suppressed = Suppressed {
+            repeated: core::mem::take(&mut state.repeated),
+            dropped: core::mem::take(&mut state.dropped),
+        };
+        state.last = Some(hash);
+        state.last_seen = now;
+        state.logged += 1;
+        Verdict::Log(suppressed)
+    })
+}
+
+/// Returns the messages suppressed so far, and resets the counters, so they can be reported
+/// without waiting for the next message.
+pub fn take_suppressed() -> Suppressed {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut state = STATE.lock();
+        Suppressed {
+            repeated: core::mem::take(&mut state.repeated),
+            dropped: core::mem::take(&mut state.dropped),
+        }
+    })
+}
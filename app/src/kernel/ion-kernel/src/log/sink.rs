@@ -0,0 +1,224 @@
+//! Where log messages go.
+//! 
+//! Messages which pass the [level filters](super::enabled) and the [rate limit](super::ratelimit)
+//! are passed to every registered [`LogSink`] whose own level they reach. There are two sinks by
+//! default:
+//! - [`ConsoleSink`] (`console`): the [active console](crate::console::active), with colors.
+//! - [`SerialSink`] (`serial`): the serial port, as plain text.
+//! 
+//! So a release kernel can keep the console clean, while still streaming everything to serial:
+//! 
+//! ```rust,no_run
+//! log::set_max_level(Level::Trace);
+//! log::sink::set_level("console", Level::Warn);
+//! ```
+use core::{fmt, panic::Location};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{Level, ratelimit::Suppressed};
+use crate::{collections::CapacityError, console, serial::{self, config::Role}, text::{Color, print, println, query_print_color, set_print_color}};
+
+/// Maximum amount of registered sinks.
+pub const MAX_SINKS: usize = 8;
+
+/// A message to be logged.
+#[derive(Debug, Clone, Copy)]
+pub struct Record<'a> {
+    /// The level of the message.
+    pub level: Level,
+    /// The module path the message was logged from.
+    pub target: &'a str,
+    /// Where the message was logged.
+    pub location: &'static Location<'static>,
+    /// The message.
+    pub args: fmt::Arguments<'a>,
+    /// Messages which were suppressed by the rate limit, since the last one logged.
+    pub suppressed: Suppressed,
+}
+
+/// An output for log messages.
+pub trait LogSink: Sync {
+    /// The name used to configure this sink.
+    fn name(&self) -> &'static str;
+
+    /// Writes `record`, it is already filtered by the levels.
+    /// 
+    /// Called from any context, including interrupt handlers.
+    fn log(&self, record: &Record<'_>);
+}
+
+impl fmt::Debug for dyn LogSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("LogSink").field(&self.name()).finish()
+    }
+}
+
+/// Writes to the [active console](crate::console::active), with the level in color.
+#[derive(Debug, Clone, Copy)]
+pub struct ConsoleSink;
+
+impl LogSink for ConsoleSink {
+    fn name(&self) -> &'static str {
+        "console"
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !record.suppressed.is_empty() {
+            println!("[...] {}", record.suppressed);
+        }
+        let (fore, back) = query_print_color().tupled();
+        print!("[");
+        let col = match record.level {
+            Level::Debug => Color::Green,
+            Level::Error => Color::LightRed,
+            Level::Trace => Color::Magenta,
+            Level::Info => Color::LightCyan,
+            Level::Warn => Color::Yellow
+        };
+        set_print_color(col, Color::Black);
+
+        print!("{:?}", record.level);
+
+        set_print_color(fore, back);
+        println!(" {}] {}", record.location, record.args);
+    }
+}
+
+/// Writes to the [serial ports](crate::serial), without colors.
+/// 
+/// Skips messages while the active console is the early debug port, or the serial console on the
+/// same port, which shows them already.
+#[derive(Debug, Clone, Copy)]
+pub struct SerialSink;
+
+impl LogSink for SerialSink {
+    fn name(&self) -> &'static str {
+        "serial"
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        let shown = match console::active().name() {
+            "early" => true,
+            "serial" => serial::config::role(Role::Log) == serial::config::role(Role::Console),
+            _ => false,
+        };
+        if shown {
+            return;
+        }
+        if !record.suppressed.is_empty() {
+            let _ = serial::write_fmt(format_args!("[...] {}\n", record.suppressed));
+        }
+        let _ = serial::write_fmt(format_args!("[{:?} {}] {}\n", record.level, record.location, record.args));
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Registered {
+    sink: &'static dyn LogSink,
+    /// The least severe level passed to the sink.
+    level: Level,
+}
+
+static SINKS: Mutex<[Option<Registered>; MAX_SINKS]> = Mutex::new({
+    let mut sinks = [None; MAX_SINKS];
+    sinks[0] = Some(Registered { sink: &ConsoleSink, level: Level::Trace });
+    sinks[1] = Some(Registered { sink: &SerialSink, level: Level::Trace });
+    sinks
+});
+
+/// Registers `sink`, which is passed messages at least as severe as `level`
+/// # Errors
+/// Returns an error if there are [`MAX_SINKS`] already, or one with the same name.
+pub fn register(sink: &'static dyn LogSink, level: Level) -> Result<(), CapacityError> {
+    without_interrupts(|| {
+        let mut sinks = SINKS.lock();
+        if sinks.iter().flatten().any(|r| r.sink.name() == sink.name()) {
+            return Err(CapacityError(()));
+        }
+        let free = sinks.iter_mut().find(|r| r.is_none()).ok_or(CapacityError(()))?;
+        *free = Some(Registered { sink, level });
+        Ok(())
+    })
+}
+
+/// Unregisters the sink called `name`, returns wether it was registered.
+pub fn unregister(name: &str) -> bool {
+    without_interrupts(|| {
+        let mut sinks = SINKS.lock();
+        let registered = sinks.iter_mut().find(|r| r.is_some_and(|r| r.sink.name() == name));
+        registered.map(Option::take).is_some()
+    })
+}
+
+/// Sets the least severe level passed to the sink called `name`, returns wether it is registered.
+/// 
+/// Messages also have to pass [`max_level`](super::max_level), or the level of their target.
+pub fn set_level(name: &str, level: Level) -> bool {
+    without_interrupts(|| {
+        let mut sinks = SINKS.lock();
+        let registered = sinks.iter_mut().flatten().find(|r| r.sink.name() == name);
+        registered.map(|r| r.level = level).is_some()
+    })
+}
+
+/// The least severe level passed to the sink called `name`, if it is registered.
+pub fn level(name: &str) -> Option<Level> {
+    without_interrupts(|| SINKS.lock().iter().flatten().find(|r| r.sink.name() == name).map(|r| r.level))
+}
+
+/// Calls `f` with the name and level of every registered sink.
+pub fn for_each(mut f: impl FnMut(&'static str, Level)) {
+    let sinks = without_interrupts(|| *SINKS.lock());
+    sinks.iter().flatten().for_each(|r| f(r.sink.name(), r.level));
+}
+
+/// Passes `record` to every sink whose level it reaches.
+pub(super) fn dispatch(record: &Record<'_>) {
+    // copied out, so a sink may log, or change the sinks.
+    let sinks = without_interrupts(|| *SINKS.lock());
+    for registered in sinks.iter().flatten().filter(|r| record.level >= r.level) {
+        registered.sink.log(record);
+    }
+}
+
+/// Tests registering sinks, and filtering by their levels.
+#[cfg(feature = "test")]
+pub fn test_log_sinks(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::test::{test_assert, test_assert_eq};
+
+    static LOGGED: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    struct Counter;
+
+    impl LogSink for Counter {
+        fn name(&self) -> &'static str {
+            "test-counter"
+        }
+
+        fn log(&self, record: &Record<'_>) {
+            if record.target.ends_with("::sink") {
+                LOGGED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    let max_level = super::max_level();
+    super::set_max_level(Level::Trace);
+    register(&Counter, Level::Warn).map_err(|_| "the sink was not registered")?;
+    test_assert!(register(&Counter, Level::Trace).is_err())?;
+    test_assert_eq!(level("test-counter"), Some(Level::Warn))?;
+    super::info!("test: not passed to the counter");
+    super::warn!("test: passed to the counter");
+    test_assert!(set_level("test-counter", Level::Trace))?;
+    super::info!("test: passed to the counter too");
+    test_assert!(unregister("test-counter"))?;
+    super::warn!("test: the counter is unregistered");
+    super::set_max_level(max_level);
+    test_assert_eq!(LOGGED.load(Ordering::Relaxed), 2)?;
+    test_assert!(!set_level("test-counter", Level::Trace))
+}
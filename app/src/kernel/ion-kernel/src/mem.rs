@@ -1,174 +0,0 @@
-use core::ptr::NonNull;
-
-use x86_64::{
-    PhysAddr, VirtAddr, structures::paging::{OffsetPageTable, PageTable}
-};
-
-use crate::{c_lib::{PHYSICAL_MEMORY_OFFSET, USABLE_ENTRY}, serial_println};
-
-/// Returns a mutable reference to the active level 4 table.
-///
-/// # Safety
-/// This function is unsafe because the caller must guarantee that the
-/// complete physical memory is mapped to virtual memory at the passed
-/// `physical_memory_offset`. Also, this function must be only called once
-/// to avoid aliasing `&mut` references (which is undefined behavior).
-pub unsafe fn active_level_4_table()
-    -> &'static mut PageTable
-{
-    let physical_memory_offset = VirtAddr::new(PHYSICAL_MEMORY_OFFSET as u64);
-
-    use x86_64::registers::control::Cr3;
-
-    let (level_4_table_frame, _) = Cr3::read();
-
-    let phys = level_4_table_frame.start_address();
-    let virt = physical_memory_offset + phys.as_u64();
-    let page_table_ptr: *mut PageTable = virt.as_mut_ptr();
-
-    unsafe { &mut *page_table_ptr }
-}
-
-/// Translates the given virtual address to the mapped physical address, or
-/// `None` if the address is not mapped.
-pub fn translate_addr(addr: VirtAddr)
-    -> Option<PhysAddr>
-{
-    translate_addr_inner(addr, VirtAddr::new(PHYSICAL_MEMORY_OFFSET as u64))
-}
-
-/// Private function that is called by `translate_addr`.
-///
-/// This function is safe to limit the scope of `unsafe` because Rust treats
-/// the whole body of unsafe functions as an unsafe block. This function must
-/// only be reachable through `unsafe fn` from outside of this module.
-fn translate_addr_inner(addr: VirtAddr, physical_memory_offset: VirtAddr)
-    -> Option<PhysAddr>
-{
-    use x86_64::registers::control::Cr3;
-    use x86_64::structures::paging::PageTable;
-
-    let (level_4_table_frame, _) = Cr3::read();
-    let table_indexes = [addr.p4_index(), addr.p3_index(), addr.p2_index(), addr.p1_index()];
-    let frame = level_4_table_frame;
-
-    for (level, &index) in table_indexes.iter().enumerate() {
-        let virt = physical_memory_offset + frame.start_address().as_u64();
-        let table_ptr: *const PageTable = virt.as_ptr();
-        let table = unsafe { &*table_ptr };
-
-        let entry = &table[index];
-        // handle 2MiB huge page at PD level
-        if level == 2 {
-            let start = entry.addr();
-            serial_println!("{:?}", start);
-            return Some(start + u64::from(addr.page_offset()));
-        }
-    }
-
-    Some(frame.start_address() + u64::from(addr.page_offset()))
-}
-
-/// Initialize a new OffsetPageTable.
-///
-/// # Safety
-/// this function must be only called once
-/// to avoid aliasing `&mut` references (which is undefined behavior).
-pub unsafe fn init() -> OffsetPageTable<'static> {
-    unsafe {
-        let level_4_table = active_level_4_table();
-        OffsetPageTable::new(level_4_table, VirtAddr::new(PHYSICAL_MEMORY_OFFSET as u64))
-    }
-}
-
-use x86_64::{
-    structures::paging::{Page, PhysFrame, Mapper, Size4KiB, FrameAllocator}
-};
-
-/// Creates an example mapping for the given page to frame `0xb8000`.
-pub fn create_example_mapping(
-    page: Page,
-    mapper: &mut OffsetPageTable,
-    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
-) {
-    use x86_64::structures::paging::PageTableFlags as Flags;
-
-    let frame = PhysFrame::containing_address(PhysAddr::new(0xb8000));
-    let flags = Flags::PRESENT | Flags::WRITABLE;
-
-    let map_to_result = unsafe {
-        // FIXME: this is not safe, we do it only for testing
-        mapper.map_to(page, frame, flags, frame_allocator)
-    };
-    map_to_result.expect("map_to failed").flush();
-}
-
-/// A FrameAllocator that always returns `None`.
-#[derive(Debug)]
-pub struct EmptyFrameAllocator;
-
-unsafe impl FrameAllocator<Size4KiB> for EmptyFrameAllocator {
-    fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        None
-    }
-}
-
-use crate::c_lib::MultibootMemory;
-
-/// A FrameAllocator that returns usable frames from the bootloader's memory map.
-#[derive(Debug)]
-pub struct BootInfoFrameAllocator {
-    memory_map: NonNull<MultibootMemory>,
-    next: usize,
-}
-
-impl BootInfoFrameAllocator {
-    /// Create a FrameAllocator from the passed memory map.
-    ///
-    /// # Safety
-    /// This function is unsafe because the caller must guarantee that the passed
-    /// memory map is valid. The main requirement is that all frames that are marked
-    /// as `USABLE` in it are really unused.
-    /// 
-    /// Also, The pointer Must point to a valid [`MultibootMemory`] map
-    pub unsafe fn init(memory_map: NonNull<MultibootMemory>) -> Self {
-        BootInfoFrameAllocator {
-            memory_map,
-            next: 0,
-        }
-    }
-}
-
-impl BootInfoFrameAllocator {
-    /// Returns an iterator over the usable frames specified in the memory map.
-    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
-        // Safety: we ensure the Memory Map is always a valid pointer.
-        // We Also ensure that the pointer is not being used elsewhere (asynchronously)
-        let mem_ref = unsafe { self.memory_map.as_ref() };
-
-        // get usable regions from memory map
-        let regions = (mem_ref.entries).iter();
-        let usable_regions = regions
-            .filter(|r| r.entry_type == USABLE_ENTRY);
-        // map each region to its address range
-        let addr_ranges = usable_regions
-            .map(|r| r.start_addr()..r.end_addr());
-        // transform to an iterator of frame start addresses
-        let frame_addresses = addr_ranges.flat_map(|r| r.step_by(4096));
-        // create `PhysFrame` types from the start addresses
-        frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr as u64)))
-    }
-}
-
-unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
-    /// Allocate a frame of the appropriate size and return it if possible.
-    /// 
-    /// # Panics
-    /// panics if the next frame is outside of usize range
-    fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        let mut iter = self.usable_frames();
-        let frame = iter.nth(self.next);
-        self.next = self.next.strict_add(1);
-        frame
-    }
-}
\ No newline at end of file
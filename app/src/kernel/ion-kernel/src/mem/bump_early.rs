@@ -0,0 +1,120 @@
+//! Early boot allocator
+//! 
+//! A fixed, static bump allocator usable before [`init_heap`](crate::lib_alloc::init_heap) is called,
+//! so early subsystems (memory map copies, ACPI parsing, etc.) do not have to panic just because the
+//! heap is not up yet.
+//! 
+//! Memory allocated here is never freed, and lives for the rest of the kernel. Once the heap is
+//! initialized, [`hand_off`] seals the allocator and reports what it allocated.
+use core::{alloc::Layout, cell::UnsafeCell, fmt::Display, ptr::NonNull, sync::atomic::{AtomicBool, AtomicUsize, Ordering}};
+
+/// Size of the early allocation pool.
+pub const EARLY_POOL_SIZE: usize = 16 * 1024; // 16 KiB
+
+#[repr(C, align(4096))]
+struct Pool(UnsafeCell<[u8; EARLY_POOL_SIZE]>);
+
+// Safety: every byte of the pool is handed out at most once, see `alloc`.
+unsafe impl Sync for Pool {}
+
+static POOL: Pool = Pool(UnsafeCell::new([0; EARLY_POOL_SIZE]));
+/// Offset of the next free byte in [`POOL`]
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+/// Amount of successful allocations.
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+/// Set once the heap took over.
+static HANDED_OFF: AtomicBool = AtomicBool::new(false);
+
+/// Allocates memory for `layout` from the early pool.
+/// 
+/// Returns [`None`] if the pool is exhausted, or if the allocator was already [handed off](hand_off).
+pub fn alloc(layout: Layout) -> Option<NonNull<u8>> {
+    if HANDED_OFF.load(Ordering::Acquire) {
+        return None;
+    }
+    let base = POOL.0.get() as usize;
+
+    let mut current = NEXT.load(Ordering::Relaxed);
+    loop {
+        let start = (base + current).checked_next_multiple_of(layout.align())? - base;
+        let end = start.checked_add(layout.size())?;
+        if end > EARLY_POOL_SIZE {
+            return None;
+        }
+        match NEXT.compare_exchange_weak(current, end, Ordering::AcqRel, Ordering::Relaxed) {
+            Ok(_) => {
+                ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+                // Safety: `start` is inside of the pool, which is never null.
+                return Some(unsafe { NonNull::new_unchecked((base + start) as *mut u8) });
+            }
+            Err(actual) => current = actual,
+        }
+    }
+}
+
+/// Moves `val` into the early pool, returning a reference to it.
+pub fn alloc_value<T>(val: T) -> Option<&'static mut T> {
+    let ptr = alloc(Layout::new::<T>())?.cast::<T>();
+    // Safety: the memory is fresh, properly aligned and large enough for a `T`.
+    unsafe {
+        ptr.write(val);
+        Some(&mut *ptr.as_ptr())
+    }
+}
+
+/// Copies `src` into the early pool, returning a reference to the copy.
+/// 
+/// Useful for copying boot structures (like the memory map) before they may be overwritten.
+pub fn alloc_slice_copy<T: Copy>(src: &[T]) -> Option<&'static mut [T]> {
+    let ptr = alloc(Layout::array::<T>(src.len()).ok()?)?.cast::<T>();
+    // Safety: the memory is fresh, properly aligned and large enough for `src.len()` `T`s.
+    unsafe {
+        core::ptr::copy_nonoverlapping(src.as_ptr(), ptr.as_ptr(), src.len());
+        Some(core::slice::from_raw_parts_mut(ptr.as_ptr(), src.len()))
+    }
+}
+
+/// Returns wether `ptr` was allocated by the early allocator.
+pub fn contains<T: ?Sized>(ptr: *const T) -> bool {
+    let base = POOL.0.get() as usize;
+    (base..base + EARLY_POOL_SIZE).contains(&(ptr as *const u8 as usize))
+}
+
+/// A record of what the early allocator allocated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EarlyAllocReport {
+    /// Amount of successful allocations
+    pub allocations: usize,
+    /// Bytes used in the pool (including alignment padding)
+    pub bytes_used: usize,
+    /// Size of the pool.
+    pub capacity: usize,
+}
+
+impl Display for EarlyAllocReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} allocations, {}/{} bytes used", self.allocations, self.bytes_used, self.capacity)
+    }
+}
+
+/// Returns a record of the current state of the early allocator.
+pub fn report() -> EarlyAllocReport {
+    EarlyAllocReport {
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        bytes_used: NEXT.load(Ordering::Relaxed),
+        capacity: EARLY_POOL_SIZE,
+    }
+}
+
+/// Hands off to the heap, sealing the early allocator.
+/// 
+/// All allocations made so far stay valid, but any further call to [`alloc`] returns [`None`].
+pub fn hand_off() -> EarlyAllocReport {
+    HANDED_OFF.store(true, Ordering::Release);
+    report()
+}
+
+/// Returns wether the early allocator was handed off to the heap.
+pub fn is_handed_off() -> bool {
+    HANDED_OFF.load(Ordering::Acquire)
+}
@@ -0,0 +1,205 @@
+//! Page table debugging tools
+//! 
+//! [`dump_mappings`] walks the active page tables and prints every mapping (coalesced into ranges)
+//! to serial, which helps to diagnose corrupt pointer bugs. The `mappings` shell command prints
+//! them to the console, see [`mappings_command`]
+use core::{fmt::Display, ops::Range};
+
+use x86_64::{
+    VirtAddr,
+    registers::control::Cr3,
+    structures::paging::{PageTable, PageTableFlags},
+};
+
+use crate::{c_lib::PHYSICAL_MEMORY_OFFSET, serial_println};
+
+/// Flags that are only set by the CPU, and should not prevent mappings from being coalesced.
+const CPU_SET_FLAGS: PageTableFlags = PageTableFlags::ACCESSED.union(PageTableFlags::DIRTY);
+
+/// Selects which mappings are printed by [`dump_mappings`]
+/// 
+/// The default filter selects everything.
+#[derive(Debug, Clone)]
+pub struct MappingFilter {
+    /// Only print mappings overlapping this virtual range.
+    pub range: Option<Range<u64>>,
+    /// Only print mappings that have all of these flags.
+    pub flags: PageTableFlags,
+}
+
+impl Default for MappingFilter {
+    fn default() -> Self {
+        Self { range: None, flags: PageTableFlags::empty() }
+    }
+}
+
+impl MappingFilter {
+    /// Returns wether `mapping` is selected by this filter.
+    pub fn matches(&self, mapping: &Mapping) -> bool {
+        let in_range = self.range.as_ref().is_none_or(|r| r.start < mapping.virt.end && mapping.virt.start < r.end);
+        in_range && mapping.flags.contains(self.flags)
+    }
+}
+
+/// A contiguous range of virtual memory, mapped to contiguous physical memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    /// The virtual range
+    pub virt: Range<u64>,
+    /// The physical address `virt.start` maps to.
+    pub phys: u64,
+    /// The effective flags (writable/user only if every level allows it, no-execute if any level sets
+    /// it)
+    pub flags: PageTableFlags,
+}
+
+impl Mapping {
+    /// Size of the mapping in bytes.
+    pub fn len(&self) -> u64 {
+        self.virt.end - self.virt.start
+    }
+
+    /// Returns wether this mapping is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns wether `next` directly continues this mapping, both virtually and physically.
+    fn continued_by(&self, next: &Mapping) -> bool {
+        self.virt.end == next.virt.start
+            && self.phys + self.len() == next.phys
+            && self.flags.difference(CPU_SET_FLAGS) == next.flags.difference(CPU_SET_FLAGS)
+    }
+}
+
+impl Display for Mapping {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let flag = |flag, c| if self.flags.contains(flag) { c } else { '-' };
+        write!(
+            f,
+            "{:#018x}-{:#018x} -> {:#014x} [{}{}{}{}] {} KiB",
+            self.virt.start,
+            self.virt.end,
+            self.phys,
+            flag(PageTableFlags::WRITABLE, 'W'),
+            if self.flags.contains(PageTableFlags::NO_EXECUTE) { "NX" } else { "--" },
+            flag(PageTableFlags::USER_ACCESSIBLE, 'U'),
+            flag(PageTableFlags::GLOBAL, 'G'),
+            self.len() / 1024
+        )
+    }
+}
+
+/// Statistics returned by [`dump_mappings`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MappingStats {
+    /// Amount of printed ranges
+    pub ranges: usize,
+    /// Total amount of printed bytes.
+    pub bytes: u64,
+}
+
+/// Sign extends a 48 bit virtual address.
+const fn canonical(addr: u64) -> u64 {
+    ((addr << 16) as i64 >> 16) as u64
+}
+
+/// Walks `table` (at `level`, 4 being the top), calling `f` on every leaf mapping in order.
+fn walk_table(table: &PageTable, level: u8, base: u64, parent: PageTableFlags, f: &mut impl FnMut(Mapping)) {
+    let shift = 12 + 9 * (level as u64 - 1);
+    for (i, entry) in table.iter().enumerate() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+        let virt = canonical(base | ((i as u64) << shift));
+        let flags = super::combine_flags(parent, flags);
+
+        if level == 1 || (level <= 3 && flags.contains(PageTableFlags::HUGE_PAGE)) {
+            f(Mapping { virt: virt..virt + (1 << shift), phys: entry.addr().as_u64(), flags });
+        } else {
+            let next = VirtAddr::new(PHYSICAL_MEMORY_OFFSET as u64) + entry.addr().as_u64();
+            // Safety: present non-leaf entries always point to a page table, and all of physical
+            // memory is mapped at `PHYSICAL_MEMORY_OFFSET`.
+            let next = unsafe { &*next.as_ptr::<PageTable>() };
+            walk_table(next, level - 1, virt, flags, f);
+        }
+    }
+}
+
+/// Calls `f` on every mapping of the active page tables, coalescing contiguous mappings into one.
+pub fn for_each_mapping(mut f: impl FnMut(&Mapping)) {
+    let (l4_frame, _) = Cr3::read();
+    let l4 = VirtAddr::new(PHYSICAL_MEMORY_OFFSET as u64) + l4_frame.start_address().as_u64();
+    // Safety: CR3 always points to the active level 4 table.
+    let l4 = unsafe { &*l4.as_ptr::<PageTable>() };
+
+    let mut current: Option<Mapping> = None;
+    let all = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    walk_table(l4, 4, 0, all, &mut |mapping| {
+        match &mut current {
+            Some(cur) if cur.continued_by(&mapping) => cur.virt.end = mapping.virt.end,
+            _ => {
+                if let Some(done) = current.replace(mapping) {
+                    f(&done);
+                }
+            }
+        }
+    });
+    if let Some(done) = current {
+        f(&done);
+    }
+}
+
+/// Prints the mappings of the active page tables selected by `filter` to serial.
+/// 
+/// Each line has the form `virt start-virt end -> phys start [flags] size`, with the flags
+/// - `W`: writable
+/// - `NX`: no execute
+/// - `U`: user accessible
+/// - `G`: global
+pub fn dump_mappings(filter: &MappingFilter) -> MappingStats {
+    let mut stats = MappingStats::default();
+    serial_println!("Page table mappings (cr3 = {:?}):", Cr3::read().0.start_address());
+    for_each_mapping(|mapping| {
+        if filter.matches(mapping) {
+            serial_println!("  {}", mapping);
+            stats.ranges += 1;
+            stats.bytes += mapping.len();
+        }
+    });
+    serial_println!("  {} ranges, {} KiB mapped", stats.ranges, stats.bytes / 1024);
+    stats
+}
+
+/// The `mappings` shell command: `mappings [START END]` prints the mappings overlapping the
+/// virtual range, or all of them, like [`dump_mappings`]
+pub fn mappings_command(args: &[&str]) -> i32 {
+    use crate::{boot::cmdline::parse_size, text::println};
+
+    let range = match args {
+        [_] => None,
+        [_, start, end] => match (parse_size(start), parse_size(end)) {
+            (Ok(start), Ok(end)) if start < end => Some(start as u64..end as u64),
+            _ => {
+                println!("mappings: {start}..{end} is not a valid range");
+                return 2;
+            }
+        },
+        _ => {
+            println!("usage: mappings [START END]");
+            return 2;
+        }
+    };
+    let filter = MappingFilter { range, ..MappingFilter::default() };
+    let mut stats = MappingStats::default();
+    for_each_mapping(|mapping| {
+        if filter.matches(mapping) {
+            println!("{mapping}");
+            stats.ranges += 1;
+            stats.bytes += mapping.len();
+        }
+    });
+    println!("{} ranges, {} KiB mapped", stats.ranges, stats.bytes / 1024);
+    0
+}
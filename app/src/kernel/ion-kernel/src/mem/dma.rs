@@ -0,0 +1,279 @@
+//! Buffers which devices read and write directly, through DMA.
+//! 
+//! A [`DmaBuffer`] is allocated from a pool of physical memory [reserved](reserve) at boot, so it is
+//! physically contiguous, identity mapped, and never moved or handed out by the frame allocator.
+//! Drivers put its [physical address](DmaBuffer::phys_addr) in their descriptors, instead of a
+//! pointer into the heap, whose pages are not contiguous.
+//! 
+//! Either the CPU or the device owns the buffer. The CPU accesses it through [`Deref`] while it
+//! owns it, then [hands it](DmaBuffer::hand_to_device) to the device, which leaves only its
+//! address. The driver [reclaims](DeviceBuffer::reclaim) it once the device is done. The cache
+//! lines are flushed and the accesses fenced at both handovers, see [`Direction`].
+use core::{fmt::{self, Display}, marker::PhantomData, mem::{align_of, size_of}, ops::{Deref, DerefMut}, sync::atomic::{AtomicBool, Ordering}};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{c_lib::PHYSICAL_MEMORY_OFFSET, log::warn, mem::regions::{self, ReserveError}};
+
+/// Physical address of the DMA pool, right after the [kexec staging area](crate::power::kexec::STAGING_ADDR).
+pub const DMA_POOL_ADDR: u64 = 0x0300_0000;
+/// Size of the DMA pool.
+pub const DMA_POOL_SIZE: u64 = 0x40_0000;
+/// Buffers are allocated in pages of the pool.
+pub const PAGE_SIZE: u64 = 4096;
+const PAGES: usize = (DMA_POOL_SIZE / PAGE_SIZE) as usize;
+const CACHE_LINE: usize = 64;
+
+/// A page is used when its bit is set.
+static POOL: Mutex<[u64; PAGES / 64]> = Mutex::new([0; PAGES / 64]);
+static RESERVED: AtomicBool = AtomicBool::new(false);
+
+/// An error while allocating a [`DmaBuffer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaError {
+    /// The pool could not be reserved at boot.
+    Unavailable,
+    /// There are not enough contiguous free pages in the pool.
+    OutOfMemory,
+}
+
+impl Display for DmaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unavailable => write!(f, "the DMA pool was not reserved at boot"),
+            Self::OutOfMemory => write!(f, "the DMA pool is out of memory"),
+        }
+    }
+}
+
+impl core::error::Error for DmaError {}
+
+/// Who accesses a buffer while the device owns it, which decides the cache maintenance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// The device reads the buffer, its cache lines are written back before the handover.
+    ToDevice,
+    /// The device writes the buffer, its cache lines are dropped when it is reclaimed.
+    FromDevice,
+    /// The device reads and writes the buffer.
+    Bidirectional,
+}
+
+/// Reserves the DMA pool.
+/// 
+/// Must be called before the frame allocator is created.
+/// # Errors
+/// Returns an error if the pool is already reserved, then no buffers can be allocated.
+pub fn reserve() -> Result<(), ReserveError> {
+    regions::reserve(DMA_POOL_ADDR..DMA_POOL_ADDR + DMA_POOL_SIZE, "dma")?;
+    RESERVED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// The first run of `pages` free pages in `bitmap`
+pub fn find_free(bitmap: &[u64], pages: usize) -> Option<usize> {
+    let used = |page: usize| bitmap[page / 64] & (1 << (page % 64)) != 0;
+    let mut start = 0;
+    let total = bitmap.len() * 64;
+    while start + pages <= total {
+        match (start..start + pages).find(|&page| used(page)) {
+            Some(page) => start = page + 1,
+            None => return Some(start),
+        }
+    }
+    None
+}
+
+/// Marks `pages` pages from `start` on as used, or as free.
+fn mark(bitmap: &mut [u64], start: usize, pages: usize, used: bool) {
+    for page in start..start + pages {
+        if used {
+            bitmap[page / 64] |= 1 << (page % 64);
+        } else {
+            bitmap[page / 64] &= !(1 << (page % 64));
+        }
+    }
+}
+
+/// The amount of free pages in the pool.
+pub fn free_pages() -> usize {
+    let used: u32 = without_interrupts(|| POOL.lock().iter().map(|word| word.count_ones()).sum());
+    PAGES - used as usize
+}
+
+/// Writes back and drops the cache lines of `len` bytes at `addr`
+fn flush_cache(addr: u64, len: usize) {
+    for line in (addr as usize..addr as usize + len).step_by(CACHE_LINE) {
+        // Safety: the line is mapped, flushing does not change its contents.
+        unsafe { core::arch::x86_64::_mm_clflush(line as *const u8) };
+    }
+}
+
+/// A physically contiguous buffer owned by the CPU, see the [module docs](self)
+#[derive(Debug)]
+pub struct DmaBuffer<T> {
+    phys: u64,
+    pages: usize,
+    _value: PhantomData<T>,
+}
+
+// Safety: the buffer owns its value, like a `Box`.
+unsafe impl<T: Send> Send for DmaBuffer<T> {}
+// Safety: shared access only hands out `&T`
+unsafe impl<T: Sync> Sync for DmaBuffer<T> {}
+
+impl<T> DmaBuffer<T> {
+    /// Allocates a buffer from the pool, and moves `value` into it.
+    /// # Errors
+    /// Returns an error if the pool is unavailable or has no room left.
+    pub fn new(value: T) -> Result<Self, DmaError> {
+        const { assert!(align_of::<T>() <= PAGE_SIZE as usize, "DMA buffers are page aligned") };
+        if !RESERVED.load(Ordering::Relaxed) {
+            return Err(DmaError::Unavailable);
+        }
+        let pages = size_of::<T>().div_ceil(PAGE_SIZE as usize).max(1);
+        let start = without_interrupts(|| {
+            let mut pool = POOL.lock();
+            let start = find_free(&*pool, pages)?;
+            mark(&mut *pool, start, pages, true);
+            Some(start)
+        }).ok_or(DmaError::OutOfMemory)?;
+        let buffer = Self { phys: DMA_POOL_ADDR + start as u64 * PAGE_SIZE, pages, _value: PhantomData };
+        // Safety: the pages were just allocated, and are identity mapped.
+        unsafe { buffer.ptr().write(value) };
+        Ok(buffer)
+    }
+
+    /// The physical address of the value, which is page aligned.
+    pub fn phys_addr(&self) -> u64 {
+        self.phys
+    }
+
+    /// The size of the buffer in bytes, which is a whole amount of pages.
+    pub fn size(&self) -> usize {
+        self.pages * PAGE_SIZE as usize
+    }
+
+    fn ptr(&self) -> *mut T {
+        (PHYSICAL_MEMORY_OFFSET as u64 + self.phys) as *mut T
+    }
+
+    /// Hands the buffer to the device, which accesses it as `direction` says. The CPU can not
+    /// access it until it is [reclaimed](DeviceBuffer::reclaim).
+    pub fn hand_to_device(self, direction: Direction) -> DeviceBuffer<T> {
+        if direction != Direction::FromDevice {
+            flush_cache(self.ptr() as u64, size_of::<T>());
+        }
+        // the writes of the CPU are visible before the device is told about the buffer.
+        core::sync::atomic::fence(Ordering::SeqCst);
+        let buffer = DeviceBuffer { phys: self.phys, pages: self.pages, direction, _value: PhantomData };
+        core::mem::forget(self);
+        buffer
+    }
+}
+
+impl<T> Deref for DmaBuffer<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: initialized in `new`, and the CPU owns the buffer.
+        unsafe { &*self.ptr() }
+    }
+}
+
+impl<T> DerefMut for DmaBuffer<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: initialized in `new`, and the CPU owns the buffer exclusively.
+        unsafe { &mut *self.ptr() }
+    }
+}
+
+impl<T> Drop for DmaBuffer<T> {
+    fn drop(&mut self) {
+        // Safety: initialized in `new`, and dropped once.
+        unsafe { self.ptr().drop_in_place() };
+        let start = ((self.phys - DMA_POOL_ADDR) / PAGE_SIZE) as usize;
+        without_interrupts(|| mark(&mut *POOL.lock(), start, self.pages, false));
+    }
+}
+
+/// A [`DmaBuffer`] owned by a device, only its address is accessible.
+/// 
+/// Dropping it leaks its pages, as the device may still access them.
+#[derive(Debug)]
+pub struct DeviceBuffer<T> {
+    phys: u64,
+    pages: usize,
+    direction: Direction,
+    _value: PhantomData<T>,
+}
+
+// Safety: the CPU does not access the value, it only moves with the buffer.
+unsafe impl<T: Send> Send for DeviceBuffer<T> {}
+
+impl<T> DeviceBuffer<T> {
+    /// The physical address of the value, for the descriptors of the device.
+    pub fn phys_addr(&self) -> u64 {
+        self.phys
+    }
+
+    /// Reclaims the buffer from the device.
+    /// 
+    /// # Safety
+    /// The device must be done with the buffer, E.g. it signalled the completion of the command
+    /// which used it, and it must have left a valid `T` in it.
+    pub unsafe fn reclaim(self) -> DmaBuffer<T> {
+        // the reads of the CPU happen after the device signalled the completion.
+        core::sync::atomic::fence(Ordering::SeqCst);
+        let buffer = DmaBuffer::<T> { phys: self.phys, pages: self.pages, _value: PhantomData };
+        if self.direction != Direction::ToDevice {
+            flush_cache(buffer.ptr() as u64, size_of::<T>());
+        }
+        core::mem::forget(self);
+        buffer
+    }
+}
+
+impl<T> Drop for DeviceBuffer<T> {
+    fn drop(&mut self) {
+        warn!("A DMA buffer at {:#x} was dropped while the device owned it, its {} pages are leaked", self.phys, self.pages);
+    }
+}
+
+/// Tests allocating buffers and handing them to a device.
+#[cfg(feature = "test")]
+pub fn test_dma(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use x86_64::VirtAddr;
+
+    use crate::test::{test_assert, test_assert_eq};
+
+    let mut bitmap = [0u64; 2];
+    mark(&mut bitmap, 1, 3, true);
+    test_assert_eq!(find_free(&bitmap, 1), Some(0))?;
+    test_assert_eq!(find_free(&bitmap, 2), Some(4))?;
+    mark(&mut bitmap, 64, 60, true);
+    test_assert_eq!(find_free(&bitmap, 61), None)?;
+    test_assert_eq!(find_free(&bitmap, 60), Some(4))?;
+
+    let free = free_pages();
+    let mut buffer = DmaBuffer::new([0u8; PAGE_SIZE as usize + 1]).map_err(|_| "the buffer was not allocated")?;
+    test_assert_eq!((free_pages(), buffer.size()), (free - 2, 2 * PAGE_SIZE as usize))?;
+    test_assert!((DMA_POOL_ADDR..DMA_POOL_ADDR + DMA_POOL_SIZE).contains(&buffer.phys_addr()))?;
+    // contiguous, and mapped where the CPU accesses it.
+    let virt = buffer.as_ptr() as u64;
+    for offset in [0, PAGE_SIZE, 2 * PAGE_SIZE - 1] {
+        let phys = super::translate_addr(VirtAddr::new(virt + offset)).map(|phys| phys.as_u64());
+        test_assert_eq!(phys, Some(buffer.phys_addr() + offset))?;
+    }
+    buffer[PAGE_SIZE as usize] = 0xAB;
+
+    let device = buffer.hand_to_device(Direction::Bidirectional);
+    let phys = device.phys_addr();
+    // Safety: there is no device, the CPU wrote the value.
+    let buffer = unsafe { device.reclaim() };
+    test_assert_eq!(buffer.phys_addr(), phys)?;
+    test_assert_eq!(buffer[PAGE_SIZE as usize], 0xAB)?;
+    drop(buffer);
+    test_assert_eq!(free_pages(), free)
+}
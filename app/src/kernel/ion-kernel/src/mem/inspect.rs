@@ -0,0 +1,326 @@
+//! Live memory inspection
+//! 
+//! Lets developers read (peek) and write (poke) physical and virtual memory while the kernel runs,
+//! E.g. to inspect device memory or kernel structures. Every access is checked to be mapped (and
+//! writable for writes), so a typo does not page fault the kernel.
+//! 
+//! Writes require a [`PokeCapability`], which can only be created unsafely.
+//! 
+//! The `md` and `mw` shell commands dump and write memory, see [`md_command`] and [`mw_command`]
+use core::fmt::Display;
+
+use x86_64::{PhysAddr, VirtAddr, structures::paging::PageTableFlags};
+
+use crate::c_lib::PHYSICAL_MEMORY_OFFSET;
+
+use super::translate;
+
+/// Maximum amount of bytes that can be read at once.
+pub const MAX_READ: usize = 4096;
+
+/// An error while inspecting memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InspectError {
+    /// The address is not mapped.
+    Unmapped(u64),
+    /// The address is mapped, but not writable.
+    ReadOnly(u64),
+    /// The address is not aligned to the access width.
+    Misaligned {
+        /// The address
+        addr: u64,
+        /// The required alignment
+        align: usize,
+    },
+    /// The access is larger than [`MAX_READ`], or wraps around the address space.
+    TooLarge(usize),
+}
+
+impl Display for InspectError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Unmapped(a) => write!(f, "address {a:#x} is not mapped"),
+            Self::ReadOnly(a) => write!(f, "address {a:#x} is not writable"),
+            Self::Misaligned { addr, align } => write!(f, "address {addr:#x} is not aligned to {align} bytes"),
+            Self::TooLarge(len) => write!(f, "access of {len} bytes is too large"),
+        }
+    }
+}
+
+impl core::error::Error for InspectError {}
+
+/// The width of a structured access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    /// 1 byte
+    Byte,
+    /// 2 bytes
+    Word,
+    /// 4 bytes
+    DWord,
+    /// 8 bytes
+    QWord,
+}
+
+impl Width {
+    /// Size of the access in bytes, also its required alignment.
+    pub const fn size(self) -> usize {
+        match self {
+            Self::Byte => 1,
+            Self::Word => 2,
+            Self::DWord => 4,
+            Self::QWord => 8,
+        }
+    }
+
+    /// Returns the width for a size in bytes.
+    pub const fn from_size(size: usize) -> Option<Self> {
+        match size {
+            1 => Some(Self::Byte),
+            2 => Some(Self::Word),
+            4 => Some(Self::DWord),
+            8 => Some(Self::QWord),
+            _ => None,
+        }
+    }
+}
+
+/// Permission to write to arbitrary memory using [`write_virt`] and [`poke`]
+/// 
+/// The capability can not be created safely, so every write site has to acknowledge the danger.
+#[derive(Debug)]
+pub struct PokeCapability(());
+
+impl PokeCapability {
+    /// Creates a new capability
+    /// 
+    /// # Safety
+    /// Writing to arbitrary memory can corrupt any kernel state. The caller must ensure every write
+    /// done with this capability is sound, or is done on explicit request of the user.
+    pub unsafe fn new() -> Self {
+        Self(())
+    }
+}
+
+/// Checks every page in `addr..addr + len` is mapped (and writable, if `write` is set)
+fn check_range(addr: VirtAddr, len: usize, write: bool) -> Result<(), InspectError> {
+    if len > MAX_READ {
+        return Err(InspectError::TooLarge(len));
+    }
+    let end = addr.as_u64().checked_add(len as u64).ok_or(InspectError::TooLarge(len))?;
+    let mut page = addr.as_u64();
+    while page < end {
+        let t = translate(VirtAddr::try_new(page).map_err(|_| InspectError::Unmapped(page))?)
+            .ok_or(InspectError::Unmapped(page))?;
+        if write && !t.flags.contains(PageTableFlags::WRITABLE) {
+            return Err(InspectError::ReadOnly(page));
+        }
+        // continue at the start of the next page
+        page = (page & !(t.page_size - 1)) + t.page_size;
+    }
+    Ok(())
+}
+
+/// Reads `buf.len()` bytes of virtual memory starting at `addr` into `buf`.
+/// # Errors
+/// Returns an error if any of the bytes are unmapped, or `buf` is larger than [`MAX_READ`]
+pub fn read_virt(addr: VirtAddr, buf: &mut [u8]) -> Result<&[u8], InspectError> {
+    check_range(addr, buf.len(), false)?;
+    let src = addr.as_ptr::<u8>();
+    for (i, byte) in buf.iter_mut().enumerate() {
+        // Safety: we checked every byte is mapped, and volatile reads are fine for device memory.
+        *byte = unsafe { src.add(i).read_volatile() };
+    }
+    Ok(buf)
+}
+
+/// Reads `buf.len()` bytes of physical memory starting at `addr` into `buf`.
+/// # Errors
+/// Returns an error if any of the bytes are not mapped at the physical memory offset, or `buf` is
+/// larger than [`MAX_READ`]
+pub fn read_phys(addr: PhysAddr, buf: &mut [u8]) -> Result<&[u8], InspectError> {
+    read_virt(phys_to_virt(addr)?, buf)
+}
+
+/// Writes `data` to virtual memory starting at `addr`.
+/// # Errors
+/// Returns an error if any of the bytes are unmapped or read only, or `data` is larger than
+/// [`MAX_READ`]
+pub fn write_virt(_cap: &PokeCapability, addr: VirtAddr, data: &[u8]) -> Result<(), InspectError> {
+    check_range(addr, data.len(), true)?;
+    let dst = addr.as_mut_ptr::<u8>();
+    for (i, byte) in data.iter().enumerate() {
+        // Safety: we checked every byte is mapped and writable, and the capability's creator ensures
+        // the write is wanted.
+        unsafe { dst.add(i).write_volatile(*byte) };
+    }
+    Ok(())
+}
+
+/// Reads a single aligned value of `width` at `addr`, zero extended.
+/// # Errors
+/// Returns an error if `addr` is unmapped or not aligned to `width`
+pub fn peek(addr: VirtAddr, width: Width) -> Result<u64, InspectError> {
+    check_aligned(addr, width)?;
+    check_range(addr, width.size(), false)?;
+    // Safety: the address is mapped and aligned, reads are done with the exact width, as devices
+    // may depend on it.
+    unsafe {
+        Ok(match width {
+            Width::Byte => addr.as_ptr::<u8>().read_volatile() as u64,
+            Width::Word => addr.as_ptr::<u16>().read_volatile() as u64,
+            Width::DWord => addr.as_ptr::<u32>().read_volatile() as u64,
+            Width::QWord => addr.as_ptr::<u64>().read_volatile(),
+        })
+    }
+}
+
+/// Writes a single aligned value of `width` at `addr`, truncating `value`.
+/// # Errors
+/// Returns an error if `addr` is unmapped, read only, or not aligned to `width`
+pub fn poke(_cap: &PokeCapability, addr: VirtAddr, width: Width, value: u64) -> Result<(), InspectError> {
+    check_aligned(addr, width)?;
+    check_range(addr, width.size(), true)?;
+    // Safety: the address is mapped, writable and aligned, and the capability's creator ensures
+    // the write is wanted.
+    unsafe {
+        match width {
+            Width::Byte => addr.as_mut_ptr::<u8>().write_volatile(value as u8),
+            Width::Word => addr.as_mut_ptr::<u16>().write_volatile(value as u16),
+            Width::DWord => addr.as_mut_ptr::<u32>().write_volatile(value as u32),
+            Width::QWord => addr.as_mut_ptr::<u64>().write_volatile(value),
+        }
+    }
+    Ok(())
+}
+
+fn check_aligned(addr: VirtAddr, width: Width) -> Result<(), InspectError> {
+    if !addr.is_aligned(width.size() as u64) {
+        return Err(InspectError::Misaligned { addr: addr.as_u64(), align: width.size() });
+    }
+    Ok(())
+}
+
+/// Converts a physical address to the virtual address it is mapped at.
+fn phys_to_virt(addr: PhysAddr) -> Result<VirtAddr, InspectError> {
+    let virt = addr.as_u64()
+        .checked_add(PHYSICAL_MEMORY_OFFSET as u64)
+        .ok_or(InspectError::Unmapped(addr.as_u64()))?;
+    VirtAddr::try_new(virt).map_err(|_| InspectError::Unmapped(addr.as_u64()))
+}
+
+/// Formats bytes as a hex dump, 16 bytes per line, with an ASCII column.
+/// 
+/// # Example
+/// ```rust,no_run
+/// use crate::mem::inspect::{HexDump, read_virt};
+/// 
+/// let mut buf = [0; 64];
+/// let bytes = read_virt(VirtAddr::new(0xb8000), &mut buf).unwrap();
+/// serial_println!("{}", HexDump { addr: 0xb8000, bytes });
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct HexDump<'a> {
+    /// Address of the first byte, printed at the start of each line.
+    pub addr: u64,
+    /// The bytes to dump
+    pub bytes: &'a [u8],
+}
+
+impl Display for HexDump<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, line) in self.bytes.chunks(16).enumerate() {
+            write!(f, "{:#014x}: ", self.addr + i as u64 * 16)?;
+            for col in 0..16 {
+                match line.get(col) {
+                    Some(b) => write!(f, "{b:02x} ")?,
+                    None => write!(f, "   ")?,
+                }
+            }
+            write!(f, " |")?;
+            for &b in line {
+                let c = if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' };
+                write!(f, "{c}")?;
+            }
+            writeln!(f, "|")?;
+        }
+        Ok(())
+    }
+}
+
+/// The `md` shell command: `md [-p] ADDR [LEN]` dumps `LEN` bytes (64 by default) of virtual
+/// memory, or physical memory with `-p`
+pub fn md_command(args: &[&str]) -> i32 {
+    use crate::{boot::cmdline::parse_size, text::{print, println}};
+
+    let (phys, args) = match args {
+        [_, "-p", rest @ ..] => (true, rest),
+        [_, rest @ ..] => (false, rest),
+        [] => (false, args),
+    };
+    let (addr, len) = match args {
+        [addr] => (parse_size(addr), Ok(64)),
+        [addr, len] => (parse_size(addr), parse_size(len)),
+        _ => {
+            println!("usage: md [-p] ADDR [LEN]");
+            return 2;
+        }
+    };
+    let (Ok(addr), Ok(len)) = (addr, len) else {
+        println!("md: the address and length must be numbers");
+        return 2;
+    };
+    let mut buf = [0; MAX_READ];
+    let Some(buf) = buf.get_mut(..len) else {
+        println!("md: at most {MAX_READ} bytes can be dumped at once");
+        return 2;
+    };
+    let bytes = if phys {
+        read_phys(PhysAddr::new_truncate(addr as u64), buf)
+    } else {
+        VirtAddr::try_new(addr as u64)
+            .map_err(|_| InspectError::Unmapped(addr as u64))
+            .and_then(|addr| read_virt(addr, buf))
+    };
+    match bytes {
+        Ok(bytes) => {
+            print!("{}", HexDump { addr: addr as u64, bytes });
+            0
+        }
+        Err(e) => {
+            println!("md: {e}");
+            1
+        }
+    }
+}
+
+/// The `mw` shell command: `mw ADDR VALUE [BYTES]` writes `VALUE` as `BYTES` bytes (1, 2, 4 or 8,
+/// the default) to the aligned virtual `ADDR`
+pub fn mw_command(args: &[&str]) -> i32 {
+    use crate::{boot::cmdline::parse_size, text::println};
+
+    let (addr, value, width) = match args {
+        [_, addr, value] => (parse_size(addr), parse_size(value), Some(Width::QWord)),
+        [_, addr, value, bytes] => (parse_size(addr), parse_size(value), parse_size(bytes).ok().and_then(Width::from_size)),
+        _ => {
+            println!("usage: mw ADDR VALUE [BYTES]");
+            return 2;
+        }
+    };
+    let (Ok(addr), Ok(value), Some(width)) = (addr, value, width) else {
+        println!("mw: the address and value must be numbers, and the width 1, 2, 4 or 8 bytes");
+        return 2;
+    };
+    // Safety: the write is done on explicit request of the user.
+    let cap = unsafe { PokeCapability::new() };
+    let written = VirtAddr::try_new(addr as u64)
+        .map_err(|_| InspectError::Unmapped(addr as u64))
+        .and_then(|addr| poke(&cap, addr, width, value as u64));
+    match written {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("mw: {e}");
+            1
+        }
+    }
+}
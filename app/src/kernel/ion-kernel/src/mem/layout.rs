@@ -0,0 +1,140 @@
+//! A report of what lives where in memory, so that question does not need the linker script.
+//! 
+//! [`report`] writes it to serial at the end of boot:
+//! 
+//! ```text
+//! Memory layout:
+//!   kernel        0x000000100000..0x000000164000   400 KiB
+//!     .text       0x000000100020..0x000000148d31   291 KiB
+//!   ...
+//!   heap          0x444444440000..0x444444459000   100 KiB (12 KiB used)
+//!   ist 0         0x000000157000..0x00000015c000    20 KiB
+//!   task 1        0x444444441050..0x444444449050    32 KiB (kernel: boot stack)
+//!   reserved      0x000000000000..0x000000001000     4 KiB (bios data)
+//! ```
+//! 
+//! The kernel sections and interrupt stacks are identity mapped, so their addresses are physical
+//! too. Reserved regions, which include DMA pools and the frame buffer, are physical.
+use alloc::string::String;
+use core::{fmt::{self, Write}, ops::Range};
+
+use crate::{collections::ArrayString, interrupts::gdt, lib_alloc::{self, HEAP_START}, serial_println, task};
+
+unsafe extern "C" {
+    #[link_name = "__text_start"]
+    static TEXT_START: u8;
+    #[link_name = "__text_end"]
+    static TEXT_END: u8;
+    #[link_name = "__rodata_start"]
+    static RODATA_START: u8;
+    #[link_name = "__rodata_end"]
+    static RODATA_END: u8;
+    #[link_name = "__data_start"]
+    static DATA_START: u8;
+    #[link_name = "__data_end"]
+    static DATA_END: u8;
+    #[link_name = "__bss_start"]
+    static BSS_START: u8;
+    #[link_name = "__bss_end"]
+    static BSS_END: u8;
+}
+
+/// The sections of the kernel image, as defined by the linker script.
+pub fn kernel_sections() -> [(&'static str, Range<u64>); 4] {
+    [
+        (".text", (&raw const TEXT_START) as u64..(&raw const TEXT_END) as u64),
+        (".rodata", (&raw const RODATA_START) as u64..(&raw const RODATA_END) as u64),
+        (".data", (&raw const DATA_START) as u64..(&raw const DATA_END) as u64),
+        (".bss", (&raw const BSS_START) as u64..(&raw const BSS_END) as u64),
+    ]
+}
+
+/// Formats the label of a line, a label which is too long is cut off.
+fn label(args: fmt::Arguments) -> ArrayString<16> {
+    let mut label = ArrayString::new();
+    let _ = label.write_fmt(args);
+    label
+}
+
+/// Writes one line of the report.
+fn line(out: &mut impl Write, label: &str, range: &Range<u64>, note: fmt::Arguments) -> fmt::Result {
+    let size = range.end.saturating_sub(range.start).div_ceil(1024);
+    writeln!(out, "  {label:<14}{:#014x}..{:#014x} {size:>5} KiB{note}", range.start, range.end)
+}
+
+/// Writes the memory layout report to `out`
+pub fn write_report(out: &mut impl Write) -> fmt::Result {
+    writeln!(out, "Memory layout:")?;
+    line(out, "kernel", &super::regions::kernel_image(), format_args!(""))?;
+    for (name, range) in kernel_sections() {
+        line(out, &label(format_args!("  {name}")), &range, format_args!(""))?;
+    }
+
+    let used = lib_alloc::stats().live_bytes.div_ceil(1024);
+    line(out, "heap", &(HEAP_START as u64..lib_alloc::heap_end() as u64), format_args!(" ({used} KiB used)"))?;
+
+    let mut result = Ok(());
+    gdt::for_each_interrupt_stack(|index, range| {
+        result = result.and_then(|()| line(out, &label(format_args!("ist {index}")), &range, format_args!("")));
+    });
+    task::for_each_stack(|id, name, stack| {
+        result = result.and_then(|()| match stack {
+            Some(range) => line(out, &label(format_args!("task {id}")), &range, format_args!(" ({name})")),
+            None => writeln!(out, "  {:<14}{:>38} ({name}: boot stack)", label(format_args!("task {id}")).as_str(), ""),
+        });
+    });
+    super::regions::for_each(|region| {
+        result = result.and_then(|()| line(out, "reserved", &region.range, format_args!(" ({})", region.owner)));
+    });
+    result
+}
+
+/// Writes the memory layout report to serial.
+pub fn report() {
+    let mut report = String::new();
+    // writing to a String never fails.
+    let _ = write_report(&mut report);
+    serial_println!("{}", report);
+}
+
+/// The `mem` shell command: `mem` shows the heap usage, `mem map` the whole [report](write_report)
+pub fn mem_command(args: &[&str]) -> i32 {
+    use crate::text::{print, println};
+
+    match args {
+        [_] => {
+            let stats = lib_alloc::stats();
+            println!("heap: {} of {} KiB used, peak {} KiB, {} allocations",
+                stats.live_bytes.div_ceil(1024), lib_alloc::heap_size() / 1024,
+                stats.peak_bytes.div_ceil(1024), stats.live_allocations());
+        }
+        [_, "map"] => {
+            let mut report = String::new();
+            // writing to a String never fails.
+            let _ = write_report(&mut report);
+            print!("{report}");
+        }
+        _ => {
+            println!("usage: mem [map]");
+            return 2;
+        }
+    }
+    0
+}
+
+/// Tests that the report has every part of the layout.
+#[cfg(feature = "test")]
+pub fn test_memory_report(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::test_assert;
+
+    let mut report = String::new();
+    write_report(&mut report).map_err(|_| "the report was not written")?;
+    for part in ["kernel", ".text", ".rodata", ".data", ".bss", "heap", "ist 0", "(kernel: boot stack)", "(bios data)"] {
+        test_assert!(report.contains(part), "a part of the layout is missing")?;
+    }
+    // the sections are in the kernel image, in order.
+    let image = super::regions::kernel_image();
+    let sections = kernel_sections();
+    test_assert!(sections.iter().all(|(_, range)| image.start <= range.start && range.end <= image.end))?;
+    test_assert!(sections.windows(2).all(|pair| pair[0].1.end <= pair[1].1.start))
+}
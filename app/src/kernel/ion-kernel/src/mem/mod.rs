@@ -0,0 +1,288 @@
+use core::ptr::NonNull;
+
+use x86_64::{
+    PhysAddr, VirtAddr, structures::paging::{OffsetPageTable, PageSize, PageTable, PageTableFlags, Size1GiB, Size2MiB}
+};
+
+use crate::{c_lib::{PHYSICAL_MEMORY_OFFSET, USABLE_ENTRY}, log::warn};
+
+/// Allocator usable before the heap is initialized.
+pub mod bump_early;
+/// Reserved physical memory regions.
+pub mod regions;
+/// Page table debugging tools.
+pub mod debug;
+/// Live memory inspection (peek/poke).
+pub mod inspect;
+/// A report of the kernel's memory layout.
+pub mod layout;
+/// NUMA nodes of physical memory, from the ACPI SRAT.
+pub mod numa;
+/// Physically contiguous buffers for DMA.
+pub mod dma;
+/// Kernel stacks of tasks, with guard pages.
+pub mod stacks;
+/// Mapping and unmapping ranges of virtual memory.
+pub mod vmm;
+
+pub use layout::report;
+
+/// Returns a mutable reference to the active level 4 table.
+///
+/// # Safety
+/// This function is unsafe because the caller must guarantee that the
+/// complete physical memory is mapped to virtual memory at the passed
+/// `physical_memory_offset`. Also, this function must be only called once
+/// to avoid aliasing `&mut` references (which is undefined behavior).
+pub unsafe fn active_level_4_table()
+    -> &'static mut PageTable
+{
+    let physical_memory_offset = VirtAddr::new(PHYSICAL_MEMORY_OFFSET as u64);
+
+    use x86_64::registers::control::Cr3;
+
+    let (level_4_table_frame, _) = Cr3::read();
+
+    let phys = level_4_table_frame.start_address();
+    let virt = physical_memory_offset + phys.as_u64();
+    let page_table_ptr: *mut PageTable = virt.as_mut_ptr();
+
+    unsafe { &mut *page_table_ptr }
+}
+
+/// Translates the given virtual address to the mapped physical address, or
+/// `None` if the address is not mapped.
+pub fn translate_addr(addr: VirtAddr)
+    -> Option<PhysAddr>
+{
+    translate(addr).map(|t| t.phys)
+}
+
+/// The result of [`translate`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Translation {
+    /// The physical address
+    pub phys: PhysAddr,
+    /// The effective flags of the mapping (writable/user only if every level allows it, no-execute
+    /// if any level sets it)
+    pub flags: PageTableFlags,
+    /// Size of the page containing the address, in bytes.
+    pub page_size: u64,
+}
+
+/// Translates the given virtual address, returning the physical address and the flags of its
+/// mapping, or `None` if the address is not mapped.
+pub fn translate(addr: VirtAddr) -> Option<Translation> {
+    translate_addr_inner(addr, VirtAddr::new(PHYSICAL_MEMORY_OFFSET as u64))
+}
+
+/// Combines the flags of a parent table entry with a child entry.
+fn combine_flags(parent: PageTableFlags, entry: PageTableFlags) -> PageTableFlags {
+    let restricting = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    let mut flags = entry & !restricting | (entry & parent & restricting);
+    if parent.contains(PageTableFlags::NO_EXECUTE) {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+    flags
+}
+
+/// Private function that is called by `translate`.
+///
+/// This function is safe to limit the scope of `unsafe` because Rust treats
+/// the whole body of unsafe functions as an unsafe block. This function must
+/// only be reachable through `unsafe fn` from outside of this module.
+fn translate_addr_inner(addr: VirtAddr, physical_memory_offset: VirtAddr)
+    -> Option<Translation>
+{
+    use x86_64::registers::control::Cr3;
+
+    let (level_4_table_frame, _) = Cr3::read();
+    let table_indexes = [addr.p4_index(), addr.p3_index(), addr.p2_index(), addr.p1_index()];
+    let mut table_addr = level_4_table_frame.start_address();
+    let mut flags = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+
+    for (level, &index) in table_indexes.iter().enumerate() {
+        let virt = physical_memory_offset + table_addr.as_u64();
+        let table_ptr: *const PageTable = virt.as_ptr();
+        // Safety: `table_addr` always points to a page table, as it is either CR3, or a present
+        // non-leaf entry.
+        let table = unsafe { &*table_ptr };
+
+        let entry = &table[index];
+        if !entry.flags().contains(PageTableFlags::PRESENT) {
+            return None;
+        }
+        flags = combine_flags(flags, entry.flags());
+
+        // level 0 is the P4 table, which can not contain huge pages.
+        let page_size = match level {
+            1 if entry.flags().contains(PageTableFlags::HUGE_PAGE) => Some(Size1GiB::SIZE),
+            2 if entry.flags().contains(PageTableFlags::HUGE_PAGE) => Some(Size2MiB::SIZE),
+            3 => Some(Size4KiB::SIZE),
+            _ => None,
+        };
+        if let Some(page_size) = page_size {
+            return Some(Translation {
+                phys: entry.addr() + (addr.as_u64() & (page_size - 1)),
+                flags,
+                page_size,
+            });
+        }
+        table_addr = entry.addr();
+    }
+
+    unreachable!("the P1 table always contains a leaf entry")
+}
+
+/// Initialize a new OffsetPageTable.
+///
+/// # Safety
+/// this function must be only called once
+/// to avoid aliasing `&mut` references (which is undefined behavior).
+pub unsafe fn init() -> OffsetPageTable<'static> {
+    unsafe {
+        let level_4_table = active_level_4_table();
+        OffsetPageTable::new(level_4_table, VirtAddr::new(PHYSICAL_MEMORY_OFFSET as u64))
+    }
+}
+
+use x86_64::{
+    structures::paging::{Page, PhysFrame, Mapper, Size4KiB, FrameAllocator}
+};
+
+/// Creates an example mapping for the given page to frame `0xb8000`.
+pub fn create_example_mapping(
+    page: Page,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) {
+    use x86_64::structures::paging::PageTableFlags as Flags;
+
+    let frame = PhysFrame::containing_address(PhysAddr::new(0xb8000));
+    let flags = Flags::PRESENT | Flags::WRITABLE;
+
+    let map_to_result = unsafe {
+        // FIXME: this is not safe, we do it only for testing
+        mapper.map_to(page, frame, flags, frame_allocator)
+    };
+    map_to_result.expect("map_to failed").flush();
+}
+
+/// A FrameAllocator that always returns `None`.
+#[derive(Debug)]
+pub struct EmptyFrameAllocator;
+
+unsafe impl FrameAllocator<Size4KiB> for EmptyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        None
+    }
+}
+
+use crate::c_lib::{MemoryMapEntry, MultibootMemory};
+
+/// Copies the memory map into the [early allocator](bump_early), so the frame allocator does not
+/// keep reading the multiboot info for as long as the kernel runs.
+/// 
+/// Returns `memory_map` itself, if the early allocator is full.
+/// 
+/// # Safety
+/// The pointer Must point to a valid [`MultibootMemory`] map
+pub unsafe fn copy_memory_map(memory_map: NonNull<MultibootMemory>) -> NonNull<MultibootMemory> {
+    // Safety: guaranteed by the caller.
+    let map = unsafe { memory_map.as_ref() };
+    let Some(copy) = bump_early::alloc(core::alloc::Layout::for_value(map)) else {
+        warn!("The memory map was not copied, the early allocator is full");
+        return memory_map;
+    };
+    // Safety: the copy is fresh, and as large as the map.
+    unsafe { core::ptr::copy_nonoverlapping(memory_map.as_ptr() as *const u8, copy.as_ptr(), size_of_val(map)) };
+    let entries = core::ptr::slice_from_raw_parts_mut(copy.as_ptr() as *mut MemoryMapEntry, map.entries.len());
+    // Safety: the copy is not null, and has the layout of `map`.
+    unsafe { NonNull::new_unchecked(entries as *mut MultibootMemory) }
+}
+
+/// A FrameAllocator that returns usable frames from the bootloader's memory map.
+#[derive(Debug)]
+pub struct BootInfoFrameAllocator {
+    memory_map: NonNull<MultibootMemory>,
+    /// The address up to which frames were handed out on each node.
+    next: [u64; numa::MAX_NODES],
+}
+
+// Safety: the memory map is only read, and stays valid, so the allocator can be moved to the
+// virtual memory manager.
+unsafe impl Send for BootInfoFrameAllocator {}
+
+impl BootInfoFrameAllocator {
+    /// Create a FrameAllocator from the passed memory map.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must guarantee that the passed
+    /// memory map is valid. The main requirement is that all frames that are marked
+    /// as `USABLE` in it are really unused.
+    /// 
+    /// Also, The pointer Must point to a valid [`MultibootMemory`] map
+    pub unsafe fn init(memory_map: NonNull<MultibootMemory>) -> Self {
+        BootInfoFrameAllocator {
+            memory_map,
+            next: [0; numa::MAX_NODES],
+        }
+    }
+}
+
+impl BootInfoFrameAllocator {
+    /// The lowest usable frame in the memory map which starts at or above `addr`
+    fn usable_from(&self, addr: u64) -> Option<u64> {
+        // Safety: we ensure the Memory Map is always a valid pointer.
+        // We Also ensure that the pointer is not being used elsewhere (asynchronously)
+        let mem_ref = unsafe { self.memory_map.as_ref() };
+
+        mem_ref.entries.iter()
+            .filter(|r| r.entry_type == USABLE_ENTRY)
+            .filter_map(|r| {
+                let start = (r.start_addr() as u64).max(addr).next_multiple_of(4096);
+                (start + 4096 <= r.end_addr() as u64).then_some(start)
+            })
+            .min()
+    }
+
+    /// Whether a frame overlapping `range` was handed out, by any node.
+    pub fn handed_out(&self, range: &core::ops::Range<u64>) -> bool {
+        // frames are handed out in order of their address, so those of a node are all below its next.
+        self.next.iter().enumerate().any(|(node, &next)| {
+            numa::next_on(node, range.start).is_some_and(|first| first < range.end.min(next))
+        })
+    }
+
+    /// Allocate a frame on the NUMA `node`, see [`numa`]
+    /// 
+    /// Frames are handed out in order of their address, frames overlapping a
+    /// [reserved region](regions) are skipped, even if it was reserved after allocating started.
+    pub fn allocate_frame_on(&mut self, node: usize) -> Option<PhysFrame> {
+        let mut addr = self.next.get(node).copied()?;
+        // every step moves `addr` up, past a frame which can not be used.
+        loop {
+            let start = self.usable_from(addr)?;
+            let on_node = numa::next_on(node, start)?;
+            if on_node != start {
+                addr = on_node;
+                continue;
+            }
+            if let Some(reserved) = regions::find_overlapping(&(start..start + 4096)) {
+                addr = reserved.range.end;
+                continue;
+            }
+            self.next[node] = start + 4096;
+            return Some(PhysFrame::containing_address(PhysAddr::new(start)));
+        }
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
+    /// Allocate a frame of the appropriate size and return it if possible.
+    /// 
+    /// Frames are taken from the first node which has any left.
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        (0..numa::node_count()).find_map(|node| self.allocate_frame_on(node))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,110 @@
+//! The NUMA nodes of physical memory, from the memory affinities of the ACPI SRAT.
+//! 
+//! Without an SRAT, or for memory it does not list, everything is on node 0. The
+//! [`BootInfoFrameAllocator`](super::BootInfoFrameAllocator) hands out frames per node with
+//! [`allocate_frame_on`](super::BootInfoFrameAllocator::allocate_frame_on).
+use core::ops::Range;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{acpi, collections::ArrayVec, log::{debug, warn}};
+
+/// Maximum amount of nodes, higher proximity domains are ignored.
+pub const MAX_NODES: usize = 8;
+/// Maximum amount of ranges assigned to nodes.
+pub const MAX_RANGES: usize = 32;
+
+/// A physical range on a node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeRange {
+    /// The node, below [`MAX_NODES`]
+    pub node: usize,
+    /// The physical range (end exclusive)
+    pub range: Range<u64>,
+}
+
+static RANGES: Mutex<ArrayVec<NodeRange, MAX_RANGES>> = Mutex::new(ArrayVec::new());
+
+/// The node of the physical `addr` in `ranges`, 0 if no range contains it.
+pub fn node_in(ranges: &[NodeRange], addr: u64) -> usize {
+    ranges.iter().find(|r| r.range.contains(&addr)).map_or(0, |r| r.node)
+}
+
+/// The node of the physical `addr`
+pub fn node_of(addr: u64) -> usize {
+    without_interrupts(|| node_in(&RANGES.lock(), addr))
+}
+
+/// The lowest address from `addr` on which is on `node` in `ranges`, if there is one.
+pub fn next_in(ranges: &[NodeRange], node: usize, mut addr: u64) -> Option<u64> {
+    // every range is skipped at most once.
+    for _ in 0..=ranges.len() {
+        match ranges.iter().find(|r| r.range.contains(&addr)) {
+            Some(r) if r.node == node => return Some(addr),
+            Some(r) => addr = r.range.end,
+            // memory which is not listed is on node 0.
+            None if node == 0 => return Some(addr),
+            None => addr = ranges.iter().filter(|r| r.node == node && r.range.start > addr).map(|r| r.range.start).min()?,
+        }
+    }
+    (node_in(ranges, addr) == node).then_some(addr)
+}
+
+/// The lowest address from `addr` on which is on `node`, if there is one.
+pub fn next_on(node: usize, addr: u64) -> Option<u64> {
+    without_interrupts(|| next_in(&RANGES.lock(), node, addr))
+}
+
+/// The amount of nodes, which is at least 1.
+pub fn node_count() -> usize {
+    without_interrupts(|| RANGES.lock().iter().map(|r| r.node + 1).max().unwrap_or(1))
+}
+
+/// Calls `f` on every range assigned to a node.
+pub fn for_each_range(f: impl FnMut(&NodeRange)) {
+    without_interrupts(|| RANGES.lock().iter().for_each(f));
+}
+
+/// Assigns the ranges of the SRAT to nodes, after [`acpi::init`].
+/// 
+/// This should be called before the frame allocator is created.
+pub fn init() {
+    let Some(srat) = acpi::find(*b"SRAT") else {
+        debug!("There is no SRAT, all memory is on node 0");
+        return;
+    };
+    without_interrupts(|| {
+        let mut ranges = RANGES.lock();
+        for affinity in acpi::srat::memory_affinities(srat) {
+            let Some(node) = usize::try_from(affinity.domain).ok().filter(|&node| node < MAX_NODES) else {
+                warn!("The memory at {:#x?} is on node {}, which is not supported", affinity.range, affinity.domain);
+                continue;
+            };
+            debug!("node {node}: {:#012x}..{:#012x}", affinity.range.start, affinity.range.end);
+            if ranges.push(NodeRange { node, range: affinity.range }).is_err() {
+                warn!("The SRAT has more than {MAX_RANGES} memory ranges, the rest is on node 0");
+                break;
+            }
+        }
+    });
+}
+
+/// Tests finding the node of an address.
+#[cfg(feature = "test")]
+pub fn test_numa_nodes(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    let ranges = [
+        NodeRange { node: 0, range: 0..0x8000_0000 },
+        NodeRange { node: 1, range: 0x1_0000_0000..0x1_8000_0000 },
+    ];
+    test_assert_eq!(node_in(&ranges, 0x1000), 0)?;
+    test_assert_eq!(node_in(&ranges, 0x1_0000_0000), 1)?;
+    test_assert_eq!(node_in(&ranges, 0x1_8000_0000), 0)?;
+    test_assert_eq!(node_in(&[], 0x1_0000_0000), 0)?;
+    test_assert_eq!(next_in(&ranges, 1, 0x1000), Some(0x1_0000_0000))?;
+    test_assert_eq!(next_in(&ranges, 0, 0x1_0000_0000), Some(0x1_8000_0000))?;
+    test_assert_eq!(next_in(&ranges, 1, 0x1_8000_0000), None)?;
+    test_assert!(node_count() >= 1)
+}
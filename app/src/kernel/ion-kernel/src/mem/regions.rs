@@ -0,0 +1,187 @@
+//! Reserved physical memory regions
+//! 
+//! Tracks which physical ranges are in use by something other than the frame allocator (the kernel
+//! image, multiboot structures, ACPI tables, framebuffers, DMA pools, etc.), so the
+//! [`BootInfoFrameAllocator`](super::BootInfoFrameAllocator) never hands them out.
+//! 
+//! Regions should be reserved before the frame allocator is created, as frames that were already
+//! handed out can not be taken back. Later, only regions whose frames were not handed out yet can be
+//! reserved, with [`vmm::reserve_unallocated`](super::vmm::reserve_unallocated).
+use core::{fmt::Display, ops::Range};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::c_lib::{BootInfo, FrameBufferInfo};
+
+/// Maximum amount of reserved regions.
+pub const MAX_REGIONS: usize = 64;
+
+/// A reserved physical memory region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReservedRegion {
+    /// The physical range (end exclusive)
+    pub range: Range<u64>,
+    /// Name of the owner, E.g. `"kernel"`
+    pub owner: &'static str,
+}
+
+impl ReservedRegion {
+    /// Returns wether this region overlaps `range`.
+    pub fn overlaps(&self, range: &Range<u64>) -> bool {
+        self.range.start < range.end && range.start < self.range.end
+    }
+}
+
+impl Display for ReservedRegion {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:#012x}..{:#012x} ({})", self.range.start, self.range.end, self.owner)
+    }
+}
+
+/// An Error while reserving a region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReserveError {
+    /// The requested region overlaps an already reserved region.
+    Conflict {
+        /// The requested region
+        requested: ReservedRegion,
+        /// The region it conflicts with.
+        existing: ReservedRegion,
+    },
+    /// The requested range is empty.
+    Empty,
+    /// There is no room left in the registry, see [`MAX_REGIONS`]
+    Full,
+    /// A frame of the requested region was handed out by the frame allocator already.
+    Allocated,
+}
+
+impl Display for ReserveError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Conflict { requested, existing } => write!(f, "region {requested} conflicts with {existing}"),
+            Self::Empty => write!(f, "cannot reserve an empty region"),
+            Self::Full => write!(f, "the reserved region registry is full ({MAX_REGIONS} regions)"),
+            Self::Allocated => write!(f, "the region was handed out by the frame allocator already"),
+        }
+    }
+}
+
+impl core::error::Error for ReserveError {}
+
+static REGIONS: Mutex<[Option<ReservedRegion>; MAX_REGIONS]> = Mutex::new([const { None }; MAX_REGIONS]);
+
+/// Reserves the physical `range` for `owner`.
+/// # Errors
+/// Returns an error if the range is empty, overlaps a reserved region, or the registry is full.
+pub fn reserve(range: Range<u64>, owner: &'static str) -> Result<(), ReserveError> {
+    if range.is_empty() {
+        return Err(ReserveError::Empty);
+    }
+    without_interrupts(|| {
+        let mut regions = REGIONS.lock();
+        if let Some(existing) = regions.iter().flatten().find(|r| r.overlaps(&range)) {
+            return Err(ReserveError::Conflict {
+                requested: ReservedRegion { range, owner },
+                existing: existing.clone(),
+            });
+        }
+        let slot = regions.iter_mut().find(|r| r.is_none()).ok_or(ReserveError::Full)?;
+        *slot = Some(ReservedRegion { range, owner });
+        Ok(())
+    })
+}
+
+/// Releases the region starting at `start` owned by `owner`.
+/// 
+/// Returns the released region, or [`None`] if there was no such region.
+pub fn release(start: u64, owner: &'static str) -> Option<ReservedRegion> {
+    without_interrupts(|| {
+        REGIONS
+            .lock()
+            .iter_mut()
+            .find(|r| r.as_ref().is_some_and(|r| r.range.start == start && r.owner == owner))
+            .and_then(Option::take)
+    })
+}
+
+/// Returns the first reserved region overlapping `range`, if any.
+pub fn find_overlapping(range: &Range<u64>) -> Option<ReservedRegion> {
+    without_interrupts(|| REGIONS.lock().iter().flatten().find(|r| r.overlaps(range)).cloned())
+}
+
+/// Returns wether any byte of `range` is reserved.
+pub fn is_reserved(range: &Range<u64>) -> bool {
+    without_interrupts(|| REGIONS.lock().iter().flatten().any(|r| r.overlaps(range)))
+}
+
+/// Calls `f` on every reserved region.
+/// 
+/// `f` is called on a copy, so it may reserve and release regions.
+pub fn for_each(f: impl FnMut(&ReservedRegion)) {
+    let regions = without_interrupts(|| REGIONS.lock().clone());
+    regions.iter().flatten().for_each(f);
+}
+
+unsafe extern "C" {
+    #[link_name = "__kernel_start"]
+    static KERNEL_START: u8;
+    #[link_name = "__kernel_end"]
+    static KERNEL_END: u8;
+}
+
+/// Returns the physical range of the kernel image, as defined by the linker script.
+pub fn kernel_image() -> Range<u64> {
+    (&raw const KERNEL_START) as u64..(&raw const KERNEL_END) as u64
+}
+
+/// Reserves the regions known at boot:
+/// - the real mode IVT and BIOS data area (the first frame)
+/// - the kernel image
+/// - the multiboot info structure
+/// - the modules loaded by the bootloader
+/// - the legacy VGA memory and BIOS ROMs
+/// - the graphical frame buffer, if there is one
+/// 
+/// # Errors
+/// Returns the first error, as a [`ReserveError`]
+pub fn reserve_boot_regions(boot_info: &BootInfo) -> Result<(), ReserveError> {
+    reserve(0..0x1000, "bios data")?;
+    reserve(0xA_0000..0x10_0000, "vga/bios rom")?;
+    reserve(kernel_image(), "kernel")?;
+
+    // an invalid info is not read, so it does not need to be kept either.
+    if let Ok(info) = boot_info.multiboot2() {
+        reserve(info.addr()..info.addr() + info.len() as u64, "multiboot")?;
+    }
+    for module in boot_info.modules.iter().filter(|module| !module.is_empty()) {
+        reserve(module.start..module.end, "module")?;
+    }
+
+    if let Some(fb) = boot_info.frame_buffer.filter(FrameBufferInfo::is_graphical) {
+        reserve(fb.addr..fb.addr + fb.size(), "framebuffer")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "test")]
+/// Tests
+pub mod test {
+    use crate::test::{TestInfo, TestResult, test_assert, test_assert_matches};
+
+    /// Tests conflict detection and releasing.
+    pub fn test_region_conflicts(_: TestInfo) -> TestResult {
+        // far outside of physical memory, so it doesn't interfere with anything.
+        const START: u64 = 0xF_0000_0000;
+        test_assert!(super::reserve(START..START + 0x2000, "test").is_ok())?;
+        test_assert_matches!(
+            super::reserve(START + 0x1000..START + 0x3000, "test"),
+            Err(super::ReserveError::Conflict { .. })
+        )?;
+        test_assert!(super::is_reserved(&(START + 0x1fff..START + 0x2000)))?;
+        test_assert!(super::release(START, "test").is_some())?;
+        test_assert!(!super::is_reserved(&(START..START + 0x2000)))
+    }
+}
@@ -0,0 +1,160 @@
+//! Kernel stacks of tasks, in a reserved virtual region.
+//! 
+//! The region holds [`MAX_STACKS`] slots, each an unmapped guard page followed by a
+//! [stack](crate::task::STACK_SIZE). A task which overflows its stack faults on the guard page,
+//! instead of writing into the stack of another task. Page faults are handled on an
+//! [interrupt stack](crate::interrupts::page_fault::PAGE_FAULT_IST_INDEX), as the CPU can not push
+//! them on the overflowed stack, and the handler reports a kernel stack overflow, see [`guard_of`]
+//! 
+//! A [`KernelStack`] maps its slot through the [virtual memory manager](vmm) when it is taken, and
+//! unmaps it when dropped, so the frames of unused stacks are free.
+use core::{fmt::{self, Display}, ops::Range};
+
+use spin::Mutex;
+use x86_64::{VirtAddr, instructions::interrupts::without_interrupts, structures::paging::PageTableFlags};
+
+use super::vmm::{self, VmmError};
+
+/// The start of the stack region, far above the largest heap.
+pub const STACK_REGION_START: u64 = 0x_5555_5555_0000;
+/// The size of the guard page below every stack.
+pub const GUARD_SIZE: u64 = 4096;
+/// Maximum amount of stacks, one per task, except the kernel task, which runs on the boot stack.
+pub const MAX_STACKS: usize = crate::task::MAX_TASKS - 1;
+const STACK_SIZE: u64 = crate::task::STACK_SIZE as u64;
+const SLOT_SIZE: u64 = GUARD_SIZE + STACK_SIZE;
+/// The end of the stack region.
+pub const STACK_REGION_END: u64 = STACK_REGION_START + MAX_STACKS as u64 * SLOT_SIZE;
+
+/// A slot is used when its bit is set.
+static USED: Mutex<u64> = Mutex::new(0);
+
+/// The stack of slot `slot`
+fn stack_range(slot: usize) -> Range<u64> {
+    let start = STACK_REGION_START + slot as u64 * SLOT_SIZE + GUARD_SIZE;
+    start..start + STACK_SIZE
+}
+
+/// The amount of slots which are free.
+pub fn free_slots() -> usize {
+    MAX_STACKS - without_interrupts(|| USED.lock().count_ones()) as usize
+}
+
+/// The slot whose guard page contains `addr`, if it is one, E.g. the address of a page fault.
+pub fn guard_of(addr: u64) -> Option<usize> {
+    if !(STACK_REGION_START..STACK_REGION_END).contains(&addr) {
+        return None;
+    }
+    let offset = addr - STACK_REGION_START;
+    (offset % SLOT_SIZE < GUARD_SIZE).then_some((offset / SLOT_SIZE) as usize)
+}
+
+/// The error of [`KernelStack::new`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackError {
+    /// Every slot is used.
+    Full,
+    /// The stack could not be mapped, E.g. there are no frames left.
+    Map(VmmError),
+}
+
+impl Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full => write!(f, "all {MAX_STACKS} kernel stacks are used"),
+            Self::Map(e) => write!(f, "the kernel stack could not be mapped: {e}"),
+        }
+    }
+}
+
+impl core::error::Error for StackError {}
+
+/// A stack in a slot of the region, which is unmapped and freed when dropped.
+pub struct KernelStack {
+    slot: usize,
+}
+
+impl fmt::Debug for KernelStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KernelStack").field("slot", &self.slot).field("range", &self.range()).finish()
+    }
+}
+
+impl KernelStack {
+    /// Takes the first free slot, and maps its stack.
+    /// # Errors
+    /// Returns [`StackError::Full`] if every slot is used, or [`StackError::Map`] if the stack could
+    /// not be mapped.
+    pub fn new() -> Result<Self, StackError> {
+        let slot = without_interrupts(|| {
+            let mut used = USED.lock();
+            let slot = (!*used).trailing_zeros() as usize;
+            if slot >= MAX_STACKS {
+                return Err(StackError::Full);
+            }
+            *used |= 1 << slot;
+            Ok(slot)
+        })?;
+        // dropped without being mapped, the slot is freed.
+        let stack = Self { slot };
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        vmm::map_guarded(VirtAddr::new(stack.range().start), STACK_SIZE, flags).map_err(StackError::Map)?;
+        Ok(stack)
+    }
+
+    /// The addresses of the stack, which grows down from the end.
+    pub fn range(&self) -> Range<u64> {
+        stack_range(self.slot)
+    }
+
+    /// The size of the stack in bytes.
+    pub fn size(&self) -> usize {
+        STACK_SIZE as usize
+    }
+
+    /// The stack, as words.
+    pub fn as_mut_slice(&mut self) -> &mut [u64] {
+        // Safety: the slot is mapped, and only used through this stack until it is dropped.
+        unsafe { core::slice::from_raw_parts_mut(self.range().start as *mut u64, STACK_SIZE as usize / 8) }
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        // not mapped if `new` failed to.
+        let _ = vmm::unmap_range(VirtAddr::new(self.range().start), STACK_SIZE);
+        without_interrupts(|| *USED.lock() &= !(1 << self.slot));
+    }
+}
+
+/// Tests taking, guarding, unmapping and reusing stacks.
+#[cfg(feature = "test")]
+pub fn test_stacks(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    let free = free_slots();
+    let mut first = KernelStack::new().map_err(|_| "no stack was taken")?;
+    let second = KernelStack::new().map_err(|_| "no stack was taken")?;
+    test_assert_eq!(free_slots(), free - 2)?;
+    test_assert!(first.range().end < second.range().start || second.range().end < first.range().start)?;
+
+    // the stacks are mapped, the guard pages between them are not.
+    let guard = second.range().start - GUARD_SIZE;
+    test_assert!(super::translate_addr(VirtAddr::new(second.range().end - 8)).is_some())?;
+    test_assert!(super::translate_addr(VirtAddr::new(guard)).is_none())?;
+    test_assert_eq!(guard_of(guard), Some(second.slot))?;
+    test_assert_eq!(guard_of(second.range().start), None)?;
+    // Safety: the guard page is not mapped, the fault is caught.
+    let caught = crate::panic::catch::catch(|| unsafe { core::ptr::write_volatile(guard as *mut u64, 1) });
+    test_assert!(caught.err().ok_or("the guard page did not fault")?.message.starts_with("kernel stack overflow"))?;
+    let words = first.as_mut_slice();
+    words[0] = 0xdead;
+    test_assert_eq!((words.len() * 8, first.as_mut_slice()[0]), (first.size(), 0xdead))?;
+
+    let (slot, range) = (first.slot, first.range());
+    drop(first);
+    test_assert!(super::translate_addr(VirtAddr::new(range.start)).is_none())?;
+    test_assert_eq!(KernelStack::new().map(|stack| stack.slot).ok(), Some(slot))?;
+    drop(second);
+    test_assert_eq!(free_slots(), free)
+}
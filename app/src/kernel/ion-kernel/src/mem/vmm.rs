@@ -0,0 +1,298 @@
+//! The virtual memory manager, which maps and unmaps ranges of pages.
+//! 
+//! [`init`] hands it the page tables and the frame allocator at boot, after which every mapping
+//! goes through it: memory backed by new frames with [`map_range`], optionally between
+//! [guard pages](map_guarded), and device memory at a fixed physical address with [`map_phys`].
+//! Frames of [unmapped](unmap_range) memory are kept, up to [`MAX_FREE_FRAMES`], and handed out
+//! before new ones.
+//! 
+//! ```rust,no_run
+//! let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+//! vmm::map_guarded(VirtAddr::new(0x_6666_0000_1000), 4 * 4096, flags)?;
+//! ```
+use core::fmt::{self, Display};
+
+use spin::Mutex;
+use x86_64::{
+    PhysAddr, VirtAddr,
+    instructions::interrupts::without_interrupts,
+    structures::paging::{
+        FrameAllocator, Mapper, OffsetPageTable, Page, PageSize, PageTableFlags, PhysFrame, Size4KiB,
+        mapper::{MapToError, UnmapError},
+    },
+};
+
+use super::{BootInfoFrameAllocator, regions::{self, ReserveError}};
+use crate::collections::ArrayVec;
+
+/// The size of a page.
+pub const PAGE_SIZE: u64 = Size4KiB::SIZE;
+/// Maximum amount of unmapped frames kept for reuse, the frame allocator can not take back more.
+pub const MAX_FREE_FRAMES: usize = 1024;
+
+/// An error while mapping or unmapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmmError {
+    /// [`init`] was not called yet.
+    Uninitialized,
+    /// The address is not page aligned.
+    Unaligned(VirtAddr),
+    /// There are no free frames left, for the memory or a page table.
+    OutOfFrames,
+    /// The page is mapped already, E.g. to another frame, or it is a guard page.
+    AlreadyMapped(VirtAddr),
+    /// The page is not mapped.
+    NotMapped(VirtAddr),
+}
+
+impl Display for VmmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Uninitialized => write!(f, "the virtual memory manager is not initialized"),
+            Self::Unaligned(addr) => write!(f, "{:#x} is not page aligned", addr.as_u64()),
+            Self::OutOfFrames => write!(f, "there are no free frames left"),
+            Self::AlreadyMapped(addr) => write!(f, "the page at {:#x} is mapped already", addr.as_u64()),
+            Self::NotMapped(addr) => write!(f, "the page at {:#x} is not mapped", addr.as_u64()),
+        }
+    }
+}
+
+impl core::error::Error for VmmError {}
+
+/// Frames for mappings and page tables, the freed ones first.
+#[derive(Debug)]
+struct Frames {
+    boot: BootInfoFrameAllocator,
+    free: ArrayVec<PhysFrame, MAX_FREE_FRAMES>,
+}
+
+unsafe impl FrameAllocator<Size4KiB> for Frames {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        self.free.pop().or_else(|| self.boot.allocate_frame())
+    }
+}
+
+#[derive(Debug)]
+struct Vmm {
+    mapper: OffsetPageTable<'static>,
+    frames: Frames,
+}
+
+impl Vmm {
+    /// Maps `page` to `frame`, or to a new frame if it is `None`
+    fn map(&mut self, page: Page, frame: Option<PhysFrame>, flags: PageTableFlags) -> Result<(), VmmError> {
+        let addr = page.start_address();
+        let new = frame.is_none();
+        let frame = match frame {
+            Some(frame) => frame,
+            None => self.frames.allocate_frame().ok_or(VmmError::OutOfFrames)?,
+        };
+        // Safety: the callers make sure nothing else uses the page, or the device memory.
+        let error = match unsafe { self.mapper.map_to(page, frame, flags, &mut self.frames) } {
+            Ok(flush) => {
+                flush.flush();
+                return Ok(());
+            }
+            Err(MapToError::FrameAllocationFailed) => VmmError::OutOfFrames,
+            Err(MapToError::PageAlreadyMapped(_) | MapToError::ParentEntryHugePage) => VmmError::AlreadyMapped(addr),
+        };
+        if new {
+            let _ = self.frames.free.push(frame);
+        }
+        Err(error)
+    }
+
+    /// Unmaps `page`, keeping its frame for later mappings if `free` is set.
+    fn unmap(&mut self, page: Page, free: bool) -> Result<(), VmmError> {
+        match self.mapper.unmap(page) {
+            Ok((frame, flush)) => {
+                flush.flush();
+                if free {
+                    // when there are too many, the frame is lost.
+                    let _ = self.frames.free.push(frame);
+                }
+                Ok(())
+            }
+            Err(UnmapError::PageNotMapped | UnmapError::ParentEntryHugePage | UnmapError::InvalidFrameAddress(_)) => {
+                Err(VmmError::NotMapped(page.start_address()))
+            }
+        }
+    }
+}
+
+static VMM: Mutex<Option<Vmm>> = Mutex::new(None);
+
+/// Takes over the page tables and the frame allocator.
+/// 
+/// Must be called once, before anything is mapped.
+pub fn init(mapper: OffsetPageTable<'static>, frame_allocator: BootInfoFrameAllocator) {
+    let frames = Frames { boot: frame_allocator, free: ArrayVec::new() };
+    without_interrupts(|| *VMM.lock() = Some(Vmm { mapper, frames }));
+}
+
+fn with<R>(f: impl FnOnce(&mut Vmm) -> Result<R, VmmError>) -> Result<R, VmmError> {
+    without_interrupts(|| f(VMM.lock().as_mut().ok_or(VmmError::Uninitialized)?))
+}
+
+/// The first page of `virt`, which must be page aligned, and the amount of pages in `len` bytes.
+fn pages(virt: VirtAddr, len: u64) -> Result<(Page, u64), VmmError> {
+    let page = Page::from_start_address(virt).map_err(|_| VmmError::Unaligned(virt))?;
+    Ok((page, len.div_ceil(PAGE_SIZE)))
+}
+
+/// Maps `len` bytes from the page aligned `virt` to new frames, with `flags`
+/// # Errors
+/// Returns an error if a page is mapped already, or there are not enough frames, then nothing is
+/// mapped.
+pub fn map_range(virt: VirtAddr, len: u64, flags: PageTableFlags) -> Result<(), VmmError> {
+    let (start, pages) = pages(virt, len)?;
+    with(|vmm| {
+        for i in 0..pages {
+            if let Err(e) = vmm.map(start + i, None, flags) {
+                // nothing stays mapped.
+                for j in 0..i {
+                    let _ = vmm.unmap(start + j, true);
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Like [`map_range`], but the pages right below and above the range must not be mapped, so an
+/// access just outside of it faults, E.g. a stack which overflows.
+/// # Errors
+/// Returns [`VmmError::AlreadyMapped`] if a guard page is mapped, or the error of [`map_range`]
+pub fn map_guarded(virt: VirtAddr, len: u64, flags: PageTableFlags) -> Result<(), VmmError> {
+    let (start, pages) = pages(virt, len)?;
+    for guard in [start - 1, start + pages] {
+        if translate(guard.start_address()).is_some() {
+            return Err(VmmError::AlreadyMapped(guard.start_address()));
+        }
+    }
+    map_range(virt, len, flags)
+}
+
+/// Maps the `len` bytes of device memory at `phys` to `virt`, which must have the same offset
+/// into their page. Pages which are mapped to the same frame already are left alone.
+/// # Errors
+/// Returns [`VmmError::AlreadyMapped`] if a page is mapped to another frame, or
+/// [`VmmError::Unaligned`] if the offsets differ, then nothing new is mapped. If there are no
+/// frames left for the page tables, the pages mapped before stay mapped.
+pub fn map_phys(virt: VirtAddr, phys: PhysAddr, len: u64, flags: PageTableFlags) -> Result<(), VmmError> {
+    let offset = virt.as_u64() % PAGE_SIZE;
+    if phys.as_u64() % PAGE_SIZE != offset {
+        return Err(VmmError::Unaligned(virt));
+    }
+    let (start, pages) = pages(virt.align_down(PAGE_SIZE), len + offset)?;
+    let first = PhysFrame::containing_address(phys);
+    let mapped = |i: u64| translate((start + i).start_address());
+    if let Some(i) = (0..pages).find(|&i| mapped(i).is_some_and(|addr| addr != (first + i).start_address())) {
+        return Err(VmmError::AlreadyMapped((start + i).start_address()));
+    }
+    with(|vmm| (0..pages).filter(|&i| mapped(i).is_none()).try_for_each(|i| vmm.map(start + i, Some(first + i), flags)))
+}
+
+/// Unmaps `len` bytes from the page aligned `virt`, which were mapped by [`map_range`], and keeps
+/// their frames for later mappings.
+/// # Errors
+/// Returns [`VmmError::NotMapped`] for the first page which was not mapped, the others are
+/// unmapped anyway.
+pub fn unmap_range(virt: VirtAddr, len: u64) -> Result<(), VmmError> {
+    unmap_pages(virt, len, true)
+}
+
+/// Unmaps `len` bytes from the page aligned `virt`, which were mapped by [`map_phys`], the device
+/// memory is not reused.
+/// # Errors
+/// Returns [`VmmError::NotMapped`] for the first page which was not mapped, the others are
+/// unmapped anyway.
+pub fn unmap_phys(virt: VirtAddr, len: u64) -> Result<(), VmmError> {
+    unmap_pages(virt, len, false)
+}
+
+fn unmap_pages(virt: VirtAddr, len: u64, free: bool) -> Result<(), VmmError> {
+    let (start, pages) = pages(virt, len)?;
+    with(|vmm| {
+        // the first error is returned, after unmapping the rest.
+        let mut result = Ok(());
+        for i in 0..pages {
+            let unmapped = vmm.unmap(start + i, free);
+            if result.is_ok() {
+                result = unmapped;
+            }
+        }
+        result
+    })
+}
+
+/// The physical address `virt` is mapped to, if it is mapped.
+pub fn translate(virt: VirtAddr) -> Option<PhysAddr> {
+    super::translate_addr(virt)
+}
+
+/// Reserves the physical `range` for `owner` like [`regions::reserve`], while the frame allocator is
+/// in use, E.g. for memory which is only needed once a feature is used.
+/// # Errors
+/// Returns [`ReserveError::Allocated`] if a frame of the range was handed out already, or the
+/// error of [`regions::reserve`]
+pub fn reserve_unallocated(range: core::ops::Range<u64>, owner: &'static str) -> Result<(), ReserveError> {
+    without_interrupts(|| {
+        // locked until it is reserved, so no frame of it is handed out in between.
+        let vmm = VMM.lock();
+        if vmm.as_ref().is_some_and(|vmm| vmm.frames.boot.handed_out(&range)) {
+            return Err(ReserveError::Allocated);
+        }
+        regions::reserve(range, owner)
+    })
+}
+
+/// The amount of frames which were unmapped, and are reused first.
+pub fn free_frames() -> usize {
+    without_interrupts(|| VMM.lock().as_ref().map_or(0, |vmm| vmm.frames.free.len()))
+}
+
+/// Tests mapping, guarding and unmapping ranges.
+#[cfg(feature = "test")]
+pub fn test_vmm(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    const BASE: u64 = 0x_6666_0000_0000;
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let range = VirtAddr::new(BASE + PAGE_SIZE);
+
+    test_assert_eq!(map_range(range + 1u64, PAGE_SIZE, flags), Err(VmmError::Unaligned(range + 1u64)))?;
+    test_assert!(map_guarded(range, 2 * PAGE_SIZE, flags).is_ok())?;
+    // Safety: just mapped, and not used by anything else.
+    unsafe { range.as_mut_ptr::<u64>().write_volatile(0xAB) };
+    test_assert!(translate(range + PAGE_SIZE).is_some())?;
+    test_assert!(translate(range - PAGE_SIZE).is_none() && translate(range + 2 * PAGE_SIZE).is_none())?;
+    // the range is where the guard page above this one would be.
+    let below = VirtAddr::new(BASE);
+    test_assert_eq!(map_guarded(below, PAGE_SIZE, flags), Err(VmmError::AlreadyMapped(range)))?;
+    test_assert_eq!(map_range(range + PAGE_SIZE, PAGE_SIZE, flags), Err(VmmError::AlreadyMapped(range + PAGE_SIZE)))?;
+
+    // mapping device memory twice to the same frame is fine, to another one is not.
+    let phys = translate(range).ok_or("the range is not mapped")?;
+    let alias = VirtAddr::new(BASE + 16 * PAGE_SIZE);
+    test_assert!(map_phys(alias + 8u64, phys + 8u64, 8, flags).is_ok())?;
+    test_assert!(map_phys(alias, phys, PAGE_SIZE, flags).is_ok())?;
+    // Safety: mapped to the same frame as the range.
+    test_assert_eq!(unsafe { alias.as_ptr::<u64>().read_volatile() }, 0xAB)?;
+    test_assert_eq!(map_phys(range, phys + PAGE_SIZE, PAGE_SIZE, flags), Err(VmmError::AlreadyMapped(range)))?;
+    test_assert!(unmap_phys(alias, PAGE_SIZE).is_ok())?;
+
+    let free = free_frames();
+    test_assert!(unmap_range(range, 2 * PAGE_SIZE).is_ok())?;
+    test_assert_eq!((translate(range), free_frames()), (None, free + 2))?;
+    test_assert_eq!(unmap_range(range, PAGE_SIZE), Err(VmmError::NotMapped(range)))?;
+    // the freed frames are used first.
+    test_assert!(map_range(range, 2 * PAGE_SIZE, flags).is_ok())?;
+    test_assert_eq!(free_frames(), free)?;
+    test_assert!(unmap_range(range, 2 * PAGE_SIZE).is_ok())?;
+
+    // the frames of the range were handed out above.
+    let frame = phys.as_u64();
+    test_assert_eq!(reserve_unallocated(frame..frame + PAGE_SIZE, "test"), Err(ReserveError::Allocated))
+}
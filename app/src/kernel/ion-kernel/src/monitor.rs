@@ -0,0 +1,116 @@
+//! A top-like monitor, shown on the [status line](crate::text::STATUS_LINE).
+//! 
+//! Once [started](start), it refreshes every second with the uptime, the rate of every
+//! [counted interrupt](crate::interrupts::stats), and the heap usage. Ctrl+Alt+M toggles it.
+//! 
+//! The scheduler does not account the time or memory of [tasks](crate::task), so there is no per
+//! task usage.
+use core::{fmt::{self, Write}, sync::atomic::{AtomicBool, Ordering}, time::Duration};
+
+use pc_keyboard::KeyCode;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{collections::ArrayString, interrupts::{keyboard::hotkeys::{self, Modifiers}, stats}, lib_alloc::{self, AllocStats}, text::{BUFFER_WIDTH, STATUS_LINE}, time::{self, TimerHandle}};
+
+/// How often the monitor refreshes.
+pub const INTERVAL: Duration = Duration::from_secs(1);
+
+/// What the monitor shows, taken at one point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    /// Time since boot.
+    pub time: Duration,
+    /// See [`stats::counts`]
+    pub interrupts: [u64; stats::COUNTED.len()],
+    /// See [`lib_alloc::stats`]
+    pub heap: AllocStats,
+}
+
+impl Sample {
+    /// Takes a sample now.
+    pub fn now() -> Self {
+        Self { time: time::now(), interrupts: stats::counts(), heap: lib_alloc::stats() }
+    }
+
+    /// Writes the line shown by the monitor, with interrupt rates since `earlier`
+    pub fn write_line(&self, earlier: &Sample, out: &mut impl Write) -> fmt::Result {
+        let secs = self.time.as_secs();
+        write!(out, "up {}:{:02}:{:02} | irq/s", secs / 3600, secs / 60 % 60, secs % 60)?;
+        let elapsed = self.time.saturating_sub(earlier.time).as_millis().max(1);
+        for ((index, now), before) in stats::COUNTED.iter().zip(self.interrupts).zip(earlier.interrupts) {
+            let rate = u128::from(now.saturating_sub(before)) * 1000 / elapsed;
+            write!(out, " {:?} {rate}", index)?;
+        }
+        write!(out, " | heap {}K in {} allocs (peak {}K)",
+            self.heap.live_bytes / 1024, self.heap.live_allocations(), self.heap.peak_bytes / 1024)
+    }
+}
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static STATE: Mutex<Option<(Sample, TimerHandle)>> = Mutex::new(None);
+
+fn refresh() {
+    if !RUNNING.load(Ordering::Relaxed) {
+        return;
+    }
+    let sample = Sample::now();
+    let Ok(timer) = time::after(INTERVAL, refresh) else {
+        crate::log::warn!("The monitor stopped, there are too many timers");
+        RUNNING.store(false, Ordering::Relaxed);
+        return;
+    };
+    let earlier = without_interrupts(|| STATE.lock().replace((sample, timer)).map(|(earlier, _)| earlier));
+    let mut line = ArrayString::<BUFFER_WIDTH>::new();
+    // a line which is too long is cut off.
+    let _ = sample.write_line(&earlier.unwrap_or(sample), &mut line);
+    STATUS_LINE.set(format_args!("{line}"));
+}
+
+/// Shows the monitor, if it is not shown already.
+pub fn start() {
+    if !RUNNING.swap(true, Ordering::Relaxed) {
+        refresh();
+    }
+}
+
+/// Stops refreshing the monitor, its last line stays on the status line.
+pub fn stop() {
+    if RUNNING.swap(false, Ordering::Relaxed) {
+        if let Some((_, timer)) = without_interrupts(|| STATE.lock().take()) {
+            timer.cancel();
+        }
+    }
+}
+
+/// Whether the monitor is shown.
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+/// Starts the monitor if it is stopped, or stops it.
+pub fn toggle() {
+    if is_running() { stop() } else { start() }
+}
+
+/// Registers the Ctrl+Alt+M hotkey, which toggles the monitor.
+pub fn init() {
+    if let Err(e) = hotkeys::register((Modifiers::CTRL | Modifiers::ALT) + KeyCode::M, toggle) {
+        crate::log::warn!("Failed to register the monitor hotkey: {e}");
+    }
+}
+
+/// Tests the rates shown by the monitor.
+#[cfg(feature = "test")]
+pub fn test_monitor(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    let heap = AllocStats { allocations: 3, deallocations: 1, live_bytes: 4096, peak_bytes: 8192, failures: 0, contended: 0 };
+    let earlier = Sample { time: Duration::from_secs(60), interrupts: [0; stats::COUNTED.len()], heap };
+    let mut later = Sample { time: Duration::from_millis(62_000), ..earlier };
+    later.interrupts[0] = 36;
+
+    let mut line = ArrayString::<BUFFER_WIDTH>::new();
+    test_assert!(later.write_line(&earlier, &mut line).is_ok())?;
+    test_assert_eq!(line.as_str(), "up 0:01:02 | irq/s Timer 18 Keyboard 0 Mouse 0 | heap 4K in 2 allocs (peak 8K)")
+}
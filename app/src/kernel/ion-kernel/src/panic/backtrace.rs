@@ -0,0 +1,96 @@
+//! Backtraces, by walking the frame pointer chain.
+//! 
+//! This relies on the kernel being built with frame pointers (`"frame-pointer": "always"` in the
+//! target spec). Every frame is checked to be mapped before it is read, so a corrupted stack ends
+//! the walk instead of faulting.
+//! 
+//! Frames are shown with their source location, if a [line table](super::lines) covers them.
+use core::fmt::{self, Display};
+
+use x86_64::VirtAddr;
+
+use crate::{mem::translate, panic::lines::{self, Location}};
+
+/// Maximum amount of frames walked.
+pub const MAX_DEPTH: usize = 32;
+
+/// A single stack frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// The address the frame returns to.
+    pub return_addr: u64,
+    /// The frame pointer (`rbp`) of the frame.
+    pub frame_ptr: u64,
+}
+
+impl Frame {
+    /// The source location of the call, see [`lines::locate`]
+    pub fn location(&self) -> Option<Location<'static>> {
+        // the return address is after the call, which may be the last instruction of a line.
+        lines::locate(self.return_addr.wrapping_sub(1))
+    }
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#018x} (frame {:#018x})", self.return_addr, self.frame_ptr)?;
+        match self.location() {
+            Some(location) => write!(f, " at {location}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns the frame pointer of the caller.
+#[inline(always)]
+pub fn frame_pointer() -> u64 {
+    let rbp: u64;
+    // Safety: reading rbp has no side effects.
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags)) };
+    rbp
+}
+
+/// Returns wether the 16 bytes of a frame record at `rbp` can be read.
+fn is_readable(rbp: u64) -> bool {
+    rbp != 0
+        && rbp.is_multiple_of(8)
+        && rbp.checked_add(15).is_some()
+        && VirtAddr::try_new(rbp).is_ok()
+        && VirtAddr::try_new(rbp + 15).is_ok()
+        && translate(VirtAddr::new(rbp)).is_some()
+        && translate(VirtAddr::new(rbp + 15)).is_some()
+}
+
+/// Walks the frames starting with the frame at `rbp`, calling `f` for each frame until it returns
+/// false.
+/// 
+/// Returns the amount of frames walked.
+pub fn walk_from(mut rbp: u64, mut f: impl FnMut(Frame) -> bool) -> usize {
+    let mut depth = 0;
+    while depth < MAX_DEPTH && is_readable(rbp) {
+        // Safety: we checked the frame record is mapped.
+        let (next, return_addr) = unsafe {
+            let record = rbp as *const u64;
+            (record.read_volatile(), record.add(1).read_volatile())
+        };
+        if return_addr == 0 {
+            break;
+        }
+        depth += 1;
+        if !f(Frame { return_addr, frame_ptr: rbp }) {
+            break;
+        }
+        // the stack grows down, so callers must have higher frame pointers, otherwise we would loop.
+        if next <= rbp {
+            break;
+        }
+        rbp = next;
+    }
+    depth
+}
+
+/// Walks the frames of the caller, see [`walk_from`]
+#[inline(always)]
+pub fn walk(f: impl FnMut(Frame) -> bool) -> usize {
+    walk_from(frame_pointer(), f)
+}
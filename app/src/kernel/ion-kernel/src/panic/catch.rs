@@ -0,0 +1,235 @@
+//! Recovering from panics.
+//! 
+//! The kernel is built with `panic = "abort"`, so there is no unwinding. Instead, [`catch`] saves
+//! the callee-saved registers and the stack pointer before running a closure, and if the closure
+//! panics, the panic handler restores them, returning from [`catch`] as if the closure had
+//! returned.
+//! 
+//! Like `longjmp` in C, this skips everything between the panic and the [`catch`]:
+//! - destructors do not run, so memory owned by the skipped frames is leaked.
+//! - locks held by the skipped frames stay locked.
+//! 
+//! So it is only suitable for isolating code which does not share much state with the rest of the
+//! kernel, such as tests, or a driver which is disabled after it panics.
+//! 
+//! Catches belong to the [task](crate::task) which started them: the scheduler [suspends](suspend)
+//! them when it switches away, so a panic only recovers to a catch of the task which panicked.
+use core::{fmt::{self, Display, Write}, panic::{Location, PanicInfo}, ptr, sync::atomic::{AtomicPtr, Ordering}};
+
+use crate::collections::ArrayString;
+
+/// Maximum length of a recorded panic message, longer messages are truncated.
+pub const MAX_MESSAGE_LEN: usize = 192;
+
+/// A panic caught by [`catch`]
+#[derive(Debug, Clone, Copy)]
+pub struct CaughtPanic {
+    /// The panic message, possibly truncated.
+    pub message: ArrayString<MAX_MESSAGE_LEN>,
+    /// Where the panic happened.
+    pub location: Option<&'static Location<'static>>,
+}
+
+impl Display for CaughtPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some(loc) => write!(f, "panicked at {loc}: {}", self.message),
+            None => write!(f, "panicked at unknown location: {}", self.message),
+        }
+    }
+}
+
+/// Registers saved by [`catch_trampoline`], restored by [`recover`]
+#[repr(C)]
+#[derive(Debug, Default)]
+struct SavedRegisters {
+    rbx: u64,
+    rbp: u64,
+    r12: u64,
+    r13: u64,
+    r14: u64,
+    r15: u64,
+    rsp: u64,
+}
+
+/// An active [`catch`], on the stack of its caller.
+#[derive(Debug)]
+struct CatchFrame {
+    regs: SavedRegisters,
+    /// The enclosing catch, if any.
+    prev: *mut CatchFrame,
+    interrupts_enabled: bool,
+    /// The interrupt handlers which were running, a panic may skip the end of later ones.
+    interrupt_depth: usize,
+    panic: Option<CaughtPanic>,
+}
+
+/// The innermost active catch of the running task.
+// TODO: this has to be per cpu once there are more than one.
+static CURRENT: AtomicPtr<CatchFrame> = AtomicPtr::new(ptr::null_mut());
+
+/// The catches of a task which is not running, see [`suspend`]
+#[derive(Debug)]
+pub(crate) struct Suspended(*mut CatchFrame);
+
+/// Takes the catches of the running task, before switching to another one, which then runs without
+/// any. Interrupts must be disabled until the switch, so a panic does not recover to no catch.
+pub(crate) fn suspend() -> Suspended {
+    Suspended(CURRENT.swap(ptr::null_mut(), Ordering::SeqCst))
+}
+
+/// Puts back the catches taken by [`suspend`], once the task runs again.
+pub(crate) fn resume(suspended: Suspended) {
+    CURRENT.store(suspended.0, Ordering::SeqCst);
+}
+
+/// Saves the callee-saved registers to `regs`, then calls `f(data)`. Returns 0 once `f` returns,
+/// or 1 if [`recover`] was called instead.
+#[unsafe(naked)]
+unsafe extern "C" fn catch_trampoline(regs: *mut SavedRegisters, f: extern "C" fn(*mut u8), data: *mut u8) -> u64 {
+    core::arch::naked_asm!(
+        // a frame of our own, which keeps backtraces working, and the stack aligned for the call.
+        "push rbp",
+        "mov rbp, rsp",
+        "mov [rdi], rbx",
+        "mov [rdi + 8], rbp",
+        "mov [rdi + 16], r12",
+        "mov [rdi + 24], r13",
+        "mov [rdi + 32], r14",
+        "mov [rdi + 40], r15",
+        "mov [rdi + 48], rsp",
+        "mov rdi, rdx",
+        "call rsi",
+        "xor eax, eax",
+        "pop rbp",
+        "ret",
+    )
+}
+
+/// Restores the registers saved by [`catch_trampoline`], and returns 1 from it.
+#[unsafe(naked)]
+unsafe extern "C" fn recover(regs: *const SavedRegisters) -> ! {
+    core::arch::naked_asm!(
+        "mov rbx, [rdi]",
+        "mov rbp, [rdi + 8]",
+        "mov r12, [rdi + 16]",
+        "mov r13, [rdi + 24]",
+        "mov r14, [rdi + 32]",
+        "mov r15, [rdi + 40]",
+        "mov rsp, [rdi + 48]",
+        "mov eax, 1",
+        "pop rbp",
+        "ret",
+    )
+}
+
+/// Runs `f`, returning the panic instead of halting if it panics.
+/// 
+/// See the [module docs](self) for what is skipped when `f` panics.
+/// # Errors
+/// Returns the [`CaughtPanic`] if `f` panicked.
+// the panic is returned by value, the heap may be what panicked.
+#[allow(clippy::result_large_err)]
+pub fn catch<F: FnOnce() -> R, R>(f: F) -> Result<R, CaughtPanic> {
+    extern "C" fn call<F: FnOnce() -> R, R>(data: *mut u8) {
+        // Safety: `data` is the `(Option<F>, Option<R>)` in `catch`, which outlives this call.
+        let (f, ret) = unsafe { &mut *data.cast::<(Option<F>, Option<R>)>() };
+        // can not fail, the closure is only taken once.
+        *ret = Some(f.take().unwrap()());
+    }
+
+    let mut frame = CatchFrame {
+        regs: SavedRegisters::default(),
+        prev: CURRENT.load(Ordering::SeqCst),
+        interrupts_enabled: x86_64::instructions::interrupts::are_enabled(),
+        interrupt_depth: crate::interrupts::context::depth(),
+        panic: None,
+    };
+    let mut data = (Some(f), None::<R>);
+    CURRENT.store(&raw mut frame, Ordering::SeqCst);
+
+    // Safety: the trampoline preserves every callee-saved register, and only returns once.
+    let recovered = unsafe {
+        catch_trampoline(&raw mut frame.regs, call::<F, R>, (&raw mut data).cast())
+    };
+
+    CURRENT.store(frame.prev, Ordering::SeqCst);
+    if recovered != 0 {
+        crate::interrupts::context::restore(frame.interrupt_depth);
+        // the panic handler disables interrupts.
+        if frame.interrupts_enabled {
+            x86_64::instructions::interrupts::enable();
+        }
+        // can not fail, the panic handler records the panic before recovering.
+        return Err(frame.panic.take().unwrap());
+    }
+    // can not fail, `call` stores the return value.
+    Ok(data.1.take().unwrap())
+}
+
+/// Returns wether a [`catch`] is active, so a panic would be recovered from.
+pub fn is_catching() -> bool {
+    !CURRENT.load(Ordering::SeqCst).is_null()
+}
+
+/// Records the panic in the innermost active [`catch`], and returns to it. Returns if there is no
+/// active catch.
+/// 
+/// Only called by the panic handler.
+pub(super) fn try_recover(info: &PanicInfo) {
+    if !is_catching() {
+        return;
+    }
+    let mut message = ArrayString::new();
+    let _ = write!(message, "{}", info.message());
+    recover_with(CaughtPanic {
+        message,
+        location: info.location().map(|loc| {
+            // Safety: panic locations are always static, `PanicInfo` only borrows them for less.
+            unsafe { &*ptr::from_ref(loc).cast::<Location<'static>>() }
+        }),
+    });
+}
+
+/// Returns to the innermost active [`catch`] with `panic`, as if the code it runs had panicked.
+/// Returns if there is no active catch.
+/// 
+/// This is meant for code which interrupted the caught code, such as a watchdog in the timer
+/// interrupt. Interrupts must be disabled, and the interrupt must already be acknowledged.
+pub fn recover_with(panic: CaughtPanic) {
+    let frame = CURRENT.load(Ordering::SeqCst);
+    if frame.is_null() {
+        return;
+    }
+    // Safety: the frame is on the stack of `catch`, which is still running, as it unregisters the
+    // frame before returning. The registers were saved by the trampoline it is in.
+    unsafe {
+        (*frame).panic = Some(panic);
+        recover(&raw const (*frame).regs)
+    }
+}
+
+#[cfg(feature = "test")]
+/// Tests
+pub mod test {
+    use crate::test::{TestInfo, TestResult, test_assert};
+
+    /// Tests that panics are caught, including nested ones.
+    pub fn test_catch(_: TestInfo) -> TestResult {
+        let caught = super::catch(|| {
+            let inner = super::catch(|| -> u32 { panic!("inner {}", 1) });
+            test_assert!(inner.is_err_and(|p| p.message.as_str() == "inner 1"))?;
+            TestResult::Ok
+        });
+        test_assert!(matches!(caught, Ok(TestResult::Ok)))?;
+        test_assert!(!super::is_catching())?;
+        test_assert!(matches!(super::catch(|| 5), Ok(5)))
+    }
+
+    /// Tests that the runner passes a test which should panic, and continues after it.
+    pub fn test_should_panic(_: TestInfo) -> TestResult {
+        let items = [1, 2, 3];
+        let index = core::hint::black_box(3);
+        test_assert!(items[index] > 0)
+    }
+}
@@ -0,0 +1,414 @@
+//! Source locations of addresses, from DWARF line tables (`.debug_line`).
+//! 
+//! Line tables are [registered](register) with the address they are loaded at, the one of the
+//! kernel is found by [`init`] in the ELF sections passed by the bootloader. [`locate`] then maps a
+//! code address to `file:line`, which [backtraces](super::backtrace) show next to every frame.
+//! 
+//! Only DWARF 2 to 4 line programs are read, which is what the kernel is built with. Looking up an
+//! address does not allocate or block, so it is safe in the panic handler.
+use core::fmt::{self, Display};
+
+use spin::Mutex;
+
+use crate::{c_lib::BootInfo, collections::{ArrayVec, CapacityError}, serial_println};
+
+/// Maximum amount of registered line tables, the kernel and loaded modules.
+pub const MAX_TABLES: usize = 8;
+
+/// An error while reading a line table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineError {
+    /// The table ends in the middle of a unit.
+    Truncated,
+    /// The DWARF version of a unit is not 2 to 4.
+    UnsupportedVersion(u16),
+    /// A header field has an invalid value.
+    Invalid(&'static str),
+}
+
+impl Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "the line table is truncated"),
+            Self::UnsupportedVersion(v) => write!(f, "DWARF version {v} is not supported"),
+            Self::Invalid(field) => write!(f, "invalid {field} in the line table header"),
+        }
+    }
+}
+
+impl core::error::Error for LineError {}
+
+/// A source location.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location<'a> {
+    /// The include directory of the file, if it is not the compilation directory.
+    pub directory: Option<&'a str>,
+    /// The file name.
+    pub file: &'a str,
+    /// The line, starting at 1, or 0 if it is not known.
+    pub line: u64,
+}
+
+impl Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(directory) = self.directory {
+            write!(f, "{directory}/")?;
+        }
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], LineError> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len).ok_or(LineError::Truncated)?).ok_or(LineError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, LineError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn uint(&mut self, len: usize) -> Result<u64, LineError> {
+        Ok(self.bytes(len)?.iter().rev().fold(0, |value, &byte| value << 8 | u64::from(byte)))
+    }
+
+    fn uleb(&mut self) -> Result<u64, LineError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(LineError::Invalid("LEB128 number"))
+    }
+
+    fn sleb(&mut self) -> Result<i64, LineError> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift >= 64 {
+                return Err(LineError::Invalid("LEB128 number"));
+            }
+            value |= i64::from(byte & 0x7F) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Ok(value);
+            }
+        }
+    }
+
+    fn str(&mut self) -> Result<&'a str, LineError> {
+        let rest = self.data.get(self.pos..).ok_or(LineError::Truncated)?;
+        let len = rest.iter().position(|&b| b == 0).ok_or(LineError::Truncated)?;
+        let s = core::str::from_utf8(self.bytes(len)?).map_err(|_| LineError::Invalid("path"))?;
+        self.pos += 1;
+        Ok(s)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+/// The header of a line program unit.
+struct Unit<'a> {
+    min_instruction_length: u64,
+    line_base: i8,
+    line_range: u8,
+    opcode_base: u8,
+    standard_lengths: &'a [u8],
+    directories: &'a [u8],
+    files: &'a [u8],
+    program: &'a [u8],
+}
+
+impl<'a> Unit<'a> {
+    /// Reads the unit at the reader, moving it to the next unit.
+    fn read(reader: &mut Reader<'a>) -> Result<Self, LineError> {
+        let (length, offset_size) = match reader.uint(4)? {
+            0xFFFF_FFFF => (reader.uint(8)?, 8),
+            length => (length, 4),
+        };
+        let mut unit = Reader { data: reader.bytes(length.try_into().map_err(|_| LineError::Truncated)?)?, pos: 0 };
+        let version = unit.uint(2)? as u16;
+        if !(2..=4).contains(&version) {
+            return Err(LineError::UnsupportedVersion(version));
+        }
+        let header_length = unit.uint(offset_size)? as usize;
+        let program_start = unit.pos.checked_add(header_length).ok_or(LineError::Truncated)?;
+        let min_instruction_length = u64::from(unit.u8()?);
+        if version >= 4 {
+            // maximum operations per instruction, only used by VLIW.
+            unit.u8()?;
+        }
+        // default is_stmt, statements are not told apart.
+        unit.u8()?;
+        let line_base = unit.u8()? as i8;
+        let line_range = unit.u8()?;
+        if line_range == 0 {
+            return Err(LineError::Invalid("line range"));
+        }
+        let opcode_base = unit.u8()?;
+        let standard_lengths = unit.bytes(usize::from(opcode_base.saturating_sub(1)))?;
+
+        let start = unit.pos;
+        while !unit.str()?.is_empty() {}
+        let directories = &unit.data[start..unit.pos];
+        let start = unit.pos;
+        while !unit.str()?.is_empty() {
+            unit.uleb()?;
+            unit.uleb()?;
+            unit.uleb()?;
+        }
+        let files = &unit.data[start..unit.pos];
+        let program = unit.data.get(program_start..).ok_or(LineError::Truncated)?;
+
+        Ok(Self {
+            min_instruction_length,
+            line_base,
+            line_range,
+            opcode_base,
+            standard_lengths,
+            directories,
+            files,
+            program,
+        })
+    }
+
+    /// The location of `line` in the file with the (1 based) index `file`
+    fn location(&self, file: u64, line: u64) -> Result<Location<'a>, LineError> {
+        let mut files = Reader { data: self.files, pos: 0 };
+        for _ in 1..file {
+            if files.str()?.is_empty() {
+                return Err(LineError::Invalid("file index"));
+            }
+            files.uleb()?;
+            files.uleb()?;
+            files.uleb()?;
+        }
+        let name = files.str()?;
+        if name.is_empty() || file == 0 {
+            return Err(LineError::Invalid("file index"));
+        }
+        let directory = files.uleb()?;
+
+        let mut directories = Reader { data: self.directories, pos: 0 };
+        let mut dir = None;
+        for _ in 0..directory {
+            dir = Some(directories.str()?).filter(|d| !d.is_empty());
+        }
+        // absolute file names ignore their directory.
+        let directory = dir.filter(|_| !name.starts_with('/'));
+        Ok(Location { directory, file: name, line })
+    }
+
+    /// Runs the line program, returning the location of the row covering `address`
+    fn find(&self, address: u64) -> Result<Option<Location<'a>>, LineError> {
+        #[derive(Clone, Copy)]
+        struct Row {
+            address: u64,
+            file: u64,
+            line: u64,
+        }
+
+        let initial = Row { address: 0, file: 1, line: 1 };
+        let mut program = Reader { data: self.program, pos: 0 };
+        let mut row = initial;
+        let mut previous: Option<Row> = None;
+        // the row before `address` starts the range covering it, if the next row is after it.
+        let mut emit = |row: Row, end_sequence: bool| {
+            let found = previous.filter(|p| p.address <= address && address < row.address);
+            previous = if end_sequence { None } else { Some(row) };
+            found
+        };
+        while !program.is_empty() {
+            let opcode = program.u8()?;
+            let found = if opcode >= self.opcode_base {
+                let adjusted = opcode - self.opcode_base;
+                row.address = row.address.wrapping_add(u64::from(adjusted / self.line_range) * self.min_instruction_length);
+                row.line = row.line.wrapping_add_signed(i64::from(self.line_base) + i64::from(adjusted % self.line_range));
+                emit(row, false)
+            } else {
+                match opcode {
+                    0 => {
+                        let len = program.uleb()? as usize;
+                        let mut extended = Reader { data: program.bytes(len)?, pos: 0 };
+                        match extended.u8()? {
+                            // end sequence
+                            1 => {
+                                let found = emit(row, true);
+                                row = initial;
+                                found
+                            }
+                            // set address
+                            2 => {
+                                row.address = extended.uint(len - 1)?;
+                                None
+                            }
+                            // files defined in the program are not supported, they are not emitted.
+                            _ => None,
+                        }
+                    }
+                    // copy
+                    1 => emit(row, false),
+                    // advance pc
+                    2 => {
+                        row.address = row.address.wrapping_add(program.uleb()?.wrapping_mul(self.min_instruction_length));
+                        None
+                    }
+                    // advance line
+                    3 => {
+                        row.line = row.line.wrapping_add_signed(program.sleb()?);
+                        None
+                    }
+                    // set file
+                    4 => {
+                        row.file = program.uleb()?;
+                        None
+                    }
+                    // const add pc
+                    8 => {
+                        let adjusted = 255 - self.opcode_base;
+                        row.address = row.address.wrapping_add(u64::from(adjusted / self.line_range) * self.min_instruction_length);
+                        None
+                    }
+                    // fixed advance pc
+                    9 => {
+                        row.address = row.address.wrapping_add(program.uint(2)?);
+                        None
+                    }
+                    // column, flags and the like, which only have operands to skip.
+                    _ => {
+                        for _ in 0..self.standard_lengths[usize::from(opcode - 1)] {
+                            program.uleb()?;
+                        }
+                        None
+                    }
+                }
+            };
+            if let Some(found) = found {
+                return self.location(found.file, found.line).map(Some);
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Finds the location of `address` in the `.debug_line` section `section`
+pub fn find(section: &[u8], address: u64) -> Result<Option<Location<'_>>, LineError> {
+    let mut reader = Reader { data: section, pos: 0 };
+    while !reader.is_empty() {
+        if let Some(location) = Unit::read(&mut reader)?.find(address)? {
+            return Ok(Some(location));
+        }
+    }
+    Ok(None)
+}
+
+/// A registered line table.
+#[derive(Debug, Clone, Copy)]
+pub struct Table {
+    /// The name, like `kernel`, or the name of a module.
+    pub name: &'static str,
+    /// The `.debug_line` section.
+    pub section: &'static [u8],
+    /// Where the code is loaded, relative to the addresses in the table.
+    pub bias: u64,
+}
+
+static TABLES: Mutex<ArrayVec<Table, MAX_TABLES>> = Mutex::new(ArrayVec::new());
+
+/// Registers a line table.
+pub fn register(table: Table) -> Result<(), CapacityError> {
+    x86_64::instructions::interrupts::without_interrupts(|| TABLES.lock().push(table).map_err(|_| CapacityError(())))
+}
+
+/// Unregisters the line table `name`, when its module is unloaded.
+pub fn unregister(name: &str) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut tables = TABLES.lock();
+        if let Some(index) = tables.iter().position(|t| t.name == name) {
+            tables.remove(index);
+        }
+    });
+}
+
+/// The source location of the code at `address`, if a registered table covers it.
+/// 
+/// This does not wait for the tables, so it returns [`None`] while they are being changed.
+pub fn locate(address: u64) -> Option<Location<'static>> {
+    let tables = TABLES.try_lock()?;
+    tables.iter().find_map(|t| find(t.section, address.wrapping_sub(t.bias)).ok().flatten())
+}
+
+/// Finds the `.debug_line` section of the kernel, from the ELF sections passed by the bootloader.
+fn kernel_section(boot_info: &BootInfo) -> Option<&'static [u8]> {
+    let sections = boot_info.multiboot2().ok()?.elf_sections()?;
+    // Safety: the bootloader loaded every section, and put its address in the header.
+    unsafe { sections.find(".debug_line")?.bytes() }
+}
+
+/// Registers the line table of the kernel, and reserves its memory.
+/// 
+/// The bootloader loads it outside of the kernel image, so this must happen before the frame
+/// allocator hands out any frames.
+pub fn init(boot_info: &BootInfo) {
+    let Some(section) = kernel_section(boot_info) else {
+        serial_println!("The kernel has no line table, backtraces will not have source locations.");
+        return;
+    };
+    let start = section.as_ptr() as u64;
+    if let Err(e) = crate::mem::regions::reserve(start..start + section.len() as u64, "debug line") {
+        serial_println!("Could not reserve the kernel line table: {}", e);
+        return;
+    }
+    let _ = register(Table { name: "kernel", section, bias: 0 });
+}
+
+/// Tests finding locations in a small line program.
+#[cfg(feature = "test")]
+pub fn test_line_table(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use alloc::{string::ToString, vec::Vec};
+
+    use crate::test::{test_assert_eq, test_assert_matches};
+
+    let mut header = Vec::new();
+    // minimum instruction length, maximum operations, default is_stmt, line base, line range, opcode base
+    header.extend_from_slice(&[1, 1, 1, (-5i8) as u8, 14, 13]);
+    header.extend_from_slice(&[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+    header.extend_from_slice(b"src\0\0");
+    header.extend_from_slice(b"lib.rs\0\x01\0\0\0");
+    let mut program = Vec::from([0, 9, 2]);
+    program.extend_from_slice(&0x1000u64.to_le_bytes());
+    // line 10 at 0x1000, line 12 at 0x1010, then the sequence ends at 0x1030.
+    program.extend_from_slice(&[3, 9, 1, 244, 2, 0x20, 0, 1, 1]);
+
+    let mut unit = Vec::from(4u16.to_le_bytes());
+    unit.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    unit.extend(header);
+    unit.extend(program);
+    let mut section = Vec::from((unit.len() as u32).to_le_bytes());
+    section.extend(unit);
+
+    let location = find(&section, 0x100f);
+    test_assert_eq!(location.map(|l| l.map(|l| l.to_string())), Ok(Some("src/lib.rs:10".to_string())))?;
+    test_assert_eq!(find(&section, 0x1010).map(|l| l.map(|l| l.line)), Ok(Some(12)))?;
+    test_assert_eq!(find(&section, 0x102f).map(|l| l.map(|l| l.line)), Ok(Some(12)))?;
+    test_assert_eq!(find(&section, 0x1030), Ok(None))?;
+    test_assert_eq!(find(&section, 0xfff), Ok(None))?;
+    section[4] = 5;
+    test_assert_matches!(find(&section, 0x1000), Err(LineError::UnsupportedVersion(5)))?;
+    test_assert_eq!(find(&section[..20], 0x1000), Err(LineError::Truncated))
+}
@@ -0,0 +1,119 @@
+use core::{fmt::Write, panic::PanicInfo, sync::atomic::{AtomicBool, Ordering}};
+
+use cfg_if::cfg_if;
+
+use crate::{hlt_loop, text::Color};
+
+pub mod backtrace;
+pub mod catch;
+pub mod lines;
+pub mod oops;
+pub mod screen;
+
+pub use catch::{CaughtPanic, catch};
+pub use oops::oops;
+
+use screen::{PanicScreen, RawSerial};
+
+/// Set once the first panic starts, so a panic while panicking does not render again.
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Frames shown on the panic screen, the serial dump has all of them.
+const SCREEN_FRAMES: usize = 5;
+
+/// This function is called on panic.
+/// 
+/// If the panic happened inside [`catch`], it returns there. Otherwise, the full report is written
+/// to serial, then the panic screen is drawn. Neither uses a lock, so a panic while printing does
+/// not deadlock.
+#[panic_handler]
+pub fn panic(info: &PanicInfo) -> ! {
+    x86_64::instructions::interrupts::disable();
+    catch::try_recover(info);
+    if PANICKING.swap(true, Ordering::SeqCst) {
+        let _ = writeln!(RawSerial, "\nabort: panic while panicking: {}", info.message());
+        hlt_loop()
+    }
+
+    dump_serial(info);
+    // log messages still queued for COM1.
+    crate::serial::tx::flush();
+    // an early panic may be caused by the VGA Buffer not being mapped.
+    if crate::text::vga_mapped() {
+        draw_screen(info);
+    }
+
+    hlt_loop()
+}
+
+/// Writes the full panic report to serial.
+fn dump_serial(info: &PanicInfo) {
+    let mut serial = RawSerial;
+    let kind = if info.can_unwind() { "Unwinding panic" } else { "abort: panic" };
+    match info.location() {
+        Some(loc) => { let _ = writeln!(serial, "{kind} caused at {loc}: "); },
+        None => { let _ = writeln!(serial, "{kind} caused at unknown location: "); },
+    }
+    let _ = writeln!(serial, "{}", info.message());
+
+    let _ = writeln!(serial, "backtrace (most recent call first):");
+    let mut i = 0;
+    let depth = backtrace::walk(|frame| {
+        let _ = writeln!(serial, "  #{i:<2} {frame}");
+        i += 1;
+        true
+    });
+    if depth == 0 {
+        let _ = writeln!(serial, "  <no frames>");
+    }
+
+    cfg_if! {
+        if #[cfg(debug_assertions)] {
+            let _ = writeln!(serial, "=> note: debug assertions are ON.");
+        } else {
+            let _ = writeln!(serial, "=> note: Debug assertions are OFF.");
+            let _ = writeln!(serial, "=> help: It is recommended to use debug assertions when developing.");
+        }
+    }
+}
+
+/// Draws the panic screen.
+fn draw_screen(info: &PanicInfo) {
+    let mut screen = PanicScreen::clear();
+
+    screen.goto(1, 2).color(Color::Blue, Color::White).banner(" ION OS - KERNEL PANIC ");
+    let _ = write!(screen.goto(3, 4).color(Color::White, Color::Blue), "Ion OS ran into a problem it could not recover from, and has been halted.");
+
+    let _ = write!(screen.goto(5, 6).color(Color::Yellow, Color::Blue), "Location: ");
+    let _ = match info.location() {
+        Some(loc) => write!(screen.color(Color::White, Color::Blue), "{loc}"),
+        None => write!(screen.color(Color::White, Color::Blue), "unknown"),
+    };
+
+    let _ = write!(screen.goto(7, 8).color(Color::Yellow, Color::Blue), "Message:");
+    let _ = write!(screen.goto(8, 14).color(Color::White, Color::Blue), "  {}", info.message());
+
+    let _ = write!(screen.goto(15, 16).color(Color::Yellow, Color::Blue), "Backtrace (most recent call first):");
+    screen.goto(16, 16 + SCREEN_FRAMES).color(Color::White, Color::Blue);
+    let mut i = 0;
+    let depth = backtrace::walk(|frame| {
+        let _ = writeln!(screen, "  #{i:<2} {:#018x}", frame.return_addr);
+        i += 1;
+        i < SCREEN_FRAMES
+    });
+    if depth == 0 {
+        let _ = write!(screen, "  <no frames>");
+    }
+
+    let _ = write!(
+        screen.goto(22, 24).color(Color::LightGray, Color::Blue),
+        "The full report, including the complete backtrace, was written to the serial port (QEMU: -debugcon stdio). Restart the machine to continue."
+    );
+    cfg_if! {
+        if #[cfg(debug_assertions)] {
+            let _ = write!(screen.goto(24, 25), "note: debug assertions are ON.");
+        } else {
+            let _ = write!(screen.goto(24, 25), "help: use debug assertions when developing.");
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Recoverable faults.
+//! 
+//! An oops is a fault confined to one subsystem (a driver, a service): it is reported like a panic,
+//! the subsystem is marked as failed, and the kernel keeps running. Code which can not continue
+//! safely must still use `panic!`.
+use core::{fmt, panic::Location, sync::atomic::{AtomicBool, AtomicUsize, Ordering}};
+
+use spin::Mutex;
+
+use crate::{collections::ArrayVec, log::error, panic::backtrace, serial_println};
+
+/// Maximum amount of subsystems which can be marked failed at once.
+pub const MAX_FAILED: usize = 32;
+
+static PANIC_ON_OOPS: AtomicBool = AtomicBool::new(false);
+static OOPS_COUNT: AtomicUsize = AtomicUsize::new(0);
+static FAILED: Mutex<ArrayVec<Failure, MAX_FAILED>> = Mutex::new(ArrayVec::new());
+
+/// A subsystem which was marked failed by an oops.
+#[derive(Debug, Clone, Copy)]
+pub struct Failure {
+    /// The name of the subsystem.
+    pub subsystem: &'static str,
+    /// Where the first oops happened.
+    pub location: &'static Location<'static>,
+    /// The amount of oopses in this subsystem.
+    pub count: usize,
+}
+
+/// Reports a recoverable fault in a subsystem, and marks it failed.
+/// 
+/// The subsystem should stop doing work once it is failed, see [`is_failed`]
+/// 
+/// # Example
+/// ```rust,no_run
+/// use ion_kernel::panic::oops;
+/// 
+/// let scancode = 0xFF;
+/// oops!("ps2", "unexpected scancode {scancode:#x}");
+/// ```
+pub macro oops($subsystem:expr, $($arg:tt)+) {
+    $crate::panic::oops::report($subsystem, format_args!($($arg)+))
+}
+
+/// Reports an oops, see [`oops`]
+#[track_caller]
+pub fn report(subsystem: &'static str, args: fmt::Arguments) {
+    let location = Location::caller();
+    if PANIC_ON_OOPS.load(Ordering::Relaxed) {
+        panic!("oops in {subsystem}: {args}");
+    }
+    OOPS_COUNT.fetch_add(1, Ordering::Relaxed);
+
+    error!("oops in {subsystem} at {location}: {args}");
+    serial_println!("oops in {} at {}: {}", subsystem, location, args);
+    serial_println!("backtrace (most recent call first):");
+    let mut i = 0;
+    backtrace::walk(|frame| {
+        serial_println!("  #{:<2} {}", i, frame);
+        i += 1;
+        true
+    });
+
+    let full = x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut failed = FAILED.lock();
+        if let Some(failure) = failed.iter_mut().find(|f| f.subsystem == subsystem) {
+            failure.count += 1;
+            return false;
+        }
+        failed.push(Failure { subsystem, location, count: 1 }).is_err()
+    });
+    if full {
+        error!("too many failed subsystems, {subsystem} is not tracked.");
+    }
+}
+
+/// Makes every oops panic instead, so faults are not missed (in tests, for example).
+pub fn set_panic_on_oops(panic: bool) {
+    PANIC_ON_OOPS.store(panic, Ordering::Relaxed);
+}
+
+/// The total amount of oopses.
+pub fn count() -> usize {
+    OOPS_COUNT.load(Ordering::Relaxed)
+}
+
+/// Returns wether `subsystem` has been marked failed.
+pub fn is_failed(subsystem: &str) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        FAILED.lock().iter().any(|f| f.subsystem == subsystem)
+    })
+}
+
+/// Clears the failed mark of `subsystem`, after it has been reset. Returns wether it was failed.
+pub fn mark_recovered(subsystem: &str) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut failed = FAILED.lock();
+        let index = failed.iter().position(|f| f.subsystem == subsystem);
+        index.map(|i| failed.swap_remove(i)).is_some()
+    })
+}
+
+/// Calls `f` for every failed subsystem.
+pub fn for_each_failed(f: impl FnMut(&Failure)) {
+    let failed = x86_64::instructions::interrupts::without_interrupts(|| FAILED.lock().clone());
+    failed.iter().for_each(f);
+}
+
+/// Tests marking a subsystem failed, counting its oopses and recovering it.
+#[cfg(feature = "test")]
+pub fn test_oops(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    let before = count();
+    oops!("test-oops", "first");
+    oops!("test-oops", "second {}", 2);
+    test_assert_eq!(count(), before + 2)?;
+    test_assert!(is_failed("test-oops"))?;
+
+    let mut oopses = 0;
+    for_each_failed(|f| if f.subsystem == "test-oops" { oopses = f.count });
+    test_assert_eq!(oopses, 2)?;
+
+    test_assert!(mark_recovered("test-oops"))?;
+    test_assert!(!is_failed("test-oops"))?;
+    test_assert!(!mark_recovered("test-oops"))
+}
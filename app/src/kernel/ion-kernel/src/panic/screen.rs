@@ -0,0 +1,123 @@
+//! The full screen panic renderer.
+//! 
+//! This writes to the VGA text buffer directly, without taking any locks, as the panicking code
+//! may hold the lock of a [`Region`](crate::text::Region). On a graphical boot, the 80x25 screen is
+//! drawn centered on the [frame buffer](crate::text::framebuffer) instead, which is
+//! [stolen](crate::text::framebuffer::steal) for the same reason.
+use core::fmt::{self, Write};
+
+use crate::text::{Color, ColorCode, framebuffer::{FrameBuffer, Rgb, font::{GLYPH_HEIGHT, GLYPH_WIDTH}}};
+
+const VGA_TEXT_BUFFER: *mut u16 = 0xb8000 as *mut u16;
+const WIDTH: usize = 80;
+const HEIGHT: usize = 25;
+
+/// A cursor on the panic screen.
+/// 
+/// Text wraps at the end of a row, and is cut off at `end_row`.
+#[derive(Debug)]
+pub struct PanicScreen {
+    row: usize,
+    col: usize,
+    end_row: usize,
+    color: ColorCode,
+    /// Drawn to instead of the VGA text buffer, on a graphical boot.
+    frame_buffer: Option<FrameBuffer>,
+}
+
+impl PanicScreen {
+    /// Fills the screen with the panic background, and returns a cursor at the top left.
+    pub fn clear() -> Self {
+        // Safety: the kernel halts after the panic screen, nothing else draws anymore.
+        let mut frame_buffer = unsafe { crate::text::framebuffer::steal() };
+        if let Some(frame_buffer) = &mut frame_buffer {
+            // drawn to the top left if it does not fit.
+            frame_buffer.set_viewport(WIDTH * GLYPH_WIDTH, HEIGHT * GLYPH_HEIGHT);
+        }
+        let color = ColorCode::new(Color::White, Color::Blue);
+        let mut screen = Self { row: 0, col: 0, end_row: HEIGHT, color, frame_buffer };
+        for row in 0..HEIGHT {
+            for col in 0..WIDTH {
+                screen.put(row, col, b' ');
+            }
+        }
+        screen
+    }
+
+    fn put(&mut self, row: usize, col: usize, byte: u8) {
+        if let Some(frame_buffer) = &mut self.frame_buffer {
+            let (fore, back) = self.color.tupled();
+            frame_buffer.draw_glyph(col * GLYPH_WIDTH, row * GLYPH_HEIGHT, char::from(byte), Rgb::from(fore), Rgb::from(back));
+            return;
+        }
+        let value = u16::from(self.color.into_inner()) << 8 | u16::from(byte);
+        // Safety: the VGA text buffer is identity mapped, and the index is in bounds.
+        unsafe { VGA_TEXT_BUFFER.add(row * WIDTH + col).write_volatile(value) };
+    }
+
+    /// Moves the cursor to the start of `row`, cutting off text at `end_row`
+    pub fn goto(&mut self, row: usize, end_row: usize) -> &mut Self {
+        self.row = row;
+        self.col = 0;
+        self.end_row = end_row.min(HEIGHT);
+        self
+    }
+
+    /// Sets the color of following text.
+    pub fn color(&mut self, fore: Color, back: Color) -> &mut Self {
+        self.color = ColorCode::new(fore, back);
+        self
+    }
+
+    /// Writes `s` centered on the current row, with the current color filling the whole row.
+    pub fn banner(&mut self, s: &str) {
+        if self.row >= self.end_row {
+            return;
+        }
+        let start = WIDTH.saturating_sub(s.len()) / 2;
+        for col in 0..WIDTH {
+            let byte = col.checked_sub(start).and_then(|i| s.as_bytes().get(i)).copied().unwrap_or(b' ');
+            self.put(self.row, col, byte);
+        }
+        self.row += 1;
+        self.col = 0;
+    }
+
+    fn new_line(&mut self) {
+        self.row += 1;
+        self.col = 0;
+    }
+}
+
+impl Write for PanicScreen {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.row >= self.end_row {
+                break;
+            }
+            match c {
+                '\n' => self.new_line(),
+                c => {
+                    let byte = if matches!(c, ' '..='~') { c as u8 } else { 0xfe };
+                    self.put(self.row, self.col, byte);
+                    self.col += 1;
+                    if self.col == WIDTH {
+                        self.new_line();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes to the serial port without taking its lock, see [`dbg`](crate::serial::dbg)
+#[derive(Debug)]
+pub struct RawSerial;
+
+impl Write for RawSerial {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        crate::serial::dbg::str(s);
+        Ok(())
+    }
+}
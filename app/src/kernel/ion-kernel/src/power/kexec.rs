@@ -0,0 +1,599 @@
+//! Soft reboots into a new kernel image, without going through the firmware and GRUB.
+//! 
+//! [`soft_reboot`] stages the loadable segments of an ELF kernel image in a fixed staging area, runs
+//! the [shutdown hooks](super::shutdown) to tear the devices down, and jumps to a trampoline in the
+//! same memory. The trampoline switches to page tables, a GDT and a stack of its
+//! own, copies the segments over the running kernel, and calls the `kernel_main` of the new image
+//! with a synthesized boot info.
+//! 
+//! The multiboot info is copied to the [handoff](HANDOFF_ADDR) area for the new kernel, without the
+//! tags which describe the old image or its modules.
+//! 
+//! The staging area is only reserved by the first soft reboot, with
+//! [`reserve_unallocated`](crate::mem::vmm::reserve_unallocated), so it is not kept from the frame
+//! allocator on every boot. That fails once the frame allocator handed out a frame of it. A kernel
+//! which was soft rebooted into still runs on the page tables and the stack in it, so it reserves
+//! the area at boot instead.
+//! 
+//! The image is passed in as bytes, E.g. [read](crate::fs::read) from a file, see [`command`]
+use core::{arch::global_asm, convert::Infallible, fmt::{self, Display}, mem::offset_of, sync::atomic::{AtomicBool, Ordering}};
+
+use x86_64::{VirtAddr, structures::DescriptorTablePointer};
+
+use crate::{c_lib::{BOOT_INFO_VERSION, BootInfoInput, PHYSICAL_MEMORY_OFFSET}, collections::ArrayVec, log::{info, warn}, mem::{regions::{self, ReserveError}, vmm}, sync::InterruptSafeOnceCell, text::println};
+
+/// Physical address of the handoff area, where the boot info and multiboot info of the new kernel
+/// are copied to. It is only written by the trampoline, so it is not reserved.
+pub const HANDOFF_ADDR: u64 = 0x0200_0000;
+/// Size of the handoff area.
+pub const HANDOFF_SIZE: u64 = 0x1_0000;
+/// Offset of the multiboot info in the handoff area, the boot info is at its start.
+const HANDOFF_MULTIBOOT: u64 = 0x1000;
+/// Physical address of the reserved staging area, right after the handoff area.
+pub const STAGING_ADDR: u64 = HANDOFF_ADDR + HANDOFF_SIZE;
+/// Size of the staging area, which limits the size of the loaded segments.
+pub const STAGING_SIZE: u64 = 0x100_0000 - HANDOFF_SIZE;
+/// Memory below this is identity mapped, by the boot stage and by the trampoline.
+pub const IDENTITY_MAPPED: u64 = 0x4000_0000;
+/// Maximum amount of loadable segments.
+pub const MAX_SEGMENTS: usize = 16;
+/// The symbol the new kernel is entered at, with its boot info.
+pub const ENTRY_SYMBOL: &str = "kernel_main";
+
+// layout of the staging area
+const TRAMPOLINE: u64 = STAGING_ADDR;
+const PLAN: u64 = STAGING_ADDR + 0x1000;
+const PML4: u64 = STAGING_ADDR + 0x2000;
+const PDPT: u64 = STAGING_ADDR + 0x3000;
+const PD: u64 = STAGING_ADDR + 0x4000;
+const STACK_BOTTOM: u64 = STAGING_ADDR + 0x5000;
+const STACK_TOP: u64 = STACK_BOTTOM + 0x1_0000;
+const DATA: u64 = STACK_TOP;
+
+/// An error while soft rebooting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KexecError {
+    /// The boot info was not kept at boot, see [`init`]
+    Unavailable,
+    /// The staging area could not be reserved, E.g. as the frame allocator handed out a frame of it.
+    StagingInUse,
+    /// The image is not an x86_64 ELF executable.
+    NotElf,
+    /// The image ends in the middle of a structure.
+    Truncated,
+    /// The image has no [`ENTRY_SYMBOL`], or it is not in a segment.
+    NoEntry,
+    /// A segment is loaded outside of the identity mapped memory, or over the staging area.
+    BadSegment(u64),
+    /// The segments do not fit in the staging area, or there are more than [`MAX_SEGMENTS`]
+    TooLarge,
+    /// The multiboot info does not fit in the handoff area, or has no memory map.
+    BadMultiboot,
+}
+
+impl Display for KexecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unavailable => write!(f, "the boot info was not kept at boot"),
+            Self::StagingInUse => write!(f, "the staging area is in use"),
+            Self::NotElf => write!(f, "not an x86_64 ELF executable"),
+            Self::Truncated => write!(f, "the image is truncated"),
+            Self::NoEntry => write!(f, "the image has no `{ENTRY_SYMBOL}`"),
+            Self::BadSegment(addr) => write!(f, "the segment at {addr:#x} can not be loaded"),
+            Self::TooLarge => write!(f, "the image does not fit in the staging area"),
+            Self::BadMultiboot => write!(f, "the multiboot info can not be handed off"),
+        }
+    }
+}
+
+impl core::error::Error for KexecError {}
+
+fn read<const N: usize>(bytes: &[u8], offset: u64) -> Result<[u8; N], KexecError> {
+    let offset = usize::try_from(offset).map_err(|_| KexecError::Truncated)?;
+    let end = offset.checked_add(N).ok_or(KexecError::Truncated)?;
+    bytes.get(offset..end).and_then(|b| b.try_into().ok()).ok_or(KexecError::Truncated)
+}
+
+/// `offset + by`, offsets read from the image may overflow.
+fn add(offset: u64, by: u64) -> Result<u64, KexecError> {
+    offset.checked_add(by).ok_or(KexecError::Truncated)
+}
+
+fn u16_at(bytes: &[u8], offset: u64) -> Result<u16, KexecError> {
+    read(bytes, offset).map(u16::from_le_bytes)
+}
+
+fn u32_at(bytes: &[u8], offset: u64) -> Result<u32, KexecError> {
+    read(bytes, offset).map(u32::from_le_bytes)
+}
+
+fn u64_at(bytes: &[u8], offset: u64) -> Result<u64, KexecError> {
+    read(bytes, offset).map(u64::from_le_bytes)
+}
+
+/// A loadable segment of an [`Image`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    /// The physical address it is loaded at.
+    pub addr: u64,
+    /// The offset of its bytes in the image.
+    pub offset: u64,
+    /// The amount of bytes in the image.
+    pub file_size: u64,
+    /// The size in memory, the bytes after the ones in the image are zeroed.
+    pub mem_size: u64,
+}
+
+/// A parsed ELF kernel image.
+#[derive(Debug)]
+pub struct Image<'a> {
+    bytes: &'a [u8],
+    segments: ArrayVec<Segment, MAX_SEGMENTS>,
+    entry: u64,
+}
+
+impl<'a> Image<'a> {
+    /// Parses the segments of an image, and finds its [`ENTRY_SYMBOL`]
+    /// # Errors
+    /// Returns an error if the image is not a valid kernel, or can not be loaded.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, KexecError> {
+        // 64 bit, little endian, executable, x86_64
+        if read::<4>(bytes, 0)? != *b"\x7fELF" || read::<2>(bytes, 4)? != [2, 1] || u16_at(bytes, 16)? != 2
+            || u16_at(bytes, 18)? != 0x3E
+        {
+            return Err(KexecError::NotElf);
+        }
+        let (phoff, phentsize, phnum) = (u64_at(bytes, 32)?, u16_at(bytes, 54)?, u16_at(bytes, 56)?);
+        let mut segments = ArrayVec::new();
+        for i in 0..u64::from(phnum) {
+            let header = add(phoff, i * u64::from(phentsize))?;
+            // PT_LOAD
+            if u32_at(bytes, header)? != 1 {
+                continue;
+            }
+            let segment = Segment {
+                offset: u64_at(bytes, add(header, 8)?)?,
+                addr: u64_at(bytes, add(header, 24)?)?,
+                file_size: u64_at(bytes, add(header, 32)?)?,
+                mem_size: u64_at(bytes, add(header, 40)?)?,
+            };
+            let end = segment.addr.checked_add(segment.mem_size).ok_or(KexecError::BadSegment(segment.addr))?;
+            if segment.addr < 0x10_0000 || end > IDENTITY_MAPPED || segment.file_size > segment.mem_size
+                || (segment.addr < STAGING_ADDR + STAGING_SIZE && end > HANDOFF_ADDR)
+            {
+                return Err(KexecError::BadSegment(segment.addr));
+            }
+            if segment.offset.checked_add(segment.file_size).is_none_or(|end| end > bytes.len() as u64) {
+                return Err(KexecError::Truncated);
+            }
+            segments.push(segment).map_err(|_| KexecError::TooLarge)?;
+        }
+        let entry = find_symbol(bytes, ENTRY_SYMBOL)?.ok_or(KexecError::NoEntry)?;
+        if !segments.iter().any(|s| (s.addr..s.addr + s.file_size).contains(&entry)) {
+            return Err(KexecError::NoEntry);
+        }
+        Ok(Self { bytes, segments, entry })
+    }
+
+    /// The loadable segments.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// The address of the [`ENTRY_SYMBOL`]
+    pub fn entry(&self) -> u64 {
+        self.entry
+    }
+
+    /// The bytes of `segment` in the image.
+    fn data(&self, segment: &Segment) -> &'a [u8] {
+        // checked by `parse`
+        &self.bytes[segment.offset as usize..(segment.offset + segment.file_size) as usize]
+    }
+}
+
+/// The value of the symbol `name`, from the symbol table of the image.
+fn find_symbol(bytes: &[u8], name: &str) -> Result<Option<u64>, KexecError> {
+    let (shoff, shentsize, shnum) = (u64_at(bytes, 40)?, u16_at(bytes, 58)?, u16_at(bytes, 60)?);
+    let section = |i: u64| add(shoff, i * u64::from(shentsize));
+    for i in 0..u64::from(shnum) {
+        let header = section(i)?;
+        // SHT_SYMTAB
+        if u32_at(bytes, add(header, 4)?)? != 2 {
+            continue;
+        }
+        let (offset, size, entsize) = (u64_at(bytes, add(header, 24)?)?, u64_at(bytes, add(header, 32)?)?, u64_at(bytes, add(header, 56)?)?);
+        let strtab = section(u64::from(u32_at(bytes, add(header, 40)?)?))?;
+        let (str_offset, str_size) = (u64_at(bytes, add(strtab, 24)?)?, u64_at(bytes, add(strtab, 32)?)?);
+        for symbol in (offset..add(offset, size)?).step_by(entsize.max(1) as usize) {
+            let name_offset = u64::from(u32_at(bytes, symbol)?);
+            if add(name_offset, name.len() as u64)? >= str_size {
+                continue;
+            }
+            let start = usize::try_from(add(str_offset, name_offset)?).map_err(|_| KexecError::Truncated)?;
+            let string = bytes.get(start..).and_then(|s| s.get(..=name.len()));
+            if string.is_some_and(|s| s[..name.len()] == *name.as_bytes() && s[name.len()] == 0) {
+                return Ok(Some(u64_at(bytes, add(symbol, 8)?)?));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Copies the multiboot info `src` to `out`, without the tags of modules and ELF sections, which
+/// describe memory the new kernel does not know about.
+/// 
+/// Returns the size of the copy, and the offset of its memory map tag.
+fn copy_multiboot(src: &[u8], out: &mut [u8]) -> Result<(usize, usize), KexecError> {
+    const MODULE: u32 = 3;
+    const MEMORY_MAP: u32 = 6;
+    const ELF_SECTIONS: u32 = 9;
+
+    let total = (u32_at(src, 0).map_err(|_| KexecError::BadMultiboot)? as usize).min(src.len());
+    let mut offset = 8;
+    let mut written = 8;
+    let mut memory_map = None;
+    while offset + 8 <= total {
+        let typ = u32_at(src, offset as u64).map_err(|_| KexecError::BadMultiboot)?;
+        let size = u32_at(src, offset as u64 + 4).map_err(|_| KexecError::BadMultiboot)? as usize;
+        let padded = (size + 7) & !7;
+        if size < 8 || offset + size > total {
+            return Err(KexecError::BadMultiboot);
+        }
+        if typ != MODULE && typ != ELF_SECTIONS {
+            if written + padded > out.len() {
+                return Err(KexecError::BadMultiboot);
+            }
+            if typ == MEMORY_MAP {
+                memory_map = Some(written);
+            }
+            out[written..written + size].copy_from_slice(&src[offset..offset + size]);
+            out[written + size..written + padded].fill(0);
+            written += padded;
+        }
+        offset += padded;
+        if typ == 0 {
+            break;
+        }
+    }
+    // the total size, and the reserved field.
+    let header = out.get_mut(..8).ok_or(KexecError::BadMultiboot)?;
+    header[..4].copy_from_slice(&(written as u32).to_le_bytes());
+    header[4..].fill(0);
+    Ok((written, memory_map.ok_or(KexecError::BadMultiboot)?))
+}
+
+/// A copy done by the trampoline, followed by zeroing.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Transfer {
+    dst: u64,
+    src: u64,
+    len: u64,
+    zero: u64,
+}
+
+/// What the trampoline does, at [`PLAN`]
+#[repr(C)]
+struct Plan {
+    cr3: u64,
+    stack_top: u64,
+    entry: u64,
+    boot_info: u64,
+    gdt: [u64; 2],
+    gdtr: DescriptorTablePointer,
+    count: u64,
+    transfers: [Transfer; MAX_SEGMENTS + 1],
+}
+
+const _: () = assert!(size_of::<Plan>() <= 0x1000);
+
+global_asm!(
+    ".global ion_kexec_trampoline",
+    "ion_kexec_trampoline:",
+    "cli",
+    "cld",
+    "mov rax, [rdi + {cr3}]",
+    "mov cr3, rax",
+    "lgdt [rdi + {gdtr}]",
+    "mov rsp, [rdi + {stack_top}]",
+    // reloads the code segment, from the new GDT.
+    "push 0x08",
+    "lea rax, [rip + 2f]",
+    "push rax",
+    "retfq",
+    "2:",
+    "xor eax, eax",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov ss, ax",
+    "mov fs, ax",
+    "mov gs, ax",
+    "mov r8, rdi",
+    "mov rdx, [r8 + {count}]",
+    "lea rbx, [r8 + {transfers}]",
+    "3:",
+    "test rdx, rdx",
+    "jz 4f",
+    "mov rdi, [rbx]",
+    "mov rsi, [rbx + 8]",
+    "mov rcx, [rbx + 16]",
+    "rep movsb",
+    "mov rcx, [rbx + 24]",
+    "rep stosb",
+    "add rbx, {transfer_size}",
+    "dec rdx",
+    "jmp 3b",
+    "4:",
+    "mov rdi, [r8 + {boot_info}]",
+    "mov rax, [r8 + {entry}]",
+    "xor ebp, ebp",
+    "call rax",
+    "5:",
+    "cli",
+    "hlt",
+    "jmp 5b",
+    ".global ion_kexec_trampoline_end",
+    "ion_kexec_trampoline_end:",
+    cr3 = const offset_of!(Plan, cr3),
+    gdtr = const offset_of!(Plan, gdtr),
+    stack_top = const offset_of!(Plan, stack_top),
+    count = const offset_of!(Plan, count),
+    transfers = const offset_of!(Plan, transfers),
+    transfer_size = const size_of::<Transfer>(),
+    boot_info = const offset_of!(Plan, boot_info),
+    entry = const offset_of!(Plan, entry),
+);
+
+unsafe extern "C" {
+    /// Runs the plan at the physical address in `rdi`, never returns. Position independent, it is
+    /// copied to [`TRAMPOLINE`] before it runs.
+    static ion_kexec_trampoline: u8;
+    static ion_kexec_trampoline_end: u8;
+}
+
+static BOOT_INFO: InterruptSafeOnceCell<BootInfoInput> = InterruptSafeOnceCell::new();
+static RESERVED: AtomicBool = AtomicBool::new(false);
+
+/// Keeps the boot info to hand a copy of it to the next kernel. If this kernel was soft rebooted
+/// into, it also reserves the staging area, which it still runs on.
+/// 
+/// Must be called before the frame allocator is created.
+/// # Errors
+/// Returns an error if the staging area must be reserved, but can not be.
+pub fn init(boot_info: &BootInfoInput) -> Result<(), ReserveError> {
+    let _ = BOOT_INFO.set(boot_info.clone());
+    let staging = STAGING_ADDR..STAGING_ADDR + STAGING_SIZE;
+    if staging.contains(&boot_info.page_table_base) || staging.contains(&boot_info.stack_top.saturating_sub(1)) {
+        regions::reserve(staging, "kexec")?;
+        RESERVED.store(true, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+/// Reserves the staging area, unless it was already.
+fn reserve() -> Result<(), KexecError> {
+    if RESERVED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    vmm::reserve_unallocated(STAGING_ADDR..STAGING_ADDR + STAGING_SIZE, "kexec").map_err(|e| {
+        warn!("Failed to reserve the soft reboot staging area: {e}");
+        KexecError::StagingInUse
+    })?;
+    RESERVED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// The staging area, from `addr` on.
+/// 
+/// # Safety
+/// The area must be reserved, and not be in use.
+unsafe fn staging(addr: u64, len: u64) -> &'static mut [u8] {
+    debug_assert!(addr >= STAGING_ADDR && addr + len <= STAGING_ADDR + STAGING_SIZE);
+    // Safety: forwarded from the caller, the area is identity mapped.
+    unsafe { core::slice::from_raw_parts_mut((PHYSICAL_MEMORY_OFFSET as u64 + addr) as *mut u8, len as usize) }
+}
+
+/// Writes the page tables, the plan, and the data of `image` to the staging area.
+/// 
+/// # Safety
+/// The staging area must be reserved.
+unsafe fn stage(image: &Image<'_>, boot_info: &BootInfoInput) -> Result<(), KexecError> {
+    let end = STAGING_ADDR + STAGING_SIZE;
+    // Safety: reserved, and only used here.
+    let data = unsafe { staging(DATA, end - DATA) };
+    let mut plan = Plan {
+        cr3: PML4,
+        stack_top: STACK_TOP,
+        entry: image.entry(),
+        boot_info: HANDOFF_ADDR,
+        // null, and 64 bit code
+        gdt: [0, (1 << 41) | (1 << 43) | (1 << 44) | (1 << 47) | (1 << 53)],
+        gdtr: DescriptorTablePointer { limit: 15, base: VirtAddr::new(PLAN + offset_of!(Plan, gdt) as u64) },
+        count: 0,
+        transfers: [Transfer { dst: 0, src: 0, len: 0, zero: 0 }; MAX_SEGMENTS + 1],
+    };
+    let mut cursor = 0;
+    for segment in image.segments() {
+        let bytes = image.data(segment);
+        let slot = data.get_mut(cursor..cursor + bytes.len()).ok_or(KexecError::TooLarge)?;
+        slot.copy_from_slice(bytes);
+        plan.transfers[plan.count as usize] = Transfer {
+            dst: segment.addr,
+            src: DATA + cursor as u64,
+            len: segment.file_size,
+            zero: segment.mem_size - segment.file_size,
+        };
+        plan.count += 1;
+        cursor = (cursor + bytes.len() + 15) & !15;
+    }
+
+    // the handoff area, copied last.
+    let handoff = data.get_mut(cursor..cursor + HANDOFF_SIZE as usize).ok_or(KexecError::TooLarge)?;
+    handoff.fill(0);
+    let old_multiboot = u64::from(boot_info.multiboot_info);
+    // Safety: the multiboot info always starts with its total size, and is identity mapped.
+    let src = unsafe {
+        let size = *((PHYSICAL_MEMORY_OFFSET as u64 + old_multiboot) as *const u32);
+        core::slice::from_raw_parts((PHYSICAL_MEMORY_OFFSET as u64 + old_multiboot) as *const u8, size as usize)
+    };
+    let (_, memory_map) = copy_multiboot(src, &mut handoff[HANDOFF_MULTIBOOT as usize..])?;
+    let mut new_info = boot_info.clone();
+    new_info.header.version = BOOT_INFO_VERSION;
+    new_info.header.length = size_of::<BootInfoInput>() as u16;
+    new_info.multiboot_info = (HANDOFF_ADDR + HANDOFF_MULTIBOOT) as u32;
+    new_info.memory_map_addr = HANDOFF_ADDR + HANDOFF_MULTIBOOT + memory_map as u64;
+    new_info.page_table_base = PML4;
+    new_info.stack_top = STACK_TOP;
+    new_info.kernel_entry = image.entry();
+    // Safety: the handoff area is far larger than the boot info.
+    unsafe { handoff.as_mut_ptr().cast::<BootInfoInput>().write_unaligned(new_info) };
+    plan.transfers[plan.count as usize] = Transfer { dst: HANDOFF_ADDR, src: DATA + cursor as u64, len: HANDOFF_SIZE, zero: 0 };
+    plan.count += 1;
+
+    // identity maps the first GiB with 2 MiB pages, like the boot stage.
+    const PRESENT_WRITABLE: u64 = 0b11;
+    const HUGE: u64 = 1 << 7;
+    // Safety: reserved, and only used here.
+    let tables = unsafe { staging(PML4, 0x3000) };
+    tables.fill(0);
+    let entries = |table: u64| (table - PML4) as usize..(table - PML4) as usize + 0x1000;
+    tables[entries(PML4)][..8].copy_from_slice(&(PDPT | PRESENT_WRITABLE).to_le_bytes());
+    tables[entries(PDPT)][..8].copy_from_slice(&(PD | PRESENT_WRITABLE).to_le_bytes());
+    for (i, entry) in tables[entries(PD)].chunks_exact_mut(8).enumerate() {
+        entry.copy_from_slice(&((i as u64) << 21 | PRESENT_WRITABLE | HUGE).to_le_bytes());
+    }
+
+    // Safety: reserved, and only used here. The trampoline is position independent.
+    unsafe {
+        let code = core::slice::from_raw_parts(
+            &raw const ion_kexec_trampoline,
+            (&raw const ion_kexec_trampoline_end).offset_from(&raw const ion_kexec_trampoline) as usize,
+        );
+        staging(TRAMPOLINE, code.len() as u64).copy_from_slice(code);
+        ((PHYSICAL_MEMORY_OFFSET as u64 + PLAN) as *mut Plan).write(plan);
+    }
+    Ok(())
+}
+
+/// Soft reboots into the kernel `image`, an ELF file like the one GRUB loads.
+/// 
+/// Only returns if the image can not be loaded, before anything was torn down.
+/// # Errors
+/// Returns an error if the staging area can not be reserved, or the image is not a valid kernel.
+pub fn soft_reboot(image: &[u8]) -> Result<Infallible, KexecError> {
+    crate::interrupts::context::assert_not_interrupt!();
+    let boot_info = BOOT_INFO.get().ok_or(KexecError::Unavailable)?;
+    let image = Image::parse(image)?;
+    reserve()?;
+    // Safety: reserved, soft reboots do not run at the same time, as the first one never returns.
+    unsafe { stage(&image, boot_info)? };
+
+    info!("Soft rebooting, into a kernel of {} segments.", image.segments().len());
+    let summary = super::shutdown::run_hooks();
+    info!("{summary}.");
+    crate::text::flush();
+    x86_64::instructions::interrupts::disable();
+    // Safety: the trampoline and its plan were staged, and it is position independent.
+    unsafe {
+        let trampoline: extern "C" fn(u64) -> ! = core::mem::transmute(TRAMPOLINE as usize);
+        trampoline(PLAN)
+    }
+}
+
+/// Soft reboots into the kernel file `kexec FILE`
+pub fn command(args: &[&str]) -> i32 {
+    let [_, path] = args else {
+        println!("usage: kexec FILE");
+        return 2;
+    };
+    let image = match crate::fs::read(path) {
+        Ok(image) => image,
+        Err(e) => {
+            println!("kexec: {path}: {e}");
+            return 1;
+        }
+    };
+    match soft_reboot(&image) {
+        Ok(never) => match never {},
+        Err(e) => {
+            println!("kexec: {e}");
+            1
+        }
+    }
+}
+
+/// Tests parsing a kernel image, and handing off the multiboot info.
+#[cfg(feature = "test")]
+pub fn test_kexec(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use alloc::vec;
+
+    use crate::test::{test_assert, test_assert_eq};
+
+    // header, one program header at 64, a symbol table and string table after the data.
+    let mut elf = vec![0u8; 0x200];
+    let put = |elf: &mut [u8], offset: usize, value: &[u8]| elf[offset..offset + value.len()].copy_from_slice(value);
+    put(&mut elf, 0, b"\x7fELF\x02\x01");
+    put(&mut elf, 16, &2u16.to_le_bytes());
+    put(&mut elf, 18, &0x3Eu16.to_le_bytes());
+    put(&mut elf, 32, &64u64.to_le_bytes());
+    put(&mut elf, 40, &0x100u64.to_le_bytes());
+    put(&mut elf, 54, &56u16.to_le_bytes());
+    put(&mut elf, 56, &1u16.to_le_bytes());
+    put(&mut elf, 58, &64u16.to_le_bytes());
+    put(&mut elf, 60, &2u16.to_le_bytes());
+    // PT_LOAD of 0x10 bytes at 1 MiB, 0x20 in memory, from offset 0xE0
+    put(&mut elf, 64, &1u32.to_le_bytes());
+    put(&mut elf, 64 + 8, &0xE0u64.to_le_bytes());
+    put(&mut elf, 64 + 24, &0x10_0000u64.to_le_bytes());
+    put(&mut elf, 64 + 32, &0x10u64.to_le_bytes());
+    put(&mut elf, 64 + 40, &0x20u64.to_le_bytes());
+    // the symbol table at 0x180, with a null symbol and `kernel_main`, linked to section 1
+    put(&mut elf, 0x100 + 4, &2u32.to_le_bytes());
+    put(&mut elf, 0x100 + 24, &0x180u64.to_le_bytes());
+    put(&mut elf, 0x100 + 32, &48u64.to_le_bytes());
+    put(&mut elf, 0x100 + 40, &1u32.to_le_bytes());
+    put(&mut elf, 0x100 + 56, &24u64.to_le_bytes());
+    // the string table at 0xF0
+    put(&mut elf, 0x140 + 4, &3u32.to_le_bytes());
+    put(&mut elf, 0x140 + 24, &0xF0u64.to_le_bytes());
+    put(&mut elf, 0x140 + 32, &0x10u64.to_le_bytes());
+    put(&mut elf, 0xF0, b"\0kernel_main\0");
+    put(&mut elf, 0x180 + 24, &1u32.to_le_bytes());
+    put(&mut elf, 0x180 + 24 + 8, &0x10_0004u64.to_le_bytes());
+
+    let image = Image::parse(&elf).map_err(|_| "the image was not parsed")?;
+    test_assert_eq!(image.segments(), &[Segment { addr: 0x10_0000, offset: 0xE0, file_size: 0x10, mem_size: 0x20 }][..])?;
+    test_assert_eq!(image.entry(), 0x10_0004)?;
+    test_assert_eq!(image.data(&image.segments()[0]).len(), 0x10)?;
+
+    // over the staging area.
+    let mut bad = elf.clone();
+    put(&mut bad, 64 + 24, &STAGING_ADDR.to_le_bytes());
+    test_assert_eq!(Image::parse(&bad).map(|_| ()), Err(KexecError::BadSegment(STAGING_ADDR)))?;
+    let mut bad = elf.clone();
+    put(&mut bad, 0xF0 + 1, b"kernel_mane");
+    test_assert_eq!(Image::parse(&bad).map(|_| ()), Err(KexecError::NoEntry))?;
+    test_assert_eq!(Image::parse(&elf[..0x100]).map(|_| ()), Err(KexecError::Truncated))?;
+    // a symbol table which ends past the end of the address space.
+    let mut bad = elf.clone();
+    put(&mut bad, 0x100 + 32, &u64::MAX.to_le_bytes());
+    test_assert_eq!(Image::parse(&bad).map(|_| ()), Err(KexecError::Truncated))?;
+
+    // a module, a memory map and the end tag, the module is dropped.
+    let mut multiboot = vec![0u8; 8];
+    for (typ, size) in [(3u32, 20u32), (6, 16), (0, 8)] {
+        let start = multiboot.len();
+        multiboot.resize(start + ((size as usize + 7) & !7), 0);
+        put(&mut multiboot, start, &typ.to_le_bytes());
+        put(&mut multiboot, start + 4, &size.to_le_bytes());
+    }
+    let len = multiboot.len() as u32;
+    put(&mut multiboot, 0, &len.to_le_bytes());
+    let mut out = [0xFFu8; 64];
+    let (size, memory_map) = copy_multiboot(&multiboot, &mut out).map_err(|_| "the multiboot info was not copied")?;
+    test_assert_eq!((size, memory_map), (32, 8))?;
+    test_assert_eq!(u32_at(&out, 0), Ok(32))?;
+    test_assert!(u32_at(&out, 8) == Ok(6) && u32_at(&out, 24) == Ok(0))?;
+    test_assert_eq!(copy_multiboot(&multiboot, &mut out[..16]), Err(KexecError::BadMultiboot))
+}
@@ -0,0 +1,74 @@
+//! Rebooting and powering off the machine.
+use x86_64::instructions::{interrupts, port::Port};
+
+/// Reboots the machine.
+/// 
+/// The 8042 keyboard controller is asked to pulse the reset line first. If that does not work, the
+/// CPU is triple faulted, which resets it as well.
+pub fn reboot() -> ! {
+    interrupts::disable();
+
+    let mut status = Port::<u8>::new(0x64);
+    // Safety: the 8042 command port is always present on PC compatibles, and we wait for its input
+    // buffer to be empty before writing.
+    unsafe {
+        for _ in 0..0x10000 {
+            if status.read() & 0b10 == 0 {
+                break;
+            }
+        }
+        status.write(0xFE);
+    }
+
+    // give the controller some time.
+    for _ in 0..0x100000 {
+        core::hint::spin_loop();
+    }
+
+    triple_fault()
+}
+
+/// Resets the CPU by loading an empty IDT, and raising an exception.
+fn triple_fault() -> ! {
+    use x86_64::{VirtAddr, instructions::tables::lidt, structures::DescriptorTablePointer};
+
+    let empty = DescriptorTablePointer { limit: 0, base: VirtAddr::zero() };
+    // Safety: we want the CPU to fault, the IDT is never used for anything else.
+    unsafe {
+        lidt(&empty);
+        core::arch::asm!("int3", options(noreturn));
+    }
+}
+
+/// Powers the machine off.
+/// 
+/// Until ACPI is parsed, this uses the fixed ports of the emulators' ACPI implementations (QEMU,
+/// Bochs and VirtualBox). If none of them work, the CPU is halted forever.
+pub fn poweroff() -> ! {
+    interrupts::disable();
+
+    // Safety: on real hardware these ports are unused, or ignore the writes.
+    unsafe {
+        Port::<u16>::new(0x604).write(0x2000);
+        Port::<u16>::new(0xB004).write(0x2000);
+        Port::<u16>::new(0x4004).write(0x3400);
+    }
+
+    crate::serial_println!("Power off failed, it is now safe to turn off the machine.");
+    crate::hlt_loop()
+}
+
+/// The `reboot` shell command.
+pub fn reboot_command(args: &[&str]) -> i32 {
+    if args.len() > 1 {
+        crate::text::println!("usage: reboot");
+        return 2;
+    }
+    reboot_sequence()
+}
+
+pub mod kexec;
+pub mod shutdown;
+
+pub use kexec::soft_reboot;
+pub use shutdown::{reboot_sequence, shutdown_sequence};
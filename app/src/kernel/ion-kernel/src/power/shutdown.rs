@@ -0,0 +1,121 @@
+//! The shutdown sequence.
+//! 
+//! Subsystems [`register`] hooks, which run once, stage by stage, before the machine is powered
+//! off. Within a stage, hooks run in the order they were registered.
+use core::{fmt::{self, Display}, sync::atomic::{AtomicBool, Ordering}};
+
+use spin::Mutex;
+
+use crate::{collections::{ArrayVec, CapacityError}, log::{info, warn}};
+
+/// Maximum amount of shutdown hooks.
+pub const MAX_HOOKS: usize = 32;
+
+/// The stages of the shutdown sequence, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// Stopping the scheduler, and background work.
+    Tasks,
+    /// Syncing files to the disk.
+    Filesystems,
+    /// Flushing block caches and journals.
+    Storage,
+    /// Detaching drivers, once nothing uses them anymore.
+    Drivers,
+}
+
+impl Stage {
+    /// Every stage, in order.
+    pub const ALL: [Stage; 4] = [Stage::Tasks, Stage::Filesystems, Stage::Storage, Stage::Drivers];
+}
+
+/// A function run while shutting down.
+/// 
+/// An error is reported, but does not stop the sequence.
+pub type HookFn = fn() -> Result<(), &'static str>;
+
+#[derive(Debug, Clone, Copy)]
+struct Hook {
+    stage: Stage,
+    name: &'static str,
+    run: HookFn,
+}
+
+static HOOKS: Mutex<ArrayVec<Hook, MAX_HOOKS>> = Mutex::new(ArrayVec::new());
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Registers a hook, which runs in `stage` of the shutdown sequence.
+/// # Errors
+/// Returns an error if there are [`MAX_HOOKS`] already.
+pub fn register(stage: Stage, name: &'static str, run: HookFn) -> Result<(), CapacityError> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        HOOKS.lock().push(Hook { stage, name, run }).map_err(|_| CapacityError(()))
+    })
+}
+
+/// What happened while running the hooks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// Hooks which succeeded.
+    pub succeeded: usize,
+    /// Hooks which returned an error.
+    pub failed: usize,
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} shutdown hooks ran, {} failed", self.succeeded + self.failed, self.failed)
+    }
+}
+
+/// Runs every hook, stage by stage, and returns a summary.
+/// 
+/// Only the first call runs the hooks, later calls return an empty summary.
+pub fn run_hooks() -> Summary {
+    let mut summary = Summary::default();
+    if SHUTTING_DOWN.swap(true, Ordering::SeqCst) {
+        return summary;
+    }
+    // copied, so hooks may not deadlock by registering hooks.
+    let hooks = x86_64::instructions::interrupts::without_interrupts(|| HOOKS.lock().clone());
+    for stage in Stage::ALL {
+        for hook in hooks.iter().filter(|h| h.stage == stage) {
+            match (hook.run)() {
+                Ok(()) => summary.succeeded += 1,
+                Err(e) => {
+                    warn!("shutdown: {:?}: {} failed: {}", stage, hook.name, e);
+                    summary.failed += 1;
+                }
+            }
+        }
+    }
+    summary
+}
+
+/// Returns whether the shutdown sequence has started.
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// Shuts the kernel down gracefully, and powers the machine off.
+/// 
+/// The hooks are run (see [`run_hooks`]), a summary is printed, and the output is flushed before
+/// powering off.
+pub fn shutdown_sequence() -> ! {
+    info!("Shutting down.");
+    finish(super::poweroff)
+}
+
+/// Shuts the kernel down gracefully like [`shutdown_sequence`], and reboots the machine.
+pub fn reboot_sequence() -> ! {
+    info!("Rebooting.");
+    finish(super::reboot)
+}
+
+/// Runs the hooks, prints the summary and flushes the output, then calls `then`
+fn finish(then: fn() -> !) -> ! {
+    let summary = run_hooks();
+    info!("{summary}.");
+    crate::text::flush();
+    then()
+}
@@ -1,30 +1,53 @@
+//! Output to the host.
+//! 
+//! Everything goes to the QEMU debug port ([`SERIAL1`]). The UARTs (COM1 to COM4) are found by
+//! [`tx`], and used by their [roles](config::Role): logs, the serial console, and a GDB stub.
+use core::fmt::{self, Write};
+
 use uart_16550::SerialPort;
-use spin::Mutex;
+use crate::sync::Mutex;
 use lazy_static::lazy_static;
 
+pub mod config;
+pub mod tty;
+pub mod tx;
+
+use config::Role;
+
 lazy_static! {
-    /// Serial Port
+    /// Serial Port, this is the QEMU debug port (`-debugcon`)
     pub static ref SERIAL1: Mutex<SerialPort> = {
         let mut serial_port = unsafe { SerialPort::new(0xE9) };
         serial_port.init();
-        Mutex::new(serial_port)
+        Mutex::new("SERIAL1", serial_port)
     };
 }
 
+/// Writes to the debug port and the port with `role`, if there is one.
+pub fn write_to(role: Role, args: fmt::Arguments) -> fmt::Result {
+    if let Some(port) = config::role(role) {
+        tx::write_fmt(port, args);
+    }
+    crate::interrupts::stats::without_interrupts(|| SERIAL1.lock().write_fmt(args))
+}
+
+/// Writes to the debug port and the [log](Role::Log) port, without recording it for tests.
+pub fn write_fmt(args: fmt::Arguments) -> fmt::Result {
+    write_to(Role::Log, args)
+}
+
 #[doc(hidden)]
 pub fn _print(args: ::core::fmt::Arguments) {
-    use core::fmt::Write;
-    use x86_64::instructions::interrupts;
-
     // Even though `write_fmt` always returns `Ok(())`, we are better off ignoring the value instead of
     // panicking.
     //
     // this also must run without interrupts, as some of our interrupt handlers print to Serial, 
     // which could cause a deadlock if we are already printing. see 
     // https://os.phil-opp.com/hardware-interrupts/#provoking-a-deadlock
-    let _ = interrupts::without_interrupts(|| {
-        SERIAL1.lock().write_fmt(args)
-    });
+    let _ = write_fmt(args);
+
+    #[cfg(feature = "test")]
+    crate::test::capture::SERIAL_CAPTURE.record(args);
 }
 
 /// Prints to the host through the serial interface.
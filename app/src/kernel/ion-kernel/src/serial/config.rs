@@ -0,0 +1,251 @@
+//! Line settings of the serial ports, and what they are used for.
+//! 
+//! Each port has its own [`Config`], written like Linux does, E.g. `115200n8` for 115200 baud, no
+//! parity and 8 data bits. A port can be given [roles](Role) on the kernel command line:
+//! 
+//! ```text
+//! console=ttyS0,115200n8 serial.log=ttyS1,9600 serial.gdb=ttyS2
+//! ```
+//! 
+//! By default, `ttyS0` (COM1) is used for logs and the serial console, if it exists.
+use core::{fmt::{self, Display}, str::FromStr, sync::atomic::{AtomicU8, Ordering}};
+
+use super::tx::{self, PORTS};
+use crate::{boot::cmdline::CommandLine, c_lib::BootInfo, log::{info, warn}};
+
+/// The rate of the UART clock, the fastest baud rate.
+pub const MAX_BAUD: u32 = 115_200;
+
+/// The parity bit of each character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    /// No parity bit, `n`
+    None,
+    /// The amount of set bits is odd, `o`
+    Odd,
+    /// The amount of set bits is even, `e`
+    Even,
+}
+
+/// The line settings of a port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// Bits per second, [`MAX_BAUD`] divided by a whole number, at most [`u16::MAX`]
+    pub baud: u32,
+    /// The parity bit.
+    pub parity: Parity,
+    /// Bits per character, 5 to 8.
+    pub data_bits: u8,
+}
+
+impl Config {
+    /// `115200n8`
+    pub const DEFAULT: Self = Self { baud: MAX_BAUD, parity: Parity::None, data_bits: 8 };
+
+    /// The divisor of the UART clock, `None` if it is not a whole number, or does not fit the
+    /// divisor latch.
+    pub fn divisor(&self) -> Option<u16> {
+        if self.baud == 0 || !MAX_BAUD.is_multiple_of(self.baud) {
+            return None;
+        }
+        u16::try_from(MAX_BAUD / self.baud).ok()
+    }
+
+    /// The value of the line control register, with one stop bit.
+    pub fn line_control(&self) -> u8 {
+        let parity = match self.parity {
+            Parity::None => 0,
+            Parity::Odd => 0b01 << 3,
+            Parity::Even => 0b11 << 3,
+        };
+        (self.data_bits - 5) | parity
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Why a [`Config`] or port could not be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseConfigError {
+    /// The baud rate is not a number, or has no [divisor](Config::divisor)
+    Baud,
+    /// The parity is not `n`, `o` or `e`
+    Parity,
+    /// The data bits are not 5 to 8.
+    DataBits,
+    /// The port is not `ttyS0` to `ttyS3`
+    Port,
+}
+
+impl Display for ParseConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Baud => write!(f, "the baud rate must divide {MAX_BAUD}, and be at least 2"),
+            Self::Parity => write!(f, "the parity must be n, o or e"),
+            Self::DataBits => write!(f, "the data bits must be 5 to 8"),
+            Self::Port => write!(f, "the port must be ttyS0 to ttyS{}", PORTS - 1),
+        }
+    }
+}
+
+impl FromStr for Config {
+    type Err = ParseConfigError;
+
+    /// Parses `BAUD[PARITY[BITS]]`, E.g. `9600` or `115200n8`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (baud, rest) = s.split_at(digits);
+        let baud: u32 = baud.parse().map_err(|_| ParseConfigError::Baud)?;
+        if (Self { baud, ..Self::DEFAULT }).divisor().is_none() {
+            return Err(ParseConfigError::Baud);
+        }
+        let mut rest = rest.chars();
+        let parity = match rest.next() {
+            None | Some('n') => Parity::None,
+            Some('o') => Parity::Odd,
+            Some('e') => Parity::Even,
+            Some(_) => return Err(ParseConfigError::Parity),
+        };
+        let data_bits = match rest.as_str() {
+            "" => 8,
+            bits => bits.parse().ok().filter(|bits| (5..=8).contains(bits)).ok_or(ParseConfigError::DataBits)?,
+        };
+        Ok(Self { baud, parity, data_bits })
+    }
+}
+
+impl Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'n',
+            Parity::Odd => 'o',
+            Parity::Even => 'e',
+        };
+        write!(f, "{}{parity}{}", self.baud, self.data_bits)
+    }
+}
+
+/// The name of a port, E.g. `ttyS0` for COM1.
+pub fn port_name(port: usize) -> &'static str {
+    ["ttyS0", "ttyS1", "ttyS2", "ttyS3"][port]
+}
+
+/// The port called `name`
+pub fn parse_port(name: &str) -> Option<usize> {
+    (0..PORTS).find(|&port| port_name(port) == name)
+}
+
+/// What a port is used for, at most one port each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Log messages, see [`SerialSink`](crate::log::sink::SerialSink): `serial.log=`
+    Log,
+    /// The [serial console](crate::console::SerialConsole): `console=`
+    Console,
+    /// A GDB remote stub, which there is none of yet: `serial.gdb=`
+    Gdb,
+}
+
+impl Role {
+    /// Every role.
+    pub const ALL: [Role; 3] = [Role::Log, Role::Console, Role::Gdb];
+
+    /// The kernel command line option which assigns this role.
+    pub fn option(self) -> &'static str {
+        match self {
+            Role::Log => "serial.log",
+            Role::Console => "console",
+            Role::Gdb => "serial.gdb",
+        }
+    }
+}
+
+/// Stored for roles without a port.
+const NO_PORT: u8 = u8::MAX;
+
+static ROLES: [AtomicU8; Role::ALL.len()] = [const { AtomicU8::new(NO_PORT) }; Role::ALL.len()];
+
+/// Gives `role` to `port`, or to no port.
+pub fn set_role(role: Role, port: Option<usize>) {
+    ROLES[role as usize].store(port.map_or(NO_PORT, |port| port as u8), Ordering::Relaxed);
+}
+
+/// The port which has `role`
+pub fn role(role: Role) -> Option<usize> {
+    match ROLES[role as usize].load(Ordering::Relaxed) {
+        NO_PORT => None,
+        port => Some(usize::from(port)),
+    }
+}
+
+/// Parses `ttySN[,CONFIG]`, the value of the role options.
+pub fn parse_assignment(value: &str) -> Result<(usize, Option<Config>), ParseConfigError> {
+    let (name, config) = match value.split_once(',') {
+        Some((name, config)) => (name, Some(config.parse()?)),
+        None => (value, None),
+    };
+    Ok((parse_port(name).ok_or(ParseConfigError::Port)?, config))
+}
+
+/// The role options on the kernel `command_line`. `console=` for other consoles, such as `tty0`,
+/// is skipped.
+pub fn from_command_line(command_line: &str) -> impl Iterator<Item = (Role, Result<(usize, Option<Config>), ParseConfigError>)> + '_ {
+    CommandLine::new(command_line).options().filter_map(|(option, value)| {
+        let value = value?;
+        let role = Role::ALL.into_iter().find(|role| role.option() == option)?;
+        if role == Role::Console && !value.starts_with("ttyS") {
+            return None;
+        }
+        Some((role, parse_assignment(value)))
+    })
+}
+
+/// Assigns the roles, and configures the ports, from the kernel command line.
+pub fn init(boot_info: &BootInfo) {
+    for (role, assignment) in from_command_line(boot_info.command_line) {
+        let (port, config) = match assignment {
+            Ok(assignment) => assignment,
+            Err(e) => {
+                warn!("Ignoring the {}= argument: {e}", role.option());
+                continue;
+            }
+        };
+        if !tx::is_present(port) {
+            warn!("Ignoring the {}= argument: there is no {}", role.option(), port_name(port));
+            continue;
+        }
+        if let Some(config) = config {
+            tx::configure(port, config);
+        }
+        set_role(role, Some(port));
+        info!("{}: {role:?} at {}", port_name(port), tx::config(port).unwrap_or_default());
+    }
+}
+
+/// Tests parsing configs and role options.
+#[cfg(feature = "test")]
+pub fn test_serial_config(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use alloc::vec::Vec;
+
+    use crate::test::test_assert_eq;
+
+    test_assert_eq!("9600".parse(), Ok(Config { baud: 9600, parity: Parity::None, data_bits: 8 }))?;
+    test_assert_eq!("57600e7".parse(), Ok(Config { baud: 57600, parity: Parity::Even, data_bits: 7 }))?;
+    test_assert_eq!("1000".parse::<Config>(), Err(ParseConfigError::Baud))?;
+    // 115200 does not fit the divisor latch.
+    test_assert_eq!("1".parse::<Config>(), Err(ParseConfigError::Baud))?;
+    test_assert_eq!("9600x".parse::<Config>(), Err(ParseConfigError::Parity))?;
+    test_assert_eq!("9600n9".parse::<Config>(), Err(ParseConfigError::DataBits))?;
+    test_assert_eq!(alloc::format!("{}", Config::DEFAULT).as_str(), "115200n8")?;
+    test_assert_eq!(Config { baud: 9600, parity: Parity::Odd, data_bits: 7 }.line_control(), 0b01010)?;
+
+    let roles: Vec<_> = from_command_line("quiet console=tty0 console=ttyS1,9600 serial.gdb=ttyS7").collect();
+    test_assert_eq!(roles, [
+        (Role::Console, Ok((1, Some(Config { baud: 9600, parity: Parity::None, data_bits: 8 })))),
+        (Role::Gdb, Err(ParseConfigError::Port)),
+    ])
+}
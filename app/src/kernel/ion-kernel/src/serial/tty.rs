@@ -0,0 +1,64 @@
+//! The serial ports as [devices](crate::fs::devfs), `/dev/ttyS0` to `/dev/ttyS3`
+//! 
+//! Writes are [queued](super::tx) for the port. The UARTs are only used for output so far, so
+//! reads always return 0 bytes.
+use alloc::boxed::Box;
+
+use super::{config::port_name, tx::{self, PORTS}};
+use crate::{fs::{File, FileType, Metadata, devfs::{self, Device}}, io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write}, log::warn};
+
+/// The device of a port.
+#[derive(Debug, Clone, Copy)]
+pub struct Tty(usize);
+
+static TTYS: [Tty; PORTS] = [Tty(0), Tty(1), Tty(2), Tty(3)];
+
+impl Device for Tty {
+    fn open(&self) -> io::Result<Box<dyn File>> {
+        Ok(Box::new(TtyFile(self.0)))
+    }
+}
+
+/// An open port.
+#[derive(Debug)]
+pub struct TtyFile(usize);
+
+impl Read for TtyFile {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Ok(0)
+    }
+}
+
+impl Write for TtyFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        tx::write(self.0, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for TtyFile {
+    fn seek(&mut self, _: SeekFrom) -> io::Result<u64> {
+        Err(Error::new(ErrorKind::InvalidInput, "a terminal has no position"))
+    }
+}
+
+impl File for TtyFile {
+    fn metadata(&self) -> Metadata {
+        Metadata { file_type: FileType::Device, len: 0 }
+    }
+
+    fn set_len(&mut self, _: u64) -> io::Result<()> {
+        Err(Error::new(ErrorKind::InvalidInput, "a terminal has no length"))
+    }
+}
+
+/// Registers the node of `port`
+pub fn register(port: usize) {
+    if devfs::register(port_name(port), &TTYS[port]).is_err() {
+        warn!("/dev/{} was not registered", port_name(port));
+    }
+}
@@ -0,0 +1,347 @@
+//! Interrupt driven output on the UARTs, COM1 to COM4.
+//! 
+//! Writers only append to a bounded queue of the port, they never wait for the UART. When its
+//! transmit holding register is empty, the UART raises its interrupt, and the handler moves up to
+//! [`FIFO_SIZE`] bytes from the queue into its FIFO. COM1 and COM3 share IRQ 4, COM2 and COM4 share
+//! IRQ 3. At 115200 baud a port sends about 11 KiB/s, so heavy logging fills the queue, and the
+//! [`Overflow`] policy decides which bytes are lost.
+//! 
+//! Ports are numbered from 0, like their [names](super::config::port_name) (`ttyS0` is COM1). The
+//! debug port used by [`SERIAL1`](super::SERIAL1) takes a byte per `out`, so it does not need this.
+//! 
+//! ```rust,no_run
+//! serial::tx::set_overflow(0, Overflow::Overwrite);
+//! serial::tx::write_fmt(0, format_args!("hello\n"));
+//! serial::tx::flush();
+//! ```
+use core::{fmt::{self, Write}, sync::atomic::{AtomicBool, Ordering}};
+
+use spin::Mutex;
+use x86_64::{instructions::{interrupts::without_interrupts, port::Port}, structures::idt::InterruptStackFrame};
+
+use super::config::{Config, Role, port_name, set_role};
+use crate::{collections::{ArrayString, RingBuffer}, interrupts::{self, pic8259::handlers::notify}, log::info};
+
+/// The amount of ports.
+pub const PORTS: usize = 4;
+/// The I/O ports of COM1 to COM4.
+pub const BASES: [u16; PORTS] = [0x3F8, 0x2F8, 0x3E8, 0x2E8];
+/// The interrupt lines of COM1 to COM4.
+pub const IRQS: [u8; PORTS] = [4, 3, 4, 3];
+/// Bytes queued for a UART at most.
+pub const QUEUE_SIZE: usize = 4096;
+/// Bytes the UART takes at once, the size of the FIFO of a 16550A.
+pub const FIFO_SIZE: usize = 16;
+
+// Registers, as offsets from the base.
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+const INTERRUPT_ID: u16 = 2;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+const SCRATCH: u16 = 7;
+
+/// Line control: the data register holds the divisor.
+const DIVISOR_LATCH: u8 = 1 << 7;
+/// Line status: the transmit holding register (and FIFO) is empty.
+const THR_EMPTY: u8 = 1 << 5;
+/// Interrupt enable: the transmit holding register is empty.
+const ENABLE_THR_EMPTY: u8 = 1 << 1;
+
+/// What happens to bytes written while the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// The new bytes are dropped, so the output has a gap, and then continues.
+    Drop,
+    /// The oldest queued bytes are dropped, so the latest output is kept.
+    Overwrite,
+}
+
+/// Bytes waiting for the UART.
+#[derive(Debug)]
+pub struct TxQueue<const N: usize> {
+    ring: RingBuffer<u8, N>,
+    overflow: Overflow,
+    dropped: u64,
+}
+
+impl<const N: usize> TxQueue<N> {
+    /// An empty queue.
+    pub const fn new(overflow: Overflow) -> Self {
+        Self { ring: RingBuffer::new(), overflow, dropped: 0 }
+    }
+
+    /// Queues `bytes`, following the overflow policy when it is full.
+    pub fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            let dropped = match self.overflow {
+                Overflow::Drop => self.ring.push(byte).is_err(),
+                Overflow::Overwrite => self.ring.push_overwrite(byte).is_some(),
+            };
+            self.dropped += u64::from(dropped);
+        }
+    }
+
+    /// Takes the oldest byte.
+    pub fn pop(&mut self) -> Option<u8> {
+        self.ring.pop()
+    }
+
+    /// The amount of queued bytes.
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    /// Wether no bytes are queued.
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    /// Bytes lost to overflows.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+impl<const N: usize> Write for TxQueue<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Uart {
+    base: u16,
+    queue: Mutex<TxQueue<QUEUE_SIZE>>,
+    /// Set once the UART is found.
+    present: AtomicBool,
+    config: Mutex<Config>,
+}
+
+impl Uart {
+    const fn new(base: u16) -> Self {
+        Self {
+            base,
+            queue: Mutex::new(TxQueue::new(Overflow::Drop)),
+            present: AtomicBool::new(false),
+            config: Mutex::new(Config::DEFAULT),
+        }
+    }
+
+    fn register(&self, register: u16) -> Port<u8> {
+        Port::new(self.base + register)
+    }
+
+    /// Moves queued bytes into the FIFO, if the UART is ready for them.
+    /// 
+    /// Called with the lock of the queue, so only one caller fills the FIFO.
+    fn drain(&self, queue: &mut TxQueue<QUEUE_SIZE>) {
+        // Safety: the UART was found by `probe`
+        unsafe {
+            if self.register(LINE_STATUS).read() & THR_EMPTY == 0 {
+                return;
+            }
+            for byte in core::iter::from_fn(|| queue.pop()).take(FIFO_SIZE) {
+                self.register(DATA).write(byte);
+            }
+        }
+    }
+
+    /// Programs the line settings, and enables the FIFOs and the transmit interrupt.
+    /// 
+    /// # Safety
+    /// There must be a UART at the base.
+    unsafe fn program(&self, config: Config) {
+        // only valid configs are programmed, see `configure`
+        let [low, high] = config.divisor().unwrap_or(1).to_le_bytes();
+        // Safety: see the caller, these are the standard registers of a 16550.
+        unsafe {
+            self.register(INTERRUPT_ENABLE).write(0);
+            self.register(LINE_CONTROL).write(DIVISOR_LATCH);
+            self.register(DATA).write(low);
+            self.register(INTERRUPT_ENABLE).write(high);
+            self.register(LINE_CONTROL).write(config.line_control());
+            // enable and clear the FIFOs.
+            self.register(FIFO_CONTROL).write(0xC7);
+            // DTR, RTS, and OUT2, which connects the interrupt line.
+            self.register(MODEM_CONTROL).write(0x0B);
+            self.register(INTERRUPT_ENABLE).write(ENABLE_THR_EMPTY);
+        }
+    }
+}
+
+static UARTS: [Uart; PORTS] = [Uart::new(BASES[0]), Uart::new(BASES[1]), Uart::new(BASES[2]), Uart::new(BASES[3])];
+
+/// Finds `port`, and sets it up with the default [`Config`]. Returns wether there is a UART.
+pub fn probe(port: usize) -> bool {
+    let uart = &UARTS[port];
+    // Safety: writes to the scratch register are read back, to find out if there is a UART at all.
+    let found = unsafe {
+        uart.register(SCRATCH).write(0x5A);
+        uart.register(SCRATCH).read() == 0x5A
+    };
+    if found {
+        let config = without_interrupts(|| *uart.config.lock());
+        // Safety: the UART was found.
+        unsafe { uart.program(config) };
+        uart.present.store(true, Ordering::Release);
+        interrupts::unmask(IRQS[port]);
+    }
+    found
+}
+
+/// Finds the ports, and registers them as [devices](super::tty). The first one is used for logs
+/// and the serial console, until the [command line](super::config::init) says otherwise.
+pub fn init() {
+    let mut found = ArrayString::<32>::new();
+    for port in (0..PORTS).filter(|&port| probe(port)) {
+        super::tty::register(port);
+        if found.is_empty() {
+            set_role(Role::Log, Some(port));
+            set_role(Role::Console, Some(port));
+        }
+        let _ = write!(found, " {}", port_name(port));
+    }
+    if !found.is_empty() {
+        info!("Serial ports:{found}, interrupt driven, {QUEUE_SIZE} bytes queued at most");
+    }
+}
+
+/// Wether there is a UART at `port`, once [`init`] found it.
+pub fn is_present(port: usize) -> bool {
+    UARTS.get(port).is_some_and(|uart| uart.present.load(Ordering::Acquire))
+}
+
+/// Changes the line settings of `port`, returns wether it is present, and the baud rate has a
+/// [divisor](Config::divisor)
+/// 
+/// Queued output is sent with the new settings.
+pub fn configure(port: usize, config: Config) -> bool {
+    if !is_present(port) || config.divisor().is_none() {
+        return false;
+    }
+    let uart = &UARTS[port];
+    without_interrupts(|| {
+        let mut queue = uart.queue.lock();
+        *uart.config.lock() = config;
+        // Safety: the port is present.
+        unsafe { uart.program(config) };
+        uart.drain(&mut queue);
+    });
+    true
+}
+
+/// The line settings of `port`, if it is present.
+pub fn config(port: usize) -> Option<Config> {
+    is_present(port).then(|| without_interrupts(|| *UARTS[port].config.lock()))
+}
+
+/// Queues `bytes` for `port`, without waiting for them to be sent. Does nothing if the port is not
+/// present.
+pub fn write(port: usize, bytes: &[u8]) {
+    if !is_present(port) {
+        return;
+    }
+    let uart = &UARTS[port];
+    without_interrupts(|| {
+        let mut queue = uart.queue.lock();
+        queue.push(bytes);
+        // the interrupt only fires when the FIFO becomes empty, so an idle UART is started here.
+        uart.drain(&mut queue);
+    });
+}
+
+/// Queues formatted output for `port`, like [`write`]
+pub fn write_fmt(port: usize, args: fmt::Arguments) {
+    if !is_present(port) {
+        return;
+    }
+    let uart = &UARTS[port];
+    without_interrupts(|| {
+        let mut queue = uart.queue.lock();
+        let _ = queue.write_fmt(args);
+        uart.drain(&mut queue);
+    });
+}
+
+/// Sets what happens to output for `port` while its queue is full.
+pub fn set_overflow(port: usize, overflow: Overflow) {
+    without_interrupts(|| UARTS[port].queue.lock().overflow = overflow);
+}
+
+/// What happens to output for `port` while its queue is full.
+pub fn overflow(port: usize) -> Overflow {
+    without_interrupts(|| UARTS[port].queue.lock().overflow)
+}
+
+/// The amount of bytes queued for `port`
+pub fn pending(port: usize) -> usize {
+    without_interrupts(|| UARTS[port].queue.lock().len())
+}
+
+/// Bytes lost because the queue of `port` was full.
+pub fn dropped(port: usize) -> u64 {
+    without_interrupts(|| UARTS[port].queue.lock().dropped())
+}
+
+/// Sends every queued byte of every port, waiting for the UARTs.
+/// 
+/// Used by the panic handler, where interrupts are disabled. Skips a port whose queue is locked,
+/// as it would never be unlocked.
+pub fn flush() {
+    without_interrupts(|| {
+        for uart in (0..PORTS).filter(|&port| is_present(port)).map(|port| &UARTS[port]) {
+            let Some(mut queue) = uart.queue.try_lock() else { continue };
+            while !queue.is_empty() {
+                uart.drain(&mut queue);
+                core::hint::spin_loop();
+            }
+        }
+    });
+}
+
+/// Refills the FIFOs of the ports on `irq`
+fn handle(irq: u8) {
+    for uart in (0..PORTS).filter(|&port| IRQS[port] == irq && is_present(port)).map(|port| &UARTS[port]) {
+        // Safety: reading the interrupt identification acknowledges a transmit interrupt.
+        let _: u8 = unsafe { uart.register(INTERRUPT_ID).read() };
+        uart.drain(&mut uart.queue.lock());
+    }
+}
+
+/// Handles IRQ 4, of COM1 and COM3.
+pub extern "x86-interrupt" fn com1_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    let _context = crate::interrupts::context::enter();
+    let entry = crate::interrupts::stats::enter();
+    handle(IRQS[0]);
+    notify!(unsafe Com1, entry);
+}
+
+/// Handles IRQ 3, of COM2 and COM4.
+pub extern "x86-interrupt" fn com2_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    let _context = crate::interrupts::context::enter();
+    let entry = crate::interrupts::stats::enter();
+    handle(IRQS[1]);
+    notify!(unsafe Com2, entry);
+}
+
+/// Tests both overflow policies of the queue.
+#[cfg(feature = "test")]
+pub fn test_serial_tx(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    let mut queue = TxQueue::<4>::new(Overflow::Drop);
+    queue.push(b"abcdef");
+    test_assert_eq!(queue.len(), 4)?;
+    test_assert_eq!(queue.dropped(), 2)?;
+    test_assert_eq!(core::iter::from_fn(|| queue.pop()).collect::<alloc::vec::Vec<_>>(), b"abcd")?;
+
+    let mut queue = TxQueue::<4>::new(Overflow::Overwrite);
+    let _ = write!(queue, "abcdef");
+    test_assert_eq!(queue.dropped(), 2)?;
+    test_assert_eq!(core::iter::from_fn(|| queue.pop()).collect::<alloc::vec::Vec<_>>(), b"cdef")?;
+    test_assert!(queue.is_empty())
+}
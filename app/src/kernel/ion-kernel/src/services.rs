@@ -0,0 +1,327 @@
+//! Long running kernel services, supervised like an init system.
+//! 
+//! A [`Service`] is declared with its dependencies and a [`Restart`] policy, and is started with
+//! [`start`], which starts its dependencies first. A service is a poll function rather than a
+//! [task](crate::task): [`supervise`] runs on the boot task, and calls it over and over, until it is
+//! done. A poll which blocks holds up every other service, so long work belongs on a task the
+//! service spawns. A poll that fails or panics crashes the service, which is then restarted, if its
+//! policy says so.
+//! 
+//! ```rust,no_run
+//! use crate::services::{self, Poll, Restart, Service};
+//! 
+//! services::register(Service::new("logger", || Ok(Poll::Pending)).restart(Restart::Always))?;
+//! services::start("logger")?;
+//! ```
+use core::fmt::{self, Display};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{collections::ArrayVec, log::{info, warn}, panic::{catch, oops::oops}};
+
+/// Maximum amount of registered services.
+pub const MAX_SERVICES: usize = 16;
+/// How often a service is restarted in a row, before it is given up on.
+pub const MAX_RESTARTS: usize = 5;
+/// How deep dependencies may be nested, deeper ones are taken for a cycle.
+const MAX_DEPENDENCY_DEPTH: usize = MAX_SERVICES;
+
+/// What a poll of a service did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Poll {
+    /// The service has more work, and is polled again.
+    Pending,
+    /// The service finished.
+    Done,
+}
+
+/// A poll of a service, which crashes it if it fails.
+pub type PollFn = fn() -> Result<Poll, &'static str>;
+
+/// What runs a service.
+#[derive(Debug, Clone, Copy)]
+pub enum Entry {
+    /// A function in the kernel.
+    Fn(PollFn),
+    /// An executable, which can not be loaded yet.
+    Elf(&'static str),
+}
+
+/// When a service is restarted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Restart {
+    /// Never, it stays exited or failed.
+    Never,
+    /// If it crashed.
+    OnFailure,
+    /// Whenever it stops, unless it was stopped with [`stop`]
+    Always,
+}
+
+/// The declaration of a service.
+#[derive(Debug, Clone, Copy)]
+pub struct Service {
+    /// The name, used by [`start`], [`stop`], and dependencies.
+    pub name: &'static str,
+    /// What runs the service.
+    pub entry: Entry,
+    /// When the service is restarted.
+    pub restart: Restart,
+    /// Services which are started before this one.
+    pub dependencies: &'static [&'static str],
+}
+
+impl Service {
+    /// A service running `poll`, which is not restarted, and has no dependencies.
+    pub const fn new(name: &'static str, poll: PollFn) -> Self {
+        Self { name, entry: Entry::Fn(poll), restart: Restart::Never, dependencies: &[] }
+    }
+
+    /// Sets the restart policy.
+    pub const fn restart(self, restart: Restart) -> Self {
+        Self { restart, ..self }
+    }
+
+    /// Sets the dependencies.
+    pub const fn dependencies(self, dependencies: &'static [&'static str]) -> Self {
+        Self { dependencies, ..self }
+    }
+}
+
+/// The state of a service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Not started, or stopped.
+    Stopped,
+    /// Being polled.
+    Running,
+    /// Finished.
+    Exited,
+    /// Crashed, and was not restarted.
+    Failed,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Stopped => "stopped",
+            Self::Running => "running",
+            Self::Exited => "exited",
+            Self::Failed => "failed",
+        })
+    }
+}
+
+/// An error of the service manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceError {
+    /// There are already [`MAX_SERVICES`]
+    Full,
+    /// A service with this name is registered.
+    Duplicate(&'static str),
+    /// There is no service with this name.
+    Unknown,
+    /// A dependency is unknown, or could not be started.
+    Dependency(&'static str),
+    /// The dependencies form a cycle.
+    Cycle,
+    /// The entry of the service can not be run yet.
+    Unsupported,
+}
+
+impl Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full => write!(f, "there are too many services"),
+            Self::Duplicate(name) => write!(f, "the service `{name}` already exists"),
+            Self::Unknown => write!(f, "unknown service"),
+            Self::Dependency(name) => write!(f, "the dependency `{name}` could not be started"),
+            Self::Cycle => write!(f, "the dependencies form a cycle"),
+            Self::Unsupported => write!(f, "executables can not be loaded yet"),
+        }
+    }
+}
+
+impl core::error::Error for ServiceError {}
+
+#[derive(Debug, Clone, Copy)]
+struct State {
+    service: Service,
+    status: Status,
+    /// Restarts since it was started.
+    restarts: usize,
+}
+
+static SERVICES: Mutex<ArrayVec<State, MAX_SERVICES>> = Mutex::new(ArrayVec::new());
+
+fn with_service<R>(name: &str, f: impl FnOnce(&mut State) -> R) -> Result<R, ServiceError> {
+    without_interrupts(|| {
+        let mut services = SERVICES.lock();
+        services.iter_mut().find(|s| s.service.name == name).map(f).ok_or(ServiceError::Unknown)
+    })
+}
+
+/// Registers a service, which is stopped until [`start`]ed.
+pub fn register(service: Service) -> Result<(), ServiceError> {
+    without_interrupts(|| {
+        let mut services = SERVICES.lock();
+        if services.iter().any(|s| s.service.name == service.name) {
+            return Err(ServiceError::Duplicate(service.name));
+        }
+        services.push(State { service, status: Status::Stopped, restarts: 0 }).map_err(|_| ServiceError::Full)
+    })
+}
+
+/// Starts a service and its dependencies, which are left running if it fails to start.
+pub fn start(name: &str) -> Result<(), ServiceError> {
+    start_nested(name, 0)
+}
+
+fn start_nested(name: &str, depth: usize) -> Result<(), ServiceError> {
+    if depth > MAX_DEPENDENCY_DEPTH {
+        return Err(ServiceError::Cycle);
+    }
+    let service = with_service(name, |s| s.service)?;
+    for &dependency in service.dependencies {
+        match start_nested(dependency, depth + 1) {
+            Ok(()) => {}
+            Err(ServiceError::Cycle) => return Err(ServiceError::Cycle),
+            Err(_) => return Err(ServiceError::Dependency(dependency)),
+        }
+    }
+    if let Entry::Elf(_) = service.entry {
+        return Err(ServiceError::Unsupported);
+    }
+    with_service(name, |s| {
+        if s.status != Status::Running {
+            s.status = Status::Running;
+            s.restarts = 0;
+        }
+    })
+}
+
+/// Stops a service, it is not restarted. Services depending on it keep running.
+pub fn stop(name: &str) -> Result<(), ServiceError> {
+    with_service(name, |s| s.status = Status::Stopped)
+}
+
+/// The status of a service.
+pub fn status(name: &str) -> Result<Status, ServiceError> {
+    with_service(name, |s| s.status)
+}
+
+/// Calls `f` with every service, and its status.
+pub fn for_each(mut f: impl FnMut(&Service, Status)) {
+    let services = without_interrupts(|| SERVICES.lock().clone());
+    for state in services.iter() {
+        f(&state.service, state.status);
+    }
+}
+
+/// Polls every running service once, restarting the ones which crashed.
+pub fn poll_all() {
+    let count = without_interrupts(|| SERVICES.lock().len());
+    for i in 0..count {
+        let Some((name, Entry::Fn(poll))) = without_interrupts(|| {
+            SERVICES.lock().get(i).filter(|s| s.status == Status::Running).map(|s| (s.service.name, s.service.entry))
+        }) else {
+            continue;
+        };
+        let crash = match catch::catch(poll) {
+            Ok(Ok(Poll::Pending)) => continue,
+            Ok(Ok(Poll::Done)) => None,
+            Ok(Err(e)) => Some(e),
+            Err(_) => Some("panicked"),
+        };
+        let _ = with_service(name, |s| {
+            // it may have been stopped while it was polled.
+            if s.status != Status::Running {
+                return;
+            }
+            let restart = match s.service.restart {
+                Restart::Always => true,
+                Restart::OnFailure => crash.is_some(),
+                Restart::Never => false,
+            };
+            if restart && s.restarts < MAX_RESTARTS {
+                s.restarts += 1;
+            } else if let Some(e) = crash {
+                s.status = Status::Failed;
+                oops!(s.service.name, "the service crashed: {e}");
+            } else {
+                s.status = Status::Exited;
+            }
+        });
+        if let Some(e) = crash {
+            warn!("The service `{name}` crashed: {e}");
+        }
+    }
+}
+
+/// Supervises the services forever, halting until the next interrupt between rounds.
+pub fn supervise() -> ! {
+    info!("Supervising services.");
+    loop {
+        poll_all();
+        x86_64::instructions::hlt();
+    }
+}
+
+/// The `service` shell command: `service start|stop|status NAME`, or `service list`
+pub fn command(args: &[&str]) -> i32 {
+    use crate::text::println;
+
+    let result = match args {
+        [_, "start", name] => start(name),
+        [_, "stop", name] => stop(name),
+        [_, "status", name] => status(name).map(|status| println!("{name}: {status}")),
+        [_, "list"] => {
+            for_each(|service, status| println!("{}: {status}", service.name));
+            Ok(())
+        }
+        _ => {
+            println!("usage: service start|stop|status NAME, or service list");
+            return 2;
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("service: {e}");
+            1
+        }
+    }
+}
+
+/// Tests dependencies, and restarting crashed services.
+#[cfg(feature = "test")]
+pub fn test_services(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::test::{test_assert, test_assert_eq};
+
+    static POLLS: AtomicUsize = AtomicUsize::new(0);
+    fn crashing() -> Result<Poll, &'static str> {
+        POLLS.fetch_add(1, Ordering::Relaxed);
+        Err("always fails")
+    }
+
+    let _ = register(Service::new("test-base", || Ok(Poll::Pending)));
+    let _ = register(Service::new("test-crashing", crashing).restart(Restart::OnFailure).dependencies(&["test-base"]));
+    let _ = register(Service::new("test-loop", || Ok(Poll::Done)).dependencies(&["test-loop"]));
+    test_assert_eq!(register(Service::new("test-base", || Ok(Poll::Done))), Err(ServiceError::Duplicate("test-base")))?;
+
+    test_assert_eq!(start("test-crashing"), Ok(()))?;
+    test_assert_eq!(status("test-base"), Ok(Status::Running))?;
+    for _ in 0..=MAX_RESTARTS {
+        poll_all();
+    }
+    test_assert_eq!(POLLS.load(Ordering::Relaxed), MAX_RESTARTS + 1)?;
+    test_assert_eq!(status("test-crashing"), Ok(Status::Failed))?;
+    test_assert!(crate::panic::oops::mark_recovered("test-crashing"))?;
+
+    test_assert_eq!(start("test-loop"), Err(ServiceError::Cycle))?;
+    test_assert_eq!(stop("test-base"), Ok(()))?;
+    test_assert_eq!(status("missing"), Err(ServiceError::Unknown))
+}
@@ -0,0 +1,207 @@
+//! Tab completion.
+//! 
+//! The first word of a line is completed from the [registered](register) command names, later
+//! words by the completer of the command, if it has one. [`complete`] returns the candidates, and
+//! how far the word can be completed. Tab on the [keyboard](crate::interrupts::keyboard) inserts it
+//! into the input line.
+//! 
+//! There is no filesystem yet, so paths are not completed.
+use alloc::{string::String, vec::Vec};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::collections::{ArrayVec, CapacityError};
+
+/// Maximum amount of registered commands.
+pub const MAX_COMMANDS: usize = 64;
+
+/// Completes an argument: gets the words before it, and the partial word, and adds candidates.
+pub type CompleterFn = fn(args: &[&str], word: &str, out: &mut Completions);
+
+/// Candidates for the word being completed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Completions {
+    word: String,
+    candidates: Vec<String>,
+}
+
+impl Completions {
+    /// No candidates yet, for `word`
+    pub fn new(word: &str) -> Self {
+        Self { word: word.into(), candidates: Vec::new() }
+    }
+
+    /// Adds `candidate`, if it starts with the word, and was not added before.
+    pub fn add(&mut self, candidate: &str) {
+        if candidate.starts_with(self.word.as_str()) && !self.candidates.iter().any(|c| c == candidate) {
+            self.candidates.push(candidate.into());
+        }
+    }
+
+    /// The matching candidates, sorted.
+    pub fn candidates(&self) -> &[String] {
+        &self.candidates
+    }
+
+    /// The longest text every candidate starts with, which is at least the word.
+    pub fn common_prefix(&self) -> &str {
+        let Some((first, rest)) = self.candidates.split_first() else { return &self.word };
+        let len = rest.iter().fold(first.len(), |len, c| {
+            first.bytes().zip(c.bytes()).take(len).take_while(|(a, b)| a == b).count()
+        });
+        // candidates are UTF-8, back off to a char boundary.
+        let len = (0..=len).rev().find(|&i| first.is_char_boundary(i)).unwrap_or(0);
+        &first[..len]
+    }
+}
+
+/// The completion of a line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// Where the completed word starts in the line.
+    pub start: usize,
+    /// The candidates.
+    pub completions: Completions,
+}
+
+impl Completion {
+    /// The text to insert after the line, which is empty if nothing can be completed. A single
+    /// candidate is completed with a space after it.
+    pub fn insertion(&self, line: &str) -> String {
+        let mut insertion = String::from(&self.completions.common_prefix()[line.len() - self.start..]);
+        if self.completions.candidates().len() == 1 {
+            insertion.push(' ');
+        }
+        insertion
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Command {
+    name: &'static str,
+    completer: Option<CompleterFn>,
+}
+
+static COMMANDS: Mutex<ArrayVec<Command, MAX_COMMANDS>> = Mutex::new(ArrayVec::new());
+
+/// Registers a command name, and the completer of its arguments.
+pub fn register(name: &'static str, completer: Option<CompleterFn>) -> Result<(), CapacityError> {
+    without_interrupts(|| {
+        let mut commands = COMMANDS.lock();
+        if let Some(command) = commands.iter_mut().find(|c| c.name == name) {
+            command.completer = completer;
+            return Ok(());
+        }
+        commands.push(Command { name, completer }).map_err(|_| CapacityError(()))
+    })
+}
+
+/// Registers a command name, keeping its completer if it has one.
+pub fn register_name(name: &'static str) -> Result<(), CapacityError> {
+    without_interrupts(|| {
+        let mut commands = COMMANDS.lock();
+        if commands.iter().any(|c| c.name == name) {
+            return Ok(());
+        }
+        commands.push(Command { name, completer: None }).map_err(|_| CapacityError(()))
+    })
+}
+
+/// Completes the last word of `line`, which is the text before the cursor.
+pub fn complete(line: &str) -> Completion {
+    let start = line.rfind(char::is_whitespace).map_or(0, |i| i + line[i..].chars().next().map_or(1, char::len_utf8));
+    let word = &line[start..];
+    let args: Vec<&str> = line[..start].split_whitespace().collect();
+    let mut completions = Completions::new(word);
+
+    let commands = without_interrupts(|| COMMANDS.lock().clone());
+    match args.first() {
+        None => commands.iter().for_each(|c| completions.add(c.name)),
+        Some(name) => {
+            if let Some(completer) = commands.iter().find(|c| c.name == *name).and_then(|c| c.completer) {
+                completer(&args, word, &mut completions);
+            }
+        }
+    }
+    completions.candidates.sort_unstable();
+    Completion { start, completions }
+}
+
+/// Completes input device names, like `event0`
+pub fn input_devices(_: &[&str], _: &str, out: &mut Completions) {
+    crate::input::for_each_device(|device| {
+        let mut name = crate::collections::ArrayString::<16>::new();
+        let _ = core::fmt::write(&mut name, format_args!("{}", device.id));
+        out.add(&name);
+    });
+}
+
+/// Completes the `service` command: its subcommands, then service names.
+pub fn services(args: &[&str], _: &str, out: &mut Completions) {
+    match args {
+        [_] => ["start", "stop", "status", "list"].into_iter().for_each(|c| out.add(c)),
+        [_, "start" | "stop" | "status"] => crate::services::for_each(|service, _| out.add(service.name)),
+        _ => {}
+    }
+}
+
+/// Completes the modes of the `stress` command, and the devices of `stress disk`
+pub fn stress(args: &[&str], _: &str, out: &mut Completions) {
+    match args {
+        [_] => ["alloc", "int", "disk", "net"].into_iter().for_each(|c| out.add(c)),
+        [_, "disk"] => crate::storage::for_each(|name, _| out.add(name)),
+        _ => {}
+    }
+}
+
+/// Completes the `irqstat` command.
+pub fn irqstat(args: &[&str], _: &str, out: &mut Completions) {
+    if let [_] = args {
+        out.add("reset");
+    }
+}
+
+/// Completes the keys of the `get` and `setconf` commands.
+pub fn config_keys(args: &[&str], _: &str, out: &mut Completions) {
+    if let [_] = args {
+        crate::config::for_each(|key, _| out.add(key));
+        ["loglevel", "loglevel.console", "loglevel.serial", "keyboard.layout", "hostname"].into_iter().for_each(|c| out.add(c));
+    }
+}
+
+/// Registers the commands which exist so far.
+pub fn register_defaults() {
+    let completers: [(_, CompleterFn); 5] = [
+        ("service", services),
+        ("stress", stress),
+        ("irqstat", irqstat),
+        ("get", config_keys),
+        ("setconf", config_keys),
+    ];
+    for (name, completer) in completers {
+        if register(name, Some(completer)).is_err() {
+            crate::log::warn!("Could not register the completion of `{name}`");
+        }
+    }
+}
+
+/// Tests completing commands and arguments.
+#[cfg(feature = "test")]
+pub fn test_completion(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::test_assert_eq;
+
+    let _ = register("test-complete", Some(|_, _, out| ["alpha", "alps", "beta"].into_iter().for_each(|c| out.add(c))));
+    let _ = register("test-completer", None);
+
+    let completion = complete("test-comp");
+    test_assert_eq!(completion.start, 0)?;
+    test_assert_eq!(completion.completions.candidates(), ["test-complete", "test-completer"])?;
+    test_assert_eq!(completion.insertion("test-comp").as_str(), "lete")?;
+
+    let completion = complete("test-complete  al");
+    test_assert_eq!(completion.start, 15)?;
+    test_assert_eq!(completion.completions.common_prefix(), "alp")?;
+    test_assert_eq!(complete("test-complete b").insertion("test-complete b").as_str(), "eta ")?;
+    test_assert_eq!(complete("test-completer x").completions.candidates().len(), 0)
+}
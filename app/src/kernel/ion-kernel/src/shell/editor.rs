@@ -0,0 +1,261 @@
+//! A tiny modal line editor, like `ed`.
+//! 
+//! In command mode, each input line is a command on the current line:
+//! 
+//! | Command | Action                                              |
+//! |---------|-----------------------------------------------------|
+//! | `N`     | go to line N, and print it                          |
+//! | `p`     | print the current line, `,p` prints every line      |
+//! | `a`     | append lines after the current one                  |
+//! | `i`     | insert lines before the current one                 |
+//! | `c`     | change the current line                             |
+//! | `d`     | delete the current line                             |
+//! | `w`     | write, see [`Event::Write`]                         |
+//! | `q`     | quit, if there are no unsaved changes, `Q` quits anyway |
+//! 
+//! `a`, `i` and `c` switch to insert mode, where lines are added until a line with a single `.`
+//! 
+//! The shell edits files with `edit FILE`, reading lines from the keyboard.
+use alloc::{string::String, vec::Vec};
+use core::fmt::{self, Display, Write};
+
+use crate::{fs, interrupts::keyboard::read_line, io, text::{print, println}};
+
+/// The mode of the editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Lines are commands.
+    Command,
+    /// Lines are added to the text.
+    Insert,
+}
+
+/// What the caller has to do after an input line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// Nothing, keep reading lines.
+    Continue,
+    /// Save the [text](Editor::text), then call [`Editor::saved`]
+    Write,
+    /// Stop editing.
+    Quit,
+}
+
+/// An invalid command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorError {
+    /// The line does not exist.
+    InvalidAddress,
+    /// The command is not known.
+    UnknownCommand,
+    /// `q` with unsaved changes.
+    Unsaved,
+}
+
+impl Display for EditorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidAddress => write!(f, "invalid address"),
+            Self::UnknownCommand => write!(f, "unknown command"),
+            Self::Unsaved => write!(f, "unsaved changes, `w` to write or `Q` to quit anyway"),
+        }
+    }
+}
+
+impl core::error::Error for EditorError {}
+
+/// The state of an editing session.
+#[derive(Debug, Clone)]
+pub struct Editor {
+    lines: Vec<String>,
+    /// The current line, starting at 1, or 0 if there are no lines.
+    current: usize,
+    mode: Mode,
+    modified: bool,
+}
+
+impl Editor {
+    /// Edits `text`, the current line is the last one.
+    pub fn open(text: &str) -> Self {
+        let lines: Vec<String> = text.lines().map(String::from).collect();
+        Self { current: lines.len(), lines, mode: Mode::Command, modified: false }
+    }
+
+    /// The mode of the editor.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Whether the text changed since it was opened or saved.
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// The edited text, every line ends with a newline.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for line in &self.lines {
+            text.push_str(line);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Marks the text as saved, after [`Event::Write`]
+    pub fn saved(&mut self) {
+        self.modified = false;
+    }
+
+    /// Handles an input line, writing printed lines to `out`
+    pub fn input(&mut self, line: &str, out: &mut impl Write) -> Result<Event, EditorError> {
+        if self.mode == Mode::Insert {
+            if line == "." {
+                self.mode = Mode::Command;
+            } else {
+                self.lines.insert(self.current, line.into());
+                self.current += 1;
+                self.modified = true;
+            }
+            return Ok(Event::Continue);
+        }
+
+        match line.trim() {
+            "" => {}
+            "p" => {
+                let line = self.lines.get(self.current.wrapping_sub(1)).ok_or(EditorError::InvalidAddress)?;
+                let _ = writeln!(out, "{line}");
+            }
+            ",p" => {
+                for line in &self.lines {
+                    let _ = writeln!(out, "{line}");
+                }
+            }
+            "a" => self.mode = Mode::Insert,
+            "i" => {
+                self.current = self.current.saturating_sub(1);
+                self.mode = Mode::Insert;
+            }
+            "c" => {
+                self.delete()?;
+                self.mode = Mode::Insert;
+            }
+            "d" => {
+                self.delete()?;
+                // like ed, the line after the deleted one becomes current.
+                self.current = (self.current + 1).min(self.lines.len());
+            }
+            "w" => return Ok(Event::Write),
+            "q" if self.modified => return Err(EditorError::Unsaved),
+            "q" | "Q" => return Ok(Event::Quit),
+            address => {
+                let n: usize = address.parse().map_err(|_| EditorError::UnknownCommand)?;
+                let line = self.lines.get(n.wrapping_sub(1)).ok_or(EditorError::InvalidAddress)?;
+                let _ = writeln!(out, "{line}");
+                self.current = n;
+            }
+        }
+        Ok(Event::Continue)
+    }
+
+    /// Deletes the current line, the line before it becomes current.
+    fn delete(&mut self) -> Result<(), EditorError> {
+        if self.current == 0 {
+            return Err(EditorError::InvalidAddress);
+        }
+        self.lines.remove(self.current - 1);
+        self.current -= 1;
+        self.modified = true;
+        Ok(())
+    }
+}
+
+/// `edit FILE`, edits a file, which is created when it is written if it does not exist.
+pub fn command(args: &[&str]) -> i32 {
+    let [_, path] = args else {
+        println!("usage: edit FILE");
+        return 2;
+    };
+    let text = match fs::read(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => {
+            println!("edit: {path}: {e}");
+            return 1;
+        }
+    };
+    let mut editor = Editor::open(&String::from_utf8_lossy(&text));
+    println!("{path}: {} lines, `q` to quit", editor.lines.len());
+    loop {
+        let line = match read_line() {
+            Ok(line) => line,
+            Err(e) => {
+                println!("edit: {e}");
+                return 1;
+            }
+        };
+        let mut out = String::new();
+        let event = editor.input(line.trim_end_matches('\n'), &mut out);
+        print!("{out}");
+        match event {
+            Ok(Event::Continue) => {}
+            Ok(Event::Write) => {
+                let text = editor.text();
+                match fs::write(path, text.as_bytes()) {
+                    Ok(()) => {
+                        editor.saved();
+                        println!("{} bytes written", text.len());
+                    }
+                    Err(e) => println!("? {e}"),
+                }
+            }
+            Ok(Event::Quit) => return 0,
+            // like ed, errors are a `?` and the reason.
+            Err(e) => println!("? {e}"),
+        }
+    }
+}
+
+/// Tests paging and editing.
+#[cfg(feature = "test")]
+pub fn test_pager_and_editor(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::{
+        shell::{pager::{Action, Pager}, script::Commands},
+        test::test_assert_eq,
+    };
+
+    let text = "one\ntwo\nthree\nfour\nfive\n";
+    let mut pager = Pager::new(text, 2);
+    test_assert_eq!(pager.key(' '), Action::Redraw)?;
+    test_assert_eq!(pager.key('G'), Action::Redraw)?;
+    test_assert_eq!(pager.top(), 3)?;
+    test_assert_eq!(pager.key('j'), Action::Ignore)?;
+    test_assert_eq!(pager.key('g'), Action::Redraw)?;
+    test_assert_eq!(pager.search("ee"), Action::Redraw)?;
+    test_assert_eq!(pager.top(), 2)?;
+    let mut page = String::new();
+    let _ = pager.render(&mut page);
+    test_assert_eq!(page.as_str(), "three\nfour\nlines 3-4 of 5 (space: next page, q: quit)")?;
+    test_assert_eq!(pager.key('q'), Action::Quit)?;
+
+    let mut editor = Editor::open("hostname=ion\nloglevel=info\n");
+    let mut out = String::new();
+    for line in ["1", "c", "hostname=ion-test", ".", "$", "a", "console=vga", "."] {
+        // `$` is not a command, which leaves the editor as it was.
+        let _ = editor.input(line, &mut out);
+    }
+    test_assert_eq!(editor.input("q", &mut out), Err(EditorError::Unsaved))?;
+    test_assert_eq!(editor.input("w", &mut out), Ok(Event::Write))?;
+    editor.saved();
+    test_assert_eq!(editor.text().as_str(), "hostname=ion-test\nconsole=vga\nloglevel=info\n")?;
+    test_assert_eq!(out.as_str(), "hostname=ion\n")?;
+    test_assert_eq!(editor.input("q", &mut out), Ok(Event::Quit))?;
+
+    // the output of `COMMAND | less`, which fits on a page, so it is printed as it is.
+    let (status, output) = crate::console::capture(|| {
+        let (status, inner) = crate::console::capture(|| super::kshell::registry().run(&["echo", "paged", "|", "less"]));
+        print!("outer {inner}");
+        status
+    });
+    test_assert_eq!(status, 0)?;
+    test_assert_eq!(output.as_str(), "outer paged\n")
+}
@@ -0,0 +1,171 @@
+//! Command history.
+//! 
+//! The history is kept in memory, the oldest commands are dropped once there are more than its
+//! capacity. It is saved as one command per line, see [`History::save`] and [`History::load`]. The
+//! shell [loads](load_file) the [`HISTORY`] from [`PATH`] when it starts, and [saves](save_file) it
+//! after every command.
+use alloc::{collections::VecDeque, string::String};
+use core::fmt::{self, Write};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{fs, io};
+
+/// Where the history is saved.
+pub const PATH: &str = "/var/history";
+/// The directory of [`PATH`]
+const DIR: &str = "/var";
+/// The capacity of the history, unless it is [configured](History::set_capacity).
+pub const DEFAULT_CAPACITY: usize = 500;
+
+/// Previously run commands, from the oldest to the newest.
+#[derive(Debug, Clone)]
+pub struct History {
+    entries: VecDeque<String>,
+    capacity: usize,
+}
+
+impl History {
+    /// An empty history, with the [`DEFAULT_CAPACITY`]
+    pub const fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// An empty history, which keeps `capacity` commands.
+    pub const fn with_capacity(capacity: usize) -> Self {
+        Self { entries: VecDeque::new(), capacity }
+    }
+
+    /// How many commands are kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Changes how many commands are kept, dropping the oldest ones if there are too many.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    /// The amount of commands.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there are no commands.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds a command. Blank commands, and repeats of the last command, are not added.
+    pub fn push(&mut self, command: &str) {
+        let command = command.trim();
+        if command.is_empty() || self.entries.back().is_some_and(|last| last == command) {
+            return;
+        }
+        self.entries.push_back(command.into());
+        self.trim();
+    }
+
+    /// The command `n` commands ago, 0 is the newest.
+    pub fn get(&self, n: usize) -> Option<&str> {
+        self.entries.iter().rev().nth(n).map(String::as_str)
+    }
+
+    /// The commands, from the oldest to the newest.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.entries.iter().map(String::as_str)
+    }
+
+    /// The newest command containing `needle`
+    pub fn search(&self, needle: &str) -> Option<&str> {
+        self.iter().rev().find(|command| command.contains(needle))
+    }
+
+    /// Writes every command, one per line.
+    pub fn save(&self, out: &mut impl Write) -> fmt::Result {
+        self.iter().try_for_each(|command| writeln!(out, "{command}"))
+    }
+
+    /// Adds the commands saved by [`save`](Self::save) before the current ones, so commands of
+    /// early boot are kept after the saved history.
+    pub fn load(&mut self, saved: &str) {
+        let current = core::mem::take(&mut self.entries);
+        for command in saved.lines() {
+            self.push(command);
+        }
+        for command in current {
+            self.push(&command);
+        }
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The history of the kernel shell.
+pub static HISTORY: Mutex<History> = Mutex::new(History::new());
+
+/// Loads the [`HISTORY`] saved at [`PATH`], if there is one.
+/// # Errors
+/// Returns an error if the file exists, but could not be read or is not UTF-8.
+pub fn load_file() -> io::Result<()> {
+    let saved = match fs::read(PATH) {
+        Ok(saved) => saved,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let saved = core::str::from_utf8(&saved)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "the history is not UTF-8"))?;
+    without_interrupts(|| HISTORY.lock().load(saved));
+    Ok(())
+}
+
+/// Saves the [`HISTORY`] to [`PATH`], creating its directory if needed.
+/// # Errors
+/// Returns an error if the file could not be written.
+pub fn save_file() -> io::Result<()> {
+    let mut saved = String::new();
+    // can not fail, writing to a string only fails if the heap is full, which aborts.
+    let _ = without_interrupts(|| HISTORY.lock().save(&mut saved));
+    match fs::create_dir(DIR) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+        _ => {}
+    }
+    fs::write(PATH, saved.as_bytes())
+}
+
+/// Tests trimming, saving and loading.
+#[cfg(feature = "test")]
+pub fn test_history(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use alloc::vec::Vec;
+
+    use crate::test::test_assert_eq;
+
+    let mut history = History::with_capacity(3);
+    for command in ["ls", "ls", " ", "cd /", "echo hi", "cat /var/history"] {
+        history.push(command);
+    }
+    test_assert_eq!(history.iter().collect::<Vec<_>>(), ["cd /", "echo hi", "cat /var/history"])?;
+    test_assert_eq!(history.get(0), Some("cat /var/history"))?;
+    test_assert_eq!(history.search("ec"), Some("echo hi"))?;
+
+    let mut saved = String::new();
+    test_assert_eq!(history.save(&mut saved), Ok(()))?;
+    let mut booted = History::new();
+    booted.push("early");
+    booted.load(&saved);
+    test_assert_eq!(booted.iter().collect::<Vec<_>>(), ["cd /", "echo hi", "cat /var/history", "early"])?;
+    booted.set_capacity(1);
+    test_assert_eq!(booted.iter().collect::<Vec<_>>(), ["early"])
+}
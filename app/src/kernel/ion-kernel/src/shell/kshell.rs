@@ -0,0 +1,288 @@
+//! The interactive kernel shell.
+//! 
+//! Reads the lines typed on the [keyboard](crate::interrupts::keyboard::read_line), and runs each
+//! one as a [script](super::script), so `set`, `$VAR` and `$?` work as in scripts. Commands are
+//! looked up in the [`Registry`], which has the [defaults](register_defaults):
+//! 
+//! ```text
+//! ion# echo hello
+//! hello
+//! ion# loglevel serial trace
+//! ion# uptime
+//! up 0:01:12.345
+//! ```
+//! 
+//! Long output is paged with `COMMAND | less`. `if` needs several lines, so it only works in scripts, which are run from files by `run FILE`.
+//! The shell runs [`INIT_SCRIPT`] before it reads the first line, if it exists, and keeps its
+//! variables.
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{complete, history::{self, HISTORY}, script::{Commands, Script}};
+use crate::{collections::{ArrayVec, CapacityError}, fs, io, log::{info, warn}, text::{print, println}};
+
+/// Maximum amount of registered commands.
+pub const MAX_COMMANDS: usize = 64;
+
+/// The exit code of a command which does not exist.
+pub const NOT_FOUND: i32 = 127;
+
+/// The script run when the shell starts, E.g. to mount filesystems or start services.
+pub const INIT_SCRIPT: &str = "/init.rc";
+
+/// Runs a command, `args[0]` is its name. Returns its exit code, 0 on success.
+pub type CommandFn = fn(args: &[&str]) -> i32;
+
+/// A shell command.
+#[derive(Debug, Clone, Copy)]
+pub struct Command {
+    /// The name it is run by.
+    pub name: &'static str,
+    /// A short description, shown by `help`
+    pub help: &'static str,
+    /// Runs it.
+    pub run: CommandFn,
+}
+
+/// The commands of the shell, which [runs](Commands::run) them by name.
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    commands: ArrayVec<Command, MAX_COMMANDS>,
+}
+
+impl Registry {
+    /// A registry without commands.
+    pub const fn new() -> Self {
+        Self { commands: ArrayVec::new() }
+    }
+
+    /// Adds `command`, replacing a command with the same name.
+    /// # Errors
+    /// Returns an error if there are [`MAX_COMMANDS`] already.
+    pub fn register(&mut self, command: Command) -> Result<(), CapacityError> {
+        if let Some(existing) = self.commands.iter_mut().find(|c| c.name == command.name) {
+            *existing = command;
+            return Ok(());
+        }
+        self.commands.push(command).map_err(|_| CapacityError(()))
+    }
+
+    /// The command called `name`
+    pub fn get(&self, name: &str) -> Option<&Command> {
+        self.commands.iter().find(|c| c.name == name)
+    }
+
+    /// Every command, in the order they were registered.
+    pub fn iter(&self) -> impl Iterator<Item = &Command> {
+        self.commands.iter()
+    }
+}
+
+impl Commands for Registry {
+    /// Runs the command called `args[0]`, or returns [`NOT_FOUND`]. If the last words are `| less`,
+    /// the output of the command is [paged](super::pager::page).
+    fn run(&mut self, args: &[&str]) -> i32 {
+        if let [command @ .., "|", "less"] = args {
+            let (status, output) = crate::console::capture(|| self.run(command));
+            super::pager::page(&output);
+            return status;
+        }
+        let Some(&name) = args.first() else { return 0 };
+        match self.get(name) {
+            Some(command) => (command.run)(args),
+            None => {
+                println!("{name}: command not found");
+                NOT_FOUND
+            }
+        }
+    }
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry::new());
+
+/// Registers `command` with the shell, and its name for [completion](complete)
+/// # Errors
+/// Returns an error if there are [`MAX_COMMANDS`] already.
+pub fn register(command: Command) -> Result<(), CapacityError> {
+    without_interrupts(|| REGISTRY.lock().register(command))?;
+    complete::register_name(command.name)
+}
+
+/// A copy of the registered commands.
+pub fn registry() -> Registry {
+    without_interrupts(|| REGISTRY.lock().clone())
+}
+
+/// `help`, lists the commands.
+fn help_command(_: &[&str]) -> i32 {
+    let registry = registry();
+    let mut commands: ArrayVec<&Command, MAX_COMMANDS> = ArrayVec::new();
+    // the registry has at most as many commands.
+    registry.iter().for_each(|command| { let _ = commands.push(command); });
+    commands.sort_unstable_by_key(|command| command.name);
+    for command in commands.iter() {
+        println!("{:<10} {}", command.name, command.help);
+    }
+    0
+}
+
+/// `echo WORDS...`, prints the words.
+fn echo_command(args: &[&str]) -> i32 {
+    let mut words = args.iter().skip(1);
+    if let Some(first) = words.next() {
+        print!("{first}");
+        words.for_each(|word| print!(" {word}"));
+    }
+    println!();
+    0
+}
+
+/// `run FILE`, runs a script file, and returns its exit code.
+fn run_command(args: &[&str]) -> i32 {
+    let [_, path] = args else {
+        println!("usage: run FILE");
+        return 2;
+    };
+    match run_file(&mut Script::new(), path) {
+        Ok(status) => status,
+        Err(e) => {
+            println!("run: {path}: {e}");
+            1
+        }
+    }
+}
+
+/// Registers the commands which exist so far.
+pub fn register_defaults() {
+    let commands = [
+        Command { name: "help", help: "lists the commands", run: help_command },
+        Command { name: "echo", help: "prints its arguments", run: echo_command },
+        Command { name: "run", help: "runs a script file", run: run_command },
+        Command { name: "less", help: "pages a file", run: super::pager::command },
+        Command { name: "edit", help: "edits a file", run: super::editor::command },
+        Command { name: "clear", help: "clears the console", run: crate::console::clear_command },
+        Command { name: "mem", help: "shows the heap usage, or the memory map", run: crate::mem::layout::mem_command },
+        Command { name: "mappings", help: "lists the page table mappings", run: crate::mem::debug::mappings_command },
+        Command { name: "md", help: "dumps memory", run: crate::mem::inspect::md_command },
+        Command { name: "mw", help: "writes a value to memory", run: crate::mem::inspect::mw_command },
+        Command { name: "uptime", help: "shows the time since boot", run: crate::time::uptime_command },
+        Command { name: "loglevel", help: "shows or sets the log levels", run: crate::log::loglevel_command },
+        Command { name: "reboot", help: "reboots the machine", run: crate::power::reboot_command },
+        Command { name: "uname", help: "shows the kernel version", run: crate::sys::uname_command },
+        Command { name: "get", help: "shows configuration keys", run: crate::config::get_command },
+        // `set` sets a shell variable.
+        Command { name: "setconf", help: "sets a configuration key", run: crate::config::set_command },
+        Command { name: "video", help: "lists or selects video modes", run: crate::video::mode::video_command },
+        Command { name: "service", help: "starts, stops and lists services", run: crate::services::command },
+        Command { name: "irqstat", help: "shows interrupt statistics", run: crate::interrupts::stats::command },
+        Command { name: "stress", help: "puts load on subsystems", run: super::stress::command },
+        Command { name: "kexec", help: "soft reboots into a kernel file", run: crate::power::kexec::command },
+    ];
+    for command in commands {
+        if register(command).is_err() {
+            warn!("Could not register the `{}` command", command.name);
+        }
+    }
+}
+
+/// Runs `line` with the registered commands, keeping the variables in `script`, and returns its
+/// exit code. Errors are printed.
+pub fn run_line(script: &mut Script, line: &str) -> i32 {
+    // copied, so a command may register commands.
+    let mut registry = registry();
+    match script.run(line, &mut registry) {
+        Ok(status) => status,
+        Err(e) => {
+            println!("kshell: {e}");
+            2
+        }
+    }
+}
+
+/// Runs the script file at `path` with the registered commands, keeping the variables in `script`,
+/// and returns its exit code. Errors in the script are printed, like by [`run_line`]
+/// # Errors
+/// Returns an error if the file could not be read, or is not UTF-8.
+pub fn run_file(script: &mut Script, path: &str) -> io::Result<i32> {
+    let source = fs::read(path)?;
+    let source = core::str::from_utf8(&source)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "the script is not UTF-8"))?;
+    Ok(run_line(script, source))
+}
+
+/// Loads the [history], runs [`INIT_SCRIPT`] if it exists, then reads and runs lines, forever.
+/// Every line is added to the history, which is saved after it ran.
+pub fn run() -> ! {
+    if let Err(e) = history::load_file() {
+        warn!("The history could not be loaded: {e}");
+    }
+    // stops saving after the first error, instead of warning about every line.
+    let mut save_history = true;
+    let mut script = Script::new();
+    if fs::metadata(INIT_SCRIPT).is_ok() {
+        match run_file(&mut script, INIT_SCRIPT) {
+            Ok(status) => info!("{INIT_SCRIPT} exited with {status}"),
+            Err(e) => warn!("{INIT_SCRIPT} could not be run: {e}"),
+        }
+    }
+    loop {
+        print!("{}# ", crate::sys::hostname());
+        let line = match crate::interrupts::keyboard::read_line() {
+            Ok(line) => line,
+            Err(e) => {
+                println!("kshell: {e}");
+                continue;
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        without_interrupts(|| HISTORY.lock().push(line));
+        run_line(&mut script, line);
+        if save_history {
+            if let Err(e) = history::save_file() {
+                warn!("The history could not be saved, it is kept in memory: {e}");
+                save_history = false;
+            }
+        }
+    }
+}
+
+/// Starts the shell, on a task of its own.
+pub fn spawn() {
+    if let Err(e) = crate::task::spawn("kshell", || run()) {
+        warn!("The shell could not be started: {e}");
+    }
+}
+
+/// Tests running lines with a registry.
+#[cfg(feature = "test")]
+pub fn test_kshell(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    fn double(args: &[&str]) -> i32 {
+        args.get(1).and_then(|n| n.parse::<i32>().ok()).map_or(2, |n| n * 2)
+    }
+
+    let mut commands = Registry::new();
+    commands.register(Command { name: "double", help: "", run: double }).map_err(|_| "not registered")?;
+    commands.register(Command { name: "double", help: "replaced", run: double }).map_err(|_| "not registered")?;
+    test_assert_eq!(commands.iter().count(), 1)?;
+    test_assert_eq!(commands.get("double").map(|c| c.help), Some("replaced"))?;
+
+    let mut script = Script::new();
+    test_assert_eq!(script.run("double 21", &mut commands), Ok(42))?;
+    test_assert_eq!(script.run("set N 4\ndouble $N", &mut commands), Ok(8))?;
+    test_assert_eq!(script.run("no-such-command", &mut commands), Ok(NOT_FOUND))?;
+    test_assert!(registry().get("help").is_some())?;
+
+    fs::write("/test-kshell.rc", b"# sets a variable
+set ANSWER 42
+exit 3").map_err(|_| "the script was not written")?;
+    let status = run_file(&mut script, "/test-kshell.rc");
+    fs::remove("/test-kshell.rc").map_err(|_| "the script was not removed")?;
+    test_assert_eq!(status.ok(), Some(3))?;
+    test_assert_eq!(script.var("ANSWER"), Some("42"))?;
+    test_assert_eq!(run_command(&["run", "/test-kshell.rc"]), 1)
+}
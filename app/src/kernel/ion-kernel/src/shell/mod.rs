@@ -0,0 +1,18 @@
+//! The kernel shell.
+//! 
+//! [`kshell`] reads lines from the keyboard, and runs them as [scripts](script) with the
+//! registered commands. Script files are run with `run FILE`, and `/init.rc` when the shell
+//! starts.
+//! 
+//! The [history] is saved between boots, and Tab [completes](complete) the typed line. Long output
+//! is shown with the [pager] by `less`, and files are edited with the [editor] by `edit`
+//! 
+//! [`stress`] puts load on subsystems, to reproduce performance problems.
+
+pub mod complete;
+pub mod editor;
+pub mod history;
+pub mod kshell;
+pub mod pager;
+pub mod script;
+pub mod stress;
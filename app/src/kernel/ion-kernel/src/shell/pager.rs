@@ -0,0 +1,166 @@
+//! A `less`-style pager, for output longer than the screen.
+//! 
+//! The pager only keeps track of the position, and renders a page; the caller feeds it keys:
+//! 
+//! | Key           | Action                    |
+//! |---------------|---------------------------|
+//! | space, `f`    | next page                 |
+//! | `b`           | previous page             |
+//! | enter, `j`    | next line                 |
+//! | `k`           | previous line             |
+//! | `g`, `G`      | first, last page          |
+//! | `n`           | next line containing the [search](Pager::search) |
+//! | `q`           | quit                      |
+//! 
+//! [`page`] shows text with the pager on the console. Keys are read as typed lines, an empty line
+//! is enter. The shell pages files with `less FILE`, and the output of a command with
+//! `COMMAND | less`
+use alloc::string::String;
+use core::fmt::{self, Write};
+
+use crate::{fs, interrupts::keyboard::read_line, text::{BUFFER_HEIGHT, print, println}};
+
+/// The lines shown at once by [`page`], the main region of the screen, less the status line.
+pub const PAGE_HEIGHT: usize = BUFFER_HEIGHT - 3;
+
+/// What to do after a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Render the page again.
+    Redraw,
+    /// The key does nothing.
+    Ignore,
+    /// Stop paging.
+    Quit,
+}
+
+/// Pages through `text`
+#[derive(Debug, Clone)]
+pub struct Pager<'a> {
+    text: &'a str,
+    /// The first shown line.
+    top: usize,
+    lines: usize,
+    height: usize,
+    search: Option<&'a str>,
+}
+
+impl<'a> Pager<'a> {
+    /// A pager showing `height` lines of `text` at once, and a status line.
+    pub fn new(text: &'a str, height: usize) -> Self {
+        Self { text, top: 0, lines: text.lines().count(), height: height.max(1), search: None }
+    }
+
+    /// Whether `text` fits on a single page, so it does not need a pager.
+    pub fn fits(text: &str, height: usize) -> bool {
+        text.lines().count() <= height
+    }
+
+    /// The first shown line, starting at 0.
+    pub fn top(&self) -> usize {
+        self.top
+    }
+
+    fn last_top(&self) -> usize {
+        self.lines.saturating_sub(self.height)
+    }
+
+    fn scroll_to(&mut self, top: usize) -> Action {
+        let top = top.min(self.last_top());
+        if top == self.top {
+            return Action::Ignore;
+        }
+        self.top = top;
+        Action::Redraw
+    }
+
+    /// Searches for `needle` with `n`, starting after the first shown line.
+    pub fn search(&mut self, needle: &'a str) -> Action {
+        self.search = Some(needle);
+        self.next_match()
+    }
+
+    fn next_match(&mut self) -> Action {
+        let Some(needle) = self.search else { return Action::Ignore };
+        match self.text.lines().enumerate().skip(self.top + 1).find(|(_, line)| line.contains(needle)) {
+            Some((i, _)) => self.scroll_to(i),
+            None => Action::Ignore,
+        }
+    }
+
+    /// Handles a key.
+    pub fn key(&mut self, key: char) -> Action {
+        match key {
+            ' ' | 'f' => self.scroll_to(self.top + self.height),
+            'b' => self.scroll_to(self.top.saturating_sub(self.height)),
+            '\n' | 'j' => self.scroll_to(self.top + 1),
+            'k' => self.scroll_to(self.top.saturating_sub(1)),
+            'g' => self.scroll_to(0),
+            'G' => self.scroll_to(self.last_top()),
+            'n' => self.next_match(),
+            'q' => Action::Quit,
+            _ => Action::Ignore,
+        }
+    }
+
+    /// Writes the shown lines, and a status line with the position.
+    pub fn render(&self, out: &mut impl Write) -> fmt::Result {
+        for line in self.text.lines().skip(self.top).take(self.height) {
+            writeln!(out, "{line}")?;
+        }
+        let end = (self.top + self.height).min(self.lines);
+        if end == self.lines {
+            write!(out, "lines {}-{end} (END) q to quit", self.top + 1)
+        } else {
+            write!(out, "lines {}-{end} of {} (space: next page, q: quit)", self.top + 1, self.lines)
+        }
+    }
+}
+
+/// Shows `text` on the console, with the pager if it does not fit on a page.
+pub fn page(text: &str) {
+    if Pager::fits(text, PAGE_HEIGHT) {
+        print!("{text}");
+        return;
+    }
+    let console = crate::console::active();
+    let mut pager = Pager::new(text, PAGE_HEIGHT);
+    let mut action = Action::Redraw;
+    loop {
+        if action == Action::Redraw {
+            let mut page = String::new();
+            // can not fail, writing to a string only fails if the heap is full, which aborts.
+            let _ = pager.render(&mut page);
+            console.clear();
+            println!("{page}");
+            action = Action::Ignore;
+        }
+        let Ok(line) = read_line() else { return };
+        let line = line.trim_end_matches('\n');
+        let keys = if line.is_empty() { "\n" } else { line };
+        for key in keys.chars() {
+            match pager.key(key) {
+                Action::Quit => return,
+                Action::Redraw => action = Action::Redraw,
+                Action::Ignore => {}
+            }
+        }
+    }
+}
+
+/// `less FILE`, pages a file.
+pub fn command(args: &[&str]) -> i32 {
+    let [_, path] = args else {
+        println!("usage: less FILE, or COMMAND | less");
+        return 2;
+    };
+    let text = match fs::read(path) {
+        Ok(text) => text,
+        Err(e) => {
+            println!("less: {path}: {e}");
+            return 1;
+        }
+    };
+    page(&String::from_utf8_lossy(&text));
+    0
+}
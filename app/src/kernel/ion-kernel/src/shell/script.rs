@@ -0,0 +1,298 @@
+//! Shell scripts, so boot time configuration does not have to live in code.
+//! 
+//! ```text
+//! # comments start with a hash
+//! set LEVEL debug
+//! loglevel $LEVEL
+//! if mount /dev/ram0 /
+//!     echo "mounted, $? is 0"
+//! else
+//!     echo "mount failed with $?"
+//!     exit 1
+//! end
+//! ```
+//! 
+//! - `set NAME VALUE...` sets a variable, which is expanded by `$NAME` or `${NAME}`
+//! - `$?` is the exit code of the last command
+//! - `if COMMAND` runs the following lines if the command exits with 0, up to `else` or `end`
+//! - `exit [CODE]` stops the script
+//! - words are split by spaces, unless they are in double quotes
+//! 
+//! Everything else is passed to the [`Commands`] of the caller.
+use alloc::{string::String, vec::Vec};
+use core::fmt::{self, Display, Write};
+
+use crate::collections::HashMap;
+
+/// How deep `if`s may be nested.
+pub const MAX_DEPTH: usize = 16;
+
+/// Runs the commands of a script.
+pub trait Commands {
+    /// Runs a command, `args[0]` is its name. Returns its exit code, 0 on success.
+    fn run(&mut self, args: &[&str]) -> i32;
+}
+
+impl<F: FnMut(&[&str]) -> i32> Commands for F {
+    fn run(&mut self, args: &[&str]) -> i32 {
+        self(args)
+    }
+}
+
+/// Why a script could not run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A double quote was not closed.
+    UnclosedQuote,
+    /// `${` was not closed.
+    UnclosedBrace,
+    /// `else` or `end` without an `if`
+    UnexpectedKeyword(&'static str),
+    /// An `if` was not ended, at the end of the script.
+    MissingEnd,
+    /// `if`s are nested deeper than [`MAX_DEPTH`]
+    TooDeep,
+    /// `set` without a name, or `if` without a command.
+    MissingArgument(&'static str),
+    /// The code of `exit` is not a number.
+    InvalidExitCode,
+}
+
+/// An error on a line of a script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptError {
+    /// The line, starting at 1.
+    pub line: usize,
+    /// What went wrong.
+    pub kind: ErrorKind,
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match self.kind {
+            ErrorKind::UnclosedQuote => write!(f, "unclosed quote"),
+            ErrorKind::UnclosedBrace => write!(f, "unclosed `${{`"),
+            ErrorKind::UnexpectedKeyword(keyword) => write!(f, "`{keyword}` without `if`"),
+            ErrorKind::MissingEnd => write!(f, "`if` without `end`"),
+            ErrorKind::TooDeep => write!(f, "more than {MAX_DEPTH} nested `if`s"),
+            ErrorKind::MissingArgument(command) => write!(f, "`{command}` needs an argument"),
+            ErrorKind::InvalidExitCode => write!(f, "the exit code is not a number"),
+        }
+    }
+}
+
+impl core::error::Error for ScriptError {}
+
+/// A nested `if`
+#[derive(Debug, Clone, Copy)]
+struct Branch {
+    /// Whether the lines of the current branch run.
+    taken: bool,
+    /// Whether the enclosing lines run.
+    outer: bool,
+}
+
+/// The state of a running script, variables are kept between [`run`](Script::run)s.
+#[derive(Debug, Default)]
+pub struct Script {
+    vars: HashMap<String, String>,
+    status: i32,
+}
+
+impl Script {
+    /// A script without variables.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value of a variable.
+    pub fn var(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(String::as_str)
+    }
+
+    /// Sets a variable.
+    pub fn set_var(&mut self, name: &str, value: &str) {
+        self.vars.insert(name.into(), value.into());
+    }
+
+    /// The exit code of the last command.
+    pub fn status(&self) -> i32 {
+        self.status
+    }
+
+    /// Runs `source`, and returns the exit code of the last command, or the one given to `exit`
+    pub fn run(&mut self, source: &str, commands: &mut impl Commands) -> Result<i32, ScriptError> {
+        let mut branches: Vec<Branch> = Vec::new();
+        for (i, line) in source.lines().enumerate() {
+            let error = |kind| ScriptError { line: i + 1, kind };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let running = branches.last().is_none_or(|b| b.taken && b.outer);
+            let keyword = line.split_whitespace().next().unwrap_or_default();
+            match keyword {
+                "else" => {
+                    let branch = branches.last_mut().ok_or(error(ErrorKind::UnexpectedKeyword("else")))?;
+                    branch.taken = !branch.taken;
+                    continue;
+                }
+                "end" => {
+                    branches.pop().ok_or(error(ErrorKind::UnexpectedKeyword("end")))?;
+                    continue;
+                }
+                "if" if !running => {
+                    // the condition is not run, and neither is either branch.
+                    if branches.len() == MAX_DEPTH {
+                        return Err(error(ErrorKind::TooDeep));
+                    }
+                    branches.push(Branch { taken: false, outer: false });
+                    continue;
+                }
+                _ if !running => continue,
+                _ => {}
+            }
+
+            let words = self.split(line).map_err(error)?;
+            let words: Vec<&str> = words.iter().map(String::as_str).collect();
+            match words[..] {
+                ["if"] => return Err(error(ErrorKind::MissingArgument("if"))),
+                ["if", ref condition @ ..] => {
+                    if branches.len() == MAX_DEPTH {
+                        return Err(error(ErrorKind::TooDeep));
+                    }
+                    self.status = commands.run(condition);
+                    branches.push(Branch { taken: self.status == 0, outer: true });
+                }
+                ["set"] => return Err(error(ErrorKind::MissingArgument("set"))),
+                ["set", name, ref value @ ..] => {
+                    self.set_var(name, &value.join(" "));
+                    self.status = 0;
+                }
+                ["exit"] => return Ok(self.status),
+                ["exit", code, ..] => return code.parse().map_err(|_| error(ErrorKind::InvalidExitCode)),
+                // a line which only expanded to nothing.
+                [] => {}
+                ref args => self.status = commands.run(args),
+            }
+        }
+        if !branches.is_empty() {
+            return Err(ScriptError { line: source.lines().count(), kind: ErrorKind::MissingEnd });
+        }
+        Ok(self.status)
+    }
+
+    /// Splits `line` into words, and expands variables.
+    fn split(&self, line: &str) -> Result<Vec<String>, ErrorKind> {
+        let mut words = Vec::new();
+        let mut word = String::new();
+        // a quoted empty word is still a word.
+        let mut in_word = false;
+        let mut quoted = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    quoted = !quoted;
+                    in_word = true;
+                }
+                c if c.is_whitespace() && !quoted => {
+                    if in_word {
+                        words.push(core::mem::take(&mut word));
+                        in_word = false;
+                    }
+                }
+                '$' => {
+                    in_word = true;
+                    let name: String = match chars.peek() {
+                        Some('?') => {
+                            chars.next();
+                            let _ = write!(word, "{}", self.status);
+                            continue;
+                        }
+                        Some('{') => {
+                            chars.next();
+                            let mut name = String::new();
+                            loop {
+                                match chars.next() {
+                                    Some('}') => break name,
+                                    Some(c) => name.push(c),
+                                    None => return Err(ErrorKind::UnclosedBrace),
+                                }
+                            }
+                        }
+                        _ => {
+                            let mut name = String::new();
+                            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || **c == '_') {
+                                name.push(c);
+                                chars.next();
+                            }
+                            if name.is_empty() {
+                                word.push('$');
+                                continue;
+                            }
+                            name
+                        }
+                    };
+                    word.push_str(self.var(&name).unwrap_or_default());
+                }
+                c => {
+                    word.push(c);
+                    in_word = true;
+                }
+            }
+        }
+        if quoted {
+            return Err(ErrorKind::UnclosedQuote);
+        }
+        if in_word {
+            words.push(word);
+        }
+        Ok(words)
+    }
+}
+
+/// Runs `source` with a fresh [`Script`]
+pub fn run(source: &str, commands: &mut impl Commands) -> Result<i32, ScriptError> {
+    Script::new().run(source, commands)
+}
+
+/// Tests variables, conditionals and errors.
+#[cfg(feature = "test")]
+pub fn test_script(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use alloc::format;
+
+    use crate::test::test_assert_eq;
+
+    let mut log = Vec::new();
+    let mut commands = |args: &[&str]| {
+        log.push(args.join(","));
+        match args[0] {
+            "false" => 1,
+            _ => 0,
+        }
+    };
+    let source = r#"
+        # configure
+        set NAME "ion os"
+        echo $NAME ${NAME}!
+        if false
+            echo skipped
+            if true
+                echo skipped
+            end
+        else
+            echo "status $?" ""
+        end
+        exit 3
+        echo unreachable
+    "#;
+    test_assert_eq!(run(source, &mut commands), Ok(3))?;
+    test_assert_eq!(log, ["echo,ion os,ion os!", "false", "echo,status 1,"])?;
+
+    let error = run("if true\n\"unclosed", &mut |_: &[&str]| 0).err();
+    test_assert_eq!(error, Some(ScriptError { line: 2, kind: ErrorKind::UnclosedQuote }))?;
+    let error = run("end", &mut |_: &[&str]| 0).err();
+    test_assert_eq!(error.map(|e| format!("{e}")).as_deref(), Some("line 1: `end` without `if`"))
+}
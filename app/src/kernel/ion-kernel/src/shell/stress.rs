@@ -0,0 +1,206 @@
+//! The `stress` command, which puts load on a subsystem and reports how it held up.
+//! 
+//! ```text
+//! stress alloc [COUNT] [SIZE]   allocate and free COUNT blocks of SIZE bytes
+//! stress int [COUNT]            wait for COUNT timer interrupts
+//! stress disk [DEVICE] [COUNT]  read COUNT sectors spread over a block device
+//! stress net                    not supported, there is no network driver yet
+//! ```
+//! 
+//! The disk mode only reads, so it is safe on disks with filesystems. Without a `DEVICE`, the first
+//! [registered](crate::storage::register) one is used.
+//! 
+//! Every mode prints a [`Report`], so it can be pasted into bug reports as is. Timing uses the
+//! [active clock source](crate::time::clocksource), which is also printed.
+use alloc::vec::Vec;
+use core::{fmt::{self, Display}, time::Duration};
+
+use crate::{io, storage::{self, BlockDevice, SECTOR_SIZE}, text::println, time::clocksource};
+
+/// Operations done by `stress alloc`, unless given.
+pub const DEFAULT_ALLOCS: usize = 10_000;
+/// The size of blocks allocated by `stress alloc`, unless given.
+pub const DEFAULT_ALLOC_SIZE: usize = 64;
+/// Interrupts waited for by `stress int`, unless given.
+pub const DEFAULT_INTERRUPTS: usize = 100;
+/// Sectors read by `stress disk`, unless given.
+pub const DEFAULT_SECTORS: usize = 1_000;
+
+/// The timing of a stress run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    /// Operations done.
+    pub ops: usize,
+    /// Time of all operations.
+    pub total: Duration,
+    /// The fastest operation.
+    pub min: Duration,
+    /// The slowest operation.
+    pub max: Duration,
+}
+
+impl Report {
+    /// Times `op`, `ops` times.
+    pub fn measure(ops: usize, mut op: impl FnMut()) -> Self {
+        let mut report = Self { ops, total: Duration::ZERO, min: Duration::MAX, max: Duration::ZERO };
+        for _ in 0..ops {
+            let start = clocksource::now();
+            op();
+            let latency = clocksource::now().saturating_sub(start);
+            report.total += latency;
+            report.min = report.min.min(latency);
+            report.max = report.max.max(latency);
+        }
+        if ops == 0 {
+            report.min = Duration::ZERO;
+        }
+        report
+    }
+
+    /// The average time of an operation.
+    pub fn average(&self) -> Duration {
+        self.total / self.ops.max(1) as u32
+    }
+
+    /// Operations per second.
+    pub fn throughput(&self) -> u128 {
+        self.ops as u128 * 1_000_000_000 / self.total.as_nanos().max(1)
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ops in {:?}: {} ops/s, latency min {:?} avg {:?} max {:?}",
+            self.ops, self.total, self.throughput(), self.min, self.average(), self.max)
+    }
+}
+
+/// Allocates and frees `count` blocks of `size` bytes, touching every block.
+pub fn alloc(count: usize, size: usize) -> Report {
+    Report::measure(count, || {
+        let mut block: Vec<u8> = Vec::with_capacity(size);
+        block.resize(size, 0xA5);
+        core::hint::black_box(&block);
+    })
+}
+
+/// Waits for `count` timer interrupts, so the latency is the interval between them.
+pub fn interrupts(count: usize) -> Report {
+    // start right after an interrupt, so the first interval is a whole one.
+    x86_64::instructions::hlt();
+    Report::measure(count, || {
+        let ticks = crate::time::ticks();
+        while crate::time::ticks() == ticks {
+            x86_64::instructions::hlt();
+        }
+    })
+}
+
+/// Reads `count` sectors of `device`, striding over it so the reads are not sequential.
+/// # Errors
+/// Returns the error of the first read which failed, the others are not done.
+pub fn disk(device: &dyn BlockDevice, count: usize) -> io::Result<Report> {
+    let sectors = device.sectors();
+    if sectors == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the device has no sectors"));
+    }
+    // a prime, so every sector is read once before any is read again, unless the size is a multiple.
+    const STRIDE: u64 = 7919;
+    let mut lba = 0;
+    let mut result = Ok(());
+    let mut buf = [0; SECTOR_SIZE];
+    let report = Report::measure(count, || {
+        if result.is_ok() {
+            result = device.read_sector(lba, &mut buf);
+            lba = (lba + STRIDE) % sectors;
+        }
+    });
+    result.map(|()| report)
+}
+
+/// `stress disk [DEVICE] [COUNT]`, returns `None` for invalid arguments.
+fn disk_command(args: &[&str]) -> Option<io::Result<Report>> {
+    let (name, count) = match args {
+        [] => (None, None),
+        [arg] => match arg.parse() {
+            Ok(count) => (None, Some(count)),
+            Err(_) => (Some(*arg), None),
+        },
+        [name, count] => (Some(*name), Some(count.parse().ok()?)),
+        _ => return None,
+    };
+    let mut first = None;
+    storage::for_each(|name, device| { first.get_or_insert((name, device)); });
+    let device = match name {
+        Some(name) => storage::get(name),
+        None => first.map(|(_, device)| device),
+    };
+    Some(match device {
+        Some(device) => disk(device, count.unwrap_or(DEFAULT_SECTORS)),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, "there is no such block device")),
+    })
+}
+
+fn parse_or<T: core::str::FromStr>(arg: Option<&&str>, default: T) -> Option<T> {
+    arg.map_or(Some(default), |arg| arg.parse().ok())
+}
+
+/// The `stress` shell command, see the [module docs](self)
+pub fn command(args: &[&str]) -> i32 {
+    let report = match args.get(1..).unwrap_or_default() {
+        ["alloc", rest @ ..] if rest.len() <= 2 => {
+            parse_or(rest.first(), DEFAULT_ALLOCS).zip(parse_or(rest.get(1), DEFAULT_ALLOC_SIZE))
+                .map(|(count, size)| alloc(count, size))
+        }
+        ["int", rest @ ..] if rest.len() <= 1 => parse_or(rest.first(), DEFAULT_INTERRUPTS).map(interrupts),
+        ["disk", rest @ ..] => match disk_command(rest) {
+            Some(Ok(report)) => Some(report),
+            Some(Err(e)) => {
+                println!("stress disk: {e}");
+                return 1;
+            }
+            None => None,
+        },
+        ["net"] => {
+            println!("stress: there is no network driver yet");
+            return 1;
+        }
+        _ => None,
+    };
+    match report {
+        Some(report) => {
+            println!("stress {}: {report} ({} clock)", args[1], clocksource::active().name());
+            0
+        }
+        None => {
+            println!("usage: stress alloc [COUNT] [SIZE], stress int [COUNT], or stress disk [DEVICE] [COUNT]");
+            2
+        }
+    }
+}
+
+/// Tests the report, and the allocation and disk stress.
+#[cfg(feature = "test")]
+pub fn test_stress(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use alloc::string::ToString;
+
+    use crate::test::{test_assert, test_assert_eq};
+
+    let report = Report { ops: 4, total: Duration::from_micros(2), min: Duration::from_nanos(200), max: Duration::from_nanos(900) };
+    test_assert_eq!(report.average(), Duration::from_nanos(500))?;
+    test_assert_eq!(report.throughput(), 2_000_000)?;
+    test_assert_eq!(report.to_string().as_str(), "4 ops in 2µs: 2000000 ops/s, latency min 200ns avg 500ns max 900ns")?;
+
+    let before = crate::lib_alloc::stats();
+    let report = alloc(100, 128);
+    test_assert_eq!(report.ops, 100)?;
+    test_assert!(report.min <= report.max)?;
+    test_assert_eq!(crate::lib_alloc::stats().live_bytes, before.live_bytes)?;
+    test_assert_eq!(command(&["stress", "alloc", "many"]), 2)?;
+
+    let ramdisk = crate::storage::ramdisk::RamDisk::new(16);
+    test_assert_eq!(disk(&ramdisk, 40).map(|report| report.ops).ok(), Some(40))?;
+    test_assert_eq!(command(&["stress", "disk", "no-such-disk"]), 1)?;
+    test_assert_eq!(command(&["stress", "disk", "hda", "many"]), 2)?;
+    test_assert_eq!(command(&["stress", "net"]), 1)
+}
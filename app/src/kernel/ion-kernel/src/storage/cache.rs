@@ -0,0 +1,221 @@
+//! A write-back cache of sectors, which evicts the least recently used one.
+//! 
+//! Written sectors are kept dirty until they are evicted, or the cache is
+//! [flushed](BlockCache::flush), so a filesystem which updates the same table sector repeatedly
+//! only writes it once.
+use alloc::{boxed::Box, vec::Vec};
+use core::fmt;
+
+use super::{BlockDevice, SECTOR_SIZE};
+use crate::{io, sync::AdaptiveMutex};
+
+/// A cached sector.
+struct Slot {
+    lba: u64,
+    data: Box<[u8; SECTOR_SIZE]>,
+    dirty: bool,
+    /// The [`State::clock`] of the last use.
+    used: u64,
+}
+
+struct State {
+    slots: Vec<Slot>,
+    /// Counts uses, so the least recently used slot has the smallest [`Slot::used`]
+    clock: u64,
+    stats: CacheStats,
+}
+
+/// How well a [`BlockCache`] works.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Reads and writes of a cached sector.
+    pub hits: u64,
+    /// Reads and writes of a sector which was not cached.
+    pub misses: u64,
+    /// Dirty sectors written to the device.
+    pub writebacks: u64,
+}
+
+/// Caches up to `capacity` sectors of a device, on the kernel heap.
+pub struct BlockCache<'a> {
+    device: &'a (dyn BlockDevice + 'a),
+    capacity: usize,
+    /// Held while the device transfers a sector, which is slow.
+    state: AdaptiveMutex<State>,
+}
+
+impl fmt::Debug for BlockCache<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockCache")
+            .field("device", &self.device)
+            .field("capacity", &self.capacity)
+            .field("stats", &self.stats())
+            .field("lock", &self.state.stats())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> BlockCache<'a> {
+    /// A cache of up to `capacity` sectors of `device`, at least one.
+    pub fn new(device: &'a dyn BlockDevice, capacity: usize) -> Self {
+        let state = State { slots: Vec::new(), clock: 0, stats: CacheStats::default() };
+        Self { device, capacity: capacity.max(1), state: AdaptiveMutex::new("block-cache", state) }
+    }
+
+    /// The maximum amount of cached sectors.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The amount of cached sectors.
+    pub fn len(&self) -> usize {
+        self.state.lock().slots.len()
+    }
+
+    /// Wether no sector is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The hits and misses so far.
+    pub fn stats(&self) -> CacheStats {
+        self.state.lock().stats
+    }
+
+    /// Writes every dirty sector to the device.
+    /// # Errors
+    /// Returns the first error of the device, the sectors which failed stay dirty.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut state = self.state.lock();
+        let State { slots, stats, .. } = &mut *state;
+        for slot in slots.iter_mut().filter(|slot| slot.dirty) {
+            self.device.write_sector(slot.lba, &slot.data)?;
+            slot.dirty = false;
+            stats.writebacks += 1;
+        }
+        Ok(())
+    }
+
+    /// Writes back every dirty sector, and drops every sector.
+    /// # Errors
+    /// Returns the error of [`flush`](Self::flush), nothing is dropped then.
+    pub fn invalidate(&self) -> io::Result<()> {
+        self.flush()?;
+        self.state.lock().slots.clear();
+        Ok(())
+    }
+
+    /// The slot of `lba`, which is [loaded](BlockDevice::read_sector) if it is not cached and
+    /// `load` is set. A slot is evicted when the cache is full.
+    fn slot<'s>(&self, state: &'s mut State, lba: u64, load: bool) -> io::Result<&'s mut Slot> {
+        state.clock += 1;
+        let clock = state.clock;
+        if let Some(i) = state.slots.iter().position(|slot| slot.lba == lba) {
+            state.stats.hits += 1;
+            let slot = &mut state.slots[i];
+            slot.used = clock;
+            return Ok(slot);
+        }
+        state.stats.misses += 1;
+
+        let mut data = Box::new([0; SECTOR_SIZE]);
+        if load {
+            self.device.read_sector(lba, &mut data)?;
+        }
+        let slot = Slot { lba, data, dirty: false, used: clock };
+        if state.slots.len() < self.capacity {
+            state.slots.push(slot);
+            return Ok(state.slots.last_mut().expect("a slot was pushed"));
+        }
+        let (i, victim) = state.slots.iter_mut().enumerate()
+            .min_by_key(|(_, slot)| slot.used)
+            .expect("the cache is not empty");
+        if victim.dirty {
+            self.device.write_sector(victim.lba, &victim.data)?;
+            state.stats.writebacks += 1;
+        }
+        state.slots[i] = slot;
+        Ok(&mut state.slots[i])
+    }
+}
+
+impl BlockDevice for BlockCache<'_> {
+    fn sectors(&self) -> u64 {
+        self.device.sectors()
+    }
+
+    fn read_sector(&self, lba: u64, buf: &mut [u8; SECTOR_SIZE]) -> io::Result<()> {
+        let mut state = self.state.lock();
+        buf.copy_from_slice(&*self.slot(&mut state, lba, true)?.data);
+        Ok(())
+    }
+
+    fn read_within(&self, lba: u64, offset: usize, buf: &mut [u8]) -> io::Result<()> {
+        let range = super::within(offset, buf.len())?;
+        let mut state = self.state.lock();
+        buf.copy_from_slice(&self.slot(&mut state, lba, true)?.data[range]);
+        Ok(())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        BlockCache::flush(self)?;
+        self.device.flush()
+    }
+
+    fn write_sector(&self, lba: u64, buf: &[u8; SECTOR_SIZE]) -> io::Result<()> {
+        if lba >= self.sectors() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the sector is past the end of the disk"));
+        }
+        let mut state = self.state.lock();
+        // the whole sector is replaced, so it is not read first.
+        let slot = self.slot(&mut state, lba, false)?;
+        slot.data.copy_from_slice(buf);
+        slot.dirty = true;
+        Ok(())
+    }
+}
+
+impl Drop for BlockCache<'_> {
+    fn drop(&mut self) {
+        // best effort, there is nobody to report the error to.
+        let _ = self.flush();
+    }
+}
+
+/// Tests hits, eviction of the least recently used sector, and writing back.
+#[cfg(feature = "test")]
+pub fn test_block_cache(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use super::ramdisk::RamDisk;
+    use crate::test::{test_assert, test_assert_eq};
+
+    let disk = RamDisk::new(8);
+    disk.write_sector(1, &[1; SECTOR_SIZE]).map_err(|_| "the disk was not written")?;
+    let cache = BlockCache::new(&disk, 2);
+    let mut buf = [0; SECTOR_SIZE];
+
+    cache.read_sector(1, &mut buf).map_err(|_| "sector 1 was not read")?;
+    test_assert_eq!(buf, [1; SECTOR_SIZE])?;
+    cache.read_sector(1, &mut buf).map_err(|_| "sector 1 was not read")?;
+    test_assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1, writebacks: 0 })?;
+
+    // written back only when evicted.
+    cache.write_sector(2, &[2; SECTOR_SIZE]).map_err(|_| "sector 2 was not written")?;
+    disk.read_sector(2, &mut buf).map_err(|_| "the disk was not read")?;
+    test_assert_eq!(buf, [0; SECTOR_SIZE])?;
+    cache.read_sector(1, &mut buf).map_err(|_| "sector 1 was not read")?;
+    cache.read_sector(3, &mut buf).map_err(|_| "sector 3 was not read")?;
+    test_assert_eq!(cache.len(), 2)?;
+    test_assert_eq!(cache.stats().writebacks, 1)?;
+    disk.read_sector(2, &mut buf).map_err(|_| "the disk was not read")?;
+    test_assert_eq!(buf, [2; SECTOR_SIZE])?;
+
+    cache.write_sector(3, &[3; SECTOR_SIZE]).map_err(|_| "sector 3 was not written")?;
+    cache.flush().map_err(|_| "the cache was not flushed")?;
+    disk.read_sector(3, &mut buf).map_err(|_| "the disk was not read")?;
+    test_assert_eq!(buf, [3; SECTOR_SIZE])?;
+    test_assert!(cache.write_sector(8, &buf).is_err())?;
+    // the bytes past the end of the sector are an error, not a panic.
+    test_assert!(cache.read_within(3, SECTOR_SIZE - 1, &mut buf[..2]).is_err())?;
+    cache.invalidate().map_err(|_| "the cache was not invalidated")?;
+    test_assert!(cache.is_empty())
+}
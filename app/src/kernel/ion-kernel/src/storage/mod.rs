@@ -0,0 +1,166 @@
+//! Block devices, which are read and written in whole sectors.
+//! 
+//! Drivers, such as [ATA](crate::drivers::ata), the [RAM disk](ramdisk) or the
+//! [boot modules](module), implement [`BlockDevice`], and [register](register) their devices by
+//! name, E.g. `hda` or `initrd`. On top of a device, a [`BlockCache`](cache::BlockCache) keeps
+//! recently used sectors on the heap, and a [`BlockStream`](stream::BlockStream) reads, writes
+//! and seeks in bytes, so filesystems can be mounted on it:
+//! 
+//! ```rust,no_run
+//! let disk = storage::get("hda").ok_or(NOT_FOUND)?;
+//! let cache = Box::leak(Box::new(BlockCache::new(disk, 64)));
+//! let volume = FatFs::new(Box::new(BlockStream::new(cache)))?;
+//! fs::mount("/mnt", Arc::new(volume))?;
+//! ```
+//! 
+//! Streams read whole sectors straight into the reader's buffer, and parts of sectors through
+//! [`BlockDevice::read_within`], which devices keeping their sectors in memory implement without a
+//! copy in between. [`copy_stats`] counts the bytes which were copied twice.
+use core::{fmt, sync::atomic::{AtomicU64, Ordering}};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{collections::{ArrayVec, CapacityError}, io};
+
+pub mod cache;
+pub mod module;
+pub mod ramdisk;
+pub mod stream;
+
+/// The size of a sector, in bytes.
+pub const SECTOR_SIZE: usize = 512;
+
+/// Maximum amount of registered devices.
+pub const MAX_DEVICES: usize = 8;
+
+/// A device which is read and written in sectors of [`SECTOR_SIZE`] bytes.
+pub trait BlockDevice: Sync {
+    /// The amount of sectors.
+    fn sectors(&self) -> u64;
+
+    /// Reads the sector at `lba` into `buf`
+    /// # Errors
+    /// Returns an error if `lba` is past the end, or the device failed.
+    fn read_sector(&self, lba: u64, buf: &mut [u8; SECTOR_SIZE]) -> io::Result<()>;
+
+    /// Reads the bytes of the sector at `lba` from `offset` on into `buf`
+    /// 
+    /// By default the sector is read into a buffer and copied from there, which is counted as
+    /// [bounced](copy_stats).
+    /// # Errors
+    /// Returns an error if `lba` is past the end, `buf` does not fit in the sector from `offset`
+    /// on, or the device failed.
+    fn read_within(&self, lba: u64, offset: usize, buf: &mut [u8]) -> io::Result<()> {
+        let range = within(offset, buf.len())?;
+        let mut sector = [0; SECTOR_SIZE];
+        self.read_sector(lba, &mut sector)?;
+        buf.copy_from_slice(&sector[range]);
+        BOUNCED.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Writes `buf` to the sector at `lba`
+    /// # Errors
+    /// Returns an error if `lba` is past the end, the device is read only, or it failed.
+    fn write_sector(&self, lba: u64, buf: &[u8; SECTOR_SIZE]) -> io::Result<()>;
+
+    /// Writes the sectors the device buffers to the medium. By default there are none.
+    /// # Errors
+    /// Returns an error if the device failed.
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The `len` bytes of a sector from `offset` on, for [`BlockDevice::read_within`]
+/// # Errors
+/// Returns [`InvalidInput`](io::ErrorKind::InvalidInput) if they do not fit in the sector.
+pub(crate) fn within(offset: usize, len: usize) -> io::Result<core::ops::Range<usize>> {
+    match offset.checked_add(len) {
+        Some(end) if end <= SECTOR_SIZE => Ok(offset..end),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "the bytes are not in the sector")),
+    }
+}
+
+impl fmt::Debug for dyn BlockDevice + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockDevice").field("sectors", &self.sectors()).finish_non_exhaustive()
+    }
+}
+
+/// How many bytes were read through [streams](stream::BlockStream), see the [module docs](self)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CopyStats {
+    /// Bytes read.
+    pub read: u64,
+    /// Bytes of those which were copied through a sector buffer first.
+    pub bounced: u64,
+}
+
+static READ: AtomicU64 = AtomicU64::new(0);
+static BOUNCED: AtomicU64 = AtomicU64::new(0);
+
+/// The bytes read through streams so far.
+pub fn copy_stats() -> CopyStats {
+    CopyStats { read: READ.load(Ordering::Relaxed), bounced: BOUNCED.load(Ordering::Relaxed) }
+}
+
+static DEVICES: Mutex<ArrayVec<(&'static str, &'static dyn BlockDevice), MAX_DEVICES>> = Mutex::new(ArrayVec::new());
+
+/// Registers `device` as `name`
+/// # Errors
+/// Returns an error if there are [`MAX_DEVICES`] already, or one with the same name.
+pub fn register(name: &'static str, device: &'static dyn BlockDevice) -> Result<(), CapacityError> {
+    without_interrupts(|| {
+        let mut devices = DEVICES.lock();
+        if devices.iter().any(|&(n, _)| n == name) {
+            return Err(CapacityError(()));
+        }
+        devices.push((name, device)).map_err(|_| CapacityError(()))
+    })
+}
+
+/// Unregisters the device called `name`, returns wether it was registered.
+pub fn unregister(name: &str) -> bool {
+    without_interrupts(|| {
+        let mut devices = DEVICES.lock();
+        let i = devices.iter().position(|&(n, _)| n == name);
+        i.map(|i| devices.remove(i)).is_some()
+    })
+}
+
+/// The device called `name`
+pub fn get(name: &str) -> Option<&'static dyn BlockDevice> {
+    without_interrupts(|| DEVICES.lock().iter().find(|&&(n, _)| n == name).map(|&(_, device)| device))
+}
+
+/// Flushes every registered device, see [`BlockDevice::flush`]
+/// # Errors
+/// Returns the first error, after trying the other devices as well.
+pub fn flush_all() -> io::Result<()> {
+    let mut result = Ok(());
+    for_each(|name, device| {
+        if let Err(e) = device.flush() {
+            crate::log::warn!("{name} could not be flushed: {e}");
+            result = result.and(Err(e));
+        }
+    });
+    result
+}
+
+/// Registers the shutdown hook, which [flushes](flush_all) the devices.
+pub fn init() {
+    let hook = || flush_all().map_err(|_| "a block device could not be flushed");
+    if crate::power::shutdown::register(crate::power::shutdown::Stage::Storage, "block devices", hook).is_err() {
+        crate::log::warn!("The block devices are not flushed on shutdown, there are too many shutdown hooks.");
+    }
+}
+
+/// Calls `f` with the name of each device, and the device.
+pub fn for_each(mut f: impl FnMut(&'static str, &'static dyn BlockDevice)) {
+    let devices = without_interrupts(|| DEVICES.lock().clone());
+    for &(name, device) in devices.iter() {
+        f(name, device);
+    }
+}
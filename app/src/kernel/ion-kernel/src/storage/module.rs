@@ -0,0 +1,114 @@
+//! Modules loaded by the bootloader, E.g. an initrd image, as read only block devices.
+//! 
+//! The first module is registered as `initrd`, the others as `module1` to `module7`. A module
+//! with a FAT volume is mounted as the root filesystem with `root=initrd`, see
+//! [`fs::mount_root`](crate::fs::mount_root).
+use alloc::boxed::Box;
+
+use super::{BlockDevice, SECTOR_SIZE};
+use crate::{c_lib::{BootInfo, MAX_MODULES}, io::{self, Cursor, Error, ErrorKind}, log::{info, warn}};
+
+/// The bytes of a module, padded with zeros to whole sectors.
+#[derive(Debug, Clone, Copy)]
+pub struct ModuleDisk {
+    bytes: &'static [u8],
+}
+
+impl ModuleDisk {
+    /// A disk of `bytes`
+    pub const fn new(bytes: &'static [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// The bytes, without the padding.
+    pub fn bytes(&self) -> &'static [u8] {
+        self.bytes
+    }
+
+    /// Reads and seeks in the bytes.
+    pub fn cursor(&self) -> Cursor<&'static [u8]> {
+        Cursor::new(self.bytes)
+    }
+}
+
+impl BlockDevice for ModuleDisk {
+    fn sectors(&self) -> u64 {
+        self.bytes.len().div_ceil(SECTOR_SIZE) as u64
+    }
+
+    fn read_sector(&self, lba: u64, buf: &mut [u8; SECTOR_SIZE]) -> io::Result<()> {
+        if lba >= self.sectors() {
+            return Err(Error::new(ErrorKind::InvalidInput, "the sector is past the end of the module"));
+        }
+        let start = lba as usize * SECTOR_SIZE;
+        let bytes = &self.bytes[start..self.bytes.len().min(start + SECTOR_SIZE)];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        buf[bytes.len()..].fill(0);
+        Ok(())
+    }
+
+    fn read_within(&self, lba: u64, offset: usize, buf: &mut [u8]) -> io::Result<()> {
+        if lba >= self.sectors() {
+            return Err(Error::new(ErrorKind::InvalidInput, "the sector is past the end of the module"));
+        }
+        super::within(offset, buf.len())?;
+        let start = (lba as usize * SECTOR_SIZE + offset).min(self.bytes.len());
+        let bytes = &self.bytes[start..self.bytes.len().min(start + buf.len())];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        buf[bytes.len()..].fill(0);
+        Ok(())
+    }
+
+    fn write_sector(&self, _: u64, _: &[u8; SECTOR_SIZE]) -> io::Result<()> {
+        Err(Error::new(ErrorKind::ReadOnlyFilesystem, "boot modules are read only"))
+    }
+}
+
+/// The name of the device of the `i`th module.
+pub fn module_name(i: usize) -> &'static str {
+    const NAMES: [&str; MAX_MODULES] = ["initrd", "module1", "module2", "module3", "module4", "module5", "module6", "module7"];
+    NAMES[i]
+}
+
+/// Registers the modules of `boot_info` as block devices.
+pub fn init(boot_info: &BootInfo) {
+    for (i, module) in boot_info.modules.iter().enumerate() {
+        // Safety: the modules are identity mapped, and reserved at boot.
+        let disk: &'static ModuleDisk = Box::leak(Box::new(ModuleDisk::new(unsafe { module.bytes() })));
+        info!("{}: {} ({} bytes at {:#x})", module_name(i), module.name, module.len(), module.start);
+        if super::register(module_name(i), disk).is_err() {
+            warn!("{} was not registered", module_name(i));
+        }
+    }
+}
+
+/// Tests reading a module, and parsing its tag.
+#[cfg(feature = "test")]
+pub fn test_boot_module(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::{c_lib::BootModule, io::{Read, Seek, SeekFrom}, test::{test_assert, test_assert_eq}};
+
+    static IMAGE: [u8; SECTOR_SIZE + 4] = {
+        let mut image = [0x11; SECTOR_SIZE + 4];
+        image[SECTOR_SIZE] = 0x22;
+        image
+    };
+    let disk = ModuleDisk::new(&IMAGE);
+    test_assert_eq!(disk.sectors(), 2)?;
+    let mut sector = [0xFF; SECTOR_SIZE];
+    disk.read_sector(1, &mut sector).map_err(|_| "the sector was not read")?;
+    test_assert_eq!(sector[..5], [0x22, 0x11, 0x11, 0x11, 0])?;
+    test_assert!(sector[4..].iter().all(|&byte| byte == 0))?;
+    test_assert!(disk.read_sector(2, &mut sector).is_err())?;
+    test_assert_eq!(disk.write_sector(0, &sector).map_err(|e| e.kind()), Err(ErrorKind::ReadOnlyFilesystem))?;
+
+    let mut cursor = disk.cursor();
+    cursor.seek(SeekFrom::End(-4)).map_err(|_| "the cursor did not seek")?;
+    let mut buf = [0; 8];
+    test_assert_eq!(cursor.read(&mut buf), Ok(4))?;
+
+    // type 3, size 24, 0x1000..0x3000, "initrd"
+    static TAG: [u8; 24] = [3, 0, 0, 0, 24, 0, 0, 0, 0, 0x10, 0, 0, 0, 0x30, 0, 0, b'i', b'n', b'i', b't', b'r', b'd', 0, 0];
+    let module = BootModule::parse(&TAG).ok_or("the tag was not parsed")?;
+    test_assert_eq!(module, BootModule { start: 0x1000, end: 0x3000, name: "initrd" })?;
+    test_assert_eq!(module.len(), 0x2000)
+}
@@ -0,0 +1,73 @@
+//! A block device in memory, E.g. for a disk image loaded by the bootloader, or for tests.
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{BlockDevice, SECTOR_SIZE};
+use crate::io::{self, Error, ErrorKind};
+
+/// Sectors on the kernel heap.
+#[derive(Debug)]
+pub struct RamDisk {
+    bytes: Mutex<Vec<u8>>,
+    read_only: bool,
+}
+
+impl RamDisk {
+    /// A disk of `sectors` zeroed sectors.
+    pub fn new(sectors: usize) -> Self {
+        Self { bytes: Mutex::new(vec![0; sectors * SECTOR_SIZE]), read_only: false }
+    }
+
+    /// A disk with the contents of `image`, padded with zeros to whole sectors.
+    pub fn from_image(image: &[u8], read_only: bool) -> Self {
+        let mut bytes = image.to_vec();
+        bytes.resize(image.len().next_multiple_of(SECTOR_SIZE), 0);
+        Self { bytes: Mutex::new(bytes), read_only }
+    }
+}
+
+/// The range of the sector at `lba`, on a disk of `len` bytes.
+fn range(lba: u64, len: usize) -> io::Result<Range<usize>> {
+    let start = usize::try_from(lba).ok().and_then(|lba| lba.checked_mul(SECTOR_SIZE)).filter(|&start| start < len);
+    let start = start.ok_or(Error::new(ErrorKind::InvalidInput, "the sector is past the end of the disk"))?;
+    Ok(start..start + SECTOR_SIZE)
+}
+
+impl BlockDevice for RamDisk {
+    fn sectors(&self) -> u64 {
+        without_interrupts(|| self.bytes.lock().len() / SECTOR_SIZE) as u64
+    }
+
+    fn read_sector(&self, lba: u64, buf: &mut [u8; SECTOR_SIZE]) -> io::Result<()> {
+        without_interrupts(|| {
+            let bytes = self.bytes.lock();
+            buf.copy_from_slice(&bytes[range(lba, bytes.len())?]);
+            Ok(())
+        })
+    }
+
+    fn read_within(&self, lba: u64, offset: usize, buf: &mut [u8]) -> io::Result<()> {
+        without_interrupts(|| {
+            let within = super::within(offset, buf.len())?;
+            let bytes = self.bytes.lock();
+            let sector = range(lba, bytes.len())?;
+            buf.copy_from_slice(&bytes[sector][within]);
+            Ok(())
+        })
+    }
+
+    fn write_sector(&self, lba: u64, buf: &[u8; SECTOR_SIZE]) -> io::Result<()> {
+        if self.read_only {
+            return Err(Error::new(ErrorKind::ReadOnlyFilesystem, "the disk is read only"));
+        }
+        without_interrupts(|| {
+            let mut bytes = self.bytes.lock();
+            let sector = range(lba, bytes.len())?;
+            bytes[sector].copy_from_slice(buf);
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,159 @@
+//! Reading, writing and seeking in bytes, over the sectors of a block device.
+//! 
+//! Partial sectors are read, changed and written back, so small writes should go through a
+//! [`BlockCache`](super::cache::BlockCache).
+use core::sync::atomic::Ordering;
+
+use super::{BlockDevice, READ, SECTOR_SIZE};
+use crate::io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+/// A position in the bytes of a device, which implements [`Read`], [`Write`] and [`Seek`]
+#[derive(Debug, Clone, Copy)]
+pub struct BlockStream<'a> {
+    device: &'a (dyn BlockDevice + 'a),
+    pos: u64,
+}
+
+impl<'a> BlockStream<'a> {
+    /// A stream at the start of `device`
+    pub const fn new(device: &'a dyn BlockDevice) -> Self {
+        Self { device, pos: 0 }
+    }
+
+    /// The size of the device in bytes.
+    pub fn len(&self) -> u64 {
+        self.device.sectors() * SECTOR_SIZE as u64
+    }
+
+    /// Wether the device has no sectors.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The sector of the position, the offset in it, and how many of `len` bytes fit in it and
+    /// on the device.
+    fn chunk(&self, len: usize) -> (u64, usize, usize) {
+        let lba = self.pos / SECTOR_SIZE as u64;
+        let offset = (self.pos % SECTOR_SIZE as u64) as usize;
+        let left = self.len().saturating_sub(self.pos);
+        (lba, offset, len.min(SECTOR_SIZE - offset).min(usize::try_from(left).unwrap_or(usize::MAX)))
+    }
+}
+
+impl Read for BlockStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (lba, offset, len) = self.chunk(buf.len());
+        if len == 0 {
+            return Ok(0);
+        }
+        match <&mut [u8; SECTOR_SIZE]>::try_from(&mut buf[..len]) {
+            // a whole sector goes straight into `buf`
+            Ok(sector) => self.device.read_sector(lba, sector)?,
+            Err(_) => self.device.read_within(lba, offset, &mut buf[..len])?,
+        }
+        READ.fetch_add(len as u64, Ordering::Relaxed);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Write for BlockStream<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let (lba, offset, len) = self.chunk(buf.len());
+        if len == 0 {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            return Err(Error::new(ErrorKind::WriteZero, "the end of the device was reached"));
+        }
+        let mut sector = [0; SECTOR_SIZE];
+        // a whole sector is replaced without reading it.
+        if len < SECTOR_SIZE {
+            self.device.read_sector(lba, &mut sector)?;
+        }
+        sector[offset..offset + len].copy_from_slice(&buf[..len]);
+        self.device.write_sector(lba, &sector)?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for BlockStream<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => (offset, 0),
+            SeekFrom::End(offset) => (self.len(), offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        self.pos = base.checked_add_signed(offset)
+            .ok_or(Error::new(ErrorKind::InvalidInput, "the position would be before the start"))?;
+        Ok(self.pos)
+    }
+}
+
+/// Tests reading and writing across sectors, and at the end of the device.
+#[cfg(feature = "test")]
+pub fn test_block_stream(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use super::ramdisk::RamDisk;
+    use crate::test::{test_assert, test_assert_eq};
+
+    let disk = RamDisk::new(4);
+    let mut stream = BlockStream::new(&disk);
+    test_assert_eq!(stream.len(), 4 * SECTOR_SIZE as u64)?;
+
+    // across the boundary of sector 0 and 1.
+    stream.seek(SeekFrom::Start(SECTOR_SIZE as u64 - 2)).map_err(|_| "the stream did not seek")?;
+    stream.write_all(b"abcd").map_err(|_| "the stream was not written")?;
+    let mut sector = [0; SECTOR_SIZE];
+    disk.read_sector(1, &mut sector).map_err(|_| "the disk was not read")?;
+    test_assert_eq!(&sector[..2], b"cd")?;
+
+    stream.seek(SeekFrom::Current(-4)).map_err(|_| "the stream did not seek")?;
+    let mut buf = [0; 4];
+    stream.read_exact(&mut buf).map_err(|_| "the stream was not read")?;
+    test_assert_eq!(&buf, b"abcd")?;
+
+    // the end of the device.
+    stream.seek(SeekFrom::End(-1)).map_err(|_| "the stream did not seek")?;
+    test_assert_eq!(stream.read(&mut buf), Ok(1))?;
+    test_assert_eq!(stream.read(&mut buf), Ok(0))?;
+    test_assert_eq!(stream.write(b"x").map_err(|e| e.kind()), Err(ErrorKind::WriteZero))?;
+    test_assert!(stream.seek(SeekFrom::Current(-5000)).is_err())?;
+
+    // the RAM disk copies parts of sectors straight into `buf`, a device without `read_within`
+    // bounces them through a sector.
+    struct Plain<'a>(&'a RamDisk);
+    impl BlockDevice for Plain<'_> {
+        fn sectors(&self) -> u64 {
+            self.0.sectors()
+        }
+        fn read_sector(&self, lba: u64, buf: &mut [u8; SECTOR_SIZE]) -> io::Result<()> {
+            self.0.read_sector(lba, buf)
+        }
+        fn write_sector(&self, lba: u64, buf: &[u8; SECTOR_SIZE]) -> io::Result<()> {
+            self.0.write_sector(lba, buf)
+        }
+    }
+    let before = super::copy_stats();
+    stream.seek(SeekFrom::Start(SECTOR_SIZE as u64 - 2)).map_err(|_| "the stream did not seek")?;
+    stream.read_exact(&mut buf).map_err(|_| "the stream was not read")?;
+    let mut sector = [0; SECTOR_SIZE];
+    test_assert_eq!(stream.read(&mut sector), Ok(SECTOR_SIZE - 2))?;
+    let plain = Plain(&disk);
+    let mut bounced = BlockStream::new(&plain);
+    bounced.seek(SeekFrom::Start(SECTOR_SIZE as u64 - 2)).map_err(|_| "the stream did not seek")?;
+    bounced.read_exact(&mut buf).map_err(|_| "the stream was not read")?;
+    test_assert_eq!(&buf, b"abcd")?;
+    let after = super::copy_stats();
+    test_assert_eq!((after.read - before.read, after.bounced - before.bounced), (8 + SECTOR_SIZE as u64 - 2, 4))?;
+
+    // an image is padded to whole sectors.
+    let disk = RamDisk::from_image(&[0x55; SECTOR_SIZE + 1], true);
+    test_assert_eq!(disk.sectors(), 2)?;
+    let mut stream = BlockStream::new(&disk);
+    test_assert_eq!(stream.write(&[0; 4]).map_err(|e| e.kind()), Err(ErrorKind::ReadOnlyFilesystem))
+}
@@ -0,0 +1,205 @@
+//! A mutex which spins briefly, and then lets other tasks run until it is free.
+//! 
+//! A spin lock must be held with interrupts disabled, if tasks share it, as a task preempted while
+//! holding it would make the others spin forever. That is fine for short critical sections, but
+//! not for one around device I/O, like the [block cache](crate::storage::cache) or a
+//! [FAT volume](crate::fs::fat), which would keep interrupts disabled for the whole transfer.
+//! 
+//! An [`AdaptiveMutex`] is held with interrupts enabled, so its holder can be preempted. A task
+//! which finds it taken spins for up to [`SPIN_CYCLES`] of the TSC, in case the holder is about to
+//! release it, then [yields](crate::task::yield_now) until it is free. The kernel runs on one CPU,
+//! so the holder only makes progress while the waiter spins if an interrupt switches tasks, so the
+//! spin is kept short.
+//! 
+//! Every mutex counts how often it was contended, and how long its waiters waited, see
+//! [`stats`](AdaptiveMutex::stats). Unlike a [`Mutex`](super::Mutex), it is not
+//! [checked](super::lockdep): the lock order checks keep a single stack of held locks, which a
+//! task switch while holding one would mix up.
+//! 
+//! It must not be taken in an interrupt handler, which can not yield.
+use core::{fmt, ops::{Deref, DerefMut}, sync::atomic::{AtomicBool, AtomicU64, Ordering}};
+
+use crate::interrupts::context::assert_not_interrupt;
+
+/// Cycles a waiter spins before it yields.
+pub const SPIN_CYCLES: u64 = 20_000;
+
+fn rdtsc() -> u64 {
+    // Safety: rdtsc has no side effects.
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// How contended an [`AdaptiveMutex`] is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContentionStats {
+    /// Times the lock was taken.
+    pub acquisitions: u64,
+    /// Times the lock was taken after waiting for it.
+    pub contended: u64,
+    /// Times a waiter spun for [`SPIN_CYCLES`] without getting the lock, and yielded.
+    pub blocked: u64,
+    /// Cycles spent waiting, in total.
+    pub wait_cycles: u64,
+    /// The longest wait, in cycles.
+    pub max_wait_cycles: u64,
+}
+
+/// A lock which spins, then yields, see the [module docs](self)
+pub struct AdaptiveMutex<T> {
+    name: &'static str,
+    inner: spin::Mutex<T>,
+    /// Whether a guard exists, which waiters yield on.
+    held: AtomicBool,
+    acquisitions: AtomicU64,
+    contended: AtomicU64,
+    blocked: AtomicU64,
+    wait_cycles: AtomicU64,
+    max_wait_cycles: AtomicU64,
+}
+
+impl<T> AdaptiveMutex<T> {
+    /// A lock named `name`, as shown with its statistics.
+    pub const fn new(name: &'static str, value: T) -> Self {
+        Self {
+            name,
+            inner: spin::Mutex::new(value),
+            held: AtomicBool::new(false),
+            acquisitions: AtomicU64::new(0),
+            contended: AtomicU64::new(0),
+            blocked: AtomicU64::new(0),
+            wait_cycles: AtomicU64::new(0),
+            max_wait_cycles: AtomicU64::new(0),
+        }
+    }
+
+    /// The name given to [`new`](Self::new)
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Takes the lock, spinning and then yielding until it is free.
+    pub fn lock(&self) -> AdaptiveMutexGuard<'_, T> {
+        assert_not_interrupt!();
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        if let Some(guard) = self.inner.try_lock() {
+            return self.guard(guard);
+        }
+        let start = rdtsc();
+        let guard = loop {
+            if let Some(guard) = self.inner.try_lock() {
+                break guard;
+            }
+            if rdtsc().wrapping_sub(start) < SPIN_CYCLES {
+                core::hint::spin_loop();
+                continue;
+            }
+            self.blocked.fetch_add(1, Ordering::Relaxed);
+            // let the holder run, it releases the lock eventually.
+            while self.held.load(Ordering::Acquire) {
+                crate::task::yield_now();
+            }
+        };
+        let waited = rdtsc().wrapping_sub(start);
+        self.contended.fetch_add(1, Ordering::Relaxed);
+        self.wait_cycles.fetch_add(waited, Ordering::Relaxed);
+        self.max_wait_cycles.fetch_max(waited, Ordering::Relaxed);
+        self.guard(guard)
+    }
+
+    /// Takes the lock, if it is free.
+    pub fn try_lock(&self) -> Option<AdaptiveMutexGuard<'_, T>> {
+        let guard = self.inner.try_lock()?;
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        Some(self.guard(guard))
+    }
+
+    fn guard<'a>(&'a self, guard: spin::MutexGuard<'a, T>) -> AdaptiveMutexGuard<'a, T> {
+        self.held.store(true, Ordering::Release);
+        AdaptiveMutexGuard { guard, held: &self.held }
+    }
+
+    /// The contention so far.
+    pub fn stats(&self) -> ContentionStats {
+        ContentionStats {
+            acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            contended: self.contended.load(Ordering::Relaxed),
+            blocked: self.blocked.load(Ordering::Relaxed),
+            wait_cycles: self.wait_cycles.load(Ordering::Relaxed),
+            max_wait_cycles: self.max_wait_cycles.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<T> fmt::Debug for AdaptiveMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdaptiveMutex").field("name", &self.name).field("stats", &self.stats()).finish_non_exhaustive()
+    }
+}
+
+/// The guard of an [`AdaptiveMutex`], which releases it when dropped.
+pub struct AdaptiveMutexGuard<'a, T> {
+    guard: spin::MutexGuard<'a, T>,
+    held: &'a AtomicBool,
+}
+
+impl<T> Drop for AdaptiveMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // the lock itself is released right after, a waiter which misses it spins again.
+        self.held.store(false, Ordering::Release);
+    }
+}
+
+impl<T> Deref for AdaptiveMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for AdaptiveMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> fmt::Debug for AdaptiveMutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdaptiveMutexGuard").finish_non_exhaustive()
+    }
+}
+
+/// Tests waiting for an adaptive mutex held by another task.
+#[cfg(feature = "test")]
+pub fn test_adaptive_mutex(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use core::sync::atomic::AtomicBool;
+
+    use crate::{task, test::{test_assert, test_assert_eq}};
+
+    static LOCK: AdaptiveMutex<u32> = AdaptiveMutex::new("test-adaptive", 0);
+    static HELD: AtomicBool = AtomicBool::new(false);
+
+    *LOCK.lock() += 1;
+    test_assert_eq!(LOCK.stats(), ContentionStats { acquisitions: 1, ..ContentionStats::default() })?;
+
+    let holder = task::spawn("test-holder", || {
+        let mut value = LOCK.lock();
+        HELD.store(true, Ordering::SeqCst);
+        // preempted while holding it, the waiter yields back here.
+        for _ in 0..3 {
+            task::yield_now();
+        }
+        *value += 1;
+    }).map_err(|_| "the task was not spawned")?;
+    while !HELD.load(Ordering::SeqCst) {
+        task::yield_now();
+    }
+    test_assert!(LOCK.try_lock().is_none())?;
+    *LOCK.lock() += 1;
+    task::join(holder);
+
+    test_assert_eq!(*LOCK.lock(), 3)?;
+    let stats = LOCK.stats();
+    test_assert_eq!((stats.acquisitions, stats.contended), (4, 1))?;
+    test_assert!(stats.max_wait_cycles > 0 && stats.wait_cycles >= stats.max_wait_cycles)
+}
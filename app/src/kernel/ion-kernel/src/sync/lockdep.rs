@@ -0,0 +1,272 @@
+//! Lock ordering checks, like Linux's lockdep.
+//! 
+//! Every checked [`Mutex`](super::Mutex) belongs to a [`LockClass`]. While a lock is held, taking
+//! another one records that the first class is taken before the second. Taking them the other way
+//! around later means two contexts can wait for each other, so the first such inversion is
+//! reported on serial, with the backtraces of both orders, before the lock is taken and may hang.
+//! Taking a lock which is already held, like an interrupt handler printing while the interrupted
+//! code prints, is reported as well.
+//! 
+//! The checks only run in debug builds, in release builds this records nothing. The kernel runs
+//! on one CPU, so there is a single stack of held locks, shared by interrupt handlers.
+use core::{fmt::Write, sync::atomic::{AtomicUsize, Ordering}};
+
+use crate::{collections::ArrayVec, panic::{backtrace, screen::RawSerial}};
+
+/// Maximum amount of lock classes, later classes are not checked.
+pub const MAX_CLASSES: usize = 64;
+/// Maximum amount of locks held at once, which are checked.
+pub const MAX_HELD: usize = 16;
+/// Maximum amount of orders with a backtrace.
+pub const MAX_ORDERS: usize = 128;
+/// Frames kept of the backtrace of an order.
+pub const TRACE_DEPTH: usize = 8;
+
+/// A class of locks, which are checked as one.
+#[derive(Debug)]
+pub struct LockClass {
+    name: &'static str,
+    /// The index of the class plus one, 0 until it was first taken.
+    id: AtomicUsize,
+}
+
+impl LockClass {
+    /// A class named `name`, as shown in reports.
+    pub const fn new(name: &'static str) -> Self {
+        Self { name, id: AtomicUsize::new(0) }
+    }
+
+    /// The name of the class.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+type Trace = [u64; TRACE_DEPTH];
+
+#[derive(Debug, Clone, Copy)]
+struct Order {
+    before: usize,
+    after: usize,
+    trace: Trace,
+}
+
+#[derive(Debug)]
+struct State {
+    classes: ArrayVec<&'static str, MAX_CLASSES>,
+    held: ArrayVec<usize, MAX_HELD>,
+    /// Bit `b` of `after[a]` is set, if `b` was taken while `a` was held.
+    after: [u64; MAX_CLASSES],
+    orders: ArrayVec<Order, MAX_ORDERS>,
+    reported: bool,
+}
+
+static STATE: spin::Mutex<State> = spin::Mutex::new(State {
+    classes: ArrayVec::new(),
+    held: ArrayVec::new(),
+    after: [0; MAX_CLASSES],
+    orders: ArrayVec::new(),
+    reported: false,
+});
+
+fn trace() -> Trace {
+    let mut trace = [0; TRACE_DEPTH];
+    let mut i = 0;
+    backtrace::walk(|frame| {
+        trace[i] = frame.return_addr;
+        i += 1;
+        i < TRACE_DEPTH
+    });
+    trace
+}
+
+impl State {
+    fn id(&mut self, class: &LockClass) -> Option<usize> {
+        match class.id.load(Ordering::Relaxed) {
+            0 => {
+                self.classes.push(class.name).ok()?;
+                class.id.store(self.classes.len(), Ordering::Relaxed);
+                Some(self.classes.len() - 1)
+            }
+            id => Some(id - 1),
+        }
+    }
+
+    /// The classes from `from` to `to`, following recorded orders, written to `path`
+    fn path(&self, from: usize, to: usize, path: &mut ArrayVec<usize, MAX_CLASSES>) -> bool {
+        if path.push(from).is_err() {
+            return false;
+        }
+        if from == to {
+            return true;
+        }
+        let mut next = self.after[from];
+        while next != 0 {
+            let class = next.trailing_zeros() as usize;
+            next &= next - 1;
+            if !path.contains(&class) && self.path(class, to, path) {
+                return true;
+            }
+        }
+        path.pop();
+        false
+    }
+}
+
+/// A problem found when a lock is taken, reported once the state is unlocked.
+// not boxed, the heap lock may be the one being checked.
+#[allow(clippy::large_enum_variant)]
+enum Report {
+    Recursive(&'static str),
+    Inversion { held: &'static str, taken: &'static str, chain: ArrayVec<&'static str, MAX_CLASSES>, trace: Option<Trace> },
+}
+
+/// Checks the order before taking a lock of `class`, which is then held until [`release`]d.
+pub fn acquire(class: &LockClass) {
+    if cfg!(debug_assertions) {
+        check(class, true);
+    }
+}
+
+/// Marks a lock of `class` as held, after it was taken without waiting, so it is not checked.
+pub fn acquired(class: &LockClass) {
+    if cfg!(debug_assertions) {
+        check(class, false);
+    }
+}
+
+/// Marks a lock of `class` as no longer held.
+pub fn release(class: &LockClass) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut state = STATE.lock();
+        let Some(id) = class.id.load(Ordering::Relaxed).checked_sub(1) else { return };
+        // locks may be released in any order.
+        if let Some(index) = state.held.iter().rposition(|&held| held == id) {
+            state.held.remove(index);
+        }
+    });
+}
+
+fn check(class: &LockClass, wait: bool) {
+    let report = x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut state = STATE.lock();
+        let id = state.id(class)?;
+        let mut report = None;
+        // locks taken without waiting can not deadlock, so they add no orders.
+        let held_count = if wait { state.held.len() } else { 0 };
+        for i in 0..held_count {
+            let held = state.held[i];
+            if held == id {
+                report = Some(Report::Recursive(class.name));
+                break;
+            }
+            let mut path = ArrayVec::new();
+            if state.path(id, held, &mut path) {
+                let trace = state.orders.iter().find(|o| o.before == path[0] && o.after == path[1]).map(|o| o.trace);
+                let mut chain = ArrayVec::new();
+                for &c in path.iter() {
+                    let _ = chain.push(state.classes[c]);
+                }
+                report = Some(Report::Inversion { held: state.classes[held], taken: class.name, chain, trace });
+                break;
+            }
+            if state.after[held] & 1 << id == 0 {
+                state.after[held] |= 1 << id;
+                let _ = state.orders.push(Order { before: held, after: id, trace: trace() });
+            }
+        }
+        let _ = state.held.push(id);
+        // only the first problem is reported, later ones are likely caused by it.
+        if report.is_some() && !core::mem::replace(&mut state.reported, true) {
+            report
+        } else {
+            None
+        }
+    });
+    if let Some(report) = report {
+        write_report(&report);
+    }
+}
+
+fn write_trace(out: &mut RawSerial, trace: &Trace) {
+    for (i, &return_addr) in trace.iter().take_while(|&&a| a != 0).enumerate() {
+        let frame = backtrace::Frame { return_addr, frame_ptr: 0 };
+        match frame.location() {
+            Some(location) => { let _ = writeln!(out, "  #{i:<2} {return_addr:#018x} at {location}"); }
+            None => { let _ = writeln!(out, "  #{i:<2} {return_addr:#018x}"); }
+        }
+    }
+}
+
+fn write_report(report: &Report) {
+    // serial is locked by checked locks too, so this can not use `serial_println`
+    let mut out = RawSerial;
+    match report {
+        Report::Recursive(name) => {
+            let _ = writeln!(out, "lockdep: `{name}` is taken while it is held, which deadlocks");
+        }
+        Report::Inversion { held, taken, chain, trace } => {
+            let _ = writeln!(out, "lockdep: `{taken}` is taken while `{held}` is held, but was taken before it:");
+            let _ = write!(out, " ");
+            for name in chain {
+                let _ = write!(out, " {name} ->");
+            }
+            let _ = writeln!(out, " {taken}");
+            let _ = writeln!(out, "the earlier order `{}` -> `{}` was taken at:", chain[0], chain[1]);
+            match trace {
+                Some(trace) => write_trace(&mut out, trace),
+                None => { let _ = writeln!(out, "  <not recorded>"); }
+            }
+        }
+    }
+    let _ = writeln!(out, "the current order was taken at:");
+    write_trace(&mut out, &trace());
+}
+
+/// Whether a problem was reported.
+pub fn reported() -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| STATE.lock().reported)
+}
+
+/// Tests detecting an inversion, across a third class.
+#[cfg(feature = "test")]
+pub fn test_lockdep(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+
+    if !cfg!(debug_assertions) {
+        return test_assert!(!reported());
+    }
+    static A: LockClass = LockClass::new("test-a");
+    static B: LockClass = LockClass::new("test-b");
+    static C: LockClass = LockClass::new("test-c");
+    let was_reported = reported();
+
+    // a -> b, then b -> c
+    acquire(&A);
+    acquire(&B);
+    release(&A);
+    acquire(&C);
+    release(&C);
+    release(&B);
+    test_assert_eq!(reported(), was_reported)?;
+
+    // c -> a inverts a -> b -> c
+    acquire(&C);
+    acquire(&A);
+    release(&A);
+    release(&C);
+    test_assert!(reported())?;
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut state = STATE.lock();
+        // the inversion was on purpose, so real ones are still reported.
+        state.reported = was_reported;
+        let (a, c) = (A.id.load(Ordering::Relaxed) - 1, C.id.load(Ordering::Relaxed) - 1);
+        let mut path = ArrayVec::new();
+        test_assert!(state.path(a, c, &mut path))?;
+        test_assert_eq!(path.len(), 3)?;
+        test_assert!(!state.held.contains(&a))
+    })
+}
@@ -0,0 +1,17 @@
+//! Synchronization primitives
+//! 
+//! Unlike the ones from `spin`, the cell may be used from interrupt handlers: it never spins on
+//! something an interrupted context would have to finish. The [`Mutex`] does spin, but has its lock
+//! order [checked](lockdep) in debug builds. The [`AdaptiveMutex`] yields to other tasks while it
+//! waits, for locks held across slow operations.
+
+/// A cell which is written once, and then read from anywhere.
+pub mod once_cell;
+pub mod lockdep;
+/// A spin lock, checked by [lockdep]
+pub mod mutex;
+pub mod adaptive;
+
+pub use adaptive::{AdaptiveMutex, AdaptiveMutexGuard};
+pub use mutex::{Mutex, MutexGuard};
+pub use once_cell::InterruptSafeOnceCell;
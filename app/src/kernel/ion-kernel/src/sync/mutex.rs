@@ -0,0 +1,75 @@
+use core::{fmt, ops::{Deref, DerefMut}};
+
+use super::lockdep::{self, LockClass};
+
+/// A spin lock, whose lock order is [checked](lockdep) in debug builds.
+/// 
+/// Like the one from `spin`, it must be taken with interrupts disabled, if an interrupt handler
+/// takes it too.
+pub struct Mutex<T> {
+    class: LockClass,
+    inner: spin::Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    /// A lock of its own class, named `name`
+    pub const fn new(name: &'static str, value: T) -> Self {
+        Self { class: LockClass::new(name), inner: spin::Mutex::new(value) }
+    }
+
+    /// Takes the lock, spinning until it is free.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        lockdep::acquire(&self.class);
+        MutexGuard { guard: self.inner.lock(), class: &self.class }
+    }
+
+    /// Takes the lock, if it is free.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let guard = self.inner.try_lock()?;
+        lockdep::acquired(&self.class);
+        Some(MutexGuard { guard, class: &self.class })
+    }
+
+    /// The class of the lock.
+    pub fn class(&self) -> &LockClass {
+        &self.class
+    }
+}
+
+impl<T> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mutex").field("class", &self.class.name()).finish_non_exhaustive()
+    }
+}
+
+/// The guard of a [`Mutex`], which releases it when dropped.
+pub struct MutexGuard<'a, T> {
+    guard: spin::MutexGuard<'a, T>,
+    class: &'a LockClass,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        lockdep::release(self.class);
+    }
+}
+
+impl<T> fmt::Debug for MutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MutexGuard").field("class", &self.class.name()).finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,113 @@
+use core::{cell::UnsafeCell, fmt, mem::MaybeUninit, sync::atomic::{AtomicU8, Ordering}};
+
+use x86_64::instructions::interrupts::without_interrupts;
+
+const EMPTY: u8 = 0;
+const WRITING: u8 = 1;
+const READY: u8 = 2;
+
+/// A cell which is written once, usable from interrupt handlers.
+/// 
+/// The value is written with interrupts disabled, so a handler can never interrupt a write on the
+/// same CPU: it either sees no value, or the whole value.
+pub struct InterruptSafeOnceCell<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// Safety: the value is only written once, before `state` is `READY`, and is only shared after.
+unsafe impl<T: Send + Sync> Sync for InterruptSafeOnceCell<T> {}
+unsafe impl<T: Send> Send for InterruptSafeOnceCell<T> {}
+
+impl<T> InterruptSafeOnceCell<T> {
+    /// An empty cell.
+    pub const fn new() -> Self {
+        Self { state: AtomicU8::new(EMPTY), value: UnsafeCell::new(MaybeUninit::uninit()) }
+    }
+
+    /// The value, `None` if it was not set (yet).
+    pub fn get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) == READY {
+            // Safety: `READY` is only stored after the value was written, and it is never written again.
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// Sets the value, or returns it back if the cell was already set.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        without_interrupts(|| {
+            if self.state.compare_exchange(EMPTY, WRITING, Ordering::Acquire, Ordering::Acquire).is_err() {
+                return Err(value);
+            }
+            // Safety: only the context which moved the state out of `EMPTY` writes.
+            unsafe { (*self.value.get()).write(value) };
+            self.state.store(READY, Ordering::Release);
+            Ok(())
+        })
+    }
+
+    /// The value, which is initialized with `init` if it was not set.
+    /// 
+    /// `init` runs with interrupts disabled. If another CPU is setting the value, this waits for
+    /// it; it returns the value set by another context, and drops its own, if it lost the race.
+    pub fn get_or_init(&self, init: impl FnOnce() -> T) -> &T {
+        if let Some(value) = self.get() {
+            return value;
+        }
+        let _ = without_interrupts(|| {
+            if self.state.load(Ordering::Acquire) == EMPTY {
+                self.set(init())
+            } else {
+                Ok(())
+            }
+        });
+        loop {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+impl<T> Default for InterruptSafeOnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for InterruptSafeOnceCell<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == READY {
+            // Safety: the value was written, and is not used again.
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for InterruptSafeOnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("InterruptSafeOnceCell").field(value).finish(),
+            None => f.write_str("InterruptSafeOnceCell(<unset>)"),
+        }
+    }
+}
+
+/// Tests setting the cell once.
+#[cfg(feature = "test")]
+pub fn test_once_cell(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::test_assert_eq;
+
+    static CELL: InterruptSafeOnceCell<u32> = InterruptSafeOnceCell::new();
+    test_assert_eq!(CELL.get(), None)?;
+    test_assert_eq!(CELL.set(1), Ok(()))?;
+    test_assert_eq!(CELL.set(2), Err(2))?;
+    test_assert_eq!(CELL.get_or_init(|| 3), &1)?;
+
+    let cell = InterruptSafeOnceCell::new();
+    test_assert_eq!(cell.get_or_init(|| 4), &4)?;
+    test_assert_eq!(cell.get(), Some(&4))
+}
@@ -0,0 +1,199 @@
+//! What the kernel is, and the machine it runs on: the version, the build, and the hostname.
+//! 
+//! [`info`] gathers all of it, and its [`Display`] is the line printed in the serial banner, and
+//! shown by [`/proc/version`](crate::fs::procfs). The `uname` shell [command](uname_command)
+//! shows the parts of it.
+//! 
+//! The git hash is taken from `ION_GIT_HASH` at build time, which the Makefile sets.
+use core::{fmt::{self, Display}, sync::atomic::{AtomicU64, Ordering}, time::Duration};
+
+use crate::config::{self, ConfigError, Value};
+
+/// The name of the kernel.
+pub const KERNEL_NAME: &str = "Ion OS";
+/// The version of the kernel.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// The git commit the kernel was built from, `unknown` if it was not built from a checkout.
+pub const GIT_HASH: &str = match option_env!("ION_GIT_HASH") {
+    Some(hash) => hash,
+    None => "unknown",
+};
+/// The architecture the kernel was built for.
+pub const MACHINE: &str = "x86_64";
+/// Maximum length of a hostname.
+pub const MAX_HOSTNAME_LEN: usize = 63;
+
+/// The cargo profile the kernel was built with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// With debug assertions.
+    Debug,
+    /// Without debug assertions.
+    Release,
+}
+
+impl Profile {
+    /// The profile of the running kernel.
+    pub const fn current() -> Self {
+        if cfg!(debug_assertions) { Self::Debug } else { Self::Release }
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Debug => "debug",
+            Self::Release => "release",
+        })
+    }
+}
+
+/// Time since boot at which booting finished, in nanoseconds, 0 while booting.
+static BOOT_TIME: AtomicU64 = AtomicU64::new(0);
+
+/// Records that booting finished.
+/// 
+/// Called once, by the kernel entry, after everything was initialized.
+pub fn booted() {
+    let nanos = crate::time::now().as_nanos().max(1) as u64;
+    let _ = BOOT_TIME.compare_exchange(0, nanos, Ordering::Relaxed, Ordering::Relaxed);
+}
+
+/// Information about the running kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Info {
+    /// See [`KERNEL_NAME`]
+    pub name: &'static str,
+    /// See [`VERSION`]
+    pub version: &'static str,
+    /// See [`GIT_HASH`]
+    pub git_hash: &'static str,
+    /// The profile the kernel was built with.
+    pub profile: Profile,
+    /// How long booting took, `None` while still booting.
+    pub boot_time: Option<Duration>,
+    /// The configured [hostname]
+    pub hostname: Value,
+}
+
+impl Display for Info {
+    /// Formats the version line, like `Ion OS version 0.1.0 (git 1a2b3c4, debug build)`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} version {} (git {}, {} build)", self.name, self.version, self.git_hash, self.profile)
+    }
+}
+
+/// Information about the running kernel.
+pub fn info() -> Info {
+    let boot_time = BOOT_TIME.load(Ordering::Relaxed);
+    Info {
+        name: KERNEL_NAME,
+        version: VERSION,
+        git_hash: GIT_HASH,
+        profile: Profile::current(),
+        boot_time: (boot_time != 0).then(|| Duration::from_nanos(boot_time)),
+        hostname: hostname(),
+    }
+}
+
+/// The hostname, see [`config::hostname`]
+pub fn hostname() -> Value {
+    config::hostname()
+}
+
+/// Whether `name` is a valid hostname: ASCII letters, digits, `-` and `.`, not starting or ending
+/// with `-` or `.`, and at most [`MAX_HOSTNAME_LEN`] bytes.
+pub fn is_valid_hostname(name: &str) -> bool {
+    let edge = |b: Option<u8>| b.is_some_and(|b| b.is_ascii_alphanumeric());
+    name.len() <= MAX_HOSTNAME_LEN
+        && edge(name.bytes().next())
+        && edge(name.bytes().last())
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b"-.".contains(&b))
+}
+
+/// Sets the hostname, saving it in the configuration.
+/// # Errors
+/// Returns an error if the name is not [valid](is_valid_hostname), the configuration is full, or it
+/// could not be saved.
+pub fn set_hostname(name: &str) -> Result<(), ConfigError> {
+    config::set("hostname", name)
+}
+
+/// The `uname` shell command: `uname [-asnrvm]`
+pub fn uname_command(args: &[&str]) -> i32 {
+    use crate::text::{print, println};
+
+    let mut fields = [false; 5];
+    for arg in args.iter().skip(1) {
+        let Some(flags) = arg.strip_prefix('-').filter(|f| !f.is_empty()) else {
+            println!("usage: uname [-asnrvm]");
+            return 2;
+        };
+        for flag in flags.chars() {
+            match flag {
+                'a' => fields = [true; 5],
+                's' => fields[0] = true,
+                'n' => fields[1] = true,
+                'r' => fields[2] = true,
+                'v' => fields[3] = true,
+                'm' => fields[4] = true,
+                _ => {
+                    println!("uname: unknown option -{flag}");
+                    return 2;
+                }
+            }
+        }
+    }
+    if fields == [false; 5] {
+        fields[0] = true;
+    }
+
+    let info = info();
+    let mut first = true;
+    let mut field = |shown: bool, args: fmt::Arguments| {
+        if shown {
+            print!("{}{args}", if first { "" } else { " " });
+            first = false;
+        }
+    };
+    field(fields[0], format_args!("{}", info.name));
+    field(fields[1], format_args!("{}", info.hostname));
+    field(fields[2], format_args!("{}", info.version));
+    field(fields[3], format_args!("{} {}", info.git_hash, info.profile));
+    field(fields[4], format_args!("{MACHINE}"));
+    println!();
+    0
+}
+
+/// Tests the version line, and setting the hostname.
+#[cfg(feature = "test")]
+pub fn test_sys_info(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use core::fmt::Write;
+
+    use crate::{collections::ArrayString, test::{test_assert, test_assert_eq}};
+
+    let info = info();
+    let mut line = ArrayString::<128>::new();
+    write!(line, "{info}").map_err(|_| "the version line is too long")?;
+    test_assert!(line.starts_with("Ion OS version ") && line.ends_with(" build)"))?;
+    test_assert!(line.contains(VERSION))?;
+
+    for bad in ["", "-ion", "ion.", "i on", "ion_os"] {
+        test_assert!(!is_valid_hostname(bad))?;
+        test_assert!(set_hostname(bad).is_err())?;
+    }
+    let old = config::get("hostname");
+    let existed = crate::fs::metadata(config::PATH).is_ok();
+    set_hostname("ion-test.local").map_err(|_| "the hostname was not set")?;
+    test_assert_eq!(hostname().as_str(), "ion-test.local")?;
+    match old {
+        Some(old) => set_hostname(&old).map_err(|_| "the hostname was not restored")?,
+        None => test_assert_eq!(config::remove("hostname"), Ok(true))?,
+    }
+    if !existed {
+        // the root filesystem keeps its nodes, which would count as leaked.
+        crate::fs::remove(config::PATH).map_err(|_| "the configuration was not removed")?;
+        let _ = crate::fs::remove("/etc");
+    }
+    test_assert_eq!(info.hostname, hostname())
+}
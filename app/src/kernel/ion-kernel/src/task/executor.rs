@@ -0,0 +1,209 @@
+//! An executor for `async` code, running cooperatively on a task of its own.
+//! 
+//! Futures are [spawned](spawn) onto the executor, and polled whenever they are woken. Waking only
+//! queues the id of the future, without allocating, so interrupt handlers can wake a future, and
+//! leave the actual work to it, like the keyboard does with its
+//! [scancodes](crate::interrupts::keyboard::scancodes).
+//! 
+//! ```rust,no_run
+//! task::executor::spawn(async {
+//!     let mut scancodes = ScancodeStream::take().unwrap();
+//!     loop {
+//!         println!("{:#x}", scancodes.next().await);
+//!     }
+//! });
+//! ```
+//! 
+//! The executor runs on the `executor` task, started by [`init`]. When no future is woken, it halts
+//! until the next interrupt, and the other tasks keep being preempted as usual.
+//! 
+//! Interrupt handlers must wake with [`Waker::wake_by_ref`], from a waker which is only replaced
+//! outside of the handler: dropping the last clone of a waker frees it.
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, task::Wake, vec::Vec};
+use core::{fmt, future::Future, pin::Pin, sync::atomic::{AtomicBool, AtomicU64, Ordering}, task::{Context, Poll, Waker}};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::{self, without_interrupts};
+
+use crate::{collections::RingBuffer, interrupts::context::assert_not_interrupt};
+
+/// How many wakes can be queued, before the executor polls every future instead.
+pub const QUEUE_LEN: usize = 128;
+
+/// Identifies a spawned future, ids are never reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FutureId(u64);
+
+impl fmt::Display for FutureId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+struct Spawned {
+    /// Taken out while the future is polled.
+    future: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    waker: Waker,
+}
+
+impl fmt::Debug for Spawned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Spawned").finish_non_exhaustive()
+    }
+}
+
+/// The futures which are not finished.
+static FUTURES: Mutex<BTreeMap<FutureId, Spawned>> = Mutex::new(BTreeMap::new());
+/// The woken futures, in the order they were woken.
+static WOKEN: Mutex<RingBuffer<FutureId, QUEUE_LEN>> = Mutex::new(RingBuffer::new());
+/// Set when a wake did not fit in [`WOKEN`], so every future is polled.
+static OVERFLOWED: AtomicBool = AtomicBool::new(false);
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Queues the future `id` to be polled.
+fn wake(id: FutureId) {
+    without_interrupts(|| {
+        let mut woken = WOKEN.lock();
+        // woken twice is polled once.
+        if !woken.iter().any(|&woken| woken == id) && woken.push(id).is_err() {
+            OVERFLOWED.store(true, Ordering::Relaxed);
+        }
+    })
+}
+
+#[derive(Debug)]
+struct FutureWaker(FutureId);
+
+impl Wake for FutureWaker {
+    fn wake(self: Arc<Self>) {
+        wake(self.0);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        wake(self.0);
+    }
+}
+
+/// Spawns `future` onto the executor, it is first polled the next time the executor runs.
+pub fn spawn(future: impl Future<Output = ()> + Send + 'static) -> FutureId {
+    assert_not_interrupt!();
+    let id = FutureId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let spawned = Spawned { future: Some(Box::pin(future)), waker: Waker::from(Arc::new(FutureWaker(id))) };
+    without_interrupts(|| FUTURES.lock().insert(id, spawned));
+    wake(id);
+    id
+}
+
+/// Whether the future `id` has not finished yet.
+pub fn is_pending(id: FutureId) -> bool {
+    without_interrupts(|| FUTURES.lock().contains_key(&id))
+}
+
+/// Polls the future `id`, if it exists.
+fn poll(id: FutureId) {
+    // not polled with the map locked, as the future may spawn others.
+    let taken = without_interrupts(|| {
+        let mut futures = FUTURES.lock();
+        let spawned = futures.get_mut(&id)?;
+        Some((spawned.future.take()?, spawned.waker.clone()))
+    });
+    let Some((mut future, waker)) = taken else { return };
+    if future.as_mut().poll(&mut Context::from_waker(&waker)).is_pending() {
+        without_interrupts(|| FUTURES.lock().get_mut(&id).map(|spawned| spawned.future = Some(future)));
+    } else {
+        // freed after the map is unlocked.
+        let spawned = without_interrupts(|| FUTURES.lock().remove(&id));
+        drop((future, spawned));
+    }
+}
+
+/// Polls every woken future once, returning how many were polled.
+pub fn run_ready() -> usize {
+    assert_not_interrupt!();
+    let mut polled = 0;
+    if OVERFLOWED.swap(false, Ordering::Relaxed) {
+        let ids: Vec<_> = without_interrupts(|| FUTURES.lock().keys().copied().collect());
+        for id in ids {
+            poll(id);
+            polled += 1;
+        }
+    }
+    while let Some(id) = without_interrupts(|| WOKEN.lock().pop()) {
+        poll(id);
+        polled += 1;
+    }
+    polled
+}
+
+/// Runs the executor forever, halting while no future is woken.
+pub fn run() -> ! {
+    loop {
+        run_ready();
+        // checked with interrupts disabled, so a wake in between is not missed.
+        interrupts::disable();
+        if WOKEN.lock().iter().next().is_none() && !OVERFLOWED.load(Ordering::Relaxed) {
+            interrupts::enable_and_hlt();
+        } else {
+            interrupts::enable();
+        }
+    }
+}
+
+/// Starts the `executor` task.
+/// 
+/// Must be called once, after the [scheduler](super::init) was started.
+pub fn init() {
+    super::spawn("executor", || { run(); }).expect("Could not start the executor task");
+}
+
+/// The amount of futures which did not finish yet.
+pub fn pending() -> usize {
+    without_interrupts(|| FUTURES.lock().len())
+}
+
+/// A future which is pending once, letting the other woken futures run first.
+#[derive(Debug, Default)]
+#[must_use = "futures do nothing unless awaited"]
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if core::mem::replace(&mut self.yielded, true) {
+            Poll::Ready(())
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+/// Lets the other woken futures run, before continuing.
+pub fn yield_now() -> YieldNow {
+    YieldNow::default()
+}
+
+/// Tests that spawned futures run, take turns, and finish.
+#[cfg(feature = "test")]
+pub fn test_executor(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use core::time::Duration;
+
+    use crate::{collections::ArrayVec, test::{test_assert, test_assert_eq}};
+
+    // inline, a static vector would keep its allocation after the test, which counts as a leak.
+    static TURNS: Mutex<ArrayVec<u8, 4>> = Mutex::new(ArrayVec::new());
+    without_interrupts(|| TURNS.lock().clear());
+    let ids = (*b"ab").map(|name| spawn(async move {
+        for _ in 0..2 {
+            // can not fail, there are four turns.
+            without_interrupts(|| { let _ = TURNS.lock().push(name); });
+            yield_now().await;
+        }
+    }));
+    let finished = crate::time::poll_until(Duration::from_secs(1), || ids.iter().all(|&id| !is_pending(id)).then_some(()));
+    test_assert!(finished.is_some())?;
+    test_assert_eq!(without_interrupts(|| TURNS.lock().clone()).as_slice(), b"abab".as_slice())
+}
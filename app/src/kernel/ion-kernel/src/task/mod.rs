@@ -0,0 +1,380 @@
+//! Kernel tasks, and a preemptive round-robin scheduler.
+//! 
+//! A task is a kernel thread with its own stack, [spawned](spawn) from a closure. The timer
+//! interrupt preempts the running task every tick, and switches to the next ready one,
+//! in the order they were spawned. The code which booted the kernel becomes the first task, named
+//! `kernel`, and never exits.
+//! 
+//! ```rust,no_run
+//! let id = task::spawn("worker", || println!("hello from a task"))?;
+//! ```
+//! 
+//! There is a single CPU. Locks shared with other tasks should be taken with interrupts disabled,
+//! so a task is never preempted while holding one: a task waiting on it with interrupts disabled
+//! would spin forever.
+use alloc::{boxed::Box, vec::Vec};
+use core::{fmt::{self, Display}, ops::Range, sync::atomic::{AtomicBool, Ordering}};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::{self, without_interrupts};
+
+use crate::{collections::CapacityError, interrupts::context::{assert_irqs_disabled, assert_not_interrupt}, log::{info, warn}, mem::stacks::KernelStack, power::shutdown::{Stage, register as register_shutdown}};
+
+pub mod executor;
+pub mod switch;
+pub mod workqueue;
+
+/// The size of the stack of a task, in the [stack region](crate::mem::stacks).
+pub const STACK_SIZE: usize = 64 * 1024;
+/// Maximum amount of tasks, including exited ones which were not cleaned up yet.
+pub const MAX_TASKS: usize = 64;
+
+/// Identifies a task, ids are never reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
+
+impl TaskId {
+    /// The id of the task which booted the kernel.
+    pub const KERNEL: Self = Self(0);
+
+    /// The raw id.
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The state of a task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// Waiting for its turn.
+    Ready,
+    /// Running on the CPU.
+    Running,
+    /// Finished, its stack is freed soon.
+    Exited,
+}
+
+impl Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ready => "ready",
+            Self::Running => "running",
+            Self::Exited => "exited",
+        })
+    }
+}
+
+/// A task, with its saved context.
+pub struct Task {
+    id: TaskId,
+    name: &'static str,
+    state: State,
+    /// The stack pointer, while the task is not running.
+    rsp: u64,
+    /// The stack, `None` for the kernel task, which runs on the boot stack.
+    stack: Option<KernelStack>,
+}
+
+impl fmt::Debug for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Task").field("id", &self.id).field("name", &self.name).field("state", &self.state)
+            .field("stack_size", &self.stack.as_ref().map_or(0, KernelStack::size))
+            .finish()
+    }
+}
+
+impl Task {
+    /// The id.
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// The name given to [`spawn`]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The state.
+    pub fn state(&self) -> State {
+        self.state
+    }
+}
+
+#[derive(Debug)]
+struct Scheduler {
+    /// Boxed, so the saved stack pointers do not move while switching.
+    #[allow(clippy::vec_box)]
+    tasks: Vec<Box<Task>>,
+    /// The index of the running task.
+    current: usize,
+    next_id: u64,
+}
+
+static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler { tasks: Vec::new(), current: 0, next_id: 1 });
+/// Set by [`init`], the timer does not preempt before.
+static PREEMPT: AtomicBool = AtomicBool::new(false);
+
+/// Makes the running code the `kernel` task, and starts preempting.
+/// 
+/// Must be called once, after the heap is initialized.
+pub fn init() {
+    without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        if !scheduler.tasks.is_empty() {
+            return;
+        }
+        scheduler.tasks.push(Box::new(Task { id: TaskId::KERNEL, name: "kernel", state: State::Running, rsp: 0, stack: None }));
+        PREEMPT.store(true, Ordering::Relaxed);
+    });
+    if register_shutdown(Stage::Tasks, "scheduler", || {
+        stop();
+        Ok(())
+    }).is_err() {
+        warn!("The scheduler is not stopped on shutdown, there are too many shutdown hooks.");
+    }
+    info!("Task scheduler started.");
+}
+
+/// Stops preempting, so the running task keeps the CPU unless it yields, E.g. while shutting down.
+pub fn stop() {
+    PREEMPT.store(false, Ordering::Relaxed);
+    let mut alive = 0;
+    for_each(|_, _, state| alive += usize::from(state != State::Exited));
+    info!("Task scheduler stopped, {alive} tasks are left.");
+}
+
+/// Frees the stacks of exited tasks, except the running one, which is still on its stack.
+/// 
+/// This must not run in an interrupt handler, as it frees memory.
+#[allow(clippy::vec_box)]
+fn reap(scheduler: &mut Scheduler) -> Vec<Box<Task>> {
+    let mut reaped = Vec::new();
+    let mut i = 0;
+    while i < scheduler.tasks.len() {
+        if scheduler.tasks[i].state == State::Exited && i != scheduler.current {
+            reaped.push(scheduler.tasks.remove(i));
+            if i < scheduler.current {
+                scheduler.current -= 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    reaped
+}
+
+/// Frees the exited tasks now, instead of on the next [`spawn`], and the room the scheduler kept
+/// for more tasks, E.g. after a burst of short tasks.
+pub fn clean_up() {
+    assert_not_interrupt!();
+    let reaped = without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let reaped = reap(&mut scheduler);
+        scheduler.tasks.shrink_to_fit();
+        reaped
+    });
+    // freed after the scheduler is unlocked, so the timer is not held up.
+    drop(reaped);
+}
+
+/// Spawns a task running `f`, which is first run on the next switch.
+/// 
+/// # Errors
+/// Returns an error if there are [`MAX_TASKS`], or no stack could be mapped, or the scheduler was not
+/// [started](init).
+pub fn spawn(name: &'static str, f: impl FnOnce() + Send + 'static) -> Result<TaskId, CapacityError> {
+    assert_not_interrupt!();
+    let mut stack = KernelStack::new().map_err(|_| CapacityError(()))?;
+    let rsp = switch::prepare_stack(stack.as_mut_slice(), Box::new(f));
+    let mut task = Box::new(Task { id: TaskId(0), name, state: State::Ready, rsp, stack: Some(stack) });
+    let mut reaped = Vec::new();
+    let id = without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        reaped = reap(&mut scheduler);
+        if scheduler.tasks.is_empty() || scheduler.tasks.len() >= MAX_TASKS {
+            return Err(CapacityError(()));
+        }
+        task.id = TaskId(scheduler.next_id);
+        scheduler.next_id += 1;
+        let id = task.id;
+        scheduler.tasks.push(task);
+        Ok(id)
+    });
+    // freed after the scheduler is unlocked, so the timer is not held up.
+    drop(reaped);
+    id
+}
+
+/// Switches to the next ready task, leaving the current one in `state`
+/// 
+/// Interrupts must be disabled.
+fn switch_next(state: State) {
+    assert_irqs_disabled!();
+    // the scheduler may be locked by the interrupted code, then this switch is skipped.
+    let Some(mut scheduler) = SCHEDULER.try_lock() else { return };
+    let len = scheduler.tasks.len();
+    let current = scheduler.current;
+    let Some(next) = (1..len).map(|i| (current + i) % len).find(|&i| scheduler.tasks[i].state == State::Ready) else {
+        return;
+    };
+    scheduler.tasks[current].state = state;
+    scheduler.tasks[next].state = State::Running;
+    scheduler.current = next;
+    let old_rsp = &mut scheduler.tasks[current].rsp as *mut u64;
+    let new_rsp = scheduler.tasks[next].rsp;
+    drop(scheduler);
+    // the catches of this task must not catch the panics of the next one.
+    let catches = crate::panic::catch::suspend();
+    // Safety: interrupts are disabled, the tasks are boxed so `old_rsp` stays valid, and the next
+    // task is not running.
+    unsafe { switch::switch(old_rsp, new_rsp) };
+    crate::panic::catch::resume(catches);
+}
+
+/// Called by the timer interrupt, after the end of interrupt was sent, to switch tasks.
+pub(crate) fn preempt() {
+    if PREEMPT.load(Ordering::Relaxed) {
+        switch_next(State::Ready);
+    }
+}
+
+/// Lets the other ready tasks run, before continuing.
+pub fn yield_now() {
+    assert_not_interrupt!();
+    without_interrupts(|| switch_next(State::Ready));
+}
+
+/// Exits the current task. The kernel task can not exit, for it, this halts forever.
+pub fn exit() -> ! {
+    if current() == TaskId::KERNEL {
+        crate::hlt_loop();
+    }
+    interrupts::disable();
+    switch_next(State::Exited);
+    // only reached if no other task is ready, which the kernel task always is, unless the
+    // scheduler was locked.
+    loop {
+        interrupts::enable_and_hlt();
+        interrupts::disable();
+        switch_next(State::Exited);
+    }
+}
+
+/// The id of the running task.
+pub fn current() -> TaskId {
+    without_interrupts(|| {
+        let scheduler = SCHEDULER.lock();
+        scheduler.tasks.get(scheduler.current).map_or(TaskId::KERNEL, |t| t.id)
+    })
+}
+
+/// The id of the running task, `None` if the scheduler is locked, E.g. by the code an interrupt
+/// handler interrupted.
+pub(crate) fn try_current() -> Option<TaskId> {
+    let scheduler = SCHEDULER.try_lock()?;
+    Some(scheduler.tasks.get(scheduler.current).map_or(TaskId::KERNEL, |t| t.id))
+}
+
+/// The state of a task, `None` if it does not exist (anymore).
+pub fn state(id: TaskId) -> Option<State> {
+    without_interrupts(|| SCHEDULER.lock().tasks.iter().find(|t| t.id == id).map(|t| t.state))
+}
+
+/// Yields until the task `id` exited.
+pub fn join(id: TaskId) {
+    assert_not_interrupt!();
+    while state(id).is_some_and(|state| state != State::Exited) {
+        yield_now();
+    }
+}
+
+/// Calls `f` with every task.
+pub fn for_each(mut f: impl FnMut(TaskId, &'static str, State)) {
+    let tasks: Vec<_> = without_interrupts(|| SCHEDULER.lock().tasks.iter().map(|t| (t.id, t.name, t.state)).collect());
+    for (id, name, state) in tasks {
+        f(id, name, state);
+    }
+}
+
+/// Calls `f` with every task, and the address range of its stack.
+/// 
+/// The stack of the kernel task is the boot stack, which is `None`
+pub fn for_each_stack(mut f: impl FnMut(TaskId, &'static str, Option<Range<u64>>)) {
+    let tasks: Vec<_> = without_interrupts(|| SCHEDULER.lock().tasks.iter()
+        .map(|t| (t.id, t.name, t.stack.as_ref().map(KernelStack::range)))
+        .collect());
+    for (id, name, stack) in tasks {
+        f(id, name, stack);
+    }
+}
+
+/// Tests that tasks run, take turns, exit, and recover from panics to their own catches.
+#[cfg(feature = "test")]
+pub fn test_tasks(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use alloc::sync::Arc;
+    use core::sync::atomic::AtomicUsize;
+
+    use crate::test::{test_assert, test_assert_eq};
+
+    let turns = Arc::new(AtomicUsize::new(0));
+    let ids = ["test-a", "test-b"].map(|name| {
+        let turns = Arc::clone(&turns);
+        spawn(name, move || {
+            for _ in 0..3 {
+                turns.fetch_add(1, Ordering::Relaxed);
+                yield_now();
+            }
+        })
+    });
+    let [Ok(a), Ok(b)] = ids else { return crate::test::TestResult::Failure("the tasks were not spawned") };
+    test_assert_eq!(state(a), Some(State::Ready))?;
+    join(a);
+    join(b);
+    test_assert_eq!(turns.load(Ordering::Relaxed), 6)?;
+    test_assert_eq!(current(), TaskId::KERNEL)?;
+
+    // preempted, without yielding.
+    static STOP: AtomicBool = AtomicBool::new(false);
+    let spinning = spawn("test-spin", || {
+        while !STOP.load(Ordering::Relaxed) {
+            core::hint::spin_loop();
+        }
+    }).map_err(|_| "the task was not spawned")?;
+    crate::time::sleep(core::time::Duration::from_millis(200));
+    test_assert_eq!(state(spinning), Some(State::Ready))?;
+    STOP.store(true, Ordering::Relaxed);
+    join(spinning);
+    // spawning cleans up the exited tasks.
+    let last = spawn("test-reap", || {}).map_err(|_| "the task was not spawned")?;
+    test_assert_eq!(state(a), None)?;
+    join(last);
+    test_assert!(state(spinning).is_none())?;
+
+    // both catches are active at once, each panic must recover to the catch of its own task.
+    static CAUGHT: AtomicBool = AtomicBool::new(false);
+    let catching = spawn("test-catch", || {
+        let caught = crate::panic::catch::catch(|| {
+            yield_now();
+            panic!("test: panic in a task");
+        });
+        CAUGHT.store(caught.is_err(), Ordering::Relaxed);
+    }).map_err(|_| "the task was not spawned")?;
+    let caught = crate::panic::catch::catch(|| {
+        // runs the task up to its yield.
+        yield_now();
+        panic!("test: panic in the test");
+    });
+    test_assert!(caught.is_err())?;
+    join(catching);
+    test_assert!(CAUGHT.load(Ordering::Relaxed))?;
+
+    // the tasks of this test must not count as leaked.
+    clean_up();
+    test_assert_eq!(state(catching), None)
+}
@@ -0,0 +1,84 @@
+//! The context switch.
+//! 
+//! A task which is not running has its callee saved registers and flags pushed on its stack,
+//! below the address to return to, and its stack pointer saved in its [`Task`](super::Task).
+use core::arch::global_asm;
+
+use alloc::boxed::Box;
+
+/// `rflags` of a new task, only the reserved bit is set, so it starts with interrupts disabled.
+const INITIAL_FLAGS: u64 = 0x2;
+
+global_asm!(
+    ".global ion_switch_context",
+    "ion_switch_context:",
+    "pushfq",
+    "push rbp",
+    "push rbx",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov [rdi], rsp",
+    "mov rsp, rsi",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbx",
+    "pop rbp",
+    "popfq",
+    "ret",
+    // a new task returns here from its first switch, with its entry in r12.
+    ".global ion_task_trampoline",
+    "ion_task_trampoline:",
+    "mov rdi, r12",
+    "call {start}",
+    "ud2",
+    start = sym start,
+);
+
+unsafe extern "C" {
+    /// Saves the registers of the current task on its stack, and its stack pointer to `old_rsp`,
+    /// then restores the task saved at `new_rsp`
+    fn ion_switch_context(old_rsp: *mut u64, new_rsp: u64);
+    fn ion_task_trampoline();
+}
+
+/// The entry of a task.
+pub type Entry = Box<dyn FnOnce() + Send + 'static>;
+
+/// Switches to the task saved at `new_rsp`, saving the current one to `old_rsp`
+/// 
+/// # Safety
+/// Interrupts must be disabled. `new_rsp` must have been saved by a switch, or made by
+/// [`prepare_stack`], and the task it belongs to must not be running.
+pub unsafe fn switch(old_rsp: *mut u64, new_rsp: u64) {
+    // Safety: forwarded from the caller.
+    unsafe { ion_switch_context(old_rsp, new_rsp) }
+}
+
+/// Prepares `stack`, so switching to the returned stack pointer runs `entry`
+pub fn prepare_stack(stack: &mut [u64], entry: Entry) -> u64 {
+    // the top must be 16 byte aligned, so the call in the trampoline aligns `start` like any call.
+    let top = (stack.as_mut_ptr_range().end as u64 & !0xF) as *mut u64;
+    let entry = Box::into_raw(Box::new(entry)) as u64;
+    // r15, r14, r13, r12, rbx, rbp, rflags, return address
+    let frame = [0, 0, 0, entry, 0, 0, INITIAL_FLAGS, ion_task_trampoline as *const () as u64];
+    // Safety: the stack is far larger than the frame, and the top is inside of it.
+    unsafe {
+        let rsp = top.sub(frame.len());
+        rsp.copy_from_nonoverlapping(frame.as_ptr(), frame.len());
+        rsp as u64
+    }
+}
+
+/// Runs the entry of a new task, then exits it.
+extern "C" fn start(entry: *mut Entry) -> ! {
+    // Safety: `prepare_stack` leaked the entry for this call.
+    let entry = unsafe { Box::from_raw(entry) };
+    // the task was switched to with interrupts disabled, by the timer or a yield.
+    x86_64::instructions::interrupts::enable();
+    entry();
+    super::exit()
+}
@@ -0,0 +1,213 @@
+//! Work queues, which run closures on a bounded pool of kernel tasks.
+//! 
+//! A subsystem keeps a named queue with a limit of workers in a static, and
+//! [enqueues](WorkQueue::enqueue) work on it, E.g. writing back dirty sectors, or the slow part of
+//! handling a device, which should not run in the caller. Workers are [spawned](super::spawn) as
+//! the queue fills up, at most `max_workers` of them, each named after the queue. They run the
+//! work in the order it was enqueued, and exit once the queue is empty.
+//! 
+//! ```rust,no_run
+//! static WRITEBACK: WorkQueue = WorkQueue::new("writeback", 1);
+//! 
+//! WRITEBACK.enqueue(|| { let _ = cache.flush(); })?;
+//! ```
+//! 
+//! Work runs in a task, so it may yield and sleep, but it must not be enqueued from an interrupt
+//! handler, as workers can not be spawned there. The [executor](super::executor) is for that.
+use alloc::{boxed::Box, collections::VecDeque};
+use core::fmt;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{collections::CapacityError, interrupts::context::assert_not_interrupt};
+
+/// Maximum amount of work waiting on a queue.
+pub const MAX_PENDING: usize = 256;
+
+type Work = Box<dyn FnOnce() + Send>;
+
+struct State {
+    pending: VecDeque<Work>,
+    /// Worker tasks which did not exit yet.
+    workers: usize,
+    /// Workers running work.
+    busy: usize,
+    completed: u64,
+}
+
+/// A queue of work, see the [module docs](self)
+pub struct WorkQueue {
+    name: &'static str,
+    max_workers: usize,
+    state: Mutex<State>,
+}
+
+impl fmt::Debug for WorkQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WorkQueue")
+            .field("name", &self.name)
+            .field("max_workers", &self.max_workers)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// The work of a [`WorkQueue`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Work waiting for a worker.
+    pub pending: usize,
+    /// Work running now.
+    pub running: usize,
+    /// Worker tasks.
+    pub workers: usize,
+    /// Work which finished.
+    pub completed: u64,
+}
+
+impl WorkQueue {
+    /// A queue named `name`, which runs work on up to `max_workers` tasks at once, at least one.
+    pub const fn new(name: &'static str, max_workers: usize) -> Self {
+        let max_workers = if max_workers == 0 { 1 } else { max_workers };
+        let state = State { pending: VecDeque::new(), workers: 0, busy: 0, completed: 0 };
+        Self { name, max_workers, state: Mutex::new(state) }
+    }
+
+    /// The name given to [`new`](Self::new)
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The maximum amount of workers.
+    pub fn max_workers(&self) -> usize {
+        self.max_workers
+    }
+
+    /// The work waiting and running, and the workers.
+    pub fn stats(&self) -> QueueStats {
+        without_interrupts(|| {
+            let state = self.state.lock();
+            QueueStats { pending: state.pending.len(), running: state.busy, workers: state.workers, completed: state.completed }
+        })
+    }
+
+    /// Enqueues `work`, and spawns a worker if every worker is taken and there are less than the
+    /// maximum.
+    /// # Errors
+    /// Returns an error if there are [`MAX_PENDING`] pieces of work waiting, or there is no worker
+    /// and none could be spawned, then `work` is dropped.
+    pub fn enqueue(&'static self, work: impl FnOnce() + Send + 'static) -> Result<(), CapacityError> {
+        assert_not_interrupt!();
+        let spawn = without_interrupts(|| {
+            let mut state = self.state.lock();
+            if state.pending.len() >= MAX_PENDING {
+                return Err(CapacityError(()));
+            }
+            state.pending.push_back(Box::new(work));
+            let idle = state.workers - state.busy;
+            let spawn = state.workers < self.max_workers && state.pending.len() > idle;
+            if spawn {
+                state.workers += 1;
+            }
+            Ok(spawn)
+        })?;
+        if spawn && super::spawn(self.name, move || self.work()).is_err() {
+            return without_interrupts(|| {
+                let mut state = self.state.lock();
+                state.workers -= 1;
+                if state.workers == 0 {
+                    // nobody would run it.
+                    drop(state.pending.pop_back());
+                    return Err(CapacityError(()));
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    /// Runs the pending work, until there is none left.
+    fn work(&self) {
+        loop {
+            let work = without_interrupts(|| {
+                let mut state = self.state.lock();
+                let work = state.pending.pop_front();
+                match work {
+                    Some(_) => state.busy += 1,
+                    None => {
+                        state.workers -= 1;
+                        // the queue holds no memory while it is idle.
+                        state.pending.shrink_to_fit();
+                    }
+                }
+                work
+            });
+            let Some(work) = work else { return };
+            work();
+            without_interrupts(|| {
+                let mut state = self.state.lock();
+                state.busy -= 1;
+                state.completed += 1;
+            });
+        }
+    }
+
+    /// Yields until the pending and running work finished, and the workers exited, so they can be
+    /// [cleaned up](super::clean_up).
+    /// 
+    /// Must not be called from work on this queue, which would wait for itself.
+    pub fn flush(&self) {
+        assert_not_interrupt!();
+        while without_interrupts(|| {
+            let state = self.state.lock();
+            !state.pending.is_empty() || state.busy > 0
+        }) {
+            super::yield_now();
+        }
+        // a worker which found the queue empty still has to return from its task.
+        while self.live_workers() > 0 {
+            super::yield_now();
+        }
+    }
+
+    /// The worker tasks of this queue which did not exit.
+    fn live_workers(&self) -> usize {
+        let mut live = 0;
+        super::for_each(|_, name, state| live += usize::from(name == self.name && state != super::State::Exited));
+        live
+    }
+}
+
+/// Tests running work on a queue with a limit of workers.
+#[cfg(feature = "test")]
+pub fn test_workqueue(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::test::{test_assert, test_assert_eq};
+
+    static DONE: AtomicUsize = AtomicUsize::new(0);
+    static RUNNING: AtomicUsize = AtomicUsize::new(0);
+    static MOST: AtomicUsize = AtomicUsize::new(0);
+
+    static QUEUE: WorkQueue = WorkQueue::new("test-wq", 2);
+    test_assert_eq!((QUEUE.name(), QUEUE.max_workers()), ("test-wq", 2))?;
+    for _ in 0..6 {
+        QUEUE.enqueue(|| {
+            let running = RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+            MOST.fetch_max(running, Ordering::SeqCst);
+            super::yield_now();
+            RUNNING.fetch_sub(1, Ordering::SeqCst);
+            DONE.fetch_add(1, Ordering::SeqCst);
+        }).map_err(|_| "the work was not enqueued")?;
+    }
+    test_assert!(QUEUE.stats().workers <= 2)?;
+    QUEUE.flush();
+    test_assert_eq!(DONE.load(Ordering::SeqCst), 6)?;
+    test_assert!((1..=2).contains(&MOST.load(Ordering::SeqCst)))?;
+    let stats = QUEUE.stats();
+    test_assert_eq!((stats.pending, stats.running, stats.workers, stats.completed), (0, 0, 0, 6))?;
+    // the workers must not count as leaked.
+    super::clean_up();
+    test_assert_eq!(QUEUE.live_workers(), 0)
+}
@@ -0,0 +1,69 @@
+//! Capturing serial output, so tests can assert on what they print.
+use core::{fmt::{self, Write}, sync::atomic::{AtomicBool, Ordering}};
+
+use spin::Mutex;
+
+use crate::collections::ArrayString;
+
+/// Maximum amount of captured bytes per test, later output is dropped.
+pub const CAPTURE_SIZE: usize = 2048;
+
+/// The serial output of the running test.
+pub static SERIAL_CAPTURE: Capture = Capture::new();
+
+/// A buffer which records output while it is active.
+#[derive(Debug)]
+pub struct Capture {
+    buf: Mutex<ArrayString<CAPTURE_SIZE>>,
+    active: AtomicBool,
+    overflowed: AtomicBool,
+}
+
+impl Capture {
+    const fn new() -> Self {
+        Self { buf: Mutex::new(ArrayString::new()), active: AtomicBool::new(false), overflowed: AtomicBool::new(false) }
+    }
+
+    /// Clears the buffer, and starts recording.
+    pub(super) fn start(&self) {
+        self.clear();
+        self.active.store(true, Ordering::SeqCst);
+    }
+
+    /// Stops recording.
+    pub(super) fn stop(&self) {
+        self.active.store(false, Ordering::SeqCst);
+    }
+
+    /// Records `args`, if the capture is active.
+    pub fn record(&self, args: fmt::Arguments) {
+        if !self.active.load(Ordering::Relaxed) {
+            return;
+        }
+        let truncated = x86_64::instructions::interrupts::without_interrupts(|| self.buf.lock().write_fmt(args).is_err());
+        if truncated {
+            self.overflowed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Everything recorded so far.
+    pub fn contents(&self) -> ArrayString<CAPTURE_SIZE> {
+        x86_64::instructions::interrupts::without_interrupts(|| *self.buf.lock())
+    }
+
+    /// Returns wether the recorded output contains `s`
+    pub fn contains(&self, s: &str) -> bool {
+        self.contents().contains(s)
+    }
+
+    /// Returns wether output was dropped, as the buffer was full.
+    pub fn overflowed(&self) -> bool {
+        self.overflowed.load(Ordering::Relaxed)
+    }
+
+    /// Discards everything recorded so far.
+    pub fn clear(&self) {
+        x86_64::instructions::interrupts::without_interrupts(|| self.buf.lock().clear());
+        self.overflowed.store(false, Ordering::Relaxed);
+    }
+}
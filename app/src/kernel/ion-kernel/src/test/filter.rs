@@ -0,0 +1,175 @@
+//! Selecting which tests run, and for how long.
+//! 
+//! Tests can be selected by a substring of their name, and by [`Tags`]. The selection is global,
+//! so it can be set from the boot command line before the tests run.
+//! 
+//! Each test is stopped once it runs longer than the [timeout](set_timeout), which defaults to
+//! [`TIMEOUT_TICKS`], and can be set in milliseconds with `test_timeout=` on the command line.
+use core::{fmt::{self, Display}, time::Duration};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::isolate::TIMEOUT_TICKS;
+use crate::{boot::cmdline::CommandLine, collections::{ArrayString, CapacityError}, log::warn};
+
+/// Categories of tests, as bit flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Tags(u32);
+
+impl Tags {
+    /// No tags.
+    pub const NONE: Tags = Tags(0);
+    /// Tests of the allocators.
+    pub const ALLOC: Tags = Tags(1 << 0);
+    /// Tests which rely on interrupts.
+    pub const INTERRUPTS: Tags = Tags(1 << 1);
+    /// Tests which take a long time.
+    pub const SLOW: Tags = Tags(1 << 2);
+    /// Tests of memory management.
+    pub const MEMORY: Tags = Tags(1 << 3);
+    /// Tests of text output.
+    pub const TEXT: Tags = Tags(1 << 4);
+    /// Tests of collections.
+    pub const COLLECTIONS: Tags = Tags(1 << 5);
+
+    const NAMES: [(&str, Tags); 6] = [
+        ("alloc", Tags::ALLOC),
+        ("interrupts", Tags::INTERRUPTS),
+        ("slow", Tags::SLOW),
+        ("memory", Tags::MEMORY),
+        ("text", Tags::TEXT),
+        ("collections", Tags::COLLECTIONS),
+    ];
+
+    /// Combines two sets of tags.
+    pub const fn union(self, other: Tags) -> Tags {
+        Tags(self.0 | other.0)
+    }
+
+    /// Returns wether any tag is in both sets.
+    pub const fn intersects(self, other: Tags) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Returns wether there are no tags.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the tag called `name`
+    pub fn from_name(name: &str) -> Option<Tags> {
+        Self::NAMES.iter().find(|(n, _)| *n == name).map(|(_, t)| *t)
+    }
+}
+
+impl Display for Tags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for (name, tag) in Self::NAMES {
+            if self.intersects(tag) {
+                if !first {
+                    write!(f, ",")?;
+                }
+                write!(f, "{name}")?;
+                first = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which tests [`run_tests`](super::run_tests) runs.
+#[derive(Debug, Clone, Copy)]
+pub struct RunConfig {
+    /// Only run tests whose name contains this.
+    pub filter: ArrayString<64>,
+    /// Only run tests with one of these tags, if any are set.
+    pub include: Tags,
+    /// Never run tests with one of these tags.
+    pub exclude: Tags,
+    /// Timer ticks each test may take, before it is stopped.
+    pub timeout_ticks: usize,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        Self { filter: ArrayString::new(), include: Tags::NONE, exclude: Tags::NONE, timeout_ticks: TIMEOUT_TICKS }
+    }
+}
+
+impl RunConfig {
+    /// Returns wether a test called `name` with `tags` runs.
+    pub fn selects(&self, name: &str, tags: Tags) -> bool {
+        name.contains(self.filter.as_str())
+            && (self.include.is_empty() || tags.intersects(self.include))
+            && !tags.intersects(self.exclude)
+    }
+
+    /// Returns wether every test runs.
+    pub fn is_default(&self) -> bool {
+        self.filter.is_empty() && self.include.is_empty() && self.exclude.is_empty()
+    }
+}
+
+static CONFIG: Mutex<RunConfig> = Mutex::new(RunConfig {
+    filter: ArrayString::new(),
+    include: Tags::NONE,
+    exclude: Tags::NONE,
+    timeout_ticks: TIMEOUT_TICKS,
+});
+
+/// Only runs tests whose name contains `filter`
+/// # Errors
+/// Returns an error if the filter is too long.
+pub fn set_filter(filter: &str) -> Result<(), CapacityError> {
+    without_interrupts(|| {
+        let mut config = CONFIG.lock();
+        config.filter.clear();
+        config.filter.push_str(filter)
+    })
+}
+
+/// Only runs tests with one of the `include` tags (unless it is empty), and none of the `exclude`
+/// tags.
+pub fn set_tags(include: Tags, exclude: Tags) {
+    without_interrupts(|| {
+        let mut config = CONFIG.lock();
+        config.include = include;
+        config.exclude = exclude;
+    })
+}
+
+/// Stops each test after `ticks` timer ticks, at least one.
+pub fn set_timeout(ticks: usize) {
+    without_interrupts(|| CONFIG.lock().timeout_ticks = ticks.max(1));
+}
+
+/// Applies the options of the `command_line`: `test_timeout=<milliseconds>`
+pub fn configure(command_line: CommandLine<'_>) {
+    match command_line.parse::<u64>("test_timeout") {
+        Some(Ok(ms)) => set_timeout(crate::time::duration_to_ticks(Duration::from_millis(ms)) as usize),
+        Some(Err(_)) => warn!("test_timeout is not a number of milliseconds, using {} ticks", config().timeout_ticks),
+        None => {}
+    }
+}
+
+/// The current selection.
+pub fn config() -> RunConfig {
+    without_interrupts(|| *CONFIG.lock())
+}
+
+/// Tests setting the timeout from the command line.
+#[cfg(feature = "test")]
+pub fn test_timeout_option(_: super::TestInfo) -> super::TestResult {
+    use super::test_assert_eq;
+
+    let before = config().timeout_ticks;
+    configure(CommandLine::new("quiet test_timeout=1000"));
+    let ticks = config().timeout_ticks;
+    // an invalid value keeps the timeout.
+    configure(CommandLine::new("test_timeout=soon"));
+    let kept = config().timeout_ticks;
+    set_timeout(before);
+    test_assert_eq!((ticks, kept), (crate::time::duration_to_ticks(Duration::from_secs(1)) as usize, ticks))
+}
@@ -0,0 +1,149 @@
+//! Running tests in isolation.
+//! 
+//! Each test runs on a [kernel stack](KernelStack) of its own, inside [`catch`], with a checkpoint
+//! of the heap, so a test which panics, overflows the stack, leaks or hangs is reported on its own
+//! and the remaining tests still run. A stack overflow faults on the guard page below the stack,
+//! which is reported as a panic.
+//! 
+//! A test which hangs is stopped by the timer interrupt once it exceeds its timeout,
+//! [`TIMEOUT_TICKS`] unless [configured](super::filter::set_timeout) otherwise. As with a panic,
+//! locks it holds stay locked, so later tests using them may hang as well. The watchdog only stops
+//! the test while the task which runs it is running, not the tasks it spawned.
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use crate::{collections::ArrayString, lib_alloc::{self, AllocStats}, mem::stacks::KernelStack, panic::{CaughtPanic, catch}, task};
+
+/// Timer ticks a test may take before it is stopped by default (about 10 seconds at the default
+/// PIT rate).
+pub const TIMEOUT_TICKS: usize = 182;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+/// The task running the test.
+static TEST_TASK: AtomicU64 = AtomicU64::new(0);
+static TICKS_LEFT: AtomicUsize = AtomicUsize::new(0);
+/// Set by the watchdog when it stops a test.
+static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+/// The time left for the running test, before the watchdog stops it.
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    /// The timer ticks the test was given.
+    pub timeout_ticks: usize,
+}
+
+impl Deadline {
+    /// Timer ticks left, 0 if no test is running.
+    pub fn remaining_ticks(&self) -> usize {
+        if RUNNING.load(Ordering::SeqCst) {
+            TICKS_LEFT.load(Ordering::SeqCst)
+        } else {
+            0
+        }
+    }
+
+    /// Returns wether the test is about to be stopped, so it should wrap up.
+    pub fn expired(&self) -> bool {
+        self.remaining_ticks() <= 1
+    }
+}
+
+/// How an isolated test ended, other than returning.
+// the panic is kept by value, the heap may be what panicked.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy)]
+pub enum Abnormal {
+    /// The test panicked.
+    Panicked(CaughtPanic),
+    /// The test took longer than its timeout, and was stopped by the watchdog.
+    TimedOut,
+}
+
+/// The result of [`run_isolated`]
+#[derive(Debug)]
+pub struct Isolated<R> {
+    /// What the test returned, or why it did not.
+    pub result: Result<R, Abnormal>,
+    /// Allocations and bytes the test did not free.
+    pub leaked: Option<(usize, usize)>,
+}
+
+/// Calls `f(data)` on the stack ending at `stack_top`, switching back afterwards.
+#[unsafe(naked)]
+unsafe extern "C" fn call_on_stack(stack_top: *mut u8, f: extern "C" fn(*mut u8), data: *mut u8) {
+    core::arch::naked_asm!(
+        "push rbp",
+        "mov rbp, rsp",
+        "mov rsp, rdi",
+        "mov rdi, rdx",
+        "call rsi",
+        "mov rsp, rbp",
+        "pop rbp",
+        "ret",
+    )
+}
+
+/// Runs `f` on `stack`
+fn on_stack<F: FnOnce() -> R, R>(stack: &mut KernelStack, f: F) -> R {
+    extern "C" fn call<F: FnOnce() -> R, R>(data: *mut u8) {
+        // Safety: `data` is the `(Option<F>, Option<R>)` in `on_stack`, which outlives this call.
+        let (f, ret) = unsafe { &mut *data.cast::<(Option<F>, Option<R>)>() };
+        // can not fail, the closure is only taken once.
+        *ret = Some(f.take().unwrap()());
+    }
+
+    let mut data = (Some(f), None::<R>);
+    let top = stack.as_mut_slice().as_mut_ptr_range().end.cast::<u8>();
+    // Safety: the stack is mapped and borrowed mutably, and the trampoline restores our stack before
+    // returning.
+    unsafe { call_on_stack(top, call::<F, R>, (&raw mut data).cast()) };
+    // can not fail, `call` stores the return value.
+    data.1.take().unwrap()
+}
+
+/// Runs `test` in isolation, stopping it after `timeout_ticks` timer ticks, see the
+/// [module docs](self)
+/// # Panics
+/// Panics if no kernel stack could be taken for the test.
+pub fn run_isolated<R>(timeout_ticks: usize, test: impl FnOnce() -> R) -> Isolated<R> {
+    // taken before the checkpoint, so mapping it does not count as a leak.
+    let mut stack = KernelStack::new().unwrap_or_else(|e| panic!("no stack for the test: {e}"));
+    let before: AllocStats = lib_alloc::stats();
+
+    TEST_TASK.store(task::current().as_u64(), Ordering::SeqCst);
+    TIMED_OUT.store(false, Ordering::SeqCst);
+    TICKS_LEFT.store(timeout_ticks.max(1), Ordering::SeqCst);
+    RUNNING.store(true, Ordering::SeqCst);
+    #[allow(clippy::result_large_err)]
+    let result = on_stack(&mut stack, || catch(test));
+    RUNNING.store(false, Ordering::SeqCst);
+
+    let leaked = lib_alloc::stats().leaked_since(&before);
+    drop(stack);
+    let result = result.map_err(|panic| {
+        if TIMED_OUT.swap(false, Ordering::SeqCst) { Abnormal::TimedOut } else { Abnormal::Panicked(panic) }
+    });
+    Isolated { result, leaked }
+}
+
+/// Counts down the deadline of the running test, stopping it once it expires.
+/// 
+/// Called by the timer interrupt, after acknowledging it.
+pub fn watchdog_tick() {
+    if !RUNNING.load(Ordering::SeqCst) {
+        return;
+    }
+    // the catch of the test is only active while its task runs, another task is left alone, and the
+    // test is stopped on a later tick.
+    let in_test = task::try_current().is_some_and(|id| id.as_u64() == TEST_TASK.load(Ordering::SeqCst));
+    if TICKS_LEFT.load(Ordering::SeqCst) == 1 && !in_test {
+        return;
+    }
+    if TICKS_LEFT.fetch_sub(1, Ordering::SeqCst) == 1 {
+        RUNNING.store(false, Ordering::SeqCst);
+        TIMED_OUT.store(true, Ordering::SeqCst);
+        let mut message = ArrayString::new();
+        // can not fail, the message is shorter than the capacity.
+        let _ = message.push_str("the test timed out");
+        crate::panic::catch::recover_with(CaughtPanic { message, location: None });
+    }
+}
@@ -0,0 +1,129 @@
+//! Mock devices, so drivers can be tested deterministically inside QEMU.
+//! 
+//! Only the PS/2 port can be mocked here, block devices are mocked with a
+//! [`RamDisk`](crate::storage::ramdisk::RamDisk). Network devices follow once their traits exist.
+use crate::{collections::ArrayVec, interrupts::keyboard::ps2::{Ps2Error, Ps2Io}};
+
+/// Maximum amount of bytes a [`ScriptedPs2`] records.
+pub const MAX_WRITTEN: usize = 64;
+
+/// A PS/2 port which replies with a canned byte stream.
+/// 
+/// Every byte written is recorded, so tests can check the commands a driver sent. Once the script
+/// is exhausted, reads time out, like a device which stopped responding.
+#[derive(Debug, Clone)]
+pub struct ScriptedPs2<'a> {
+    script: &'a [u8],
+    pos: usize,
+    written: ArrayVec<u8, MAX_WRITTEN>,
+}
+
+impl<'a> ScriptedPs2<'a> {
+    /// Creates a port which replies with `script`.
+    pub const fn new(script: &'a [u8]) -> Self {
+        Self { script, pos: 0, written: ArrayVec::new() }
+    }
+
+    /// The bytes written to the port so far.
+    pub fn written(&self) -> &[u8] {
+        self.written.as_slice()
+    }
+
+    /// The bytes of the script which have not been read yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.script[self.pos..]
+    }
+
+    /// Whether the whole script was read.
+    pub fn is_exhausted(&self) -> bool {
+        self.pos == self.script.len()
+    }
+}
+
+impl Ps2Io for ScriptedPs2<'_> {
+    fn write_data(&mut self, byte: u8) -> Result<(), Ps2Error> {
+        // a full record acts like a controller whose input buffer never clears.
+        self.written.push(byte).map_err(|_| Ps2Error::Timeout)
+    }
+
+    fn read_data(&mut self) -> Result<u8, Ps2Error> {
+        let byte = *self.script.get(self.pos).ok_or(Ps2Error::Timeout)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+}
+
+impl Iterator for ScriptedPs2<'_> {
+    type Item = u8;
+
+    /// Yields the rest of the script, as a stream of scancodes.
+    fn next(&mut self) -> Option<u8> {
+        self.read_data().ok()
+    }
+}
+
+/// Tests the PS/2 driver against scripted devices.
+#[cfg(feature = "test")]
+pub fn test_scripted_ps2(_: super::TestInfo) -> super::TestResult {
+    use pc_keyboard::{DecodedKey, HandleControl, Keyboard, layouts::Us104Key};
+
+    use crate::interrupts::keyboard::ps2::{self, ScancodeSet};
+    use super::{test_assert, test_assert_eq};
+
+    // one resend is retried.
+    let mut port = ScriptedPs2::new(&[0xFA, 0xFE, 0xFA]);
+    test_assert!(ps2::set_scancode_set(&mut port, ScancodeSet::Set2).is_ok())?;
+    test_assert_eq!(port.written(), &[0xF0, 0x02, 0x02][..])?;
+    test_assert!(port.is_exhausted())?;
+
+    // translated identifiers.
+    let mut port = ScriptedPs2::new(&[0xFA, 0xFA, 0x41]);
+    test_assert!(matches!(ps2::get_scancode_set(&mut port), Ok(ScancodeSet::Set2)))?;
+
+    // silent and misbehaving devices.
+    let mut port = ScriptedPs2::new(&[0xFA]);
+    test_assert!(matches!(ps2::set_scancode_set(&mut port, ScancodeSet::Set1), Err(Ps2Error::Timeout)))?;
+    let mut port = ScriptedPs2::new(&[0x00]);
+    test_assert!(matches!(ps2::set_scancode_set(&mut port, ScancodeSet::Set1), Err(Ps2Error::UnexpectedByte(0))))?;
+
+    // an MF2 keyboard, scanning is disabled and enabled around identifying.
+    let mut port = ScriptedPs2::new(&[0xFA, 0xFA, 0xAB, 0x83, 0xFA]);
+    test_assert!(matches!(ps2::identify(&mut port), Ok(ps2::DeviceType::Mf2Keyboard)))?;
+    test_assert_eq!(port.written(), &[0xF5, 0xF2, 0xF4][..])?;
+    test_assert_eq!(ps2::DeviceType::from_id(&[0x03]), ps2::DeviceType::ScrollMouse)?;
+
+    // negotiating: translated keyboards are moved to set 2, set 3 keyboards send every break code.
+    let mut port = ScriptedPs2::new(&[0xFA, 0xFA, 0x3F, 0xFA, 0xFA]);
+    test_assert!(matches!(ps2::negotiate(&mut port, true, None), Ok(ScancodeSet::Set1)))?;
+    test_assert_eq!(port.written(), &[0xF0, 0x00, 0xF0, 0x02][..])?;
+    let mut port = ScriptedPs2::new(&[0xFA, 0xFA, 0x03, 0xFA]);
+    test_assert!(matches!(ps2::negotiate(&mut port, false, None), Ok(ScancodeSet::Set3)))?;
+    test_assert_eq!(port.written(), &[0xF0, 0x00, 0xFA][..])?;
+    // a keyboard plugged in later is switched to the set which is decoded.
+    let mut port = ScriptedPs2::new(&[0xFA, 0xFA, 0x02, 0xFA, 0xFA, 0xFA]);
+    test_assert!(matches!(ps2::negotiate(&mut port, false, Some(ScancodeSet::Set3)), Ok(ScancodeSet::Set3)))?;
+    test_assert_eq!(port.written(), &[0xF0, 0x00, 0xF0, 0x03, 0xFA][..])?;
+
+    let decode = |set, bytes: &[u8]| {
+        let mut keyboard = Keyboard::new(ps2::Decoder::new(set), Us104Key, HandleControl::Ignore);
+        let mut text = crate::collections::ArrayString::<8>::new();
+        for byte in ScriptedPs2::new(bytes) {
+            if let Ok(Some(event)) = keyboard.add_byte(byte) {
+                if let Some(DecodedKey::Unicode(c)) = keyboard.process_keyevent(event) {
+                    let _ = text.push(c);
+                }
+            }
+        }
+        text
+    };
+    // shift + h, i
+    test_assert_eq!(decode(ScancodeSet::Set1, &[0x2A, 0x23, 0xA3, 0xAA, 0x17, 0x97]).as_str(), "Hi")?;
+    test_assert_eq!(decode(ScancodeSet::Set2, &[0x12, 0x33, 0xF0, 0x33, 0xF0, 0x12, 0x43, 0xF0, 0x43]).as_str(), "Hi")?;
+    test_assert_eq!(decode(ScancodeSet::Set3, &[0x12, 0x33, 0xF0, 0x33, 0xF0, 0x12, 0x43, 0xF0, 0x43]).as_str(), "Hi")?;
+    // set 3 has no prefixes: keypad 7 and home are separate keys.
+    use pc_keyboard::{KeyCode, KeyEvent, KeyState, ScancodeSet as _};
+    let mut decoder = ps2::Decoder::new(ScancodeSet::Set3);
+    test_assert_eq!(decoder.advance_state(0x6E), Ok(Some(KeyEvent::new(KeyCode::Home, KeyState::Down))))?;
+    test_assert_eq!(decoder.advance_state(0xF0), Ok(None))?;
+    test_assert_eq!(decoder.advance_state(0x6C), Ok(Some(KeyEvent::new(KeyCode::Numpad7, KeyState::Up))))
+}
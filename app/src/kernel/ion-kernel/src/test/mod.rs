@@ -0,0 +1,438 @@
+//! This module is not for tests, but instead the test frame-work
+//! 
+//! It includes the test runner, and other related items.
+#![cfg_attr(not(feature = "test"), allow(dead_code))]
+use alloc::sync::Arc;
+use core::{any::{Any, TypeId, type_name}, cmp::Ordering, convert::Infallible, ops::{FromResidual, Try}};
+
+use crate::{fs::{Dir, FileSystem, ramfs::RamFs}, hlt_loop, lib_alloc::Arena, serial_print, serial_println};
+
+pub mod capture;
+pub mod filter;
+pub mod isolate;
+pub mod mock;
+pub mod persist;
+pub mod stats;
+
+pub use filter::Tags;
+
+/// Size of the arena each test gets, see [`TestInfo::arena`]
+pub const TEST_ARENA_SIZE: usize = 4096;
+
+/// Info Passed to Tests
+/// 
+/// This also carries resources owned by the test runner, so tests do not have to use global
+/// statics. Infos are compared by [`ord`](Self::ord) and [`type_id`](Self::type_id) only.
+#[derive(Debug, Clone)]
+pub struct TestInfo<'a> {
+    /// The index at which the test is ran
+    pub ord: usize,
+    /// TypeID of the Test.
+    /// 
+    /// Usually a function's
+    pub type_id: TypeId,
+    /// An arena for scratch allocations, which is freed after the test, so it does not count as a
+    /// leak.
+    pub arena: &'a Arena,
+    /// The serial output of the test, so far.
+    pub output: &'a capture::Capture,
+    /// The time left until the test is stopped.
+    pub deadline: isolate::Deadline,
+    /// An empty directory on a [`RamFs`] of its own, which is dropped after the test. Entries left
+    /// in it are counted as a leak, so remove them before returning.
+    pub scratch: Arc<dyn Dir>,
+}
+
+impl PartialEq for TestInfo<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.ord, self.type_id) == (other.ord, other.type_id)
+    }
+}
+
+impl Eq for TestInfo<'_> {}
+
+impl PartialOrd for TestInfo<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TestInfo<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.ord, self.type_id).cmp(&(other.ord, other.type_id))
+    }
+}
+
+/// A Testable Object
+/// 
+/// This allows for any type to be a test.
+pub trait Testable: Any {
+    /// Runs the test.
+    fn run(&self, info: TestInfo<'_>) -> TestResult;
+
+    /// The name of the test, which is printed, and matched by the [filter](filter::set_filter).
+    fn name(&self) -> &'static str;
+
+    /// The tags of the test, see [`Tags`]
+    fn tags(&self) -> Tags {
+        Tags::NONE
+    }
+
+    /// How the result of the test is judged, see [`TestKind`]
+    fn kind(&self) -> TestKind {
+        TestKind::Normal
+    }
+}
+
+/// How the runner judges a test.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TestKind {
+    /// The test passes if it returns [`TestResult::Ok`]
+    #[default]
+    Normal,
+    /// The test passes if it panics, or faults, with a message containing `expected` if it is set.
+    /// Returning is a failure, unless the test is [ignored](TestResult::Ignored).
+    /// 
+    /// The panic is caught like any other, so the next test runs afterwards.
+    ShouldPanic {
+        /// A part of the expected panic message.
+        expected: Option<&'static str>,
+    },
+}
+
+impl<T: Fn(TestInfo<'_>) -> TestResult + Any> Testable for T {
+    fn run(&self, info: TestInfo<'_>) -> TestResult {
+        self(info)
+    }
+
+    fn name(&self) -> &'static str {
+        type_name::<T>()
+    }
+}
+
+/// A test with [`Tags`]
+/// 
+/// # Example
+/// ```rust,no_run
+/// use crate::test::{Tagged, Tags};
+/// 
+/// let test = &Tagged { test: test_large_alloc, tags: Tags::ALLOC.union(Tags::SLOW) };
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Tagged<T> {
+    /// The test.
+    pub test: T,
+    /// Its tags.
+    pub tags: Tags,
+}
+
+impl<T: Testable> Testable for Tagged<T> {
+    fn run(&self, info: TestInfo<'_>) -> TestResult {
+        self.test.run(info)
+    }
+
+    fn name(&self) -> &'static str {
+        self.test.name()
+    }
+
+    fn tags(&self) -> Tags {
+        self.tags
+    }
+
+    fn kind(&self) -> TestKind {
+        self.test.kind()
+    }
+}
+
+/// A test which should panic, see [`TestKind::ShouldPanic`]
+/// 
+/// # Example
+/// ```rust,no_run
+/// use crate::test::ShouldPanic;
+/// 
+/// let test = &ShouldPanic { test: test_out_of_bounds, expected: Some("index out of bounds") };
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ShouldPanic<T> {
+    /// The test.
+    pub test: T,
+    /// A part of the expected panic message.
+    pub expected: Option<&'static str>,
+}
+
+impl<T: Testable> Testable for ShouldPanic<T> {
+    fn run(&self, info: TestInfo<'_>) -> TestResult {
+        self.test.run(info)
+    }
+
+    fn name(&self) -> &'static str {
+        self.test.name()
+    }
+
+    fn tags(&self) -> Tags {
+        self.test.tags()
+    }
+
+    fn kind(&self) -> TestKind {
+        TestKind::ShouldPanic { expected: self.expected }
+    }
+}
+
+/// The result of a test
+/// 
+/// A test can: 
+/// - pass (Ok)
+/// - fail: (Failure(/* err */))
+/// - be ignored (Ignored)
+#[derive(Debug, Clone)]
+pub enum TestResult {
+    /// The Test Has Passed
+    Ok,
+    /// The Test has Failed
+    /// 
+    /// The inner value is a description of why
+    Failure(&'static str),
+    /// This test was ignored, for whatever reason.
+    Ignored,
+}
+
+impl TestResult {
+    /// Returns a fail
+    /// 
+    /// useful for map functions
+    pub fn fail(err: &'static str) -> Self {
+        Self::Failure(err)
+    }
+
+    /// asserts the first argument, failing with `err` if it is false
+    pub fn assertion(assert: bool, err: &'static str) -> Self {
+        if assert {
+            Self::Ok
+        } else {
+            Self::Failure(err)
+        }
+    }
+}
+
+impl FromResidual<&'static str> for TestResult {
+    fn from_residual(residual: &'static str) -> Self {
+        Self::Failure(residual)
+    }
+}
+
+impl FromResidual<Result<Infallible, &'static str>> for TestResult {
+    fn from_residual(residual: Result<Infallible, &'static str>) -> Self {
+        let Err(e) = residual;
+        Self::Failure(e)
+    }
+}
+
+impl Try for TestResult {
+    type Output = ();
+    // a residual must implement `Residual`, which a plain `&str` does not.
+    type Residual = Result<Infallible, &'static str>;
+    fn branch(self) -> core::ops::ControlFlow<Self::Residual, Self::Output> {
+        match self {
+            Self::Failure(e) => core::ops::ControlFlow::Break(Err(e)),
+            _ => core::ops::ControlFlow::Continue(())
+        }
+    }
+
+    fn from_output(_: Self::Output) -> Self {
+        Self::Ok
+    }
+}
+
+/// Runs tests
+/// 
+/// Only the tests selected by the [filter](filter::config) run, the others are skipped without
+/// being counted.
+/// 
+/// do not call - this function is called automatically in lib.rs
+/// 
+/// however, you may be able to find alternative uses elsewhere
+pub fn run_tests(tests: &'static [&(dyn Testable + 'static)]) -> ! {
+    filter::configure(crate::boot::cmdline::command_line());
+    let config = filter::config();
+    let selected = tests.iter().filter(|t| config.selects(t.name(), t.tags())).count();
+    if config.is_default() {
+        serial_println!("Now Running {} Tests.", tests.len());
+    } else {
+        serial_println!("Now Running {} of {} Tests (filter: {:?}, tags: +[{}] -[{}]).", selected, tests.len(), config.filter.as_str(), config.include, config.exclude);
+    }
+    if config.timeout_ticks != isolate::TIMEOUT_TICKS {
+        serial_println!("Tests time out after {} ticks.", config.timeout_ticks);
+    }
+    let mut fail_count = 0;
+    let mut pass_count = 0;
+    let mut ignore_count = 0;
+    let mut total_cycles = 0u64;
+    let mut slowest: Option<(&str, u64)> = None;
+    for (i, test) in tests.iter().enumerate() {
+        if !config.selects(test.name(), test.tags()) {
+            continue;
+        }
+        serial_print!("[{}] {}: ", i + 1, test.name());
+        // created before the test starts, so they are not counted as a leak.
+        let arena = Arena::new(TEST_ARENA_SIZE).expect("no memory for the test arena");
+        let scratch = RamFs::new();
+        let info = TestInfo {
+            ord: i,
+            type_id: test.type_id(),
+            arena: &arena,
+            output: &capture::SERIAL_CAPTURE,
+            deadline: isolate::Deadline { timeout_ticks: config.timeout_ticks },
+            scratch: scratch.root(),
+        };
+        // a panicking, hanging or leaking test fails, instead of ending the run.
+        capture::SERIAL_CAPTURE.start();
+        let (mut isolated, cycles) = stats::timed(|| isolate::run_isolated(config.timeout_ticks, || test.run(info)));
+        capture::SERIAL_CAPTURE.stop();
+        // in case the test panicked before dropping its clock.
+        #[cfg(feature = "test")]
+        crate::time::test_clock::uninstall();
+        drop(arena);
+        drop(scratch);
+        total_cycles += cycles;
+        if slowest.is_none_or(|(_, c)| cycles > c) {
+            slowest = Some((test.name(), cycles));
+        }
+        let (result, panic) = match (test.kind(), isolated.result) {
+            // the test was stopped, so it may have leaked as well.
+            (_, Err(isolate::Abnormal::TimedOut)) => {
+                isolated.leaked = None;
+                (TestResult::Failure("timeout"), None)
+            }
+            // the frames skipped by the panic leak, which is expected.
+            (TestKind::ShouldPanic { expected }, Err(isolate::Abnormal::Panicked(panic))) => {
+                isolated.leaked = None;
+                match expected {
+                    Some(expected) if !panic.message.contains(expected) => (TestResult::Failure("the test panicked with another message"), Some(panic)),
+                    _ => (TestResult::Ok, None),
+                }
+            }
+            (TestKind::ShouldPanic { .. }, Ok(TestResult::Ignored)) => (TestResult::Ignored, None),
+            (TestKind::ShouldPanic { .. }, Ok(_)) => (TestResult::Failure("the test did not panic"), None),
+            (TestKind::Normal, Ok(TestResult::Ok)) if isolated.leaked.is_some() => (TestResult::Failure("the test leaked memory"), None),
+            (TestKind::Normal, Ok(result)) => (result, None),
+            (TestKind::Normal, Err(isolate::Abnormal::Panicked(panic))) => (TestResult::Failure("the test panicked"), Some(panic)),
+        };
+        match result {
+            TestResult::Ok => { 
+                serial_println!("[OK] ({} cycles)", cycles);
+                pass_count += 1;
+            },
+            TestResult::Failure(e) => {
+                serial_println!("[FAIL]");
+                serial_println!(" => {}", e);
+                if let Some(panic) = panic {
+                    serial_println!(" => {}", panic);
+                }
+                if let Some((allocs, bytes)) = isolated.leaked {
+                    serial_println!(" => {} allocations ({} bytes) were not freed", allocs, bytes);
+                }
+                fail_count += 1;
+            },
+            TestResult::Ignored => { 
+                serial_println!("[IGNORED]");
+                ignore_count += 1;
+            }
+        }
+    }
+    serial_print!("Ran Tests: ");
+    if fail_count > 0 {
+        serial_println!("[FAILED]");
+    } else {
+        serial_println!("[OK]");
+    }
+    serial_println!("=> {} Passed", pass_count);
+    serial_println!("=> {} Failed", fail_count);
+    serial_println!("=> {} Ignored", ignore_count);
+    serial_println!("=> {} cycles in total", total_cycles);
+    if let Some((name, cycles)) = slowest {
+        serial_println!("=> slowest: {} ({} cycles)", name, cycles);
+    }
+    // shut down like a normal boot would, before exiting QEMU.
+    serial_println!("=> {}", crate::power::shutdown::run_hooks());
+    if fail_count > 0 {
+        exit(QemuExitCode::Failed)
+    } else {
+        exit(QemuExitCode::Passed)
+    }
+}
+
+/// Asserts the passed in value, with an optional, Statically set message
+pub macro test_assert {
+    ($test:expr $(,)?) => {{
+        $crate::test::TestResult::assertion($test, concat!("Assertion `", stringify!($test), "` Failed"))
+    }},
+    ($test:expr, $msg:literal) => {{
+        $crate::test::TestResult::assertion($test, concat!("Assertion `", stringify!($test), "` Failed: ", $msg))
+    }}
+}
+
+/// Asserts the passed in values are equal, with an optional, Statically set message
+pub macro test_assert_eq {
+    ($a:expr, $b:expr $(,)?) => {
+        $crate::test::test_assert!($a == $b)
+    },
+    ($a:expr, $b:expr, $msg:literal) => {
+        $crate::test::test_assert!($a == $b, $msg)
+    }
+}
+
+/// Asserts the passed in values are not equal, with an optional, Statically set message
+pub macro test_assert_ne {
+    ($a:expr, $b:expr $(,)?) => {
+        $crate::test::test_assert!($a != $b)
+    },
+    ($a:expr, $b:expr, $msg:literal) => {
+        $crate::test::test_assert!($a != $b, $msg)
+    }
+}
+
+
+/// Asserts the passed in value matches the pattern, with an optional, Statically set message
+pub macro test_assert_matches {
+    ($a:expr, $pat:pat $(,)?) => {
+        $crate::test::test_assert!(matches!($a, $pat))
+    },
+    ($a:expr, $b:expr, $msg:literal) => {
+        $crate::test::test_assert!(matches!($a, $pat), $msg)
+    }
+}
+
+// QEMU exiting.
+
+/// Represents a Qemu Exit Code
+/// 
+/// This is used when ending tests, which is why prints must be serial.
+/// 
+/// # Example
+/// in run_tests...
+/// ```rust,no_run
+/// # let fails = 0
+/// use crate::test::{QemuExitCode, exit};
+/// 
+/// exit(QemuExitCode::Passed);
+/// ```
+#[derive(Debug)]
+pub enum QemuExitCode {
+    /// Tests Passed
+    Passed = 0x10,
+    /// Tests Failed
+    Failed = 0x11
+}
+
+/// Exits QEMU using the code
+/// 
+/// see [`QemuExitCode`] for more info
+pub fn exit(code: QemuExitCode) -> ! {
+    use x86_64::instructions::port::Port;
+
+    unsafe {
+        let mut port = Port::new(0xf4);
+        port.write(code as u32);
+    }
+    hlt_loop();
+}
\ No newline at end of file
@@ -0,0 +1,234 @@
+//! Tests which reboot the machine.
+//! 
+//! A [`Persistent`] test runs in stages: each stage either finishes the test, or asks for a reboot,
+//! after which the next stage runs. Progress and results are kept in a journal in a reserved page of
+//! low memory, which is not cleared by a warm reboot.
+//! 
+//! This allows testing reboot paths and early boot code, which can not be tested within a single
+//! boot.
+use core::{hash::Hasher, mem::size_of, sync::atomic::{AtomicBool, Ordering}};
+
+use crate::{c_lib::PHYSICAL_MEMORY_OFFSET, collections::{ArrayString, hash::FnvHasher}, mem::regions::{self, ReserveError}, power, serial_println, test::{TestInfo, TestResult}};
+
+/// Physical address of the journal page.
+pub const JOURNAL_ADDR: u64 = 0x5000;
+/// Maximum amount of persistent tests per run.
+pub const MAX_TESTS: usize = 32;
+/// Maximum amount of reboots a single test may ask for.
+pub const MAX_REBOOTS: u32 = 8;
+/// Maximum length of a persisted failure message.
+pub const MAX_MESSAGE_LEN: usize = 64;
+
+const JOURNAL_MAGIC: u64 = u64::from_le_bytes(*b"IONTJRNL");
+
+/// Set by [`run`], checked by [`test_persistent_passed`]
+static ALL_PASSED: AtomicBool = AtomicBool::new(true);
+
+/// A test which may reboot the machine.
+#[derive(Debug, Clone, Copy)]
+pub struct Persistent {
+    /// The name of the test, for reports.
+    pub name: &'static str,
+    /// Runs one stage of the test.
+    pub run: fn(&mut Context) -> Step,
+}
+
+/// What to do after a stage of a [`Persistent`] test.
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// Reboot, and run the next stage.
+    Reboot,
+    /// The test is done.
+    Done(TestResult),
+}
+
+/// State of a [`Persistent`] test, which survives reboots.
+#[derive(Debug)]
+pub struct Context<'a> {
+    /// The stage to run, starting at 0.
+    pub stage: u32,
+    /// The amount of boots since the persistent run started, starting at 1.
+    pub boot_count: u32,
+    /// Values the test wants to keep across reboots.
+    pub data: &'a mut [u64; 4],
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Passed = 1,
+    Failed = 2,
+    Ignored = 3,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Record {
+    outcome: u8,
+    message_len: u8,
+    message: [u8; MAX_MESSAGE_LEN],
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct Journal {
+    magic: u64,
+    /// Checksum of everything after this field.
+    checksum: u64,
+    boot_count: u32,
+    /// Index of the running test.
+    test: u32,
+    stage: u32,
+    /// Reboots requested by the running test.
+    reboots: u32,
+    data: [u64; 4],
+    records: [Record; MAX_TESTS],
+}
+
+const _: () = assert!(size_of::<Journal>() <= 4096);
+
+impl Journal {
+    fn compute_checksum(&self) -> u64 {
+        let mut hasher = FnvHasher::default();
+        // Safety: the journal is plain old data, and the range is inside it.
+        let bytes = unsafe {
+            let start = (&raw const self.boot_count).cast::<u8>();
+            core::slice::from_raw_parts(start, size_of::<Self>() - 16)
+        };
+        hasher.write(bytes);
+        hasher.finish()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.magic == JOURNAL_MAGIC && self.checksum == self.compute_checksum()
+    }
+
+    fn reset(&mut self) {
+        self.boot_count = 0;
+        self.test = 0;
+        self.stage = 0;
+        self.reboots = 0;
+        self.data = [0; 4];
+        self.records = [Record { outcome: 0, message_len: 0, message: [0; MAX_MESSAGE_LEN] }; MAX_TESTS];
+        self.magic = JOURNAL_MAGIC;
+    }
+
+    fn save(&mut self) {
+        self.checksum = self.compute_checksum();
+    }
+
+    fn finish_test(&mut self, result: &TestResult) {
+        let record = &mut self.records[self.test as usize];
+        let (outcome, message) = match result {
+            TestResult::Ok => (Outcome::Passed, ""),
+            TestResult::Failure(e) => (Outcome::Failed, *e),
+            TestResult::Ignored => (Outcome::Ignored, ""),
+        };
+        let mut len = message.len().min(MAX_MESSAGE_LEN);
+        while !message.is_char_boundary(len) {
+            len -= 1;
+        }
+        record.outcome = outcome as u8;
+        record.message_len = len as u8;
+        record.message[..len].copy_from_slice(&message.as_bytes()[..len]);
+        self.test += 1;
+        self.stage = 0;
+        self.reboots = 0;
+        self.data = [0; 4];
+        self.save();
+    }
+}
+
+/// Reserves the journal page, so the frame allocator does not hand it out.
+/// # Errors
+/// Returns an error if the page is already reserved.
+pub fn reserve() -> Result<(), ReserveError> {
+    regions::reserve(JOURNAL_ADDR..JOURNAL_ADDR + 4096, "test journal")
+}
+
+fn journal() -> &'static mut Journal {
+    let addr = PHYSICAL_MEMORY_OFFSET as u64 + JOURNAL_ADDR;
+    // Safety: the page is identity mapped, reserved by `reserve`, and only used here.
+    unsafe { &mut *(addr as *mut Journal) }
+}
+
+/// Runs `tests`, resuming where the previous boot left off. Rebooting as often as they ask.
+/// 
+/// Returns once all of them are done, after reporting the results to serial, and clearing the
+/// journal so the next boot starts over. Returns wether all of them passed.
+/// 
+/// [`reserve`] must have been called.
+/// # Panics
+/// Panics if there are more than [`MAX_TESTS`] tests.
+pub fn run(tests: &[Persistent]) -> bool {
+    assert!(tests.len() <= MAX_TESTS, "too many persistent tests");
+    let journal = journal();
+    if !journal.is_valid() || journal.test as usize > tests.len() {
+        journal.reset();
+    }
+    journal.boot_count += 1;
+    journal.save();
+    if journal.boot_count > 1 {
+        serial_println!("Resuming persistent tests, boot {}.", journal.boot_count);
+    }
+
+    while let Some(test) = tests.get(journal.test as usize) {
+        let mut data = journal.data;
+        let step = (test.run)(&mut Context { stage: journal.stage, boot_count: journal.boot_count, data: &mut data });
+        journal.data = data;
+        match step {
+            Step::Reboot if journal.reboots >= MAX_REBOOTS => {
+                journal.finish_test(&TestResult::Failure("too many reboots"));
+            }
+            Step::Reboot => {
+                journal.stage += 1;
+                journal.reboots += 1;
+                journal.save();
+                serial_println!("{} rebooting for stage {}.", test.name, journal.stage);
+                power::reboot();
+            }
+            Step::Done(result) => journal.finish_test(&result),
+        }
+    }
+
+    serial_println!("Ran {} Persistent Tests:", tests.len());
+    let mut passed = true;
+    for (test, record) in tests.iter().zip(&journal.records) {
+        let mut message = ArrayString::<MAX_MESSAGE_LEN>::new();
+        let bytes = &record.message[..usize::from(record.message_len).min(MAX_MESSAGE_LEN)];
+        let _ = message.push_str(core::str::from_utf8(bytes).unwrap_or("<invalid message>"));
+        match record.outcome {
+            o if o == Outcome::Passed as u8 => serial_println!("{}: [OK]", test.name),
+            o if o == Outcome::Ignored as u8 => serial_println!("{}: [IGNORED]", test.name),
+            _ => {
+                passed = false;
+                serial_println!("{}: [FAIL]", test.name);
+                serial_println!(" => {}", message);
+            }
+        }
+    }
+
+    // the next boot starts over.
+    journal.magic = 0;
+    ALL_PASSED.store(passed, Ordering::Relaxed);
+    passed
+}
+
+/// Fails if any persistent test failed, so they count towards the result of the test run.
+pub fn test_persistent_passed(_: TestInfo) -> TestResult {
+    TestResult::assertion(ALL_PASSED.load(Ordering::Relaxed), "a persistent test failed, see above")
+}
+
+/// Tests that the machine reboots, and the journal survives it.
+pub fn test_reboot(ctx: &mut Context) -> Step {
+    match ctx.stage {
+        0 => {
+            ctx.data[0] = u64::from(ctx.boot_count);
+            Step::Reboot
+        }
+        _ => Step::Done(TestResult::assertion(
+            u64::from(ctx.boot_count) == ctx.data[0] + 1,
+            "boot count did not increase by one across the reboot",
+        )),
+    }
+}
@@ -0,0 +1,85 @@
+//! Timing tests and benchmarks.
+//! 
+//! Durations are measured with the TSC, in cycles, as the kernel does not know the TSC frequency.
+use core::fmt::{self, Display};
+
+use crate::collections::ArrayVec;
+
+/// Reads the time stamp counter.
+pub fn now() -> u64 {
+    // Safety: rdtsc has no side effects.
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Runs `f`, returning its result, and how many cycles it took.
+pub fn timed<R>(f: impl FnOnce() -> R) -> (R, u64) {
+    let start = now();
+    let ret = f();
+    (ret, now().wrapping_sub(start))
+}
+
+/// Maximum amount of samples kept by [`bench`]
+pub const MAX_SAMPLES: usize = 256;
+
+/// Statistics of repeated measurements, in cycles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// The amount of samples.
+    pub samples: usize,
+    /// The fastest sample.
+    pub min: u64,
+    /// The slowest sample.
+    pub max: u64,
+    /// The mean of all samples.
+    pub mean: u64,
+    /// The median of all samples.
+    pub median: u64,
+}
+
+impl Stats {
+    /// Computes the statistics of `samples`, sorting them.
+    /// 
+    /// Returns `None` if there are no samples.
+    pub fn from_samples(samples: &mut [u64]) -> Option<Stats> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let sum: u128 = samples.iter().map(|s| u128::from(*s)).sum();
+        Some(Stats {
+            samples: samples.len(),
+            min: samples[0],
+            max: samples[samples.len() - 1],
+            // the mean of u64s always fits in a u64.
+            mean: (sum / samples.len() as u128) as u64,
+            median: samples[samples.len() / 2],
+        })
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{} {} {}] cycles (min median max), mean {} over {} samples",
+            self.min, self.median, self.max, self.mean, self.samples
+        )
+    }
+}
+
+/// Runs `f` `iterations` times (at most [`MAX_SAMPLES`]), after one warm up run, and returns the
+/// statistics of the durations.
+/// # Panics
+/// Panics if `iterations` is 0.
+pub fn bench(iterations: usize, mut f: impl FnMut()) -> Stats {
+    assert!(iterations > 0, "a benchmark needs at least one iteration");
+    f();
+    let mut samples = ArrayVec::<u64, MAX_SAMPLES>::new();
+    for _ in 0..iterations.min(MAX_SAMPLES) {
+        let ((), cycles) = timed(&mut f);
+        // can not fail, the iterations are limited to the capacity.
+        let _ = samples.push(cycles);
+    }
+    // can not fail, there is at least one sample.
+    Stats::from_samples(&mut samples).unwrap()
+}
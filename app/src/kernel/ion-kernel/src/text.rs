@@ -36,6 +36,16 @@ impl ColorCode {
         ColorCode((background as u8) << 4 | (foreground as u8))
     }
 
+    /// Returns the raw attribute byte, as stored in the VGA Buffer.
+    pub fn into_inner(self) -> u8 {
+        self.0
+    }
+
+    /// Swaps the foreground and background, used to highlight text.
+    pub fn inverted(self) -> ColorCode {
+        ColorCode(self.0.rotate_left(4))
+    }
+
     /// Returned As (fore, back)
     pub fn tupled(self) -> (Color, Color) {
         let combined_value = self.0;
@@ -58,8 +68,10 @@ struct ScreenChar {
     color_code: ColorCode,
 }
 
-const BUFFER_HEIGHT: usize = 25;
-const BUFFER_WIDTH: usize = 80;
+/// Rows of the VGA Buffer.
+pub const BUFFER_HEIGHT: usize = 25;
+/// Columns of the VGA Buffer.
+pub const BUFFER_WIDTH: usize = 80;
 
 use volatile::Volatile;
 
@@ -69,15 +81,157 @@ struct Buffer {
     chars: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
 }
 
-/// Writer used to add text to the VGA Buffer
+/// Writer used to add text to a range of rows of the VGA Buffer
+/// 
+/// Text is written to the last row of the range, which scrolls up on a new line.
+/// 
+/// Writes go to a shadow buffer in normal memory, and only reach the VGA Buffer on [`flush`],
+/// which copies the rows that changed since the last flush. This way a burst of output is shown
+/// at once, and scrolling does not have to read back from video memory.
+/// 
+/// [`flush`]: Writer::flush
 #[derive(Debug)]
 pub struct Writer {
     column_position: usize,
     color_code: ColorCode,
-    buffer: &'static mut Buffer,
+    shadow: [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    /// Bit `n` is set if row `n` of the shadow buffer differs from the VGA Buffer.
+    dirty: u32,
+    rows: Range<usize>,
+    /// Shared between the writers of all [`Region`]s, each only touches its own rows.
+    buffer: *mut Buffer,
 }
 
+// Safety: the buffer is only accessed through `&mut self`, and writers of different regions
+// never access the same rows.
+unsafe impl Send for Writer {}
+
 impl Writer {
+    /// Creates a writer for `rows` of `buffer`, keeping their current contents.
+    /// 
+    /// # Safety
+    /// `buffer` must be valid for the lifetime of the writer, and no other writer may use `rows`.
+    unsafe fn new(buffer: *mut Buffer, rows: Range<usize>) -> Self {
+        assert!(!rows.is_empty() && rows.end <= BUFFER_HEIGHT, "invalid writer rows {rows:?}");
+        let mut shadow = [[ScreenChar { ascii_character: b' ', color_code: ColorCode(0) }; BUFFER_WIDTH]; BUFFER_HEIGHT];
+        for row in rows.clone() {
+            for (col, c) in shadow[row].iter_mut().enumerate() {
+                // Safety: the caller guarantees the buffer is valid, and the rows are ours.
+                *c = unsafe { (*buffer).chars[row][col].read() };
+            }
+        }
+        Self {
+            column_position: 0,
+            color_code: ColorCode::new(Color::White, Color::Black),
+            shadow,
+            dirty: 0,
+            rows,
+            buffer,
+        }
+    }
+
+    /// The rows of the VGA Buffer this writer uses.
+    pub fn rows(&self) -> Range<usize> {
+        self.rows.clone()
+    }
+
+    /// The row currently written to.
+    fn last_row(&self) -> usize {
+        self.rows.end - 1
+    }
+
+    /// The column of the cursor, in the row currently written to.
+    pub fn column(&self) -> usize {
+        self.column_position
+    }
+
+    /// Returns the text of the row currently written to, without trailing spaces.
+    pub fn current_line(&self) -> ArrayString<BUFFER_WIDTH> {
+        let mut line = ArrayString::new();
+        for c in &self.shadow[self.last_row()][..self.column_position.min(BUFFER_WIDTH)] {
+            // can not fail, there are at most BUFFER_WIDTH chars, and we only push ASCII.
+            let _ = line.push(if c.ascii_character.is_ascii() { char::from(c.ascii_character) } else { '?' });
+        }
+        let len = line.trim_end().len();
+        line.truncate(len);
+        line
+    }
+
+    /// Clears every row of this writer.
+    pub fn clear(&mut self) {
+        for row in self.rows() {
+            self.clear_row(row);
+        }
+        self.column_position = 0;
+    }
+
+    #[cfg(feature = "test")]
+    fn read_screen(&self, row: usize, col: usize) -> ScreenChar {
+        assert!(self.rows.contains(&row), "row {row} is not owned by this writer");
+        // Safety: the buffer is valid, and the row is ours.
+        unsafe { (*self.buffer).chars[row][col].read() }
+    }
+
+    /// Copies the rows changed since the last flush to the VGA Buffer, with the [highlight]
+    /// 
+    /// Does nothing until the VGA Buffer is [verified to be mapped](verify_vga_mapping), the rows
+    /// stay changed, and are copied by the first flush after that.
+    /// 
+    /// [highlight]: set_highlight
+    pub fn flush(&mut self) {
+        if !vga_mapped() {
+            return;
+        }
+        let (highlight, pointer) = highlight();
+        while self.dirty != 0 {
+            let row = self.dirty.trailing_zeros() as usize;
+            self.dirty &= !(1 << row);
+            for col in 0..BUFFER_WIDTH {
+                let cell = row * BUFFER_WIDTH + col;
+                let mut c = self.shadow[row][col];
+                if highlight.contains(&cell) != (pointer == Some(cell)) {
+                    c.color_code = c.color_code.inverted();
+                }
+                // Safety: the buffer is valid, and only rows we own are ever marked dirty.
+                unsafe { (*self.buffer).chars[row][col].write(c) };
+            }
+        }
+    }
+
+    /// Appends the text of `cols` of `row`, which must be ours, without trailing spaces.
+    fn read_row(&self, row: usize, cols: Range<usize>, out: &mut impl fmt::Write) -> fmt::Result {
+        let text = &self.shadow[row][cols];
+        let len = text.iter().rposition(|c| c.ascii_character != b' ').map_or(0, |i| i + 1);
+        for c in &text[..len] {
+            out.write_char(line_drawing::from_cp437(c.ascii_character).unwrap_or('?'))?;
+        }
+        Ok(())
+    }
+
+    fn redraw(&mut self) {
+        self.dirty |= ((1 << self.rows.len()) - 1) << self.rows.start;
+    }
+
+    /// Sets the color of following text.
+    pub fn set_color(&mut self, color: ColorCode) {
+        self.color_code = color;
+    }
+
+    /// The color of following text.
+    pub fn color(&self) -> ColorCode {
+        self.color_code
+    }
+
+    /// Returns wether there are changes that have not been flushed.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty != 0
+    }
+
+    fn set_char(&mut self, row: usize, col: usize, c: ScreenChar) {
+        self.shadow[row][col] = c;
+        self.dirty |= 1 << row;
+    }
+
     /// write a byte to the VGA Buffer
     /// 
     /// use [`write_char`] to write a char instead
@@ -89,11 +243,11 @@ impl Writer {
                     self.new_line();
                 }
 
-                let row = BUFFER_HEIGHT - 1;
+                let row = self.last_row();
                 let col = self.column_position;
 
                 let color_code = self.color_code;
-                self.buffer.chars[row][col].write(ScreenChar {
+                self.set_char(row, col, ScreenChar {
                     ascii_character: byte,
                     color_code,
                 });
@@ -104,11 +258,11 @@ impl Writer {
 
     /// Removes the most recent character.
     pub fn backspace(&mut self) {
-        let row = BUFFER_HEIGHT - 1;
+        let row = self.last_row();
         let col = self.column_position;
 
         self.column_position = self.column_position.saturating_sub(1);
-        self.buffer.chars[row][col].write(ScreenChar {
+        self.set_char(row, col, ScreenChar {
             ascii_character: b' ',
             color_code: self.color_code,
         });
@@ -116,7 +270,7 @@ impl Writer {
 
     /// deletes the current row.
     pub fn delete_row(&mut self) {
-        self.clear_row(BUFFER_HEIGHT - 1);
+        self.clear_row(self.last_row());
         self.column_position = 0;
     }
 
@@ -131,21 +285,19 @@ impl Writer {
             match char {
                 // printable ASCII byte or newline
                 ' '..='~' | '\n' => self.write_char(char),
-                // not part of printable ASCII range
-                _ => self.write_byte(0xfe),
+                // line-drawing characters are at other codes in CP437, the rest is shown as ■
+                _ => self.write_byte(line_drawing::to_cp437(char).unwrap_or(0xfe)),
             }
 
         }
     }
 
     fn new_line(&mut self) {
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let character = self.buffer.chars[row][col].read();
-                self.buffer.chars[row - 1][col].write(character);
-            }
-        }
-        self.clear_row(BUFFER_HEIGHT - 1);
+        let rows = self.rows();
+        self.shadow[rows.clone()].copy_within(1.., 0);
+        // every row moved, so every row has to be redrawn.
+        self.redraw();
+        self.clear_row(self.last_row());
         self.column_position = 0;
     }
 
@@ -155,12 +307,14 @@ impl Writer {
             color_code: self.color_code,
         };
         for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+            self.set_char(row, col, blank);
         }
     }
 }
 
-use core::{fmt, mem};
+use core::{fmt, mem, ops::{Deref, Range}, sync::atomic::{AtomicBool, AtomicU32, Ordering}};
+
+use crate::collections::ArrayString;
 
 impl fmt::Write for Writer {
     fn write_str(&mut self, s: &str) -> fmt::Result {
@@ -169,21 +323,126 @@ impl fmt::Write for Writer {
     }
 }
 
+/// Text on graphical frame buffers.
+pub mod framebuffer;
+/// Line-drawing characters, and their CP437 codes.
+pub mod line_drawing;
+
 // Global Writer
 
 use lazy_static::lazy_static;
-use spin::Mutex;
+use crate::sync::Mutex;
+
+/// An independently locked area of the screen.
+/// 
+/// Each region has its own [`Writer`], so printing to one region never waits on, or interleaves
+/// with, printing to another.
+#[derive(Debug)]
+pub struct Region {
+    name: &'static str,
+    rows: Range<usize>,
+    writer: Mutex<Writer>,
+}
+
+impl Region {
+    /// Creates a region for `rows` of the VGA Buffer.
+    /// 
+    /// # Safety
+    /// No other region may use `rows`.
+    unsafe fn new(name: &'static str, rows: Range<usize>) -> Self {
+        // Safety: the VGA Buffer is identity mapped and always valid, the caller guarantees the rows
+        // are unused.
+        let writer = unsafe { Writer::new(VGA_BUFFER as *mut Buffer, rows.clone()) };
+        Self { name, rows, writer: Mutex::new(name, writer) }
+    }
+
+    /// The name of this region.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Replaces the contents of this region with `args`
+    pub fn set(&self, args: fmt::Arguments) {
+        use core::fmt::Write;
+
+        crate::interrupts::stats::without_interrupts(|| {
+            let mut writer = self.writer.lock();
+            writer.clear();
+            let _ = writer.write_fmt(args);
+            writer.flush();
+        });
+    }
+
+    /// Appends `args` to this region.
+    pub fn print(&self, args: fmt::Arguments) {
+        use core::fmt::Write;
+
+        // Even though `write_fmt` always returns `Ok(())`, we are better off ignoring the value
+        // instead of panicking.
+        //
+        // this also must run without interrupts, as some of our interrupt handlers print to the
+        // VGA buffer, which could cause a deadlock if we are already printing. see
+        // https://os.phil-opp.com/hardware-interrupts/#provoking-a-deadlock
+        crate::interrupts::stats::without_interrupts(|| {
+            let mut writer = self.writer.lock();
+            let _ = writer.write_fmt(args);
+            writer.flush();
+        });
+    }
+}
+
+impl Deref for Region {
+    type Target = Mutex<Writer>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.writer
+    }
+}
 
 #[cfg(feature = "test")]
 use crate::test::{TestInfo, TestResult};
 
 lazy_static! {
-    /// The Global Writer
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
-        column_position: 0,
-        color_code: ColorCode::new(Color::White, Color::Black),
-        buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
+    /// The top row of the screen, for status information.
+    pub static ref STATUS_LINE: Region = unsafe { Region::new("status", 0..1) };
+    /// The Global Writer, the scrolling area in the middle of the screen.
+    /// 
+    /// This is where [`print`] writes to, while the [VGA console](crate::console::VgaConsole) is
+    /// active.
+    pub static ref WRITER: Region = unsafe { Region::new("main", 1..BUFFER_HEIGHT - 1) };
+    /// The bottom row of the screen, where keyboard input is echoed.
+    pub static ref INPUT_LINE: Region = unsafe { Region::new("input", BUFFER_HEIGHT - 1..BUFFER_HEIGHT) };
+}
+
+/// Physical (and virtual, it is identity mapped) address of the VGA Buffer.
+pub const VGA_BUFFER: u64 = 0xb8000;
+
+static VGA_MAPPED: AtomicBool = AtomicBool::new(false);
+
+/// Checks that the VGA Buffer is identity mapped and writable, and lets the [`Region`]s draw to
+/// it if it is. Returns wether it is.
+/// 
+/// Until then, [`Writer::flush`] does nothing, and early prints go to the
+/// [early console](crate::console::EarlyConsole), so a change to the boot page tables can not
+/// turn the first print into a triple fault.
+pub fn verify_vga_mapping() -> bool {
+    use x86_64::{VirtAddr, structures::paging::PageTableFlags};
+
+    let mapped = crate::mem::translate(VirtAddr::new(VGA_BUFFER)).is_some_and(|translation| {
+        translation.phys.as_u64() == VGA_BUFFER && translation.flags.contains(PageTableFlags::WRITABLE)
     });
+    VGA_MAPPED.store(mapped, Ordering::Relaxed);
+    mapped
+}
+
+/// Whether the VGA Buffer was [verified to be mapped](verify_vga_mapping)
+pub fn vga_mapped() -> bool {
+    VGA_MAPPED.load(Ordering::Relaxed)
+}
+
+/// Every region of the screen.
+pub fn regions() -> [&'static Region; 3] {
+    [&STATUS_LINE, &WRITER, &INPUT_LINE]
 }
 
 /// Prints the passed in text, without a newline at the end
@@ -224,10 +483,9 @@ cfg_if::cfg_if! {
     }
 }
 
-/// sets the global print color
+/// sets the global print color, if the active console supports colors.
 pub fn set_print_color(fore: Color, back: Color) {
-    WRITER.lock().color_code = ColorCode::new(fore, back);
-    // lock is dropped here, WRITER is released for future use.
+    crate::console::active().set_color(ColorCode::new(fore, back));
 }
 
 /// resets the global print color to White on Black
@@ -238,23 +496,77 @@ pub fn reset_print_color() {
 /// Gets the global print color
 #[allow(unused)]
 pub fn query_print_color() -> ColorCode {
-    WRITER.lock().color_code
+    crate::console::active().color()
 }
 
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
-    use core::fmt::Write;
-    use x86_64::instructions::interrupts;
+    crate::console::active().write_args(args);
+}
 
-    // Even though `write_fmt` always returns `Ok(())`, we are better off ignoring the value instead of
-    // panicking.
-    //
-    // this also must run without interrupts, as some of our interrupt handlers print to the VGA
-    // buffer, which could cause a deadlock if we are already printing. see 
-    // https://os.phil-opp.com/hardware-interrupts/#provoking-a-deadlock
-    let _ = interrupts::without_interrupts(|| {
-        WRITER.lock().write_fmt(args)
-    });
+/// Replaces the contents of the [`STATUS_LINE`]
+pub macro set_status($($arg:tt)*) {
+    $crate::text::STATUS_LINE.set(format_args!($($arg)*))
+}
+
+/// Flushes the writers of all regions which are not in use.
+/// 
+/// This is called by the timer interrupt, so writes which do not go through [`print`] (such as
+/// [`Writer::backspace`]) still show up, and so does a changed [highlight](set_highlight).
+pub fn flush() {
+    let redraw = REDRAW.swap(false, Ordering::Relaxed);
+    for region in regions() {
+        if let Some(mut writer) = region.try_lock() {
+            if redraw {
+                writer.redraw();
+            }
+            writer.flush();
+        } else if redraw {
+            // try again next time.
+            REDRAW.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Packed highlighted cells, `start << 16 | end`
+static HIGHLIGHT: AtomicU32 = AtomicU32::new(0);
+/// The cell of the mouse pointer, [`u32::MAX`] if there is none.
+static POINTER: AtomicU32 = AtomicU32::new(u32::MAX);
+/// The highlight changed, so every row has to be drawn again.
+static REDRAW: AtomicBool = AtomicBool::new(false);
+
+/// The highlighted cells, and the pointer cell, see [`set_highlight`]
+pub fn highlight() -> (Range<usize>, Option<usize>) {
+    let packed = HIGHLIGHT.load(Ordering::Relaxed);
+    let pointer = POINTER.load(Ordering::Relaxed);
+    ((packed >> 16) as usize..(packed & 0xFFFF) as usize, (pointer != u32::MAX).then_some(pointer as usize))
+}
+
+/// Highlights `cells` (counted row by row, from the top left), and the `pointer` cell, with
+/// inverted colors.
+/// 
+/// The highlight is only drawn, it does not change the text, and is shown on the next [`flush`]
+pub fn set_highlight(cells: Range<usize>, pointer: Option<usize>) {
+    let cells = cells.start.min(BUFFER_WIDTH * BUFFER_HEIGHT)..cells.end.min(BUFFER_WIDTH * BUFFER_HEIGHT);
+    HIGHLIGHT.store((cells.start as u32) << 16 | cells.end as u32, Ordering::Relaxed);
+    POINTER.store(pointer.map_or(u32::MAX, |p| p as u32), Ordering::Relaxed);
+    REDRAW.store(true, Ordering::Relaxed);
+}
+
+/// Appends the text of `cells` of the screen, like [`set_highlight`]
+/// 
+/// Trailing spaces of rows are dropped, and rows are separated by newlines.
+pub fn read_screen(cells: Range<usize>, out: &mut impl fmt::Write) -> fmt::Result {
+    for row in cells.start / BUFFER_WIDTH..cells.end.div_ceil(BUFFER_WIDTH).min(BUFFER_HEIGHT) {
+        let start = cells.start.max(row * BUFFER_WIDTH) - row * BUFFER_WIDTH;
+        let end = cells.end.min((row + 1) * BUFFER_WIDTH) - row * BUFFER_WIDTH;
+        if row != cells.start / BUFFER_WIDTH {
+            out.write_char('\n')?;
+        }
+        let Some(region) = regions().into_iter().find(|region| region.rows.contains(&row)) else { continue };
+        x86_64::instructions::interrupts::without_interrupts(|| region.lock().read_row(row, start..end, out))?;
+    }
+    Ok(())
 }
 
 // test
@@ -271,10 +583,29 @@ pub fn test_println_output(_: TestInfo) -> TestResult {
     interrupts::without_interrupts(|| {
         let mut writer = WRITER.lock();
         writeln!(writer, "\n{}", s).expect("writeln failed");
+        writer.flush();
+        let row = writer.last_row() - 1;
         for (i, c) in s.chars().enumerate() {
-            let screen_char = writer.buffer.chars[BUFFER_HEIGHT - 2][i].read();
+            let screen_char = writer.read_screen(row, i);
             assert_eq!(char::from(screen_char.ascii_character), c);
         }
     });
     TestResult::Ok
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "test")]
+/// Tests that printing to one region does not touch the others.
+pub fn test_regions(_: TestInfo) -> TestResult {
+    use crate::test::test_assert_eq;
+
+    set_status!("status");
+    for i in 0..BUFFER_HEIGHT {
+        println!("scrolling {i}");
+    }
+    let status = STATUS_LINE.lock();
+    test_assert_eq!(status.current_line().as_str(), "status")?;
+    for (i, c) in "status".bytes().enumerate() {
+        test_assert_eq!(status.read_screen(0, i).ascii_character, c)?;
+    }
+    TestResult::Ok
+}
@@ -0,0 +1,451 @@
+//! Text on a graphical frame buffer, for machines without VGA text mode (such as UEFI, with GOP).
+//! 
+//! The bootloader keeps VGA text mode when it can, the kernel asks for it in its multiboot header.
+//! Otherwise it passes a graphical [frame buffer](crate::c_lib::FrameBufferInfo), which [`init`]
+//! maps and sets up as the `framebuffer` [console](crate::console), drawing text with an 8x8
+//! [font].
+//! 
+//! [`FrameBuffer`] plots pixels and glyphs, [`FrameBufferWriter`] keeps a cursor on top of it, and
+//! implements [`fmt::Write`]
+//! 
+//! The [panic screen](crate::panic::screen) draws to the frame buffer through [`steal`], without
+//! locking [`FRAME_BUFFER`]
+use core::{fmt, ptr::NonNull};
+
+use spin::Mutex;
+use x86_64::{PhysAddr, VirtAddr, structures::paging::PageTableFlags};
+
+use crate::{c_lib::{BootInfo, FrameBufferInfo, PixelFormat}, log::warn, mem::vmm::{self, VmmError}, sync::InterruptSafeOnceCell, text::{Color, ColorCode}};
+
+pub mod font;
+
+use font::{GLYPH_HEIGHT, GLYPH_WIDTH};
+
+/// A color, with 8 bits per channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+    /// Red
+    pub r: u8,
+    /// Green
+    pub g: u8,
+    /// Blue
+    pub b: u8,
+}
+
+impl Rgb {
+    /// Creates a new [`Rgb`] color.
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+impl From<Color> for Rgb {
+    /// The color of the standard VGA palette.
+    fn from(color: Color) -> Self {
+        let (r, g, b) = match color {
+            Color::Black => (0, 0, 0),
+            Color::Blue => (0, 0, 170),
+            Color::Green => (0, 170, 0),
+            Color::Cyan => (0, 170, 170),
+            Color::Red => (170, 0, 0),
+            Color::Magenta => (170, 0, 170),
+            Color::Brown => (170, 85, 0),
+            Color::LightGray => (170, 170, 170),
+            Color::DarkGray => (85, 85, 85),
+            Color::LightBlue => (85, 85, 255),
+            Color::LightGreen => (85, 255, 85),
+            Color::LightCyan => (85, 255, 255),
+            Color::LightRed => (255, 85, 85),
+            Color::Pink => (255, 85, 255),
+            Color::Yellow => (255, 255, 85),
+            Color::White => (255, 255, 255),
+        };
+        Self::new(r, g, b)
+    }
+}
+
+/// An error while setting up the frame buffer.
+#[derive(Debug)]
+pub enum FrameBufferError {
+    /// The bootloader did not pass a graphical frame buffer.
+    Missing,
+    /// The pixels are not direct color, with 16, 24 or 32 bits each.
+    Unsupported(PixelFormat, u8),
+    /// The frame buffer could not be mapped.
+    Map(VmmError),
+}
+
+impl fmt::Display for FrameBufferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "there is no graphical frame buffer"),
+            Self::Unsupported(format, bpp) => write!(f, "unsupported pixel format {format:?}, with {bpp} bits per pixel"),
+            Self::Map(e) => write!(f, "could not map the frame buffer: {e}"),
+        }
+    }
+}
+
+/// A graphical frame buffer.
+#[derive(Debug)]
+pub struct FrameBuffer {
+    /// The viewport drawn to.
+    info: FrameBufferInfo,
+    red: (u8, u8),
+    green: (u8, u8),
+    blue: (u8, u8),
+    buffer: NonNull<u8>,
+    /// The whole frame buffer, the viewport is centered in it.
+    screen: FrameBufferInfo,
+    base: NonNull<u8>,
+}
+
+// Safety: the frame buffer is only accessed through `&mut self`
+unsafe impl Send for FrameBuffer {}
+
+impl FrameBuffer {
+    /// Creates a new [`FrameBuffer`], drawing to `buffer`
+    /// # Errors
+    /// Returns [`FrameBufferError::Unsupported`] if the pixels are not direct color, with 16, 24
+    /// or 32 bits each.
+    /// # Safety
+    /// `buffer` must be valid for writes of [`FrameBufferInfo::size`] bytes, for as long as the
+    /// frame buffer is used, and only be accessed through it.
+    pub unsafe fn new(info: FrameBufferInfo, buffer: NonNull<u8>) -> Result<Self, FrameBufferError> {
+        let [red, green, blue] = Self::channels(&info)?;
+        Ok(Self { info, red, green, blue, buffer, screen: info, base: buffer })
+    }
+
+    /// The position and size of red, green and blue.
+    fn channels(info: &FrameBufferInfo) -> Result<[(u8, u8); 3], FrameBufferError> {
+        let PixelFormat::Rgb { red, green, blue } = info.format else {
+            return Err(FrameBufferError::Unsupported(info.format, info.bpp));
+        };
+        let fits = u64::from(info.bpp.div_ceil(8)) * u64::from(info.width) <= u64::from(info.pitch);
+        if !matches!(info.bpp, 16 | 24 | 32) || !fits {
+            return Err(FrameBufferError::Unsupported(info.format, info.bpp));
+        }
+        Ok([red, green, blue])
+    }
+
+    /// The viewport drawn to, the whole frame buffer unless [`set_viewport`](Self::set_viewport)
+    /// made it smaller.
+    pub fn info(&self) -> &FrameBufferInfo {
+        &self.info
+    }
+
+    /// The whole frame buffer, as set up by the bootloader.
+    pub fn screen(&self) -> &FrameBufferInfo {
+        &self.screen
+    }
+
+    /// Draws to a `width` by `height` viewport, centered in the frame buffer, from now on.
+    /// 
+    /// Clears the whole frame buffer to black, and returns `false` if the viewport does not fit.
+    pub fn set_viewport(&mut self, width: usize, height: usize) -> bool {
+        let (screen_width, screen_height) = (self.screen.width as usize, self.screen.height as usize);
+        if width == 0 || height == 0 || width > screen_width || height > screen_height {
+            return false;
+        }
+        (self.info, self.buffer) = (self.screen, self.base);
+        self.fill_rect(0, 0, screen_width, screen_height, Rgb::new(0, 0, 0));
+
+        let (x, y) = ((screen_width - width) / 2, (screen_height - height) / 2);
+        let offset = y * self.screen.pitch as usize + x * self.bytes_per_pixel();
+        // Safety: the offset is in the frame buffer, as the viewport fits.
+        self.buffer = unsafe { self.base.add(offset) };
+        self.info = FrameBufferInfo {
+            addr: self.screen.addr + offset as u64,
+            width: width as u32,
+            height: height as u32,
+            ..self.screen
+        };
+        true
+    }
+
+    /// Width, in pixels.
+    pub fn width(&self) -> usize {
+        self.info.width as usize
+    }
+
+    /// Height, in pixels.
+    pub fn height(&self) -> usize {
+        self.info.height as usize
+    }
+
+    fn bytes_per_pixel(&self) -> usize {
+        self.info.bpp.div_ceil(8) as usize
+    }
+
+    /// The value stored for `color`
+    pub fn encode(&self, color: Rgb) -> u32 {
+        let channel = |value: u8, (position, size): (u8, u8)| {
+            let size = size.min(8);
+            (u32::from(value) >> (8 - size)) << position
+        };
+        channel(color.r, self.red) | channel(color.g, self.green) | channel(color.b, self.blue)
+    }
+
+    /// The stored value of the pixel at `x`, `y`, `None` if it is outside of the frame buffer.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<u32> {
+        if x >= self.width() || y >= self.height() {
+            return None;
+        }
+        let offset = y * self.info.pitch as usize + x * self.bytes_per_pixel();
+        let mut value = 0;
+        for i in 0..self.bytes_per_pixel() {
+            // Safety: the pixel is in the frame buffer, which is valid.
+            value |= u32::from(unsafe { self.buffer.add(offset + i).read_volatile() }) << (i * 8);
+        }
+        Some(value)
+    }
+
+    /// Sets the pixel at `x`, `y` to `color`, pixels outside of the frame buffer are ignored.
+    pub fn plot(&mut self, x: usize, y: usize, color: Rgb) {
+        if x >= self.width() || y >= self.height() {
+            return;
+        }
+        let value = self.encode(color);
+        let offset = y * self.info.pitch as usize + x * self.bytes_per_pixel();
+        // Safety: the pixel is in the frame buffer, which is valid.
+        unsafe {
+            let pixel = self.buffer.add(offset);
+            match self.info.bpp {
+                32 => pixel.cast::<u32>().write_volatile(value),
+                16 => pixel.cast::<u16>().write_volatile(value as u16),
+                _ => (0..3).for_each(|i| pixel.add(i).write_volatile((value >> (i * 8)) as u8)),
+            }
+        }
+    }
+
+    /// Fills a rectangle with `color`, clipped to the frame buffer.
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: Rgb) {
+        for y in y..(y + height).min(self.height()) {
+            for x in x..(x + width).min(self.width()) {
+                self.plot(x, y, color);
+            }
+        }
+    }
+
+    /// Draws the [glyph](font::glyph) of `c`, with its top left corner at `x`, `y`
+    pub fn draw_glyph(&mut self, x: usize, y: usize, c: char, fore: Rgb, back: Rgb) {
+        for (dy, row) in font::glyph(c).iter().enumerate() {
+            for dx in 0..GLYPH_WIDTH {
+                let color = if row & (1 << dx) != 0 { fore } else { back };
+                self.plot(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    /// Moves everything up by `lines` rows of pixels, filling the bottom with `back`
+    pub fn scroll_up(&mut self, lines: usize, back: Rgb) {
+        let lines = lines.min(self.height());
+        let pitch = self.info.pitch as usize;
+        // row by row, a viewport does not span whole rows.
+        let len = self.width() * self.bytes_per_pixel();
+        for y in 0..self.height() - lines {
+            // Safety: both rows are in the viewport.
+            unsafe {
+                core::ptr::copy(self.buffer.add((y + lines) * pitch).as_ptr(), self.buffer.add(y * pitch).as_ptr(), len);
+            }
+        }
+        let (width, height) = (self.width(), self.height());
+        self.fill_rect(0, height - lines, width, lines, back);
+    }
+}
+
+/// Writes text to a [`FrameBuffer`], scrolling at the bottom.
+#[derive(Debug)]
+pub struct FrameBufferWriter {
+    buffer: FrameBuffer,
+    column: usize,
+    row: usize,
+    color: ColorCode,
+}
+
+impl FrameBufferWriter {
+    /// Creates a new [`FrameBufferWriter`], and clears the frame buffer.
+    pub fn new(buffer: FrameBuffer) -> Self {
+        let mut writer = Self { buffer, column: 0, row: 0, color: ColorCode::new(Color::White, Color::Black) };
+        writer.clear();
+        writer
+    }
+
+    /// The frame buffer written to.
+    pub fn buffer(&mut self) -> &mut FrameBuffer {
+        &mut self.buffer
+    }
+
+    /// Draws to a `width` by `height` viewport from now on, and lays the text out for it.
+    /// 
+    /// Clears the screen, returns `false` if the viewport does not fit.
+    pub fn set_viewport(&mut self, width: usize, height: usize) -> bool {
+        if !self.buffer.set_viewport(width, height) {
+            return false;
+        }
+        self.clear();
+        true
+    }
+
+    /// Width, in characters.
+    pub fn columns(&self) -> usize {
+        self.buffer.width() / GLYPH_WIDTH
+    }
+
+    /// Height, in characters.
+    pub fn rows(&self) -> usize {
+        self.buffer.height() / GLYPH_HEIGHT
+    }
+
+    /// The cursor, as (column, row)
+    pub fn position(&self) -> (usize, usize) {
+        (self.column, self.row)
+    }
+
+    /// Sets the color of following text.
+    pub fn set_color(&mut self, color: ColorCode) {
+        self.color = color;
+    }
+
+    /// The color of following text.
+    pub fn color(&self) -> ColorCode {
+        self.color
+    }
+
+    /// Clears the frame buffer with the background color, and moves the cursor to the top left.
+    pub fn clear(&mut self) {
+        let back = Rgb::from(self.color.tupled().1);
+        let (width, height) = (self.buffer.width(), self.buffer.height());
+        self.buffer.fill_rect(0, 0, width, height, back);
+        (self.column, self.row) = (0, 0);
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+        if self.row + 1 < self.rows() {
+            self.row += 1;
+        } else {
+            let back = Rgb::from(self.color.tupled().1);
+            self.buffer.scroll_up(GLYPH_HEIGHT, back);
+        }
+    }
+
+    /// Writes `c` at the cursor, `\n`, `\r` and backspace move the cursor instead.
+    pub fn write_char(&mut self, c: char) {
+        if self.rows() == 0 || self.columns() == 0 {
+            return;
+        }
+        match c {
+            '\n' => self.new_line(),
+            '\r' => self.column = 0,
+            '\x08' => self.column = self.column.saturating_sub(1),
+            c => {
+                if self.column >= self.columns() {
+                    self.new_line();
+                }
+                let (fore, back) = self.color.tupled();
+                let (x, y) = (self.column * GLYPH_WIDTH, self.row * GLYPH_HEIGHT);
+                self.buffer.draw_glyph(x, y, c, fore.into(), back.into());
+                self.column += 1;
+            }
+        }
+    }
+}
+
+impl fmt::Write for FrameBufferWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        s.chars().for_each(|c| self.write_char(c));
+        Ok(())
+    }
+}
+
+/// The frame buffer set up by [`init`], written to by the `framebuffer` console.
+pub static FRAME_BUFFER: Mutex<Option<FrameBufferWriter>> = Mutex::new(None);
+
+/// The frame buffer mapped by [`init`], for [`steal`]
+static MAPPED: InterruptSafeOnceCell<FrameBufferInfo> = InterruptSafeOnceCell::new();
+
+/// The whole frame buffer set up by [`init`], without locking [`FRAME_BUFFER`], `None` if the
+/// bootloader kept VGA text mode.
+/// 
+/// For the panic screen, as the panicking code may hold the lock.
+/// # Safety
+/// Nothing else may draw to the frame buffer anymore, E.g. because the kernel is halting.
+pub unsafe fn steal() -> Option<FrameBuffer> {
+    let info = *MAPPED.get()?;
+    // Safety: the frame buffer is mapped, and only drawn to by the caller from now on.
+    unsafe { FrameBuffer::new(info, NonNull::new(info.addr as *mut u8)?) }.ok()
+}
+
+/// Maps the graphical frame buffer passed by the bootloader, and makes it the active console.
+/// # Errors
+/// Returns [`FrameBufferError::Missing`] if the bootloader kept VGA text mode, or another error if
+/// the frame buffer could not be used.
+pub fn init(boot_info: &BootInfo) -> Result<(), FrameBufferError> {
+    let info = boot_info.frame_buffer.filter(FrameBufferInfo::is_graphical).ok_or(FrameBufferError::Missing)?;
+    // checked before mapping anything.
+    FrameBuffer::channels(&info)?;
+
+    // identity mapped, the boot stage already mapped the frame buffers below 1 GiB.
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+    vmm::map_phys(VirtAddr::new(info.addr), PhysAddr::new(info.addr), info.size(), flags)
+        .map_err(FrameBufferError::Map)?;
+
+    // a fault on it names the frame buffer, see `drivers::isolation`.
+    if crate::drivers::isolation::track_mmio("framebuffer", info.addr..info.addr + info.size()).is_err() {
+        warn!("The frame buffer MMIO is not tracked");
+    }
+
+    let buffer = NonNull::new(info.addr as *mut u8).ok_or(FrameBufferError::Missing)?;
+    // Safety: the frame buffer was reserved at boot, and is mapped.
+    let writer = FrameBufferWriter::new(unsafe { FrameBuffer::new(info, buffer) }?);
+    x86_64::instructions::interrupts::without_interrupts(|| *FRAME_BUFFER.lock() = Some(writer));
+    let _ = MAPPED.set(info);
+    // can not fail, the console always exists.
+    let _ = crate::console::select("framebuffer");
+    Ok(())
+}
+
+/// Tests drawing pixels and text, and scrolling.
+#[cfg(feature = "test")]
+pub fn test_framebuffer(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use alloc::vec;
+    use core::fmt::Write;
+
+    use crate::test::{test_assert, test_assert_eq};
+
+    // 8x4 characters, 32 bits per pixel, as xRGB.
+    let (width, height) = (64, 32);
+    let mut pixels = vec![0u32; width * height];
+    let info = FrameBufferInfo {
+        addr: pixels.as_mut_ptr() as u64,
+        pitch: width as u32 * 4,
+        width: width as u32,
+        height: height as u32,
+        bpp: 32,
+        format: PixelFormat::Rgb { red: (16, 8), green: (8, 8), blue: (0, 8) },
+    };
+    let buffer = NonNull::new(pixels.as_mut_ptr().cast::<u8>()).ok_or("the buffer is null")?;
+    // Safety: the buffer is `pitch * height` bytes, and only used through the frame buffer.
+    let mut fb = unsafe { FrameBuffer::new(info, buffer) }.map_err(|_| "the format is supported")?;
+    test_assert_eq!(fb.encode(Rgb::from(Color::Brown)), 0xAA5500)?;
+    fb.plot(3, 2, Rgb::new(1, 2, 3));
+    fb.plot(width, 0, Rgb::new(1, 2, 3));
+    test_assert_eq!(fb.pixel(3, 2), Some(0x010203))?;
+    test_assert_eq!(fb.pixel(width, 0), None)?;
+
+    let mut writer = FrameBufferWriter::new(fb);
+    test_assert_eq!((writer.columns(), writer.rows()), (8, 4))?;
+    test_assert_eq!(writer.buffer().pixel(3, 2), Some(0))?;
+    let _ = write!(writer, "|");
+    // the bar of `|` is in columns 3 and 4, the last row is empty.
+    test_assert_eq!(writer.buffer().pixel(3, 0), Some(0xFFFFFF))?;
+    test_assert_eq!(writer.buffer().pixel(2, 0), Some(0))?;
+    test_assert_eq!(writer.buffer().pixel(3, 7), Some(0))?;
+
+    // the bar scrolls off the top.
+    let _ = write!(writer, "\n\n\n\nabcdefghi");
+    test_assert_eq!(writer.position(), (1, 3))?;
+    test_assert!((0..GLYPH_HEIGHT).all(|y| writer.buffer().pixel(3, y) == Some(0)))?;
+    // `i` wrapped to the start of the last row.
+    test_assert_eq!(writer.buffer().pixel(2, 3 * GLYPH_HEIGHT), Some(0xFFFFFF))
+}
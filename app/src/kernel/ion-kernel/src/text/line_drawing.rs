@@ -0,0 +1,55 @@
+//! Unicode line-drawing and block characters.
+//! 
+//! The VGA text buffer uses code page 437, which has the common box-drawing and block characters,
+//! but at other codes than Unicode. [`Writer`](super::Writer) maps them with [`to_cp437`], the
+//! [frame buffer font](super::framebuffer) only has ASCII, so it draws the [`to_ascii`] fallback.
+
+/// Light horizontal line.
+pub const HORIZONTAL: char = '─';
+/// Light vertical line.
+pub const VERTICAL: char = '│';
+/// Full block, the filled part of progress bars.
+pub const FULL_BLOCK: char = '█';
+/// Light shade, the empty part of progress bars.
+pub const LIGHT_SHADE: char = '░';
+
+/// The Unicode character, its CP437 code, and the closest printable ASCII character.
+static TABLE: [(char, u8, char); 40] = [
+    ('░', 0xB0, '.'), ('▒', 0xB1, ':'), ('▓', 0xB2, '#'),
+    ('│', 0xB3, '|'), ('┤', 0xB4, '+'), ('╡', 0xB5, '+'), ('╢', 0xB6, '+'),
+    ('╖', 0xB7, '+'), ('╕', 0xB8, '+'), ('╣', 0xB9, '+'), ('║', 0xBA, '|'),
+    ('╗', 0xBB, '+'), ('╝', 0xBC, '+'), ('╜', 0xBD, '+'), ('╛', 0xBE, '+'),
+    ('┐', 0xBF, '+'), ('└', 0xC0, '+'), ('┴', 0xC1, '+'), ('┬', 0xC2, '+'),
+    ('├', 0xC3, '+'), ('─', 0xC4, '-'), ('┼', 0xC5, '+'),
+    ('╚', 0xC8, '+'), ('╔', 0xC9, '+'), ('╩', 0xCA, '+'), ('╦', 0xCB, '+'),
+    ('╠', 0xCC, '+'), ('═', 0xCD, '='), ('╬', 0xCE, '+'),
+    ('┘', 0xD9, '+'), ('┌', 0xDA, '+'),
+    ('█', 0xDB, '#'), ('▄', 0xDC, '_'), ('▌', 0xDD, '|'), ('▐', 0xDE, '|'), ('▀', 0xDF, '-'),
+    ('■', 0xFE, '#'), ('·', 0xFA, '.'), ('•', 0x07, '*'), ('°', 0xF8, 'o'),
+];
+
+/// The CP437 code of `c`, for the VGA text buffer.
+/// 
+/// Printable ASCII is the same in CP437, other characters are only mapped if they are in the table.
+pub fn to_cp437(c: char) -> Option<u8> {
+    match c {
+        ' '..='~' => Some(c as u8),
+        _ => TABLE.iter().find(|(unicode, ..)| *unicode == c).map(|&(_, code, _)| code),
+    }
+}
+
+/// The Unicode character of the CP437 `code`, if it is printable ASCII or in the table.
+pub fn from_cp437(code: u8) -> Option<char> {
+    match code {
+        b' '..=b'~' => Some(char::from(code)),
+        _ => TABLE.iter().find(|&&(_, cp437, _)| cp437 == code).map(|&(unicode, ..)| unicode),
+    }
+}
+
+/// The closest printable ASCII character to `c`, for outputs without line-drawing characters.
+pub fn to_ascii(c: char) -> Option<char> {
+    match c {
+        ' '..='~' => Some(c),
+        _ => TABLE.iter().find(|(unicode, ..)| *unicode == c).map(|&(.., ascii)| ascii),
+    }
+}
@@ -0,0 +1,361 @@
+//! Clock sources, which [`now`](super::now) reads.
+//! 
+//! Every source has a rating, at boot [`init`] selects the best available one. Once the
+//! [HPET](super::hpet) is found, [`init_late`] selects the one named by `clocksource=` on the
+//! kernel command line (E.g. `clocksource=pit`), or the best one again. It can be changed later
+//! with [`select`], and time stays monotonic when switching.
+//! 
+//! | Source | Rating | Notes                                            |
+//! |--------|--------|--------------------------------------------------|
+//! | `tsc`  | 300    | 200 if it is not invariant, calibrated at boot   |
+//! | `hpet` | 250    | from the ACPI tables, only with a 64 bit counter |
+//! | `pit`  | 110    | about 55ms resolution, always available          |
+use core::{fmt::{self, Display}, sync::atomic::{AtomicU64, Ordering}, time::Duration};
+
+use spin::RwLock;
+
+use crate::{arch::cpuid::{cpuid, max_extended_leaf, max_leaf}, collections::{ArrayVec, CapacityError}, log::{debug, info, warn}};
+use super::{PIT_DIVISOR, PIT_FREQUENCY, ticks, ticks_to_duration};
+
+/// Maximum amount of clock sources.
+pub const MAX_SOURCES: usize = 8;
+
+/// PIT ticks the TSC is calibrated over (about 220ms).
+pub const CALIBRATION_TICKS: u64 = 4;
+
+/// A monotonic clock.
+pub trait ClockSource: Sync {
+    /// The name used to select this source.
+    fn name(&self) -> &'static str;
+
+    /// How good this source is, higher is better.
+    fn rating(&self) -> u32;
+
+    /// Whether the source can be used.
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    /// Time since some point, which does not change.
+    fn now(&self) -> Duration;
+
+    /// Whether the source only advances while interrupts are enabled.
+    fn needs_interrupts(&self) -> bool {
+        false
+    }
+}
+
+impl fmt::Debug for dyn ClockSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ClockSource").field(&self.name()).finish()
+    }
+}
+
+/// The PIT, counted by the timer interrupt.
+#[derive(Debug, Clone, Copy)]
+pub struct PitClock;
+
+impl ClockSource for PitClock {
+    fn name(&self) -> &'static str {
+        "pit"
+    }
+
+    fn rating(&self) -> u32 {
+        110
+    }
+
+    fn now(&self) -> Duration {
+        ticks_to_duration(ticks())
+    }
+
+    fn needs_interrupts(&self) -> bool {
+        true
+    }
+}
+
+/// The time stamp counter, once its frequency is known.
+#[derive(Debug)]
+pub struct TscClock {
+    freq_hz: AtomicU64,
+    base: AtomicU64,
+}
+
+impl TscClock {
+    /// The frequency in Hz, 0 if it is not known yet.
+    pub fn frequency(&self) -> u64 {
+        self.freq_hz.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether the TSC runs at a constant rate, regardless of power states.
+    pub fn is_invariant(&self) -> bool {
+        max_extended_leaf() >= 0x8000_0007 && cpuid(0x8000_0007, 0).edx & (1 << 8) != 0
+    }
+
+    /// Sets the frequency, and starts counting from now.
+    fn start(&self, freq_hz: u64) {
+        // Safety: the TSC is present, the frequency is only known if it is.
+        self.base.store(unsafe { core::arch::x86_64::_rdtsc() }, Ordering::Relaxed);
+        self.freq_hz.store(freq_hz, Ordering::Release);
+    }
+}
+
+impl ClockSource for TscClock {
+    fn name(&self) -> &'static str {
+        "tsc"
+    }
+
+    fn rating(&self) -> u32 {
+        if self.is_invariant() { 300 } else { 200 }
+    }
+
+    fn is_available(&self) -> bool {
+        self.frequency() != 0
+    }
+
+    fn now(&self) -> Duration {
+        let freq = self.freq_hz.load(Ordering::Acquire);
+        if freq == 0 {
+            return Duration::ZERO;
+        }
+        // Safety: see `start`
+        let cycles = unsafe { core::arch::x86_64::_rdtsc() }.wrapping_sub(self.base.load(Ordering::Relaxed));
+        Duration::from_nanos((cycles as u128 * 1_000_000_000 / freq as u128) as u64)
+    }
+}
+
+/// The TSC clock source.
+pub static TSC: TscClock = TscClock { freq_hz: AtomicU64::new(0), base: AtomicU64::new(0) };
+
+#[derive(Debug)]
+struct State {
+    sources: ArrayVec<&'static dyn ClockSource, MAX_SOURCES>,
+    active: &'static dyn ClockSource,
+    /// Added to the active source, so time does not jump when switching.
+    offset_nanos: i64,
+}
+
+static STATE: RwLock<State> = RwLock::new(State { sources: ArrayVec::new(), active: &PitClock, offset_nanos: 0 });
+
+/// Time of the active clock source.
+pub fn now() -> Duration {
+    let state = STATE.read();
+    let nanos = state.active.now().as_nanos() as i64 + state.offset_nanos;
+    Duration::from_nanos(nanos.max(0) as u64)
+}
+
+/// The active clock source.
+pub fn active() -> &'static dyn ClockSource {
+    STATE.read().active
+}
+
+/// Calls `f` with every registered clock source, available or not.
+pub fn for_each(mut f: impl FnMut(&'static dyn ClockSource)) {
+    // copied, so `f` may register or select sources.
+    let sources = STATE.read().sources.clone();
+    for source in sources.iter() {
+        f(*source);
+    }
+}
+
+/// Registers a clock source, without selecting it.
+/// # Errors
+/// Returns an error if there are [`MAX_SOURCES`] already.
+pub fn register(source: &'static dyn ClockSource) -> Result<(), CapacityError> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        STATE.write().sources.push(source).map_err(|_| CapacityError(()))
+    })
+}
+
+/// An error while selecting a clock source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectError<'a> {
+    /// There is no source with the name.
+    Unknown(&'a str),
+    /// The source exists, but can not be used.
+    Unavailable(&'static str),
+}
+
+impl Display for SelectError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown(name) => write!(f, "unknown clock source `{name}`"),
+            Self::Unavailable(name) => write!(f, "the clock source `{name}` is not available"),
+        }
+    }
+}
+
+/// Makes the clock source called `name` active.
+/// # Errors
+/// Returns an error if there is no such source, or it is not available.
+pub fn select(name: &str) -> Result<(), SelectError<'_>> {
+    let mut found = None;
+    for_each(|source| if source.name() == name { found = Some(source) });
+    let source = found.ok_or(SelectError::Unknown(name))?;
+    if !source.is_available() {
+        return Err(SelectError::Unavailable(source.name()));
+    }
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let current = now();
+        let mut state = STATE.write();
+        state.offset_nanos = current.as_nanos() as i64 - source.now().as_nanos() as i64;
+        state.active = source;
+    });
+    Ok(())
+}
+
+/// How much a clock source runs ahead of the active one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Drift {
+    /// The active source.
+    pub reference: &'static str,
+    /// The measured source.
+    pub other: &'static str,
+    /// Parts per million `other` runs ahead, negative if it runs behind.
+    pub ppm: i64,
+}
+
+impl Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} drifts {:+} ppm against {}", self.other, self.ppm, self.reference)
+    }
+}
+
+/// Measures the drift of the source called `name` against the active one, over `interval`.
+/// 
+/// This sleeps, so interrupts must be enabled.
+/// # Errors
+/// Returns an error if there is no such source, or it is not available.
+pub fn measure_drift(name: &str, interval: Duration) -> Result<Drift, SelectError<'_>> {
+    let mut found = None;
+    for_each(|source| if source.name() == name { found = Some(source) });
+    let other = found.ok_or(SelectError::Unknown(name))?;
+    if !other.is_available() {
+        return Err(SelectError::Unavailable(other.name()));
+    }
+    let reference = active();
+
+    // start right after the reference changes, for coarse sources like the PIT.
+    let first = reference.now();
+    while reference.now() == first {
+        core::hint::spin_loop();
+    }
+    let (ref_start, other_start) = (reference.now(), other.now());
+    super::sleep(interval);
+    let (ref_end, other_end) = (reference.now(), other.now());
+
+    let ref_elapsed = (ref_end - ref_start).as_nanos() as i128;
+    let other_elapsed = (other_end - other_start).as_nanos() as i128;
+    let ppm = (other_elapsed - ref_elapsed) * 1_000_000 / ref_elapsed.max(1);
+    Ok(Drift { reference: reference.name(), other: other.name(), ppm: ppm as i64 })
+}
+
+/// The TSC frequency reported by CPUID leaf `0x15`, if it is reported completely.
+fn tsc_frequency_from_cpuid() -> Option<u64> {
+    if max_leaf() < 0x15 {
+        return None;
+    }
+    let res = cpuid(0x15, 0);
+    if res.eax == 0 || res.ebx == 0 || res.ecx == 0 {
+        return None;
+    }
+    Some(res.ecx as u64 * res.ebx as u64 / res.eax as u64)
+}
+
+/// Measures the TSC frequency against the PIT, between tick edges.
+fn calibrate_tsc() -> Option<u64> {
+    if !x86_64::instructions::interrupts::are_enabled() {
+        return None;
+    }
+    let wait_for_tick = |tick| while ticks() < tick {
+        x86_64::instructions::hlt();
+    };
+    wait_for_tick(ticks() + 1);
+    let start_tick = ticks();
+    // Safety: the caller checked the TSC is present.
+    let start = unsafe { core::arch::x86_64::_rdtsc() };
+    wait_for_tick(start_tick + CALIBRATION_TICKS);
+    // Safety: as above
+    let cycles = unsafe { core::arch::x86_64::_rdtsc() } - start;
+    Some(cycles * PIT_FREQUENCY / (PIT_DIVISOR * CALIBRATION_TICKS))
+}
+
+/// Registers the built in sources, calibrates the TSC, and selects the best source.
+/// 
+/// Must be called after interrupts are enabled.
+pub fn init() {
+    // can not fail, there are no other sources yet.
+    let _ = register(&PitClock);
+    let _ = register(&TSC);
+
+    if cpuid(1, 0).edx & (1 << 4) != 0 {
+        match tsc_frequency_from_cpuid().or_else(calibrate_tsc) {
+            Some(freq) => {
+                TSC.start(freq);
+                debug!("TSC runs at {} kHz (invariant: {})", freq / 1000, TSC.is_invariant());
+            }
+            None => debug!("The TSC could not be calibrated"),
+        }
+    }
+
+    select_best();
+}
+
+/// Selects the available source with the highest rating.
+fn select_best() {
+    let mut best: Option<&'static dyn ClockSource> = None;
+    for_each(|source| {
+        if source.is_available() && best.is_none_or(|b| source.rating() > b.rating()) {
+            best = Some(source);
+        }
+    });
+    if let Some(best) = best.filter(|best| best.name() != active().name()) {
+        // can not fail, the source is available.
+        let _ = select(best.name());
+        info!("Using the `{}` clock source.", best.name());
+    }
+}
+
+/// Registers the [HPET](super::hpet) if there is one, then selects the source named by
+/// `clocksource=` on the kernel command line, or the best one.
+/// 
+/// Must be called after ACPI and the virtual memory manager are initialized.
+pub fn init_late() {
+    match super::hpet::init() {
+        Ok(()) => debug!("HPET runs at {} kHz", super::hpet::HPET.frequency() / 1000),
+        Err(e) => debug!("The HPET is unavailable: {e}"),
+    }
+    match crate::boot::cmdline::command_line().get("clocksource") {
+        Some(name) => match select(name) {
+            Ok(()) => info!("Using the `{name}` clock source."),
+            Err(e) => {
+                warn!("Ignoring the clocksource= argument: {e}");
+                select_best();
+            }
+        },
+        None => select_best(),
+    }
+}
+
+/// Tests that switching sources keeps time monotonic.
+#[cfg(feature = "test")]
+pub fn test_clocksource(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{TestResult, test_assert, test_assert_eq};
+
+    let previous = active().name();
+    test_assert!(matches!(select("sundial"), Err(SelectError::Unknown("sundial"))))?;
+
+    for other in ["pit", "hpet"] {
+        if other == "hpet" && !super::hpet::HPET.is_available() {
+            test_assert!(matches!(select(other), Err(SelectError::Unknown(_) | SelectError::Unavailable(_))))?;
+            continue;
+        }
+        let before = now();
+        test_assert!(select(other).is_ok())?;
+        let during = now();
+        test_assert!(select(previous).is_ok())?;
+        let after = now();
+        test_assert_eq!(active().name(), previous)?;
+        test_assert!(before <= during && during <= after)?;
+    }
+    TestResult::Ok
+}
@@ -0,0 +1,150 @@
+//! The High Precision Event Timer, as a [clock source](super::clocksource).
+//! 
+//! Its registers are found through the ACPI `HPET` table. Only the main counter is used, which
+//! counts up at a fixed rate of at least 10 MHz from the moment it is enabled. Counters which are
+//! only 32 bits wide wrap within minutes, so they are not used.
+use core::{fmt::{self, Display}, sync::atomic::{AtomicU64, Ordering}, time::Duration};
+
+use x86_64::{PhysAddr, VirtAddr, structures::paging::PageTableFlags};
+
+use super::clocksource::{self, ClockSource};
+use crate::{acpi, mem::vmm::{self, VmmError}};
+
+/// The register block, in the Generic Address Structure at byte 40 of the table.
+const TABLE_ADDRESS: usize = 44;
+
+const REG_CAPABILITIES: u64 = 0x00;
+const REG_CONFIG: u64 = 0x10;
+const REG_COUNTER: u64 = 0xF0;
+
+/// The main counter is 64 bits wide.
+const CAP_COUNTER_64: u64 = 1 << 13;
+/// The main counter runs.
+const CONFIG_ENABLE: u64 = 1 << 0;
+/// The longest period the specification allows, 100ns.
+const MAX_PERIOD_FS: u64 = 100_000_000;
+
+/// Why the HPET can not be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HpetError {
+    /// There is no ACPI `HPET` table.
+    NoTable,
+    /// The main counter is only 32 bits wide.
+    Counter32,
+    /// The counter period is 0, or longer than the specification allows.
+    BadPeriod(u64),
+    /// The registers could not be mapped.
+    Map(VmmError),
+}
+
+impl Display for HpetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoTable => write!(f, "there is no ACPI HPET table"),
+            Self::Counter32 => write!(f, "the HPET counter is only 32 bits wide"),
+            Self::BadPeriod(period) => write!(f, "the HPET counter period of {period} fs is not valid"),
+            Self::Map(e) => write!(f, "the HPET registers could not be mapped: {e}"),
+        }
+    }
+}
+
+impl core::error::Error for HpetError {}
+
+/// The HPET main counter, once [`init`] found it.
+#[derive(Debug)]
+pub struct HpetClock {
+    /// The identity mapped registers, 0 until [`init`]
+    base: AtomicU64,
+    /// Femtoseconds per count.
+    period_fs: AtomicU64,
+}
+
+impl HpetClock {
+    /// # Safety
+    /// The registers must be mapped.
+    unsafe fn read(&self, reg: u64) -> u64 {
+        // Safety: forwarded from the caller, the registers are 64 bits wide and aligned.
+        unsafe { core::ptr::read_volatile((self.base.load(Ordering::Acquire) + reg) as *const u64) }
+    }
+
+    /// # Safety
+    /// The registers must be mapped.
+    unsafe fn write(&self, reg: u64, value: u64) {
+        // Safety: as above
+        unsafe { core::ptr::write_volatile((self.base.load(Ordering::Acquire) + reg) as *mut u64, value) }
+    }
+
+    /// The counter frequency in Hz, 0 if it is not known yet.
+    pub fn frequency(&self) -> u64 {
+        match self.period_fs.load(Ordering::Relaxed) {
+            0 => 0,
+            period => 1_000_000_000_000_000 / period,
+        }
+    }
+}
+
+impl ClockSource for HpetClock {
+    fn name(&self) -> &'static str {
+        "hpet"
+    }
+
+    fn rating(&self) -> u32 {
+        250
+    }
+
+    fn is_available(&self) -> bool {
+        self.period_fs.load(Ordering::Acquire) != 0
+    }
+
+    fn now(&self) -> Duration {
+        let period = self.period_fs.load(Ordering::Acquire);
+        if period == 0 {
+            return Duration::ZERO;
+        }
+        // Safety: the period is only set once the registers are mapped.
+        let counts = unsafe { self.read(REG_COUNTER) };
+        Duration::from_nanos((counts as u128 * period as u128 / 1_000_000) as u64)
+    }
+}
+
+/// The HPET clock source.
+pub static HPET: HpetClock = HpetClock { base: AtomicU64::new(0), period_fs: AtomicU64::new(0) };
+
+/// Finds the HPET, starts its main counter, and registers it as a clock source, without
+/// selecting it.
+/// 
+/// Must be called after ACPI and the [virtual memory manager](vmm) are initialized.
+/// # Errors
+/// Returns an error if there is no usable HPET.
+pub fn init() -> Result<(), HpetError> {
+    if HPET.is_available() {
+        return Ok(());
+    }
+    let table = acpi::find(*b"HPET").ok_or(HpetError::NoTable)?;
+    let addr = table.get(TABLE_ADDRESS..TABLE_ADDRESS + 8)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(HpetError::NoTable)?;
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+    vmm::map_phys(VirtAddr::new(addr), PhysAddr::new(addr), REG_COUNTER + 8, flags).map_err(HpetError::Map)?;
+    HPET.base.store(addr, Ordering::Release);
+
+    // Safety: the registers were mapped above.
+    let capabilities = unsafe { HPET.read(REG_CAPABILITIES) };
+    if capabilities & CAP_COUNTER_64 == 0 {
+        return Err(HpetError::Counter32);
+    }
+    let period = capabilities >> 32;
+    if period == 0 || period > MAX_PERIOD_FS {
+        return Err(HpetError::BadPeriod(period));
+    }
+    // Safety: as above, enabling only starts the main counter, the timers stay disabled.
+    unsafe {
+        let config = HPET.read(REG_CONFIG);
+        HPET.write(REG_CONFIG, config | CONFIG_ENABLE);
+    }
+    HPET.period_fs.store(period, Ordering::Release);
+    // can not fail unless there are MAX_SOURCES, then the HPET is just not used.
+    let _ = clocksource::register(&HPET);
+    Ok(())
+}
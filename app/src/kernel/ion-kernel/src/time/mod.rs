@@ -0,0 +1,196 @@
+//! Time keeping.
+//! 
+//! The PIT is left at its default rate, so a tick is about 55ms. Time is read from the best
+//! [clock source](clocksource), which is usually the TSC, then the [HPET](hpet), and the PIT
+//! otherwise. Under the `test`
+//! feature, a [`TestClock`] can take over, so time only passes when the test says so.
+use core::{sync::atomic::{AtomicU64, Ordering}, time::Duration};
+
+pub mod clocksource;
+pub mod hpet;
+pub mod timer;
+#[cfg(feature = "test")]
+pub mod test_clock;
+#[cfg(feature = "test")]
+pub use test_clock::TestClock;
+pub use timer::{TimerHandle, after};
+
+/// Frequency of the PIT's oscillator, in Hz.
+pub const PIT_FREQUENCY: u64 = 1_193_182;
+/// The divisor the PIT runs with, the default one.
+pub const PIT_DIVISOR: u64 = 65536;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Counts a tick.
+/// 
+/// Called by the timer interrupt.
+pub(crate) fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Timer ticks since interrupts were enabled.
+/// 
+/// This is always the real count, even while a [`TestClock`] is installed.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Converts timer ticks to a [`Duration`]
+pub const fn ticks_to_duration(ticks: u64) -> Duration {
+    let nanos = ticks as u128 * PIT_DIVISOR as u128 * 1_000_000_000 / PIT_FREQUENCY as u128;
+    Duration::from_nanos(nanos as u64)
+}
+
+/// Converts a [`Duration`] to timer ticks, rounding up.
+pub const fn duration_to_ticks(duration: Duration) -> u64 {
+    let scale = PIT_DIVISOR as u128 * 1_000_000_000;
+    (duration.as_nanos() * PIT_FREQUENCY as u128).div_ceil(scale) as u64
+}
+
+/// Time since boot, or the time of the [`TestClock`], if one is installed.
+pub fn now() -> Duration {
+    #[cfg(feature = "test")]
+    if let Some(now) = test_clock::now() {
+        return now;
+    }
+    clocksource::now()
+}
+
+/// The `uptime` shell command, prints the time since boot.
+pub fn uptime_command(args: &[&str]) -> i32 {
+    if args.len() > 1 {
+        crate::text::println!("usage: uptime");
+        return 2;
+    }
+    let now = now();
+    let secs = now.as_secs();
+    crate::text::println!("up {}:{:02}:{:02}.{:03}", secs / 3600, secs / 60 % 60, secs % 60, now.subsec_millis());
+    0
+}
+
+/// Initializes time keeping, see [`clocksource::init`]
+/// 
+/// Must be called after interrupts are enabled.
+pub fn init() {
+    clocksource::init();
+}
+
+/// Waits for at least `duration`.
+/// 
+/// With a [`TestClock`] installed, this advances it instead, and returns right away.
+/// # Panics
+/// Panics if interrupts are disabled, as the time would never change.
+pub fn sleep(duration: Duration) {
+    #[cfg(feature = "test")]
+    if test_clock::advance(duration) {
+        return;
+    }
+    assert!(x86_64::instructions::interrupts::are_enabled(), "sleeping with interrupts disabled");
+    crate::interrupts::context::assert_not_interrupt!();
+    let timeout = Timeout::after(duration);
+    while !timeout.expired() {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Reads the current count of the PIT's channel 0, which counts down from [`PIT_DIVISOR`] at
+/// [`PIT_FREQUENCY`]
+/// 
+/// Unlike the tick count, this keeps changing while interrupts are disabled.
+pub fn pit_counter() -> u16 {
+    use x86_64::instructions::port::Port;
+
+    // Safety: latching channel 0 does not change its mode, and only the timer uses it.
+    x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+        Port::<u8>::new(0x43).write(0x00);
+        let mut data = Port::<u8>::new(0x40);
+        let low = data.read();
+        let high = data.read();
+        u16::from_le_bytes([low, high])
+    })
+}
+
+/// Returns whether the PIT's channel 0 is in square wave mode (mode 3), in which the counter
+/// decrements by 2, twice per period.
+fn pit_square_wave() -> bool {
+    use x86_64::instructions::port::Port;
+
+    // Safety: the read-back command only latches the status of channel 0.
+    let status = x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+        Port::<u8>::new(0x43).write(0b1110_0010);
+        Port::<u8>::new(0x40).read()
+    });
+    (status >> 1) & 0b11 == 0b11
+}
+
+/// Calls `poll` until it returns `Some`, or `timeout` passes.
+/// 
+/// This is for waiting on hardware. The timeout is accurate to a few microseconds, even while
+/// interrupts are disabled and the clock source depends on them: then, the PIT's counter is read
+/// directly. Called by a task with interrupts enabled, the other tasks run in between polls.
+pub fn poll_until<T>(timeout: Duration, mut poll: impl FnMut() -> Option<T>) -> Option<T> {
+    if x86_64::instructions::interrupts::are_enabled() || !clocksource::active().needs_interrupts() {
+        let timeout = Timeout::after(timeout);
+        loop {
+            if let Some(value) = poll() {
+                return Some(value);
+            }
+            if timeout.expired() {
+                return None;
+            }
+            if x86_64::instructions::interrupts::are_enabled() && !crate::interrupts::context::in_interrupt() {
+                crate::task::yield_now();
+            } else {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    let mut limit = (timeout.as_nanos() * PIT_FREQUENCY as u128).div_ceil(1_000_000_000);
+    if pit_square_wave() {
+        limit *= 2;
+    }
+    let mut elapsed = 0u128;
+    let mut last = pit_counter();
+    loop {
+        if let Some(value) = poll() {
+            return Some(value);
+        }
+        // the counter counts down, and wraps at least every tick, which is far longer than a poll.
+        let counter = pit_counter();
+        elapsed += last.wrapping_sub(counter) as u128;
+        last = counter;
+        if elapsed >= limit {
+            return None;
+        }
+    }
+}
+
+/// A point in time, after which something should stop waiting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timeout {
+    end: Duration,
+}
+
+impl Timeout {
+    /// A timeout which expires `duration` from [`now`]
+    pub fn after(duration: Duration) -> Self {
+        Self { end: now().saturating_add(duration) }
+    }
+
+    /// A timeout which never expires.
+    pub const fn never() -> Self {
+        Self { end: Duration::MAX }
+    }
+
+    /// Whether the timeout has passed.
+    pub fn expired(&self) -> bool {
+        now() >= self.end
+    }
+
+    /// Time left until the timeout, zero if it has passed.
+    pub fn remaining(&self) -> Duration {
+        self.end.saturating_sub(now())
+    }
+}
@@ -0,0 +1,99 @@
+//! A fake clock for tests.
+use core::{sync::atomic::{AtomicBool, AtomicU64, Ordering}, time::Duration};
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// The time of the clock, in nanoseconds.
+static NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// A clock which only moves with [`advance`](TestClock::advance).
+/// 
+/// While installed, [`now`](super::now), [`sleep`](super::sleep) and every
+/// [`Timeout`](super::Timeout) use it instead of the timer interrupt, so timeouts, debouncing and
+/// retransmissions can be tested without waiting. The test watchdog still uses real ticks.
+/// 
+/// Dropping it switches back to the real clock. The test runner also does that after every test,
+/// in case a test panics while it holds one.
+#[derive(Debug)]
+pub struct TestClock {
+    _private: (),
+}
+
+impl TestClock {
+    /// Installs a test clock, starting at the current time.
+    /// # Panics
+    /// Panics if a test clock is installed already.
+    pub fn install() -> Self {
+        let start = super::clocksource::now();
+        assert!(!ACTIVE.swap(true, Ordering::SeqCst), "a test clock is installed already");
+        NANOS.store(start.as_nanos() as u64, Ordering::SeqCst);
+        Self { _private: () }
+    }
+
+    /// Moves the clock forward by `ms` milliseconds.
+    pub fn advance(&self, ms: u64) {
+        self.advance_by(Duration::from_millis(ms));
+    }
+
+    /// Moves the clock forward by `duration`
+    /// 
+    /// Timers which expire are fired right away.
+    pub fn advance_by(&self, duration: Duration) {
+        advance(duration);
+    }
+
+    /// The time of the clock.
+    pub fn now(&self) -> Duration {
+        Duration::from_nanos(NANOS.load(Ordering::SeqCst))
+    }
+}
+
+impl Drop for TestClock {
+    fn drop(&mut self) {
+        uninstall();
+    }
+}
+
+/// The time of the test clock, if it is installed.
+pub(super) fn now() -> Option<Duration> {
+    ACTIVE.load(Ordering::SeqCst).then(|| Duration::from_nanos(NANOS.load(Ordering::SeqCst)))
+}
+
+/// Advances the test clock, and fires expired timers. Returns false if it is not installed.
+pub(super) fn advance(duration: Duration) -> bool {
+    if !ACTIVE.load(Ordering::SeqCst) {
+        return false;
+    }
+    let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+    // can not fail, the closure always returns Some.
+    let _ = NANOS.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| Some(n.saturating_add(nanos)));
+    super::timer::run_expired();
+    true
+}
+
+/// Switches back to the real clock.
+pub(crate) fn uninstall() {
+    ACTIVE.store(false, Ordering::SeqCst);
+}
+
+/// Tests [`TestClock`], and the time functions using it.
+pub fn test_clock(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use crate::test::{test_assert, test_assert_eq};
+    use super::{Timeout, now, sleep};
+
+    let clock = TestClock::install();
+    let start = now();
+    let timeout = Timeout::after(Duration::from_millis(100));
+    test_assert!(!timeout.expired())?;
+    clock.advance(99);
+    test_assert_eq!(timeout.remaining(), Duration::from_millis(1))?;
+    clock.advance(1);
+    test_assert!(timeout.expired())?;
+
+    // sleeping only moves the fake clock.
+    sleep(Duration::from_secs(60));
+    test_assert_eq!(now() - start, Duration::from_millis(60_100))?;
+    test_assert!(Timeout::never().remaining() > Duration::from_secs(1 << 40))?;
+
+    drop(clock);
+    test_assert!(now() < start + Duration::from_secs(60))
+}
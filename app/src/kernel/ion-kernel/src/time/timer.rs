@@ -0,0 +1,420 @@
+//! One-shot timers, with closures.
+//! 
+//! ```rust,no_run
+//! use core::time::Duration;
+//! 
+//! let handle = time::after(Duration::from_millis(500), || log::info!("half a second passed"))?;
+//! handle.cancel();
+//! ```
+//! 
+//! Timers live in a hierarchical timer wheel of [`LEVELS`] levels with [`WHEEL_SIZE`] slots each,
+//! which the timer interrupt advances. A slot of the first level is one tick, and a slot of every
+//! further level spans a whole turn of the level below. When the wheel reaches a slot of a higher
+//! level, its timers are moved down to the levels below, until they are in the first level on the
+//! tick they expire. So starting, cancelling and firing a timer takes constant time, however many
+//! are pending.
+//! 
+//! Nothing here allocates, so callbacks are stored inline, and must be at most
+//! [`MAX_CALLBACK_SIZE`] bytes. Callbacks run inside the timer interrupt, so they must be short.
+use core::{fmt, mem::{ManuallyDrop, MaybeUninit}, time::Duration};
+
+use spin::Mutex;
+
+use crate::collections::CapacityError;
+use super::duration_to_ticks;
+
+/// Maximum amount of pending timers.
+pub const MAX_TIMERS: usize = 2048;
+/// Levels of the timer wheel.
+pub const LEVELS: usize = 4;
+/// Slots of every level of the timer wheel.
+pub const WHEEL_SIZE: usize = 64;
+const SLOT_BITS: u32 = WHEEL_SIZE.trailing_zeros();
+/// Ticks the wheel spans, about 10 days. Timers farther away are put in the last level, and moved
+/// again when it reaches them.
+const RANGE: u64 = 1 << (SLOT_BITS * LEVELS as u32);
+/// Maximum size of a callback, in bytes.
+pub const MAX_CALLBACK_SIZE: usize = 32;
+
+/// A `FnOnce` closure, stored inline.
+struct Callback {
+    data: MaybeUninit<[u64; MAX_CALLBACK_SIZE / 8]>,
+    call: unsafe fn(*mut u8),
+    drop: unsafe fn(*mut u8),
+}
+
+// Safety: only `Send` closures are stored.
+unsafe impl Send for Callback {}
+
+impl Callback {
+    fn new<F: FnOnce() + Send + 'static>(f: F) -> Self {
+        const {
+            assert!(size_of::<F>() <= MAX_CALLBACK_SIZE, "the timer callback is too big");
+            assert!(align_of::<F>() <= align_of::<u64>(), "the timer callback is over aligned");
+        }
+        unsafe fn call<F: FnOnce()>(data: *mut u8) {
+            // Safety: `data` holds an `F`, which is read once.
+            unsafe { data.cast::<F>().read()() }
+        }
+        unsafe fn drop<F>(data: *mut u8) {
+            // Safety: `data` holds an `F`, which is dropped once.
+            unsafe { data.cast::<F>().drop_in_place() }
+        }
+        let mut data = MaybeUninit::<[u64; MAX_CALLBACK_SIZE / 8]>::uninit();
+        // Safety: the size and alignment were checked above.
+        unsafe { data.as_mut_ptr().cast::<F>().write(f) };
+        Self { data, call: call::<F>, drop: drop::<F> }
+    }
+
+    fn call(self) {
+        let mut this = ManuallyDrop::new(self);
+        // Safety: `this` is not dropped, so the closure is only consumed here.
+        unsafe { (this.call)(this.data.as_mut_ptr().cast()) }
+    }
+}
+
+impl Drop for Callback {
+    fn drop(&mut self) {
+        // Safety: the closure was not called, as `call` does not drop.
+        unsafe { (self.drop)(self.data.as_mut_ptr().cast()) }
+    }
+}
+
+impl fmt::Debug for Callback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Callback")
+    }
+}
+
+/// The list a pending timer is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum List {
+    /// A slot of a level.
+    Slot { level: usize, slot: usize },
+    /// Expired, and waiting for its callback to be called.
+    Expired,
+}
+
+/// The slot of the timer expiring at tick `expires`, when the next tick the wheel processes is
+/// `base`
+fn slot_for(expires: u64, base: u64) -> List {
+    let expires = expires.clamp(base, base + RANGE - 1);
+    let delta = expires - base;
+    // the last level is never too small, as `expires` is clamped.
+    let level = (0..LEVELS).find(|&level| delta >> (SLOT_BITS * (level as u32 + 1)) == 0).unwrap_or(LEVELS - 1);
+    List::Slot { level, slot: (expires >> (SLOT_BITS * level as u32)) as usize % WHEEL_SIZE }
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// The tick at which the timer fires.
+    expires: u64,
+    callback: Callback,
+    list: List,
+    /// The neighbours in the same list.
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+#[derive(Debug)]
+struct Wheel {
+    entries: [Option<Entry>; MAX_TIMERS],
+    /// Bumped whenever an entry is reused, so old handles do not cancel new timers.
+    generations: [u32; MAX_TIMERS],
+    /// The unused entries, the first `unused` of them.
+    free: [usize; MAX_TIMERS],
+    unused: usize,
+    /// The first entry of every slot.
+    slots: [[Option<usize>; WHEEL_SIZE]; LEVELS],
+    /// The first and last expired entry, which fire in the order they expired.
+    expired: Option<usize>,
+    expired_tail: Option<usize>,
+    /// The last tick which was processed.
+    now: u64,
+}
+
+/// Every entry, the last one first, so the first one is used first.
+const fn all_free() -> [usize; MAX_TIMERS] {
+    let mut free = [0; MAX_TIMERS];
+    let mut i = 0;
+    while i < MAX_TIMERS {
+        free[i] = MAX_TIMERS - 1 - i;
+        i += 1;
+    }
+    free
+}
+
+impl Wheel {
+    fn head(&mut self, list: List) -> &mut Option<usize> {
+        match list {
+            List::Slot { level, slot } => &mut self.slots[level][slot],
+            List::Expired => &mut self.expired,
+        }
+    }
+
+    /// Adds the unlinked entry `index` to `list`, at the front of a slot, or the back of the
+    /// expired timers.
+    fn link(&mut self, index: usize, list: List) {
+        let (prev, next) = match list {
+            List::Slot { .. } => (None, *self.head(list)),
+            List::Expired => (self.expired_tail, None),
+        };
+        if let Some(entry) = self.entries[index].as_mut() {
+            entry.list = list;
+            entry.prev = prev;
+            entry.next = next;
+        }
+        match prev.and_then(|p| self.entries[p].as_mut()) {
+            Some(entry) => entry.next = Some(index),
+            None => *self.head(list) = Some(index),
+        }
+        match next.and_then(|n| self.entries[n].as_mut()) {
+            Some(entry) => entry.prev = Some(index),
+            None if list == List::Expired => self.expired_tail = Some(index),
+            None => {}
+        }
+    }
+
+    /// Removes entry `index` from its list, leaving it unlinked.
+    fn unlink(&mut self, index: usize) {
+        let Some((list, prev, next)) = self.entries[index].as_ref().map(|e| (e.list, e.prev, e.next)) else { return };
+        match prev.and_then(|p| self.entries[p].as_mut()) {
+            Some(entry) => entry.next = next,
+            None => *self.head(list) = next,
+        }
+        match next.and_then(|n| self.entries[n].as_mut()) {
+            Some(entry) => entry.prev = prev,
+            None if list == List::Expired => self.expired_tail = prev,
+            None => {}
+        }
+    }
+
+    /// Puts the unlinked entry `index` in its slot, when the next tick processed is `base`
+    fn place(&mut self, index: usize, base: u64) {
+        if let Some(expires) = self.entries[index].as_ref().map(|e| e.expires) {
+            self.link(index, slot_for(expires, base));
+        }
+    }
+
+    /// Starts a timer firing `callback` at tick `expires`
+    fn insert(&mut self, expires: u64, callback: Callback) -> Result<TimerHandle, CapacityError> {
+        self.unused = self.unused.checked_sub(1).ok_or(CapacityError(()))?;
+        let index = self.free[self.unused];
+        self.entries[index] = Some(Entry { expires, callback, list: List::Expired, prev: None, next: None });
+        self.place(index, self.now + 1);
+        Ok(TimerHandle { index, generation: self.generations[index] })
+    }
+
+    /// Removes entry `index` from the wheel, so its handle is no longer pending.
+    fn remove(&mut self, index: usize) -> Option<Entry> {
+        self.unlink(index);
+        let entry = self.entries[index].take()?;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free[self.unused] = index;
+        self.unused += 1;
+        Some(entry)
+    }
+
+    /// Calls `f` with every entry of `list`, which is emptied first, so `f` may put them back.
+    fn drain(&mut self, list: List, mut f: impl FnMut(&mut Self, usize, u64)) {
+        let mut link = self.head(list).take();
+        while let Some(index) = link {
+            let Some((expires, next)) = self.entries[index].as_ref().map(|e| (e.expires, e.next)) else { break };
+            f(self, index, expires);
+            link = next;
+        }
+    }
+
+    /// Processes every tick up to `now`, moving the timers which expired to the expired list.
+    fn advance(&mut self, now: u64) {
+        if now < self.now {
+            // time went back, when a test clock was uninstalled, so the slots are computed again.
+            self.slots = [[None; WHEEL_SIZE]; LEVELS];
+            self.now = now;
+            for index in 0..MAX_TIMERS {
+                if self.entries[index].as_ref().is_some_and(|e| e.list != List::Expired) {
+                    self.place(index, now + 1);
+                }
+            }
+            return;
+        }
+        if self.unused == MAX_TIMERS {
+            // nothing to move, which skips long jumps of a test clock.
+            self.now = now;
+            return;
+        }
+        for tick in self.now + 1..=now {
+            // the higher levels first, as they may move timers to the slot of a lower one.
+            for level in (1..LEVELS).rev() {
+                if tick & ((1 << (SLOT_BITS * level as u32)) - 1) == 0 {
+                    let slot = (tick >> (SLOT_BITS * level as u32)) as usize % WHEEL_SIZE;
+                    self.drain(List::Slot { level, slot }, |wheel, index, _| wheel.place(index, tick));
+                }
+            }
+            let slot = tick as usize % WHEEL_SIZE;
+            self.drain(List::Slot { level: 0, slot }, |wheel, index, expires| {
+                if expires <= tick {
+                    wheel.link(index, List::Expired);
+                } else {
+                    wheel.place(index, tick);
+                }
+            });
+            self.now = tick;
+        }
+    }
+
+    /// Removes the timer which expired first, returning its callback.
+    fn pop_expired(&mut self) -> Option<Callback> {
+        let index = self.expired?;
+        self.remove(index).map(|entry| entry.callback)
+    }
+}
+
+static WHEEL: Mutex<Wheel> = Mutex::new(Wheel {
+    entries: [const { None }; MAX_TIMERS],
+    generations: [0; MAX_TIMERS],
+    free: all_free(),
+    unused: MAX_TIMERS,
+    slots: [[None; WHEEL_SIZE]; LEVELS],
+    expired: None,
+    expired_tail: None,
+    now: 0,
+});
+
+/// The current tick, of the clock [`now`](super::now) reads.
+fn current_tick() -> u64 {
+    let scale = super::PIT_DIVISOR as u128 * 1_000_000_000;
+    (super::now().as_nanos() * super::PIT_FREQUENCY as u128 / scale) as u64
+}
+
+/// A pending timer, which can be cancelled.
+/// 
+/// Dropping the handle does not cancel the timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerHandle {
+    index: usize,
+    generation: u32,
+}
+
+impl TimerHandle {
+    /// Cancels the timer, returning whether it was still pending.
+    pub fn cancel(self) -> bool {
+        let entry = x86_64::instructions::interrupts::without_interrupts(|| {
+            let mut wheel = WHEEL.lock();
+            if wheel.generations[self.index] != self.generation {
+                return None;
+            }
+            wheel.remove(self.index)
+        });
+        // dropped outside the lock, in case the closure owns something which uses timers.
+        entry.is_some()
+    }
+
+    /// Returns whether the timer has not fired, nor was cancelled.
+    pub fn is_pending(&self) -> bool {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let wheel = WHEEL.lock();
+            wheel.generations[self.index] == self.generation && wheel.entries[self.index].is_some()
+        })
+    }
+}
+
+/// Calls `callback` from the timer interrupt, once `duration` passed.
+/// 
+/// The timer fires on the first tick after `duration` passed, so it may be late by up to a tick
+/// (about 55ms), but is never early.
+/// # Errors
+/// Returns an error if there are [`MAX_TIMERS`] pending already.
+pub fn after<F: FnOnce() + Send + 'static>(duration: Duration, callback: F) -> Result<TimerHandle, CapacityError> {
+    let callback = Callback::new(callback);
+    let expires = duration_to_ticks(super::now().saturating_add(duration)).max(current_tick() + 1);
+    x86_64::instructions::interrupts::without_interrupts(|| WHEEL.lock().insert(expires, callback))
+}
+
+/// Fires every expired timer.
+/// 
+/// Called by the timer interrupt, and when a [`TestClock`](super::TestClock) advances.
+pub(crate) fn run_expired() {
+    let now = current_tick();
+    {
+        let Some(mut wheel) = WHEEL.try_lock() else { return };
+        wheel.advance(now);
+    }
+    // the lock is dropped while a callback runs, so it may start timers.
+    while let Some(callback) = WHEEL.try_lock().and_then(|mut wheel| wheel.pop_expired()) {
+        callback.call();
+    }
+}
+
+/// Tests firing and cancelling timers, with a [`TestClock`](super::TestClock)
+#[cfg(feature = "test")]
+pub fn test_timers(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::test::{test_assert, test_assert_eq};
+
+    static FIRED: AtomicUsize = AtomicUsize::new(0);
+
+    let clock = super::TestClock::install();
+    let add = |n: usize| move || { FIRED.fetch_add(n, Ordering::SeqCst); };
+
+    let first = after(Duration::from_millis(100), add(1));
+    let cancelled = after(Duration::from_millis(100), add(10));
+    let late = after(Duration::from_secs(10), add(100));
+    test_assert!(first.is_ok() && cancelled.is_ok() && late.is_ok())?;
+    test_assert!(cancelled.is_ok_and(|h| h.cancel()))?;
+
+    clock.advance(50);
+    test_assert_eq!(FIRED.load(Ordering::SeqCst), 0)?;
+    clock.advance(200);
+    test_assert_eq!(FIRED.load(Ordering::SeqCst), 1)?;
+    test_assert!(first.is_ok_and(|h| !h.is_pending() && !h.cancel()))?;
+
+    // more than a full turn of the wheel.
+    clock.advance(10_000);
+    test_assert_eq!(FIRED.load(Ordering::SeqCst), 101)?;
+    drop(clock);
+    test_assert!(late.is_ok_and(|h| !h.is_pending()))
+}
+
+/// Tests placing timers in the levels of the wheel, and firing a lot of them.
+#[cfg(feature = "test")]
+pub fn test_timer_wheel(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::{log::debug, test::{stats, test_assert_eq}};
+
+    static FIRED: AtomicUsize = AtomicUsize::new(0);
+    static EARLY: AtomicUsize = AtomicUsize::new(0);
+    const TIMERS: usize = 1000;
+
+    test_assert_eq!(slot_for(100, 50), List::Slot { level: 0, slot: 100 % WHEEL_SIZE })?;
+    test_assert_eq!(slot_for(50 + 64, 50), List::Slot { level: 1, slot: 114 / 64 })?;
+    test_assert_eq!(slot_for(5000, 50), List::Slot { level: 2, slot: 1 })?;
+    // late, or too far away.
+    test_assert_eq!(slot_for(10, 50), List::Slot { level: 0, slot: 50 })?;
+    test_assert_eq!(slot_for(u64::MAX, 0), slot_for(RANGE - 1, 0))?;
+
+    let clock = super::TestClock::install();
+    let start = super::now();
+    let empty = stats::bench(64, || { let _ = after(Duration::from_secs(60), || {}).map(TimerHandle::cancel); });
+    // from a few ticks to a few hours away, so every level is used.
+    let duration = |i: usize| Duration::from_millis((i * i * 13 % 10_000_000) as u64);
+    for i in 0..TIMERS {
+        let deadline = start + duration(i);
+        after(duration(i), move || {
+            if super::now() < deadline {
+                EARLY.fetch_add(1, Ordering::SeqCst);
+            }
+            FIRED.fetch_add(1, Ordering::SeqCst);
+        }).map_err(|_| "the timer was not started")?;
+    }
+    let full = stats::bench(64, || { let _ = after(Duration::from_secs(60), || {}).map(TimerHandle::cancel); });
+    debug!("starting and cancelling a timer: {empty} with none pending, {full} with {TIMERS} pending");
+
+    clock.advance(3_600_000);
+    let due = (0..TIMERS).filter(|&i| duration_to_ticks(start + duration(i)) <= current_tick()).count();
+    test_assert_eq!(FIRED.load(Ordering::SeqCst), due)?;
+    clock.advance(10_000_000);
+    test_assert_eq!((FIRED.load(Ordering::SeqCst), EARLY.load(Ordering::SeqCst)), (TIMERS, 0))
+}
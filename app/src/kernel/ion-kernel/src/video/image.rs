@@ -0,0 +1,266 @@
+//! Decoding images, and drawing them to a [`FrameBuffer`]
+//! 
+//! Only uncompressed BMP is supported, with 1, 4 or 8 bit palettes, or 24 or 32 bit pixels. PNG
+//! needs an inflate implementation, which the kernel does not have yet.
+//! 
+//! [`show_logo`] draws the boot logo at the top of the frame buffer console. There is no initrd to
+//! load it from yet, so nothing calls it at boot.
+use core::fmt;
+
+use crate::text::framebuffer::{FRAME_BUFFER, FrameBuffer, Rgb, font::GLYPH_HEIGHT};
+
+/// Largest width or height of an image, in pixels.
+pub const MAX_DIMENSION: usize = 1 << 14;
+
+/// The first bytes of every PNG file.
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// An error while decoding an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageError {
+    /// The data ends before the image does.
+    Truncated,
+    /// The data is not in a known image format.
+    UnknownFormat,
+    /// The format is known, but decoding it is not supported.
+    Unsupported(&'static str),
+    /// The image is empty, or larger than [`MAX_DIMENSION`]
+    BadSize(i64, i64),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "the image is truncated"),
+            Self::UnknownFormat => write!(f, "unknown image format"),
+            Self::Unsupported(what) => write!(f, "unsupported image: {what}"),
+            Self::BadSize(width, height) => write!(f, "bad image size {width}x{height}"),
+        }
+    }
+}
+
+/// Decodes `data`, detecting its format.
+/// # Errors
+/// See [`Bmp::parse`], PNG is recognized but [unsupported](ImageError::Unsupported)
+pub fn decode(data: &[u8]) -> Result<Bmp<'_>, ImageError> {
+    if data.starts_with(b"BM") {
+        Bmp::parse(data)
+    } else if data.starts_with(&PNG_SIGNATURE) {
+        Err(ImageError::Unsupported("PNG"))
+    } else {
+        Err(ImageError::UnknownFormat)
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, ImageError> {
+    let bytes = data.get(offset..offset + 2).ok_or(ImageError::Truncated)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, ImageError> {
+    let bytes = data.get(offset..offset + 4).ok_or(ImageError::Truncated)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// A BMP image, decoded from the borrowed file as pixels are read.
+#[derive(Debug, Clone, Copy)]
+pub struct Bmp<'a> {
+    pixels: &'a [u8],
+    /// Entries are blue, green, red and unused.
+    palette: &'a [u8],
+    width: usize,
+    height: usize,
+    bpp: u16,
+    /// Rows are stored from the bottom up, unless the height is negative.
+    top_down: bool,
+    /// Bytes per row, rows are padded to 4 bytes.
+    stride: usize,
+}
+
+impl<'a> Bmp<'a> {
+    /// Size of the file header.
+    const FILE_HEADER: usize = 14;
+    /// Size of `BITMAPINFOHEADER`, later headers only add fields.
+    const INFO_HEADER: u32 = 40;
+
+    /// Parses the headers of the BMP file `data`
+    /// # Errors
+    /// Returns an error if `data` is not a BMP file, it is compressed or has an unsupported amount
+    /// of bits per pixel, or it is shorter than its headers say.
+    pub fn parse(data: &'a [u8]) -> Result<Self, ImageError> {
+        if !data.starts_with(b"BM") {
+            return Err(ImageError::UnknownFormat);
+        }
+        let offset = read_u32(data, 10)? as usize;
+        let header_size = read_u32(data, 14)?;
+        if header_size < Self::INFO_HEADER {
+            return Err(ImageError::Unsupported("OS/2 bitmap header"));
+        }
+        let width = read_u32(data, 18)? as i32;
+        let height = read_u32(data, 22)? as i32;
+        let bpp = read_u16(data, 28)?;
+        if read_u32(data, 30)? != 0 {
+            return Err(ImageError::Unsupported("compressed BMP"));
+        }
+        if !matches!(bpp, 1 | 4 | 8 | 24 | 32) {
+            return Err(ImageError::Unsupported("bits per pixel"));
+        }
+
+        let bad_size = ImageError::BadSize(i64::from(width), i64::from(height));
+        let (w, h) = (width.unsigned_abs() as usize, height.unsigned_abs() as usize);
+        if width <= 0 || h == 0 || w > MAX_DIMENSION || h > MAX_DIMENSION {
+            return Err(bad_size);
+        }
+        let stride = (w * usize::from(bpp)).div_ceil(32) * 4;
+        let pixels = data.get(offset..).and_then(|p| p.get(..stride * h)).ok_or(ImageError::Truncated)?;
+
+        let palette = if bpp <= 8 {
+            let colors = match read_u32(data, 46)? {
+                0 => 1 << bpp,
+                colors => colors.min(1 << bpp) as usize,
+            };
+            let start = Self::FILE_HEADER + header_size as usize;
+            data.get(start..start + colors * 4).ok_or(ImageError::Truncated)?
+        } else {
+            &[]
+        };
+        Ok(Self { pixels, palette, width: w, height: h, bpp, top_down: height < 0, stride })
+    }
+
+    /// Width, in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height, in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The color of the pixel at `x`, `y` from the top left, `None` if it is outside of the image.
+    /// 
+    /// Palette indices past the end of the palette are black.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<Rgb> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let row = if self.top_down { y } else { self.height - 1 - y };
+        let row = &self.pixels[row * self.stride..][..self.stride];
+        let color = match self.bpp {
+            24 | 32 => {
+                let pixel = &row[x * usize::from(self.bpp / 8)..];
+                Rgb::new(pixel[2], pixel[1], pixel[0])
+            }
+            bpp => {
+                // the leftmost pixel is in the highest bits.
+                let bit = x * usize::from(bpp);
+                let shift = 8 - usize::from(bpp) - bit % 8;
+                let index = usize::from(row[bit / 8] >> shift) & ((1 << bpp) - 1);
+                match self.palette.get(index * 4..index * 4 + 3) {
+                    Some(&[b, g, r]) => Rgb::new(r, g, b),
+                    _ => Rgb::new(0, 0, 0),
+                }
+            }
+        };
+        Some(color)
+    }
+}
+
+/// Draws `image` with its top left corner at `x`, `y`, clipped to the frame buffer.
+pub fn blit(buffer: &mut FrameBuffer, image: &Bmp<'_>, x: usize, y: usize) {
+    let height = image.height().min(buffer.height().saturating_sub(y));
+    let width = image.width().min(buffer.width().saturating_sub(x));
+    for dy in 0..height {
+        for dx in 0..width {
+            if let Some(color) = image.pixel(dx, dy) {
+                buffer.plot(x + dx, y + dy, color);
+            }
+        }
+    }
+}
+
+/// Draws the logo `data` centered at the top of the frame buffer console, and moves the cursor
+/// below it.
+/// # Errors
+/// Returns an error if the logo can not be [decoded](decode). Without a frame buffer console
+/// nothing is drawn.
+pub fn show_logo(data: &[u8]) -> Result<(), ImageError> {
+    let logo = decode(data)?;
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut console = FRAME_BUFFER.lock();
+        let Some(writer) = console.as_mut() else {
+            return;
+        };
+        let x = writer.buffer().width().saturating_sub(logo.width()) / 2;
+        blit(writer.buffer(), &logo, x, 0);
+        for _ in 0..logo.height().div_ceil(GLYPH_HEIGHT) {
+            writer.write_char('\n');
+        }
+    });
+    Ok(())
+}
+
+/// Tests decoding BMP files, and drawing them to a frame buffer.
+#[cfg(feature = "test")]
+pub fn test_image(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use alloc::{vec, vec::Vec};
+    use core::ptr::NonNull;
+
+    use crate::{c_lib::{FrameBufferInfo, PixelFormat}, test::{test_assert, test_assert_eq}};
+
+    fn bmp(width: i32, height: i32, bpp: u16, palette: &[u8], pixels: &[u8]) -> Vec<u8> {
+        let offset = 54 + palette.len() as u32;
+        let mut data = vec![0; 54];
+        data[..2].copy_from_slice(b"BM");
+        data[2..6].copy_from_slice(&(offset + pixels.len() as u32).to_le_bytes());
+        data[10..14].copy_from_slice(&offset.to_le_bytes());
+        data[14..18].copy_from_slice(&40u32.to_le_bytes());
+        data[18..22].copy_from_slice(&width.to_le_bytes());
+        data[22..26].copy_from_slice(&height.to_le_bytes());
+        data[26..28].copy_from_slice(&1u16.to_le_bytes());
+        data[28..30].copy_from_slice(&bpp.to_le_bytes());
+        data.extend_from_slice(palette);
+        data.extend_from_slice(pixels);
+        data
+    }
+
+    let red = Rgb::new(255, 0, 0);
+    let green = Rgb::new(0, 255, 0);
+    let blue = Rgb::new(0, 0, 255);
+    // 2x2, bottom up: blue and green, then red and white. Rows are padded to 8 bytes.
+    let data = bmp(2, 2, 24, &[], &[255, 0, 0, 0, 255, 0, 0, 0, 0, 0, 255, 255, 255, 255, 0, 0]);
+    let image = decode(&data).map_err(|_| "the image is valid")?;
+    test_assert_eq!((image.width(), image.height()), (2, 2))?;
+    test_assert_eq!(image.pixel(0, 0), Some(red))?;
+    test_assert_eq!(image.pixel(1, 0), Some(Rgb::new(255, 255, 255)))?;
+    test_assert_eq!(image.pixel(0, 1), Some(blue))?;
+    test_assert_eq!(image.pixel(2, 0), None)?;
+    test_assert_eq!(decode(&data[..60]).err(), Some(ImageError::Truncated))?;
+    test_assert_eq!(decode(&PNG_SIGNATURE).err(), Some(ImageError::Unsupported("PNG")))?;
+    test_assert!(decode(&bmp(0, 2, 24, &[], &[])).is_err())?;
+
+    // 3x1, top down, 1 bit: green, blue, green
+    let indexed = bmp(3, -1, 1, &[0, 255, 0, 0, 255, 0, 0, 0], &[0b0100_0000, 0, 0, 0]);
+    let indexed = decode(&indexed).map_err(|_| "the indexed image is valid")?;
+    test_assert_eq!(indexed.pixel(0, 0), Some(green))?;
+    test_assert_eq!(indexed.pixel(1, 0), Some(blue))?;
+    test_assert_eq!(indexed.pixel(2, 0), Some(green))?;
+
+    // drawn to a 3x3 frame buffer at 2, 1, so the right column is clipped.
+    let mut pixels = vec![0u32; 9];
+    let info = FrameBufferInfo {
+        addr: pixels.as_mut_ptr() as u64,
+        pitch: 12,
+        width: 3,
+        height: 3,
+        bpp: 32,
+        format: PixelFormat::Rgb { red: (16, 8), green: (8, 8), blue: (0, 8) },
+    };
+    let buffer = NonNull::new(pixels.as_mut_ptr().cast::<u8>()).ok_or("the buffer is null")?;
+    // Safety: the buffer is `pitch * height` bytes, and only used through the frame buffer.
+    let mut fb = unsafe { FrameBuffer::new(info, buffer) }.map_err(|_| "the format is supported")?;
+    blit(&mut fb, &image, 2, 1);
+    test_assert_eq!(fb.pixel(2, 1), Some(0xFF0000))?;
+    test_assert_eq!(fb.pixel(2, 2), Some(0x0000FF))?;
+    test_assert_eq!(fb.pixel(1, 1), Some(0))
+}
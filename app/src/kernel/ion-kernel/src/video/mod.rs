@@ -0,0 +1,6 @@
+//! Graphics on the [frame buffer](crate::text::framebuffer).
+
+/// Decoding images, and drawing them to the frame buffer.
+pub mod image;
+/// Screen modes, and selecting them at boot.
+pub mod mode;
@@ -0,0 +1,271 @@
+//! Screen modes of the frame buffer console.
+//! 
+//! The bootloader sets the hardware mode before the kernel runs, the kernel can not call VBE in
+//! long mode, or GOP after boot services exited. To get a larger mode, set `gfxpayload` in
+//! `grub.cfg`:
+//! 
+//! ```text
+//! set gfxpayload=1024x768x32
+//! multiboot2 /boot/kernel.bin video=800x600
+//! ```
+//! 
+//! Any mode up to the hardware mode, with its depth, can then be [selected](set), with
+//! `video=WIDTHxHEIGHT[xDEPTH]` on the kernel command line or the `video` command. The console is
+//! drawn to a viewport of that size, centered on the screen, and lays its text out again.
+use core::{fmt, str::FromStr};
+
+use crate::{boot::cmdline::CommandLine, c_lib::BootInfo, collections::ArrayVec, log::{debug, info, warn}, text::framebuffer::FRAME_BUFFER};
+
+/// Common resolutions, listed by [`modes`] if they fit in the hardware mode.
+pub const STANDARD: [(u32, u32); 8] = [
+    (640, 480), (800, 600), (1024, 768), (1280, 720), (1280, 1024), (1600, 900), (1920, 1080), (2560, 1440),
+];
+
+/// Most VBE mode numbers logged at boot.
+const MAX_VBE_MODES: usize = 64;
+
+/// A screen mode, as `WIDTHxHEIGHT[xDEPTH]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode {
+    /// Width, in pixels.
+    pub width: u32,
+    /// Height, in pixels.
+    pub height: u32,
+    /// Bits per pixel, `None` accepts the depth of the hardware mode.
+    pub bpp: Option<u8>,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)?;
+        match self.bpp {
+            Some(bpp) => write!(f, "x{bpp}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The string is not a [`Mode`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseModeError;
+
+impl fmt::Display for ParseModeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected WIDTHxHEIGHT or WIDTHxHEIGHTxDEPTH")
+    }
+}
+
+impl FromStr for Mode {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('x').map(str::parse::<u32>);
+        let (Some(Ok(width)), Some(Ok(height))) = (parts.next(), parts.next()) else {
+            return Err(ParseModeError);
+        };
+        let bpp = match parts.next() {
+            None => None,
+            Some(Ok(bpp)) => Some(u8::try_from(bpp).map_err(|_| ParseModeError)?),
+            Some(Err(_)) => return Err(ParseModeError),
+        };
+        if parts.next().is_some() || width == 0 || height == 0 {
+            return Err(ParseModeError);
+        }
+        Ok(Self { width, height, bpp })
+    }
+}
+
+/// An error while selecting a [`Mode`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeError {
+    /// There is no frame buffer console.
+    NoFrameBuffer,
+    /// The mode is larger than the hardware mode, or has another depth.
+    Unavailable(Mode),
+}
+
+impl fmt::Display for ModeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoFrameBuffer => write!(f, "there is no frame buffer console"),
+            Self::Unavailable(mode) => write!(f, "mode {mode} is not available, see `video` for the available modes"),
+        }
+    }
+}
+
+fn with_console<T>(f: impl FnOnce(&mut crate::text::framebuffer::FrameBufferWriter) -> T) -> Result<T, ModeError> {
+    x86_64::instructions::interrupts::without_interrupts(|| FRAME_BUFFER.lock().as_mut().map(f).ok_or(ModeError::NoFrameBuffer))
+}
+
+/// The hardware mode, set by the bootloader.
+/// # Errors
+/// Returns [`ModeError::NoFrameBuffer`] without a frame buffer console.
+pub fn hardware() -> Result<Mode, ModeError> {
+    with_console(|writer| {
+        let screen = writer.buffer().screen();
+        Mode { width: screen.width, height: screen.height, bpp: Some(screen.bpp) }
+    })
+}
+
+/// The selected mode.
+/// # Errors
+/// Returns [`ModeError::NoFrameBuffer`] without a frame buffer console.
+pub fn current() -> Result<Mode, ModeError> {
+    with_console(|writer| {
+        let info = writer.buffer().info();
+        Mode { width: info.width, height: info.height, bpp: Some(info.bpp) }
+    })
+}
+
+/// The modes which can be [selected](set): the [`STANDARD`] resolutions which fit in `hardware`,
+/// then `hardware` itself, all with its depth.
+pub fn modes(hardware: Mode) -> ArrayVec<Mode, { STANDARD.len() + 1 }> {
+    let mut modes = ArrayVec::new();
+    let fits = |&&(width, height): &&(u32, u32)| {
+        width <= hardware.width && height <= hardware.height && (width, height) != (hardware.width, hardware.height)
+    };
+    for &(width, height) in STANDARD.iter().filter(fits).chain([(hardware.width, hardware.height)].iter()) {
+        // there is room for every standard mode, and the hardware mode.
+        let _ = modes.push(Mode { width, height, bpp: hardware.bpp });
+    }
+    modes
+}
+
+/// Selects `mode`, the console text is cleared and laid out for it.
+/// # Errors
+/// Returns an error without a frame buffer console, or if the mode does not fit in the hardware
+/// mode.
+pub fn set(mode: Mode) -> Result<(), ModeError> {
+    with_console(|writer| {
+        let screen = writer.buffer().screen();
+        if mode.bpp.is_some_and(|bpp| bpp != screen.bpp) {
+            return Err(ModeError::Unavailable(mode));
+        }
+        if writer.set_viewport(mode.width as usize, mode.height as usize) {
+            Ok(())
+        } else {
+            Err(ModeError::Unavailable(mode))
+        }
+    })?
+}
+
+/// The value of `video=` on the kernel `command_line`, if there is one.
+pub fn from_command_line(command_line: &str) -> Option<Result<Mode, ParseModeError>> {
+    CommandLine::new(command_line).parse("video")
+}
+
+/// Logs the VBE modes, and selects the mode from the kernel command line.
+/// 
+/// Called after the frame buffer console is set up.
+pub fn init(boot_info: &BootInfo) {
+    if let Some(vbe) = boot_info.vbe {
+        let (width, height, bpp) = vbe.current;
+        debug!("VBE {:x}.{:02x}, {} KiB, mode {:#x} ({width}x{height}x{bpp})",
+            vbe.version >> 8, vbe.version & 0xFF, vbe.memory / 1024, vbe.mode);
+        // Safety: the first MiB is identity mapped at boot.
+        unsafe { vbe.for_each_mode(MAX_VBE_MODES, |mode| debug!("VBE mode {mode:#x}")) };
+    }
+    match from_command_line(boot_info.command_line) {
+        None => {}
+        Some(Err(e)) => warn!("Ignoring the video= argument: {e}"),
+        Some(Ok(mode)) => match set(mode) {
+            Ok(()) => info!("Switched to video mode {mode}."),
+            Err(e) => warn!("Could not switch to video mode {mode}: {e}"),
+        },
+    }
+}
+
+/// `video [WIDTHxHEIGHT[xDEPTH]]`, lists the available modes, or selects one.
+pub fn video_command(args: &[&str]) -> i32 {
+    use crate::text::println;
+
+    match args.get(1..).unwrap_or_default() {
+        [] => {
+            let (hardware, current) = match hardware().and_then(|hardware| current().map(|current| (hardware, current))) {
+                Ok(modes) => modes,
+                Err(e) => {
+                    println!("video: {e}");
+                    return 1;
+                }
+            };
+            for mode in &modes(hardware) {
+                let marker = if (mode.width, mode.height) == (current.width, current.height) { '*' } else { ' ' };
+                println!("{marker} {mode}");
+            }
+            0
+        }
+        [mode] => {
+            let mode = match mode.parse() {
+                Ok(mode) => mode,
+                Err(e) => {
+                    println!("video: {e}");
+                    return 2;
+                }
+            };
+            match set(mode) {
+                Ok(()) => 0,
+                Err(e) => {
+                    println!("video: {e}");
+                    1
+                }
+            }
+        }
+        _ => {
+            println!("usage: video [WIDTHxHEIGHT[xDEPTH]]");
+            2
+        }
+    }
+}
+
+/// Tests parsing modes, listing them, and laying text out for a viewport.
+#[cfg(feature = "test")]
+pub fn test_video_modes(_: crate::test::TestInfo) -> crate::test::TestResult {
+    use alloc::vec;
+    use core::ptr::NonNull;
+
+    use crate::{c_lib::{FrameBufferInfo, PixelFormat}, text::framebuffer::{FrameBuffer, FrameBufferWriter}, test::{test_assert, test_assert_eq}};
+
+    test_assert_eq!("1024x768x32".parse(), Ok(Mode { width: 1024, height: 768, bpp: Some(32) }))?;
+    test_assert_eq!("800x600".parse(), Ok(Mode { width: 800, height: 600, bpp: None }))?;
+    test_assert!("800".parse::<Mode>().is_err())?;
+    test_assert!("0x600".parse::<Mode>().is_err())?;
+    test_assert!("800x600x32x1".parse::<Mode>().is_err())?;
+    test_assert_eq!(from_command_line("quiet video=640x480 loglevel=info"), Some(Ok(Mode { width: 640, height: 480, bpp: None })))?;
+    test_assert_eq!(from_command_line("quiet"), None)?;
+
+    let listed = modes(Mode { width: 1024, height: 768, bpp: Some(32) });
+    test_assert_eq!(listed.len(), 3)?;
+    test_assert_eq!(listed[0], Mode { width: 640, height: 480, bpp: Some(32) })?;
+    test_assert_eq!(listed[2], Mode { width: 1024, height: 768, bpp: Some(32) })?;
+
+    // 64x32 pixels, so 8x4 characters, and 4x2 in a 32x16 viewport.
+    let (width, height) = (64, 32);
+    let mut pixels = vec![0xFFFF_FFFFu32; width * height];
+    let info = FrameBufferInfo {
+        addr: pixels.as_mut_ptr() as u64,
+        pitch: width as u32 * 4,
+        width: width as u32,
+        height: height as u32,
+        bpp: 32,
+        format: PixelFormat::Rgb { red: (16, 8), green: (8, 8), blue: (0, 8) },
+    };
+    let buffer = NonNull::new(pixels.as_mut_ptr().cast::<u8>()).ok_or("the buffer is null")?;
+    // Safety: the buffer is `pitch * height` bytes, and only used through the frame buffer.
+    let fb = unsafe { FrameBuffer::new(info, buffer) }.map_err(|_| "the format is supported")?;
+    let mut writer = FrameBufferWriter::new(fb);
+    test_assert!(!writer.set_viewport(width + 1, height))?;
+    test_assert!(writer.set_viewport(32, 16))?;
+    test_assert_eq!((writer.columns(), writer.rows()), (4, 2))?;
+    test_assert_eq!(writer.buffer().screen().width, width as u32)?;
+    test_assert_eq!(writer.buffer().info().addr, info.addr + (8 * width as u64 + 16) * 4)?;
+    // the viewport starts at 16, 8.
+    writer.write_char('|');
+    test_assert_eq!(writer.buffer().pixel(3, 0), Some(0xFFFFFF))?;
+    for c in "abcd\nefgh\n".chars() {
+        writer.write_char(c);
+    }
+    test_assert_eq!(writer.position(), (0, 1))?;
+    // the rest of the screen was cleared, and scrolling stays inside the viewport.
+    test_assert_eq!(pixels[0], 0)?;
+    test_assert_eq!(pixels[8 * width + 48], 0)
+}
@@ -9,8 +9,22 @@ global start
 global stack_top
 extern long_mode_start           ; 64-bit entry point (defined in a separate bits 64 file)
 
+; BootInfo header feature bits (see `BootFeatures` in the kernel's c_lib)
+%define BOOT_FEATURE_MEMORY_MAP  (1 << 0)
+%define BOOT_FEATURE_FRAMEBUFFER (1 << 1)
+%define BOOT_FEATURE_CPUID       (1 << 2)
+
 section .data
+global boot_info_header
 global boot_info_data
+; versioned header, directly followed by the data.
+; offsets in the data are relative to `boot_info_data`.
+boot_info_header:
+    dd 0x424E4F49     ; +0x00 magic ("IONB")
+    dw 2              ; +0x04 version
+    dw boot_info_end - boot_info_header ; +0x06 length (header included)
+    dd 0              ; +0x08 features
+    dd 0              ; +0x0C reserved
 boot_info_data:
     dd 0              ; +0x00 multiboot_magic
     dd 0              ; +0x04 multiboot_info
@@ -21,6 +35,7 @@ boot_info_data:
     dq 0              ; +0x20 framebuffer_addr
     dq 0              ; +0x28 memory_map_addr
     dq 0              ; +0x30 kernel_entry
+boot_info_end:
 
 section .text
 bits 32
@@ -130,11 +145,16 @@ check_multiboot1_mmap:
     mov     eax, [esi + 0x30]  ; mmap_addr (phys)
     mov     [boot_info_data + 0x28], eax
     mov     dword [boot_info_data + 0x2C], 0  ; high dword zero for 64-bit field
+    or      dword [boot_info_header + 0x08], BOOT_FEATURE_MEMORY_MAP
 
     ; Optional: publish framebuffer_addr if bit 12 set (video info)
-    mov     eax, [esi + 0x54] ; fb_addr low (if VBE/graphics provided)
+    test    dword [esi + 0x00], 1 << 12
+    jz      .done
+    mov     eax, [esi + 0x58] ; framebuffer_addr low
     mov     [boot_info_data + 0x20], eax
-    mov     dword [boot_info_data + 0x24], 0
+    mov     eax, [esi + 0x5C] ; framebuffer_addr high
+    mov     [boot_info_data + 0x24], eax
+    or      dword [boot_info_header + 0x08], BOOT_FEATURE_FRAMEBUFFER
 
     jmp     .done
 .no_mmap:
@@ -180,15 +200,19 @@ check_multiboot2_mmap:
     lea     eax, [esi]
     mov     [boot_info_data + 0x28], eax
     mov     dword [boot_info_data + 0x2C], 0
+    or      dword [boot_info_header + 0x08], BOOT_FEATURE_MEMORY_MAP
     jmp     .advance
 
 .check_fb:
     cmp     eax, 8
     jne     .advance
+    cmp     edx, 30           ; need the common part, up to framebuffer_type
+    jb      .advance
     mov     eax, [esi + 8]    ; low
     mov     [boot_info_data + 0x20], eax
     mov     eax, [esi + 12]   ; high
     mov     [boot_info_data + 0x24], eax
+    or      dword [boot_info_header + 0x08], BOOT_FEATURE_FRAMEBUFFER
 
 
 .advance:
@@ -261,8 +285,10 @@ check_cpuid:
 
     mov     eax, 1
     cpuid
+    ; legacy: the kernel re-queries cpuid, these are only kept for comparison.
     mov     [boot_info_data + 0x08], edx
     mov     [boot_info_data + 0x0C], ecx
+    or      dword [boot_info_header + 0x08], BOOT_FEATURE_CPUID
     ret
 .no_cpuid:
     mov     dword [0xb8000+44], 0x0F434F53  ; "SC"
@@ -2,6 +2,7 @@ global long_mode_start
 extern kernel_main
 extern stack_top
 extern boot_info_data
+extern boot_info_header
 
 
 section .text
@@ -24,8 +25,8 @@ long_mode_start:
     mov al, 'X'
     out 0xE9, al
 
-    ; Pass BootInfo in rdi (SysV AMD64 ABI)
-    lea rdi, [rel boot_info_data]
+    ; Pass BootInfo (starting at its header) in rdi (SysV AMD64 ABI)
+    lea rdi, [rel boot_info_header]
 
     ; Store full 64-bit kernel_main in BootInfo
     mov rax, kernel_main
@@ -9,7 +9,25 @@ header_start:
     ; checksum
     dd 0x100000000 - (0xe85250d6 + 0 + (header_end - header_start))
 
+    ; console flags tag: EGA text is supported (optional)
+    align 8
+    dw 4
+    dw 1
+    dd 12
+    dd 1 << 1
+
+    ; framebuffer tag: prefer 80x25 EGA text (depth 0), so a graphical framebuffer is only
+    ; used where there is no text mode, such as UEFI (optional)
+    align 8
+    dw 5
+    dw 1
+    dd 20
+    dd 80
+    dd 25
+    dd 0
+
     ; end tag
+    align 8
     dw 0
     dw 0
     dd 0